@@ -30,7 +30,7 @@ use mas_data_model::{Client, JwksOrJwksUri};
 use mas_http::HttpServiceExt;
 use mas_iana::oauth::OAuthClientAuthenticationMethod;
 use mas_jose::{jwk::PublicJsonWebKeySet, jwt::Jwt};
-use mas_keystore::Encrypter;
+use mas_keystore::{CertificateIdentity, Encrypter};
 use mas_storage::{oauth2::OAuth2ClientRepository, RepositoryAccess};
 use oauth2_types::errors::{ClientError, ClientErrorCode};
 use serde::{de::DeserializeOwned, Deserialize};
@@ -38,7 +38,7 @@ use serde_json::Value;
 use thiserror::Error;
 use tower::{Service, ServiceExt};
 
-use crate::http_client_factory::HttpClientFactory;
+use crate::{http_client_factory::HttpClientFactory, ClientCertificate};
 
 static JWT_BEARER_CLIENT_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
 
@@ -70,6 +70,10 @@ pub enum Credentials {
         client_id: String,
         jwt: Box<Jwt<'static, HashMap<String, serde_json::Value>>>,
     },
+    TlsClientAuth {
+        client_id: String,
+        certificate: Vec<u8>,
+    },
 }
 
 impl Credentials {
@@ -80,7 +84,18 @@ impl Credentials {
             Credentials::None { client_id }
             | Credentials::ClientSecretBasic { client_id, .. }
             | Credentials::ClientSecretPost { client_id, .. }
-            | Credentials::ClientAssertionJwtBearer { client_id, .. } => client_id,
+            | Credentials::ClientAssertionJwtBearer { client_id, .. }
+            | Credentials::TlsClientAuth { client_id, .. } => client_id,
+        }
+    }
+
+    /// Get the DER-encoded TLS client certificate the client authenticated
+    /// with, if any
+    #[must_use]
+    pub fn certificate(&self) -> Option<&[u8]> {
+        match self {
+            Credentials::TlsClientAuth { certificate, .. } => Some(certificate),
+            _ => None,
         }
     }
 
@@ -98,7 +113,8 @@ impl Credentials {
             Credentials::None { client_id }
             | Credentials::ClientSecretBasic { client_id, .. }
             | Credentials::ClientSecretPost { client_id, .. }
-            | Credentials::ClientAssertionJwtBearer { client_id, .. } => client_id,
+            | Credentials::ClientAssertionJwtBearer { client_id, .. }
+            | Credentials::TlsClientAuth { client_id, .. } => client_id,
         };
 
         repo.oauth2_client().find_by_client_id(client_id).await
@@ -118,7 +134,10 @@ impl Credentials {
         client: &Client,
     ) -> Result<(), CredentialsVerificationError> {
         match (self, method) {
-            (Credentials::None { .. }, OAuthClientAuthenticationMethod::None) => {}
+            (
+                Credentials::None { .. } | Credentials::TlsClientAuth { .. },
+                OAuthClientAuthenticationMethod::None,
+            ) => {}
 
             (
                 Credentials::ClientSecretPost { client_secret, .. },
@@ -180,6 +199,29 @@ impl Credentials {
                     .map_err(|_| CredentialsVerificationError::InvalidAssertionSignature)?;
             }
 
+            (
+                Credentials::TlsClientAuth { certificate, .. },
+                OAuthClientAuthenticationMethod::TlsClientAuth,
+            ) => {
+                // The certificate chain was verified during the TLS handshake, check
+                // that it was issued to this client
+                let identity = CertificateIdentity::from_der(certificate)
+                    .map_err(|_| CredentialsVerificationError::CertificateMismatch)?;
+
+                let matches = match (
+                    &client.tls_client_auth_san_dns,
+                    &client.tls_client_auth_san_email,
+                ) {
+                    (Some(dns_name), None) => identity.dns_names.contains(dns_name),
+                    (None, Some(email)) => identity.emails.contains(email),
+                    _ => return Err(CredentialsVerificationError::InvalidClientConfig),
+                };
+
+                if !matches {
+                    return Err(CredentialsVerificationError::CertificateMismatch);
+                }
+            }
+
             (_, _) => {
                 return Err(CredentialsVerificationError::AuthenticationMethodMismatch);
             }
@@ -231,6 +273,9 @@ pub enum CredentialsVerificationError {
 
     #[error("failed to fetch jwks")]
     JwksFetchFailed,
+
+    #[error("client certificate was not issued to this client")]
+    CertificateMismatch,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -352,6 +397,12 @@ where
         // Split the request into parts so we can extract some headers
         let (mut parts, body) = req.into_parts();
 
+        // The TLS client certificate, set by listeners which request them
+        let ClientCertificate(certificate) =
+            ClientCertificate::from_request_parts(&mut parts, state)
+                .await
+                .unwrap_or_default();
+
         let header =
             TypedHeader::<Authorization<Basic>>::from_request_parts(&mut parts, state).await;
 
@@ -428,8 +479,15 @@ where
             }
 
             (None, Some(client_id), None, None, None) => {
-                // Only got a client_id in the form
-                Credentials::None { client_id }
+                // Only got a client_id in the form, the client may have authenticated
+                // with a TLS client certificate
+                match certificate {
+                    Some(certificate) => Credentials::TlsClientAuth {
+                        client_id,
+                        certificate,
+                    },
+                    None => Credentials::None { client_id },
+                }
             }
 
             (
@@ -518,6 +576,61 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn tls_client_auth_test() {
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(
+                http::header::CONTENT_TYPE,
+                mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+            )
+            .extension(ClientCertificate(Some(b"certificate".to_vec())))
+            .body(Full::<Bytes>::new("client_id=client-id&foo=bar".into()))
+            .unwrap();
+
+        let authorization = ClientAuthorization::<serde_json::Value>::from_request(req, &())
+            .await
+            .unwrap();
+        assert_eq!(
+            authorization,
+            ClientAuthorization {
+                credentials: Credentials::TlsClientAuth {
+                    client_id: "client-id".to_owned(),
+                    certificate: b"certificate".to_vec(),
+                },
+                form: Some(serde_json::json!({"foo": "bar"})),
+            }
+        );
+        assert_eq!(
+            authorization.credentials.certificate(),
+            Some(&b"certificate"[..])
+        );
+
+        // The certificate is ignored if the client authenticates otherwise
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(
+                http::header::CONTENT_TYPE,
+                mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+            )
+            .extension(ClientCertificate(Some(b"certificate".to_vec())))
+            .body(Full::<Bytes>::new(
+                "client_id=client-id&client_secret=client-secret&foo=bar".into(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            ClientAuthorization::<serde_json::Value>::from_request(req, &())
+                .await
+                .unwrap()
+                .credentials,
+            Credentials::ClientSecretPost {
+                client_id: "client-id".to_owned(),
+                client_secret: "client-secret".to_owned(),
+            },
+        );
+    }
+
     #[tokio::test]
     async fn client_secret_basic_test() {
         let req = Request::builder()
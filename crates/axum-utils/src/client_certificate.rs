@@ -0,0 +1,39 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use http::request::Parts;
+
+/// The DER-encoded TLS client certificate presented on the connection, if any
+///
+/// It is set as a request extension by the listener, only on listeners which
+/// request client certificates. In that case the certificate was already
+/// verified against the configured certificate authorities during the TLS
+/// handshake.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientCertificate(pub Option<Vec<u8>>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientCertificate
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
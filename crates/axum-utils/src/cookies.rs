@@ -22,7 +22,10 @@ use axum::{
     response::{IntoResponseParts, ResponseParts},
 };
 use axum_extra::extract::cookie::{Cookie, Key, PrivateCookieJar, SameSite};
-use http::request::Parts;
+use http::{
+    header::{ORIGIN, REFERER},
+    request::Parts,
+};
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 use url::Url;
@@ -33,7 +36,11 @@ pub enum CookieDecodeError {
     Deserialize(#[from] serde_json::Error),
 }
 
-/// Manages cookie options and encryption key
+/// Manages cookie options and encryption keys
+///
+/// Cookies are always encrypted with the primary key, but can be decrypted
+/// with any of the fallback keys, which allows rotating the key without
+/// invalidating existing cookies.
 ///
 /// This is meant to be accessible through axum's state via the [`FromRef`]
 /// trait
@@ -41,13 +48,18 @@ pub enum CookieDecodeError {
 pub struct CookieManager {
     options: CookieOption,
     key: Key,
+    fallback_keys: Vec<Key>,
 }
 
 impl CookieManager {
     #[must_use]
     pub const fn new(base_url: Url, key: Key) -> Self {
         let options = CookieOption::new(base_url);
-        Self { options, key }
+        Self {
+            options,
+            key,
+            fallback_keys: Vec::new(),
+        }
     }
 
     #[must_use]
@@ -56,20 +68,49 @@ impl CookieManager {
         Self::new(base_url, key)
     }
 
+    /// Derive a cookie manager from a primary key and a list of older keys,
+    /// which are only used to decrypt existing cookies
+    #[must_use]
+    pub fn derive_from_keys<'a>(
+        base_url: Url,
+        key: &[u8],
+        fallback_keys: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Self {
+        let mut manager = Self::derive_from(base_url, key);
+        manager.fallback_keys = fallback_keys.into_iter().map(Key::derive_from).collect();
+        manager
+    }
+
     #[must_use]
     pub fn cookie_jar(&self) -> CookieJar {
         let inner = PrivateCookieJar::new(self.key.clone());
         let options = self.options.clone();
 
-        CookieJar { inner, options }
+        CookieJar {
+            inner,
+            fallbacks: Vec::new(),
+            options,
+            request_origin: None,
+        }
     }
 
     #[must_use]
     pub fn cookie_jar_from_headers(&self, headers: &http::HeaderMap) -> CookieJar {
         let inner = PrivateCookieJar::from_headers(headers, self.key.clone());
+        let fallbacks = self
+            .fallback_keys
+            .iter()
+            .map(|key| PrivateCookieJar::from_headers(headers, key.clone()))
+            .collect();
         let options = self.options.clone();
+        let request_origin = request_origin(headers);
 
-        CookieJar { inner, options }
+        CookieJar {
+            inner,
+            fallbacks,
+            options,
+            request_origin,
+        }
     }
 }
 
@@ -87,6 +128,25 @@ where
     }
 }
 
+/// Find out from which origin the request was made, using the `Origin` header,
+/// or falling back to the `Referer` header
+///
+/// Browsers send a `null` origin in some legitimate cases, for example after
+/// a cross-origin redirect or with strict referrer policies, so it is treated
+/// as if the header was missing.
+fn request_origin(headers: &http::HeaderMap) -> Option<String> {
+    if let Some(origin) = headers.get(ORIGIN) {
+        if origin != "null" {
+            // An origin which isn't valid UTF-8 will never match ours
+            return Some(origin.to_str().unwrap_or("invalid").to_owned());
+        }
+    }
+
+    let referer = headers.get(REFERER)?.to_str().ok()?;
+    let referer = Url::parse(referer).ok()?;
+    Some(referer.origin().ascii_serialization())
+}
+
 #[derive(Debug, Clone)]
 struct CookieOption {
     base_url: Url,
@@ -97,6 +157,10 @@ impl CookieOption {
         Self { base_url }
     }
 
+    fn origin(&self) -> String {
+        self.base_url.origin().ascii_serialization()
+    }
+
     fn secure(&self) -> bool {
         self.base_url.scheme() == "https"
     }
@@ -117,7 +181,9 @@ impl CookieOption {
 /// A cookie jar which encrypts cookies & sets secure options
 pub struct CookieJar {
     inner: PrivateCookieJar<Key>,
+    fallbacks: Vec<PrivateCookieJar<Key>>,
     options: CookieOption,
+    request_origin: Option<String>,
 }
 
 impl CookieJar {
@@ -148,19 +214,36 @@ impl CookieJar {
 
     /// Load and deserialize a cookie from the jar
     ///
-    /// Returns `None` if the cookie is not present
+    /// Returns `None` if the cookie is not present, or if it could not be
+    /// decrypted with any of the known keys
     ///
     /// # Errors
     ///
     /// Returns an error if the cookie cannot be deserialized
     pub fn load<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CookieDecodeError> {
-        let Some(cookie) = self.inner.get(key) else {
+        let cookie = self
+            .inner
+            .get(key)
+            .or_else(|| self.fallbacks.iter().find_map(|jar| jar.get(key)));
+
+        let Some(cookie) = cookie else {
             return Ok(None);
         };
 
         let decoded = serde_json::from_str(cookie.value())?;
         Ok(Some(decoded))
     }
+
+    /// Check that the request this jar was extracted from was made from the
+    /// same origin as the one cookies are set for.
+    ///
+    /// Requests which don't tell where they come from are considered to be
+    /// same-origin.
+    pub(crate) fn is_same_origin(&self) -> bool {
+        self.request_origin
+            .as_deref()
+            .map_or(true, |origin| origin == self.options.origin())
+    }
 }
 
 impl IntoResponseParts for CookieJar {
@@ -170,3 +253,84 @@ impl IntoResponseParts for CookieJar {
         self.inner.into_response_parts(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use http::header::{COOKIE, SET_COOKIE};
+
+    use super::*;
+
+    #[test]
+    fn test_same_origin() {
+        let manager = CookieManager::derive_from(
+            Url::parse("https://example.com/account/").unwrap(),
+            &[0x01; 32],
+        );
+        let is_same_origin = |origin: Option<&str>, referer: Option<&str>| {
+            let mut headers = http::HeaderMap::new();
+            if let Some(origin) = origin {
+                headers.insert(ORIGIN, origin.parse().unwrap());
+            }
+            if let Some(referer) = referer {
+                headers.insert(REFERER, referer.parse().unwrap());
+            }
+            manager.cookie_jar_from_headers(&headers).is_same_origin()
+        };
+
+        assert!(is_same_origin(None, None));
+        assert!(is_same_origin(Some("https://example.com"), None));
+        assert!(!is_same_origin(Some("https://evil.example.org"), None));
+        assert!(!is_same_origin(Some("http://example.com"), None));
+
+        // The Referer is used if there is no Origin
+        assert!(is_same_origin(None, Some("https://example.com/login")));
+        assert!(!is_same_origin(None, Some("https://evil.example.org/")));
+
+        // A null Origin falls back to the Referer, or to the CSRF token alone
+        assert!(is_same_origin(Some("null"), None));
+        assert!(is_same_origin(Some("null"), Some("https://example.com/")));
+        assert!(!is_same_origin(
+            Some("null"),
+            Some("https://evil.example.org/")
+        ));
+
+        // The Origin takes precedence over the Referer
+        assert!(!is_same_origin(
+            Some("https://evil.example.org"),
+            Some("https://example.com/")
+        ));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let base_url = Url::parse("https://example.com/").unwrap();
+        let old = CookieManager::derive_from(base_url.clone(), &[0x01; 32]);
+        let new = CookieManager::derive_from_keys(base_url.clone(), &[0x02; 32], [&[0x01; 32][..]]);
+        let other = CookieManager::derive_from(base_url, &[0x02; 32]);
+
+        // Set a cookie with the old key
+        let response = (old.cookie_jar().save("test", &"hello", false), ()).into_response();
+        let set_cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let cookie = set_cookie.split(';').next().unwrap();
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(COOKIE, cookie.parse().unwrap());
+
+        // It can be read with the fallback key
+        let value: Option<String> = new.cookie_jar_from_headers(&headers).load("test").unwrap();
+        assert_eq!(value.as_deref(), Some("hello"));
+
+        // But not by a manager which doesn't know about the old key
+        let value: Option<String> = other
+            .cookie_jar_from_headers(&headers)
+            .load("test")
+            .unwrap();
+        assert_eq!(value, None);
+    }
+}
@@ -44,6 +44,10 @@ pub enum CsrfError {
     /// Failed to decode the token
     #[error("could not decode CSRF token")]
     Decode(#[from] DecodeError),
+
+    /// The form was submitted from another origin
+    #[error("form submitted from another origin")]
+    OriginMismatch,
 }
 
 /// A CSRF token
@@ -124,8 +128,8 @@ pub trait CsrfExt {
     ///
     /// # Errors
     ///
-    /// Returns an error if the CSRF cookie is missing or if the value in the
-    /// form is invalid
+    /// Returns an error if the CSRF cookie is missing, if the value in the
+    /// form is invalid, or if the form was submitted from another origin
     fn verify_form<C, T>(&self, clock: &C, form: ProtectedForm<T>) -> Result<T, CsrfError>
     where
         C: Clock;
@@ -165,6 +169,10 @@ impl CsrfExt for CookieJar {
     where
         C: Clock,
     {
+        if !self.is_same_origin() {
+            return Err(CsrfError::OriginMismatch);
+        }
+
         let token: CsrfToken = self.load("csrf")?.ok_or(CsrfError::Missing)?;
         let token = token.verify_expiration(clock.now())?;
         token.verify_form_value(&form.csrf)?;
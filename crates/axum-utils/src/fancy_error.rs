@@ -20,16 +20,45 @@ use axum::{
 use headers::ContentType;
 use mas_templates::ErrorContext;
 
-use crate::sentry::SentryEventID;
+use crate::{csrf::CsrfError, sentry::SentryEventID};
 
 pub struct FancyError {
+    status: StatusCode,
     context: ErrorContext,
 }
 
 impl FancyError {
     #[must_use]
     pub fn new(context: ErrorContext) -> Self {
-        Self { context }
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            context,
+        }
+    }
+
+    /// Set the status code of the response, which defaults to `500 Internal
+    /// Server Error`
+    #[must_use]
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Build an error from a failed CSRF check, which most of the time means
+    /// that the form was left open for too long, rather than a forged request
+    #[must_use]
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn csrf(error: CsrfError) -> Self {
+        let code = match error {
+            CsrfError::Missing | CsrfError::Expired => "session_expired",
+            _ => "csrf_failed",
+        };
+
+        let context = ErrorContext::new()
+            .with_code(code)
+            .with_description(error.to_string());
+
+        Self::new(context).with_status(StatusCode::FORBIDDEN)
     }
 }
 
@@ -55,16 +84,28 @@ impl<E: std::fmt::Debug + std::fmt::Display> From<E> for FancyError {
         let context = ErrorContext::new()
             .with_description(format!("{err}"))
             .with_details(format!("{err:?}"));
-        FancyError { context }
+        FancyError::new(context)
     }
 }
 
 impl IntoResponse for FancyError {
     fn into_response(self) -> Response {
         let error = format!("{}", self.context);
+
+        // Only server errors are worth reporting
+        if !self.status.is_server_error() {
+            return (
+                self.status,
+                TypedHeader(ContentType::text()),
+                Extension(self.context),
+                error,
+            )
+                .into_response();
+        }
+
         let event_id = sentry::capture_message(&error, sentry::Level::Error);
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            self.status,
             TypedHeader(ContentType::text()),
             SentryEventID::from(event_id),
             Extension(self.context),
@@ -14,8 +14,9 @@
 
 use axum::body::Full;
 use mas_http::{
-    make_traced_connector, BodyToBytesResponseLayer, Client, ClientLayer, ClientService,
-    HttpService, TracedClient, TracedConnector,
+    make_public_traced_connector, make_traced_connector, BodyToBytesResponseLayer, CircuitBreakers,
+    Client, ClientLayer, ClientService, ConnectorOptions, HttpService, PublicTracedClient,
+    PublicTracedConnector, TracedClient, TracedConnector,
 };
 use tower::{
     util::{MapErrLayer, MapRequestLayer},
@@ -25,6 +26,7 @@ use tower::{
 #[derive(Debug, Clone)]
 pub struct HttpClientFactory {
     traced_connector: TracedConnector,
+    public_traced_connector: PublicTracedConnector,
     client_layer: ClientLayer,
 }
 
@@ -38,12 +40,28 @@ impl HttpClientFactory {
     /// Constructs a new HTTP client factory
     #[must_use]
     pub fn new() -> Self {
+        Self::with_options(&ConnectorOptions::default())
+    }
+
+    /// Constructs a new HTTP client factory, with the given options applied to
+    /// the outbound connections, like a proxy or extra trusted root
+    /// certificates
+    #[must_use]
+    pub fn with_options(options: &ConnectorOptions) -> Self {
         Self {
-            traced_connector: make_traced_connector(),
+            traced_connector: make_traced_connector(options),
+            public_traced_connector: make_public_traced_connector(options),
             client_layer: ClientLayer::new(),
         }
     }
 
+    /// The circuit breakers of the servers the clients built by this factory
+    /// talk to
+    #[must_use]
+    pub const fn circuit_breakers(&self) -> &CircuitBreakers {
+        self.client_layer.circuit_breakers()
+    }
+
     /// Constructs a new HTTP client
     pub fn client<B>(&self, category: &'static str) -> ClientService<TracedClient<B>>
     where
@@ -57,6 +75,22 @@ impl HttpClientFactory {
             .layer(client)
     }
 
+    /// Constructs a new HTTP client, suitable for requests to URLs provided by
+    /// untrusted parties: it only connects to publicly routable addresses and
+    /// doesn't follow redirects
+    pub fn public_client<B>(&self, category: &'static str) -> ClientService<PublicTracedClient<B>>
+    where
+        B: axum::body::HttpBody + Send,
+        B::Data: Send,
+    {
+        let client = Client::builder().build(self.public_traced_connector.clone());
+        self.client_layer
+            .clone()
+            .with_category(category)
+            .without_redirects()
+            .layer(client)
+    }
+
     /// Constructs a new [`HttpService`], suitable for `mas-oidc-client`
     pub fn http_service(&self, category: &'static str) -> HttpService {
         let client = self.client(category);
@@ -16,6 +16,7 @@
 #![allow(clippy::module_name_repetitions)]
 
 pub mod client_authorization;
+pub mod client_certificate;
 pub mod cookies;
 pub mod csrf;
 pub mod error_wrapper;
@@ -23,6 +24,7 @@ pub mod fancy_error;
 pub mod http_client_factory;
 pub mod jwt;
 pub mod language_detection;
+pub mod request_id;
 pub mod sentry;
 pub mod session;
 pub mod user_authorization;
@@ -30,7 +32,9 @@ pub mod user_authorization;
 pub use axum;
 
 pub use self::{
+    client_certificate::ClientCertificate,
     error_wrapper::ErrorWrapper,
     fancy_error::FancyError,
+    request_id::RequestId,
     session::{SessionInfo, SessionInfoExt},
 };
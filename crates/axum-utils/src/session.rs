@@ -23,6 +23,12 @@ use crate::cookies::CookieJar;
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SessionInfo {
     current: Option<Ulid>,
+
+    /// Whether the cookie should only last for the browser session. This is
+    /// the case for impersonation sessions, which should never be remembered,
+    /// and for sessions where the user did not ask to stay signed in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    transient: bool,
 }
 
 impl SessionInfo {
@@ -31,13 +37,23 @@ impl SessionInfo {
     pub fn from_session(session: &BrowserSession) -> Self {
         Self {
             current: Some(session.id),
+            transient: session.is_impersonation(),
         }
     }
 
+    /// Set whether the user asked to stay signed in. If not, the cookie only
+    /// lasts for the browser session.
+    #[must_use]
+    pub fn remember(mut self, remember: bool) -> Self {
+        self.transient = self.transient || !remember;
+        self
+    }
+
     /// Mark the session as ended
     #[must_use]
     pub fn mark_session_ended(mut self) -> Self {
         self.current = None;
+        self.transient = false;
         self
     }
 
@@ -78,8 +94,15 @@ pub trait SessionInfoExt {
     where
         Self: Sized,
     {
-        let session_info = SessionInfo::from_session(session);
-        self.update_session_info(&session_info)
+        let (previous, jar) = self.session_info();
+        let mut session_info = SessionInfo::from_session(session);
+
+        // Re-authenticating must not remember a session which wasn't before
+        if previous.current == Some(session.id) {
+            session_info.transient |= previous.transient;
+        }
+
+        jar.update_session_info(&session_info)
     }
 }
 
@@ -99,6 +122,6 @@ impl SessionInfoExt for CookieJar {
     }
 
     fn update_session_info(self, info: &SessionInfo) -> Self {
-        self.save("session", info, true)
+        self.save("session", info, !info.transient)
     }
 }
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, error::Error};
+use std::{convert::Infallible, error::Error};
 
 use async_trait::async_trait;
 use axum::{
@@ -21,16 +21,17 @@ use axum::{
         rejection::{FailedToDeserializeForm, FormRejection, TypedHeaderRejectionReason},
         Form, FromRequest, FromRequestParts, TypedHeader,
     },
-    response::{IntoResponse, Response},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
     BoxError,
 };
 use headers::{authorization::Bearer, Authorization, Header, HeaderMapExt, HeaderName};
-use http::{header::WWW_AUTHENTICATE, HeaderMap, HeaderValue, Request, StatusCode};
+use http::{header::WWW_AUTHENTICATE, HeaderValue, Request, StatusCode};
 use mas_data_model::Session;
 use mas_storage::{
     oauth2::{OAuth2AccessTokenRepository, OAuth2SessionRepository},
     Clock, RepositoryAccess,
 };
+use oauth2_types::scope::ScopeToken;
 use serde::{de::DeserializeOwned, Deserialize};
 use thiserror::Error;
 
@@ -74,6 +75,27 @@ impl AccessToken {
 
         Ok((token, session))
     }
+
+    async fn verify<E>(
+        &self,
+        repo: &mut impl RepositoryAccess<Error = E>,
+        clock: &impl Clock,
+        required_scope: &ScopeToken,
+    ) -> Result<Session, AuthorizationVerificationError<E>> {
+        let (token, session) = self.fetch(repo).await?;
+
+        if !token.is_valid(clock.now()) || !session.is_valid() {
+            return Err(AuthorizationVerificationError::InvalidToken);
+        }
+
+        if !session.scope.contains(required_scope.as_str()) {
+            return Err(AuthorizationVerificationError::InsufficientScope(
+                required_scope.clone(),
+            ));
+        }
+
+        Ok(session)
+    }
 }
 
 #[derive(Debug)]
@@ -83,50 +105,44 @@ pub struct UserAuthorization<F = ()> {
 }
 
 impl<F: Send> UserAuthorization<F> {
-    // TODO: take scopes to validate as parameter
     /// Verify a user authorization and return the session and the protected
     /// form value
     ///
     /// # Errors
     ///
-    /// Returns an error if the token is invalid, if the user session ended or
-    /// if the form is missing
+    /// Returns an error if the token is invalid, if the user session ended, if
+    /// the session doesn't have the required scope or if the form is missing
     pub async fn protected_form<E>(
         self,
         repo: &mut impl RepositoryAccess<Error = E>,
         clock: &impl Clock,
+        required_scope: &ScopeToken,
     ) -> Result<(Session, F), AuthorizationVerificationError<E>> {
         let Some(form) = self.form else {
             return Err(AuthorizationVerificationError::MissingForm);
         };
 
-        let (token, session) = self.access_token.fetch(repo).await?;
-
-        if !token.is_valid(clock.now()) || !session.is_valid() {
-            return Err(AuthorizationVerificationError::InvalidToken);
-        }
+        let session = self
+            .access_token
+            .verify(repo, clock, required_scope)
+            .await?;
 
         Ok((session, form))
     }
 
-    // TODO: take scopes to validate as parameter
     /// Verify a user authorization and return the session
     ///
     /// # Errors
     ///
-    /// Returns an error if the token is invalid or if the user session ended
+    /// Returns an error if the token is invalid, if the user session ended or
+    /// if the session doesn't have the required scope
     pub async fn protected<E>(
         self,
         repo: &mut impl RepositoryAccess<Error = E>,
         clock: &impl Clock,
+        required_scope: &ScopeToken,
     ) -> Result<Session, AuthorizationVerificationError<E>> {
-        let (token, session) = self.access_token.fetch(repo).await?;
-
-        if !token.is_valid(clock.now()) || !session.is_valid() {
-            return Err(AuthorizationVerificationError::InvalidToken);
-        }
-
-        Ok(session)
+        self.access_token.verify(repo, clock, required_scope).await
     }
 }
 
@@ -145,6 +161,9 @@ pub enum AuthorizationVerificationError<E> {
     #[error("invalid token")]
     InvalidToken,
 
+    #[error("missing scope {0}")]
+    InsufficientScope(ScopeToken),
+
     #[error("missing form")]
     MissingForm,
 
@@ -152,33 +171,83 @@ pub enum AuthorizationVerificationError<E> {
     Internal(#[from] E),
 }
 
-enum BearerError {
+/// An error on a request authenticated with a bearer token, as defined in
+/// [RFC 6750 section 3.1](https://www.rfc-editor.org/rfc/rfc6750#section-3.1)
+///
+/// It can be used as a response part to set the `WWW-Authenticate` header, and
+/// gives the status code to respond with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BearerError {
+    /// The request had no authentication information at all
+    MissingToken,
+
+    /// The request is malformed
     InvalidRequest,
+
+    /// The token is unknown, expired or revoked
     InvalidToken,
-    #[allow(dead_code)]
-    InsufficientScope {
-        scope: Option<HeaderValue>,
-    },
+
+    /// The token is valid, but doesn't grant the scope needed for this request
+    InsufficientScope { scope: Option<ScopeToken> },
 }
 
 impl BearerError {
-    fn error(&self) -> HeaderValue {
+    /// The status code to respond with for this error
+    #[must_use]
+    pub const fn status_code(&self) -> StatusCode {
         match self {
-            BearerError::InvalidRequest => HeaderValue::from_static("invalid_request"),
-            BearerError::InvalidToken => HeaderValue::from_static("invalid_token"),
-            BearerError::InsufficientScope { .. } => HeaderValue::from_static("insufficient_scope"),
+            BearerError::InvalidRequest => StatusCode::BAD_REQUEST,
+            BearerError::MissingToken | BearerError::InvalidToken => StatusCode::UNAUTHORIZED,
+            BearerError::InsufficientScope { .. } => StatusCode::FORBIDDEN,
         }
     }
 
-    fn params(&self) -> HashMap<&'static str, HeaderValue> {
+    fn error(&self) -> Option<HeaderValue> {
         match self {
-            BearerError::InsufficientScope { scope: Some(scope) } => {
-                let mut m = HashMap::new();
-                m.insert("scope", scope.clone());
-                m
+            // Per the RFC, no error code is set if no authentication information was
+            // sent
+            BearerError::MissingToken => None,
+            BearerError::InvalidRequest => Some(HeaderValue::from_static("invalid_request")),
+            BearerError::InvalidToken => Some(HeaderValue::from_static("invalid_token")),
+            BearerError::InsufficientScope { .. } => {
+                Some(HeaderValue::from_static("insufficient_scope"))
+            }
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, HeaderValue)> {
+        let mut params = Vec::new();
+
+        if let Some(error) = self.error() {
+            params.push(("error", error));
+        }
+
+        if let BearerError::InsufficientScope { scope: Some(scope) } = self {
+            if let Ok(scope) = HeaderValue::from_str(scope.as_str()) {
+                params.push(("scope", scope));
             }
-            _ => HashMap::new(),
         }
+
+        params
+    }
+}
+
+impl IntoResponseParts for BearerError {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.headers_mut().typed_insert(WwwAuthenticate::Bearer {
+            realm: None,
+            error: self,
+            error_description: None,
+        });
+        Ok(res)
+    }
+}
+
+impl IntoResponse for BearerError {
+    fn into_response(self) -> Response {
+        (self.status_code(), self).into_response()
     }
 }
 
@@ -207,33 +276,38 @@ impl Header for WwwAuthenticate {
 
     fn encode<E: Extend<http::HeaderValue>>(&self, values: &mut E) {
         let (scheme, params) = match self {
-            WwwAuthenticate::Basic { realm } => {
-                let mut params = HashMap::new();
-                params.insert("realm", realm.clone());
-                ("Basic", params)
-            }
+            WwwAuthenticate::Basic { realm } => ("Basic", vec![("realm", realm.clone())]),
             WwwAuthenticate::Bearer {
                 realm,
                 error,
                 error_description,
             } => {
-                let mut params = error.params();
-                params.insert("error", error.error());
+                let mut params = Vec::new();
 
                 if let Some(realm) = realm {
-                    params.insert("realm", realm.clone());
+                    params.push(("realm", realm.clone()));
                 }
 
+                params.extend(error.params());
+
                 if let Some(error_description) = error_description {
-                    params.insert("error_description", error_description.clone());
+                    params.push(("error_description", error_description.clone()));
                 }
 
                 ("Bearer", params)
             }
         };
 
-        let params = params.into_iter().map(|(k, v)| format!(" {k}={v:?}"));
-        let value: String = std::iter::once(scheme.to_owned()).chain(params).collect();
+        let params = params
+            .into_iter()
+            .map(|(k, v)| format!("{k}={v:?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let value = if params.is_empty() {
+            scheme.to_owned()
+        } else {
+            format!("{scheme} {params}")
+        };
         let value = HeaderValue::from_str(&value).unwrap();
         values.extend(std::iter::once(value));
     }
@@ -243,14 +317,7 @@ impl IntoResponse for UserAuthorizationError {
     fn into_response(self) -> Response {
         match self {
             Self::BadForm(_) | Self::InvalidHeader | Self::TokenInFormAndHeader => {
-                let mut headers = HeaderMap::new();
-
-                headers.typed_insert(WwwAuthenticate::Bearer {
-                    realm: None,
-                    error: BearerError::InvalidRequest,
-                    error_description: None,
-                });
-                (StatusCode::BAD_REQUEST, headers).into_response()
+                BearerError::InvalidRequest.into_response()
             }
             Self::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
         }
@@ -263,25 +330,11 @@ where
 {
     fn into_response(self) -> Response {
         match self {
-            Self::MissingForm | Self::MissingToken => {
-                let mut headers = HeaderMap::new();
-
-                headers.typed_insert(WwwAuthenticate::Bearer {
-                    realm: None,
-                    error: BearerError::InvalidRequest,
-                    error_description: None,
-                });
-                (StatusCode::BAD_REQUEST, headers).into_response()
-            }
-            Self::InvalidToken => {
-                let mut headers = HeaderMap::new();
-
-                headers.typed_insert(WwwAuthenticate::Bearer {
-                    realm: None,
-                    error: BearerError::InvalidToken,
-                    error_description: None,
-                });
-                (StatusCode::BAD_REQUEST, headers).into_response()
+            Self::MissingForm => BearerError::InvalidRequest.into_response(),
+            Self::MissingToken => BearerError::MissingToken.into_response(),
+            Self::InvalidToken => BearerError::InvalidToken.into_response(),
+            Self::InsufficientScope(scope) => {
+                BearerError::InsufficientScope { scope: Some(scope) }.into_response()
             }
             Self::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
         }
@@ -342,3 +395,41 @@ where
         Ok(UserAuthorization { access_token, form })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use oauth2_types::scope::OPENID;
+
+    use super::*;
+
+    #[test]
+    fn test_bearer_error_response() {
+        let response = BearerError::MissingToken.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers().get(WWW_AUTHENTICATE).unwrap(), "Bearer");
+
+        let response = BearerError::InvalidToken.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer error="invalid_token""#
+        );
+
+        let response = BearerError::InvalidRequest.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer error="invalid_request""#
+        );
+
+        let response = BearerError::InsufficientScope {
+            scope: Some(OPENID),
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer error="insufficient_scope", scope="openid""#
+        );
+    }
+}
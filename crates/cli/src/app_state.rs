@@ -12,34 +12,95 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{convert::Infallible, net::IpAddr, sync::Arc, time::Instant};
+use std::{
+    convert::Infallible,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use axum::{
     async_trait,
     extract::{FromRef, FromRequestParts},
+    http::{Extensions, HeaderMap},
 };
 use ipnetwork::IpNetwork;
 use mas_data_model::SiteConfig;
 use mas_handlers::{
-    passwords::PasswordManager, ActivityTracker, BoundActivityTracker, CookieManager, ErrorWrapper,
-    GraphQLSchema, HttpClientFactory, MetadataCache,
+    passwords::PasswordManager, ActivityTracker, BoundActivityTracker, ClientLogoCache,
+    CookieManager, DocumentCache, ErrorWrapper, ForwardedPrincipal, GeoIp, GraphQLSchema,
+    HttpClientFactory, IntrospectionCache, Limiter, LoginRisk, MetadataCache, NetworkBans,
+    SpamChecker,
 };
+use mas_http::CircuitState;
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, Keystore};
 use mas_matrix::BoxHomeserverConnection;
 use mas_matrix_synapse::SynapseConnection;
 use mas_policy::{Policy, PolicyFactory};
 use mas_router::UrlBuilder;
-use mas_storage::{BoxClock, BoxRepository, BoxRng, Repository, SystemClock};
+use mas_storage::{
+    compat::CompatSessionFilter, oauth2::OAuth2SessionFilter, user::BrowserSessionFilter,
+    BoxClock, BoxRepository, BoxRng, Clock, Repository, RepositoryAccess, SystemClock,
+};
 use mas_storage_pg::PgRepository;
 use mas_templates::Templates;
 use opentelemetry::{
     metrics::{Histogram, MetricsError, Unit},
     KeyValue,
 };
+use opentelemetry_semantic_conventions::trace::{CLIENT_ADDRESS, SERVER_ADDRESS};
 use rand::SeedableRng;
 use sqlx::PgPool;
 
+/// How often the usage statistics exposed as metrics are refreshed
+const USAGE_STATISTICS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A snapshot of usage statistics, exposed as gauges
+#[derive(Debug, Default)]
+struct UsageStatistics {
+    oauth2_sessions: u64,
+    compat_sessions: u64,
+    browser_sessions: u64,
+    pending_jobs: Vec<(String, u64)>,
+}
+
+impl UsageStatistics {
+    async fn fetch(pool: &PgPool) -> Result<Self, anyhow::Error> {
+        let mut repo = PgRepository::from_pool(pool).await?;
+
+        let oauth2_sessions = repo
+            .oauth2_session()
+            .count(OAuth2SessionFilter::new().active_only())
+            .await?;
+        let compat_sessions = repo
+            .compat_session()
+            .count(CompatSessionFilter::new().active_only())
+            .await?;
+        let browser_sessions = repo
+            .browser_session()
+            .count(BrowserSessionFilter::new().active_only())
+            .await?;
+        Box::new(repo).cancel().await?;
+
+        let pending_jobs: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT job_type, COUNT(*) FROM apalis.jobs WHERE status = 'Pending' GROUP BY job_type",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Self {
+            oauth2_sessions: oauth2_sessions.try_into().unwrap_or(u64::MAX),
+            compat_sessions: compat_sessions.try_into().unwrap_or(u64::MAX),
+            browser_sessions: browser_sessions.try_into().unwrap_or(u64::MAX),
+            pending_jobs: pending_jobs
+                .into_iter()
+                .map(|(job_type, count)| (job_type, count.try_into().unwrap_or_default()))
+                .collect(),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
@@ -54,9 +115,17 @@ pub struct AppState {
     pub http_client_factory: HttpClientFactory,
     pub password_manager: PasswordManager,
     pub metadata_cache: MetadataCache,
+    pub client_logo_cache: ClientLogoCache,
+    pub document_cache: DocumentCache,
     pub site_config: SiteConfig,
     pub activity_tracker: ActivityTracker,
     pub trusted_proxies: Vec<IpNetwork>,
+    pub limiter: Limiter,
+    pub network_bans: NetworkBans,
+    pub spam_checker: SpamChecker,
+    pub login_risk: LoginRisk,
+    pub introspection_cache: IntrospectionCache,
+    pub geoip: GeoIp,
     pub conn_acquisition_histogram: Option<Histogram<u64>>,
 }
 
@@ -105,6 +174,112 @@ impl AppState {
             .init();
         self.conn_acquisition_histogram = Some(histogram);
 
+        // Counting the sessions and jobs is too expensive to be done on every
+        // scrape, so we refresh those statistics in the background
+        let statistics = Arc::new(Mutex::new(UsageStatistics::default()));
+
+        let active_sessions = meter
+            .u64_observable_gauge("mas.sessions.active")
+            .with_description("The number of active sessions, by kind of session")
+            .with_unit(Unit::new("{session}"))
+            .init();
+
+        let pending_jobs = meter
+            .u64_observable_gauge("mas.jobs.pending")
+            .with_description("The number of jobs waiting in the queue, by job name")
+            .with_unit(Unit::new("{job}"))
+            .init();
+
+        let observed = statistics.clone();
+        meter.register_callback(
+            &[active_sessions.as_any(), pending_jobs.as_any()],
+            move |observer| {
+                let statistics = observed.lock().unwrap();
+                observer.observe_u64(
+                    &active_sessions,
+                    statistics.oauth2_sessions,
+                    &[KeyValue::new("session_kind", "oauth2")],
+                );
+                observer.observe_u64(
+                    &active_sessions,
+                    statistics.compat_sessions,
+                    &[KeyValue::new("session_kind", "compat")],
+                );
+                observer.observe_u64(
+                    &active_sessions,
+                    statistics.browser_sessions,
+                    &[KeyValue::new("session_kind", "browser")],
+                );
+                for (job_name, count) in &statistics.pending_jobs {
+                    observer.observe_u64(
+                        &pending_jobs,
+                        *count,
+                        &[KeyValue::new("job.name", job_name.clone())],
+                    );
+                }
+            },
+        )?;
+
+        // Expose the state of the circuit breakers of the outbound HTTP clients
+        let circuit_breaker_state = meter
+            .u64_observable_gauge("http.client.circuit_breaker.state")
+            .with_description("The state of the circuit breaker of the servers the service talks to, set to 1 for the current state")
+            .init();
+
+        let circuit_breakers = self.http_client_factory.circuit_breakers().clone();
+        meter.register_callback(&[circuit_breaker_state.as_any()], move |observer| {
+            for (server, current) in circuit_breakers.states() {
+                for state in [
+                    CircuitState::Closed,
+                    CircuitState::Open,
+                    CircuitState::HalfOpen,
+                ] {
+                    observer.observe_u64(
+                        &circuit_breaker_state,
+                        u64::from(state == current),
+                        &[
+                            KeyValue::new(SERVER_ADDRESS, server.clone()),
+                            KeyValue::new("state", state.as_str()),
+                        ],
+                    );
+                }
+            }
+        })?;
+
+        // Expose how old the cached JWKS of the upstream providers are
+        let jwks_age = meter
+            .f64_observable_gauge("mas.upstream_oauth2.jwks.age")
+            .with_description("How long ago the JWKS of the upstream providers were last fetched")
+            .with_unit(Unit::new("s"))
+            .init();
+
+        let jwks_cache = self.metadata_cache.jwks().clone();
+        meter.register_callback(&[jwks_age.as_any()], move |observer| {
+            for (jwks_uri, age) in jwks_cache.ages(SystemClock::default().now()) {
+                #[allow(clippy::cast_precision_loss)]
+                observer.observe_f64(
+                    &jwks_age,
+                    age.num_milliseconds() as f64 / 1000.0,
+                    &[KeyValue::new("url.full", jwks_uri.to_string())],
+                );
+            }
+        })?;
+
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(USAGE_STATISTICS_INTERVAL);
+            loop {
+                interval.tick().await;
+                match UsageStatistics::fetch(&pool).await {
+                    Ok(fresh) => *statistics.lock().unwrap() = fresh,
+                    Err(e) => tracing::warn!(
+                        error = &*e as &dyn std::error::Error,
+                        "Failed to refresh the usage statistics"
+                    ),
+                }
+            }
+        });
+
         Ok(())
     }
 
@@ -192,18 +367,66 @@ impl FromRef<AppState> for PasswordManager {
     }
 }
 
+impl FromRef<AppState> for Limiter {
+    fn from_ref(input: &AppState) -> Self {
+        input.limiter.clone()
+    }
+}
+
+impl FromRef<AppState> for NetworkBans {
+    fn from_ref(input: &AppState) -> Self {
+        input.network_bans.clone()
+    }
+}
+
+impl FromRef<AppState> for SpamChecker {
+    fn from_ref(input: &AppState) -> Self {
+        input.spam_checker.clone()
+    }
+}
+
+impl FromRef<AppState> for LoginRisk {
+    fn from_ref(input: &AppState) -> Self {
+        input.login_risk.clone()
+    }
+}
+
+impl FromRef<AppState> for IntrospectionCache {
+    fn from_ref(input: &AppState) -> Self {
+        input.introspection_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for GeoIp {
+    fn from_ref(input: &AppState) -> Self {
+        input.geoip.clone()
+    }
+}
+
 impl FromRef<AppState> for CookieManager {
     fn from_ref(input: &AppState) -> Self {
         input.cookie_manager.clone()
     }
 }
 
+impl FromRef<AppState> for DocumentCache {
+    fn from_ref(input: &AppState) -> Self {
+        input.document_cache.clone()
+    }
+}
+
 impl FromRef<AppState> for MetadataCache {
     fn from_ref(input: &AppState) -> Self {
         input.metadata_cache.clone()
     }
 }
 
+impl FromRef<AppState> for ClientLogoCache {
+    fn from_ref(input: &AppState) -> Self {
+        input.client_logo_cache.clone()
+    }
+}
+
 impl FromRef<AppState> for SiteConfig {
     fn from_ref(input: &AppState) -> Self {
         input.site_config.clone()
@@ -271,51 +494,97 @@ impl FromRequestParts<AppState> for ActivityTracker {
     }
 }
 
-fn infer_client_ip(
-    parts: &axum::http::request::Parts,
-    trusted_proxies: &[IpNetwork],
-) -> Option<IpAddr> {
-    let connection_info = parts.extensions.get::<mas_listener::ConnectionInfo>();
+/// Get the IP address of the peer directly connected to us, as reported by
+/// the listener
+fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    let info = extensions.get::<mas_listener::ConnectionInfo>()?;
 
-    let peer = if let Some(info) = connection_info {
-        // We can always trust the proxy protocol to give us the correct IP address
-        if let Some(proxy) = info.get_proxy_ref() {
-            if let Some(source) = proxy.source() {
-                return Some(source.ip());
-            }
+    // We can always trust the proxy protocol to give us the correct IP address
+    if let Some(proxy) = info.get_proxy_ref() {
+        if let Some(source) = proxy.source() {
+            return Some(source.ip());
         }
+    }
 
-        info.get_peer_addr().map(|addr| addr.ip())
-    } else {
-        None
-    };
+    info.get_peer_addr().map(|addr| addr.ip())
+}
+
+/// Infer the IP address of the client, walking through the `X-Forwarded-For`
+/// header as long as the hops are trusted proxies
+pub(crate) fn infer_client_ip(
+    extensions: &Extensions,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNetwork],
+) -> Option<IpAddr> {
+    let peer = peer_ip(extensions);
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|network| network.contains(*ip));
+
+    // The header can only be trusted if a trusted proxy set it. Connections
+    // without a peer address come through a local UNIX socket.
+    if peer.is_some_and(|ip| !is_trusted(&ip)) {
+        return peer;
+    }
 
     // Get the list of IPs from the X-Forwarded-For header
-    let peers_from_header = parts
-        .headers
-        .get("x-forwarded-for")
-        .and_then(|value| value.to_str().ok())
-        .map(|value| value.split(',').filter_map(|v| v.parse().ok()))
-        .into_iter()
-        .flatten();
-
-    // This constructs a list of IP addresses that might be the client's IP address.
-    // Each intermediate proxy is supposed to add the client's IP address to front
-    // of the list. We are effectively adding the IP we got from the socket to the
-    // front of the list.
-    let peer_list: Vec<IpAddr> = peer.into_iter().chain(peers_from_header).collect();
-
-    // We'll fallback to the first IP in the list if all the IPs we got are trusted
-    let fallback = peer_list.first().copied();
-
-    // Now we go through the list, and the IP of the client is the first IP that is
-    // not in the list of trusted proxies, starting from the back.
-    let client_ip = peer_list
+    let peers_from_header: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
         .iter()
-        .rfind(|ip| !trusted_proxies.iter().any(|network| network.contains(**ip)))
-        .copied();
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(',').filter_map(|v| v.trim().parse().ok()))
+        .collect();
+
+    // Each proxy appends the IP address it received the request from to the
+    // header, so the chain of hops is the header followed by the peer. We walk it
+    // from the right for as long as the hops are trusted proxies: the client is
+    // the first untrusted hop, or the leftmost one if all of them are trusted.
+    let mut client_ip = None;
+    for ip in peers_from_header.into_iter().chain(peer).rev() {
+        client_ip = Some(ip);
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+
+    client_ip
+}
+
+/// Whether the peer directly connected to us is one of the trusted proxies
+fn is_trusted_peer(extensions: &Extensions, trusted_proxies: &[IpNetwork]) -> bool {
+    peer_ip(extensions).is_some_and(|ip| trusted_proxies.iter().any(|network| network.contains(ip)))
+}
+
+/// Infer the scheme used by the client to reach us.
+///
+/// The `X-Forwarded-Proto` header is only honored if the peer directly
+/// connected to us is a trusted proxy.
+pub(crate) fn infer_client_scheme(
+    extensions: &Extensions,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNetwork],
+) -> &'static str {
+    let connection_info = extensions.get::<mas_listener::ConnectionInfo>();
+
+    if is_trusted_peer(extensions, trusted_proxies) {
+        let forwarded_proto = headers
+            .get("x-forwarded-proto")
+            .and_then(|value| value.to_str().ok())
+            // In case multiple proxies appended to the header, the first one is the
+            // closest to the client
+            .and_then(|value| value.split(',').next())
+            .map(str::trim);
+
+        match forwarded_proto {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => return "https",
+            Some(proto) if proto.eq_ignore_ascii_case("http") => return "http",
+            _ => {}
+        }
+    }
 
-    client_ip.or(fallback)
+    if connection_info.is_some_and(|info| info.get_tls_ref().is_some()) {
+        "https"
+    } else {
+        "http"
+    }
 }
 
 #[async_trait]
@@ -326,12 +595,45 @@ impl FromRequestParts<AppState> for BoundActivityTracker {
         parts: &mut axum::http::request::Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let ip = infer_client_ip(parts, &state.trusted_proxies);
+        let ip = infer_client_ip(&parts.extensions, &parts.headers, &state.trusted_proxies);
         tracing::debug!(ip = ?ip, "Inferred client IP address");
+        if let Some(ip) = ip {
+            tracing::Span::current().record(CLIENT_ADDRESS, tracing::field::display(ip));
+        }
         Ok(state.activity_tracker.clone().bind(ip))
     }
 }
 
+#[async_trait]
+impl FromRequestParts<AppState> for ForwardedPrincipal {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(config) = &state.site_config.spnego_login else {
+            return Ok(Self(None));
+        };
+
+        // The header is set by the reverse proxy which negotiated the Kerberos
+        // ticket, so only trust it if the request comes directly from it
+        if !is_trusted_peer(&parts.extensions, &state.trusted_proxies) {
+            return Ok(Self(None));
+        }
+
+        let principal = parts
+            .headers
+            .get(config.principal_header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(ToOwned::to_owned);
+
+        Ok(Self(principal))
+    }
+}
+
 #[async_trait]
 impl FromRequestParts<AppState> for BoxRepository {
     type Rejection = ErrorWrapper<mas_storage_pg::DatabaseError>;
@@ -356,3 +658,118 @@ impl FromRequestParts<AppState> for BoxRepository {
             .boxed())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted_proxies() -> Vec<IpNetwork> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()]
+    }
+
+    fn peer(addr: &str) -> Extensions {
+        let mut extensions = Extensions::new();
+        extensions.insert(mas_listener::ConnectionInfo::from_peer_addr(
+            addr.parse().unwrap(),
+        ));
+        extensions
+    }
+
+    fn headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_infer_client_ip() {
+        let extensions = Extensions::new();
+        let trusted_proxies = trusted_proxies();
+
+        // The last untrusted hop is the client
+        let ip = infer_client_ip(
+            &extensions,
+            &headers("192.0.2.1, 198.51.100.1, 10.0.0.1"),
+            &trusted_proxies,
+        );
+        assert_eq!(ip, Some("198.51.100.1".parse().unwrap()));
+
+        // If all the hops are trusted, fallback to the first one
+        let ip = infer_client_ip(&extensions, &headers("10.0.0.2, ::1"), &trusted_proxies);
+        assert_eq!(ip, Some("10.0.0.2".parse().unwrap()));
+
+        // Garbage in the header is ignored
+        let ip = infer_client_ip(&extensions, &headers("foo, 10.0.0.2"), &trusted_proxies);
+        assert_eq!(ip, Some("10.0.0.2".parse().unwrap()));
+
+        let ip = infer_client_ip(&extensions, &HeaderMap::new(), &trusted_proxies);
+        assert_eq!(ip, None);
+    }
+
+    #[test]
+    fn test_infer_client_ip_untrusted_peer() {
+        let extensions = peer("203.0.113.1:1234");
+        let trusted_proxies = trusted_proxies();
+
+        // A client connecting directly can't spoof its address through the header
+        let ip = infer_client_ip(
+            &extensions,
+            &headers("192.0.2.1, 10.0.0.1"),
+            &trusted_proxies,
+        );
+        assert_eq!(ip, Some("203.0.113.1".parse().unwrap()));
+
+        let ip = infer_client_ip(&extensions, &HeaderMap::new(), &trusted_proxies);
+        assert_eq!(ip, Some("203.0.113.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_infer_client_ip_trusted_peer() {
+        let extensions = peer("10.0.0.1:1234");
+        let trusted_proxies = trusted_proxies();
+
+        // Hops left of the first untrusted one were added by the client, and are
+        // ignored
+        let ip = infer_client_ip(
+            &extensions,
+            &headers("192.0.2.1, 198.51.100.1, 10.0.0.2"),
+            &trusted_proxies,
+        );
+        assert_eq!(ip, Some("198.51.100.1".parse().unwrap()));
+
+        // If all the hops are trusted, fallback to the leftmost one
+        let ip = infer_client_ip(&extensions, &headers("10.0.0.2"), &trusted_proxies);
+        assert_eq!(ip, Some("10.0.0.2".parse().unwrap()));
+
+        // Without the header, the client is the proxy itself
+        let ip = infer_client_ip(&extensions, &HeaderMap::new(), &trusted_proxies);
+        assert_eq!(ip, Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_infer_client_scheme() {
+        let extensions = Extensions::new();
+        let trusted_proxies = trusted_proxies();
+
+        // Without a trusted peer, the X-Forwarded-Proto header is ignored
+        let scheme = infer_client_scheme(&extensions, &headers("10.0.0.1"), &trusted_proxies);
+        assert_eq!(scheme, "http");
+
+        let scheme = infer_client_scheme(&extensions, &HeaderMap::new(), &trusted_proxies);
+        assert_eq!(scheme, "http");
+
+        // Nor is it from an untrusted peer
+        let extensions = peer("203.0.113.1:1234");
+        let scheme = infer_client_scheme(&extensions, &headers("10.0.0.1"), &trusted_proxies);
+        assert_eq!(scheme, "http");
+
+        // But it is from a trusted one
+        let extensions = peer("10.0.0.1:1234");
+        let scheme = infer_client_scheme(&extensions, &headers("10.0.0.1"), &trusted_proxies);
+        assert_eq!(scheme, "https");
+
+        let scheme = infer_client_scheme(&extensions, &HeaderMap::new(), &trusted_proxies);
+        assert_eq!(scheme, "http");
+    }
+}
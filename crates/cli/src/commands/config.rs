@@ -14,12 +14,12 @@
 
 use anyhow::Context;
 use camino::Utf8PathBuf;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use figment::Figment;
-use mas_config::{ConfigurationSection, RootConfig, SyncConfig};
+use mas_config::{ConfigurationSection, RootConfig, SecretsConfig, SigningKeyKind, SyncConfig};
 use mas_storage::SystemClock;
 use mas_storage_pg::MIGRATOR;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use tokio::io::AsyncWriteExt;
 use tracing::{info, info_span, Instrument};
 
@@ -54,6 +54,39 @@ enum Subcommand {
         output: Option<Utf8PathBuf>,
     },
 
+    /// Generate a new cookie encryption key and output the updated secrets
+    /// section
+    ///
+    /// The new key is used to encrypt new cookies, while the previous keys
+    /// are kept to decrypt existing ones.
+    AddCookieKey {
+        /// The path to the file to write the secrets section to
+        ///
+        /// If not specified, it will be written to stdout
+        #[clap(short, long)]
+        output: Option<Utf8PathBuf>,
+
+        /// Maximum number of keys to keep, including the new one
+        #[clap(long)]
+        keep: Option<usize>,
+    },
+
+    /// Generate a new signing key and output the updated secrets section
+    ///
+    /// The new key is added after the existing ones, so it only gets used
+    /// for signing if there was no key for the same algorithms yet.
+    AddSigningKey {
+        /// The kind of key to generate
+        #[clap(long, value_enum, default_value_t = KeyKind::Rsa)]
+        kind: KeyKind,
+
+        /// The path to the file to write the secrets section to
+        ///
+        /// If not specified, it will be written to stdout
+        #[clap(short, long)]
+        output: Option<Utf8PathBuf>,
+    },
+
     /// Sync the clients and providers from the config file to the database
     Sync {
         /// Prune elements that are in the database but not in the config file
@@ -67,6 +100,33 @@ enum Subcommand {
     },
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum KeyKind {
+    /// RSA key, for the RS256, RS384, RS512, PS256, PS384 and PS512
+    /// algorithms
+    Rsa,
+
+    /// EC key on the P-256 curve, for the ES256 algorithm
+    EcP256,
+
+    /// EC key on the P-384 curve, for the ES384 algorithm
+    EcP384,
+
+    /// EC key on the secp256k1 curve, for the ES256K algorithm
+    EcK256,
+}
+
+impl From<KeyKind> for SigningKeyKind {
+    fn from(kind: KeyKind) -> Self {
+        match kind {
+            KeyKind::Rsa => Self::Rsa,
+            KeyKind::EcP256 => Self::EcP256,
+            KeyKind::EcP384 => Self::EcP384,
+            KeyKind::EcK256 => Self::EcK256,
+        }
+    }
+}
+
 impl Options {
     pub async fn run(self, figment: &Figment) -> anyhow::Result<()> {
         use Subcommand as SC;
@@ -112,10 +172,72 @@ impl Options {
                 }
             }
 
+            SC::AddCookieKey { output, keep } => {
+                let _span = info_span!("cli.config.add_cookie_key").entered();
+
+                let mut secrets = SecretsConfig::extract(figment)?;
+
+                // If no cookie keys were set, cookies were encrypted using the `encryption`
+                // secret, so keep it as a fallback
+                let (primary, fallbacks) = secrets.cookie_encryption_keys().await?;
+                let mut cookie_keys = Vec::with_capacity(fallbacks.len() + 2);
+                // XXX: we should disallow SeedableRng::from_entropy
+                cookie_keys.push(rand_chacha::ChaChaRng::from_entropy().gen());
+                cookie_keys.push(primary);
+                cookie_keys.extend(fallbacks);
+
+                if let Some(keep) = keep {
+                    cookie_keys.truncate(keep.max(1));
+                }
+
+                info!(
+                    "Added a new cookie key, {count} keys are now configured",
+                    count = cookie_keys.len()
+                );
+                secrets.cookie_keys = cookie_keys;
+
+                let section = std::collections::BTreeMap::from([("secrets", secrets)]);
+                let section = serde_yaml::to_string(&section)?;
+
+                if let Some(output) = output {
+                    info!("Writing secrets section to {output:?}");
+                    let mut file = tokio::fs::File::create(output).await?;
+                    file.write_all(section.as_bytes()).await?;
+                } else {
+                    info!("Writing secrets section to standard output");
+                    tokio::io::stdout().write_all(section.as_bytes()).await?;
+                }
+            }
+
+            SC::AddSigningKey { kind, output } => {
+                let _span = info_span!("cli.config.add_signing_key").entered();
+
+                let mut secrets = SecretsConfig::extract(figment)?;
+
+                // XXX: we should disallow SeedableRng::from_entropy
+                let rng = rand_chacha::ChaChaRng::from_entropy();
+                secrets.add_signing_key(rng, kind.into()).await?;
+
+                info!("Added a new {kind:?} signing key");
+
+                let section = std::collections::BTreeMap::from([("secrets", secrets)]);
+                let section = serde_yaml::to_string(&section)?;
+
+                if let Some(output) = output {
+                    info!("Writing secrets section to {output:?}");
+                    let mut file = tokio::fs::File::create(output).await?;
+                    file.write_all(section.as_bytes()).await?;
+                } else {
+                    info!("Writing secrets section to standard output");
+                    tokio::io::stdout().write_all(section.as_bytes()).await?;
+                }
+            }
+
             SC::Sync { prune, dry_run } => {
                 let config = SyncConfig::extract(figment)?;
                 let clock = SystemClock::default();
-                let encrypter = config.secrets.encrypter();
+                let mut rng = rand_chacha::ChaChaRng::from_entropy();
+                let encrypter = config.secrets.encrypter().await?;
 
                 // Grab a connection to the database
                 let mut conn = database_connection_from_config(&config.database).await?;
@@ -131,6 +253,7 @@ impl Options {
                     config.clients,
                     &mut conn,
                     &encrypter,
+                    &mut rng,
                     &clock,
                     prune,
                     dry_run,
@@ -17,7 +17,7 @@ use figment::Figment;
 use hyper::{Response, Uri};
 use mas_config::{ConfigurationSection, PolicyConfig};
 use mas_handlers::HttpClientFactory;
-use mas_http::HttpServiceExt;
+use mas_http::{EmptyBody, HttpServiceExt};
 use tokio::io::AsyncWriteExt;
 use tower::{Service, ServiceExt};
 use tracing::{info, info_span};
@@ -77,9 +77,7 @@ impl Options {
             } => {
                 let _span = info_span!("cli.debug.http").entered();
                 let mut client = http_client_factory.client("debug");
-                let request = hyper::Request::builder()
-                    .uri(url)
-                    .body(hyper::Body::empty())?;
+                let request = hyper::Request::builder().uri(url).body(EmptyBody::new())?;
 
                 let response = client.ready().await?.call(request).await?;
                 let (parts, body) = response.into_parts();
@@ -103,9 +101,7 @@ impl Options {
                     .client("debug")
                     .response_body_to_bytes()
                     .json_response();
-                let request = hyper::Request::builder()
-                    .uri(url)
-                    .body(hyper::Body::empty())?;
+                let request = hyper::Request::builder().uri(url).body(EmptyBody::new())?;
 
                 let response: Response<serde_json::Value> =
                     client.ready().await?.call(request).await?;
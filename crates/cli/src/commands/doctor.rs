@@ -21,12 +21,13 @@ use anyhow::Context;
 use clap::Parser;
 use figment::Figment;
 use mas_config::{ConfigurationSection, RootConfig};
-use mas_handlers::HttpClientFactory;
-use mas_http::HttpServiceExt;
+use mas_http::{EmptyBody, HttpServiceExt};
 use tower::{Service, ServiceExt};
 use tracing::{error, info, info_span, warn};
 use url::{Host, Url};
 
+use crate::util::http_client_factory_from_config;
+
 /// Base URL for the human-readable documentation
 const DOCS_BASE: &str = "https://matrix-org.github.io/matrix-authentication-service";
 
@@ -42,7 +43,7 @@ impl Options {
         let config = RootConfig::extract(figment)?;
 
         // We'll need an HTTP client
-        let http_client_factory = HttpClientFactory::new();
+        let http_client_factory = http_client_factory_from_config(&config.http)?;
         let base_url = config.http.public_base.as_str();
         let issuer = config.http.issuer.as_ref().map(url::Url::as_str);
         let issuer = issuer.unwrap_or(base_url);
@@ -68,7 +69,7 @@ This means some clients will refuse to use it."#
 
         let request = hyper::Request::builder()
             .uri(&well_known_uri)
-            .body(hyper::Body::empty())?;
+            .body(EmptyBody::new())?;
         let result = client.ready().await?.call(request).await;
 
         let expected_well_known = serde_json::json!({
@@ -180,7 +181,7 @@ Error details: {e}
         let client_versions = hs_api.join("/_matrix/client/versions")?;
         let request = hyper::Request::builder()
             .uri(client_versions.as_str())
-            .body(hyper::Body::empty())?;
+            .body(EmptyBody::new())?;
         let result = client.ready().await?.call(request).await;
         let can_reach_cs = match result {
             Ok(response) => {
@@ -234,7 +235,7 @@ Error details: {e}
                     "Bearer averyinvalidtokenireallyhopethisisnotvalid",
                 )
                 .uri(whoami.as_str())
-                .body(hyper::Body::empty())?;
+                .body(EmptyBody::new())?;
             let result = client.ready().await?.call(request).await;
             match result {
                 Ok(response) => {
@@ -284,7 +285,7 @@ Error details: {e}
             let server_version = hs_api.join("/_synapse/admin/v1/server_version")?;
             let request = hyper::Request::builder()
                 .uri(server_version.as_str())
-                .body(hyper::Body::empty())?;
+                .body(EmptyBody::new())?;
             let result = client.ready().await?.call(request).await;
             match result {
                 Ok(response) => {
@@ -313,7 +314,7 @@ Error details: {e}
             let request = hyper::Request::builder()
                 .uri(background_updates.as_str())
                 .header("Authorization", format!("Bearer {admin_token}"))
-                .body(hyper::Body::empty())?;
+                .body(EmptyBody::new())?;
             let result = client.ready().await?.call(request).await;
             match result {
                 Ok(response) => {
@@ -361,7 +362,7 @@ Error details: {e}
         let compat_login = compat_login.as_str();
         let request = hyper::Request::builder()
             .uri(compat_login)
-            .body(hyper::Body::empty())?;
+            .body(EmptyBody::new())?;
         let result = client.ready().await?.call(request).await;
         match result {
             Ok(response) => {
@@ -12,31 +12,45 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{btree_map::Entry, BTreeMap};
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use clap::{ArgAction, CommandFactory, Parser};
 use console::{pad_str, style, Alignment, Style, Term};
 use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, Password};
 use figment::Figment;
-use mas_config::{ConfigurationSection, DatabaseConfig, MatrixConfig, PasswordsConfig};
-use mas_data_model::{Device, TokenType, Ulid, UpstreamOAuthProvider, User};
-use mas_email::Address;
-use mas_handlers::HttpClientFactory;
+use mas_config::{
+    BrandingConfig, CaptchaConfig, ConfigurationSection, DatabaseConfig, EmailConfig,
+    ExperimentalConfig, HttpConfig, IntrospectionConfig, MatrixConfig, PasswordsConfig,
+    RedisConfig, SecretsConfig, TemplatesConfig,
+};
+use mas_data_model::{
+    AccessToken, Device, Session, TokenType, Ulid, UpstreamOAuthProvider, User,
+};
+use mas_email::{Address, Mailbox};
 use mas_matrix::HomeserverConnection;
 use mas_matrix_synapse::SynapseConnection;
 use mas_storage::{
     compat::{CompatAccessTokenRepository, CompatSessionRepository},
-    job::{DeactivateUserJob, DeleteDeviceJob, JobRepositoryExt, ProvisionUserJob},
+    job::{
+        DeactivateUserJob, DeleteDeviceJob, JobRepositoryExt, ProvisionDeviceJob, ProvisionUserJob,
+    },
+    oauth2::{OAuth2AccessTokenRepository, OAuth2ClientRepository, OAuth2SessionRepository},
     user::{UserEmailRepository, UserPasswordRepository, UserRepository},
-    Clock, RepositoryAccess, SystemClock,
+    Clock, Pagination, RepositoryAccess, SystemClock,
 };
 use mas_storage_pg::{DatabaseError, PgRepository};
+use oauth2_types::scope::Scope;
 use rand::{RngCore, SeedableRng};
 use sqlx::{types::Uuid, Acquire};
 use tracing::{info, info_span, warn};
 
-use crate::util::{database_connection_from_config, password_manager_from_config};
+use crate::util::{
+    database_connection_from_config, http_client_factory_from_config,
+    introspection_cache_from_config, mailer_from_config, password_manager_from_config,
+    site_config_from_config, templates_from_config,
+};
 
 const USER_ATTRIBUTES_HEADING: &str = "User attributes";
 
@@ -68,6 +82,16 @@ enum Subcommand {
     /// Mark email address as verified
     VerifyEmail { username: String, email: String },
 
+    /// Send a test email, to check that emails get delivered with the
+    /// configured transport
+    TestEmail {
+        /// Email address to send the test email to
+        to: Address,
+    },
+
+    /// List the emails which could not be delivered, even after retrying
+    ListFailedEmails,
+
     /// Set a user password
     SetPassword { username: String, password: String },
 
@@ -85,6 +109,24 @@ enum Subcommand {
         admin: bool,
     },
 
+    /// Issue a long-lived, scope-restricted access token for a bot or a bridge
+    ///
+    /// The user is created and flagged as a non-interactive account if it
+    /// doesn't exist yet.
+    IssueBotToken {
+        /// Username of the bot
+        username: String,
+
+        /// ID of the OAuth 2.0 client for which to issue the token, e.g. one of
+        /// the static clients from the configuration
+        #[arg(long)]
+        client_id: Ulid,
+
+        /// Scope of the token
+        #[arg(long, default_value = "urn:matrix:org.matrix.msc2967.client:api:*")]
+        scope: Scope,
+    },
+
     /// Trigger a provisioning job for all users
     ProvisionAllUsers,
 
@@ -98,6 +140,30 @@ enum Subcommand {
         dry_run: bool,
     },
 
+    /// Revoke all the sessions, and their tokens, matching the given
+    /// criteria, e.g. after a client secret or key compromise
+    ///
+    /// At least one of the criteria must be set.
+    RevokeTokens {
+        /// Only revoke the sessions of this user
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Only revoke the sessions of this OAuth 2.0 client. Compatibility
+        /// sessions are not revoked if set
+        #[arg(long)]
+        client: Option<Ulid>,
+
+        /// Only revoke the sessions started before this date, in the RFC 3339
+        /// format, e.g. `2024-07-01T00:00:00Z`
+        #[arg(long)]
+        before: Option<DateTime<Utc>>,
+
+        /// Do a dry run
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Lock a user
     LockUser {
         /// User to lock
@@ -114,6 +180,19 @@ enum Subcommand {
         username: String,
     },
 
+    /// Shadow-ban a user
+    ///
+    /// The user can still sign in, but their tokens are flagged on
+    /// introspection so that the homeserver can silently drop their traffic.
+    ShadowBanUser {
+        /// User to shadow-ban
+        username: String,
+
+        /// Lift the shadow-ban instead
+        #[arg(long)]
+        lift: bool,
+    },
+
     /// Register a user
     ///
     /// This will interactively prompt for the user's attributes unless the
@@ -239,6 +318,83 @@ impl Options {
                 Ok(())
             }
 
+            SC::TestEmail { to } => {
+                let _span = info_span!("cli.manage.test_email", email.to = %to).entered();
+
+                let email_config = EmailConfig::extract(figment)?;
+                let http_config = HttpConfig::extract(figment)?;
+                let template_config = TemplatesConfig::extract(figment)?;
+                let branding_config = BrandingConfig::extract(figment)?;
+                let matrix_config = MatrixConfig::extract(figment)?;
+                let experimental_config = ExperimentalConfig::extract(figment)?;
+                let password_config = PasswordsConfig::extract(figment)?;
+                let captcha_config = CaptchaConfig::extract(figment)?;
+
+                let http_client_factory = http_client_factory_from_config(&http_config)?;
+                let url_builder =
+                    mas_router::UrlBuilder::new(http_config.public_base, http_config.issuer, None);
+                let site_config = site_config_from_config(
+                    &branding_config,
+                    &matrix_config,
+                    &experimental_config,
+                    &password_config,
+                    &captcha_config,
+                )?;
+                let templates =
+                    templates_from_config(&template_config, &site_config, &url_builder).await?;
+
+                let mailer = mailer_from_config(&email_config, &templates, &http_client_factory)?;
+                mailer.test_connection().await?;
+
+                mailer.send_test_email(Mailbox::new(None, to)).await?;
+                info!("Test email sent");
+
+                Ok(())
+            }
+
+            SC::ListFailedEmails => {
+                let _span = info_span!("cli.manage.list_failed_emails").entered();
+                let database_config = DatabaseConfig::extract(figment)?;
+                let encrypter = SecretsConfig::extract(figment)?.encrypter().await?;
+                let mut conn = database_connection_from_config(&database_config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let count = repo.email_dead_letter().count().await?;
+                info!("{count} email(s) could not be delivered");
+
+                let mut cursor = Pagination::first(100);
+                loop {
+                    let page = repo.email_dead_letter().list(cursor).await?;
+
+                    for dead_letter in page.edges {
+                        // The subject is stored encrypted, as it may contain a code or a link
+                        let subject = encrypter.decrypt_string(&dead_letter.subject).map_or_else(
+                            |_| "(undecryptable subject)".to_owned(),
+                            |subject| String::from_utf8_lossy(&subject).into_owned(),
+                        );
+                        warn!(
+                            %dead_letter.id,
+                            %dead_letter.created_at,
+                            dead_letter.recipients = %dead_letter.recipients.join(", "),
+                            dead_letter.attempts,
+                            %dead_letter.last_error,
+                            "{subject}",
+                        );
+
+                        cursor = cursor.after(dead_letter.id);
+                    }
+
+                    if !page.has_next_page {
+                        break;
+                    }
+                }
+
+                repo.into_inner().commit().await?;
+
+                Ok(())
+            }
+
             SC::IssueCompatibilityToken {
                 username,
                 admin,
@@ -287,6 +443,36 @@ impl Options {
                 Ok(())
             }
 
+            SC::IssueBotToken {
+                username,
+                client_id,
+                scope,
+            } => {
+                let _span =
+                    info_span!("cli.manage.issue_bot_token", user.username = username).entered();
+                let database_config = DatabaseConfig::extract(figment)?;
+                let mut conn = database_connection_from_config(&database_config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let (user, session, access_token) =
+                    issue_bot_token(&mut repo, &mut rng, &clock, username, client_id, scope)
+                        .await?;
+
+                repo.into_inner().commit().await?;
+
+                info!(
+                    %access_token.id,
+                    %session.id,
+                    %session.scope,
+                    %user.id,
+                    %user.username,
+                    "Bot token issued: {}", access_token.access_token
+                );
+
+                Ok(())
+            }
+
             SC::ProvisionAllUsers => {
                 let _span = info_span!("cli.manage.provision_all_users").entered();
                 let database_config = DatabaseConfig::extract(figment)?;
@@ -430,6 +616,194 @@ impl Options {
                 Ok(())
             }
 
+            SC::RevokeTokens {
+                user,
+                client,
+                before,
+                dry_run,
+            } => {
+                let _span = info_span!("cli.manage.revoke_tokens").entered();
+
+                if user.is_none() && client.is_none() && before.is_none() {
+                    anyhow::bail!("At least one of --user, --client or --before must be set");
+                }
+
+                let database_config = DatabaseConfig::extract(figment)?;
+                let mut conn = database_connection_from_config(&database_config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let user = if let Some(username) = user {
+                    let user = repo
+                        .user()
+                        .find_by_username(&username)
+                        .await?
+                        .context("User not found")?;
+                    Some(user)
+                } else {
+                    None
+                };
+
+                if let Some(client_id) = client {
+                    repo.oauth2_client()
+                        .lookup(client_id)
+                        .await?
+                        .context("Client not found")?;
+                }
+
+                let user_id = user.as_ref().map(|user| Uuid::from(user.id));
+                let client_id = client.map(Uuid::from);
+
+                // Keep the users around, to schedule the device deletion jobs
+                let mut users = BTreeMap::new();
+                if let Some(user) = user {
+                    users.insert(user.id, user);
+                }
+
+                // Keep the finished sessions around, to invalidate their cached introspection
+                // results once the transaction is committed
+                let mut finished_sessions = Vec::new();
+
+                let oauth2_sessions_ids: Vec<Uuid> = sqlx::query_scalar(
+                    r"
+                        SELECT oauth2_session_id FROM oauth2_sessions
+                        WHERE finished_at IS NULL
+                          AND ($1::uuid IS NULL OR user_id = $1)
+                          AND ($2::uuid IS NULL OR oauth2_client_id = $2)
+                          AND ($3::timestamptz IS NULL OR created_at < $3)
+                    ",
+                )
+                .bind(user_id)
+                .bind(client_id)
+                .bind(before)
+                .fetch_all(&mut **repo)
+                .await?;
+
+                info!(
+                    "Revoking {} OAuth 2.0 session(s)",
+                    oauth2_sessions_ids.len()
+                );
+
+                for id in oauth2_sessions_ids {
+                    let id = id.into();
+                    let oauth2_session = repo
+                        .oauth2_session()
+                        .lookup(id)
+                        .await?
+                        .context("Session not found")?;
+                    info!(%oauth2_session.id, %oauth2_session.client_id, %oauth2_session.scope, "Revoking oauth2 session");
+
+                    if dry_run {
+                        continue;
+                    }
+
+                    // Sessions from the client credentials grant don't have a user, nor devices
+                    if let Some(user_id) = oauth2_session.user_id {
+                        let user = match users.entry(user_id) {
+                            Entry::Occupied(entry) => entry.into_mut(),
+                            Entry::Vacant(entry) => {
+                                let user = repo
+                                    .user()
+                                    .lookup(user_id)
+                                    .await?
+                                    .context("User not found")?;
+                                entry.insert(user)
+                            }
+                        };
+
+                        for scope in &*oauth2_session.scope {
+                            if let Some(device) = Device::from_scope_token(scope) {
+                                // Schedule a job to delete the device.
+                                repo.job()
+                                    .schedule_job(DeleteDeviceJob::new(user, &device))
+                                    .await?;
+                            }
+                        }
+                    }
+
+                    finished_sessions.push(oauth2_session.id);
+                    repo.oauth2_session().finish(&clock, oauth2_session).await?;
+                }
+
+                // Compatibility sessions don't belong to any OAuth 2.0 client
+                if client_id.is_none() {
+                    let compat_sessions_ids: Vec<Uuid> = sqlx::query_scalar(
+                        r"
+                            SELECT compat_session_id FROM compat_sessions
+                            WHERE finished_at IS NULL
+                              AND ($1::uuid IS NULL OR user_id = $1)
+                              AND ($2::timestamptz IS NULL OR created_at < $2)
+                        ",
+                    )
+                    .bind(user_id)
+                    .bind(before)
+                    .fetch_all(&mut **repo)
+                    .await?;
+
+                    info!(
+                        "Revoking {} compatibility session(s)",
+                        compat_sessions_ids.len()
+                    );
+
+                    for id in compat_sessions_ids {
+                        let id = id.into();
+                        let compat_session = repo
+                            .compat_session()
+                            .lookup(id)
+                            .await?
+                            .context("Session not found")?;
+                        info!(%compat_session.id, %compat_session.device, "Revoking compat session");
+
+                        if dry_run {
+                            continue;
+                        }
+
+                        let user = match users.entry(compat_session.user_id) {
+                            Entry::Occupied(entry) => entry.into_mut(),
+                            Entry::Vacant(entry) => {
+                                let user = repo
+                                    .user()
+                                    .lookup(compat_session.user_id)
+                                    .await?
+                                    .context("User not found")?;
+                                entry.insert(user)
+                            }
+                        };
+
+                        let job = DeleteDeviceJob::new(user, &compat_session.device);
+                        repo.job().schedule_job(job).await?;
+                        finished_sessions.push(compat_session.id);
+                        repo.compat_session().finish(&clock, compat_session).await?;
+                    }
+                }
+
+                let txn = repo.into_inner();
+                if dry_run {
+                    info!("Dry run, not saving");
+                    txn.rollback().await?;
+                } else {
+                    txn.commit().await?;
+                }
+
+                let introspection_config = IntrospectionConfig::extract(figment)?;
+                let redis_config = RedisConfig::extract(figment)?;
+                if introspection_config.cache_ttl.is_some() && !finished_sessions.is_empty() {
+                    if redis_config.uri.is_some() {
+                        let introspection_cache =
+                            introspection_cache_from_config(&introspection_config, &redis_config)
+                                .await?;
+                        for session_id in finished_sessions {
+                            introspection_cache.invalidate_session(session_id).await;
+                        }
+                    } else {
+                        // The servers each keep their own cache, which we can't reach from here
+                        warn!("Tokens may stay valid until their cached introspection expires");
+                    }
+                }
+
+                Ok(())
+            }
+
             SC::LockUser {
                 username,
                 deactivate,
@@ -466,7 +840,8 @@ impl Options {
             }
 
             SC::UnlockUser { username } => {
-                let _span = info_span!("cli.manage.lock_user", user.username = username).entered();
+                let _span =
+                    info_span!("cli.manage.unlock_user", user.username = username).entered();
                 let config = DatabaseConfig::extract(figment)?;
                 let mut conn = database_connection_from_config(&config).await?;
                 let txn = conn.begin().await?;
@@ -486,6 +861,49 @@ impl Options {
                 Ok(())
             }
 
+            SC::ShadowBanUser { username, lift } => {
+                let _span =
+                    info_span!("cli.manage.shadow_ban_user", user.username = username).entered();
+                let config = DatabaseConfig::extract(figment)?;
+                let mut conn = database_connection_from_config(&config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let user = repo
+                    .user()
+                    .find_by_username(&username)
+                    .await?
+                    .context("User not found")?;
+
+                let user = if lift {
+                    info!(%user.id, "Lifting the shadow-ban of user");
+                    repo.user().lift_shadow_ban(user).await?
+                } else {
+                    info!(%user.id, "Shadow-banning user");
+                    repo.user().shadow_ban(&clock, user).await?
+                };
+
+                repo.into_inner().commit().await?;
+
+                // The introspection results of the user's tokens carry the flag, so
+                // they must not be served from the cache anymore
+                let introspection_config = IntrospectionConfig::extract(figment)?;
+                let redis_config = RedisConfig::extract(figment)?;
+                if introspection_config.cache_ttl.is_some() {
+                    if redis_config.uri.is_some() {
+                        let introspection_cache =
+                            introspection_cache_from_config(&introspection_config, &redis_config)
+                                .await?;
+                        introspection_cache.invalidate_user(user.id).await;
+                    } else {
+                        // The servers each keep their own cache, which we can't reach from here
+                        warn!("Introspection results may stay cached until they expire");
+                    }
+                }
+
+                Ok(())
+            }
+
             SC::RegisterUser {
                 username,
                 password,
@@ -496,7 +914,8 @@ impl Options {
                 display_name,
                 yes,
             } => {
-                let http_client_factory = HttpClientFactory::new();
+                let http_config = HttpConfig::extract(figment)?;
+                let http_client_factory = http_client_factory_from_config(&http_config)?;
                 let password_config = PasswordsConfig::extract(figment)?;
                 let database_config = DatabaseConfig::extract(figment)?;
                 let matrix_config = MatrixConfig::extract(figment)?;
@@ -772,6 +1191,60 @@ async fn check_and_normalize_username<'a>(
     Ok(localpart)
 }
 
+/// Issue an access token for a bot, creating the bot user if it doesn't exist
+/// yet
+async fn issue_bot_token(
+    repo: &mut dyn RepositoryAccess<Error = DatabaseError>,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    username: String,
+    client_id: Ulid,
+    scope: Scope,
+) -> anyhow::Result<(User, Session, AccessToken)> {
+    let client = repo
+        .oauth2_client()
+        .lookup(client_id)
+        .await?
+        .context("Client not found")?;
+
+    let user = if let Some(user) = repo.user().find_by_username(&username).await? {
+        if !user.is_bot {
+            anyhow::bail!("User {username} exists and is not a bot");
+        }
+
+        user
+    } else {
+        info!("Creating bot user {username}");
+        let user = repo.user().add(rng, clock, username).await?;
+        let user = repo.user().set_bot(user, true).await?;
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+        user
+    };
+
+    let session = repo
+        .oauth2_session()
+        .add(rng, clock, &client, Some(&user), None, scope)
+        .await?;
+
+    for scope in &*session.scope {
+        if let Some(device) = Device::from_scope_token(scope) {
+            repo.job()
+                .schedule_job(ProvisionDeviceJob::new(&user, &device))
+                .await?;
+        }
+    }
+
+    let token = TokenType::AccessToken.generate(rng);
+    let access_token = repo
+        .oauth2_access_token()
+        .add(rng, clock, &session, token, None)
+        .await?;
+
+    Ok((user, session, access_token))
+}
+
 struct UserCreationRequest<'a> {
     username: String,
     hashed_password: Option<(u16, String)>,
@@ -945,12 +1418,16 @@ impl UserCreationRequest<'_> {
             user = repo.user().set_can_request_admin(user, admin).await?;
         }
 
-        let mut provision_job = ProvisionUserJob::new(&user);
         if let Some(display_name) = display_name {
-            provision_job = provision_job.set_display_name(display_name);
+            user = repo
+                .user()
+                .set_display_name(user, Some(display_name))
+                .await?;
         }
 
-        repo.job().schedule_job(provision_job).await?;
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
 
         Ok(user)
     }
@@ -1044,3 +1521,113 @@ impl std::fmt::Display for UserCreationCommand<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{clock::MockClock, Repository};
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_issue_bot_token(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec![],
+                None,
+                None,
+                vec![],
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let scope: Scope = "urn:matrix:org.matrix.msc2967.client:api:*"
+            .parse()
+            .unwrap();
+
+        // The bot user is created on the first token
+        let (user, session, access_token) = issue_bot_token(
+            &mut repo,
+            &mut rng,
+            &clock,
+            "bot".to_owned(),
+            client.id,
+            scope.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(user.is_bot);
+        assert_eq!(user.username, "bot");
+        assert_eq!(session.user_id, Some(user.id));
+        assert_eq!(session.scope, scope);
+        assert_eq!(access_token.session_id, session.id);
+        // The token doesn't expire
+        assert_eq!(access_token.expires_at, None);
+
+        // And reused for the next ones
+        let (user2, session2, _) = issue_bot_token(
+            &mut repo,
+            &mut rng,
+            &clock,
+            "bot".to_owned(),
+            client.id,
+            scope.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(user2.id, user.id);
+        assert_ne!(session2.id, session.id);
+
+        // Tokens are not issued for humans
+        let human = repo
+            .user()
+            .add(&mut rng, &clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let res = issue_bot_token(
+            &mut repo,
+            &mut rng,
+            &clock,
+            human.username.clone(),
+            client.id,
+            scope.clone(),
+        )
+        .await;
+        assert!(res.is_err());
+
+        // Nor for unknown clients
+        let res = issue_bot_token(
+            &mut repo,
+            &mut rng,
+            &clock,
+            "bot".to_owned(),
+            Ulid::nil(),
+            scope,
+        )
+        .await;
+        assert!(res.is_err());
+
+        Box::new(repo).cancel().await.unwrap();
+    }
+}
@@ -19,7 +19,7 @@ use clap::Parser;
 use figment::Figment;
 use itertools::Itertools;
 use mas_config::{AppConfig, ClientsConfig, ConfigurationSection, UpstreamOAuth2Config};
-use mas_handlers::{ActivityTracker, CookieManager, HttpClientFactory, MetadataCache};
+use mas_handlers::{ActivityTracker, ClientLogoCache, CookieManager, DocumentCache, MetadataCache};
 use mas_listener::{server::Server, shutdown::ShutdownStream};
 use mas_matrix_synapse::SynapseConnection;
 use mas_router::UrlBuilder;
@@ -27,7 +27,7 @@ use mas_storage::SystemClock;
 use mas_storage_pg::MIGRATOR;
 use rand::{
     distributions::{Alphanumeric, DistString},
-    thread_rng,
+    thread_rng, SeedableRng,
 };
 use sqlx::migrate::Migrate;
 use tokio::signal::unix::SignalKind;
@@ -36,8 +36,11 @@ use tracing::{info, info_span, warn, Instrument};
 use crate::{
     app_state::AppState,
     util::{
-        database_pool_from_config, mailer_from_config, password_manager_from_config,
-        policy_factory_from_config, register_sighup, site_config_from_config,
+        database_connection_from_config, database_pool_from_config, geoip_from_config,
+        http_client_factory_from_config, introspection_cache_from_config, limiter_from_config,
+        login_risk_from_config, mailer_from_config, network_bans_from_config,
+        notification_webhook_from_config, password_manager_from_config, policy_factory_from_config,
+        register_sighup, site_config_from_config, sms_sender_from_config, spam_checker_from_config,
         templates_from_config,
     },
 };
@@ -61,6 +64,13 @@ pub(super) struct Options {
     /// Do not sync the configuration with the database
     #[arg(long)]
     no_sync: bool,
+
+    /// Development mode: watch the templates and translations, and reload
+    /// them when they change, and provision a well-known client and test user
+    ///
+    /// Never use this in production.
+    #[arg(long)]
+    dev: bool,
 }
 
 impl Options {
@@ -89,14 +99,17 @@ impl Options {
             }
         } else {
             info!("Running pending database migrations");
+            // Migrations run on a dedicated connection, as they are not subject to
+            // the statement timeout of the pool
+            let mut conn = database_connection_from_config(&config.database).await?;
             MIGRATOR
-                .run(&pool)
+                .run(&mut conn)
                 .instrument(info_span!("db.migrate"))
                 .await
                 .context("could not run database migrations")?;
         }
 
-        let encrypter = config.secrets.encrypter();
+        let encrypter = config.secrets.encrypter().await?;
 
         if self.no_sync {
             info!("Skipping configuration sync");
@@ -105,12 +118,14 @@ impl Options {
             let mut conn = pool.acquire().await?;
             let clients_config = ClientsConfig::extract(figment)?;
             let upstream_oauth2_config = UpstreamOAuth2Config::extract(figment)?;
+            let mut rng = rand_chacha::ChaChaRng::from_entropy();
 
             crate::sync::config_sync(
                 upstream_oauth2_config,
                 clients_config,
                 &mut conn,
                 &encrypter,
+                &mut rng,
                 &SystemClock::default(),
                 false,
                 false,
@@ -125,8 +140,12 @@ impl Options {
             .await
             .context("could not import keys from config")?;
 
-        let cookie_manager =
-            CookieManager::derive_from(config.http.public_base.clone(), &config.secrets.encryption);
+        let (cookie_key, fallback_cookie_keys) = config.secrets.cookie_encryption_keys().await?;
+        let cookie_manager = CookieManager::derive_from_keys(
+            config.http.public_base.clone(),
+            &cookie_key,
+            fallback_cookie_keys.iter().map(|key| &key[..]),
+        );
 
         // Load and compile the WASM policies (and fallback to the default embedded one)
         info!("Loading and compiling the policy module");
@@ -152,7 +171,12 @@ impl Options {
         let templates =
             templates_from_config(&config.templates, &site_config, &url_builder).await?;
 
-        let http_client_factory = HttpClientFactory::new();
+        if self.dev || config.templates.watch {
+            info!("Watching the templates and translations for changes");
+            templates.watch(Duration::from_secs(1));
+        }
+
+        let http_client_factory = http_client_factory_from_config(&config.http)?;
 
         let homeserver_connection = SynapseConnection::new(
             config.matrix.homeserver.clone(),
@@ -161,10 +185,21 @@ impl Options {
             http_client_factory.clone(),
         );
 
-        if !self.no_worker {
-            let mailer = mailer_from_config(&config.email, &templates)?;
+        let shutdown_timeout = config.http.shutdown_timeout;
+        let request_timeout = config.http.request_timeout;
+
+        let worker = if self.no_worker {
+            None
+        } else {
+            let mailer = mailer_from_config(&config.email, &templates, &http_client_factory)?;
             mailer.test_connection().await?;
 
+            let sms_sender = sms_sender_from_config(&config.sms, &templates, &http_client_factory)?;
+            let webhook = notification_webhook_from_config(
+                &config.experimental.security_notifications,
+                &http_client_factory,
+            );
+
             #[allow(clippy::disallowed_methods)]
             let mut rng = thread_rng();
             let worker_name = Alphanumeric.sample_string(&mut rng, 10);
@@ -174,26 +209,76 @@ impl Options {
                 &worker_name,
                 &pool,
                 &mailer,
+                &sms_sender,
+                &templates,
+                &encrypter,
+                webhook,
                 homeserver_connection.clone(),
                 url_builder.clone(),
+                site_config.clone(),
             )
             .await?;
-            // TODO: grab the handle
-            tokio::spawn(monitor.run());
-        }
+
+            // The worker is stopped once the HTTP servers are done shutting down
+            let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+            let handle = tokio::spawn(monitor.shutdown_timeout(shutdown_timeout).run_with_signal(
+                async move {
+                    // An error here means the sender was dropped, which we also
+                    // treat as a shutdown request
+                    let _ = shutdown_rx.await;
+                    Ok(())
+                },
+            ));
+
+            Some((shutdown_tx, handle))
+        };
 
         let listeners_config = config.http.listeners.clone();
+        let access_control = config.http.access_control.clone();
 
         let password_manager = password_manager_from_config(&config.passwords).await?;
 
+        if self.dev {
+            warn!("Running in development mode, do not use this in production");
+            let mut conn = pool.acquire().await?;
+            let mut rng = rand_chacha::ChaChaRng::from_entropy();
+            crate::dev::seed(
+                &mut conn,
+                &password_manager,
+                &url_builder,
+                &mut rng,
+                &SystemClock::default(),
+            )
+            .await?;
+        }
+
         // The upstream OIDC metadata cache
         let metadata_cache = MetadataCache::new();
 
+        // The cache of the logos of the OAuth 2.0 clients
+        let client_logo_cache = ClientLogoCache::new();
+
+        // The discovery document and the JWKS only depend on the configuration, so
+        // they are generated once
+        let document_cache = DocumentCache::new();
+
         // Initialize the activity tracker
         // Activity is flushed every minute
         let activity_tracker = ActivityTracker::new(pool.clone(), Duration::from_secs(60));
         let trusted_proxies = config.http.trusted_proxies.clone();
 
+        let limiter = limiter_from_config(&config.rate_limiting, &config.redis).await?;
+        let network_bans = network_bans_from_config(
+            &config.rate_limiting,
+            &config.http.trusted_proxies,
+            limiter.clone(),
+        )?;
+        let introspection_cache =
+            introspection_cache_from_config(&config.introspection, &config.redis).await?;
+        let geoip = geoip_from_config(&config.experimental).await?;
+        let spam_checker = spam_checker_from_config(&config.experimental, &http_client_factory);
+        let login_risk = login_risk_from_config(&config.experimental).await?;
+
         // Explicitly the config to properly zeroize secret keys
         drop(config);
 
@@ -206,14 +291,27 @@ impl Options {
             homeserver_connection.clone(),
             site_config.clone(),
             password_manager.clone(),
+            introspection_cache.clone(),
+            geoip.clone(),
+            encrypter.clone(),
+            key_store.clone(),
+            http_client_factory.clone(),
+            metadata_cache.clone(),
+            network_bans.clone(),
         );
 
+        // Reload the network bans regularly, to pick up the ones put in place or
+        // lifted by other instances
+        network_bans.run(pool.clone(), Duration::from_secs(30));
+
         let state = {
             let mut s = AppState {
                 pool,
                 templates,
                 key_store,
                 metadata_cache,
+                client_logo_cache,
+                document_cache,
                 cookie_manager,
                 encrypter,
                 url_builder,
@@ -225,6 +323,12 @@ impl Options {
                 site_config,
                 activity_tracker,
                 trusted_proxies,
+                limiter,
+                network_bans,
+                spam_checker,
+                login_risk,
+                introspection_cache,
+                geoip,
                 conn_acquisition_histogram: None,
             };
             s.init_metrics()?;
@@ -249,12 +353,22 @@ impl Options {
                     None
                 };
 
+                let security_headers = crate::server::SecurityHeaders::from_config(
+                    &config.security_headers,
+                    state.trusted_proxies.clone(),
+                )?;
+
                 // and build the router
                 let router = crate::server::build_router(
                     state.clone(),
                     &config.resources,
                     config.prefix.as_deref(),
                     config.name.as_deref(),
+                    security_headers,
+                    &access_control,
+                    config.compression,
+                    config.request_body_limit,
+                    config.request_timeout.unwrap_or(request_timeout),
                 );
 
 
@@ -299,7 +413,7 @@ impl Options {
             .collect::<Result<Vec<_>, _>>()?;
 
         let shutdown = ShutdownStream::default()
-            .with_timeout(Duration::from_secs(60))
+            .with_timeout(shutdown_timeout)
             .with_signal(SignalKind::terminate())?
             .with_signal(SignalKind::interrupt())?;
 
@@ -307,8 +421,23 @@ impl Options {
 
         mas_listener::server::run_servers(servers, shutdown).await;
 
+        // At this point, no new requests are being accepted and the in-flight ones
+        // are done, so we can stop the background worker
+        if let Some((shutdown_tx, handle)) = worker {
+            info!("Waiting for the task worker to finish");
+            let _ = shutdown_tx.send(());
+            match handle.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Task worker exited with an error: {e}"),
+                Err(e) => warn!("Task worker panicked: {e}"),
+            }
+        }
+
         state.activity_tracker.shutdown().await;
 
+        // Wait for the remaining database connections to be released
+        state.pool.close().await;
+
         Ok(())
     }
 }
@@ -15,17 +15,19 @@
 use clap::Parser;
 use figment::Figment;
 use mas_config::{AppConfig, ConfigurationSection};
-use mas_handlers::HttpClientFactory;
 use mas_matrix_synapse::SynapseConnection;
 use mas_router::UrlBuilder;
 use rand::{
     distributions::{Alphanumeric, DistString},
     thread_rng,
 };
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::{info, info_span};
 
 use crate::util::{
-    database_pool_from_config, mailer_from_config, site_config_from_config, templates_from_config,
+    database_pool_from_config, http_client_factory_from_config, mailer_from_config,
+    notification_webhook_from_config, site_config_from_config, sms_sender_from_config,
+    templates_from_config,
 };
 
 #[derive(Parser, Debug, Default)]
@@ -59,10 +61,20 @@ impl Options {
         let templates =
             templates_from_config(&config.templates, &site_config, &url_builder).await?;
 
-        let mailer = mailer_from_config(&config.email, &templates)?;
+        let http_client_factory = http_client_factory_from_config(&config.http)?;
+
+        // Emails queued by the workers are encrypted
+        let encrypter = config.secrets.encrypter().await?;
+
+        let mailer = mailer_from_config(&config.email, &templates, &http_client_factory)?;
         mailer.test_connection().await?;
 
-        let http_client_factory = HttpClientFactory::new();
+        let sms_sender = sms_sender_from_config(&config.sms, &templates, &http_client_factory)?;
+        let webhook = notification_webhook_from_config(
+            &config.experimental.security_notifications,
+            &http_client_factory,
+        );
+
         let conn = SynapseConnection::new(
             config.matrix.homeserver.clone(),
             config.matrix.endpoint.clone(),
@@ -70,6 +82,8 @@ impl Options {
             http_client_factory,
         );
 
+        let shutdown_timeout = config.http.shutdown_timeout;
+
         drop(config);
 
         #[allow(clippy::disallowed_methods)]
@@ -77,11 +91,37 @@ impl Options {
         let worker_name = Alphanumeric.sample_string(&mut rng, 10);
 
         info!(worker_name, "Starting task scheduler");
-        let monitor = mas_tasks::init(&worker_name, &pool, &mailer, conn, url_builder).await?;
+        let monitor = mas_tasks::init(
+            &worker_name,
+            &pool,
+            &mailer,
+            &sms_sender,
+            &templates,
+            &encrypter,
+            webhook,
+            conn,
+            url_builder,
+            site_config,
+        )
+        .await?;
+
+        let mut sigterm = signal(SignalKind::terminate())?;
 
         span.exit();
 
-        monitor.run().await?;
+        monitor
+            .shutdown_timeout(shutdown_timeout)
+            .run_with_signal(async move {
+                tokio::select! {
+                    _ = sigterm.recv() => {},
+                    res = tokio::signal::ctrl_c() => res?,
+                }
+
+                info!("Received shutdown signal, waiting for running jobs to finish");
+                Ok(())
+            })
+            .await?;
+
         Ok(())
     }
 }
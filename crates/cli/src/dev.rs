@@ -0,0 +1,134 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seed data for the development mode of the server
+
+use mas_data_model::Ulid;
+use mas_handlers::passwords::PasswordManager;
+use mas_iana::oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob},
+    Clock, RepositoryAccess,
+};
+use mas_storage_pg::PgRepository;
+use oauth2_types::{oidc::ApplicationType, requests::GrantType};
+use rand::{CryptoRng, RngCore};
+use sqlx::{Connection, PgConnection};
+use tracing::{info, warn};
+use url::Url;
+
+/// The client ID of the development client
+const DEV_CLIENT_ID: &str = "00000000000000000000000DEV";
+
+/// The redirect URI of the development client. As it is a native client, any
+/// port on localhost is accepted.
+const DEV_REDIRECT_URI: &str = "http://localhost/callback";
+
+/// The username and password of the development user
+const DEV_USERNAME: &str = "dev";
+const DEV_PASSWORD: &str = "dev";
+
+/// Create a well-known public client and a test user, and log an
+/// authorization URL which can be used right away to go through a login flow.
+///
+/// This is idempotent: the client is updated and the user is left untouched if
+/// they already exist.
+#[tracing::instrument(name = "dev.seed", skip_all, err(Debug))]
+pub async fn seed(
+    connection: &mut PgConnection,
+    password_manager: &PasswordManager,
+    url_builder: &UrlBuilder,
+    rng: &mut (impl RngCore + CryptoRng + Send),
+    clock: &dyn Clock,
+) -> anyhow::Result<()> {
+    let txn = connection.begin().await?;
+    let mut repo = PgRepository::from_conn(txn);
+
+    let client_id = Ulid::from_string(DEV_CLIENT_ID)?;
+    let redirect_uri: Url = DEV_REDIRECT_URI.parse()?;
+
+    repo.oauth2_client()
+        .upsert_static(
+            client_id,
+            OAuthClientAuthenticationMethod::None,
+            None,
+            None,
+            None,
+            vec![redirect_uri.clone()],
+            vec![OAuthAuthorizationEndpointResponseType::Code],
+            vec![GrantType::AuthorizationCode, GrantType::RefreshToken],
+            Some(ApplicationType::Native),
+            true,
+            None,
+            None,
+            false,
+            Some("Development client".to_owned()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?;
+
+    if repo.user().find_by_username(DEV_USERNAME).await?.is_some() {
+        info!(username = DEV_USERNAME, "Development user already exists");
+    } else {
+        let user = repo
+            .user()
+            .add(&mut *rng, clock, DEV_USERNAME.to_owned())
+            .await?;
+
+        if password_manager.is_enabled() {
+            let password = DEV_PASSWORD.as_bytes().to_vec().into();
+            let (version, hashed_password) = password_manager.hash(&mut *rng, password).await?;
+            repo.user_password()
+                .add(&mut *rng, clock, &user, version, hashed_password, None)
+                .await?;
+        } else {
+            warn!("Password authentication is disabled, the development user won't be able to log in with a password");
+        }
+
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+
+        info!(
+            username = DEV_USERNAME,
+            password = DEV_PASSWORD,
+            "Created the development user"
+        );
+    }
+
+    repo.into_inner().commit().await?;
+
+    let mut authorization_url = url_builder.oauth_authorization_endpoint();
+    authorization_url
+        .query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", DEV_CLIENT_ID)
+        .append_pair("redirect_uri", redirect_uri.as_str())
+        .append_pair("scope", "openid urn:matrix:org.matrix.msc2967.client:api:*")
+        .append_pair("state", "dev");
+
+    info!(
+        client_id = DEV_CLIENT_ID,
+        redirect_uri = DEV_REDIRECT_URI,
+        "Development client is ready, start a login flow at {authorization_url}"
+    );
+
+    Ok(())
+}
@@ -18,7 +18,7 @@ use std::{io::IsTerminal, sync::Arc};
 
 use anyhow::Context;
 use clap::Parser;
-use mas_config::{ConfigurationSection, TelemetryConfig};
+use mas_config::{ConfigurationSection, LogFormat, TelemetryConfig};
 use sentry_tracing::EventFilter;
 use tracing_subscriber::{
     filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
@@ -28,6 +28,7 @@ use crate::sentry_transport::HyperTransportFactory;
 
 mod app_state;
 mod commands;
+mod dev;
 mod sentry_transport;
 mod server;
 mod sync;
@@ -52,29 +53,49 @@ async fn try_main() -> anyhow::Result<()> {
         // Display the error if it is something other than the .env file not existing
         .or_else(|e| if e.not_found() { Ok(None) } else { Err(e) });
 
+    // Parse the CLI arguments
+    let opts = self::commands::Options::parse();
+
+    // Load the base configuration files
+    let figment = opts.figment();
+
+    // Load the telemetry config first, as it is needed to setup logging. The
+    // section is optional, and some commands run without any configuration file,
+    // but if it is present, errors in it are reported right away.
+    let telemetry_config = if figment.contains("telemetry") {
+        TelemetryConfig::extract(&figment)
+            .context("could not load the telemetry configuration")?
+    } else {
+        TelemetryConfig::default()
+    };
+
     // Setup logging
     // This writes logs to stderr
     let output = std::io::stderr();
     let with_ansi = output.is_terminal();
     let (log_writer, _guard) = tracing_appender::non_blocking(output);
-    let fmt_layer = tracing_subscriber::fmt::layer()
-        .with_writer(log_writer)
-        .with_ansi(with_ansi);
+    let (text_layer, json_layer) = match telemetry_config.logging.format {
+        LogFormat::Text => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(log_writer)
+                .with_ansi(with_ansi);
+            (Some(layer), None)
+        }
+        LogFormat::Json => {
+            // Include the fields of the current span and its parents, so that
+            // each line carries the request ID and other correlation fields
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(log_writer);
+            (None, Some(layer))
+        }
+    };
     let filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("info"))
         .context("could not setup logging filter")?;
 
-    // Parse the CLI arguments
-    let opts = self::commands::Options::parse();
-
-    // Load the base configuration files
-    let figment = opts.figment();
-
-    // Telemetry config could fail to load, but that's probably OK, since the whole
-    // config will be loaded afterwards, and crash if there is a problem.
-    // Falling back to default.
-    let telemetry_config = TelemetryConfig::extract(&figment).unwrap_or_default();
-
     // Setup Sentry
     let sentry = sentry::init((
         telemetry_config.sentry.dsn.as_deref(),
@@ -82,9 +103,13 @@ async fn try_main() -> anyhow::Result<()> {
             transport: Some(Arc::new(HyperTransportFactory::new(
                 mas_http::make_untraced_client(),
             ))),
-            traces_sample_rate: 1.0,
+            release: sentry::release_name!(),
+            environment: telemetry_config.sentry.environment.clone().map(Into::into),
+            sample_rate: telemetry_config.sentry.sample_rate.unwrap_or(1.0),
+            traces_sample_rate: telemetry_config.sentry.traces_sample_rate.unwrap_or(1.0),
             auto_session_tracking: true,
             session_mode: sentry::SessionMode::Request,
+            before_send: Some(Arc::new(self::telemetry::scrub_sentry_event)),
             ..Default::default()
         },
     ));
@@ -115,7 +140,8 @@ async fn try_main() -> anyhow::Result<()> {
         .with(sentry_layer)
         .with(telemetry_layer)
         .with(filter_layer)
-        .with(fmt_layer);
+        .with(text_layer)
+        .with(json_layer);
     subscriber
         .try_init()
         .context("could not initialize logging")?;
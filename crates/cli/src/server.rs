@@ -16,23 +16,37 @@ use std::{
     future::ready,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
     os::unix::net::UnixListener,
+    sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Context;
 use axum::{
     body::HttpBody,
     error_handling::HandleErrorLayer,
-    extract::{FromRef, MatchedPath},
+    extract::{DefaultBodyLimit, FromRef, MatchedPath, State},
+    middleware::Next,
+    response::IntoResponse,
     Extension, Router,
 };
 use hyper::{
-    header::{HeaderValue, CACHE_CONTROL, USER_AGENT},
-    Method, Request, Response, StatusCode, Version,
+    header::{
+        HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+        REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, USER_AGENT, X_CONTENT_TYPE_OPTIONS,
+        X_FRAME_OPTIONS,
+    },
+    http::Extensions,
+    HeaderMap, Method, Request, Response, StatusCode, Version,
 };
+use ipnetwork::IpNetwork;
 use listenfd::ListenFd;
-use mas_config::{HttpBindConfig, HttpResource, HttpTlsConfig, UnixOrTcp};
+use mas_config::{
+    HttpAccessControlConfig, HttpBindConfig, HttpFrameOptions, HttpResource,
+    HttpSecurityHeadersConfig, HttpTlsConfig, UnixOrTcp,
+};
+use mas_handlers::{ClientCertificate, PreferredLanguage, RequestId};
 use mas_listener::{unix_or_tcp::UnixOrTcpListener, ConnectionInfo};
-use mas_router::Route;
+use mas_router::{Route, SimpleRoute};
 use mas_templates::Templates;
 use mas_tower::{
     make_span_fn, metrics_attributes_fn, DurationRecorderLayer, InFlightCounterLayer, TraceLayer,
@@ -41,17 +55,25 @@ use mas_tower::{
 use opentelemetry::{Key, KeyValue};
 use opentelemetry_http::HeaderExtractor;
 use opentelemetry_semantic_conventions::trace::{
-    HTTP_REQUEST_METHOD, HTTP_RESPONSE_STATUS_CODE, HTTP_ROUTE, NETWORK_PROTOCOL_NAME,
-    NETWORK_PROTOCOL_VERSION, URL_PATH, URL_QUERY, URL_SCHEME, USER_AGENT_ORIGINAL,
+    CLIENT_ADDRESS, HTTP_REQUEST_METHOD, HTTP_RESPONSE_STATUS_CODE, HTTP_ROUTE,
+    NETWORK_PROTOCOL_NAME, NETWORK_PROTOCOL_VERSION, URL_PATH, URL_QUERY, URL_SCHEME,
+    USER_AGENT_ORIGINAL,
+};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
 };
-use rustls::ServerConfig;
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
 use sentry_tower::{NewSentryLayer, SentryHttpLayer};
 use tower::Layer;
-use tower_http::{services::ServeDir, set_header::SetResponseHeaderLayer};
+use tower_http::{
+    compression::{predicate::DefaultPredicate, CompressionLayer, Predicate},
+    services::ServeDir,
+};
 use tracing::Span;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::app_state::AppState;
+use crate::app_state::{infer_client_ip, infer_client_scheme, AppState};
 
 const MAS_LISTENER_NAME: Key = Key::from_static_str("mas.listener.name");
 
@@ -105,8 +127,301 @@ fn otel_url_scheme<B>(request: &Request<B>) -> &'static str {
         })
 }
 
-fn make_http_span<B>(req: &Request<B>) -> Span {
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+fn is_valid_request_id(value: &HeaderValue) -> bool {
+    !value.is_empty()
+        && value.len() <= 64
+        && value
+            .as_bytes()
+            .iter()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.'))
+}
+
+/// Give a unique identifier to the request, used to correlate the logs, traces
+/// and audit records.
+///
+/// It is either taken from the incoming `X-Request-Id` header, or randomly
+/// generated, and is sent back in the response `X-Request-Id` header.
+async fn set_request_id<B>(mut request: Request<B>, next: Next<B>) -> axum::response::Response {
+    let request_id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .filter(|value| is_valid_request_id(value))
+        .cloned()
+        .unwrap_or_else(|| {
+            #[allow(clippy::disallowed_methods)]
+            let mut rng = thread_rng();
+            let id = Alphanumeric.sample_string(&mut rng, 16);
+            HeaderValue::from_str(&id).expect("alphanumeric strings are valid header values")
+        });
+
+    // Valid request IDs are restricted to visible ASCII characters
+    let id = request_id.to_str().ok().map(ToOwned::to_owned);
+    request.extensions_mut().insert(RequestId(id));
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(X_REQUEST_ID, request_id);
+    response
+}
+
+/// Expose the TLS client certificate presented on the connection to the
+/// handlers. Certificates are only requested by listeners configured with a
+/// client CA, and are verified by rustls during the handshake.
+async fn set_client_certificate<B>(
+    mut request: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    let certificate = request
+        .extensions()
+        .get::<ConnectionInfo>()
+        .and_then(ConnectionInfo::get_tls_ref)
+        .and_then(|tls| tls.peer_certificates.as_ref())
+        .and_then(|certificates| certificates.first())
+        .map(|certificate| certificate.as_ref().to_vec());
+
+    request
+        .extensions_mut()
+        .insert(ClientCertificate(certificate));
+
+    next.run(request).await
+}
+
+/// The default `Content-Security-Policy`, compatible with the built-in
+/// templates and the supported CAPTCHA services. `{frame_ancestors}` is
+/// replaced according to the `X-Frame-Options` setting.
+const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'nonce-{nonce}' https://www.google.com https://www.gstatic.com \
+    https://challenges.cloudflare.com https://js.hcaptcha.com https://*.hcaptcha.com; \
+    style-src 'self' 'unsafe-inline' https://*.hcaptcha.com; \
+    img-src 'self' data: https:; \
+    connect-src 'self' https://*.hcaptcha.com; \
+    frame-src https://www.google.com https://challenges.cloudflare.com https://*.hcaptcha.com; \
+    frame-ancestors {frame_ancestors}; \
+    base-uri 'self'; \
+    object-src 'none'";
+
+/// Security-related headers set on HTML responses
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    content_security_policy: String,
+    frame_options: HeaderValue,
+    referrer_policy: HeaderValue,
+    strict_transport_security: Option<HeaderValue>,
+    trusted_proxies: Vec<IpNetwork>,
+}
+
+impl SecurityHeaders {
+    /// Build the security headers from the listener configuration
+    ///
+    /// Returns `None` if the headers are disabled on this listener.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the configured values is not a valid header
+    /// value
+    pub fn from_config(
+        config: &HttpSecurityHeadersConfig,
+        trusted_proxies: Vec<IpNetwork>,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let (frame_options, frame_ancestors) = match config.frame_options {
+            HttpFrameOptions::Deny => (HeaderValue::from_static("DENY"), "'none'"),
+            HttpFrameOptions::SameOrigin => (HeaderValue::from_static("SAMEORIGIN"), "'self'"),
+        };
+
+        let content_security_policy = config.content_security_policy.clone().unwrap_or_else(|| {
+            DEFAULT_CONTENT_SECURITY_POLICY.replace("{frame_ancestors}", frame_ancestors)
+        });
+
+        // Make sure the policy is a valid header value once the nonce is set
+        HeaderValue::from_str(&content_security_policy)
+            .context("invalid Content-Security-Policy")?;
+
+        let referrer_policy =
+            HeaderValue::from_str(&config.referrer_policy).context("invalid Referrer-Policy")?;
+
+        let strict_transport_security = config.hsts_max_age.map(|max_age| {
+            HeaderValue::from_str(&format!("max-age={}", max_age.as_secs()))
+                .expect("a number is a valid header value")
+        });
+
+        Ok(Some(Self {
+            content_security_policy,
+            frame_options,
+            referrer_policy,
+            strict_transport_security,
+            trusted_proxies,
+        }))
+    }
+}
+
+/// Sets the security headers on HTML responses, and makes a fresh CSP nonce
+/// available to the templates
+async fn set_security_headers<B>(
+    headers: SecurityHeaders,
+    request: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    let is_https = infer_client_scheme(
+        request.extensions(),
+        request.headers(),
+        &headers.trusted_proxies,
+    ) == "https";
+
+    let nonce = {
+        #[allow(clippy::disallowed_methods)]
+        let mut rng = thread_rng();
+        Alphanumeric.sample_string(&mut rng, 22)
+    };
+
+    let mut response = mas_templates::with_csp_nonce(nonce.clone(), next.run(request)).await;
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+
+    if !is_html {
+        return response;
+    }
+
+    let content_security_policy = headers.content_security_policy.replace("{nonce}", &nonce);
+    let response_headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&content_security_policy) {
+        response_headers.insert(CONTENT_SECURITY_POLICY, value);
+    }
+    response_headers.insert(X_FRAME_OPTIONS, headers.frame_options);
+    response_headers.insert(REFERRER_POLICY, headers.referrer_policy);
+    response_headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    if let Some(value) = headers.strict_transport_security.filter(|_| is_https) {
+        response_headers.insert(STRICT_TRANSPORT_SECURITY, value);
+    }
+
+    response
+}
+
+/// Only HTML and JSON responses are compressed: the static assets are served
+/// precompressed, and the other responses are either binary or tiny
+fn should_compress(
+    _status: StatusCode,
+    _version: Version,
+    headers: &HeaderMap,
+    _extensions: &Extensions,
+) -> bool {
+    let Some(content_type) = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence == "text/html" || essence == "application/json" || essence.ends_with("+json")
+}
+
+/// Networks allowed to reach an endpoint. Requests are not restricted if no
+/// networks are configured.
+#[derive(Debug, Clone)]
+struct AllowedNetworks {
+    networks: Option<Vec<IpNetwork>>,
+    trusted_proxies: Vec<IpNetwork>,
+}
+
+impl AllowedNetworks {
+    fn new(networks: Option<&Vec<IpNetwork>>, trusted_proxies: &[IpNetwork]) -> Self {
+        Self {
+            networks: networks.cloned(),
+            trusted_proxies: trusted_proxies.to_vec(),
+        }
+    }
+
+    /// Like [`Self::new`], but nothing is allowed if no networks are set
+    fn deny_by_default(networks: Option<&Vec<IpNetwork>>, trusted_proxies: &[IpNetwork]) -> Self {
+        Self {
+            networks: Some(networks.cloned().unwrap_or_default()),
+            trusted_proxies: trusted_proxies.to_vec(),
+        }
+    }
+
+    /// Check whether the client which made a request is allowed. Requests for
+    /// which the IP of the client can't be inferred are rejected.
+    fn allows<B>(&self, request: &Request<B>) -> bool {
+        let Some(networks) = &self.networks else {
+            return true;
+        };
+
+        let ip = infer_client_ip(
+            request.extensions(),
+            request.headers(),
+            &self.trusted_proxies,
+        );
+
+        let allowed = ip.is_some_and(|ip| networks.iter().any(|network| network.contains(ip)));
+        if !allowed {
+            tracing::warn!(
+                client.address = ?ip,
+                url.path = request.uri().path(),
+                "Rejected a request from a network which is not allowed",
+            );
+        }
+
+        allowed
+    }
+}
+
+/// Rejects requests from networks which are not allowed
+async fn restrict_networks<B>(
+    allowed: AllowedNetworks,
+    request: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    if !allowed.allows(&request) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Name of the Vite manifest in the assets directory, which is the only file
+/// without a content-hashed name
+const ASSETS_MANIFEST: &str = "manifest.json";
+
+/// Sets the `Cache-Control` header on static assets. Content-hashed files never
+/// change, so they can be cached forever, whereas the manifest has to be
+/// revalidated.
+async fn set_asset_cache_headers<B>(
+    request: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    let fingerprinted = request
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name != ASSETS_MANIFEST);
+
+    let mut response = next.run(request).await;
+
+    if response.status().is_success() {
+        let value = if fingerprinted {
+            HeaderValue::from_static("public, max-age=31536000, immutable")
+        } else {
+            HeaderValue::from_static("no-cache")
+        };
+        response.headers_mut().insert(CACHE_CONTROL, value);
+    }
+
+    response
+}
+
+fn make_http_span<B>(req: &Request<B>, trusted_proxies: &[IpNetwork]) -> Span {
     let method = otel_http_method(req);
+    let scheme = infer_client_scheme(req.extensions(), req.headers(), trusted_proxies);
     let route = otel_http_route(req);
 
     let span_name = if let Some(route) = route.as_ref() {
@@ -127,10 +442,16 @@ fn make_http_span<B>(req: &Request<B>) -> Span {
         { HTTP_RESPONSE_STATUS_CODE } = tracing::field::Empty,
         { URL_PATH } = req.uri().path(),
         { URL_QUERY } = tracing::field::Empty,
-        { URL_SCHEME } = otel_url_scheme(req),
+        { URL_SCHEME } = scheme,
         { USER_AGENT_ORIGINAL } = tracing::field::Empty,
+        { CLIENT_ADDRESS } = tracing::field::Empty,
+        "request.id" = tracing::field::Empty,
     );
 
+    if let Some(RequestId(Some(request_id))) = req.extensions().get() {
+        span.record("request.id", request_id.as_str());
+    }
+
     if let Some(route) = route.as_ref() {
         span.record(HTTP_ROUTE, route);
     }
@@ -179,11 +500,17 @@ fn on_http_response_labels<B>(res: &Response<B>) -> Vec<KeyValue> {
     )]
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn build_router<B>(
     state: AppState,
     resources: &[HttpResource],
     prefix: Option<&str>,
     name: Option<&str>,
+    security_headers: Option<SecurityHeaders>,
+    access_control: &HttpAccessControlConfig,
+    compression: bool,
+    request_body_limit: usize,
+    request_timeout: Duration,
 ) -> Router<(), B>
 where
     B: HttpBody + Send + 'static,
@@ -191,6 +518,7 @@ where
     <B as HttpBody>::Error: std::error::Error + Send + Sync,
 {
     let templates = Templates::from_ref(&state);
+    let trusted_proxies = state.trusted_proxies.clone();
     let mut router = Router::new();
 
     for resource in resources {
@@ -199,16 +527,44 @@ where
                 router.merge(mas_handlers::healthcheck_router::<AppState, B>())
             }
             mas_config::HttpResource::Prometheus => {
-                router.route_service("/metrics", crate::telemetry::prometheus_service())
+                let allowed =
+                    AllowedNetworks::new(access_control.prometheus.as_ref(), &trusted_proxies);
+                router.route_service(
+                    "/metrics",
+                    axum::middleware::from_fn(move |request: Request<B>, next: Next<B>| {
+                        restrict_networks(allowed.clone(), request, next)
+                    })
+                    .layer(crate::telemetry::prometheus_service()),
+                )
             }
             mas_config::HttpResource::Discovery => {
                 router.merge(mas_handlers::discovery_router::<AppState, B>())
             }
             mas_config::HttpResource::Human => {
-                router.merge(mas_handlers::human_router::<AppState, B>(templates.clone()))
+                let human_router = mas_handlers::human_router::<AppState, B>(templates.clone());
+                if let Some(headers) = security_headers.clone() {
+                    router.merge(human_router.layer(axum::middleware::from_fn(
+                        move |request: Request<B>, next: Next<B>| {
+                            set_security_headers(headers.clone(), request, next)
+                        },
+                    )))
+                } else {
+                    router.merge(human_router)
+                }
             }
             mas_config::HttpResource::GraphQL { playground } => {
-                router.merge(mas_handlers::graphql_router::<AppState, B>(*playground))
+                let allowed =
+                    AllowedNetworks::new(access_control.graphql.as_ref(), &trusted_proxies);
+                router.merge(
+                    mas_handlers::graphql_router::<AppState, B>(*playground).layer(
+                        axum::middleware::from_fn(move |request: Request<B>, next: Next<B>| {
+                            restrict_networks(allowed.clone(), request, next)
+                        }),
+                    ),
+                )
+            }
+            mas_config::HttpResource::OpenApi { explorer } => {
+                router.merge(mas_handlers::openapi_router::<AppState, B>(*explorer))
             }
             mas_config::HttpResource::Assets { path } => {
                 let static_service = ServeDir::new(path)
@@ -220,22 +576,60 @@ where
                 let error_layer =
                     HandleErrorLayer::new(|_e| ready(StatusCode::INTERNAL_SERVER_ERROR));
 
-                let cache_layer = SetResponseHeaderLayer::overriding(
-                    CACHE_CONTROL,
-                    HeaderValue::from_static("public, max-age=31536000, immutable"),
-                );
+                let cache_layer = axum::middleware::from_fn(set_asset_cache_headers);
 
                 router.nest_service(
                     mas_router::StaticAsset::route(),
-                    (error_layer, cache_layer).layer(static_service),
+                    (cache_layer, error_layer).layer(static_service),
                 )
             }
             mas_config::HttpResource::OAuth => {
-                router.merge(mas_handlers::api_router::<AppState, B>())
+                // Only the introspection endpoint is restricted, the other ones are used by
+                // the clients
+                let allowed =
+                    AllowedNetworks::new(access_control.introspection.as_ref(), &trusted_proxies);
+                router.merge(mas_handlers::api_router::<AppState, B>().layer(
+                    axum::middleware::from_fn(move |request: Request<B>, next: Next<B>| {
+                        let allowed = allowed.clone();
+                        async move {
+                            if request.uri().path() == mas_router::OAuth2Introspection::PATH {
+                                restrict_networks(allowed, request, next).await
+                            } else {
+                                next.run(request).await
+                            }
+                        }
+                    }),
+                ))
             }
             mas_config::HttpResource::Compat => {
                 router.merge(mas_handlers::compat_router::<AppState, B>())
             }
+            mas_config::HttpResource::MatrixWellKnown {
+                homeserver_base_url,
+                extra,
+            } => router.merge(mas_handlers::matrix_well_known_router::<AppState, B>(
+                homeserver_base_url.clone(),
+                extra.clone(),
+            )),
+            mas_config::HttpResource::Admin => {
+                // The administration pages are not authenticated, so they are not reachable
+                // from anywhere unless some networks are explicitly allowed
+                if access_control.admin.as_ref().map_or(true, Vec::is_empty) {
+                    tracing::warn!(
+                        "The admin resource is mounted but `http.access_control.admin` is empty, \
+                         the administration pages will refuse every request"
+                    );
+                }
+                let allowed = AllowedNetworks::deny_by_default(
+                    access_control.admin.as_ref(),
+                    &trusted_proxies,
+                );
+                router.merge(mas_handlers::admin_router::<AppState, B>().layer(
+                    axum::middleware::from_fn(move |request: Request<B>, next: Next<B>| {
+                        restrict_networks(allowed.clone(), request, next)
+                    }),
+                ))
+            }
             // TODO: do a better handler here
             mas_config::HttpResource::ConnectionInfo => router.route(
                 "/connection-info",
@@ -251,7 +645,31 @@ where
         router = Router::new().nest(&path, router);
     }
 
-    router = router.fallback(mas_handlers::fallback);
+    router = router
+        .fallback(mas_handlers::fallback)
+        .layer(DefaultBodyLimit::max(request_body_limit))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            mas_handlers::reject_banned_networks,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            move |templates: State<Templates>,
+                  locale: PreferredLanguage,
+                  request: Request<B>,
+                  next: Next<B>| {
+                mas_handlers::request_timeout(request_timeout, templates, locale, request, next)
+            },
+        ));
+
+    if compression {
+        router = router.layer(
+            CompressionLayer::new()
+                .gzip(true)
+                .br(true)
+                .compress_when(DefaultPredicate::new().and(should_compress)),
+        );
+    }
 
     router
         .layer(
@@ -270,7 +688,7 @@ where
         )
         .layer(
             TraceLayer::new((
-                make_span_fn(make_http_span),
+                make_span_fn(move |req: &Request<B>| make_http_span(req, &trusted_proxies)),
                 name.map(|name| KV("mas.listener.name", name.to_owned())),
             ))
             .on_response_fn(|span: &Span, response: &Response<_>| {
@@ -281,14 +699,35 @@ where
         )
         .layer(SentryHttpLayer::new())
         .layer(NewSentryLayer::new_from_top())
+        .layer(axum::middleware::from_fn(set_request_id::<B>))
+        .layer(axum::middleware::from_fn(set_client_certificate::<B>))
         .with_state(state)
 }
 
 pub fn build_tls_server_config(config: &HttpTlsConfig) -> Result<ServerConfig, anyhow::Error> {
     let (key, chain) = config.load()?;
 
-    let mut config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+    let builder = rustls::ServerConfig::builder();
+
+    // If client certificate authorities are configured, require clients to
+    // present a certificate issued by one of them
+    let builder = if let Some(client_ca) = config.load_client_ca()? {
+        let mut roots = RootCertStore::empty();
+        for certificate in client_ca {
+            roots
+                .add(certificate)
+                .context("invalid client certificate authority")?;
+        }
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .build()
+            .context("failed to build the client certificate verifier")?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let mut config = builder
         .with_single_cert(chain, key)
         .context("failed to build TLS server config")?;
     config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
@@ -364,3 +803,127 @@ pub fn build_listeners(
 
     Ok(listeners)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_security_headers_from_config() {
+        let config = HttpSecurityHeadersConfig::default();
+        let headers = SecurityHeaders::from_config(&config, Vec::new())
+            .unwrap()
+            .unwrap();
+        assert!(headers
+            .content_security_policy
+            .contains("script-src 'self' 'nonce-{nonce}'"));
+        assert!(headers
+            .content_security_policy
+            .contains("frame-ancestors 'none';"));
+        assert_eq!(headers.frame_options, "DENY");
+        assert_eq!(
+            headers.strict_transport_security.unwrap(),
+            "max-age=31536000"
+        );
+
+        let config = HttpSecurityHeadersConfig {
+            frame_options: HttpFrameOptions::SameOrigin,
+            hsts_max_age: None,
+            ..HttpSecurityHeadersConfig::default()
+        };
+        let headers = SecurityHeaders::from_config(&config, Vec::new())
+            .unwrap()
+            .unwrap();
+        assert!(headers
+            .content_security_policy
+            .contains("frame-ancestors 'self';"));
+        assert_eq!(headers.frame_options, "SAMEORIGIN");
+        assert!(headers.strict_transport_security.is_none());
+
+        let config = HttpSecurityHeadersConfig {
+            referrer_policy: "no-referrer\n".to_owned(),
+            ..HttpSecurityHeadersConfig::default()
+        };
+        assert!(SecurityHeaders::from_config(&config, Vec::new()).is_err());
+
+        let config = HttpSecurityHeadersConfig {
+            enabled: false,
+            ..HttpSecurityHeadersConfig::default()
+        };
+        assert!(SecurityHeaders::from_config(&config, Vec::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_should_compress() {
+        let compress = |content_type: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(content_type) = content_type {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            }
+            should_compress(
+                StatusCode::OK,
+                Version::HTTP_11,
+                &headers,
+                &Extensions::new(),
+            )
+        };
+
+        assert!(compress(Some("text/html; charset=utf-8")));
+        assert!(compress(Some("application/json")));
+        assert!(compress(Some("application/problem+json")));
+        assert!(!compress(Some("image/png")));
+        assert!(!compress(Some("text/css")));
+        assert!(!compress(None));
+    }
+
+    #[test]
+    fn test_allowed_networks() {
+        let request = |peer: &str, forwarded_for: Option<&str>| {
+            let mut request = Request::get("/metrics");
+            if let Some(forwarded_for) = forwarded_for {
+                request = request.header("x-forwarded-for", forwarded_for);
+            }
+            request
+                .extension(mas_listener::ConnectionInfo::from_peer_addr(
+                    peer.parse().unwrap(),
+                ))
+                .body(())
+                .unwrap()
+        };
+        let trusted_proxies: Vec<IpNetwork> = vec!["10.0.0.0/8".parse().unwrap()];
+
+        // Without networks, everything is allowed
+        let allowed = AllowedNetworks::new(None, &trusted_proxies);
+        assert!(allowed.allows(&request("198.51.100.1:1234", None)));
+        assert!(allowed.allows(&Request::get("/metrics").body(()).unwrap()));
+
+        let networks = vec!["192.0.2.0/24".parse().unwrap()];
+        let allowed = AllowedNetworks::new(Some(&networks), &trusted_proxies);
+        assert!(allowed.allows(&request("192.0.2.1:1234", None)));
+        assert!(!allowed.allows(&request("198.51.100.1:1234", None)));
+
+        // Behind a trusted proxy, the forwarded address is checked
+        assert!(allowed.allows(&request("10.0.0.1:1234", Some("192.0.2.1"))));
+        assert!(allowed.allows(&request("10.0.0.1:1234", Some("192.0.2.1, 10.0.0.2"))));
+        assert!(!allowed.allows(&request("10.0.0.1:1234", Some("198.51.100.1"))));
+        assert!(!allowed.allows(&request("10.0.0.1:1234", Some("192.0.2.1, 198.51.100.1"))));
+
+        // But an untrusted client can't forge it
+        assert!(!allowed.allows(&request("198.51.100.1:1234", Some("192.0.2.1"))));
+        assert!(!allowed.allows(&request("198.51.100.1:1234", Some("192.0.2.1, 10.0.0.1"))));
+
+        // Requests for which the client IP is unknown are rejected
+        assert!(!allowed.allows(&Request::get("/metrics").body(()).unwrap()));
+
+        // Unless they are explicitly allowed, no networks are allowed when denying by
+        // default
+        let allowed = AllowedNetworks::deny_by_default(None, &trusted_proxies);
+        assert!(!allowed.allows(&request("192.0.2.1:1234", None)));
+        assert!(!allowed.allows(&request("127.0.0.1:1234", None)));
+        let allowed = AllowedNetworks::deny_by_default(Some(&networks), &trusted_proxies);
+        assert!(allowed.allows(&request("192.0.2.1:1234", None)));
+        assert!(!allowed.allows(&request("198.51.100.1:1234", None)));
+    }
+}
@@ -16,6 +16,7 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 
+use anyhow::Context;
 use mas_config::{ClientsConfig, UpstreamOAuth2Config};
 use mas_keystore::Encrypter;
 use mas_storage::{
@@ -23,9 +24,54 @@ use mas_storage::{
     Clock, Pagination, RepositoryAccess,
 };
 use mas_storage_pg::PgRepository;
+use rand::{CryptoRng, RngCore};
 use sqlx::{postgres::PgAdvisoryLock, Connection, PgConnection};
 use tracing::{error, info, info_span, warn};
 
+fn map_application_type(
+    config: mas_config::ClientApplicationTypeConfig,
+) -> oauth2_types::oidc::ApplicationType {
+    match config {
+        mas_config::ClientApplicationTypeConfig::Web => oauth2_types::oidc::ApplicationType::Web,
+        mas_config::ClientApplicationTypeConfig::Native => {
+            oauth2_types::oidc::ApplicationType::Native
+        }
+    }
+}
+
+fn map_grant_type(config: mas_config::ClientGrantTypeConfig) -> oauth2_types::requests::GrantType {
+    match config {
+        mas_config::ClientGrantTypeConfig::AuthorizationCode => {
+            oauth2_types::requests::GrantType::AuthorizationCode
+        }
+        mas_config::ClientGrantTypeConfig::RefreshToken => {
+            oauth2_types::requests::GrantType::RefreshToken
+        }
+        mas_config::ClientGrantTypeConfig::ClientCredentials => {
+            oauth2_types::requests::GrantType::ClientCredentials
+        }
+        mas_config::ClientGrantTypeConfig::DeviceCode => {
+            oauth2_types::requests::GrantType::DeviceCode
+        }
+        mas_config::ClientGrantTypeConfig::Ciba => {
+            oauth2_types::requests::GrantType::ClientInitiatedBackchannelAuthentication
+        }
+    }
+}
+
+fn map_response_type(
+    config: mas_config::ClientResponseTypeConfig,
+) -> mas_iana::oauth::OAuthAuthorizationEndpointResponseType {
+    match config {
+        mas_config::ClientResponseTypeConfig::Code => {
+            mas_iana::oauth::OAuthAuthorizationEndpointResponseType::Code
+        }
+        mas_config::ClientResponseTypeConfig::None => {
+            mas_iana::oauth::OAuthAuthorizationEndpointResponseType::None
+        }
+    }
+}
+
 fn map_import_action(
     config: mas_config::UpstreamOAuth2ImportAction,
 ) -> mas_data_model::UpstreamOAuthProviderImportAction {
@@ -75,6 +121,11 @@ fn map_claims_imports(
                 mas_data_model::UpsreamOAuthProviderSetEmailVerification::Import
             }
         },
+        groups: mas_data_model::UpstreamOAuthProviderGroupsPreference {
+            action: map_import_action(config.groups.action),
+            claim: config.groups.claim.clone(),
+            admin_groups: config.groups.admin_groups.clone(),
+        },
     }
 }
 
@@ -84,6 +135,7 @@ pub async fn config_sync(
     clients_config: ClientsConfig,
     connection: &mut PgConnection,
     encrypter: &Encrypter,
+    rng: &mut (impl RngCore + CryptoRng + Send),
     clock: &dyn Clock,
     prune: bool,
     dry_run: bool,
@@ -198,7 +250,9 @@ pub async fn config_sync(
             let encrypted_client_secret = provider
                 .client_secret
                 .as_deref()
-                .map(|client_secret| encrypter.encrypt_to_string(client_secret.as_bytes()))
+                .map(|client_secret| {
+                    encrypter.encrypt_to_string(&mut *rng, client_secret.as_bytes())
+                })
                 .transpose()?;
 
             let discovery_mode = match provider.discovery_mode {
@@ -239,6 +293,18 @@ pub async fn config_sync(
                 }
             };
 
+            let on_deprovision = match provider.on_deprovision {
+                mas_config::UpstreamOAuth2OnDeprovision::Ignore => {
+                    mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore
+                }
+                mas_config::UpstreamOAuth2OnDeprovision::Lock => {
+                    mas_data_model::UpstreamOAuthProviderOnDeprovision::Lock
+                }
+                mas_config::UpstreamOAuth2OnDeprovision::Deactivate => {
+                    mas_data_model::UpstreamOAuthProviderOnDeprovision::Deactivate
+                }
+            };
+
             repo.upstream_oauth_provider()
                 .upsert(
                     clock,
@@ -264,6 +330,8 @@ pub async fn config_sync(
                             .additional_authorization_parameters
                             .into_iter()
                             .collect(),
+                        store_tokens: provider.store_tokens,
+                        on_deprovision,
                     },
                 )
                 .await?;
@@ -318,9 +386,18 @@ pub async fn config_sync(
 
             // TODO: should be moved somewhere else
             let encrypted_client_secret = client_secret
-                .map(|client_secret| encrypter.encrypt_to_string(client_secret.as_bytes()))
+                .map(|client_secret| {
+                    encrypter.encrypt_to_string(&mut *rng, client_secret.as_bytes())
+                })
                 .transpose()?;
 
+            let default_scope = client
+                .default_scope
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .context("Invalid default_scope")?;
+
             repo.oauth2_client()
                 .upsert_static(
                     client.client_id,
@@ -329,6 +406,24 @@ pub async fn config_sync(
                     jwks.cloned(),
                     jwks_uri.cloned(),
                     client.redirect_uris,
+                    client
+                        .response_types
+                        .into_iter()
+                        .map(map_response_type)
+                        .collect(),
+                    client.grant_types.into_iter().map(map_grant_type).collect(),
+                    client.application_type.map(map_application_type),
+                    client.skip_consent,
+                    client.allowed_scopes,
+                    default_scope,
+                    client.strip_disallowed_scopes,
+                    client.client_name,
+                    client.client_uri,
+                    client.logo_uri,
+                    client.policy_uri,
+                    client.tos_uri,
+                    client.tls_client_auth_san_dns,
+                    client.tls_client_auth_san_email,
                 )
                 .await?;
         }
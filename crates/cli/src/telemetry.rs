@@ -40,6 +40,7 @@ use opentelemetry_sdk::{
 };
 use opentelemetry_semantic_conventions as semcov;
 use prometheus::Registry;
+use sentry::protocol::{Context as SentryContext, Event};
 use tokio::sync::OnceCell;
 use url::Url;
 
@@ -256,3 +257,104 @@ fn resource() -> Resource {
 
     resource.merge(&detected)
 }
+
+/// Headers which carry credentials, and are never sent to Sentry
+const SENTRY_SENSITIVE_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+/// Strip the credentials from the events sent to Sentry
+///
+/// The request context attached by the HTTP layer contains the full URL and
+/// headers of the request: query strings carry authorization codes and `state`
+/// parameters, headers carry access tokens and session cookies, and form bodies
+/// carry passwords. The query string recorded on the request span is removed
+/// from the event fields for the same reason.
+#[allow(clippy::unnecessary_wraps)]
+pub fn scrub_sentry_event(mut event: Event<'static>) -> Option<Event<'static>> {
+    if let Some(request) = &mut event.request {
+        if let Some(url) = &mut request.url {
+            url.set_query(None);
+            url.set_fragment(None);
+        }
+        request.query_string = None;
+        request.data = None;
+        request.cookies = None;
+        request.headers.retain(|name, _| {
+            !SENTRY_SENSITIVE_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header))
+        });
+    }
+
+    let url_query = semcov::trace::URL_QUERY;
+    event.extra.remove(url_query);
+    for context in event.contexts.values_mut() {
+        if let SentryContext::Other(fields) = context {
+            fields.remove(url_query);
+        }
+    }
+
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use sentry::protocol::{Map, Request};
+
+    use super::*;
+
+    #[test]
+    fn test_scrub_sentry_event() {
+        let mut headers = Map::new();
+        headers.insert("Authorization".to_owned(), "Bearer mat_secret".to_owned());
+        headers.insert("cookie".to_owned(), "mas-session=secret".to_owned());
+        headers.insert("user-agent".to_owned(), "Firefox".to_owned());
+
+        let mut fields = Map::new();
+        fields.insert("url.query".to_owned(), "code=secret&state=secret".into());
+        fields.insert("url.path".to_owned(), "/upstream/callback/01".into());
+
+        let mut event = Event {
+            request: Some(Request {
+                url: Some(
+                    "https://example.com/upstream/callback/01?code=secret&state=secret#frag"
+                        .parse()
+                        .unwrap(),
+                ),
+                method: Some("POST".to_owned()),
+                data: Some("username=john&password=secret".to_owned()),
+                query_string: Some("code=secret&state=secret".to_owned()),
+                cookies: Some("mas-session=secret".to_owned()),
+                headers,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        event.contexts.insert(
+            "Rust Tracing Fields".to_owned(),
+            SentryContext::Other(fields),
+        );
+        event
+            .extra
+            .insert("url.query".to_owned(), "code=secret".into());
+
+        let event = scrub_sentry_event(event).unwrap();
+        let request = event.request.unwrap();
+        assert_eq!(
+            request.url.unwrap().as_str(),
+            "https://example.com/upstream/callback/01"
+        );
+        assert_eq!(request.method.as_deref(), Some("POST"));
+        assert!(request.data.is_none());
+        assert!(request.query_string.is_none());
+        assert!(request.cookies.is_none());
+        assert_eq!(request.headers.len(), 1);
+        assert_eq!(request.headers["user-agent"], "Firefox");
+        assert!(event.extra.is_empty());
+
+        let Some(SentryContext::Other(fields)) = event.contexts.get("Rust Tracing Fields") else {
+            panic!("missing tracing fields context");
+        };
+        assert!(!fields.contains_key("url.query"));
+        assert!(fields.contains_key("url.path"));
+    }
+}
@@ -12,16 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Context;
+use camino::Utf8Path;
+use ipnetwork::IpNetwork;
 use mas_config::{
-    BrandingConfig, CaptchaConfig, DatabaseConfig, EmailConfig, EmailSmtpMode, EmailTransportKind,
-    ExperimentalConfig, MatrixConfig, PasswordsConfig, PolicyConfig, TemplatesConfig,
+    BrandingConfig, CaptchaConfig, ClientCertificateMapping, DatabaseConfig, EmailConfig,
+    EmailSmtpMode, EmailTransportKind, ExperimentalConfig, HttpConfig, IntrospectionConfig,
+    MatrixConfig, NotificationChannel, PasswordsConfig, PolicyConfig, RateLimiterConfig,
+    RateLimitingConfig, RedisConfig, SecurityNotificationsConfig, SmsConfig, SmsTransportKind,
+    TemplatesConfig,
 };
-use mas_data_model::SiteConfig;
-use mas_email::{MailTransport, Mailer};
-use mas_handlers::{passwords::PasswordManager, ActivityTracker};
+use mas_data_model::{Network, NotificationChannels, SiteConfig};
+use mas_email::{MailTransport, Mailer, SmsSender, SmsTransport, Webhook};
+use mas_handlers::{
+    introspection_cache,
+    login_risk::{self, LoginRiskConfig},
+    network_ban::NetworkBansConfig,
+    passwords::PasswordManager,
+    rate_limit::{LimiterConfig, Quota, RedisBackend},
+    ActivityTracker, GeoIp, HttpClientFactory, IntrospectionCache, Limiter, LoginRisk, NetworkBans,
+    SpamChecker,
+};
+use mas_http::{ConnectorOptions, Proxy};
 use mas_policy::PolicyFactory;
 use mas_router::UrlBuilder;
 use mas_templates::{SiteConfigExt, TemplateLoadingError, Templates};
@@ -56,9 +70,44 @@ pub async fn password_manager_from_config(
     PasswordManager::new(schemes)
 }
 
+pub fn http_client_factory_from_config(
+    config: &HttpConfig,
+) -> Result<HttpClientFactory, anyhow::Error> {
+    let mut options = ConnectorOptions::default();
+
+    if let Some(url) = &config.client.proxy {
+        let mut proxy_url = url.clone();
+        // Credentials are sent in the `Proxy-Authorization` header, not as part of
+        // the proxy URI
+        let _ = proxy_url.set_username("");
+        let _ = proxy_url.set_password(None);
+
+        let mut proxy =
+            Proxy::new(proxy_url.as_str()).context("invalid outbound HTTP proxy URL")?;
+        if !url.username().is_empty() {
+            proxy = proxy.with_basic_auth(url.username(), url.password().unwrap_or_default());
+        }
+
+        options = options.with_proxy(proxy.with_no_proxy(config.client.no_proxy.clone()));
+    }
+
+    let extra_roots = config
+        .client
+        .load_ca_certificates()
+        .context("failed to load the extra root certificates")?;
+    if !extra_roots.is_empty() {
+        options = options
+            .with_extra_roots(extra_roots)
+            .context("invalid extra root certificates")?;
+    }
+
+    Ok(HttpClientFactory::with_options(&options))
+}
+
 pub fn mailer_from_config(
     config: &EmailConfig,
     templates: &Templates,
+    http_client_factory: &HttpClientFactory,
 ) -> Result<Mailer, anyhow::Error> {
     let from = config.from.parse()?;
     let reply_to = config.reply_to.parse()?;
@@ -91,15 +140,103 @@ pub fn mailer_from_config(
                 EmailSmtpMode::Tls => mas_email::SmtpMode::Tls,
             };
 
-            MailTransport::smtp(mode, hostname, config.port(), credentials)
+            let mut pool_config = mas_email::SmtpPoolConfig::new();
+            if let Some(max_connections) = config.max_connections() {
+                pool_config = pool_config.max_size(max_connections.get());
+            }
+            if let Some(idle_timeout) = config.idle_timeout() {
+                pool_config = pool_config.idle_timeout(idle_timeout);
+            }
+
+            MailTransport::smtp(mode, hostname, config.port(), credentials, pool_config)
                 .context("failed to build SMTP transport")?
         }
         EmailTransportKind::Sendmail => MailTransport::sendmail(config.command()),
+        EmailTransportKind::Mailgun => {
+            // This should have been set ahead of time
+            let domain = config
+                .domain()
+                .context("invalid configuration: missing domain")?;
+
+            let api_key = config
+                .api_key()
+                .context("invalid configuration: missing api_key")?;
+
+            let endpoint = match config.endpoint() {
+                Some(endpoint) => endpoint.clone(),
+                None => mas_email::MAILGUN_DEFAULT_ENDPOINT.parse()?,
+            };
+
+            MailTransport::mailgun(
+                http_client_factory.http_service("email"),
+                &endpoint,
+                domain,
+                api_key.to_owned(),
+            )
+            .context("failed to build Mailgun transport")?
+        }
     };
 
     Ok(Mailer::new(templates.clone(), transport, from, reply_to))
 }
 
+pub fn sms_sender_from_config(
+    config: &SmsConfig,
+    templates: &Templates,
+    http_client_factory: &HttpClientFactory,
+) -> Result<SmsSender, anyhow::Error> {
+    let transport = match config.transport() {
+        SmsTransportKind::Blackhole => SmsTransport::blackhole(),
+        SmsTransportKind::Twilio => {
+            // This should have been set ahead of time
+            let account_sid = config
+                .account_sid()
+                .context("invalid configuration: missing account_sid")?;
+
+            let auth_token = config
+                .auth_token()
+                .context("invalid configuration: missing auth_token")?;
+
+            let from = config
+                .from()
+                .context("invalid configuration: missing from")?;
+
+            SmsTransport::twilio(
+                http_client_factory.http_service("sms"),
+                account_sid.to_owned(),
+                auth_token.to_owned(),
+                from.to_owned(),
+            )
+            .context("failed to build Twilio transport")?
+        }
+        SmsTransportKind::Http => {
+            // This should have been set ahead of time
+            let url = config.url().context("invalid configuration: missing url")?;
+
+            SmsTransport::http(
+                http_client_factory.http_service("sms"),
+                url.clone(),
+                config.token().map(ToOwned::to_owned),
+            )
+        }
+    };
+
+    Ok(SmsSender::new(templates.clone(), transport))
+}
+
+pub fn notification_webhook_from_config(
+    config: &SecurityNotificationsConfig,
+    http_client_factory: &HttpClientFactory,
+) -> Option<Webhook> {
+    let webhook = config.webhook.as_ref()?;
+
+    Some(Webhook::new(
+        http_client_factory.http_service("security-notifications"),
+        webhook.url.clone(),
+        webhook.token.clone(),
+    ))
+}
+
 pub async fn policy_factory_from_config(
     config: &PolicyConfig,
 ) -> Result<PolicyFactory, anyhow::Error> {
@@ -148,6 +285,177 @@ pub fn captcha_config_from_config(
     }))
 }
 
+pub async fn limiter_from_config(
+    config: &RateLimitingConfig,
+    redis_config: &RedisConfig,
+) -> Result<Limiter, anyhow::Error> {
+    let quota = |config: RateLimiterConfig| Quota {
+        burst: config.burst,
+        per_second: config.per_second,
+    };
+
+    let config = LimiterConfig {
+        login_per_ip: quota(config.login.per_ip),
+        login_per_account: quota(config.login.per_account),
+        registration_per_ip: quota(config.registration),
+        account_recovery_per_ip: quota(config.account_recovery.per_ip),
+        account_recovery_per_address: quota(config.account_recovery.per_address),
+        token_per_ip: quota(config.token.per_ip),
+        token_per_client: quota(config.token.per_client),
+        backchannel_authentication_per_client: quota(config.backchannel_authentication.per_client),
+        backchannel_authentication_per_user: quota(config.backchannel_authentication.per_user),
+    };
+
+    let Some(uri) = &redis_config.uri else {
+        return Ok(Limiter::new(config));
+    };
+
+    info!("Sharing the rate limiting state through Redis");
+    let backend = RedisBackend::connect(uri)
+        .await
+        .context("could not connect to Redis")?;
+
+    Ok(Limiter::with_backend(config, Arc::new(backend)))
+}
+
+pub fn network_bans_from_config(
+    config: &RateLimitingConfig,
+    trusted_proxies: &[IpNetwork],
+    limiter: Limiter,
+) -> Result<NetworkBans, anyhow::Error> {
+    // Failures are attributed to a trusted proxy when the address of the client
+    // could not be inferred, so those addresses must never be banned
+    let trusted_proxies = trusted_proxies
+        .iter()
+        .map(|network| Network::new(network.network(), network.prefix()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid trusted proxy network")?;
+
+    let Some(config) = &config.network_bans else {
+        return Ok(NetworkBans::new(limiter, None).with_trusted_proxies(trusted_proxies));
+    };
+
+    let config = NetworkBansConfig {
+        failed_attempts: Quota {
+            burst: config.failed_attempts.burst,
+            per_second: config.failed_attempts.per_second,
+        },
+        duration: chrono::Duration::from_std(config.duration)
+            .context("invalid network ban duration")?,
+        ipv4_prefix_len: config.ipv4_prefix_len,
+        ipv6_prefix_len: config.ipv6_prefix_len,
+    };
+
+    Ok(NetworkBans::new(limiter, Some(config)).with_trusted_proxies(trusted_proxies))
+}
+
+pub async fn introspection_cache_from_config(
+    config: &IntrospectionConfig,
+    redis_config: &RedisConfig,
+) -> Result<IntrospectionCache, anyhow::Error> {
+    let Some(ttl) = config.cache_ttl else {
+        return Ok(IntrospectionCache::disabled());
+    };
+
+    let Some(uri) = &redis_config.uri else {
+        return Ok(IntrospectionCache::new(ttl));
+    };
+
+    info!("Sharing the introspection cache through Redis");
+    let backend = introspection_cache::RedisBackend::connect(uri)
+        .await
+        .context("could not connect to Redis")?;
+
+    Ok(IntrospectionCache::with_backend(ttl, Arc::new(backend)))
+}
+
+pub async fn geoip_from_config(config: &ExperimentalConfig) -> Result<GeoIp, anyhow::Error> {
+    if config.geoip_databases.is_empty() {
+        return Ok(GeoIp::disabled());
+    }
+
+    let mut files = Vec::with_capacity(config.geoip_databases.len());
+    for path in &config.geoip_databases {
+        let file = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read GeoIP database {path}"))?;
+        files.push(file);
+    }
+
+    let geoip = GeoIp::new(files).context("failed to load the GeoIP databases")?;
+    info!("Loaded {} GeoIP database(s)", config.geoip_databases.len());
+    Ok(geoip)
+}
+
+pub fn spam_checker_from_config(
+    config: &ExperimentalConfig,
+    http_client_factory: &HttpClientFactory,
+) -> SpamChecker {
+    let Some(spam_check) = &config.spam_check else {
+        return SpamChecker::disabled();
+    };
+
+    SpamChecker::new(
+        http_client_factory.clone(),
+        spam_check.url.clone(),
+        spam_check.token.clone(),
+        spam_check.allow_on_error,
+    )
+}
+
+async fn network_list_from_file(path: &Utf8Path) -> Result<Vec<Network>, anyhow::Error> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read network list {path}"))?;
+
+    let networks = login_risk::parse_network_list(&contents)
+        .with_context(|| format!("invalid network in {path}"))?;
+    info!("Loaded {} network(s) from {path}", networks.len());
+    Ok(networks)
+}
+
+pub async fn login_risk_from_config(
+    config: &ExperimentalConfig,
+) -> Result<LoginRisk, anyhow::Error> {
+    let Some(login_risk) = &config.login_risk else {
+        return Ok(LoginRisk::disabled());
+    };
+
+    let low_reputation_networks = match &login_risk.low_reputation_networks_file {
+        Some(path) => network_list_from_file(path).await?,
+        None => Vec::new(),
+    };
+
+    let tor_exit_nodes = match &login_risk.tor_exit_nodes_file {
+        Some(path) => network_list_from_file(path).await?,
+        None => Vec::new(),
+    };
+
+    let login_risk_config = LoginRiskConfig {
+        new_device_score: login_risk.new_device_score,
+        low_reputation_score: login_risk.low_reputation_score,
+        tor_exit_node_score: login_risk.tor_exit_node_score,
+        impossible_travel_score: login_risk.impossible_travel_score,
+        impossible_travel_window: login_risk.impossible_travel_window,
+        mfa_threshold: login_risk.mfa_threshold,
+        deny_threshold: login_risk.deny_threshold,
+    };
+
+    Ok(LoginRisk::new(
+        login_risk_config,
+        low_reputation_networks,
+        tor_exit_nodes,
+    ))
+}
+
+fn notification_channels_from_config(channels: &[NotificationChannel]) -> NotificationChannels {
+    NotificationChannels {
+        email: channels.contains(&NotificationChannel::Email),
+        matrix: channels.contains(&NotificationChannel::Matrix),
+        webhook: channels.contains(&NotificationChannel::Webhook),
+    }
+}
+
 pub fn site_config_from_config(
     branding_config: &BrandingConfig,
     matrix_config: &MatrixConfig,
@@ -160,9 +468,21 @@ pub fn site_config_from_config(
         access_token_ttl: experimental_config.access_token_ttl,
         compat_token_ttl: experimental_config.compat_token_ttl,
         server_name: matrix_config.homeserver.clone(),
+        service_name: branding_config.service_name.clone(),
+        logo_uri: branding_config.logo_uri.clone(),
+        primary_color: branding_config.primary_color.clone(),
+        accent_color: branding_config.accent_color.clone(),
+        custom_css: branding_config.custom_css.clone(),
         policy_uri: branding_config.policy_uri.clone(),
         tos_uri: branding_config.tos_uri.clone(),
         imprint: branding_config.imprint.clone(),
+        announcement: branding_config.announcement.as_ref().map(|announcement| {
+            mas_data_model::SiteAnnouncement {
+                message: announcement.message.clone(),
+                starts_at: announcement.starts_at,
+                ends_at: announcement.ends_at,
+            }
+        }),
         password_login_enabled: password_config.enabled(),
         password_registration_enabled: password_config.enabled()
             && experimental_config.password_registration_enabled,
@@ -172,7 +492,54 @@ pub fn site_config_from_config(
             && experimental_config.password_change_allowed,
         account_recovery_allowed: password_config.enabled()
             && experimental_config.account_recovery_enabled,
+        login_alerts_enabled: experimental_config.login_alerts_enabled,
+        email_otp_enabled: password_config.enabled() && experimental_config.email_otp_enabled,
+        magic_link_login_allowed: experimental_config.magic_link_login_enabled,
+        guest_registration_allowed: experimental_config.guest_registration_enabled,
+        public_clients_allowed: experimental_config.public_clients_allowed,
+        offline_access_required: experimental_config.offline_access_required,
+        browser_session_idle_timeout: experimental_config.browser_session_idle_timeout,
+        browser_session_max_lifetime: experimental_config.browser_session_max_lifetime,
+        sensitive_action_reauth_ttl: experimental_config.sensitive_action_reauth_ttl,
+        data_retention: mas_data_model::DataRetentionConfig {
+            ip_addresses: experimental_config
+                .data_retention
+                .ip_addresses
+                .or(experimental_config.session_activity_retention),
+            user_agents: experimental_config.data_retention.user_agents,
+            inactive_sessions: experimental_config.data_retention.inactive_sessions,
+        },
+        security_notifications: mas_data_model::SecurityNotificationsConfig {
+            new_login: notification_channels_from_config(
+                &experimental_config.security_notifications.new_login,
+            ),
+            password_changed: notification_channels_from_config(
+                &experimental_config.security_notifications.password_changed,
+            ),
+            email_changed: notification_channels_from_config(
+                &experimental_config.security_notifications.email_changed,
+            ),
+        },
         captcha,
+        spnego_login: experimental_config.spnego_login.as_ref().map(|config| {
+            mas_data_model::SpnegoLoginConfig {
+                realms: config.realms.clone(),
+                principal_header: config.principal_header.clone(),
+            }
+        }),
+        client_certificate_login: experimental_config.client_certificate_login.as_ref().map(
+            |config| mas_data_model::ClientCertificateLoginConfig {
+                url: config.url.clone(),
+                mapping: match config.mapping {
+                    ClientCertificateMapping::CommonName => {
+                        mas_data_model::ClientCertificateMapping::CommonName
+                    }
+                    ClientCertificateMapping::Email => {
+                        mas_data_model::ClientCertificateMapping::Email
+                    }
+                },
+            },
+        ),
     })
 }
 
@@ -183,6 +550,7 @@ pub async fn templates_from_config(
 ) -> Result<Templates, TemplateLoadingError> {
     Templates::load(
         config.path.clone(),
+        config.overrides_path.clone(),
         url_builder.clone(),
         config.assets_manifest.clone(),
         config.translations_path.clone(),
@@ -238,11 +606,21 @@ fn database_connect_options_from_config(
 /// Create a database connection pool from the configuration
 #[tracing::instrument(name = "db.connect", skip_all, err(Debug))]
 pub async fn database_pool_from_config(config: &DatabaseConfig) -> Result<PgPool, anyhow::Error> {
-    let options = database_connect_options_from_config(config)?;
+    let mut options = database_connect_options_from_config(config)?;
+
+    // The timeout is set on the connections of the pool only, as single
+    // connections are used for long-running operations like migrations
+    if let Some(statement_timeout) = config.statement_timeout {
+        options = options.options([(
+            "statement_timeout",
+            format!("{}ms", statement_timeout.as_millis()),
+        )]);
+    }
+
     PgPoolOptions::new()
         .max_connections(config.max_connections.into())
         .min_connections(config.min_connections)
-        .acquire_timeout(config.connect_timeout)
+        .acquire_timeout(config.acquire_timeout.unwrap_or(config.connect_timeout))
         .idle_timeout(config.idle_timeout)
         .max_lifetime(config.max_lifetime)
         .connect_with(options)
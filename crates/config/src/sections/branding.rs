@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use chrono::{DateTime, Utc};
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Serialize};
 use url::Url;
 
 use crate::ConfigurationSection;
@@ -42,9 +43,55 @@ pub struct BrandingConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub imprint: Option<String>,
 
-    /// Logo displayed in some web pages.
+    /// Logo displayed at the top of web pages and emails.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logo_uri: Option<Url>,
+
+    /// Primary brand colour, as a hexadecimal `#rrggbb` or `#rgb` value. It is
+    /// used for buttons and other primary actions in web pages and emails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_color: Option<String>,
+
+    /// Accent colour, as a hexadecimal `#rrggbb` or `#rgb` value. It is used
+    /// for links in web pages and emails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+
+    /// Additional CSS, injected in a `<style>` element in all web pages and
+    /// emails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_css: Option<String>,
+
+    /// Announcement banner displayed at the top of the login, consent and
+    /// account pages, e.g. to communicate a planned maintenance window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub announcement: Option<AnnouncementConfig>,
+}
+
+/// An announcement banner displayed on web pages
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct AnnouncementConfig {
+    /// The message to display. A small subset of Markdown is supported:
+    /// `**bold**`, `*italic*`, `` `code` `` and `[links](https://…)`. Any HTML
+    /// is escaped.
+    pub message: String,
+
+    /// When to start displaying the banner. If not set, it is displayed
+    /// immediately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+
+    /// When to stop displaying the banner. If not set, it is displayed
+    /// until removed from the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// Check that a colour is a hexadecimal `#rrggbb` or `#rgb` value
+fn is_valid_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
 }
 
 impl BrandingConfig {
@@ -55,9 +102,130 @@ impl BrandingConfig {
             && self.tos_uri.is_none()
             && self.imprint.is_none()
             && self.logo_uri.is_none()
+            && self.primary_color.is_none()
+            && self.accent_color.is_none()
+            && self.custom_css.is_none()
+            && self.announcement.is_none()
     }
 }
 
 impl ConfigurationSection for BrandingConfig {
     const PATH: Option<&'static str> = Some("branding");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let metadata = figment.find_metadata(Self::PATH.unwrap());
+
+        let error_on_field = |mut error: figment::error::Error, field: &'static str| {
+            error.metadata = metadata.cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned(), field.to_owned()];
+            error
+        };
+
+        for (field, color) in [
+            ("primary_color", &self.primary_color),
+            ("accent_color", &self.accent_color),
+        ] {
+            if let Some(color) = color {
+                if !is_valid_color(color) {
+                    return Err(error_on_field(
+                        figment::error::Error::custom("must be a #rrggbb or #rgb colour"),
+                        field,
+                    ));
+                }
+            }
+        }
+
+        // The CSS ends up in a `<style>` element, make sure it can't close it
+        if let Some(css) = &self.custom_css {
+            if css.to_ascii_lowercase().contains("</style") {
+                return Err(error_on_field(
+                    figment::error::Error::custom("must not contain a closing </style> tag"),
+                    "custom_css",
+                ));
+            }
+        }
+
+        if let Some(announcement) = &self.announcement {
+            if announcement.message.trim().is_empty() {
+                return Err(error_on_field(
+                    figment::error::Error::custom("announcement message must not be empty"),
+                    "announcement",
+                ));
+            }
+
+            if let (Some(starts_at), Some(ends_at)) = (announcement.starts_at, announcement.ends_at)
+            {
+                if ends_at <= starts_at {
+                    return Err(error_on_field(
+                        figment::error::Error::custom("announcement must end after it starts"),
+                        "announcement",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Yaml},
+        Figment, Jail,
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r##"
+                    branding:
+                      service_name: Example
+                      primary_color: "#0dbd8b"
+                      accent_color: "#fff"
+                      custom_css: "body { font-family: serif; }"
+                      announcement:
+                        message: "**Maintenance** tonight"
+                        starts_at: 2024-07-31T20:00:00Z
+                        ends_at: 2024-07-31T22:00:00Z
+                "##,
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<BrandingConfig>("branding")?;
+
+            assert_eq!(config.service_name.as_deref(), Some("Example"));
+            assert_eq!(config.primary_color.as_deref(), Some("#0dbd8b"));
+            assert_eq!(config.accent_color.as_deref(), Some("#fff"));
+
+            let announcement = config.announcement.unwrap();
+            assert_eq!(announcement.message, "**Maintenance** tonight");
+            assert_eq!(
+                announcement.starts_at,
+                Some("2024-07-31T20:00:00Z".parse().unwrap())
+            );
+            assert_eq!(
+                announcement.ends_at,
+                Some("2024-07-31T22:00:00Z".parse().unwrap())
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn validate_colors() {
+        assert!(is_valid_color("#0dbd8b"));
+        assert!(is_valid_color("#FFF"));
+        assert!(!is_valid_color("0dbd8b"));
+        assert!(!is_valid_color("#0dbd8"));
+        assert!(!is_valid_color("red"));
+        assert!(!is_valid_color("#fff;}"));
+    }
 }
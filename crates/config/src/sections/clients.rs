@@ -59,6 +59,10 @@ pub enum ClientAuthMethodConfig {
     /// `client_secret_basic`: a `client_assertion` sent in the request body and
     /// signed by an asymmetric key
     PrivateKeyJwt,
+
+    /// `tls_client_auth`: a TLS client certificate issued by one of the
+    /// certificate authorities trusted by the listener
+    TlsClientAuth,
 }
 
 impl std::fmt::Display for ClientAuthMethodConfig {
@@ -69,10 +73,74 @@ impl std::fmt::Display for ClientAuthMethodConfig {
             ClientAuthMethodConfig::ClientSecretPost => write!(f, "client_secret_post"),
             ClientAuthMethodConfig::ClientSecretJwt => write!(f, "client_secret_jwt"),
             ClientAuthMethodConfig::PrivateKeyJwt => write!(f, "private_key_jwt"),
+            ClientAuthMethodConfig::TlsClientAuth => write!(f, "tls_client_auth"),
         }
     }
 }
 
+/// The kind of application a client is
+#[derive(JsonSchema, Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientApplicationTypeConfig {
+    /// `web`: a web application. Redirect URIs must match exactly
+    Web,
+
+    /// `native`: a native application. Redirect URIs on the loopback
+    /// interface can use any port, and private-use URI schemes are allowed
+    Native,
+}
+
+/// A grant type a client can use at the token endpoint
+#[derive(JsonSchema, Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientGrantTypeConfig {
+    /// `authorization_code`: exchange an authorization code obtained through
+    /// the authorization endpoint
+    AuthorizationCode,
+
+    /// `refresh_token`: refresh an access token
+    RefreshToken,
+
+    /// `client_credentials`: get an access token for the client itself
+    ClientCredentials,
+
+    /// `device_code`: the device authorization grant, for devices with limited
+    /// input capabilities
+    DeviceCode,
+
+    /// `ciba`: the client-initiated backchannel authentication grant, where
+    /// the user approves the request out of band
+    Ciba,
+}
+
+fn default_grant_types() -> Vec<ClientGrantTypeConfig> {
+    vec![
+        ClientGrantTypeConfig::AuthorizationCode,
+        ClientGrantTypeConfig::RefreshToken,
+        ClientGrantTypeConfig::ClientCredentials,
+        ClientGrantTypeConfig::DeviceCode,
+    ]
+}
+
+/// A response type a client can use at the authorization endpoint
+#[derive(JsonSchema, Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientResponseTypeConfig {
+    /// `code`: get back an authorization code, requires the
+    /// `authorization_code` grant type
+    Code,
+
+    /// `none`: don't get back anything, other than the `state` parameter
+    None,
+}
+
+fn default_response_types() -> Vec<ClientResponseTypeConfig> {
+    vec![
+        ClientResponseTypeConfig::Code,
+        ClientResponseTypeConfig::None,
+    ]
+}
+
 /// An OAuth 2.0 client configuration
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ClientConfig {
@@ -105,6 +173,74 @@ pub struct ClientConfig {
     /// List of allowed redirect URIs
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub redirect_uris: Vec<Url>,
+
+    /// The kind of application this client is, which controls how strictly
+    /// redirect URIs are matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_type: Option<ClientApplicationTypeConfig>,
+
+    /// List of grant types this client is allowed to use. Defaults to all the
+    /// supported grant types
+    #[serde(default = "default_grant_types")]
+    pub grant_types: Vec<ClientGrantTypeConfig>,
+
+    /// List of response types this client is allowed to use at the
+    /// authorization endpoint. Defaults to `code` and `none`
+    #[serde(default = "default_response_types")]
+    pub response_types: Vec<ClientResponseTypeConfig>,
+
+    /// Whether to skip the consent screen for this client. This should only
+    /// be enabled for trusted first-party clients. The consent screen is
+    /// still shown if the client explicitly asks for it with `prompt=consent`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_consent: bool,
+
+    /// List of scope tokens this client is allowed to request. A trailing `*`
+    /// matches any suffix, e.g. `urn:mas:graphql:*`. If not set, the client
+    /// can request any scope allowed by the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_scopes: Option<Vec<String>>,
+
+    /// Scope used when the client doesn't request any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_scope: Option<String>,
+
+    /// Whether requested scope tokens which are not in `allowed_scopes` are
+    /// removed from the request, instead of rejecting it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_disallowed_scopes: bool,
+
+    /// Human-readable name of the client, shown on the consent screens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+
+    /// URL of the home page of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<Url>,
+
+    /// URL of the logo of the client, shown on the consent screens. It is
+    /// fetched by the service, so it has to be a publicly reachable `https`
+    /// URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<Url>,
+
+    /// URL of the privacy policy of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_uri: Option<Url>,
+
+    /// URL of the terms of service of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tos_uri: Option<Url>,
+
+    /// DNS name which must be in the subject alternative names of the
+    /// certificate used by the `tls_client_auth` authentication method
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_client_auth_san_dns: Option<String>,
+
+    /// Email address which must be in the subject alternative names of the
+    /// certificate used by the `tls_client_auth` authentication method
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_client_auth_san_email: Option<String>,
 }
 
 impl ClientConfig {
@@ -158,6 +294,31 @@ impl ClientConfig {
                 }
             }
 
+            ClientAuthMethodConfig::TlsClientAuth => {
+                if self.tls_client_auth_san_dns.is_some()
+                    == self.tls_client_auth_san_email.is_some()
+                {
+                    let error = figment::error::Error::custom(
+                        "exactly one of tls_client_auth_san_dns or tls_client_auth_san_email is required for tls_client_auth",
+                    );
+                    return Err(error.with_path("client_auth_method"));
+                }
+
+                if self.client_secret.is_some() {
+                    let error = figment::error::Error::custom(
+                        "client_secret is not allowed with tls_client_auth",
+                    );
+                    return Err(error.with_path("client_secret"));
+                }
+
+                if self.jwks.is_some() || self.jwks_uri.is_some() {
+                    let error = figment::error::Error::custom(
+                        "jwks and jwks_uri are not allowed with tls_client_auth",
+                    );
+                    return Err(error.with_path("jwks"));
+                }
+            }
+
             ClientAuthMethodConfig::None => {
                 if self.client_secret.is_some() {
                     let error = figment::error::Error::custom(
@@ -182,6 +343,59 @@ impl ClientConfig {
             }
         }
 
+        if !matches!(
+            self.client_auth_method,
+            ClientAuthMethodConfig::TlsClientAuth
+        ) && (self.tls_client_auth_san_dns.is_some() || self.tls_client_auth_san_email.is_some())
+        {
+            let error = figment::error::Error::custom(format!(
+                "tls_client_auth_san_dns and tls_client_auth_san_email are not allowed with {auth_method}"
+            ));
+            return Err(error.with_path("client_auth_method"));
+        }
+
+        if self
+            .response_types
+            .contains(&ClientResponseTypeConfig::Code)
+            && !self
+                .grant_types
+                .contains(&ClientGrantTypeConfig::AuthorizationCode)
+        {
+            let error = figment::error::Error::custom(
+                "the code response type requires the authorization_code grant type",
+            );
+            return Err(error.with_path("response_types"));
+        }
+
+        if self.strip_disallowed_scopes && self.allowed_scopes.is_none() {
+            let error = figment::error::Error::custom(
+                "strip_disallowed_scopes requires allowed_scopes to be set",
+            );
+            return Err(error.with_path("strip_disallowed_scopes"));
+        }
+
+        for (field, uri) in [
+            ("client_uri", &self.client_uri),
+            ("policy_uri", &self.policy_uri),
+            ("tos_uri", &self.tos_uri),
+        ] {
+            if let Some(uri) = uri {
+                if uri.scheme() != "https" && uri.scheme() != "http" {
+                    let error = figment::error::Error::custom(format!(
+                        "{field} must use the http or https scheme"
+                    ));
+                    return Err(error.with_path(field));
+                }
+            }
+        }
+
+        if let Some(logo_uri) = &self.logo_uri {
+            if logo_uri.scheme() != "https" {
+                let error = figment::error::Error::custom("logo_uri must use the https scheme");
+                return Err(error.with_path("logo_uri"));
+            }
+        }
+
         Ok(())
     }
 
@@ -200,6 +414,7 @@ impl ClientConfig {
                 OAuthClientAuthenticationMethod::ClientSecretJwt
             }
             ClientAuthMethodConfig::PrivateKeyJwt => OAuthClientAuthenticationMethod::PrivateKeyJwt,
+            ClientAuthMethodConfig::TlsClientAuth => OAuthClientAuthenticationMethod::TlsClientAuth,
         }
     }
 }
@@ -274,10 +489,20 @@ mod tests {
                       client_auth_method: none
                       redirect_uris:
                         - https://exemple.fr/callback
+                      skip_consent: true
+                      client_name: Exemple
+                      logo_uri: https://exemple.fr/logo.png
+                      policy_uri: https://exemple.fr/privacy
 
                     - client_id: 01GFWR32NCQ12B8Z0J8CPXRRB6
                       client_auth_method: client_secret_basic
                       client_secret: hello
+                      grant_types:
+                        - client_credentials
+                      response_types: []
+                      allowed_scopes:
+                        - urn:mas:graphql:*
+                      default_scope: urn:mas:graphql:*
 
                     - client_id: 01GFWR3WHR93Y5HK389H28VHZ9
                       client_auth_method: client_secret_post
@@ -304,6 +529,13 @@ mod tests {
                           use: "sig"
                           e: "AQAB"
                           n: "0hukqytPwrj1RbMYhYoepCi3CN5k7DwYkTe_Cmb7cP9_qv4ok78KdvFXt5AnQxCRwBD7-qTNkkfMWO2RxUMBdQD0ED6tsSb1n5dp0XY8dSWiBDCX8f6Hr-KolOpvMLZKRy01HdAWcM6RoL9ikbjYHUEW1C8IJnw3MzVHkpKFDL354aptdNLaAdTCBvKzU9WpXo10g-5ctzSlWWjQuecLMQ4G1mNdsR1LHhUENEnOvgT8cDkX0fJzLbEbyBYkdMgKggyVPEB1bg6evG4fTKawgnf0IDSPxIU-wdS9wdSP9ZCJJPLi5CEp-6t6rE_sb2dGcnzjCGlembC57VwpkUvyMw"
+
+                    - client_id: 01GFWR4N5PHHXNZ0KMG5YDA1J8
+                      client_auth_method: tls_client_auth
+                      tls_client_auth_san_dns: service.example.com
+                      grant_types:
+                        - client_credentials
+                      response_types: []
                 "#,
             )?;
 
@@ -311,7 +543,7 @@ mod tests {
                 .merge(Yaml::file("config.yaml"))
                 .extract_inner::<ClientsConfig>("clients")?;
 
-            assert_eq!(config.0.len(), 5);
+            assert_eq!(config.0.len(), 6);
 
             assert_eq!(
                 config.0[0].client_id,
@@ -321,12 +553,48 @@ mod tests {
                 config.0[0].redirect_uris,
                 vec!["https://exemple.fr/callback".parse().unwrap()]
             );
+            assert!(config.0[0].skip_consent);
+            assert_eq!(config.0[0].client_name.as_deref(), Some("Exemple"));
+            assert_eq!(
+                config.0[0].logo_uri,
+                Some("https://exemple.fr/logo.png".parse().unwrap())
+            );
+            assert_eq!(
+                config.0[0].policy_uri,
+                Some("https://exemple.fr/privacy".parse().unwrap())
+            );
+            assert_eq!(config.0[0].tos_uri, None);
+            assert_eq!(config.0[0].grant_types, default_grant_types());
+            assert_eq!(config.0[0].response_types, default_response_types());
 
             assert_eq!(
                 config.0[1].client_id,
                 Ulid::from_str("01GFWR32NCQ12B8Z0J8CPXRRB6").unwrap()
             );
             assert_eq!(config.0[1].redirect_uris, Vec::new());
+            assert!(!config.0[1].skip_consent);
+            assert_eq!(
+                config.0[1].grant_types,
+                vec![ClientGrantTypeConfig::ClientCredentials]
+            );
+            assert_eq!(config.0[1].response_types, Vec::new());
+            assert_eq!(
+                config.0[1].allowed_scopes,
+                Some(vec!["urn:mas:graphql:*".to_owned()])
+            );
+            assert_eq!(
+                config.0[1].default_scope.as_deref(),
+                Some("urn:mas:graphql:*")
+            );
+
+            assert_eq!(
+                config.0[5].client_auth_method(),
+                OAuthClientAuthenticationMethod::TlsClientAuth
+            );
+            assert_eq!(
+                config.0[5].tls_client_auth_san_dns.as_deref(),
+                Some("service.example.com")
+            );
 
             Ok(())
         });
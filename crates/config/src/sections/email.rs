@@ -14,10 +14,15 @@
 
 #![allow(deprecated)]
 
-use std::num::NonZeroU16;
+use std::{
+    num::{NonZeroU16, NonZeroU32},
+    time::Duration,
+};
 
 use schemars::JsonSchema;
 use serde::{de::Error, Deserialize, Serialize};
+use serde_with::serde_as;
+use url::Url;
 
 use super::ConfigurationSection;
 
@@ -57,6 +62,9 @@ pub enum EmailTransportKind {
 
     /// Send emails by calling sendmail
     Sendmail,
+
+    /// Send emails through the Mailgun HTTP API
+    Mailgun,
 }
 
 fn default_email() -> String {
@@ -69,6 +77,7 @@ fn default_sendmail_command() -> Option<String> {
 }
 
 /// Configuration related to sending emails
+#[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct EmailConfig {
     /// Email address to use as From when sending emails
@@ -113,10 +122,37 @@ pub struct EmailConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     password: Option<String>,
 
+    /// SMTP transport: Maximum number of connections to keep open to the SMTP
+    /// server. Defaults to 10
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<u32>", range(min = 1))]
+    max_connections: Option<NonZeroU32>,
+
+    /// SMTP transport: How long in seconds an unused connection to the SMTP
+    /// server is kept open. Defaults to 60 seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<u64>")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    idle_timeout: Option<Duration>,
+
     /// Sendmail transport: Command to use to send emails
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schemars(default = "default_sendmail_command")]
     command: Option<String>,
+
+    /// Mailgun transport: Domain configured in Mailgun to send emails from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+
+    /// Mailgun transport: API key to authenticate with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<String>,
+
+    /// Mailgun transport: Base URL of the API. Defaults to
+    /// `https://api.mailgun.net/`, use `https://api.eu.mailgun.net/` for
+    /// accounts in the EU region
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoint: Option<Url>,
 }
 
 impl EmailConfig {
@@ -156,11 +192,41 @@ impl EmailConfig {
         self.password.as_deref()
     }
 
+    /// Maximum number of connections to keep open to the SMTP server
+    #[must_use]
+    pub fn max_connections(&self) -> Option<NonZeroU32> {
+        self.max_connections
+    }
+
+    /// How long an unused connection to the SMTP server is kept open
+    #[must_use]
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
     /// Command to use to send emails
     #[must_use]
     pub fn command(&self) -> Option<&str> {
         self.command.as_deref()
     }
+
+    /// Domain configured in Mailgun to send emails from
+    #[must_use]
+    pub fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// API key to authenticate with Mailgun
+    #[must_use]
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref()
+    }
+
+    /// Base URL of the Mailgun API
+    #[must_use]
+    pub fn endpoint(&self) -> Option<&Url> {
+        self.endpoint.as_ref()
+    }
 }
 
 impl Default for EmailConfig {
@@ -174,7 +240,12 @@ impl Default for EmailConfig {
             port: None,
             username: None,
             password: None,
+            max_connections: None,
+            idle_timeout: None,
             command: None,
+            domain: None,
+            api_key: None,
+            endpoint: None,
         }
     }
 }
@@ -203,10 +274,51 @@ impl ConfigurationSection for EmailConfig {
             )
         };
 
+        // Fields which are specific to each transport
+        let smtp_fields = [
+            ("mode", self.mode.is_some()),
+            ("hostname", self.hostname.is_some()),
+            ("port", self.port.is_some()),
+            ("username", self.username.is_some()),
+            ("password", self.password.is_some()),
+            ("max_connections", self.max_connections.is_some()),
+            ("idle_timeout", self.idle_timeout.is_some()),
+        ];
+        let sendmail_fields = [("command", self.command.is_some())];
+        let mailgun_fields = [
+            ("domain", self.domain.is_some()),
+            ("api_key", self.api_key.is_some()),
+            ("endpoint", self.endpoint.is_some()),
+        ];
+
+        let check_unexpected = |fields: &[(&'static str, bool)],
+                                expected: &'static [&'static str]| {
+            for &(field, set) in fields {
+                if set {
+                    return Err(unexpected_field(field, expected));
+                }
+            }
+
+            Ok(())
+        };
+
         match self.transport {
             EmailTransportKind::Blackhole => {}
 
             EmailTransportKind::Smtp => {
+                let expected_fields = &[
+                    "from",
+                    "reply_to",
+                    "transport",
+                    "mode",
+                    "hostname",
+                    "port",
+                    "username",
+                    "password",
+                    "max_connections",
+                    "idle_timeout",
+                ];
+
                 match (self.username.is_some(), self.password.is_some()) {
                     (true, true) | (false, false) => {}
                     (true, false) => {
@@ -225,21 +337,8 @@ impl ConfigurationSection for EmailConfig {
                     return Err(missing_field("hostname"));
                 }
 
-                if self.command.is_some() {
-                    return Err(unexpected_field(
-                        "command",
-                        &[
-                            "from",
-                            "reply_to",
-                            "transport",
-                            "mode",
-                            "hostname",
-                            "port",
-                            "username",
-                            "password",
-                        ],
-                    ));
-                }
+                check_unexpected(&sendmail_fields, expected_fields)?;
+                check_unexpected(&mailgun_fields, expected_fields)?;
             }
 
             EmailTransportKind::Sendmail => {
@@ -249,25 +348,30 @@ impl ConfigurationSection for EmailConfig {
                     return Err(missing_field("command"));
                 }
 
-                if self.mode.is_some() {
-                    return Err(unexpected_field("mode", expected_fields));
-                }
-
-                if self.hostname.is_some() {
-                    return Err(unexpected_field("hostname", expected_fields));
-                }
+                check_unexpected(&smtp_fields, expected_fields)?;
+                check_unexpected(&mailgun_fields, expected_fields)?;
+            }
 
-                if self.port.is_some() {
-                    return Err(unexpected_field("port", expected_fields));
+            EmailTransportKind::Mailgun => {
+                let expected_fields = &[
+                    "from",
+                    "reply_to",
+                    "transport",
+                    "domain",
+                    "api_key",
+                    "endpoint",
+                ];
+
+                if self.domain.is_none() {
+                    return Err(missing_field("domain"));
                 }
 
-                if self.username.is_some() {
-                    return Err(unexpected_field("username", expected_fields));
+                if self.api_key.is_none() {
+                    return Err(missing_field("api_key"));
                 }
 
-                if self.password.is_some() {
-                    return Err(unexpected_field("password", expected_fields));
-                }
+                check_unexpected(&smtp_fields, expected_fields)?;
+                check_unexpected(&sendmail_fields, expected_fields)?;
             }
         }
 
@@ -12,10 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use camino::Utf8PathBuf;
 use chrono::Duration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use url::Url;
 
 use crate::ConfigurationSection;
 
@@ -45,6 +47,341 @@ const fn is_default_false(value: &bool) -> bool {
     *value == default_false()
 }
 
+fn default_principal_header() -> String {
+    "X-Remote-User".to_owned()
+}
+
+fn is_default_principal_header(value: &String) -> bool {
+    *value == default_principal_header()
+}
+
+/// Configuration of the login through SPNEGO, for intranet deployments
+///
+/// The Kerberos ticket exchange is done by a reverse proxy in front of the
+/// `/login/negotiate` endpoint, which forwards the authenticated principal in
+/// a request header. That header is only honored on requests coming from one
+/// of the `http.trusted_proxies`.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SpnegoLoginConfig {
+    /// The Kerberos realms whose principals map to local users. A principal
+    /// `alice@EXAMPLE.COM` logs in as the existing user `alice` if
+    /// `EXAMPLE.COM` is in this list.
+    #[schemars(length(min = 1))]
+    pub realms: Vec<String>,
+
+    /// The request header in which the reverse proxy forwards the
+    /// authenticated principal. Defaults to `X-Remote-User`.
+    #[serde(
+        default = "default_principal_header",
+        skip_serializing_if = "is_default_principal_header"
+    )]
+    pub principal_header: String,
+}
+
+/// How a TLS client certificate is mapped to a user
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientCertificateMapping {
+    /// The common name (`CN`) of the certificate subject is the username
+    #[default]
+    CommonName,
+
+    /// One of the email addresses in the subject alternative names of the
+    /// certificate is a confirmed email address of the user
+    Email,
+}
+
+impl ClientCertificateMapping {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Configuration of the login with TLS client certificates, for example
+/// through smartcards
+///
+/// Client certificates are requested on a dedicated listener, which has
+/// `client_ca` or `client_ca_file` set in its TLS configuration. That listener
+/// must be reachable on the same host name as the public base URL, as the
+/// session cookie is shared between both.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct ClientCertificateLoginConfig {
+    /// The public base URL of the listener which requests client certificates,
+    /// for example `https://auth.example.com:8443/`
+    pub url: Url,
+
+    /// How certificates are mapped to users. Defaults to `common_name`.
+    #[serde(default, skip_serializing_if = "ClientCertificateMapping::is_default")]
+    pub mapping: ClientCertificateMapping,
+}
+
+/// How long personal data about the activity of users is kept
+///
+/// The data is kept indefinitely for windows which are not set. Expired data
+/// is scrubbed by a background job which runs every hour.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct DataRetentionConfig {
+    /// How long, in seconds, IP addresses are kept. This covers the last IP
+    /// address of sessions, and the addresses recorded for login alerts,
+    /// account recovery and login links. Known devices and networks used to
+    /// detect unusual logins are forgotten after this window too.
+    #[schemars(with = "Option<u64>", range(min = 3600))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub ip_addresses: Option<Duration>,
+
+    /// How long, in seconds, user agents are kept. This covers the user agent
+    /// of sessions and of login alerts.
+    #[schemars(with = "Option<u64>", range(min = 3600))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub user_agents: Option<Duration>,
+
+    /// How long, in seconds, a session can stay unused before it is ended.
+    /// This applies to browser, OAuth 2.0 and compatibility sessions, and the
+    /// devices of ended sessions are removed from the homeserver. The OAuth 2.0
+    /// and compatibility sessions of bot users are kept.
+    #[schemars(with = "Option<u64>", range(min = 3600))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub inactive_sessions: Option<Duration>,
+}
+
+impl DataRetentionConfig {
+    fn is_default(&self) -> bool {
+        self.ip_addresses.is_none()
+            && self.user_agents.is_none()
+            && self.inactive_sessions.is_none()
+    }
+}
+
+/// A channel through which security notifications are sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// Send an email to the primary email address of the user
+    Email,
+
+    /// Send a direct message to the user on the homeserver. This uses the
+    /// server notices of Synapse, which must be enabled.
+    Matrix,
+
+    /// Post the event as JSON to the configured webhook
+    Webhook,
+}
+
+fn default_notification_channels() -> Vec<NotificationChannel> {
+    vec![NotificationChannel::Email]
+}
+
+fn is_default_notification_channels(value: &[NotificationChannel]) -> bool {
+    value == [NotificationChannel::Email]
+}
+
+/// Configuration of the webhook security notifications are posted to
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct NotificationWebhookConfig {
+    /// URL of the endpoint to post the notifications to. It receives a JSON
+    /// body with the `event` and `user` fields, plus fields specific to the
+    /// event, and must reply with a 2xx status code.
+    pub url: Url,
+
+    /// Bearer token to authenticate with against the endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Through which channels users are told about security-sensitive events on
+/// their account
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SecurityNotificationsConfig {
+    /// Channels through which users are told about logins from unknown devices
+    /// or networks, if `login_alerts_enabled` is set. Defaults to `[email]`.
+    #[serde(
+        default = "default_notification_channels",
+        skip_serializing_if = "is_default_notification_channels"
+    )]
+    pub new_login: Vec<NotificationChannel>,
+
+    /// Channels through which users are told that their password was changed.
+    /// Defaults to `[email]`.
+    #[serde(
+        default = "default_notification_channels",
+        skip_serializing_if = "is_default_notification_channels"
+    )]
+    pub password_changed: Vec<NotificationChannel>,
+
+    /// Channels through which users are told that their primary email address
+    /// was changed. The email goes to the previous address. Defaults to
+    /// `[email]`.
+    #[serde(
+        default = "default_notification_channels",
+        skip_serializing_if = "is_default_notification_channels"
+    )]
+    pub email_changed: Vec<NotificationChannel>,
+
+    /// Webhook to post notifications to, required if the `webhook` channel is
+    /// used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<NotificationWebhookConfig>,
+}
+
+impl Default for SecurityNotificationsConfig {
+    fn default() -> Self {
+        Self {
+            new_login: default_notification_channels(),
+            password_changed: default_notification_channels(),
+            email_changed: default_notification_channels(),
+            webhook: None,
+        }
+    }
+}
+
+impl SecurityNotificationsConfig {
+    fn is_default(&self) -> bool {
+        is_default_notification_channels(&self.new_login)
+            && is_default_notification_channels(&self.password_changed)
+            && is_default_notification_channels(&self.email_changed)
+            && self.webhook.is_none()
+    }
+
+    /// Whether any kind of notification is sent through the webhook
+    #[must_use]
+    pub fn uses_webhook(&self) -> bool {
+        [&self.new_login, &self.password_changed, &self.email_changed]
+            .into_iter()
+            .flatten()
+            .any(|channel| *channel == NotificationChannel::Webhook)
+    }
+}
+
+/// Configuration of the anti-abuse service registrations and authorizations
+/// are checked against
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SpamCheckConfig {
+    /// URL of the endpoint to call. It receives a JSON body with the `check`
+    /// field set to `registration` or `authorization`, plus fields describing
+    /// the request, and must reply with a JSON object whose `verdict` field is
+    /// `allow`, `deny` or `shadow_ban`.
+    pub url: Url,
+
+    /// Bearer token to authenticate with against the endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// Whether registrations and authorizations go through when the endpoint
+    /// can't be reached or replies with an error. Defaults to `true`.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub allow_on_error: bool,
+}
+
+const fn default_new_device_score() -> u32 {
+    40
+}
+
+const fn default_low_reputation_score() -> u32 {
+    40
+}
+
+const fn default_tor_exit_node_score() -> u32 {
+    60
+}
+
+const fn default_impossible_travel_score() -> u32 {
+    60
+}
+
+fn default_impossible_travel_window() -> Duration {
+    Duration::microseconds(2 * 60 * 60 * 1000 * 1000)
+}
+
+const fn default_mfa_threshold() -> u32 {
+    40
+}
+
+const fn default_deny_threshold() -> u32 {
+    100
+}
+
+/// Configuration of the risk assessment of password logins
+///
+/// Each login is given a score, the sum of the scores of the signals it
+/// matches. Logins scoring at least `mfa_threshold` are confirmed with a code
+/// sent to the primary email address of the user, and logins scoring at least
+/// `deny_threshold` are refused. Every assessment is recorded for auditing.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct LoginRiskConfig {
+    /// Score of a login from a device or network the user never logged in
+    /// from before. Defaults to 40.
+    #[serde(default = "default_new_device_score")]
+    pub new_device_score: u32,
+
+    /// Score of a login from one of the `low_reputation_networks_file`
+    /// networks. Defaults to 40.
+    #[serde(default = "default_low_reputation_score")]
+    pub low_reputation_score: u32,
+
+    /// Path to a file listing networks with a low reputation, one IP address
+    /// or CIDR range per line. Lines starting with `#` are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub low_reputation_networks_file: Option<Utf8PathBuf>,
+
+    /// Score of a login from one of the `tor_exit_nodes_file` addresses.
+    /// Defaults to 60.
+    #[serde(default = "default_tor_exit_node_score")]
+    pub tor_exit_node_score: u32,
+
+    /// Path to a file listing the Tor exit nodes, one IP address or CIDR range
+    /// per line. Lines starting with `#` are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub tor_exit_nodes_file: Option<Utf8PathBuf>,
+
+    /// Score of a login from another country than the previous login of the
+    /// user, if it happened less than `impossible_travel_window` ago. This
+    /// needs a GeoIP database with countries in `geoip_databases`. Defaults to
+    /// 60.
+    #[serde(default = "default_impossible_travel_score")]
+    pub impossible_travel_score: u32,
+
+    /// How long, in seconds, after a login a login from another country is
+    /// considered impossible travel. Defaults to 2 hours.
+    #[schemars(with = "u64", range(min = 60))]
+    #[serde(default = "default_impossible_travel_window")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub impossible_travel_window: Duration,
+
+    /// Score from which the login must be confirmed with a code sent by email.
+    /// Users without a confirmed primary email address are refused instead.
+    /// Defaults to 40.
+    #[serde(default = "default_mfa_threshold")]
+    pub mfa_threshold: u32,
+
+    /// Score from which the login is refused. Defaults to 100.
+    #[serde(default = "default_deny_threshold")]
+    pub deny_threshold: u32,
+}
+
+impl Default for LoginRiskConfig {
+    fn default() -> Self {
+        Self {
+            new_device_score: default_new_device_score(),
+            low_reputation_score: default_low_reputation_score(),
+            low_reputation_networks_file: None,
+            tor_exit_node_score: default_tor_exit_node_score(),
+            tor_exit_nodes_file: None,
+            impossible_travel_score: default_impossible_travel_score(),
+            impossible_travel_window: default_impossible_travel_window(),
+            mfa_threshold: default_mfa_threshold(),
+            deny_threshold: default_deny_threshold(),
+        }
+    }
+}
+
 /// Configuration sections for experimental options
 ///
 /// Do not change these options unless you know what you are doing.
@@ -81,8 +418,8 @@ pub struct ExperimentalConfig {
     #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
     pub email_change_allowed: bool,
 
-    /// Whether users are allowed to change their display names. Defaults to
-    /// `true`.
+    /// Whether users are allowed to change their display names and avatars.
+    /// Defaults to `true`.
     #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
     pub displayname_change_allowed: bool,
 
@@ -93,6 +430,113 @@ pub struct ExperimentalConfig {
     /// Whether email-based account recovery is enabled. Defaults to `false`.
     #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
     pub account_recovery_enabled: bool,
+
+    /// Whether to alert users by email when they log in from an unknown device
+    /// or network. Defaults to `false`.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub login_alerts_enabled: bool,
+
+    /// Whether users are asked for a code sent to their primary email address
+    /// when they log in with a password from an unknown device or network.
+    /// Defaults to `false`.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub email_otp_enabled: bool,
+
+    /// Whether users can log in without a password, by receiving a single-use
+    /// link by email. Defaults to `false`.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub magic_link_login_enabled: bool,
+
+    /// Whether Matrix clients can create guest accounts through the
+    /// compatibility layer. Defaults to `false`.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub guest_registration_enabled: bool,
+
+    /// Whether public clients, which don't authenticate at the token endpoint
+    /// (`token_endpoint_auth_method: none`), are allowed. Those clients must
+    /// use PKCE and send the exact redirect URI used in the authorization
+    /// request when exchanging a code. Defaults to `true`.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub public_clients_allowed: bool,
+
+    /// Whether refresh tokens are only issued to sessions which were granted
+    /// the `offline_access` scope, as recommended by OpenID Connect. Defaults
+    /// to `false`, as most Matrix clients don't request that scope yet.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub offline_access_required: bool,
+
+    /// How long, in seconds, a browser session can stay unused before it is
+    /// ended. Browser sessions don't expire on inactivity if not set.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub browser_session_idle_timeout: Option<Duration>,
+
+    /// Maximum lifetime, in seconds, of a browser session, regardless of its
+    /// activity. Browser sessions are not limited in time if not set.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub browser_session_max_lifetime: Option<Duration>,
+
+    /// How recent, in seconds, the last authentication of a browser session
+    /// must be for the user to perform sensitive actions, like changing their
+    /// email address. Users are asked to enter their password again if it is
+    /// older. Sensitive actions don't require a recent authentication if not
+    /// set.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub sensitive_action_reauth_ttl: Option<Duration>,
+
+    /// How long, in seconds, the IP address of the last activity of sessions
+    /// is kept. It is kept for as long as the session exists if not set.
+    ///
+    /// Deprecated in favour of `data_retention.ip_addresses`, which takes
+    /// precedence if both are set.
+    #[schemars(with = "Option<u64>", range(min = 3600))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub session_activity_retention: Option<Duration>,
+
+    /// How long personal data about the activity of users is kept
+    #[serde(default, skip_serializing_if = "DataRetentionConfig::is_default")]
+    pub data_retention: DataRetentionConfig,
+
+    /// Through which channels users are told about security-sensitive events
+    /// on their account
+    #[serde(
+        default,
+        skip_serializing_if = "SecurityNotificationsConfig::is_default"
+    )]
+    pub security_notifications: SecurityNotificationsConfig,
+
+    /// Paths to MaxMind DB files, like the GeoLite2 City and ASN databases,
+    /// used to show the approximate location of logins and sessions. The
+    /// databases are only read locally.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<String>")]
+    pub geoip_databases: Vec<Utf8PathBuf>,
+
+    /// Let browsers which present a valid Kerberos ticket log in without a
+    /// password. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spnego_login: Option<SpnegoLoginConfig>,
+
+    /// Let users log in with a TLS client certificate. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_certificate_login: Option<ClientCertificateLoginConfig>,
+    /// Check registrations and authorizations against an external anti-abuse
+    /// service, which can allow them, deny them, or let them through but
+    /// shadow-ban the user on the homeserver. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spam_check: Option<SpamCheckConfig>,
+
+    /// Score password logins on signals like new devices, low reputation
+    /// networks, Tor exit nodes and impossible travel, and require a code sent
+    /// by email or refuse the riskiest ones. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login_risk: Option<LoginRiskConfig>,
 }
 
 impl Default for ExperimentalConfig {
@@ -105,6 +549,23 @@ impl Default for ExperimentalConfig {
             displayname_change_allowed: default_true(),
             password_change_allowed: default_true(),
             account_recovery_enabled: default_false(),
+            login_alerts_enabled: default_false(),
+            email_otp_enabled: default_false(),
+            magic_link_login_enabled: default_false(),
+            guest_registration_enabled: default_false(),
+            public_clients_allowed: default_true(),
+            offline_access_required: default_false(),
+            browser_session_idle_timeout: None,
+            browser_session_max_lifetime: None,
+            sensitive_action_reauth_ttl: None,
+            session_activity_retention: None,
+            data_retention: DataRetentionConfig::default(),
+            security_notifications: SecurityNotificationsConfig::default(),
+            geoip_databases: Vec::new(),
+            spnego_login: None,
+            client_certificate_login: None,
+            spam_check: None,
+            login_risk: None,
         }
     }
 }
@@ -118,9 +579,228 @@ impl ExperimentalConfig {
             && is_default_true(&self.displayname_change_allowed)
             && is_default_true(&self.password_change_allowed)
             && is_default_false(&self.account_recovery_enabled)
+            && is_default_false(&self.login_alerts_enabled)
+            && is_default_false(&self.email_otp_enabled)
+            && is_default_false(&self.magic_link_login_enabled)
+            && is_default_false(&self.guest_registration_enabled)
+            && is_default_true(&self.public_clients_allowed)
+            && is_default_false(&self.offline_access_required)
+            && self.browser_session_idle_timeout.is_none()
+            && self.browser_session_max_lifetime.is_none()
+            && self.sensitive_action_reauth_ttl.is_none()
+            && self.session_activity_retention.is_none()
+            && self.data_retention.is_default()
+            && self.security_notifications.is_default()
+            && self.geoip_databases.is_empty()
+            && self.spnego_login.is_none()
+            && self.client_certificate_login.is_none()
+            && self.spam_check.is_none()
+            && self.login_risk.is_none()
     }
 }
 
 impl ConfigurationSection for ExperimentalConfig {
     const PATH: Option<&'static str> = Some("experimental");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let error_on_path = |mut error: figment::Error, path: &[&str]| {
+            error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = std::iter::once(Self::PATH.unwrap())
+                .chain(path.iter().copied())
+                .map(ToOwned::to_owned)
+                .collect();
+            error
+        };
+
+        if let Some(spnego_login) = &self.spnego_login {
+            if spnego_login.realms.is_empty() {
+                return Err(error_on_path(
+                    figment::error::Error::custom("at least one Kerberos realm must be mapped"),
+                    &["spnego_login", "realms"],
+                ));
+            }
+        }
+
+        if let Some(login_risk) = &self.login_risk {
+            if login_risk.mfa_threshold > login_risk.deny_threshold {
+                return Err(error_on_path(
+                    figment::error::Error::custom(
+                        "the MFA threshold must not be above the deny threshold",
+                    ),
+                    &["login_risk", "mfa_threshold"],
+                ));
+            }
+        }
+
+        if self.security_notifications.uses_webhook()
+            && self.security_notifications.webhook.is_none()
+        {
+            return Err(error_on_path(
+                figment::error::Error::missing_field("webhook"),
+                &["security_notifications", "webhook"],
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Yaml},
+        Figment, Jail,
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_security_notifications() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    experimental:
+                      security_notifications:
+                        new_login: [email, matrix]
+                        password_changed: [webhook]
+                        webhook:
+                          url: https://hooks.example.com/security
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<ExperimentalConfig>("experimental")?;
+            config.validate(&figment)?;
+
+            let notifications = &config.security_notifications;
+            assert_eq!(
+                notifications.new_login,
+                [NotificationChannel::Email, NotificationChannel::Matrix]
+            );
+            assert_eq!(
+                notifications.password_changed,
+                [NotificationChannel::Webhook]
+            );
+            assert_eq!(notifications.email_changed, [NotificationChannel::Email]);
+            assert!(notifications.uses_webhook());
+            assert!(notifications.webhook.is_some());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn webhook_channel_requires_webhook() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    experimental:
+                      security_notifications:
+                        email_changed: [email, webhook]
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<ExperimentalConfig>("experimental")?;
+            let error = config.validate(&figment).unwrap_err();
+            assert_eq!(
+                error.path,
+                ["experimental", "security_notifications", "webhook"]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn load_spam_check() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    experimental:
+                      spam_check:
+                        url: https://antispam.example.com/check
+                        token: secret
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<ExperimentalConfig>("experimental")?;
+            config.validate(&figment)?;
+
+            let spam_check = config.spam_check.unwrap();
+            assert_eq!(
+                spam_check.url.as_str(),
+                "https://antispam.example.com/check"
+            );
+            assert_eq!(spam_check.token.as_deref(), Some("secret"));
+            assert!(spam_check.allow_on_error);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn load_login_risk() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    experimental:
+                      login_risk:
+                        tor_exit_nodes_file: /var/lib/mas/tor-exit-nodes.txt
+                        impossible_travel_window: 3600
+                        deny_threshold: 150
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<ExperimentalConfig>("experimental")?;
+            config.validate(&figment)?;
+
+            let login_risk = config.login_risk.unwrap();
+            assert_eq!(
+                login_risk
+                    .tor_exit_nodes_file
+                    .as_ref()
+                    .map(Utf8PathBuf::as_str),
+                Some("/var/lib/mas/tor-exit-nodes.txt")
+            );
+            assert!(login_risk.low_reputation_networks_file.is_none());
+            assert_eq!(
+                login_risk.impossible_travel_window,
+                Duration::try_hours(1).unwrap()
+            );
+            assert_eq!(login_risk.new_device_score, 40);
+            assert_eq!(login_risk.mfa_threshold, 40);
+            assert_eq!(login_risk.deny_threshold, 150);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn login_risk_thresholds_are_ordered() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    experimental:
+                      login_risk:
+                        mfa_threshold: 120
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<ExperimentalConfig>("experimental")?;
+            let error = config.validate(&figment).unwrap_err();
+            assert_eq!(error.path, ["experimental", "login_risk", "mfa_threshold"]);
+
+            Ok(())
+        });
+    }
 }
@@ -14,7 +14,7 @@
 
 #![allow(deprecated)]
 
-use std::{borrow::Cow, io::Cursor};
+use std::{borrow::Cow, io::Cursor, time::Duration};
 
 use anyhow::bail;
 use camino::Utf8PathBuf;
@@ -23,6 +23,7 @@ use mas_keystore::PrivateKey;
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 use url::Url;
 
 use super::ConfigurationSection;
@@ -31,6 +32,22 @@ fn default_public_base() -> Url {
     "http://[::]:8080".parse().unwrap()
 }
 
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn is_default_shutdown_timeout(value: &Duration) -> bool {
+    *value == default_shutdown_timeout()
+}
+
+fn default_request_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn is_default_request_timeout(value: &Duration) -> bool {
+    *value == default_request_timeout()
+}
+
 fn http_address_example_1() -> &'static str {
     "[::1]:8080"
 }
@@ -65,9 +82,9 @@ fn is_default_http_listener_assets_path(value: &Utf8PathBuf) -> bool {
 
 fn default_trusted_proxies() -> Vec<IpNetwork> {
     vec![
-        IpNetwork::new([192, 128, 0, 0].into(), 16).unwrap(),
+        IpNetwork::new([192, 168, 0, 0].into(), 16).unwrap(),
         IpNetwork::new([172, 16, 0, 0].into(), 12).unwrap(),
-        IpNetwork::new([10, 0, 0, 0].into(), 10).unwrap(),
+        IpNetwork::new([10, 0, 0, 0].into(), 8).unwrap(),
         IpNetwork::new(std::net::Ipv4Addr::LOCALHOST.into(), 8).unwrap(),
         IpNetwork::new([0xfd00, 0, 0, 0, 0, 0, 0, 0].into(), 8).unwrap(),
         IpNetwork::new(std::net::Ipv6Addr::LOCALHOST.into(), 128).unwrap(),
@@ -196,6 +213,27 @@ pub struct TlsConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schemars(with = "Option<String>")]
     pub password_file: Option<Utf8PathBuf>,
+
+    /// PEM-encoded X509 certificates of the authorities which issue client
+    /// certificates
+    ///
+    /// If one of `client_ca` or `client_ca_file` is set, clients must present a
+    /// certificate issued by one of those authorities to connect to this
+    /// listener. This is meant for a listener dedicated to logging in with a
+    /// client certificate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<String>,
+
+    /// File containing the PEM-encoded X509 certificates of the authorities
+    /// which issue client certificates
+    ///
+    /// If one of `client_ca` or `client_ca_file` is set, clients must present a
+    /// certificate issued by one of those authorities to connect to this
+    /// listener. This is meant for a listener dedicated to logging in with a
+    /// client certificate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub client_ca_file: Option<Utf8PathBuf>,
 }
 
 impl TlsConfig {
@@ -270,6 +308,36 @@ impl TlsConfig {
 
         Ok((key, certificate_chain))
     }
+
+    /// Load the certificates of the authorities which issue client
+    /// certificates, if client certificates are required on this listener
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an error was encountered either while:
+    ///   - reading the certificates file
+    ///   - decoding the certificates as PEM
+    ///   - no certificate was found
+    pub fn load_client_ca(&self) -> Result<Option<Vec<CertificateDer<'static>>>, anyhow::Error> {
+        let client_ca_pem = match (&self.client_ca, &self.client_ca_file) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => {
+                bail!("Only one of `client_ca` or `client_ca_file` can be set at a time")
+            }
+            (Some(client_ca), None) => Cow::Borrowed(client_ca),
+            (None, Some(path)) => Cow::Owned(std::fs::read_to_string(path)?),
+        };
+
+        let mut client_ca_reader = Cursor::new(client_ca_pem.as_bytes());
+        let client_ca: Result<Vec<_>, _> = rustls_pemfile::certs(&mut client_ca_reader).collect();
+        let client_ca = client_ca?;
+
+        if client_ca.is_empty() {
+            bail!("Client certificate authorities are empty (or invalid)")
+        }
+
+        Ok(Some(client_ca))
+    }
 }
 
 /// HTTP resources to mount
@@ -301,6 +369,13 @@ pub enum Resource {
     /// Matrix compatibility API
     Compat,
 
+    /// OpenAPI description of the JSON APIs (/api/spec.json)
+    OpenApi {
+        /// Serve an interactive explorer of the API (/api/doc/)
+        #[serde(default)]
+        explorer: bool,
+    },
+
     /// Static files
     Assets {
         /// Path to the directory to serve.
@@ -312,13 +387,243 @@ pub enum Resource {
         path: Utf8PathBuf,
     },
 
+    /// Matrix client discovery document (/.well-known/matrix/client), pointing
+    /// Matrix clients at the homeserver and at this service
+    #[serde(rename = "matrix-well-known")]
+    MatrixWellKnown {
+        /// Base URL of the homeserver client API, advertised as
+        /// `m.homeserver`
+        homeserver_base_url: Url,
+
+        /// Additional properties to add to the document, e.g.
+        /// `m.identity_server`
+        #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+        extra: serde_json::Map<String, serde_json::Value>,
+    },
+
+    /// Read-only administration pages (/admin/), to browse users, their
+    /// sessions and the registered clients. Those pages are not
+    /// authenticated, so they are only reachable from the networks listed in
+    /// `access_control.admin`.
+    Admin,
+
     /// Mount a "/connection-info" handler which helps debugging informations on
     /// the upstream connection
     #[serde(rename = "connection-info")]
     ConnectionInfo,
 }
 
+/// Value of the `X-Frame-Options` header
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameOptions {
+    /// The pages can't be displayed in a frame at all
+    #[default]
+    Deny,
+
+    /// The pages can only be displayed in a frame on the same origin
+    SameOrigin,
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_true(value: &bool) -> bool {
+    *value == default_true()
+}
+
+const fn default_request_body_limit() -> usize {
+    1024 * 1024
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_request_body_limit(value: &usize) -> bool {
+    *value == default_request_body_limit()
+}
+
+fn default_referrer_policy() -> String {
+    "strict-origin-when-cross-origin".to_owned()
+}
+
+fn is_default_referrer_policy(value: &str) -> bool {
+    value == default_referrer_policy()
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_hsts_max_age() -> Option<Duration> {
+    Some(Duration::from_secs(365 * 24 * 60 * 60))
+}
+
+fn is_default_hsts_max_age(value: &Option<Duration>) -> bool {
+    *value == default_hsts_max_age()
+}
+
+/// Security-related headers set on HTML responses
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct SecurityHeadersConfig {
+    /// Whether to set those headers at all. Defaults to `true`.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub enabled: bool,
+
+    /// Value of the `Content-Security-Policy` header.
+    ///
+    /// The `{nonce}` placeholder is replaced by a random value generated for
+    /// each response, which is also given to the templates. Defaults to a
+    /// policy which works with the built-in templates and CAPTCHA services.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_security_policy: Option<String>,
+
+    /// Value of the `X-Frame-Options` header. Defaults to `deny`.
+    #[serde(default, skip_serializing_if = "is_default_frame_options")]
+    pub frame_options: FrameOptions,
+
+    /// Value of the `Referrer-Policy` header. Defaults to
+    /// `strict-origin-when-cross-origin`.
+    #[serde(
+        default = "default_referrer_policy",
+        skip_serializing_if = "is_default_referrer_policy"
+    )]
+    pub referrer_policy: String,
+
+    /// `max-age` of the `Strict-Transport-Security` header, in seconds. It is
+    /// only sent on requests made over HTTPS. Set to `null` to disable.
+    /// Defaults to one year.
+    #[schemars(with = "Option<u64>")]
+    #[serde(
+        default = "default_hsts_max_age",
+        skip_serializing_if = "is_default_hsts_max_age"
+    )]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    pub hsts_max_age: Option<Duration>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_frame_options(value: &FrameOptions) -> bool {
+    *value == FrameOptions::default()
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+            content_security_policy: None,
+            frame_options: FrameOptions::default(),
+            referrer_policy: default_referrer_policy(),
+            hsts_max_age: default_hsts_max_age(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Networks allowed to reach sensitive endpoints, regardless of the listener
+/// they are mounted on
+///
+/// Each entry is a list of CIDRs. Endpoints without an entry are not
+/// restricted, except for the administration pages which are then not
+/// reachable at all. The IP of the client is inferred from the `X-Forwarded-For`
+/// header when the request comes from a trusted proxy.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct AccessControlConfig {
+    /// Networks allowed to use the GraphQL API, which includes the admin API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<Vec<IpNetwork>>,
+
+    /// Networks allowed to scrape the Prometheus metrics endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prometheus: Option<Vec<IpNetwork>>,
+
+    /// Networks allowed to use the OAuth 2.0 token introspection endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub introspection: Option<Vec<IpNetwork>>,
+
+    /// Networks allowed to browse the administration pages. They can't be
+    /// reached from anywhere if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<Vec<IpNetwork>>,
+}
+
+impl AccessControlConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Configuration of the outbound HTTP requests, made to the homeserver, the
+/// upstream providers, and other external services
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    /// URL of an HTTP proxy through which the outbound requests are tunneled,
+    /// like `http://proxy.example.com:3128`. Credentials for the proxy can be
+    /// set in the URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Url>,
+
+    /// Hosts which are reached directly, without going through the proxy.
+    ///
+    /// Each entry is either a host name, which also matches its subdomains,
+    /// an IP address, or `*` to match all hosts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+
+    /// PEM-encoded root certificates to trust, on top of the ones trusted by
+    /// the system
+    ///
+    /// Only one of `ca_certificates` or `ca_certificates_file` can be set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_certificates: Option<String>,
+
+    /// File containing PEM-encoded root certificates to trust, on top of the
+    /// ones trusted by the system
+    ///
+    /// Only one of `ca_certificates` or `ca_certificates_file` can be set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub ca_certificates_file: Option<Utf8PathBuf>,
+}
+
+impl HttpClientConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Load the extra root certificates to trust
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificates file could not be read, or if the
+    /// certificates could not be decoded as PEM
+    pub fn load_ca_certificates(&self) -> Result<Vec<CertificateDer<'static>>, anyhow::Error> {
+        let pem = match (&self.ca_certificates, &self.ca_certificates_file) {
+            (None, None) => return Ok(Vec::new()),
+            (Some(_), Some(_)) => bail!(
+                "Only one of `ca_certificates` or `ca_certificates_file` can be set at a time"
+            ),
+            (Some(pem), None) => Cow::Borrowed(pem),
+            (None, Some(path)) => Cow::Owned(std::fs::read_to_string(path)?),
+        };
+
+        let mut reader = Cursor::new(pem.as_bytes());
+        let certificates: Result<Vec<_>, _> = rustls_pemfile::certs(&mut reader).collect();
+        let certificates = certificates?;
+
+        if certificates.is_empty() {
+            bail!("No certificate found in the extra root certificates")
+        }
+
+        Ok(certificates)
+    }
+}
+
 /// Configuration of a listener
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct ListenerConfig {
     /// A unique name for this listener which will be shown in traces and in
@@ -343,26 +648,84 @@ pub struct ListenerConfig {
     /// If set, makes the listener use TLS with the provided certificate and key
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tls: Option<TlsConfig>,
+
+    /// Security-related headers to set on HTML responses
+    #[serde(default, skip_serializing_if = "SecurityHeadersConfig::is_default")]
+    pub security_headers: SecurityHeadersConfig,
+
+    /// Whether to compress the HTML and JSON responses with gzip or brotli,
+    /// if the client supports it. Defaults to `true`.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub compression: bool,
+
+    /// Maximum size of the form and JSON request bodies, in bytes. Defaults
+    /// to 1 MiB.
+    #[schemars(range(min = 1024))]
+    #[serde(
+        default = "default_request_body_limit",
+        skip_serializing_if = "is_default_request_body_limit"
+    )]
+    pub request_body_limit: usize,
+
+    /// Maximum time spent handling a single request on this listener, in
+    /// seconds. Overrides the global `http.request_timeout` if set.
+    #[schemars(with = "Option<u64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<u64>>")]
+    pub request_timeout: Option<Duration>,
 }
 
 /// Configuration related to the web server
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct HttpConfig {
     /// List of listeners to run
     #[serde(default)]
     pub listeners: Vec<ListenerConfig>,
 
-    /// List of trusted reverse proxies that can set the `X-Forwarded-For`
-    /// header
+    /// List of trusted reverse proxies that can set the `X-Forwarded-For` and
+    /// `X-Forwarded-Proto` headers
     #[serde(default = "default_trusted_proxies")]
     pub trusted_proxies: Vec<IpNetwork>,
 
+    /// Restrict sensitive endpoints to some networks
+    #[serde(default, skip_serializing_if = "AccessControlConfig::is_default")]
+    pub access_control: AccessControlConfig,
+
+    /// Outbound HTTP requests settings, like a proxy or extra trusted root
+    /// certificates
+    #[serde(default, skip_serializing_if = "HttpClientConfig::is_default")]
+    pub client: HttpClientConfig,
+
     /// Public URL base from where the authentication service is reachable
     pub public_base: Url,
 
     /// OIDC issuer URL. Defaults to `public_base` if not set.
+    ///
+    /// An instance serves a single issuer.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub issuer: Option<Url>,
+
+    /// How long to wait for in-flight requests and background jobs to finish
+    /// when shutting down, in seconds. Defaults to 60 seconds.
+    #[schemars(with = "u64")]
+    #[serde(
+        default = "default_shutdown_timeout",
+        skip_serializing_if = "is_default_shutdown_timeout"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    pub shutdown_timeout: Duration,
+
+    /// Maximum time spent handling a single request, in seconds, after which
+    /// the request is aborted with a `503 Service Unavailable` response.
+    /// Defaults to 60 seconds.
+    #[schemars(with = "u64")]
+    #[serde(
+        default = "default_request_timeout",
+        skip_serializing_if = "is_default_request_timeout"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    pub request_timeout: Duration,
 }
 
 impl Default for HttpConfig {
@@ -384,6 +747,10 @@ impl Default for HttpConfig {
                     prefix: None,
                     tls: None,
                     proxy_protocol: false,
+                    security_headers: SecurityHeadersConfig::default(),
+                    compression: true,
+                    request_body_limit: default_request_body_limit(),
+                    request_timeout: None,
                     binds: vec![BindConfig::Address {
                         address: "[::]:8080".into(),
                     }],
@@ -394,6 +761,10 @@ impl Default for HttpConfig {
                     prefix: None,
                     tls: None,
                     proxy_protocol: false,
+                    security_headers: SecurityHeadersConfig::default(),
+                    compression: true,
+                    request_body_limit: default_request_body_limit(),
+                    request_timeout: None,
                     binds: vec![BindConfig::Listen {
                         host: Some("localhost".to_owned()),
                         port: 8081,
@@ -401,8 +772,12 @@ impl Default for HttpConfig {
                 },
             ],
             trusted_proxies: default_trusted_proxies(),
+            access_control: AccessControlConfig::default(),
+            client: HttpClientConfig::default(),
             issuer: Some(default_public_base()),
             public_base: default_public_base(),
+            shutdown_timeout: default_shutdown_timeout(),
+            request_timeout: default_request_timeout(),
         }
     }
 }
@@ -435,6 +810,18 @@ impl ConfigurationSection for HttpConfig {
                 ));
             }
 
+            if listener.request_body_limit < 1024 {
+                return annotate(figment::Error::from(
+                    "the request body limit must be at least 1024 bytes".to_owned(),
+                ));
+            }
+
+            if listener.request_timeout == Some(Duration::ZERO) {
+                return annotate(figment::Error::from(
+                    "the request timeout must not be zero".to_owned(),
+                ));
+            }
+
             if let Some(tls_config) = &listener.tls {
                 if tls_config.certificate.is_some() && tls_config.certificate_file.is_some() {
                     return annotate(figment::Error::from(
@@ -469,6 +856,41 @@ impl ConfigurationSection for HttpConfig {
             }
         }
 
+        if let Some(proxy) = &self.client.proxy {
+            if proxy.scheme() != "http" || proxy.host().is_none() {
+                let mut error = figment::Error::from(
+                    "the proxy URL must be an http:// URL with a host".to_owned(),
+                );
+                error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+                error.profile = Some(figment::Profile::Default);
+                error.path = vec![
+                    Self::PATH.unwrap().to_owned(),
+                    "client".to_owned(),
+                    "proxy".to_owned(),
+                ];
+                return Err(error);
+            }
+        }
+
+        if self.client.ca_certificates.is_some() && self.client.ca_certificates_file.is_some() {
+            let mut error = figment::Error::from(
+                "Only one of `ca_certificates` or `ca_certificates_file` can be set at a time"
+                    .to_owned(),
+            );
+            error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned(), "client".to_owned()];
+            return Err(error);
+        }
+
+        if self.request_timeout.is_zero() {
+            let mut error = figment::Error::from("the request timeout must not be zero".to_owned());
+            error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned(), "request_timeout".to_owned()];
+            return Err(error);
+        }
+
         Ok(())
     }
 }
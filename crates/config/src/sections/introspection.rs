@@ -0,0 +1,66 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::Duration;
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::ConfigurationSection;
+
+/// Configuration section related to the token introspection endpoint
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, Default)]
+pub struct IntrospectionConfig {
+    /// How long, in seconds, positive introspection results can be cached.
+    /// The cache is disabled if not set.
+    ///
+    /// The cache is kept in memory, or in Redis if the `redis` section is
+    /// configured. Tokens and sessions revoked through the HTTP API are
+    /// removed from the cache right away, but changes made by the background
+    /// worker or the CLI are only picked up once the cached entries expire.
+    #[schemars(with = "Option<u64>", range(min = 1, max = 3600))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub cache_ttl: Option<Duration>,
+}
+
+impl IntrospectionConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.cache_ttl.is_none()
+    }
+}
+
+impl ConfigurationSection for IntrospectionConfig {
+    const PATH: Option<&'static str> = Some("introspection");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let Some(cache_ttl) = self.cache_ttl else {
+            return Ok(());
+        };
+
+        if cache_ttl > Duration::zero() && cache_ttl <= Duration::hours(1) {
+            return Ok(());
+        }
+
+        let mut error = figment::error::Error::custom(
+            "the introspection cache TTL must be between 1 second and 1 hour",
+        );
+        error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+        error.profile = Some(figment::Profile::Default);
+        error.path = vec![Self::PATH.unwrap().to_owned(), "cache_ttl".to_owned()];
+        Err(error)
+    }
+}
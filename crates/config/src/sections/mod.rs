@@ -23,38 +23,61 @@ mod database;
 mod email;
 mod experimental;
 mod http;
+mod introspection;
 mod matrix;
 mod passwords;
 mod policy;
+mod rate_limiting;
+mod redis;
 mod secrets;
+mod sms;
 mod telemetry;
 mod templates;
 mod upstream_oauth2;
 
 pub use self::{
-    branding::BrandingConfig,
+    branding::{AnnouncementConfig, BrandingConfig},
     captcha::{CaptchaConfig, CaptchaServiceKind},
-    clients::{ClientAuthMethodConfig, ClientConfig, ClientsConfig},
+    clients::{
+        ClientApplicationTypeConfig, ClientAuthMethodConfig, ClientConfig, ClientGrantTypeConfig,
+        ClientResponseTypeConfig, ClientsConfig,
+    },
     database::DatabaseConfig,
     email::{EmailConfig, EmailSmtpMode, EmailTransportKind},
-    experimental::ExperimentalConfig,
+    experimental::{
+        ClientCertificateLoginConfig, ClientCertificateMapping, DataRetentionConfig,
+        ExperimentalConfig, LoginRiskConfig, NotificationChannel, NotificationWebhookConfig,
+        SecurityNotificationsConfig, SpamCheckConfig, SpnegoLoginConfig,
+    },
     http::{
-        BindConfig as HttpBindConfig, HttpConfig, ListenerConfig as HttpListenerConfig,
-        Resource as HttpResource, TlsConfig as HttpTlsConfig, UnixOrTcp,
+        AccessControlConfig as HttpAccessControlConfig, BindConfig as HttpBindConfig,
+        FrameOptions as HttpFrameOptions, HttpClientConfig, HttpConfig,
+        ListenerConfig as HttpListenerConfig, Resource as HttpResource,
+        SecurityHeadersConfig as HttpSecurityHeadersConfig, TlsConfig as HttpTlsConfig, UnixOrTcp,
     },
+    introspection::IntrospectionConfig,
     matrix::MatrixConfig,
     passwords::{Algorithm as PasswordAlgorithm, PasswordsConfig},
     policy::PolicyConfig,
-    secrets::SecretsConfig,
+    rate_limiting::{
+        AccountRecoveryRateLimitingConfig, BackchannelAuthenticationRateLimitingConfig,
+        LoginRateLimitingConfig, NetworkBansConfig, RateLimiterConfig, RateLimitingConfig,
+        TokenRateLimitingConfig,
+    },
+    redis::RedisConfig,
+    secrets::{SecretsConfig, SigningKeyKind},
+    sms::{SmsConfig, SmsTransportKind},
     telemetry::{
-        MetricsConfig, MetricsExporterKind, Propagator, TelemetryConfig, TracingConfig,
-        TracingExporterKind,
+        LogFormat, LoggingConfig, MetricsConfig, MetricsExporterKind, Propagator, TelemetryConfig,
+        TracingConfig, TracingExporterKind,
     },
     templates::TemplatesConfig,
     upstream_oauth2::{
         ClaimsImports as UpstreamOAuth2ClaimsImports, DiscoveryMode as UpstreamOAuth2DiscoveryMode,
         EmailImportPreference as UpstreamOAuth2EmailImportPreference,
-        ImportAction as UpstreamOAuth2ImportAction, PkceMethod as UpstreamOAuth2PkceMethod,
+        GroupsImportPreference as UpstreamOAuth2GroupsImportPreference,
+        ImportAction as UpstreamOAuth2ImportAction, OnDeprovision as UpstreamOAuth2OnDeprovision,
+        PkceMethod as UpstreamOAuth2PkceMethod,
         SetEmailVerification as UpstreamOAuth2SetEmailVerification, UpstreamOAuth2Config,
     },
 };
@@ -87,6 +110,10 @@ pub struct RootConfig {
     #[serde(default)]
     pub email: EmailConfig,
 
+    /// Configuration related to sending text messages
+    #[serde(default, skip_serializing_if = "SmsConfig::is_default")]
+    pub sms: SmsConfig,
+
     /// Application secrets
     pub secrets: SecretsConfig,
 
@@ -113,6 +140,19 @@ pub struct RootConfig {
     #[serde(default, skip_serializing_if = "CaptchaConfig::is_default")]
     pub captcha: CaptchaConfig,
 
+    /// Configuration section to tweak the rate limiting of sensitive operations
+    #[serde(default, skip_serializing_if = "RateLimitingConfig::is_default")]
+    pub rate_limiting: RateLimitingConfig,
+
+    /// Configuration section to share state between multiple instances of the
+    /// service through Redis
+    #[serde(default, skip_serializing_if = "RedisConfig::is_default")]
+    pub redis: RedisConfig,
+
+    /// Configuration section related to the token introspection endpoint
+    #[serde(default, skip_serializing_if = "IntrospectionConfig::is_default")]
+    pub introspection: IntrospectionConfig,
+
     /// Experimental configuration options
     #[serde(default, skip_serializing_if = "ExperimentalConfig::is_default")]
     pub experimental: ExperimentalConfig,
@@ -126,6 +166,7 @@ impl ConfigurationSection for RootConfig {
         self.telemetry.validate(figment)?;
         self.templates.validate(figment)?;
         self.email.validate(figment)?;
+        self.sms.validate(figment)?;
         self.passwords.validate(figment)?;
         self.secrets.validate(figment)?;
         self.matrix.validate(figment)?;
@@ -133,6 +174,9 @@ impl ConfigurationSection for RootConfig {
         self.upstream_oauth2.validate(figment)?;
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
+        self.rate_limiting.validate(figment)?;
+        self.redis.validate(figment)?;
+        self.introspection.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
@@ -156,6 +200,7 @@ impl RootConfig {
             telemetry: TelemetryConfig::default(),
             templates: TemplatesConfig::default(),
             email: EmailConfig::default(),
+            sms: SmsConfig::default(),
             passwords: PasswordsConfig::default(),
             secrets: SecretsConfig::generate(&mut rng).await?,
             matrix: MatrixConfig::generate(&mut rng),
@@ -163,6 +208,9 @@ impl RootConfig {
             upstream_oauth2: UpstreamOAuth2Config::default(),
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
+            rate_limiting: RateLimitingConfig::default(),
+            redis: RedisConfig::default(),
+            introspection: IntrospectionConfig::default(),
             experimental: ExperimentalConfig::default(),
         })
     }
@@ -178,12 +226,16 @@ impl RootConfig {
             templates: TemplatesConfig::default(),
             passwords: PasswordsConfig::default(),
             email: EmailConfig::default(),
+            sms: SmsConfig::default(),
             secrets: SecretsConfig::test(),
             matrix: MatrixConfig::test(),
             policy: PolicyConfig::default(),
             upstream_oauth2: UpstreamOAuth2Config::default(),
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
+            rate_limiting: RateLimitingConfig::default(),
+            redis: RedisConfig::default(),
+            introspection: IntrospectionConfig::default(),
             experimental: ExperimentalConfig::default(),
         }
     }
@@ -205,6 +257,9 @@ pub struct AppConfig {
     #[serde(default)]
     pub email: EmailConfig,
 
+    #[serde(default)]
+    pub sms: SmsConfig,
+
     pub secrets: SecretsConfig,
 
     #[serde(default)]
@@ -221,6 +276,15 @@ pub struct AppConfig {
     #[serde(default)]
     pub captcha: CaptchaConfig,
 
+    #[serde(default)]
+    pub rate_limiting: RateLimitingConfig,
+
+    #[serde(default)]
+    pub redis: RedisConfig,
+
+    #[serde(default)]
+    pub introspection: IntrospectionConfig,
+
     #[serde(default)]
     pub experimental: ExperimentalConfig,
 }
@@ -231,12 +295,16 @@ impl ConfigurationSection for AppConfig {
         self.database.validate(figment)?;
         self.templates.validate(figment)?;
         self.email.validate(figment)?;
+        self.sms.validate(figment)?;
         self.passwords.validate(figment)?;
         self.secrets.validate(figment)?;
         self.matrix.validate(figment)?;
         self.policy.validate(figment)?;
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
+        self.rate_limiting.validate(figment)?;
+        self.redis.validate(figment)?;
+        self.introspection.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
@@ -0,0 +1,340 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{num::NonZeroU32, time::Duration};
+
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::ConfigurationSection;
+
+/// Parameters of a single rate limiter
+///
+/// Rate limiters follow a token-bucket model: the bucket holds up to `burst`
+/// tokens, each request takes one, and tokens are refilled at a rate of
+/// `per_second` tokens per second.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct RateLimiterConfig {
+    /// The maximum number of requests which can be done in a short burst
+    pub burst: NonZeroU32,
+
+    /// The sustained rate of requests allowed, in requests per second
+    pub per_second: f64,
+}
+
+impl RateLimiterConfig {
+    fn new(burst: u32, per_second: f64) -> Self {
+        let burst = NonZeroU32::new(burst).expect("burst must be non-zero");
+        Self { burst, per_second }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.per_second.is_finite() && self.per_second > 0.0
+    }
+}
+
+fn default_login_per_ip() -> RateLimiterConfig {
+    RateLimiterConfig::new(3, 3.0 / 60.0)
+}
+
+fn default_login_per_account() -> RateLimiterConfig {
+    RateLimiterConfig::new(1800, 1800.0 / 3600.0)
+}
+
+fn default_registration() -> RateLimiterConfig {
+    RateLimiterConfig::new(3, 3.0 / 3600.0)
+}
+
+fn default_account_recovery_per_ip() -> RateLimiterConfig {
+    RateLimiterConfig::new(3, 3.0 / 3600.0)
+}
+
+fn default_account_recovery_per_address() -> RateLimiterConfig {
+    RateLimiterConfig::new(3, 1.0 / 3600.0)
+}
+
+fn default_token_per_ip() -> RateLimiterConfig {
+    RateLimiterConfig::new(100, 10.0)
+}
+
+fn default_token_per_client() -> RateLimiterConfig {
+    RateLimiterConfig::new(500, 50.0)
+}
+
+fn default_backchannel_authentication_per_client() -> RateLimiterConfig {
+    RateLimiterConfig::new(100, 1.0)
+}
+
+fn default_backchannel_authentication_per_user() -> RateLimiterConfig {
+    RateLimiterConfig::new(3, 3.0 / 3600.0)
+}
+
+fn default_failed_attempts_per_network() -> RateLimiterConfig {
+    RateLimiterConfig::new(50, 50.0 / 3600.0)
+}
+
+fn default_ban_duration() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_ipv4_prefix_len() -> u8 {
+    24
+}
+
+fn default_ipv6_prefix_len() -> u8 {
+    64
+}
+
+/// Rate limits applied to password logins
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct LoginRateLimitingConfig {
+    /// Limits the number of login attempts from a single IP address
+    #[serde(default = "default_login_per_ip")]
+    pub per_ip: RateLimiterConfig,
+
+    /// Limits the number of login attempts against a single account,
+    /// regardless of where they come from
+    #[serde(default = "default_login_per_account")]
+    pub per_account: RateLimiterConfig,
+}
+
+impl Default for LoginRateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_login_per_ip(),
+            per_account: default_login_per_account(),
+        }
+    }
+}
+
+/// Rate limits applied to account recovery
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct AccountRecoveryRateLimitingConfig {
+    /// Limits the number of recovery attempts from a single IP address
+    #[serde(default = "default_account_recovery_per_ip")]
+    pub per_ip: RateLimiterConfig,
+
+    /// Limits the number of recovery emails sent to a single address
+    #[serde(default = "default_account_recovery_per_address")]
+    pub per_address: RateLimiterConfig,
+}
+
+impl Default for AccountRecoveryRateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_account_recovery_per_ip(),
+            per_address: default_account_recovery_per_address(),
+        }
+    }
+}
+
+/// Rate limits applied to the OAuth 2.0 token endpoint
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct TokenRateLimitingConfig {
+    /// Limits the number of token requests from a single IP address
+    #[serde(default = "default_token_per_ip")]
+    pub per_ip: RateLimiterConfig,
+
+    /// Limits the number of token requests made by a single client
+    #[serde(default = "default_token_per_client")]
+    pub per_client: RateLimiterConfig,
+}
+
+impl Default for TokenRateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_token_per_ip(),
+            per_client: default_token_per_client(),
+        }
+    }
+}
+
+/// Rate limits applied to the OAuth 2.0 backchannel authentication endpoint
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct BackchannelAuthenticationRateLimitingConfig {
+    /// Limits the number of authentication requests made by a single client
+    #[serde(default = "default_backchannel_authentication_per_client")]
+    pub per_client: RateLimiterConfig,
+
+    /// Limits the number of authentication requests targeting a single user,
+    /// each of them sending an email to the user
+    #[serde(default = "default_backchannel_authentication_per_user")]
+    pub per_user: RateLimiterConfig,
+}
+
+impl Default for BackchannelAuthenticationRateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            per_client: default_backchannel_authentication_per_client(),
+            per_user: default_backchannel_authentication_per_user(),
+        }
+    }
+}
+
+/// Automatic bans of the networks failing too many authentication attempts
+#[serde_as]
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct NetworkBansConfig {
+    /// Limits the number of failed authentication attempts from a single
+    /// network, across all accounts. The network is banned once this limit is
+    /// exceeded.
+    #[serde(default = "default_failed_attempts_per_network")]
+    pub failed_attempts: RateLimiterConfig,
+
+    /// How long a network is banned for, in seconds
+    #[schemars(with = "u64")]
+    #[serde(default = "default_ban_duration")]
+    #[serde_as(as = "serde_with::DurationSeconds<u64>")]
+    pub duration: Duration,
+
+    /// Prefix length of the IPv4 networks on which failed attempts are
+    /// aggregated
+    #[serde(default = "default_ipv4_prefix_len")]
+    pub ipv4_prefix_len: u8,
+
+    /// Prefix length of the IPv6 networks on which failed attempts are
+    /// aggregated
+    #[serde(default = "default_ipv6_prefix_len")]
+    pub ipv6_prefix_len: u8,
+}
+
+impl Default for NetworkBansConfig {
+    fn default() -> Self {
+        Self {
+            failed_attempts: default_failed_attempts_per_network(),
+            duration: default_ban_duration(),
+            ipv4_prefix_len: default_ipv4_prefix_len(),
+            ipv6_prefix_len: default_ipv6_prefix_len(),
+        }
+    }
+}
+
+/// Configuration related to rate limiting of sensitive operations
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+pub struct RateLimitingConfig {
+    /// Rate limits applied to password logins, both on the web interface and
+    /// on the compatibility login API
+    #[serde(default)]
+    pub login: LoginRateLimitingConfig,
+
+    /// Rate limits applied to self-service registrations, per IP address
+    #[serde(default = "default_registration")]
+    pub registration: RateLimiterConfig,
+
+    /// Rate limits applied to account recovery
+    #[serde(default)]
+    pub account_recovery: AccountRecoveryRateLimitingConfig,
+
+    /// Rate limits applied to the OAuth 2.0 token endpoint
+    #[serde(default)]
+    pub token: TokenRateLimitingConfig,
+
+    /// Rate limits applied to the OAuth 2.0 backchannel authentication
+    /// endpoint
+    #[serde(default)]
+    pub backchannel_authentication: BackchannelAuthenticationRateLimitingConfig,
+
+    /// Automatically ban the networks failing too many authentication
+    /// attempts. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_bans: Option<NetworkBansConfig>,
+}
+
+impl Default for RateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            login: LoginRateLimitingConfig::default(),
+            registration: default_registration(),
+            account_recovery: AccountRecoveryRateLimitingConfig::default(),
+            token: TokenRateLimitingConfig::default(),
+            backchannel_authentication: BackchannelAuthenticationRateLimitingConfig::default(),
+            network_bans: None,
+        }
+    }
+}
+
+impl RateLimitingConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl ConfigurationSection for RateLimitingConfig {
+    const PATH: Option<&'static str> = Some("rate_limiting");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let metadata = figment.find_metadata(Self::PATH.unwrap());
+
+        let check = |limiter: &RateLimiterConfig, path: &[&str]| {
+            if limiter.is_valid() {
+                return Ok(());
+            }
+
+            let mut error = figment::error::Error::custom(
+                "`per_second` must be a strictly positive, finite number",
+            );
+            error.metadata = metadata.cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = std::iter::once(Self::PATH.unwrap())
+                .chain(path.iter().copied())
+                .chain(std::iter::once("per_second"))
+                .map(ToOwned::to_owned)
+                .collect();
+            Err(error)
+        };
+
+        check(&self.login.per_ip, &["login", "per_ip"])?;
+        check(&self.login.per_account, &["login", "per_account"])?;
+        check(&self.registration, &["registration"])?;
+        check(
+            &self.account_recovery.per_ip,
+            &["account_recovery", "per_ip"],
+        )?;
+        check(
+            &self.account_recovery.per_address,
+            &["account_recovery", "per_address"],
+        )?;
+        check(&self.token.per_ip, &["token", "per_ip"])?;
+        check(&self.token.per_client, &["token", "per_client"])?;
+        check(
+            &self.backchannel_authentication.per_client,
+            &["backchannel_authentication", "per_client"],
+        )?;
+        check(
+            &self.backchannel_authentication.per_user,
+            &["backchannel_authentication", "per_user"],
+        )?;
+
+        if let Some(network_bans) = &self.network_bans {
+            check(
+                &network_bans.failed_attempts,
+                &["network_bans", "failed_attempts"],
+            )?;
+
+            if network_bans.ipv4_prefix_len > 32 || network_bans.ipv6_prefix_len > 128 {
+                let mut error = figment::error::Error::custom(
+                    "prefix lengths must be at most 32 for IPv4 and 128 for IPv6",
+                );
+                error.metadata = metadata.cloned();
+                error.profile = Some(figment::Profile::Default);
+                error.path = vec![Self::PATH.unwrap().to_owned(), "network_bans".to_owned()];
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use url::Url;
+
+use crate::ConfigurationSection;
+
+/// Configuration section to share state between multiple instances of the
+/// service through Redis
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, Default)]
+pub struct RedisConfig {
+    /// Connection URI of the Redis server, e.g.
+    /// `redis://:password@localhost:6379/0`.
+    ///
+    /// When set, the state of the rate limiters, of the device code grant
+    /// polling and the token introspection cache are kept in Redis instead of
+    /// in memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<Url>,
+}
+
+impl RedisConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.uri.is_none()
+    }
+}
+
+impl ConfigurationSection for RedisConfig {
+    const PATH: Option<&'static str> = Some("redis");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let Some(uri) = &self.uri else {
+            return Ok(());
+        };
+
+        if matches!(uri.scheme(), "redis" | "rediss" | "redis+unix" | "unix") {
+            return Ok(());
+        }
+
+        let mut error = figment::error::Error::custom(
+            "the Redis URI must use the `redis`, `rediss`, `redis+unix` or `unix` scheme",
+        );
+        error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+        error.profile = Some(figment::Profile::Default);
+        error.path = vec![Self::PATH.unwrap().to_owned(), "uri".to_owned()];
+        Err(error)
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Serialize};
+use url::Url;
+
+use super::ConfigurationSection;
+
+/// What gateway should be used when sending text messages
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsTransportKind {
+    /// Don't send text messages anywhere
+    #[default]
+    Blackhole,
+
+    /// Send text messages through the Twilio Messaging API
+    Twilio,
+
+    /// Send text messages by posting them to a generic HTTP endpoint
+    Http,
+}
+
+/// Configuration related to sending text messages, used to verify phone
+/// numbers
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Default)]
+pub struct SmsConfig {
+    /// What gateway should be used when sending text messages
+    #[serde(default)]
+    transport: SmsTransportKind,
+
+    /// Twilio transport: SID of the Twilio account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_sid: Option<String>,
+
+    /// Twilio transport: Auth token of the Twilio account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+
+    /// Twilio transport: Phone number or messaging service SID to send the
+    /// messages from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+
+    /// HTTP transport: URL of the endpoint to post the messages to.
+    ///
+    /// It receives an `application/x-www-form-urlencoded` body with the `to`
+    /// and `body` fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<Url>,
+
+    /// HTTP transport: Bearer token to authenticate with against the endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl SmsConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.transport == SmsTransportKind::Blackhole
+            && self.account_sid.is_none()
+            && self.auth_token.is_none()
+            && self.from.is_none()
+            && self.url.is_none()
+            && self.token.is_none()
+    }
+
+    /// What gateway should be used when sending text messages
+    #[must_use]
+    pub fn transport(&self) -> SmsTransportKind {
+        self.transport
+    }
+
+    /// SID of the Twilio account
+    #[must_use]
+    pub fn account_sid(&self) -> Option<&str> {
+        self.account_sid.as_deref()
+    }
+
+    /// Auth token of the Twilio account
+    #[must_use]
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
+
+    /// Phone number or messaging service SID to send the messages from
+    #[must_use]
+    pub fn from(&self) -> Option<&str> {
+        self.from.as_deref()
+    }
+
+    /// URL of the endpoint to post the messages to
+    #[must_use]
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    /// Bearer token to authenticate with against the endpoint
+    #[must_use]
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
+impl ConfigurationSection for SmsConfig {
+    const PATH: Option<&'static str> = Some("sms");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::error::Error> {
+        let metadata = figment.find_metadata(Self::PATH.unwrap());
+
+        let error_on_field = |mut error: figment::error::Error, field: &'static str| {
+            error.metadata = metadata.cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned(), field.to_owned()];
+            error
+        };
+
+        let missing_field = |field: &'static str| {
+            error_on_field(figment::error::Error::missing_field(field), field)
+        };
+
+        let unexpected_field = |field: &'static str, expected_fields: &'static [&'static str]| {
+            error_on_field(
+                figment::error::Error::unknown_field(field, expected_fields),
+                field,
+            )
+        };
+
+        // Fields which are specific to each transport
+        let twilio_fields = [
+            ("account_sid", self.account_sid.is_some()),
+            ("auth_token", self.auth_token.is_some()),
+            ("from", self.from.is_some()),
+        ];
+        let http_fields = [("url", self.url.is_some()), ("token", self.token.is_some())];
+
+        let check_unexpected = |fields: &[(&'static str, bool)],
+                                expected: &'static [&'static str]| {
+            for &(field, set) in fields {
+                if set {
+                    return Err(unexpected_field(field, expected));
+                }
+            }
+
+            Ok(())
+        };
+
+        match self.transport {
+            SmsTransportKind::Blackhole => {}
+
+            SmsTransportKind::Twilio => {
+                let expected_fields = &["transport", "account_sid", "auth_token", "from"];
+
+                if self.account_sid.is_none() {
+                    return Err(missing_field("account_sid"));
+                }
+
+                if self.auth_token.is_none() {
+                    return Err(missing_field("auth_token"));
+                }
+
+                if self.from.is_none() {
+                    return Err(missing_field("from"));
+                }
+
+                check_unexpected(&http_fields, expected_fields)?;
+            }
+
+            SmsTransportKind::Http => {
+                let expected_fields = &["transport", "url", "token"];
+
+                if self.url.is_none() {
+                    return Err(missing_field("url"));
+                }
+
+                check_unexpected(&twilio_fields, expected_fields)?;
+            }
+        }
+
+        Ok(())
+    }
+}
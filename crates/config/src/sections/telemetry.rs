@@ -54,8 +54,12 @@ pub enum TracingExporterKind {
     Otlp,
 }
 
+fn default_propagators() -> Vec<Propagator> {
+    vec![Propagator::TraceContext]
+}
+
 /// Configuration related to exporting traces
-#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TracingConfig {
     /// Exporter to use when exporting traces
     #[serde(default)]
@@ -66,16 +70,30 @@ pub struct TracingConfig {
     #[schemars(url, default = "otlp_endpoint_default")]
     pub endpoint: Option<Url>,
 
-    /// List of propagation formats to use for incoming and outgoing requests
+    /// List of propagation formats to use for incoming and outgoing requests.
+    ///
+    /// Defaults to the W3C Trace Context format, so that the `traceparent`
+    /// header is honored on incoming requests and sent on outgoing requests.
+    #[serde(default = "default_propagators")]
     pub propagators: Vec<Propagator>,
 }
 
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            exporter: TracingExporterKind::default(),
+            endpoint: None,
+            propagators: default_propagators(),
+        }
+    }
+}
+
 impl TracingConfig {
     /// Returns true if all fields are at their default values
     fn is_default(&self) -> bool {
         matches!(self.exporter, TracingExporterKind::None)
             && self.endpoint.is_none()
-            && self.propagators.is_empty()
+            && self.propagators == default_propagators()
     }
 }
 
@@ -123,6 +141,10 @@ fn sentry_dsn_example() -> &'static str {
     "https://public@host:port/1"
 }
 
+fn sentry_environment_example() -> &'static str {
+    "production"
+}
+
 /// Configuration related to the Sentry integration
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct SentryConfig {
@@ -130,18 +152,74 @@ pub struct SentryConfig {
     #[schemars(url, example = "sentry_dsn_example")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dsn: Option<String>,
+
+    /// Environment to use when sending events to Sentry
+    ///
+    /// Defaults to `production` if not set.
+    #[schemars(example = "sentry_environment_example")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+
+    /// Sample rate for error events, between 0.0 and 1.0
+    ///
+    /// Defaults to sending all the errors.
+    #[schemars(range(min = 0.0, max = 1.0))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f32>,
+
+    /// Sample rate for performance monitoring, between 0.0 and 1.0
+    ///
+    /// Defaults to sending all the transactions.
+    #[schemars(range(min = 0.0, max = 1.0))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traces_sample_rate: Option<f32>,
 }
 
 impl SentryConfig {
     /// Returns true if all fields are at their default values
     fn is_default(&self) -> bool {
         self.dsn.is_none()
+            && self.environment.is_none()
+            && self.sample_rate.is_none()
+            && self.traces_sample_rate.is_none()
+    }
+}
+
+/// Format of the logs written to the standard error output
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable text, with colors if the output is a terminal
+    #[default]
+    Text,
+
+    /// One JSON object per line, including the fields of the current spans,
+    /// like the request ID
+    Json,
+}
+
+/// Configuration related to logging
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LoggingConfig {
+    /// Format of the logs
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl LoggingConfig {
+    /// Returns true if all fields are at their default values
+    fn is_default(&self) -> bool {
+        self.format == LogFormat::default()
     }
 }
 
 /// Configuration related to sending monitoring data
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct TelemetryConfig {
+    /// Configuration related to logging
+    #[serde(default, skip_serializing_if = "LoggingConfig::is_default")]
+    pub logging: LoggingConfig,
+
     /// Configuration related to exporting traces
     #[serde(default, skip_serializing_if = "TracingConfig::is_default")]
     pub tracing: TracingConfig,
@@ -158,10 +236,42 @@ pub struct TelemetryConfig {
 impl TelemetryConfig {
     /// Returns true if all fields are at their default values
     pub(crate) fn is_default(&self) -> bool {
-        self.tracing.is_default() && self.metrics.is_default() && self.sentry.is_default()
+        self.logging.is_default()
+            && self.tracing.is_default()
+            && self.metrics.is_default()
+            && self.sentry.is_default()
     }
 }
 
 impl ConfigurationSection for TelemetryConfig {
     const PATH: Option<&'static str> = Some("telemetry");
+
+    fn validate(&self, figment: &figment::Figment) -> Result<(), figment::Error> {
+        let rates = [
+            ("sample_rate", self.sentry.sample_rate),
+            ("traces_sample_rate", self.sentry.traces_sample_rate),
+        ];
+
+        for (field, rate) in rates {
+            if let Some(rate) = rate {
+                if !(0.0..=1.0).contains(&rate) {
+                    let mut error = figment::Error::from(format!(
+                        "Sentry {field} must be between 0.0 and 1.0, got {rate}"
+                    ));
+                    error.metadata = figment
+                        .find_metadata(&format!("{root}.sentry", root = Self::PATH.unwrap()))
+                        .cloned();
+                    error.profile = Some(figment::Profile::Default);
+                    error.path = vec![
+                        Self::PATH.unwrap().to_owned(),
+                        "sentry".to_owned(),
+                        field.to_owned(),
+                    ];
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
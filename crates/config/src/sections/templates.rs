@@ -83,6 +83,14 @@ pub struct TemplatesConfig {
     #[schemars(with = "Option<String>")]
     pub path: Utf8PathBuf,
 
+    /// Path to a folder which holds templates overriding the built-in ones
+    ///
+    /// Templates in this folder replace the ones with the same relative path
+    /// in the main templates folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub overrides_path: Option<Utf8PathBuf>,
+
     /// Path to the assets manifest
     #[serde(
         default = "default_assets_path",
@@ -98,14 +106,24 @@ pub struct TemplatesConfig {
     )]
     #[schemars(with = "Option<String>")]
     pub translations_path: Utf8PathBuf,
+
+    /// Whether to watch the templates and translations for changes, and
+    /// reload them automatically
+    ///
+    /// This is meant to be used during development, and can also be enabled
+    /// with the `--dev` flag of the `server` command.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub watch: bool,
 }
 
 impl Default for TemplatesConfig {
     fn default() -> Self {
         Self {
             path: default_path(),
+            overrides_path: None,
             assets_manifest: default_assets_path(),
             translations_path: default_translations_path(),
+            watch: false,
         }
     }
 }
@@ -114,8 +132,10 @@ impl TemplatesConfig {
     /// Returns true if all fields are at their default values
     pub(crate) fn is_default(&self) -> bool {
         is_default_path(&self.path)
+            && self.overrides_path.is_none()
             && is_default_assets_path(&self.assets_manifest)
             && is_default_translations_path(&self.translations_path)
+            && !self.watch
     }
 }
 
@@ -55,6 +55,12 @@ impl ConfigurationSection for UpstreamOAuth2Config {
                 Err(error)
             };
 
+            if provider.claims_imports.groups.action == ImportAction::Suggest {
+                return annotate(figment::Error::custom(
+                    "The `suggest` action is not supported for `claims_imports.groups`",
+                ));
+            }
+
             match provider.token_endpoint_auth_method {
                 TokenAuthMethod::None | TokenAuthMethod::PrivateKeyJwt => {
                     if provider.client_secret.is_some() {
@@ -262,6 +268,34 @@ impl EmailImportPreference {
     }
 }
 
+/// What should be done with the groups or roles of the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct GroupsImportPreference {
+    /// How to handle the claim
+    ///
+    /// The groups are synced on every login, so `suggest` is not supported
+    #[serde(default, skip_serializing_if = "ImportAction::is_default")]
+    pub action: ImportAction,
+
+    /// The claim holding the list of groups or roles of the user
+    ///
+    /// If not provided, the `groups` claim is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim: Option<String>,
+
+    /// Users in any of those groups are allowed to request admin access.
+    ///
+    /// If empty, the admin flag of users is left untouched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_groups: Vec<String>,
+}
+
+impl GroupsImportPreference {
+    fn is_default(&self) -> bool {
+        self.action.is_default() && self.claim.is_none() && self.admin_groups.is_empty()
+    }
+}
+
 /// How claims should be imported
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct ClaimsImports {
@@ -284,14 +318,20 @@ pub struct ClaimsImports {
     /// `email_verified` claims
     #[serde(default, skip_serializing_if = "EmailImportPreference::is_default")]
     pub email: EmailImportPreference,
+
+    /// Import the groups or roles of the user, which are exposed to the
+    /// policies and can grant the admin flag. They are synced on every login.
+    #[serde(default, skip_serializing_if = "GroupsImportPreference::is_default")]
+    pub groups: GroupsImportPreference,
 }
 
 impl ClaimsImports {
-    const fn is_default(&self) -> bool {
+    fn is_default(&self) -> bool {
         self.subject.is_default()
             && self.localpart.is_default()
             && self.displayname.is_default()
             && self.email.is_default()
+            && self.groups.is_default()
     }
 }
 
@@ -342,6 +382,28 @@ impl PkceMethod {
     }
 }
 
+/// What to do when the provider says that a user is gone
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDeprovision {
+    /// Don't do anything
+    #[default]
+    Ignore,
+
+    /// Lock the local user, which can be undone by an admin
+    Lock,
+
+    /// Lock the local user and deactivate them on the homeserver
+    Deactivate,
+}
+
+impl OnDeprovision {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    const fn is_default(&self) -> bool {
+        matches!(self, OnDeprovision::Ignore)
+    }
+}
+
 fn default_true() -> bool {
     true
 }
@@ -454,4 +516,22 @@ pub struct Provider {
     /// Orders of the keys are not preserved.
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub additional_authorization_parameters: BTreeMap<String, String>,
+
+    /// Whether to keep the access and refresh tokens obtained from the
+    /// provider, encrypted, so that they can be used later on to call the
+    /// provider on behalf of the user
+    ///
+    /// Defaults to `false`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub store_tokens: bool,
+
+    /// What to do with the local user when the provider says that the
+    /// upstream identity was disabled or deleted
+    ///
+    /// The provider notifies this through a RISC security event token, pushed
+    /// to the `/upstream/deprovision/{id}` endpoint.
+    ///
+    /// Defaults to `ignore`.
+    #[serde(default, skip_serializing_if = "OnDeprovision::is_default")]
+    pub on_deprovision: OnDeprovision,
 }
@@ -0,0 +1,28 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ulid::Ulid;
+
+/// An email which could not be delivered, even after retrying
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmailDeadLetter {
+    pub id: Ulid,
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,98 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+/// Approximate location of an IP address, as found in a GeoIP database
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    /// The ISO 3166-1 code of the country
+    pub country_code: Option<String>,
+
+    /// The English name of the country
+    pub country: Option<String>,
+
+    /// The English name of the city
+    pub city: Option<String>,
+
+    /// The number of the autonomous system the address belongs to
+    pub asn: Option<u32>,
+
+    /// The organization operating the autonomous system
+    pub as_organization: Option<String>,
+}
+
+impl GeoLocation {
+    /// Returns `true` if nothing is known about the location
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Fill the fields which are not known yet from another location
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            country_code: self.country_code.or(other.country_code),
+            country: self.country.or(other.country),
+            city: self.city.or(other.city),
+            asn: self.asn.or(other.asn),
+            as_organization: self.as_organization.or(other.as_organization),
+        }
+    }
+}
+
+impl std::fmt::Display for GeoLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let place = match (&self.city, &self.country, &self.country_code) {
+            (Some(city), Some(country), _) => Some(format!("{city}, {country}")),
+            (None, Some(country), _) => Some(country.clone()),
+            (_, None, Some(code)) => Some(code.clone()),
+            (Some(city), None, None) => Some(city.clone()),
+            (None, None, None) => None,
+        };
+
+        match (place, &self.as_organization) {
+            (Some(place), Some(organization)) => write!(f, "{place} ({organization})"),
+            (Some(place), None) => f.write_str(&place),
+            (None, Some(organization)) => f.write_str(organization),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let location = GeoLocation {
+            country_code: Some("FR".to_owned()),
+            country: Some("France".to_owned()),
+            city: Some("Paris".to_owned()),
+            ..GeoLocation::default()
+        };
+        assert_eq!(location.to_string(), "Paris, France");
+
+        let location = GeoLocation {
+            country_code: Some("FR".to_owned()),
+            as_organization: Some("Example ISP".to_owned()),
+            ..GeoLocation::default()
+        };
+        assert_eq!(location.to_string(), "FR (Example ISP)");
+
+        assert_eq!(GeoLocation::default().to_string(), "");
+    }
+}
@@ -0,0 +1,38 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ulid::Ulid;
+
+/// An idempotency key sent by a client along a request, so that retrying the
+/// request replays the original response instead of running it again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IdempotencyKey {
+    pub id: Ulid,
+    pub oauth2_client_id: Ulid,
+    pub key: String,
+    pub request_hash: String,
+    pub response: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IdempotencyKey {
+    /// Whether the request which used this key completed, and its response
+    /// can be replayed
+    #[must_use]
+    pub fn is_completed(&self) -> bool {
+        self.response.is_some()
+    }
+}
@@ -17,8 +17,14 @@
 use thiserror::Error;
 
 pub(crate) mod compat;
+pub(crate) mod emails;
+pub(crate) mod geo_location;
+pub(crate) mod idempotency;
+pub(crate) mod login_risk;
+pub(crate) mod network_ban;
 pub(crate) mod oauth2;
 mod site_config;
+pub(crate) mod stats;
 pub(crate) mod tokens;
 pub(crate) mod upstream_oauth2;
 pub(crate) mod user_agent;
@@ -36,24 +42,43 @@ pub use self::{
         CompatAccessToken, CompatRefreshToken, CompatRefreshTokenState, CompatSession,
         CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device,
     },
+    emails::EmailDeadLetter,
+    geo_location::GeoLocation,
+    idempotency::IdempotencyKey,
+    login_risk::{
+        InvalidLoginRiskDecisionError, InvalidLoginRiskSignalError, LoginRiskAssessment,
+        LoginRiskDecision, LoginRiskSignal,
+    },
+    network_ban::{InvalidNetworkError, Network, NetworkBan},
     oauth2::{
-        AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Client, DeviceCodeGrant,
-        DeviceCodeGrantState, InvalidRedirectUriError, JwksOrJwksUri, Pkce, Session, SessionState,
+        AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage,
+        BackchannelAuthenticationGrant, BackchannelAuthenticationGrantState, Client,
+        DeviceCodeGrant, DeviceCodeGrantState, InvalidRedirectUriError, JwksOrJwksUri, Pkce,
+        RedirectUriPolicy, ScopeNotAllowedError, Session, SessionState,
+    },
+    site_config::{
+        CaptchaConfig, CaptchaService, ClientCertificateLoginConfig, ClientCertificateMapping,
+        DataRetentionConfig, NotificationChannels, SecurityNotificationsConfig, SiteAnnouncement,
+        SiteConfig, SpnegoLoginConfig,
     },
-    site_config::{CaptchaConfig, CaptchaService, SiteConfig},
+    stats::{ClientDailyCount, DailyCount},
     tokens::{
         AccessToken, AccessTokenState, RefreshToken, RefreshTokenState, TokenFormatError, TokenType,
     },
     upstream_oauth2::{
         UpsreamOAuthProviderSetEmailVerification, UpstreamOAuthAuthorizationSession,
-        UpstreamOAuthAuthorizationSessionState, UpstreamOAuthLink, UpstreamOAuthProvider,
-        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthAuthorizationSessionState, UpstreamOAuthLink, UpstreamOAuthLinkToken,
+        UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderGroupsPreference,
         UpstreamOAuthProviderImportAction, UpstreamOAuthProviderImportPreference,
-        UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderSubjectPreference,
+        UpstreamOAuthProviderOnDeprovision, UpstreamOAuthProviderPkceMode,
+        UpstreamOAuthProviderSubjectPreference,
     },
     user_agent::{DeviceType, UserAgent},
     users::{
-        Authentication, AuthenticationMethod, BrowserSession, Password, User, UserEmail,
-        UserEmailVerification, UserEmailVerificationState, UserRecoverySession, UserRecoveryTicket,
+        Authentication, AuthenticationMethod, BrowserSession, LoginSighting, Password, User,
+        UserEmail, UserEmailChange, UserEmailVerification, UserEmailVerificationState, UserExport,
+        UserLoginAlert, UserLoginCode, UserMagicLink, UserMagicLinkSession, UserPhone,
+        UserPhoneVerification, UserPhoneVerificationState, UserRecoverySession, UserRecoveryTicket,
     },
 };
@@ -0,0 +1,169 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+use crate::UserAgent;
+
+/// A reason for a login to be considered risky
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginRiskSignal {
+    /// The device or the network were never used by the user before
+    NewDevice,
+
+    /// The address belongs to a network with a low reputation
+    LowReputation,
+
+    /// The previous login of the user was from another country, too recently
+    /// to have travelled since
+    ImpossibleTravel,
+
+    /// The address is a Tor exit node
+    TorExitNode,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid login risk signal {0:?}")]
+pub struct InvalidLoginRiskSignalError(String);
+
+impl std::str::FromStr for LoginRiskSignal {
+    type Err = InvalidLoginRiskSignalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new_device" => Ok(Self::NewDevice),
+            "low_reputation" => Ok(Self::LowReputation),
+            "impossible_travel" => Ok(Self::ImpossibleTravel),
+            "tor_exit_node" => Ok(Self::TorExitNode),
+            s => Err(InvalidLoginRiskSignalError(s.to_owned())),
+        }
+    }
+}
+
+impl LoginRiskSignal {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NewDevice => "new_device",
+            Self::LowReputation => "low_reputation",
+            Self::ImpossibleTravel => "impossible_travel",
+            Self::TorExitNode => "tor_exit_node",
+        }
+    }
+}
+
+impl std::fmt::Display for LoginRiskSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What to do with a login, depending on its risk score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginRiskDecision {
+    /// Let the login through
+    Allow,
+
+    /// Ask for a code sent by email before completing the login
+    RequireMfa,
+
+    /// Reject the login
+    Deny,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid login risk decision {0:?}")]
+pub struct InvalidLoginRiskDecisionError(String);
+
+impl std::str::FromStr for LoginRiskDecision {
+    type Err = InvalidLoginRiskDecisionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "require_mfa" => Ok(Self::RequireMfa),
+            "deny" => Ok(Self::Deny),
+            s => Err(InvalidLoginRiskDecisionError(s.to_owned())),
+        }
+    }
+}
+
+impl LoginRiskDecision {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::RequireMfa => "require_mfa",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl std::fmt::Display for LoginRiskDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The risk assessment of a login, kept to audit the decisions taken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoginRiskAssessment {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub user_agent: Option<UserAgent>,
+    pub ip_address: Option<IpAddr>,
+    pub request_id: Option<String>,
+    pub score: u32,
+    pub signals: Vec<LoginRiskSignal>,
+    pub decision: LoginRiskDecision,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for signal in [
+            LoginRiskSignal::NewDevice,
+            LoginRiskSignal::LowReputation,
+            LoginRiskSignal::ImpossibleTravel,
+            LoginRiskSignal::TorExitNode,
+        ] {
+            assert_eq!(signal.as_str().parse::<LoginRiskSignal>().unwrap(), signal);
+        }
+
+        for decision in [
+            LoginRiskDecision::Allow,
+            LoginRiskDecision::RequireMfa,
+            LoginRiskDecision::Deny,
+        ] {
+            assert_eq!(
+                decision.as_str().parse::<LoginRiskDecision>().unwrap(),
+                decision
+            );
+        }
+
+        "tor".parse::<LoginRiskSignal>().unwrap_err();
+        "mfa".parse::<LoginRiskDecision>().unwrap_err();
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// A range of IP addresses, in the CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Network {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+/// Error when parsing or building an invalid [`Network`]
+#[derive(Debug, Error)]
+#[error("invalid network")]
+pub struct InvalidNetworkError;
+
+fn max_prefix_len(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl Network {
+    /// Build the network of the given length which contains the address
+    ///
+    /// The bits of the address beyond the prefix are cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix is too long for the kind of address
+    pub fn new(address: IpAddr, prefix_len: u8) -> Result<Self, InvalidNetworkError> {
+        if prefix_len > max_prefix_len(address) {
+            return Err(InvalidNetworkError);
+        }
+
+        let address = match address {
+            IpAddr::V4(address) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+            }
+            IpAddr::V6(address) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+            }
+        };
+
+        Ok(Self {
+            address,
+            prefix_len,
+        })
+    }
+
+    /// The first address of the network
+    #[must_use]
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// The length of the prefix of the network
+    #[must_use]
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether the network contains the given address
+    #[must_use]
+    pub fn contains(&self, address: IpAddr) -> bool {
+        Self::new(address, self.prefix_len).is_ok_and(|network| network == *self)
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl FromStr for Network {
+    type Err = InvalidNetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => {
+                let address: IpAddr = address.parse().map_err(|_| InvalidNetworkError)?;
+                let prefix_len = prefix_len.parse().map_err(|_| InvalidNetworkError)?;
+                (address, prefix_len)
+            }
+            None => {
+                let address: IpAddr = s.parse().map_err(|_| InvalidNetworkError)?;
+                (address, max_prefix_len(address))
+            }
+        };
+
+        Self::new(address, prefix_len)
+    }
+}
+
+/// A temporary ban of a network, which can't reach the service until it
+/// expires or is lifted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NetworkBan {
+    pub id: Ulid,
+    pub network: Network,
+
+    /// Why the network was banned by an administrator. Automatic bans, caused
+    /// by too many failed authentication attempts, have no reason.
+    pub reason: Option<String>,
+
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub lifted_at: Option<DateTime<Utc>>,
+}
+
+impl NetworkBan {
+    /// Whether the ban was put in place automatically
+    #[must_use]
+    pub fn is_automatic(&self) -> bool {
+        self.reason.is_none()
+    }
+
+    /// Whether the ban is still in effect
+    #[must_use]
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.lifted_at.is_none() && self.expires_at > now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network() {
+        let network: Network = "192.0.2.42/24".parse().unwrap();
+        assert_eq!(network.to_string(), "192.0.2.0/24");
+        assert!(network.contains("192.0.2.1".parse().unwrap()));
+        assert!(!network.contains("192.0.3.1".parse().unwrap()));
+        assert!(!network.contains("::1".parse().unwrap()));
+
+        let network: Network = "2001:db8:1:2:3::4/64".parse().unwrap();
+        assert_eq!(network.to_string(), "2001:db8:1:2::/64");
+        assert!(network.contains("2001:db8:1:2::1".parse().unwrap()));
+        assert!(!network.contains("2001:db8:1:3::1".parse().unwrap()));
+
+        // A single address is a network of its own
+        let network: Network = "192.0.2.1".parse().unwrap();
+        assert_eq!(network.to_string(), "192.0.2.1/32");
+        assert!(network.contains("192.0.2.1".parse().unwrap()));
+        assert!(!network.contains("192.0.2.2".parse().unwrap()));
+
+        // Prefixes can cover everything
+        let network: Network = "0.0.0.0/0".parse().unwrap();
+        assert!(network.contains("203.0.113.1".parse().unwrap()));
+
+        "192.0.2.1/33".parse::<Network>().unwrap_err();
+        "2001:db8::/129".parse::<Network>().unwrap_err();
+        "not a network".parse::<Network>().unwrap_err();
+        "192.0.2.1/abc".parse::<Network>().unwrap_err();
+    }
+}
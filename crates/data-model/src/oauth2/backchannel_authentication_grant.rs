@@ -0,0 +1,295 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use oauth2_types::scope::Scope;
+use serde::Serialize;
+use ulid::Ulid;
+
+use crate::{BrowserSession, InvalidTransitionError, Session, UserAgent};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum BackchannelAuthenticationGrantState {
+    /// The backchannel authentication grant is pending.
+    Pending,
+
+    /// The backchannel authentication grant has been fulfilled by a user.
+    Fulfilled {
+        /// The browser session which was used to complete this
+        /// backchannel authentication grant.
+        browser_session_id: Ulid,
+
+        /// The time at which this backchannel authentication grant was
+        /// fulfilled.
+        fulfilled_at: DateTime<Utc>,
+    },
+
+    /// The backchannel authentication grant has been rejected by a user.
+    Rejected {
+        /// The browser session which was used to reject this backchannel
+        /// authentication grant.
+        browser_session_id: Ulid,
+
+        /// The time at which this backchannel authentication grant was
+        /// rejected.
+        rejected_at: DateTime<Utc>,
+    },
+
+    /// The backchannel authentication grant was exchanged for an access token.
+    Exchanged {
+        /// The browser session which was used to exchange this
+        /// backchannel authentication grant.
+        browser_session_id: Ulid,
+
+        /// The time at which the backchannel authentication grant was
+        /// fulfilled.
+        fulfilled_at: DateTime<Utc>,
+
+        /// The time at which this backchannel authentication grant was
+        /// exchanged.
+        exchanged_at: DateTime<Utc>,
+
+        /// The OAuth 2.0 session ID which was created by this
+        /// backchannel authentication grant.
+        session_id: Ulid,
+    },
+}
+
+impl BackchannelAuthenticationGrantState {
+    /// Mark this backchannel authentication grant as fulfilled, returning a new
+    /// state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Pending`] state.
+    ///
+    /// [`Pending`]: BackchannelAuthenticationGrantState::Pending
+    pub fn fulfill(
+        self,
+        browser_session: &BrowserSession,
+        fulfilled_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        match self {
+            BackchannelAuthenticationGrantState::Pending => {
+                Ok(BackchannelAuthenticationGrantState::Fulfilled {
+                    browser_session_id: browser_session.id,
+                    fulfilled_at,
+                })
+            }
+            _ => Err(InvalidTransitionError),
+        }
+    }
+
+    /// Mark this backchannel authentication grant as rejected, returning a new
+    /// state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Pending`] state.
+    ///
+    /// [`Pending`]: BackchannelAuthenticationGrantState::Pending
+    pub fn reject(
+        self,
+        browser_session: &BrowserSession,
+        rejected_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        match self {
+            BackchannelAuthenticationGrantState::Pending => {
+                Ok(BackchannelAuthenticationGrantState::Rejected {
+                    browser_session_id: browser_session.id,
+                    rejected_at,
+                })
+            }
+            _ => Err(InvalidTransitionError),
+        }
+    }
+
+    /// Mark this backchannel authentication grant as exchanged, returning a new
+    /// state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Fulfilled`] state.
+    ///
+    /// [`Fulfilled`]: BackchannelAuthenticationGrantState::Fulfilled
+    pub fn exchange(
+        self,
+        session: &Session,
+        exchanged_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        match self {
+            BackchannelAuthenticationGrantState::Fulfilled {
+                fulfilled_at,
+                browser_session_id,
+                ..
+            } => Ok(BackchannelAuthenticationGrantState::Exchanged {
+                browser_session_id,
+                fulfilled_at,
+                exchanged_at,
+                session_id: session.id,
+            }),
+            _ => Err(InvalidTransitionError),
+        }
+    }
+
+    /// Returns `true` if the backchannel authentication grant state is
+    /// [`Pending`].
+    ///
+    /// [`Pending`]: BackchannelAuthenticationGrantState::Pending
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Pending)
+    }
+
+    /// Returns `true` if the backchannel authentication grant state is
+    /// [`Fulfilled`].
+    ///
+    /// [`Fulfilled`]: BackchannelAuthenticationGrantState::Fulfilled
+    #[must_use]
+    pub fn is_fulfilled(&self) -> bool {
+        matches!(self, Self::Fulfilled { .. })
+    }
+
+    /// Returns `true` if the backchannel authentication grant state is
+    /// [`Rejected`].
+    ///
+    /// [`Rejected`]: BackchannelAuthenticationGrantState::Rejected
+    #[must_use]
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, Self::Rejected { .. })
+    }
+
+    /// Returns `true` if the backchannel authentication grant state is
+    /// [`Exchanged`].
+    ///
+    /// [`Exchanged`]: BackchannelAuthenticationGrantState::Exchanged
+    #[must_use]
+    pub fn is_exchanged(&self) -> bool {
+        matches!(self, Self::Exchanged { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackchannelAuthenticationGrant {
+    pub id: Ulid,
+    #[serde(flatten)]
+    pub state: BackchannelAuthenticationGrantState,
+
+    /// The client ID which requested this backchannel authentication grant.
+    pub client_id: Ulid,
+
+    /// The scope which was requested by this backchannel authentication grant.
+    pub scope: Scope,
+
+    /// The user for which the authentication was requested.
+    pub user_id: Ulid,
+
+    /// The identifier of the authentication request, which the client uses to
+    /// poll for an access token.
+    pub auth_req_id: String,
+
+    /// A message displayed to the user, which should also be displayed by
+    /// the client, to help the user make sure they are approving the right
+    /// request.
+    pub binding_message: Option<String>,
+
+    /// The time at which this backchannel authentication grant was created.
+    pub created_at: DateTime<Utc>,
+
+    /// The time at which this backchannel authentication grant will expire.
+    pub expires_at: DateTime<Utc>,
+
+    /// The IP address of the client which requested this backchannel
+    /// authentication grant.
+    pub ip_address: Option<IpAddr>,
+
+    /// The user agent used to request this backchannel authentication grant.
+    pub user_agent: Option<UserAgent>,
+}
+
+impl std::ops::Deref for BackchannelAuthenticationGrant {
+    type Target = BackchannelAuthenticationGrantState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl BackchannelAuthenticationGrant {
+    /// Mark this backchannel authentication grant as fulfilled, returning the
+    /// updated grant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Pending`] state.
+    ///
+    /// [`Pending`]: BackchannelAuthenticationGrantState::Pending
+    pub fn fulfill(
+        self,
+        browser_session: &BrowserSession,
+        fulfilled_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        Ok(Self {
+            state: self.state.fulfill(browser_session, fulfilled_at)?,
+            ..self
+        })
+    }
+
+    /// Mark this backchannel authentication grant as rejected, returning the
+    /// updated grant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Pending`]
+    ///
+    /// [`Pending`]: BackchannelAuthenticationGrantState::Pending
+    pub fn reject(
+        self,
+        browser_session: &BrowserSession,
+        rejected_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        Ok(Self {
+            state: self.state.reject(browser_session, rejected_at)?,
+            ..self
+        })
+    }
+
+    /// Mark this backchannel authentication grant as exchanged, returning the
+    /// updated grant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backchannel authentication grant is not in the
+    /// [`Fulfilled`] state.
+    ///
+    /// [`Fulfilled`]: BackchannelAuthenticationGrantState::Fulfilled
+    pub fn exchange(
+        self,
+        session: &Session,
+        exchanged_at: DateTime<Utc>,
+    ) -> Result<Self, InvalidTransitionError> {
+        Ok(Self {
+            state: self.state.exchange(session, exchanged_at)?,
+            ..self
+        })
+    }
+}
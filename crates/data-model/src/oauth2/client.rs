@@ -18,7 +18,11 @@ use mas_iana::{
     oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod},
 };
 use mas_jose::jwk::PublicJsonWebKeySet;
-use oauth2_types::{oidc::ApplicationType, requests::GrantType};
+use oauth2_types::{
+    oidc::ApplicationType,
+    requests::GrantType,
+    scope::{Scope, ScopeToken},
+};
 use rand::RngCore;
 use serde::Serialize;
 use thiserror::Error;
@@ -97,8 +101,35 @@ pub struct Client {
     /// URI using the https scheme that a third party can use to initiate a
     /// login by the RP
     pub initiate_login_uri: Option<Url>,
+
+    /// Whether the consent screen is skipped for this client, for trusted
+    /// first-party clients
+    pub skip_consent: bool,
+
+    /// Scope tokens this client is allowed to request. A trailing `*` matches
+    /// any suffix. `None` means the client is not restricted
+    pub allowed_scopes: Option<Vec<String>>,
+
+    /// Scope used when the client doesn't request any
+    pub default_scope: Option<Scope>,
+
+    /// Whether requested scope tokens which are not allowed are removed,
+    /// instead of rejecting the request
+    pub strip_disallowed_scopes: bool,
+
+    /// DNS name which must be in the subject alternative names of the client
+    /// certificate, for the `tls_client_auth` authentication method
+    pub tls_client_auth_san_dns: Option<String>,
+
+    /// Email address which must be in the subject alternative names of the
+    /// client certificate, for the `tls_client_auth` authentication method
+    pub tls_client_auth_san_email: Option<String>,
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("scope {0:?} is not allowed for this client")]
+pub struct ScopeNotAllowedError(pub String);
+
 #[derive(Debug, Error)]
 pub enum InvalidRedirectUriError {
     #[error("redirect_uri is not allowed for this client")]
@@ -109,9 +140,125 @@ pub enum InvalidRedirectUriError {
 
     #[error("client has no redirect_uri registered")]
     NoneRegistered,
+
+    #[error("redirect_uri must not contain wildcards")]
+    Wildcard,
+
+    #[error("redirect_uri must not contain credentials or a fragment")]
+    Unsafe,
+
+    #[error("redirect_uri uses a scheme which is not allowed for this client")]
+    SchemeNotAllowed,
+
+    #[error("redirect_uri is too long")]
+    TooLong,
+}
+
+/// Controls how lenient the matching of redirect URIs is for a client
+///
+/// By default, the requested redirect URI must exactly match one of the
+/// registered ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RedirectUriPolicy {
+    /// Accept any port on loopback interface redirect URIs, as described in
+    /// [RFC 8252 section 7.3](https://www.rfc-editor.org/rfc/rfc8252#section-7.3)
+    pub allow_loopback_any_port: bool,
+
+    /// Accept redirect URIs with a private-use scheme, as described in
+    /// [RFC 8252 section 7.1](https://www.rfc-editor.org/rfc/rfc8252#section-7.1)
+    pub allow_private_use_schemes: bool,
+}
+
+impl RedirectUriPolicy {
+    /// A policy for native applications, which allows loopback interface
+    /// redirect URIs on any port and private-use schemes
+    #[must_use]
+    pub const fn native() -> Self {
+        Self {
+            allow_loopback_any_port: true,
+            allow_private_use_schemes: true,
+        }
+    }
+
+    /// Check that the given URI can be redirected to under this policy
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URI is too long, contains wildcards,
+    /// credentials or a fragment, or if it uses a scheme not allowed by this
+    /// policy
+    pub fn check(&self, uri: &Url) -> Result<(), InvalidRedirectUriError> {
+        let serialized = uri.as_str();
+        if serialized.len() > MAX_REDIRECT_URI_LENGTH {
+            return Err(InvalidRedirectUriError::TooLong);
+        }
+
+        // Wildcards may have been percent-encoded by the URL parser
+        if serialized.contains('*') || serialized.to_ascii_lowercase().contains("%2a") {
+            return Err(InvalidRedirectUriError::Wildcard);
+        }
+
+        // Credentials in the URI are a common trick to make it look like it
+        // redirects somewhere else
+        if !uri.username().is_empty() || uri.password().is_some() || uri.fragment().is_some() {
+            return Err(InvalidRedirectUriError::Unsafe);
+        }
+
+        match uri.scheme() {
+            "https" | "http" => Ok(()),
+            scheme if FORBIDDEN_SCHEMES.contains(&scheme) => Err(InvalidRedirectUriError::Unsafe),
+            _ if self.allow_private_use_schemes => Ok(()),
+            _ => Err(InvalidRedirectUriError::SchemeNotAllowed),
+        }
+    }
+
+    /// Whether the given URI matches one of the registered URIs under this
+    /// policy
+    fn matches_one_of(&self, uri: &Url, registered_uris: &[Url]) -> bool {
+        if self.allow_loopback_any_port
+            && uri.scheme() == "http"
+            && LOCAL_HOSTS.contains(&uri.host_str().unwrap_or_default())
+        {
+            // Compare without the port, since the client may have registered the
+            // URI with a port which it can't necessarily reuse
+            let without_port = |uri: &Url| {
+                let mut uri = uri.clone();
+                uri.set_port(None).ok().map(|()| uri)
+            };
+
+            let uri = without_port(uri);
+            if uri.is_some() && registered_uris.iter().any(|r| without_port(r) == uri) {
+                return true;
+            }
+        }
+
+        registered_uris.contains(uri)
+    }
 }
 
 impl Client {
+    /// Whether this is a public client, which doesn't authenticate at the token
+    /// endpoint
+    #[must_use]
+    pub fn is_public(&self) -> bool {
+        matches!(
+            self.token_endpoint_auth_method,
+            Some(OAuthClientAuthenticationMethod::None)
+        )
+    }
+
+    /// The policy used when resolving redirect URIs for this client
+    ///
+    /// Native applications get the allowances described in RFC 8252, other
+    /// clients need an exact match
+    #[must_use]
+    pub fn redirect_uri_policy(&self) -> RedirectUriPolicy {
+        match self.application_type {
+            Some(ApplicationType::Native) => RedirectUriPolicy::native(),
+            _ => RedirectUriPolicy::default(),
+        }
+    }
+
     /// Determine which redirect URI to use for the given request.
     ///
     /// # Errors
@@ -119,19 +266,70 @@ impl Client {
     /// Returns an error if:
     ///
     ///  - no URL was given but multiple redirect URIs are registered,
-    ///  - no URL was registered, or
-    ///  - the given URL is not registered
+    ///  - no URL was registered,
+    ///  - the given URL is not registered, or
+    ///  - the URL is not allowed by the client [`RedirectUriPolicy`]
     pub fn resolve_redirect_uri<'a>(
         &'a self,
         redirect_uri: &'a Option<Url>,
     ) -> Result<&'a Url, InvalidRedirectUriError> {
-        match (&self.redirect_uris[..], redirect_uri) {
-            ([], _) => Err(InvalidRedirectUriError::NoneRegistered),
-            ([one], None) => Ok(one),
-            (_, None) => Err(InvalidRedirectUriError::MultipleRegistered),
-            (uris, Some(uri)) if uri_matches_one_of(uri, uris) => Ok(uri),
-            _ => Err(InvalidRedirectUriError::NotAllowed),
+        let policy = self.redirect_uri_policy();
+        let uri = match (&self.redirect_uris[..], redirect_uri) {
+            ([], _) => return Err(InvalidRedirectUriError::NoneRegistered),
+            ([one], None) => one,
+            (_, None) => return Err(InvalidRedirectUriError::MultipleRegistered),
+            (uris, Some(uri)) if policy.matches_one_of(uri, uris) => uri,
+            _ => return Err(InvalidRedirectUriError::NotAllowed),
+        };
+
+        policy.check(uri)?;
+        Ok(uri)
+    }
+
+    /// Whether this client is allowed to request the given scope token
+    #[must_use]
+    pub fn is_scope_token_allowed(&self, token: &ScopeToken) -> bool {
+        let Some(allowed_scopes) = &self.allowed_scopes else {
+            return true;
+        };
+
+        allowed_scopes
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => token.as_str().starts_with(prefix),
+                None => token.as_str() == pattern,
+            })
+    }
+
+    /// Apply the default scope and the scope allow-list of this client to a
+    /// requested scope
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scope contains a token which is not allowed,
+    /// unless the client strips them
+    pub fn restrict_scope(&self, scope: Scope) -> Result<Scope, ScopeNotAllowedError> {
+        let scope = match &self.default_scope {
+            Some(default_scope) if scope.is_empty() => default_scope.clone(),
+            _ => scope,
+        };
+
+        if self.strip_disallowed_scopes {
+            return Ok(scope
+                .iter()
+                .filter(|token| self.is_scope_token_allowed(token))
+                .cloned()
+                .collect());
         }
+
+        if let Some(token) = scope
+            .iter()
+            .find(|token| !self.is_scope_token_allowed(token))
+        {
+            return Err(ScopeNotAllowedError(token.to_string()));
+        }
+
+        Ok(scope)
     }
 
     #[doc(hidden)]
@@ -163,6 +361,12 @@ impl Client {
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
                 jwks: None,
+                skip_consent: false,
+                allowed_scopes: None,
+                default_scope: None,
+                strip_disallowed_scopes: false,
+                tls_client_auth_san_dns: None,
+                tls_client_auth_san_email: None,
             },
             // Another client without any URIs set
             Self {
@@ -185,29 +389,26 @@ impl Client {
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
                 jwks: None,
+                skip_consent: false,
+                allowed_scopes: None,
+                default_scope: None,
+                strip_disallowed_scopes: false,
+                tls_client_auth_san_dns: None,
+                tls_client_auth_san_email: None,
             },
         ]
     }
 }
 
+/// The maximum length of a redirect URI, in bytes
+const MAX_REDIRECT_URI_LENGTH: usize = 2048;
+
 /// The hosts that match the loopback interface.
 const LOCAL_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
 
-/// Whether the given URI matches one of the registered URIs.
-///
-/// If the URI host is one if `localhost`, `127.0.0.1` or `[::1]`, any port is
-/// accepted.
-fn uri_matches_one_of(uri: &Url, registered_uris: &[Url]) -> bool {
-    if LOCAL_HOSTS.contains(&uri.host_str().unwrap_or_default()) {
-        let mut uri = uri.clone();
-        // Try matching without the port first
-        if uri.set_port(None).is_ok() && registered_uris.contains(&uri) {
-            return true;
-        }
-    }
-
-    registered_uris.contains(uri)
-}
+/// Schemes which can never be used as redirect URIs, as they would execute
+/// or load content in the context of the authorization server.
+const FORBIDDEN_SCHEMES: &[&str] = &["javascript", "data", "vbscript", "file", "blob"];
 
 #[cfg(test)]
 mod tests {
@@ -216,34 +417,133 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_uri_matches_one_of() {
+    fn test_matches_one_of() {
         let registered_uris = &[
             Url::parse("http://127.0.0.1").unwrap(),
             Url::parse("https://example.org").unwrap(),
         ];
 
-        // Non-loopback interface URIs.
-        assert!(uri_matches_one_of(
-            &Url::parse("https://example.org").unwrap(),
-            registered_uris
+        for policy in [RedirectUriPolicy::default(), RedirectUriPolicy::native()] {
+            // Non-loopback interface URIs.
+            assert!(
+                policy.matches_one_of(&Url::parse("https://example.org").unwrap(), registered_uris)
+            );
+            assert!(!policy.matches_one_of(
+                &Url::parse("https://example.org:8080").unwrap(),
+                registered_uris
+            ));
+
+            // Loopback interface URIS.
+            assert!(
+                policy.matches_one_of(&Url::parse("http://127.0.0.1").unwrap(), registered_uris)
+            );
+            assert!(
+                !policy.matches_one_of(&Url::parse("http://localhost").unwrap(), registered_uris)
+            );
+        }
+
+        // Only native apps can use any port on the loopback interface
+        let uri = Url::parse("http://127.0.0.1:8080").unwrap();
+        assert!(!RedirectUriPolicy::default().matches_one_of(&uri, registered_uris));
+        assert!(RedirectUriPolicy::native().matches_one_of(&uri, registered_uris));
+
+        // Even if the URI was registered with a port
+        let registered_uris = &[Url::parse("http://[::1]:1234/callback").unwrap()];
+        let uri = Url::parse("http://[::1]:5678/callback").unwrap();
+        assert!(!RedirectUriPolicy::default().matches_one_of(&uri, registered_uris));
+        assert!(RedirectUriPolicy::native().matches_one_of(&uri, registered_uris));
+        let uri = Url::parse("http://[::1]:5678/other").unwrap();
+        assert!(!RedirectUriPolicy::native().matches_one_of(&uri, registered_uris));
+    }
+
+    #[test]
+    fn test_policy_check() {
+        let strict = RedirectUriPolicy::default();
+        let native = RedirectUriPolicy::native();
+        let check = |policy: RedirectUriPolicy, uri: &str| policy.check(&Url::parse(uri).unwrap());
+
+        assert!(check(strict, "https://example.com/callback").is_ok());
+        assert!(check(strict, "http://127.0.0.1:1234/callback").is_ok());
+
+        // Wildcards are never allowed
+        assert!(matches!(
+            check(native, "https://*.example.com/callback"),
+            Err(InvalidRedirectUriError::Wildcard)
         ));
-        assert!(!uri_matches_one_of(
-            &Url::parse("https://example.org:8080").unwrap(),
-            registered_uris
+        assert!(matches!(
+            check(native, "https://example.com/*"),
+            Err(InvalidRedirectUriError::Wildcard)
         ));
 
-        // Loopback interface URIS.
-        assert!(uri_matches_one_of(
-            &Url::parse("http://127.0.0.1").unwrap(),
-            registered_uris
+        // Neither are credentials, fragments or dangerous schemes
+        assert!(matches!(
+            check(native, "https://example.com@evil.com/callback"),
+            Err(InvalidRedirectUriError::Unsafe)
+        ));
+        assert!(matches!(
+            check(native, "https://example.com/callback#fragment"),
+            Err(InvalidRedirectUriError::Unsafe)
+        ));
+        assert!(matches!(
+            check(native, "javascript:alert(1)"),
+            Err(InvalidRedirectUriError::Unsafe)
         ));
-        assert!(uri_matches_one_of(
-            &Url::parse("http://127.0.0.1:8080").unwrap(),
-            registered_uris
+
+        // Private-use schemes are only allowed for native apps
+        assert!(matches!(
+            check(strict, "com.example.app:/callback"),
+            Err(InvalidRedirectUriError::SchemeNotAllowed)
         ));
-        assert!(!uri_matches_one_of(
-            &Url::parse("http://localhost").unwrap(),
-            registered_uris
+        assert!(check(native, "com.example.app:/callback").is_ok());
+
+        // Very long URIs are rejected
+        let long = format!(
+            "https://example.com/{}",
+            "a".repeat(MAX_REDIRECT_URI_LENGTH)
+        );
+        assert!(matches!(
+            check(strict, &long),
+            Err(InvalidRedirectUriError::TooLong)
         ));
     }
+
+    #[test]
+    fn test_restrict_scope() {
+        let now = chrono::Utc::now();
+        let mut rng = rand::thread_rng();
+        let mut client = Client::samples(now, &mut rng).remove(0);
+        let scope = |s: &str| s.parse::<Scope>().unwrap();
+        let device = "urn:matrix:org.matrix.msc2967.client:device:ABCDEFGHIJ";
+
+        // Clients without an allow-list can request anything
+        assert_eq!(
+            client.restrict_scope(scope(&format!("openid {device}"))),
+            Ok(scope(&format!("openid {device}")))
+        );
+
+        client.allowed_scopes = Some(vec!["openid".to_owned(), "urn:mas:graphql:*".to_owned()]);
+        client.default_scope = Some(scope("openid urn:mas:graphql:*"));
+
+        assert_eq!(
+            client.restrict_scope(scope("openid urn:mas:graphql:*")),
+            Ok(scope("openid urn:mas:graphql:*"))
+        );
+        assert_eq!(
+            client.restrict_scope(scope(&format!("openid {device}"))),
+            Err(ScopeNotAllowedError(device.to_owned()))
+        );
+
+        // The default scope is used when nothing is requested
+        assert_eq!(
+            client.restrict_scope(Scope::default()),
+            Ok(scope("openid urn:mas:graphql:*"))
+        );
+
+        // Disallowed tokens can be stripped instead
+        client.strip_disallowed_scopes = true;
+        assert_eq!(
+            client.restrict_scope(scope(&format!("openid {device}"))),
+            Ok(scope("openid"))
+        );
+    }
 }
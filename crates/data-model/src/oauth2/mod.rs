@@ -13,13 +13,19 @@
 // limitations under the License.
 
 mod authorization_grant;
+mod backchannel_authentication_grant;
 mod client;
 mod device_code_grant;
 mod session;
 
 pub use self::{
     authorization_grant::{AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Pkce},
-    client::{Client, InvalidRedirectUriError, JwksOrJwksUri},
+    backchannel_authentication_grant::{
+        BackchannelAuthenticationGrant, BackchannelAuthenticationGrantState,
+    },
+    client::{
+        Client, InvalidRedirectUriError, JwksOrJwksUri, RedirectUriPolicy, ScopeNotAllowedError,
+    },
     device_code_grant::{DeviceCodeGrant, DeviceCodeGrantState},
     session::{Session, SessionState},
 };
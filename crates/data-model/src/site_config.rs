@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use url::Url;
 
 /// Which Captcha service is being used
@@ -36,6 +36,125 @@ pub struct CaptchaConfig {
     pub secret_key: String,
 }
 
+/// An announcement banner displayed on web pages
+#[derive(Debug, Clone)]
+pub struct SiteAnnouncement {
+    /// The message to display, in a small subset of Markdown
+    pub message: String,
+
+    /// When to start displaying the banner
+    pub starts_at: Option<DateTime<Utc>>,
+
+    /// When to stop displaying the banner
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// Configuration of the SPNEGO login, where a reverse proxy negotiates a
+/// Kerberos ticket with the browser and forwards the authenticated principal
+#[derive(Debug, Clone)]
+pub struct SpnegoLoginConfig {
+    /// The Kerberos realms whose principals map to local users
+    pub realms: Vec<String>,
+
+    /// The request header in which the reverse proxy forwards the principal
+    pub principal_header: String,
+}
+
+impl SpnegoLoginConfig {
+    /// Get the username of the local user a Kerberos principal maps to, if its
+    /// realm is one of the mapped realms
+    #[must_use]
+    pub fn username_for<'a>(&self, principal: &'a str) -> Option<&'a str> {
+        let (username, realm) = principal.rsplit_once('@')?;
+        if username.is_empty() || !self.realms.iter().any(|r| r == realm) {
+            return None;
+        }
+
+        Some(username)
+    }
+}
+
+/// How a TLS client certificate is mapped to a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientCertificateMapping {
+    /// The common name of the subject is the username
+    CommonName,
+
+    /// One of the email addresses in the subject alternative names is a
+    /// confirmed email address of the user
+    Email,
+}
+
+/// Configuration of the login with TLS client certificates
+#[derive(Debug, Clone)]
+pub struct ClientCertificateLoginConfig {
+    /// The public base URL of the listener which requests client certificates
+    pub url: Url,
+
+    /// How certificates are mapped to users
+    pub mapping: ClientCertificateMapping,
+}
+
+/// How long personal data about the activity of users is kept
+///
+/// Each window is optional, the data is kept indefinitely if not set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataRetentionConfig {
+    /// How long IP addresses are kept
+    pub ip_addresses: Option<Duration>,
+
+    /// How long user agents are kept
+    pub user_agents: Option<Duration>,
+
+    /// How long a session can stay unused before it is ended
+    pub inactive_sessions: Option<Duration>,
+}
+
+/// Through which channels a kind of security notification is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NotificationChannels {
+    /// Send an email to the primary email address of the user
+    pub email: bool,
+
+    /// Send a direct message to the user on the homeserver
+    pub matrix: bool,
+
+    /// Post the event to the configured webhook
+    pub webhook: bool,
+}
+
+impl NotificationChannels {
+    /// Only send notifications by email
+    pub const EMAIL: Self = Self {
+        email: true,
+        matrix: false,
+        webhook: false,
+    };
+}
+
+/// Which channels each kind of security notification is sent through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityNotificationsConfig {
+    /// When the account is signed in to from an unknown device or network
+    pub new_login: NotificationChannels,
+
+    /// When the password of the account is changed
+    pub password_changed: NotificationChannels,
+
+    /// When the primary email address of the account is changed
+    pub email_changed: NotificationChannels,
+}
+
+impl Default for SecurityNotificationsConfig {
+    fn default() -> Self {
+        Self {
+            new_login: NotificationChannels::EMAIL,
+            password_changed: NotificationChannels::EMAIL,
+            email_changed: NotificationChannels::EMAIL,
+        }
+    }
+}
+
 /// Random site configuration we want accessible in various places.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone)]
@@ -49,6 +168,21 @@ pub struct SiteConfig {
     /// The server name, e.g. "matrix.org".
     pub server_name: String,
 
+    /// A human-readable name for the service.
+    pub service_name: Option<String>,
+
+    /// The URL to the logo of the service.
+    pub logo_uri: Option<Url>,
+
+    /// Primary brand colour, as a hexadecimal value.
+    pub primary_color: Option<String>,
+
+    /// Accent colour, as a hexadecimal value.
+    pub accent_color: Option<String>,
+
+    /// Additional CSS to inject in web pages and emails.
+    pub custom_css: Option<String>,
+
     /// The URL to the privacy policy.
     pub policy_uri: Option<Url>,
 
@@ -58,6 +192,9 @@ pub struct SiteConfig {
     /// Imprint to show in the footer.
     pub imprint: Option<String>,
 
+    /// Announcement banner to show at the top of web pages.
+    pub announcement: Option<SiteAnnouncement>,
+
     /// Whether password login is enabled.
     pub password_login_enabled: bool,
 
@@ -76,6 +213,69 @@ pub struct SiteConfig {
     /// Whether users can recover their account via email.
     pub account_recovery_allowed: bool,
 
+    /// Whether users get alerted by email of logins from unknown devices or
+    /// networks.
+    pub login_alerts_enabled: bool,
+
+    /// Whether password logins from unknown devices or networks have to be
+    /// confirmed with a code sent by email.
+    pub email_otp_enabled: bool,
+
+    /// Whether users can log in with a single-use link sent by email.
+    pub magic_link_login_allowed: bool,
+
+    /// Whether Matrix clients can create guest accounts.
+    pub guest_registration_allowed: bool,
+
+    /// Whether public clients, which don't authenticate at the token endpoint,
+    /// are allowed.
+    pub public_clients_allowed: bool,
+
+    /// Whether refresh tokens are only issued to sessions granted the
+    /// `offline_access` scope.
+    pub offline_access_required: bool,
+
+    /// How long a browser session can stay unused before it is ended.
+    pub browser_session_idle_timeout: Option<Duration>,
+
+    /// Maximum lifetime of a browser session, regardless of its activity.
+    pub browser_session_max_lifetime: Option<Duration>,
+
+    /// How recent the last authentication must be to perform sensitive
+    /// actions.
+    pub sensitive_action_reauth_ttl: Option<Duration>,
+
+    /// How long personal data about the activity of users is kept.
+    pub data_retention: DataRetentionConfig,
+
+    /// Which channels security notifications are sent through.
+    pub security_notifications: SecurityNotificationsConfig,
+
     /// Captcha configuration
     pub captcha: Option<CaptchaConfig>,
+
+    /// SPNEGO login configuration, if enabled
+    pub spnego_login: Option<SpnegoLoginConfig>,
+
+    /// TLS client certificate login configuration, if enabled
+    pub client_certificate_login: Option<ClientCertificateLoginConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spnego_username_mapping() {
+        let config = SpnegoLoginConfig {
+            realms: vec!["EXAMPLE.COM".to_owned()],
+            principal_header: "X-Remote-User".to_owned(),
+        };
+
+        assert_eq!(config.username_for("alice@EXAMPLE.COM"), Some("alice"));
+        assert_eq!(config.username_for("alice@OTHER.COM"), None);
+        assert_eq!(config.username_for("alice@example.com"), None);
+        assert_eq!(config.username_for("@EXAMPLE.COM"), None);
+        assert_eq!(config.username_for("alice"), None);
+    }
 }
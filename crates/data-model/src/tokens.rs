@@ -63,6 +63,10 @@ pub struct AccessToken {
     pub access_token: String,
     pub created_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
+
+    /// The base64url-encoded SHA-256 thumbprint of the client certificate this
+    /// token is bound to, as per RFC 8705
+    pub certificate_thumbprint: Option<String>,
 }
 
 impl AccessToken {
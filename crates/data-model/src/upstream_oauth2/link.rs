@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::Serialize;
 use ulid::Ulid;
 
@@ -24,3 +24,24 @@ pub struct UpstreamOAuthLink {
     pub subject: String,
     pub created_at: DateTime<Utc>,
 }
+
+/// The tokens obtained from the upstream provider for a link, when the
+/// provider is configured to keep them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamOAuthLinkToken {
+    pub link_id: Ulid,
+    pub encrypted_access_token: String,
+    pub encrypted_refresh_token: Option<String>,
+    pub access_token_expires_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UpstreamOAuthLinkToken {
+    /// Whether the access token is expired, or about to expire within the
+    /// given leeway
+    #[must_use]
+    pub fn is_access_token_expired(&self, now: DateTime<Utc>, leeway: Duration) -> bool {
+        self.access_token_expires_at
+            .is_some_and(|expires_at| expires_at <= now + leeway)
+    }
+}
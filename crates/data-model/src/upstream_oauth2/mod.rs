@@ -17,12 +17,14 @@ mod provider;
 mod session;
 
 pub use self::{
-    link::UpstreamOAuthLink,
+    link::{UpstreamOAuthLink, UpstreamOAuthLinkToken},
     provider::{
         ClaimsImports as UpstreamOAuthProviderClaimsImports,
         DiscoveryMode as UpstreamOAuthProviderDiscoveryMode,
+        GroupsPreference as UpstreamOAuthProviderGroupsPreference,
         ImportAction as UpstreamOAuthProviderImportAction,
         ImportPreference as UpstreamOAuthProviderImportPreference,
+        OnDeprovision as UpstreamOAuthProviderOnDeprovision,
         PkceMode as UpstreamOAuthProviderPkceMode,
         SetEmailVerification as UpsreamOAuthProviderSetEmailVerification,
         SubjectPreference as UpstreamOAuthProviderSubjectPreference, UpstreamOAuthProvider,
@@ -124,6 +124,54 @@ impl std::fmt::Display for PkceMode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDeprovision {
+    /// Don't do anything when the provider says the user is gone
+    #[default]
+    Ignore,
+
+    /// Lock the local user
+    Lock,
+
+    /// Lock the local user and deactivate them on the homeserver
+    Deactivate,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid deprovisioning action {0:?}")]
+pub struct InvalidOnDeprovisionError(String);
+
+impl std::str::FromStr for OnDeprovision {
+    type Err = InvalidOnDeprovisionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "lock" => Ok(Self::Lock),
+            "deactivate" => Ok(Self::Deactivate),
+            s => Err(InvalidOnDeprovisionError(s.to_owned())),
+        }
+    }
+}
+
+impl OnDeprovision {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ignore => "ignore",
+            Self::Lock => "lock",
+            Self::Deactivate => "deactivate",
+        }
+    }
+}
+
+impl std::fmt::Display for OnDeprovision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct UpstreamOAuthProvider {
     pub id: Ulid,
@@ -144,6 +192,8 @@ pub struct UpstreamOAuthProvider {
     pub disabled_at: Option<DateTime<Utc>>,
     pub claims_imports: ClaimsImports,
     pub additional_authorization_parameters: Vec<(String, String)>,
+    pub store_tokens: bool,
+    pub on_deprovision: OnDeprovision,
 }
 
 impl PartialOrd for UpstreamOAuthProvider {
@@ -208,6 +258,9 @@ pub struct ClaimsImports {
 
     #[serde(default)]
     pub verify_email: SetEmailVerification,
+
+    #[serde(default)]
+    pub groups: GroupsPreference,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -225,6 +278,46 @@ pub struct ImportPreference {
     pub template: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct GroupsPreference {
+    #[serde(default)]
+    pub action: ImportAction,
+
+    #[serde(default)]
+    pub claim: Option<String>,
+
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+}
+
+impl GroupsPreference {
+    /// The claim to import the groups from
+    #[must_use]
+    pub fn claim(&self) -> &str {
+        self.claim.as_deref().unwrap_or("groups")
+    }
+
+    /// Whether the given groups grant the admin flag
+    ///
+    /// Returns `None` if the admin flag isn't derived from the groups
+    #[must_use]
+    pub fn is_admin(&self, groups: &[String]) -> Option<bool> {
+        if self.admin_groups.is_empty() {
+            return None;
+        }
+
+        Some(groups.iter().any(|group| self.admin_groups.contains(group)))
+    }
+}
+
+impl std::ops::Deref for GroupsPreference {
+    type Target = ImportAction;
+
+    fn deref(&self) -> &Self::Target {
+        &self.action
+    }
+}
+
 impl std::ops::Deref for ImportPreference {
     type Target = ImportAction;
 
@@ -29,7 +29,13 @@ pub struct User {
     pub primary_user_email_id: Option<Ulid>,
     pub created_at: DateTime<Utc>,
     pub locked_at: Option<DateTime<Utc>>,
+    pub shadow_banned_at: Option<DateTime<Utc>>,
     pub can_request_admin: bool,
+    pub is_bot: bool,
+    pub is_guest: bool,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub groups: Vec<String>,
 }
 
 impl User {
@@ -38,6 +44,16 @@ impl User {
     pub fn is_valid(&self) -> bool {
         self.locked_at.is_none()
     }
+
+    /// Returns `true` if the user is shadow-banned.
+    ///
+    /// Shadow-banned users can still authenticate and get tokens, but those
+    /// are flagged on introspection so that the homeserver can silently drop
+    /// their traffic.
+    #[must_use]
+    pub fn is_shadow_banned(&self) -> bool {
+        self.shadow_banned_at.is_some()
+    }
 }
 
 impl User {
@@ -51,7 +67,13 @@ impl User {
             primary_user_email_id: None,
             created_at: now,
             locked_at: None,
+            shadow_banned_at: None,
             can_request_admin: false,
+            is_bot: false,
+            is_guest: false,
+            display_name: None,
+            avatar_url: None,
+            groups: Vec::new(),
         }]
     }
 }
@@ -76,6 +98,9 @@ pub struct Authentication {
 pub enum AuthenticationMethod {
     Password { user_password_id: Ulid },
     UpstreamOAuth2 { upstream_oauth2_session_id: Ulid },
+    MagicLink { user_magic_link_id: Ulid },
+    Kerberos { principal: String },
+    ClientCertificate { subject: String },
     Unknown,
 }
 
@@ -117,6 +142,237 @@ impl UserRecoveryTicket {
     }
 }
 
+/// A request to log in without a password, by receiving a link by email
+///
+/// For each session initiated, a [`UserMagicLink`] is sent to every [`User`]
+/// which has a verified email address matching the one requested.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserMagicLinkSession {
+    pub id: Ulid,
+    pub email: String,
+    pub user_agent: UserAgent,
+    pub ip_address: Option<IpAddr>,
+    pub locale: String,
+    pub created_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+impl UserMagicLinkSession {
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        vec![
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                email: "alice@example.com".to_owned(),
+                user_agent: UserAgent::parse(
+                    "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"
+                        .to_owned(),
+                ),
+                ip_address: Some(IpAddr::from([192, 0, 2, 1])),
+                locale: "en".to_owned(),
+                created_at: now,
+                consumed_at: None,
+            },
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                email: "bob@example.com".to_owned(),
+                user_agent: UserAgent::parse("Mozilla/5.0".to_owned()),
+                ip_address: None,
+                locale: "en".to_owned(),
+                created_at: now,
+                consumed_at: None,
+            },
+        ]
+    }
+}
+
+/// A single-use link to log in as a user, sent by email
+///
+/// The link is only valid for a short time, and can only be used once: using
+/// it consumes the whole [`UserMagicLinkSession`] it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserMagicLink {
+    pub id: Ulid,
+    pub user_magic_link_session_id: Ulid,
+    pub user_email_id: Ulid,
+    pub ticket: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl UserMagicLink {
+    /// How long the link can be used after it was sent
+    pub const VALIDITY: Duration = Duration::minutes(15);
+
+    #[must_use]
+    pub fn active(&self, now: DateTime<Utc>) -> bool {
+        now < self.expires_at
+    }
+}
+
+/// How a login compares to the previous logins of a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LoginSighting {
+    /// This is the first login recorded for this user
+    First,
+
+    /// The device and network were already used by this user
+    Known,
+
+    /// The device or the network were never used by this user before
+    Unknown,
+}
+
+impl LoginSighting {
+    /// Summarize a user agent to the browser and operating system it comes
+    /// from, so that their updates are not considered to be new devices
+    #[must_use]
+    pub fn device_of(user_agent: &UserAgent) -> String {
+        if user_agent.name.is_none() && user_agent.os.is_none() {
+            return user_agent.raw.clone();
+        }
+
+        let name = user_agent.name.as_deref().unwrap_or("Unknown");
+        let os = user_agent.os.as_deref().unwrap_or("Unknown");
+        match &user_agent.model {
+            Some(model) => format!("{name} on {os} ({model})"),
+            None => format!("{name} on {os}"),
+        }
+    }
+
+    /// Mask an IP address to the network it belongs to, so that logins from
+    /// neighbouring addresses are considered to come from the same network
+    #[must_use]
+    pub fn network_of(ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                IpAddr::from([a, b, c, 0])
+            }
+            IpAddr::V6(ip) => {
+                if let Some(ip) = ip.to_ipv4_mapped() {
+                    return Self::network_of(IpAddr::V4(ip));
+                }
+
+                let [a, b, c, ..] = ip.segments();
+                IpAddr::from([a, b, c, 0, 0, 0, 0, 0])
+            }
+        }
+    }
+}
+
+/// An alert sent to a user after a login from an unknown device or network
+///
+/// The alert carries a ticket, which is sent to the user by email as a link
+/// they can use to report that the login wasn't them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserLoginAlert {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub user_session_id: Ulid,
+    pub user_agent: Option<UserAgent>,
+    pub ip_address: Option<IpAddr>,
+    pub ticket: String,
+    pub created_at: DateTime<Utc>,
+    pub reported_at: Option<DateTime<Utc>>,
+}
+
+impl UserLoginAlert {
+    /// How long the link in the alert can be used to report the login
+    pub const VALIDITY: Duration = Duration::days(7);
+
+    #[must_use]
+    pub fn active(&self, now: DateTime<Utc>) -> bool {
+        self.reported_at.is_none() && now < self.created_at + Self::VALIDITY
+    }
+
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        BrowserSession::samples(now, rng)
+            .into_iter()
+            .map(|session| UserLoginAlert {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: session.user.id,
+                user_session_id: session.id,
+                user_agent: session.user_agent,
+                ip_address: Some(IpAddr::from([192, 0, 2, 1])),
+                ticket: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_owned(),
+                created_at: now,
+                reported_at: None,
+            })
+            .collect()
+    }
+}
+
+/// A code sent by email to confirm a login from an unknown device or network
+///
+/// The browser session is only started once the code is entered. The code can
+/// be sent again a few times, and only a few wrong attempts are allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserLoginCode {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub user_email_id: Ulid,
+    pub user_password_id: Ulid,
+    pub user_agent: Option<UserAgent>,
+    pub ip_address: Option<IpAddr>,
+    pub code: String,
+    pub attempts: u32,
+    pub sent_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_sent_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+impl UserLoginCode {
+    /// How long the code can be used after the login attempt
+    pub const VALIDITY: Duration = Duration::minutes(15);
+
+    /// How many wrong codes can be entered before the login is cancelled
+    pub const MAX_ATTEMPTS: u32 = 5;
+
+    /// How many times the code can be sent, including the first one
+    pub const MAX_SENDS: u32 = 3;
+
+    /// How long to wait before sending the code again
+    pub const RESEND_INTERVAL: Duration = Duration::minutes(1);
+
+    #[must_use]
+    pub fn active(&self, now: DateTime<Utc>) -> bool {
+        self.consumed_at.is_none() && now < self.expires_at && self.attempts < Self::MAX_ATTEMPTS
+    }
+
+    #[must_use]
+    pub fn can_resend(&self, now: DateTime<Utc>) -> bool {
+        self.active(now)
+            && self.sent_count < Self::MAX_SENDS
+            && now >= self.last_sent_at + Self::RESEND_INTERVAL
+    }
+
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        BrowserSession::samples(now, rng)
+            .into_iter()
+            .map(|session| UserLoginCode {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: session.user.id,
+                user_email_id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_password_id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_agent: session.user_agent,
+                ip_address: Some(IpAddr::from([192, 0, 2, 1])),
+                code: "123456".to_owned(),
+                attempts: 0,
+                sent_count: 1,
+                created_at: now,
+                last_sent_at: now,
+                expires_at: now + Self::VALIDITY,
+                consumed_at: None,
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct BrowserSession {
     pub id: Ulid,
@@ -126,6 +382,12 @@ pub struct BrowserSession {
     pub user_agent: Option<UserAgent>,
     pub last_active_at: Option<DateTime<Utc>>,
     pub last_active_ip: Option<IpAddr>,
+
+    /// The admin who started this session to impersonate the user, if any
+    pub impersonated_by: Option<Ulid>,
+
+    /// When this session expires. Only impersonation sessions expire.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl BrowserSession {
@@ -133,6 +395,12 @@ impl BrowserSession {
     pub fn active(&self) -> bool {
         self.finished_at.is_none() && self.user.is_valid()
     }
+
+    /// Whether this session was started by an admin impersonating the user
+    #[must_use]
+    pub fn is_impersonation(&self) -> bool {
+        self.impersonated_by.is_some()
+    }
 }
 
 impl BrowserSession {
@@ -150,6 +418,8 @@ impl BrowserSession {
                 )),
                 last_active_at: Some(now),
                 last_active_ip: None,
+                impersonated_by: None,
+                expires_at: None,
             })
             .collect()
     }
@@ -249,3 +519,240 @@ impl UserEmailVerification {
             .collect()
     }
 }
+
+/// A change of the primary email address of a user
+///
+/// The change is confirmed once the user proves they own the new address. The
+/// previous address is then notified, with a link carrying the undo ticket to
+/// revert the change in case it wasn't them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserEmailChange {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub old_email: String,
+    pub new_email: String,
+    pub undo_ticket: String,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub undone_at: Option<DateTime<Utc>>,
+}
+
+impl UserEmailChange {
+    /// How long the link sent to the previous address can be used to undo the
+    /// change
+    pub const UNDO_VALIDITY: Duration = Duration::days(7);
+
+    /// Whether the user still has to confirm the new address
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.confirmed_at.is_none()
+    }
+
+    /// Whether the change can still be reverted
+    #[must_use]
+    pub fn can_undo(&self, now: DateTime<Utc>) -> bool {
+        self.undone_at.is_none()
+            && self
+                .confirmed_at
+                .is_some_and(|confirmed_at| now < confirmed_at + Self::UNDO_VALIDITY)
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        User::samples(now, rng)
+            .into_iter()
+            .map(|user| UserEmailChange {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: user.id,
+                old_email: "alice@example.com".to_owned(),
+                new_email: "alice@example.org".to_owned(),
+                undo_ticket: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_owned(),
+                created_at: now - Duration::microseconds(10 * 60 * 1000 * 1000),
+                confirmed_at: Some(now),
+                undone_at: None,
+            })
+            .collect()
+    }
+}
+
+/// An export of the data of a user, generated in the background
+///
+/// The user gets a link by email once it is ready, and can download it from
+/// their account for a limited time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserExport {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl UserExport {
+    /// How long the export can be downloaded once it is ready
+    pub const VALIDITY: Duration = Duration::days(7);
+
+    /// Whether the export is still being generated
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.completed_at.is_none()
+    }
+
+    /// When the export stops being available for download, if it is ready
+    #[must_use]
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.completed_at
+            .map(|completed_at| completed_at + Self::VALIDITY)
+    }
+
+    /// Whether the export can be downloaded
+    #[must_use]
+    pub fn is_available(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at().is_some_and(|expires_at| now < expires_at)
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        User::samples(now, rng)
+            .into_iter()
+            .map(|user| UserExport {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: user.id,
+                created_at: now - Duration::microseconds(10 * 60 * 1000 * 1000),
+                completed_at: Some(now),
+            })
+            .collect()
+    }
+}
+
+/// A phone number (MSISDN) attached to a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserPhone {
+    pub id: Ulid,
+    pub user_id: Ulid,
+
+    /// The phone number, in the E.164 format, e.g. `+447700900123`
+    pub phone_number: String,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+impl UserPhone {
+    /// Check that a phone number is in the E.164 format: a `+` followed by up
+    /// to 15 digits, the first one not being a zero
+    #[must_use]
+    pub fn is_valid_number(phone_number: &str) -> bool {
+        let Some(digits) = phone_number.strip_prefix('+') else {
+            return false;
+        };
+
+        (2..=15).contains(&digits.len())
+            && !digits.starts_with('0')
+            && digits.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        vec![
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: Ulid::from_datetime_with_source(now.into(), rng),
+                phone_number: "+447700900123".to_owned(),
+                created_at: now,
+                confirmed_at: Some(now),
+            },
+            Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_id: Ulid::from_datetime_with_source(now.into(), rng),
+                phone_number: "+15555550123".to_owned(),
+                created_at: now,
+                confirmed_at: None,
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum UserPhoneVerificationState {
+    AlreadyUsed { when: DateTime<Utc> },
+    Expired { when: DateTime<Utc> },
+    Valid,
+}
+
+impl UserPhoneVerificationState {
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid)
+    }
+}
+
+/// A code sent by SMS to verify a [`UserPhone`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserPhoneVerification {
+    pub id: Ulid,
+    pub user_phone_id: Ulid,
+    pub code: String,
+    pub created_at: DateTime<Utc>,
+    pub state: UserPhoneVerificationState,
+}
+
+impl Deref for UserPhoneVerification {
+    type Target = UserPhoneVerificationState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl UserPhoneVerification {
+    #[doc(hidden)]
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        UserPhone::samples(now, rng)
+            .into_iter()
+            .map(|phone| Self {
+                id: Ulid::from_datetime_with_source(now.into(), rng),
+                user_phone_id: phone.id,
+                code: "123456".to_owned(),
+                created_at: now,
+                state: UserPhoneVerificationState::Valid,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phone_number_validation() {
+        assert!(UserPhone::is_valid_number("+447700900123"));
+        assert!(UserPhone::is_valid_number("+15555550123"));
+
+        assert!(!UserPhone::is_valid_number("447700900123"));
+        assert!(!UserPhone::is_valid_number("+0447700900123"));
+        assert!(!UserPhone::is_valid_number("+44 7700 900123"));
+        assert!(!UserPhone::is_valid_number("+1234567890123456"));
+        assert!(!UserPhone::is_valid_number("+"));
+    }
+
+    #[test]
+    fn test_login_sighting_device() {
+        let firefox = |version: &str| {
+            UserAgent::parse(format!(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:{version}) Gecko/20100101 Firefox/{version}"
+            ))
+        };
+
+        // Browser updates are not a new device
+        let device = LoginSighting::device_of(&firefox("127.0"));
+        assert_eq!(device, LoginSighting::device_of(&firefox("128.0")));
+        assert!(device.starts_with("Firefox on Linux"));
+
+        // User agents which can't be parsed are used as-is
+        let unknown = UserAgent::parse("some-custom-client".to_owned());
+        assert_eq!(LoginSighting::device_of(&unknown), "some-custom-client");
+    }
+}
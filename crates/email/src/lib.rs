@@ -12,19 +12,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Helps sending emails to users, with different email backends
+//! Helps sending emails and text messages to users, with different backends,
+//! and posting notifications to webhooks
 
 #![deny(missing_docs)]
 
 mod mailer;
+mod mailgun;
+mod sms;
 mod transport;
+mod webhook;
 
 pub use lettre::{
-    message::Mailbox, transport::smtp::authentication::Credentials as SmtpCredentials, Address,
+    address::Envelope,
+    message::Mailbox,
+    transport::smtp::{
+        authentication::Credentials as SmtpCredentials, PoolConfig as SmtpPoolConfig,
+    },
+    Address, Message,
 };
 pub use mas_templates::EmailVerificationContext;
 
 pub use self::{
     mailer::Mailer,
+    mailgun::DEFAULT_ENDPOINT as MAILGUN_DEFAULT_ENDPOINT,
+    sms::{SmsSender, SmsTransport},
     transport::{SmtpMode, Transport as MailTransport},
+    webhook::Webhook,
 };
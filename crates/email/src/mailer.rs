@@ -15,10 +15,16 @@
 //! Send emails to users
 
 use lettre::{
-    message::{Mailbox, MessageBuilder, MultiPart},
+    address::Envelope,
+    message::{Mailbox, MessageBuilder, MultiPart, SinglePart},
     AsyncTransport, Message,
 };
-use mas_templates::{EmailRecoveryContext, EmailVerificationContext, Templates, WithLanguage};
+use mas_templates::{
+    EmailBackchannelAuthenticationContext, EmailChangeNotificationContext, EmailLoginAlertContext,
+    EmailLoginCodeContext, EmailMagicLinkContext, EmailPasswordChangeNotificationContext,
+    EmailRecoveryContext, EmailUserExportContext, EmailVerificationContext, Templates,
+    WithLanguage,
+};
 use thiserror::Error;
 
 use crate::MailTransport;
@@ -63,7 +69,23 @@ impl Mailer {
             .reply_to(self.reply_to.clone())
     }
 
-    fn prepare_verification_email(
+    /// Render the verification email for a user, ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.verification.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_email_verification.id = %context.verification().id,
+        ),
+        err,
+    )]
+    pub fn prepare_verification_email(
         &self,
         to: Mailbox,
         context: &WithLanguage<EmailVerificationContext>,
@@ -85,7 +107,23 @@ impl Mailer {
         Ok(message)
     }
 
-    fn prepare_recovery_email(
+    /// Render the recovery email for a user, ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.recovery.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_recovery_session.id = %context.session().id,
+        ),
+        err,
+    )]
+    pub fn prepare_recovery_email(
         &self,
         to: Mailbox,
         context: &WithLanguage<EmailRecoveryContext>,
@@ -107,55 +145,328 @@ impl Mailer {
         Ok(message)
     }
 
-    /// Send the verification email to a user
+    /// Render the magic link email for a user, ready to be queued
     ///
     /// # Errors
     ///
-    /// Will return `Err` if the email failed rendering or failed sending
+    /// Will return `Err` if the email failed rendering
     #[tracing::instrument(
-        name = "email.verification.send",
+        name = "email.magic_link.prepare",
         skip_all,
         fields(
             email.to = %to,
             email.language = %context.language(),
             user.id = %context.user().id,
-            user_email_verification.id = %context.verification().id,
-            user_email_verification.code = context.verification().code,
+            user_magic_link_session.id = %context.session().id,
         ),
         err,
     )]
-    pub async fn send_verification_email(
+    pub fn prepare_magic_link_email(
         &self,
         to: Mailbox,
-        context: &WithLanguage<EmailVerificationContext>,
-    ) -> Result<(), Error> {
-        let message = self.prepare_verification_email(to, context)?;
-        self.transport.send(message).await?;
-        Ok(())
+        context: &WithLanguage<EmailMagicLinkContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_magic_link_txt(context)?;
+
+        let html = self.templates.render_email_magic_link_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_magic_link_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
     }
 
-    /// Send the recovery email to a user
+    /// Render the login alert email for a user, ready to be queued
     ///
     /// # Errors
     ///
-    /// Will return `Err` if the email failed rendering or failed sending
+    /// Will return `Err` if the email failed rendering
     #[tracing::instrument(
-        name = "email.recovery.send",
+        name = "email.login_alert.prepare",
         skip_all,
         fields(
             email.to = %to,
             email.language = %context.language(),
             user.id = %context.user().id,
-            user_recovery_session.id = %context.session().id,
+            user_login_alert.id = %context.alert().id,
         ),
         err,
     )]
-    pub async fn send_recovery_email(
+    pub fn prepare_login_alert_email(
         &self,
         to: Mailbox,
-        context: &WithLanguage<EmailRecoveryContext>,
-    ) -> Result<(), Error> {
-        let message = self.prepare_recovery_email(to, context)?;
+        context: &WithLanguage<EmailLoginAlertContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_login_alert_txt(context)?;
+
+        let html = self.templates.render_email_login_alert_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_login_alert_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Render the email with the code to confirm a login, ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.login_code.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_login_code.id = %context.code().id,
+        ),
+        err,
+    )]
+    pub fn prepare_login_code_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailLoginCodeContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_login_code_txt(context)?;
+
+        let html = self.templates.render_email_login_code_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_login_code_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Render the email asking a user to review a backchannel authentication
+    /// request, ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.backchannel_authentication.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            oauth2_backchannel_authentication_grant.id = %context.grant().id,
+        ),
+        err,
+    )]
+    pub fn prepare_backchannel_authentication_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailBackchannelAuthenticationContext>,
+    ) -> Result<Message, Error> {
+        let plain = self
+            .templates
+            .render_email_backchannel_authentication_txt(context)?;
+
+        let html = self
+            .templates
+            .render_email_backchannel_authentication_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self
+            .templates
+            .render_email_backchannel_authentication_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Render the email notifying the previous address of a user that their
+    /// primary email address changed, ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.email_change.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_email_change.id = %context.change().id,
+        ),
+        err,
+    )]
+    pub fn prepare_email_change_notification_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailChangeNotificationContext>,
+    ) -> Result<Message, Error> {
+        let plain = self
+            .templates
+            .render_email_change_notification_txt(context)?;
+
+        let html = self
+            .templates
+            .render_email_change_notification_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self
+            .templates
+            .render_email_change_notification_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Render the email notifying a user that their password changed, ready
+    /// to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.password_change.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+        err,
+    )]
+    pub fn prepare_password_change_notification_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailPasswordChangeNotificationContext>,
+    ) -> Result<Message, Error> {
+        let plain = self
+            .templates
+            .render_email_password_change_notification_txt(context)?;
+
+        let html = self
+            .templates
+            .render_email_password_change_notification_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self
+            .templates
+            .render_email_password_change_notification_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Render the email telling a user that the export of their data is ready,
+    /// ready to be queued
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering
+    #[tracing::instrument(
+        name = "email.user_export.prepare",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_export.id = %context.export().id,
+        ),
+        err,
+    )]
+    pub fn prepare_user_export_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailUserExportContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_user_export_txt(context)?;
+
+        let html = self.templates.render_email_user_export_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_user_export_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send an email which was already rendered and formatted
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed sending
+    #[tracing::instrument(
+        name = "email.send_raw",
+        skip_all,
+        fields(email.to = ?envelope.to()),
+        err,
+    )]
+    pub async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<(), Error> {
+        self.transport.send_raw(envelope, email).await?;
+        Ok(())
+    }
+
+    /// Send a plain test email, to check that emails get delivered
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed sending
+    #[tracing::instrument(
+        name = "email.test.send",
+        skip_all,
+        fields(email.to = %to),
+        err,
+    )]
+    pub async fn send_test_email(&self, to: Mailbox) -> Result<(), Error> {
+        let message = self
+            .base_message()
+            .subject("Test email")
+            .to(to)
+            .singlepart(SinglePart::plain(
+                "This is a test email sent by the Matrix Authentication Service.".to_owned(),
+            ))?;
         self.transport.send(message).await?;
         Ok(())
     }
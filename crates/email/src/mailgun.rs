@@ -0,0 +1,130 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mailgun HTTP API transport
+
+use bytes::{BufMut, Bytes, BytesMut};
+use headers::{Authorization, HeaderMapExt};
+use http::{header::CONTENT_TYPE, HeaderValue};
+use lettre::address::Envelope;
+use mas_http::HttpService;
+use thiserror::Error;
+use tower::{BoxError, ServiceExt};
+use url::Url;
+
+/// The default Mailgun API endpoint, in the US region
+pub const DEFAULT_ENDPOINT: &str = "https://api.mailgun.net/";
+
+const BOUNDARY: &str = "mas-mailgun-boundary-f3a9b8d1c6e2";
+const BODY_CONTENT_TYPE: &str = "multipart/form-data; boundary=mas-mailgun-boundary-f3a9b8d1c6e2";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid Mailgun API endpoint")]
+    InvalidEndpoint(#[from] url::ParseError),
+
+    #[error("failed to build the request to the Mailgun API")]
+    Request(#[from] http::Error),
+
+    #[error("the email contains the multipart boundary")]
+    BoundaryCollision,
+
+    #[error("failed to call the Mailgun API")]
+    Service(#[source] BoxError),
+
+    #[error("the Mailgun API returned an error: {status}")]
+    Status { status: http::StatusCode },
+}
+
+/// Sends emails through the `messages.mime` endpoint of the Mailgun API
+#[derive(Clone)]
+pub struct MailgunTransport {
+    http_service: HttpService,
+    url: Url,
+    api_key: String,
+}
+
+impl MailgunTransport {
+    /// Construct a new Mailgun transport sending emails for the given domain
+    pub fn new(
+        http_service: HttpService,
+        endpoint: &Url,
+        domain: &str,
+        api_key: String,
+    ) -> Result<Self, Error> {
+        let url = endpoint.join(&format!("v3/{domain}/messages.mime"))?;
+
+        Ok(Self {
+            http_service,
+            url,
+            api_key,
+        })
+    }
+
+    /// Build the `multipart/form-data` body expected by the API
+    fn body(envelope: &Envelope, email: &[u8]) -> Result<Bytes, Error> {
+        if email
+            .windows(BOUNDARY.len())
+            .any(|window| window == BOUNDARY.as_bytes())
+        {
+            return Err(Error::BoundaryCollision);
+        }
+
+        let mut body = BytesMut::new();
+        for to in envelope.to() {
+            body.put_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"to\"\r\n\r\n{to}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+
+        body.put_slice(
+            format!(
+                "--{BOUNDARY}\r\n\
+                 Content-Disposition: form-data; name=\"message\"; filename=\"message.mime\"\r\n\
+                 Content-Type: message/rfc822\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.put_slice(email);
+        body.put_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+        Ok(body.freeze())
+    }
+
+    pub(crate) async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<(), Error> {
+        let body = Self::body(envelope, email)?;
+
+        let mut request = http::Request::post(self.url.as_str()).body(body)?;
+        let headers = request.headers_mut();
+        headers.typed_insert(Authorization::basic("api", &self.api_key));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(BODY_CONTENT_TYPE));
+
+        let response = self
+            .http_service
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(Error::Service)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status { status });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Send text messages (SMS) to users through an HTTP gateway
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use headers::{Authorization, HeaderMapExt};
+use http::{header::CONTENT_TYPE, HeaderValue};
+use mas_http::HttpService;
+use mas_templates::{PhoneVerificationContext, Templates, WithLanguage};
+use thiserror::Error;
+use tower::{BoxError, ServiceExt};
+use url::{form_urlencoded, Url};
+
+/// The base URL of the Twilio API
+const TWILIO_ENDPOINT: &str = "https://api.twilio.com/";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid gateway URL")]
+    InvalidUrl(#[from] url::ParseError),
+
+    #[error("invalid bearer token for the SMS gateway")]
+    InvalidToken,
+
+    #[error("failed to build the request to the SMS gateway")]
+    Request(#[from] http::Error),
+
+    #[error("failed to call the SMS gateway")]
+    Service(#[source] BoxError),
+
+    #[error("the SMS gateway returned an error: {status}")]
+    Status { status: http::StatusCode },
+
+    #[error(transparent)]
+    Templates(#[from] mas_templates::TemplateError),
+}
+
+enum Gateway {
+    Blackhole,
+    Twilio {
+        http_service: HttpService,
+        url: Url,
+        account_sid: String,
+        auth_token: String,
+        from: String,
+    },
+    Http {
+        http_service: HttpService,
+        url: Url,
+        token: Option<String>,
+    },
+}
+
+/// A gateway to send text messages through
+#[derive(Clone)]
+pub struct SmsTransport {
+    inner: Arc<Gateway>,
+}
+
+impl SmsTransport {
+    fn new(inner: Gateway) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Construct a transport which drops all the messages
+    #[must_use]
+    pub fn blackhole() -> Self {
+        Self::new(Gateway::Blackhole)
+    }
+
+    /// Construct a transport sending messages through the Twilio Messaging
+    /// API
+    ///
+    /// # Parameters
+    ///
+    /// * `http_service`: The HTTP service to use to call the API
+    /// * `account_sid`: The SID of the Twilio account
+    /// * `auth_token`: The auth token of the Twilio account
+    /// * `from`: The phone number or messaging service SID to send from
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API URL could not be built
+    pub fn twilio(
+        http_service: HttpService,
+        account_sid: String,
+        auth_token: String,
+        from: String,
+    ) -> Result<Self, Error> {
+        let url = Url::parse(TWILIO_ENDPOINT)?
+            .join(&format!("2010-04-01/Accounts/{account_sid}/Messages.json"))?;
+
+        Ok(Self::new(Gateway::Twilio {
+            http_service,
+            url,
+            account_sid,
+            auth_token,
+            from,
+        }))
+    }
+
+    /// Construct a transport posting the messages to a generic HTTP endpoint
+    ///
+    /// The endpoint receives a `application/x-www-form-urlencoded` body with
+    /// the `to` and `body` fields, and must reply with a 2xx status code.
+    ///
+    /// # Parameters
+    ///
+    /// * `http_service`: The HTTP service to use to call the endpoint
+    /// * `url`: The URL of the endpoint
+    /// * `token`: An optional bearer token to authenticate with
+    #[must_use]
+    pub fn http(http_service: HttpService, url: Url, token: Option<String>) -> Self {
+        Self::new(Gateway::Http {
+            http_service,
+            url,
+            token,
+        })
+    }
+
+    /// Get a short name for the kind of transport, used in traces
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self.inner.as_ref() {
+            Gateway::Blackhole => "blackhole",
+            Gateway::Twilio { .. } => "twilio",
+            Gateway::Http { .. } => "http",
+        }
+    }
+
+    /// Send a text message to a phone number
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the gateway could not be called or replied with an
+    /// error
+    #[tracing::instrument(
+        name = "sms.transport.send",
+        skip_all,
+        fields(
+            "otel.kind" = "client",
+            sms.transport = self.kind(),
+        ),
+        err,
+    )]
+    pub async fn send(&self, to: &str, body: &str) -> Result<(), Error> {
+        let (http_service, mut request) = match self.inner.as_ref() {
+            Gateway::Blackhole => {
+                tracing::warn!(
+                    "A text message was supposed to be sent but no SMS gateway is configured"
+                );
+                return Ok(());
+            }
+
+            Gateway::Twilio {
+                http_service,
+                url,
+                account_sid,
+                auth_token,
+                from,
+            } => {
+                let form = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("To", to)
+                    .append_pair("From", from)
+                    .append_pair("Body", body)
+                    .finish();
+                let mut request = http::Request::post(url.as_str()).body(Bytes::from(form))?;
+                request
+                    .headers_mut()
+                    .typed_insert(Authorization::basic(account_sid, auth_token));
+                (http_service, request)
+            }
+
+            Gateway::Http {
+                http_service,
+                url,
+                token,
+            } => {
+                let form = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("to", to)
+                    .append_pair("body", body)
+                    .finish();
+                let mut request = http::Request::post(url.as_str()).body(Bytes::from(form))?;
+                if let Some(token) = token {
+                    let authorization =
+                        Authorization::bearer(token).map_err(|_| Error::InvalidToken)?;
+                    request.headers_mut().typed_insert(authorization);
+                }
+                (http_service, request)
+            }
+        };
+
+        request.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+
+        let response = http_service
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(Error::Service)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status { status });
+        }
+
+        Ok(())
+    }
+}
+
+/// Helps sending text messages to users
+#[derive(Clone)]
+pub struct SmsSender {
+    templates: Templates,
+    transport: SmsTransport,
+}
+
+impl SmsSender {
+    /// Constructs a new [`SmsSender`]
+    #[must_use]
+    pub fn new(templates: Templates, transport: SmsTransport) -> Self {
+        Self {
+            templates,
+            transport,
+        }
+    }
+
+    /// Send the verification code to a phone number
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the message failed rendering or sending
+    #[tracing::instrument(
+        name = "sms.verification.send",
+        skip_all,
+        fields(
+            sms.language = %context.language(),
+            user.id = %context.user().id,
+            user_phone_verification.id = %context.verification().id,
+        ),
+        err,
+    )]
+    pub async fn send_verification(
+        &self,
+        to: &str,
+        context: &WithLanguage<PhoneVerificationContext>,
+    ) -> Result<(), Error> {
+        let body = self.templates.render_sms_verification(context)?;
+        self.transport.send(to, body.trim()).await
+    }
+}
@@ -21,11 +21,15 @@ use lettre::{
     address::Envelope,
     transport::{
         sendmail::AsyncSendmailTransport,
-        smtp::{authentication::Credentials, AsyncSmtpTransport},
+        smtp::{authentication::Credentials, AsyncSmtpTransport, PoolConfig},
     },
     AsyncTransport, Tokio1Executor,
 };
+use mas_http::HttpService;
 use thiserror::Error;
+use url::Url;
+
+use crate::mailgun::{self, MailgunTransport};
 
 /// Encryption mode to use
 #[derive(Debug, Clone, Copy)]
@@ -48,6 +52,7 @@ enum TransportInner {
     Blackhole,
     Smtp(AsyncSmtpTransport<Tokio1Executor>),
     Sendmail(AsyncSendmailTransport<Tokio1Executor>),
+    Mailgun(MailgunTransport),
 }
 
 impl Transport {
@@ -64,6 +69,9 @@ impl Transport {
 
     /// Construct a SMTP transport
     ///
+    /// Connections to the SMTP server are pooled and reused across emails,
+    /// according to the given [`PoolConfig`]
+    ///
     /// # Errors
     ///
     /// Returns an error if the underlying SMTP transport could not be built
@@ -72,6 +80,7 @@ impl Transport {
         hostname: &str,
         port: Option<NonZeroU16>,
         credentials: Option<Credentials>,
+        pool_config: PoolConfig,
     ) -> Result<Self, lettre::transport::smtp::Error> {
         let mut t = match mode {
             SmtpMode::Plain => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(hostname),
@@ -87,6 +96,8 @@ impl Transport {
             t = t.port(port.into());
         }
 
+        t = t.pool_config(pool_config);
+
         Ok(Self::new(TransportInner::Smtp(t.build())))
     }
 
@@ -100,11 +111,46 @@ impl Transport {
         };
         Self::new(TransportInner::Sendmail(transport))
     }
+
+    /// Construct a transport sending emails through the Mailgun HTTP API
+    ///
+    /// # Parameters
+    ///
+    /// * `http_service`: The HTTP service to use to call the API
+    /// * `endpoint`: The base URL of the API, which depends on the region of
+    ///   the Mailgun account
+    /// * `domain`: The domain configured in Mailgun to send the emails from
+    /// * `api_key`: The API key to authenticate with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API URL could not be built
+    pub fn mailgun(
+        http_service: HttpService,
+        endpoint: &Url,
+        domain: &str,
+        api_key: String,
+    ) -> Result<Self, mailgun::Error> {
+        let transport = MailgunTransport::new(http_service, endpoint, domain, api_key)?;
+        Ok(Self::new(TransportInner::Mailgun(transport)))
+    }
 }
 
 impl Transport {
+    /// Get a short name for the kind of transport, used in traces
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self.inner.as_ref() {
+            TransportInner::Blackhole => "blackhole",
+            TransportInner::Smtp(_) => "smtp",
+            TransportInner::Sendmail(_) => "sendmail",
+            TransportInner::Mailgun(_) => "mailgun",
+        }
+    }
+
     /// Test the connection to the underlying transport. Only works with the
-    /// SMTP backend for now
+    /// SMTP backend for now, use [`crate::Mailer::send_test_email`] to check
+    /// the other backends
     ///
     /// # Errors
     ///
@@ -114,7 +160,9 @@ impl Transport {
             TransportInner::Smtp(t) => {
                 t.test_connection().await?;
             }
-            TransportInner::Blackhole | TransportInner::Sendmail(_) => {}
+            TransportInner::Blackhole
+            | TransportInner::Sendmail(_)
+            | TransportInner::Mailgun(_) => {}
         }
 
         Ok(())
@@ -132,6 +180,7 @@ impl Default for TransportInner {
 pub enum Error {
     Smtp(#[from] lettre::transport::smtp::Error),
     Sendmail(#[from] lettre::transport::sendmail::Error),
+    Mailgun(#[from] mailgun::Error),
 }
 
 #[async_trait]
@@ -139,6 +188,16 @@ impl AsyncTransport for Transport {
     type Ok = ();
     type Error = Error;
 
+    #[tracing::instrument(
+        name = "email.transport.send",
+        skip_all,
+        fields(
+            "otel.kind" = "client",
+            email.transport = self.kind(),
+            email.size = email.len(),
+        ),
+        err,
+    )]
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         match self.inner.as_ref() {
             TransportInner::Blackhole => {
@@ -152,6 +211,9 @@ impl AsyncTransport for Transport {
             TransportInner::Sendmail(t) => {
                 t.send_raw(envelope, email).await?;
             }
+            TransportInner::Mailgun(t) => {
+                t.send_raw(envelope, email).await?;
+            }
         };
 
         Ok(())
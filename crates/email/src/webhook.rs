@@ -0,0 +1,115 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Post JSON-encoded events to an HTTP webhook
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use headers::{Authorization, HeaderMapExt};
+use http::{header::CONTENT_TYPE, HeaderValue};
+use mas_http::HttpService;
+use thiserror::Error;
+use tower::{BoxError, ServiceExt};
+use url::Url;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid bearer token for the webhook")]
+    InvalidToken,
+
+    #[error("failed to build the request to the webhook")]
+    Request(#[from] http::Error),
+
+    #[error("failed to call the webhook")]
+    Service(#[source] BoxError),
+
+    #[error("the webhook returned an error: {status}")]
+    Status { status: http::StatusCode },
+}
+
+struct Inner {
+    http_service: HttpService,
+    url: Url,
+    token: Option<String>,
+}
+
+/// A webhook to post JSON-encoded events to
+#[derive(Clone)]
+pub struct Webhook {
+    inner: Arc<Inner>,
+}
+
+impl Webhook {
+    /// Construct a webhook posting to the given endpoint
+    ///
+    /// # Parameters
+    ///
+    /// * `http_service`: The HTTP service to use to call the endpoint
+    /// * `url`: The URL of the endpoint
+    /// * `token`: An optional bearer token to authenticate with
+    #[must_use]
+    pub fn new(http_service: HttpService, url: Url, token: Option<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                http_service,
+                url,
+                token,
+            }),
+        }
+    }
+
+    /// Post a JSON-encoded body to the webhook
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the endpoint could not be called or replied with an
+    /// error
+    #[tracing::instrument(
+        name = "webhook.send",
+        skip_all,
+        fields(
+            "otel.kind" = "client",
+            url.full = %self.inner.url,
+        ),
+        err,
+    )]
+    pub async fn send(&self, body: Vec<u8>) -> Result<(), Error> {
+        let mut request = http::Request::post(self.inner.url.as_str()).body(Bytes::from(body))?;
+
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        if let Some(token) = &self.inner.token {
+            let authorization = Authorization::bearer(token).map_err(|_| Error::InvalidToken)?;
+            request.headers_mut().typed_insert(authorization);
+        }
+
+        let response = self
+            .inner
+            .http_service
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(Error::Service)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status { status });
+        }
+
+        Ok(())
+    }
+}
@@ -25,6 +25,7 @@ use ulid::Ulid;
 
 pub use self::bound::Bound;
 use self::worker::Worker;
+use crate::introspection_cache::CachedSession;
 
 static MESSAGE_QUEUE_SIZE: usize = 1000;
 
@@ -126,6 +127,34 @@ impl ActivityTracker {
         }
     }
 
+    /// Record activity in a session for which the introspection result was
+    /// cached.
+    pub(crate) async fn record_cached_session(
+        &self,
+        clock: &dyn Clock,
+        session: CachedSession,
+        ip: Option<IpAddr>,
+    ) {
+        let (kind, id) = match session {
+            CachedSession::OAuth2(id) => (SessionKind::OAuth2, id),
+            CachedSession::Compat(id) => (SessionKind::Compat, id),
+        };
+
+        let res = self
+            .channel
+            .send(Message::Record {
+                kind,
+                id,
+                date_time: clock.now(),
+                ip,
+            })
+            .await;
+
+        if let Err(e) = res {
+            tracing::error!("Failed to record cached session: {}", e);
+        }
+    }
+
     /// Record activity in a browser session.
     pub async fn record_browser_session(
         &self,
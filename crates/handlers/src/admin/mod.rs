@@ -0,0 +1,241 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-only administration pages, to browse the users, their sessions and
+//! the registered clients.
+//!
+//! Those pages are not authenticated: they are only reachable from the
+//! networks listed in the `http.access_control.admin` configuration, and
+//! refuse every request if none are.
+
+use axum::{
+    extract::{OriginalUri, Path, Query, State},
+    response::{Html, IntoResponse, Response},
+};
+use hyper::{Method, StatusCode, Version};
+use mas_axum_utils::FancyError;
+use mas_router::{AdminUsers, UrlBuilder};
+use mas_storage::{
+    compat::CompatSessionFilter,
+    oauth2::OAuth2SessionFilter,
+    user::{BrowserSessionFilter, UserEmailFilter, UserFilter},
+    BoxRepository, Pagination,
+};
+use mas_templates::{
+    AdminClientsContext, AdminUserContext, AdminUsersContext, NotFoundContext, TemplateContext,
+    Templates,
+};
+use ulid::Ulid;
+
+use crate::PreferredLanguage;
+
+pub(crate) mod stats;
+
+/// How many users are shown on each page of the list
+const USERS_PER_PAGE: usize = 50;
+
+/// How many of the latest sessions of each kind are shown for a user
+const SESSIONS_PER_KIND: usize = 20;
+
+#[tracing::instrument(name = "handlers.admin.users", skip_all, err)]
+pub(crate) async fn users(
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    Query(query): Query<AdminUsers>,
+) -> Result<Response, FancyError> {
+    let mut filter = UserFilter::new();
+    if let Some(search) = query.search() {
+        filter = filter.matching(search);
+    }
+
+    let mut pagination = Pagination::first(USERS_PER_PAGE);
+    if let Some(cursor) = query.cursor() {
+        pagination = pagination.after(cursor);
+    }
+
+    let page = repo.user().list(filter, pagination).await?;
+    let total = repo.user().count(filter).await?;
+
+    let next_page = page
+        .edges
+        .last()
+        .filter(|_| page.has_next_page)
+        .map(|last| {
+            let route = query
+                .search()
+                .map_or_else(AdminUsers::default, |search| {
+                    AdminUsers::with_search(search.to_owned())
+                })
+                .after(last.id);
+            url_builder.relative_url_for(&route)
+        });
+
+    let mut context = AdminUsersContext::new(page.edges, total);
+    if let Some(search) = query.search() {
+        context = context.with_search(search.to_owned());
+    }
+    if let Some(next_page) = next_page {
+        context = context.with_next_page(next_page);
+    }
+
+    let rendered = templates.render_admin_users(&context.with_language(locale))?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[tracing::instrument(name = "handlers.admin.user", fields(user.id = %user_id), skip_all, err)]
+pub(crate) async fn user(
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    OriginalUri(uri): OriginalUri,
+    method: Method,
+    version: Version,
+    Path(user_id): Path<Ulid>,
+) -> Result<Response, FancyError> {
+    let Some(user) = repo.user().lookup(user_id).await? else {
+        let context = NotFoundContext::new(&method, version, &uri).with_language(locale);
+        let rendered = templates.render_not_found(&context)?;
+        return Ok((StatusCode::NOT_FOUND, Html(rendered)).into_response());
+    };
+
+    let emails = repo
+        .user_email()
+        .list(
+            UserEmailFilter::new().for_user(&user),
+            Pagination::first(SESSIONS_PER_KIND),
+        )
+        .await?
+        .edges;
+
+    // Show the latest sessions first
+    let pagination = Pagination::last(SESSIONS_PER_KIND);
+    let browser_sessions = repo
+        .browser_session()
+        .list(BrowserSessionFilter::new().for_user(&user), pagination)
+        .await?
+        .edges
+        .into_iter()
+        .rev()
+        .collect();
+    let oauth2_sessions = repo
+        .oauth2_session()
+        .list(OAuth2SessionFilter::new().for_user(&user), pagination)
+        .await?
+        .edges
+        .into_iter()
+        .rev()
+        .collect();
+    let compat_sessions = repo
+        .compat_session()
+        .list(CompatSessionFilter::new().for_user(&user), pagination)
+        .await?
+        .edges
+        .into_iter()
+        .rev()
+        .map(|(session, _sso_login)| session)
+        .collect();
+
+    let context = AdminUserContext::new(user)
+        .with_emails(emails)
+        .with_sessions(browser_sessions, oauth2_sessions, compat_sessions)
+        .with_language(locale);
+
+    let rendered = templates.render_admin_user(&context)?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[tracing::instrument(name = "handlers.admin.clients", skip_all, err)]
+pub(crate) async fn clients(
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+) -> Result<Response, FancyError> {
+    let clients = repo.oauth2_client().all().await?;
+
+    let context = AdminClientsContext::new(clients).with_language(locale);
+    let rendered = templates.render_admin_clients(&context)?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_router::{AdminClients, AdminUser, AdminUsers, Route, SimpleRoute};
+    use mas_storage::{
+        user::{BrowserSessionRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_admin_pages(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Both users are listed
+        let request = Request::get(AdminUsers::default().path_and_query().as_ref()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("alice"));
+        assert!(response.body().contains("bob"));
+
+        // Only the matching user is listed when searching
+        let route = AdminUsers::with_search("ALI".to_owned());
+        let request = Request::get(route.path_and_query().as_ref()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("alice"));
+        assert!(!response.body().contains("bob"));
+
+        // The details of the user are shown
+        let request = Request::get(AdminUser::new(alice.id).path_and_query().as_ref()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("alice"));
+
+        // Unknown users give a 404
+        let request = Request::get(AdminUser::new(Ulid::nil()).path_and_query().as_ref()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        let request = Request::get(AdminClients::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+}
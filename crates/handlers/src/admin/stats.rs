@@ -0,0 +1,173 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON endpoints exposing aggregated statistics, e.g. to feed dashboards
+//!
+//! The statistics are computed periodically by a background job, so they lag
+//! behind the actual data.
+
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Duration, NaiveDate};
+use hyper::StatusCode;
+use mas_axum_utils::sentry::SentryEventID;
+use mas_data_model::{ClientDailyCount, DailyCount};
+use mas_storage::{BoxClock, BoxRepository, Clock};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::impl_from_error_for_route;
+
+/// How many days of statistics are returned by default
+const DEFAULT_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+pub(crate) struct StatsQuery {
+    /// The first day to include, in UTC
+    since: Option<NaiveDate>,
+}
+
+impl StatsQuery {
+    fn since(&self, clock: &dyn Clock) -> NaiveDate {
+        self.since
+            .unwrap_or_else(|| (clock.now() - Duration::days(DEFAULT_DAYS)).date_naive())
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        let event_id = sentry::capture_error(&self);
+        let body = serde_json::json!({
+            "error": "server_error",
+            "error_description": self.to_string(),
+        });
+
+        (
+            SentryEventID::from(event_id),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+#[tracing::instrument(name = "handlers.admin.stats.authentications", skip_all, err)]
+pub(crate) async fn authentications(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Vec<DailyCount>>, RouteError> {
+    let counts = repo
+        .stats()
+        .daily_authentications(query.since(&clock))
+        .await?;
+    Ok(Json(counts))
+}
+
+#[tracing::instrument(name = "handlers.admin.stats.registrations", skip_all, err)]
+pub(crate) async fn registrations(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Vec<DailyCount>>, RouteError> {
+    let counts = repo
+        .stats()
+        .daily_registrations(query.since(&clock))
+        .await?;
+    Ok(Json(counts))
+}
+
+#[tracing::instrument(name = "handlers.admin.stats.tokens", skip_all, err)]
+pub(crate) async fn tokens(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Vec<ClientDailyCount>>, RouteError> {
+    let counts = repo
+        .stats()
+        .daily_oauth2_tokens(query.since(&clock))
+        .await?;
+    Ok(Json(counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::DailyCount;
+    use mas_router::SimpleRoute;
+    use mas_storage::{stats::StatsRepository, user::UserRepository, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_stats(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // Nothing happened yet
+        let request = Request::get(mas_router::AdminStatsRegistrations::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let counts: Vec<DailyCount> = response.json();
+        assert!(counts.is_empty());
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.stats().refresh().await.unwrap();
+        repo.save().await.unwrap();
+
+        // The registration is now counted
+        let request = Request::get(mas_router::AdminStatsRegistrations::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let counts: Vec<DailyCount> = response.json();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].count, 1);
+
+        // Days before the requested range are left out
+        let request = Request::get(format!(
+            "{}?since=2999-01-01",
+            mas_router::AdminStatsRegistrations::PATH
+        ))
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let counts: Vec<DailyCount> = response.json();
+        assert!(counts.is_empty());
+
+        let request = Request::get(mas_router::AdminStatsAuthentications::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let request = Request::get(mas_router::AdminStatsTokens::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+}
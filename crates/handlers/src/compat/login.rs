@@ -30,15 +30,19 @@ use mas_storage::{
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use rand::{CryptoRng, RngCore};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none, DurationMilliSeconds};
 use thiserror::Error;
 use zeroize::Zeroizing;
 
 use super::MatrixError;
-use crate::{impl_from_error_for_route, passwords::PasswordManager, BoundActivityTracker};
+use crate::{
+    impl_from_error_for_route, passwords::PasswordManager, rate_limit::RateLimited,
+    BoundActivityTracker, Limiter, NetworkBans,
+};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "type")]
 enum LoginType {
     #[serde(rename = "m.login.password")]
@@ -58,14 +62,14 @@ enum LoginType {
     },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct SsoIdentityProvider {
     id: &'static str,
     name: &'static str,
 }
 
-#[derive(Debug, Serialize)]
-struct LoginTypes {
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct LoginTypes {
     flows: Vec<LoginType>,
 }
 
@@ -95,7 +99,7 @@ pub(crate) async fn get(State(password_manager): State<PasswordManager>) -> impl
     Json(res)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RequestBody {
     #[serde(flatten)]
     credentials: Credentials,
@@ -104,7 +108,7 @@ pub struct RequestBody {
     refresh_token: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum Credentials {
     #[serde(rename = "m.login.password")]
@@ -117,28 +121,32 @@ pub enum Credentials {
     Token { token: String },
 
     #[serde(other)]
+    #[schemars(skip)]
     Unsupported,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum Identifier {
     #[serde(rename = "m.id.user")]
     User { user: String },
 
     #[serde(other)]
+    #[schemars(skip)]
     Unsupported,
 }
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ResponseBody {
     access_token: String,
+    #[schemars(with = "String")]
     device_id: Device,
     user_id: String,
     refresh_token: Option<String>,
     #[serde_as(as = "Option<DurationMilliSeconds<i64>>")]
+    #[schemars(with = "Option<i64>")]
     expires_in_ms: Option<Duration>,
 }
 
@@ -167,12 +175,29 @@ pub enum RouteError {
 
     #[error("invalid login token")]
     InvalidLoginToken,
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
+        // Being rate limited is not worth reporting to Sentry
+        if let Self::RateLimited(e) = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                e,
+                Json(serde_json::json!({
+                    "errcode": "M_LIMIT_EXCEEDED",
+                    "error": "Too many login attempts",
+                    "retry_after_ms": e.retry_after().num_milliseconds(),
+                })),
+            )
+                .into_response();
+        }
+
         let event_id = sentry::capture_error(&self);
         let response = match self {
             Self::Internal(_) | Self::SessionNotFound => MatrixError {
@@ -202,6 +227,7 @@ impl IntoResponse for RouteError {
                 error: "Invalid login token",
                 status: StatusCode::FORBIDDEN,
             },
+            Self::RateLimited(_) => unreachable!("handled above"),
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -217,6 +243,8 @@ pub(crate) async fn post(
     activity_tracker: BoundActivityTracker,
     State(homeserver): State<BoxHomeserverConnection>,
     State(site_config): State<SiteConfig>,
+    State(limiter): State<Limiter>,
+    State(network_bans): State<NetworkBans>,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     Json(input): Json<RequestBody>,
 ) -> Result<impl IntoResponse, RouteError> {
@@ -229,7 +257,11 @@ pub(crate) async fn post(
                 password,
             },
         ) => {
-            user_password_login(
+            limiter
+                .check_password(&clock, activity_tracker.ip(), &user)
+                .await?;
+
+            let res = user_password_login(
                 &mut rng,
                 &clock,
                 &password_manager,
@@ -237,7 +269,23 @@ pub(crate) async fn post(
                 user,
                 password,
             )
-            .await?
+            .await;
+
+            match res {
+                Ok(res) => res,
+                Err(
+                    e @ (RouteError::UserNotFound
+                    | RouteError::NoPassword
+                    | RouteError::PasswordVerificationFailed(_)),
+                ) => {
+                    network_bans
+                        .record_failure(&mut rng, &clock, &mut repo, activity_tracker.ip())
+                        .await?;
+                    repo.save().await?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
         }
 
         (_, Credentials::Token { token }) => token_login(&mut repo, &clock, &token).await?,
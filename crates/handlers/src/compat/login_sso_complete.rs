@@ -142,7 +142,9 @@ pub async fn post(
     Form(form): Form<ProtectedForm<()>>,
 ) -> Result<Response, FancyError> {
     let (session_info, cookie_jar) = cookie_jar.session_info();
-    cookie_jar.verify_form(&clock, form)?;
+    cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     let maybe_session = session_info.load_session(&mut repo).await?;
 
@@ -14,6 +14,7 @@
 
 use axum::{response::IntoResponse, Json};
 use hyper::StatusCode;
+use schemars::JsonSchema;
 use serde::Serialize;
 
 pub(crate) mod login;
@@ -21,9 +22,10 @@ pub(crate) mod login_sso_complete;
 pub(crate) mod login_sso_redirect;
 pub(crate) mod logout;
 pub(crate) mod refresh;
+pub(crate) mod register;
 
-#[derive(Debug, Serialize)]
-struct MatrixError {
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct MatrixError {
     errcode: &'static str,
     error: &'static str,
     #[serde(skip)]
@@ -19,16 +19,18 @@ use mas_axum_utils::sentry::SentryEventID;
 use mas_data_model::{SiteConfig, TokenFormatError, TokenType};
 use mas_storage::{
     compat::{CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository},
+    user::UserRepository,
     BoxClock, BoxRepository, BoxRng, Clock,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DurationMilliSeconds};
 use thiserror::Error;
 
 use super::MatrixError;
-use crate::{impl_from_error_for_route, BoundActivityTracker};
+use crate::{impl_from_error_for_route, BoundActivityTracker, IntrospectionCache};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct RequestBody {
     refresh_token: String,
 }
@@ -49,13 +51,19 @@ pub enum RouteError {
 
     #[error("unknown session")]
     UnknownSession,
+
+    #[error("unknown user")]
+    UnknownUser,
+
+    #[error("user is locked")]
+    UserLocked,
 }
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
         let event_id = sentry::capture_error(&self);
         let response = match self {
-            Self::Internal(_) | Self::UnknownSession => MatrixError {
+            Self::Internal(_) | Self::UnknownSession | Self::UnknownUser => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Internal error",
                 status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -65,6 +73,11 @@ impl IntoResponse for RouteError {
                 error: "Invalid refresh token",
                 status: StatusCode::UNAUTHORIZED,
             },
+            Self::UserLocked => MatrixError {
+                errcode: "M_USER_LOCKED",
+                error: "This account has been locked",
+                status: StatusCode::UNAUTHORIZED,
+            },
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -80,11 +93,12 @@ impl From<TokenFormatError> for RouteError {
 }
 
 #[serde_as]
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ResponseBody {
     access_token: String,
     refresh_token: String,
     #[serde_as(as = "DurationMilliSeconds<i64>")]
+    #[schemars(with = "i64")]
     expires_in_ms: Duration,
 }
 
@@ -95,6 +109,7 @@ pub(crate) async fn post(
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
     State(site_config): State<SiteConfig>,
+    State(introspection_cache): State<IntrospectionCache>,
     Json(input): Json<RequestBody>,
 ) -> Result<impl IntoResponse, RouteError> {
     let token_type = TokenType::check(&input.refresh_token)?;
@@ -123,6 +138,16 @@ pub(crate) async fn post(
         return Err(RouteError::InvalidSession);
     }
 
+    let user = repo
+        .user()
+        .lookup(session.user_id)
+        .await?
+        .ok_or(RouteError::UnknownUser)?;
+
+    if !user.is_valid() {
+        return Err(RouteError::UserLocked);
+    }
+
     activity_tracker
         .record_compat_session(&clock, &session)
         .await;
@@ -162,14 +187,22 @@ pub(crate) async fn post(
         .consume(&clock, refresh_token)
         .await?;
 
-    if let Some(access_token) = access_token {
-        repo.compat_access_token()
+    let expired_access_token = if let Some(access_token) = access_token {
+        let access_token = repo
+            .compat_access_token()
             .expire(&clock, access_token)
             .await?;
-    }
+        Some(access_token.token)
+    } else {
+        None
+    };
 
     repo.save().await?;
 
+    if let Some(token) = expired_access_token {
+        introspection_cache.invalidate_token(&token).await;
+    }
+
     Ok(Json(ResponseBody {
         access_token: new_access_token.token,
         refresh_token: new_refresh_token.token,
@@ -0,0 +1,672 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json, TypedHeader,
+};
+use chrono::Duration;
+use hyper::StatusCode;
+use mas_axum_utils::sentry::SentryEventID;
+use mas_data_model::{Device, SiteConfig, TokenType, User, UserAgent};
+use mas_matrix::BoxHomeserverConnection;
+use mas_policy::Policy;
+use mas_storage::{
+    compat::{CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository},
+    job::{JobRepositoryExt, ProvisionDeviceJob, ProvisionUserJob},
+    user::{UserPasswordRepository, UserRepository},
+    BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
+};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, DurationMilliSeconds};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::MatrixError;
+use crate::{
+    impl_from_error_for_route,
+    passwords::PasswordManager,
+    rate_limit::RateLimited,
+    spam_check::{SpamChecker, Verdict},
+    BoundActivityTracker, IntrospectionCache, Limiter,
+};
+
+/// The characters used in the generated guest usernames
+const GUEST_USERNAME_CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// How many times we try to find an available guest username
+const GUEST_USERNAME_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationKind {
+    #[default]
+    User,
+    Guest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Params {
+    #[serde(default)]
+    kind: RegistrationKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestBody {
+    #[serde(default)]
+    refresh_token: bool,
+
+    /// The username to register. When upgrading a guest account, this must
+    /// match the username of the guest.
+    username: Option<String>,
+
+    /// The password to set on the account when upgrading a guest account
+    password: Option<String>,
+
+    /// The access token of the guest account to upgrade
+    guest_access_token: Option<String>,
+}
+
+#[skip_serializing_none]
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseBody {
+    access_token: String,
+    device_id: Device,
+    user_id: String,
+    refresh_token: Option<String>,
+    #[serde_as(as = "Option<DurationMilliSeconds<i64>>")]
+    expires_in_ms: Option<Duration>,
+}
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("homeserver connection error")]
+    HomeserverConnection(#[source] anyhow::Error),
+
+    #[error("failed to hash the password")]
+    PasswordHash(#[source] anyhow::Error),
+
+    #[error("guest registration is disabled")]
+    GuestRegistrationDisabled,
+
+    #[error("user registration is not supported through the compatibility layer")]
+    UserRegistrationUnsupported,
+
+    #[error("password authentication is disabled")]
+    PasswordDisabled,
+
+    #[error("missing password")]
+    MissingPassword,
+
+    #[error("registration requires a CAPTCHA, which can't be completed through this endpoint")]
+    CaptchaRequired,
+
+    #[error("the password was rejected by the policy")]
+    WeakPassword,
+
+    #[error("the registration was rejected by the policy")]
+    PolicyViolation,
+
+    #[error("the registration was denied by the anti-abuse service")]
+    Denied,
+
+    #[error("invalid token format")]
+    TokenFormat(#[from] mas_data_model::TokenFormatError),
+
+    #[error("invalid guest access token")]
+    InvalidGuestToken,
+
+    #[error("user {0} is not a guest")]
+    NotAGuest(ulid::Ulid),
+
+    #[error("username does not match the guest account")]
+    UsernameMismatch,
+
+    #[error("could not find an available guest username")]
+    NoAvailableUsername,
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_policy::EvaluationError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        // Being rate limited is not worth reporting to Sentry
+        if let Self::RateLimited(e) = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                e,
+                Json(serde_json::json!({
+                    "errcode": "M_LIMIT_EXCEEDED",
+                    "error": "Too many registration attempts",
+                    "retry_after_ms": e.retry_after().num_milliseconds(),
+                })),
+            )
+                .into_response();
+        }
+
+        let event_id = sentry::capture_error(&self);
+        let response = match self {
+            Self::Internal(_)
+            | Self::HomeserverConnection(_)
+            | Self::PasswordHash(_)
+            | Self::NoAvailableUsername => MatrixError {
+                errcode: "M_UNKNOWN",
+                error: "Internal server error",
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::GuestRegistrationDisabled => MatrixError {
+                errcode: "M_GUEST_ACCESS_FORBIDDEN",
+                error: "Guest access is disabled",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::UserRegistrationUnsupported | Self::PasswordDisabled => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Registration is not enabled",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::CaptchaRequired => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Registration requires a CAPTCHA, use the web interface instead",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::WeakPassword => MatrixError {
+                errcode: "M_WEAK_PASSWORD",
+                error: "The password does not meet the requirements",
+                status: StatusCode::BAD_REQUEST,
+            },
+            Self::PolicyViolation | Self::Denied => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Registration was denied",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::MissingPassword => MatrixError {
+                errcode: "M_MISSING_PARAM",
+                error: "Missing password",
+                status: StatusCode::BAD_REQUEST,
+            },
+            Self::TokenFormat(_) | Self::InvalidGuestToken => MatrixError {
+                errcode: "M_UNKNOWN_TOKEN",
+                error: "Invalid guest access token",
+                status: StatusCode::UNAUTHORIZED,
+            },
+            Self::NotAGuest(_) => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "The account is not a guest account",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::UsernameMismatch => MatrixError {
+                errcode: "M_INVALID_USERNAME",
+                error: "The username does not match the guest account",
+                status: StatusCode::BAD_REQUEST,
+            },
+            Self::RateLimited(_) => unreachable!("handled above"),
+        };
+
+        (SentryEventID::from(event_id), response).into_response()
+    }
+}
+
+#[tracing::instrument(name = "handlers.compat.register.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    State(password_manager): State<PasswordManager>,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    State(homeserver): State<BoxHomeserverConnection>,
+    State(site_config): State<SiteConfig>,
+    State(limiter): State<Limiter>,
+    State(spam_checker): State<SpamChecker>,
+    State(introspection_cache): State<IntrospectionCache>,
+    mut policy: Policy,
+    Query(params): Query<Params>,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    Json(input): Json<RequestBody>,
+) -> Result<impl IntoResponse, RouteError> {
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    let (user, device) = match (params.kind, input.guest_access_token) {
+        (RegistrationKind::Guest, _) => {
+            if !site_config.guest_registration_allowed {
+                return Err(RouteError::GuestRegistrationDisabled);
+            }
+
+            limiter
+                .check_registration(&clock, activity_tracker.ip())
+                .await?;
+
+            let user = register_guest(&mut rng, &clock, &mut repo, &homeserver).await?;
+            let device = Device::generate(&mut rng);
+            repo.job()
+                .schedule_job(ProvisionDeviceJob::new(&user, &device))
+                .await?;
+
+            (user, device)
+        }
+
+        (RegistrationKind::User, Some(guest_access_token)) => {
+            // Upgrading a guest is a password registration, and gets the same checks
+            // as one done through the web interface
+            if !password_manager.is_enabled() || !site_config.password_registration_enabled {
+                return Err(RouteError::PasswordDisabled);
+            }
+
+            if site_config.captcha.is_some() {
+                return Err(RouteError::CaptchaRequired);
+            }
+
+            let password = input.password.ok_or(RouteError::MissingPassword)?;
+
+            let token_type = TokenType::check(&guest_access_token)?;
+            if token_type != TokenType::CompatAccessToken {
+                return Err(RouteError::InvalidGuestToken);
+            }
+
+            let token = repo
+                .compat_access_token()
+                .find_by_token(&guest_access_token)
+                .await?
+                .filter(|t| t.is_valid(clock.now()))
+                .ok_or(RouteError::InvalidGuestToken)?;
+
+            let session = repo
+                .compat_session()
+                .lookup(token.session_id)
+                .await?
+                .filter(|s| s.is_valid())
+                .ok_or(RouteError::InvalidGuestToken)?;
+
+            let user = repo
+                .user()
+                .lookup(session.user_id)
+                .await?
+                .filter(User::is_valid)
+                .ok_or(RouteError::InvalidGuestToken)?;
+
+            if !user.is_guest {
+                return Err(RouteError::NotAGuest(user.id));
+            }
+
+            if input
+                .username
+                .as_deref()
+                .is_some_and(|username| username != user.username)
+            {
+                return Err(RouteError::UsernameMismatch);
+            }
+
+            // No email is collected when upgrading a guest, so the violations on it are
+            // ignored
+            let res = policy
+                .evaluate_register(&user.username, &password, "")
+                .await?;
+            let mut violations = res
+                .violations
+                .into_iter()
+                .filter(|violation| violation.field.as_deref() != Some("email"))
+                .peekable();
+            if let Some(violation) = violations.peek() {
+                tracing::info!(
+                    %user.id,
+                    violation = %violation.msg,
+                    "Guest upgrade rejected by the policy"
+                );
+                if violations.any(|violation| violation.field.as_deref() == Some("password")) {
+                    return Err(RouteError::WeakPassword);
+                }
+                return Err(RouteError::PolicyViolation);
+            }
+
+            limiter
+                .check_registration(&clock, activity_tracker.ip())
+                .await?;
+
+            let verdict = spam_checker
+                .check_registration(
+                    &activity_tracker,
+                    user_agent.as_ref(),
+                    &user.username,
+                    None,
+                    "password",
+                )
+                .await;
+            let user = match verdict {
+                Verdict::Deny => return Err(RouteError::Denied),
+                Verdict::ShadowBan => {
+                    let user = repo.user().shadow_ban(&clock, user).await?;
+                    repo.job()
+                        .schedule_job(ProvisionUserJob::new(&user).shadow_ban())
+                        .await?;
+                    user
+                }
+                Verdict::Allow => user,
+            };
+
+            let password = Zeroizing::new(password.into_bytes());
+            let (version, hashed_password) = password_manager
+                .hash(&mut rng, password)
+                .await
+                .map_err(RouteError::PasswordHash)?;
+            repo.user_password()
+                .add(&mut rng, &clock, &user, version, hashed_password, None)
+                .await?;
+
+            let user = repo.user().set_guest(user, false).await?;
+
+            // The guest session is replaced by a new one on the same device, so that
+            // the old guest tokens stop working
+            let device = session.device.clone();
+            let session = repo.compat_session().finish(&clock, session).await?;
+            introspection_cache.invalidate_session(session.id).await;
+            introspection_cache.invalidate_user(user.id).await;
+
+            (user, device)
+        }
+
+        (RegistrationKind::User, None) => return Err(RouteError::UserRegistrationUnsupported),
+    };
+
+    let mut session = repo
+        .compat_session()
+        .add(&mut rng, &clock, &user, device, None, false)
+        .await?;
+
+    if let Some(user_agent) = user_agent {
+        session = repo
+            .compat_session()
+            .record_user_agent(session, user_agent)
+            .await?;
+    }
+
+    let user_id = homeserver.mxid(&user.username);
+
+    // If the client asked for a refreshable token, make it expire
+    let expires_in = if input.refresh_token {
+        Some(site_config.compat_token_ttl)
+    } else {
+        None
+    };
+
+    let access_token = TokenType::CompatAccessToken.generate(&mut rng);
+    let access_token = repo
+        .compat_access_token()
+        .add(&mut rng, &clock, &session, access_token, expires_in)
+        .await?;
+
+    let refresh_token = if input.refresh_token {
+        let refresh_token = TokenType::CompatRefreshToken.generate(&mut rng);
+        let refresh_token = repo
+            .compat_refresh_token()
+            .add(&mut rng, &clock, &session, &access_token, refresh_token)
+            .await?;
+        Some(refresh_token.token)
+    } else {
+        None
+    };
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_compat_session(&clock, &session)
+        .await;
+
+    Ok(Json(ResponseBody {
+        access_token: access_token.token,
+        device_id: session.device,
+        user_id,
+        refresh_token,
+        expires_in_ms: expires_in,
+    }))
+}
+
+/// Create a new guest account with a random username
+async fn register_guest(
+    rng: &mut (impl RngCore + Send),
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    homeserver: &BoxHomeserverConnection,
+) -> Result<User, RouteError> {
+    for _ in 0..GUEST_USERNAME_ATTEMPTS {
+        let suffix: String = (0..12)
+            .map(|_| {
+                let idx = rng.gen_range(0..GUEST_USERNAME_CHARSET.len());
+                char::from(GUEST_USERNAME_CHARSET[idx])
+            })
+            .collect();
+        let username = format!("guest-{suffix}");
+
+        if repo.user().exists(&username).await?
+            || !homeserver
+                .is_localpart_available(&username)
+                .await
+                .map_err(RouteError::HomeserverConnection)?
+        {
+            continue;
+        }
+
+        let user = repo.user().add(rng, clock, username).await?;
+        let user = repo.user().set_guest(user, true).await?;
+
+        repo.job()
+            .schedule_job(ProvisionUserJob::new(&user))
+            .await?;
+
+        return Ok(user);
+    }
+
+    Err(RouteError::NoAvailableUsername)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::{CaptchaConfig, CaptchaService};
+    use mas_storage::compat::CompatSessionFilter;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{
+        init_tracing, test_site_config, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    /// Test that guest registration is refused when it is disabled
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_guest_registration_disabled(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                guest_registration_allowed: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let request =
+            Request::post("/_matrix/client/v3/register?kind=guest").json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_GUEST_ACCESS_FORBIDDEN");
+
+        // Regular registrations are never allowed through this endpoint
+        let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+            "username": "alice",
+            "password": "password",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+    }
+
+    /// Test that a guest account can be created and later upgraded to a full
+    /// account
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_guest_registration_and_upgrade(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request =
+            Request::post("/_matrix/client/v3/register?kind=guest").json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let guest: ResponseBody = response.json();
+        assert!(guest.user_id.starts_with("@guest-"));
+        assert!(guest.user_id.ends_with(":example.com"));
+        assert_eq!(guest.refresh_token, None);
+
+        let mut repo = state.repository().await.unwrap();
+        let username = guest
+            .user_id
+            .trim_start_matches('@')
+            .trim_end_matches(":example.com");
+        let user = repo
+            .user()
+            .find_by_username(username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.is_guest);
+        repo.save().await.unwrap();
+
+        // Upgrading with the wrong username fails
+        let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+            "username": "alice",
+            "password": "password",
+            "guest_access_token": guest.access_token,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_INVALID_USERNAME");
+
+        // Upgrade the guest account
+        let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+            "username": username,
+            "password": "password",
+            "guest_access_token": guest.access_token,
+            "refresh_token": true,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let upgraded: ResponseBody = response.json();
+        assert_eq!(upgraded.user_id, guest.user_id);
+        assert_eq!(upgraded.device_id, guest.device_id);
+        assert_ne!(upgraded.access_token, guest.access_token);
+        assert!(upgraded.refresh_token.is_some());
+
+        // The user is not a guest anymore, has a password, and only has the new
+        // session active
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(!user.is_guest);
+        assert!(repo.user_password().active(&user).await.unwrap().is_some());
+        let active_sessions = repo
+            .compat_session()
+            .count(CompatSessionFilter::new().for_user(&user).active_only())
+            .await
+            .unwrap();
+        assert_eq!(active_sessions, 1);
+        repo.save().await.unwrap();
+
+        // The guest token can't be used to upgrade again
+        let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+            "password": "password",
+            "guest_access_token": guest.access_token,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // The new token can't be used either, as the user is not a guest anymore
+        let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+            "password": "password",
+            "guest_access_token": upgraded.access_token,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // The user can now login with their password
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": username,
+            },
+            "password": "password",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    /// Test that upgrading a guest account is refused when password
+    /// registration is disabled or requires a CAPTCHA
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_guest_upgrade_gates(pool: PgPool) {
+        init_tracing();
+        let site_configs = [
+            (
+                SiteConfig {
+                    password_registration_enabled: false,
+                    ..test_site_config()
+                },
+                "Registration is not enabled",
+            ),
+            (
+                SiteConfig {
+                    captcha: Some(CaptchaConfig {
+                        service: CaptchaService::HCaptcha,
+                        site_key: "site_key".to_owned(),
+                        secret_key: "secret_key".to_owned(),
+                    }),
+                    ..test_site_config()
+                },
+                "Registration requires a CAPTCHA, use the web interface instead",
+            ),
+        ];
+
+        for (site_config, error) in site_configs {
+            let state = TestState::from_pool_with_site_config(pool.clone(), site_config)
+                .await
+                .unwrap();
+
+            let request =
+                Request::post("/_matrix/client/v3/register?kind=guest").json(serde_json::json!({}));
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::OK);
+            let guest: ResponseBody = response.json();
+
+            let request = Request::post("/_matrix/client/v3/register").json(serde_json::json!({
+                "password": "password",
+                "guest_access_token": guest.access_token,
+            }));
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::FORBIDDEN);
+            let body: serde_json::Value = response.json();
+            assert_eq!(body["errcode"], "M_FORBIDDEN");
+            assert_eq!(body["error"], error);
+        }
+    }
+}
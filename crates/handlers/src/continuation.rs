@@ -0,0 +1,173 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Binding of multi-step interactive flows to the browser which started them
+//!
+//! Flows like the OAuth 2.0 authorization are persisted in the database when
+//! they start, and the user is sent through the login, registration,
+//! re-authentication or consent pages with a `PostAuthAction` pointing back
+//! to them. The IDs of the flows started in a browser are remembered in a
+//! cookie, so that they can't be continued from another browser, e.g. by
+//! sending a link to someone else.
+//!
+//! The compatibility SSO login is not bound: it starts on the homeserver
+//! domain, where the cookie can't be set for this service. Its completion page
+//! instead asks the user to confirm where they are being sent.
+
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::{cookies::CookieJar, FancyError};
+use mas_templates::ErrorContext;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Name of the cookie
+static COOKIE_NAME: &str = "continuations";
+
+/// Flows have to be completed within an hour
+static CONTINUATION_MAX_TIME: Duration = Duration::microseconds(60 * 60 * 1000 * 1000);
+
+/// How many flows are remembered at most, to keep the cookie small
+const MAX_CONTINUATIONS: usize = 16;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("the flow was not started in this browser, or it expired")]
+pub(crate) struct ContinuationNotFound;
+
+impl IntoResponse for ContinuationNotFound {
+    fn into_response(self) -> Response {
+        let context = ErrorContext::new()
+            .with_code("session_expired")
+            .with_description(self.to_string());
+
+        FancyError::new(context)
+            .with_status(StatusCode::FORBIDDEN)
+            .into_response()
+    }
+}
+
+fn expired(id: Ulid, now: DateTime<Utc>) -> bool {
+    let Ok(ts) = id.timestamp_ms().try_into() else {
+        return true;
+    };
+    let Some(when) = DateTime::from_timestamp_millis(ts) else {
+        return true;
+    };
+    now - when > CONTINUATION_MAX_TIME
+}
+
+/// The flows started in this browser
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct Continuations(Vec<Ulid>);
+
+impl Continuations {
+    /// Load the continuations cookie
+    pub fn load(cookie_jar: &CookieJar) -> Self {
+        match cookie_jar.load(COOKIE_NAME) {
+            Ok(Some(continuations)) => continuations,
+            Ok(None) => Self::default(),
+            Err(e) => {
+                tracing::warn!("Invalid continuations cookie: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the continuations to the cookie jar
+    pub fn save(self, cookie_jar: CookieJar) -> CookieJar {
+        cookie_jar.save(COOKIE_NAME, &self, false)
+    }
+
+    /// Remember a flow started in this browser, forgetting the expired ones
+    pub fn add(&mut self, id: Ulid, now: DateTime<Utc>) {
+        self.0.retain(|other| !expired(*other, now));
+        self.0.push(id);
+
+        if self.0.len() > MAX_CONTINUATIONS {
+            let excess = self.0.len() - MAX_CONTINUATIONS;
+            self.0.drain(..excess);
+        }
+    }
+
+    /// Forget a flow once it is completed
+    pub fn remove(&mut self, id: Ulid) {
+        self.0.retain(|other| *other != id);
+    }
+
+    /// Check that a flow was started in this browser and did not expire
+    pub fn verify(&self, id: Ulid, now: DateTime<Utc>) -> Result<(), ContinuationNotFound> {
+        if self.0.contains(&id) && !expired(id, now) {
+            Ok(())
+        } else {
+            Err(ContinuationNotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use super::*;
+
+    #[test]
+    fn test_continuations() {
+        let now = chrono::Utc
+            .with_ymd_and_hms(2018, 1, 18, 1, 30, 22)
+            .unwrap();
+        let mut rng = ChaChaRng::seed_from_u64(42);
+
+        let mut continuations = Continuations::default();
+
+        let first = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        continuations.add(first, now);
+
+        let now = now + Duration::microseconds(30 * 60 * 1000 * 1000);
+        let second = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        continuations.add(second, now);
+
+        // Flows not started in this browser can't be continued
+        let unknown = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        assert_eq!(continuations.verify(first, now), Ok(()));
+        assert_eq!(continuations.verify(second, now), Ok(()));
+        assert_eq!(
+            continuations.verify(unknown, now),
+            Err(ContinuationNotFound)
+        );
+
+        // The first flow expires after an hour
+        let now = now + Duration::microseconds(45 * 60 * 1000 * 1000);
+        assert_eq!(continuations.verify(first, now), Err(ContinuationNotFound));
+        assert_eq!(continuations.verify(second, now), Ok(()));
+
+        // Completed flows are forgotten
+        continuations.remove(second);
+        assert_eq!(continuations.verify(second, now), Err(ContinuationNotFound));
+
+        // Only the most recent flows are remembered
+        let mut continuations = Continuations::default();
+        let ids: Vec<Ulid> = (0..=MAX_CONTINUATIONS)
+            .map(|_| Ulid::from_datetime_with_source(now.into(), &mut rng))
+            .collect();
+        for id in &ids {
+            continuations.add(*id, now);
+        }
+        assert_eq!(continuations.verify(ids[0], now), Err(ContinuationNotFound));
+        assert_eq!(continuations.verify(ids[MAX_CONTINUATIONS], now), Ok(()));
+    }
+}
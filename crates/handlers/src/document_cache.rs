@@ -0,0 +1,175 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of the public JSON documents which only depend on the service
+//! configuration, like the discovery document and the JWKS.
+//!
+//! Those documents are serialized once, and served with an `ETag` so that
+//! clients can revalidate them with conditional requests.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    body::Bytes,
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+use headers::{CacheControl, ContentType, ETag, HeaderMapExt, IfNoneMatch};
+use hyper::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// How long clients can use the documents without revalidating them
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A JSON document, serialized once
+#[derive(Debug, Clone)]
+pub(crate) struct CachedDocument {
+    body: Bytes,
+    etag: ETag,
+}
+
+impl CachedDocument {
+    fn new<T: Serialize>(document: &T) -> Self {
+        let body = serde_json::to_vec(document).expect("documents should always serialize");
+        let digest = Sha256::digest(&body);
+        let etag = format!("\"{digest:x}\"")
+            .parse()
+            .expect("a hex digest should be a valid ETag");
+
+        Self {
+            body: body.into(),
+            etag,
+        }
+    }
+
+    /// Build the response to a request, replying with a `304 Not Modified` if
+    /// the client already has the current version of the document
+    pub(crate) fn respond(&self, if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Response {
+        let not_modified = if_none_match.is_some_and(|TypedHeader(if_none_match)| {
+            !if_none_match.precondition_passes(&self.etag)
+        });
+
+        let mut response = if not_modified {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            (TypedHeader(ContentType::json()), self.body.clone()).into_response()
+        };
+
+        let headers = response.headers_mut();
+        headers.typed_insert(self.etag.clone());
+        headers.typed_insert(CacheControl::new().with_public().with_max_age(MAX_AGE));
+        response
+    }
+}
+
+/// Holds the serialized versions of the public documents
+///
+/// The documents are generated the first time they are requested. The cache
+/// must be invalidated if anything they depend on changes, like the keys in
+/// the keystore.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentCache {
+    jwks: Arc<RwLock<Option<CachedDocument>>>,
+    discovery: Arc<RwLock<Option<CachedDocument>>>,
+}
+
+fn get_or_generate<T: Serialize>(
+    slot: &RwLock<Option<CachedDocument>>,
+    generate: impl FnOnce() -> T,
+) -> CachedDocument {
+    if let Some(document) = &*slot.read().expect("document cache lock poisoned") {
+        return document.clone();
+    }
+
+    let document = CachedDocument::new(&generate());
+    *slot.write().expect("document cache lock poisoned") = Some(document.clone());
+    document
+}
+
+impl DocumentCache {
+    /// Create a new, empty, document cache
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all the cached documents, e.g. after the keys were rotated
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock was poisoned
+    pub fn invalidate(&self) {
+        *self.jwks.write().expect("document cache lock poisoned") = None;
+        *self
+            .discovery
+            .write()
+            .expect("document cache lock poisoned") = None;
+    }
+
+    /// Get the JWKS document, generating it if needed
+    pub(crate) fn jwks<T: Serialize>(&self, generate: impl FnOnce() -> T) -> CachedDocument {
+        get_or_generate(&self.jwks, generate)
+    }
+
+    /// Get the discovery document, generating it if needed
+    pub(crate) fn discovery<T: Serialize>(&self, generate: impl FnOnce() -> T) -> CachedDocument {
+        get_or_generate(&self.discovery, generate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn test_conditional_requests() {
+        let cache = DocumentCache::new();
+        let document = cache.jwks(|| serde_json::json!({ "keys": [] }));
+
+        // The generator is not called again once the document is cached
+        let cached = cache.jwks(|| -> serde_json::Value { unreachable!() });
+        assert_eq!(cached.etag, document.etag);
+
+        let response = document.respond(None);
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag: ETag = response.headers().typed_get().unwrap();
+        assert_eq!(etag, document.etag);
+        assert!(response.headers().typed_get::<CacheControl>().is_some());
+
+        // The client has the current version of the document
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(IfNoneMatch::from(etag.clone()));
+        let if_none_match = headers.typed_get().map(TypedHeader);
+        let response = document.respond(if_none_match);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // The client has an outdated version of the document
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(IfNoneMatch::from("\"outdated\"".parse::<ETag>().unwrap()));
+        let if_none_match = headers.typed_get().map(TypedHeader);
+        let response = document.respond(if_none_match);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Invalidating the cache generates the document again
+        cache.invalidate();
+        let regenerated = cache.jwks(|| serde_json::json!({ "keys": [{}] }));
+        assert_ne!(regenerated.etag, document.etag);
+    }
+}
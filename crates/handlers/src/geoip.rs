@@ -0,0 +1,542 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Approximate location of IP addresses, looked up in local MaxMind DB files
+//!
+//! This implements just enough of the [MaxMind DB format] to read the
+//! GeoLite2 and GeoIP2 City, Country and ASN databases, without doing any
+//! network request.
+//!
+//! [MaxMind DB format]: https://maxmind.github.io/MaxMind-DB/
+
+use std::{net::IpAddr, sync::Arc};
+
+use mas_data_model::GeoLocation;
+use thiserror::Error;
+
+/// Marks the start of the metadata section, at the end of the file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+/// Size of the zeroed section between the search tree and the data section
+const DATA_SECTION_SEPARATOR: usize = 16;
+
+/// Nested maps and arrays deeper than this are rejected
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Error)]
+#[error("invalid MaxMind DB file: {0}")]
+pub struct GeoIpError(&'static str);
+
+/// A value decoded from the data section. Only the types needed to read the
+/// locations are kept, the others are skipped.
+enum Value<'a> {
+    String(&'a str),
+    Uint(u128),
+    Map(Vec<(&'a str, Value<'a>)>),
+    Other,
+}
+
+impl<'a> Value<'a> {
+    fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Self::Map(entries) => entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_uint(&self) -> Option<u128> {
+        match self {
+            Self::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Read a big-endian unsigned integer of at most 16 bytes
+fn read_uint(bytes: &[u8]) -> u128 {
+    bytes
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | u128::from(*byte))
+}
+
+/// Read a big-endian unsigned integer of at most 4 bytes
+fn read_usize(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | usize::from(*byte))
+}
+
+/// Decodes values from a data or metadata section
+struct Decoder<'a> {
+    section: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], GeoIpError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.section.get(offset..end))
+            .ok_or(GeoIpError("truncated value"))
+    }
+
+    /// Decode the value at the given offset, returning it along with the
+    /// offset of the next value
+    fn decode(&self, offset: usize, depth: usize) -> Result<(Value<'a>, usize), GeoIpError> {
+        if depth > MAX_DEPTH {
+            return Err(GeoIpError("values are nested too deeply"));
+        }
+
+        let control = self.bytes(offset, 1)?[0];
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+
+        if kind == 1 {
+            // Pointers to another value of the section
+            let size = usize::from((control >> 3) & 0x3);
+            let high = usize::from(control & 0x7);
+            let bytes = self.bytes(offset, size + 1)?;
+            let target = match size {
+                0 => (high << 8) | read_usize(bytes),
+                1 => ((high << 16) | read_usize(bytes)) + 2048,
+                2 => ((high << 24) | read_usize(bytes)) + 526_336,
+                _ => read_usize(bytes),
+            };
+
+            let (value, _) = self.decode(target, depth + 1)?;
+            return Ok((value, offset + size + 1));
+        }
+
+        if kind == 0 {
+            // Extended types
+            kind = self.bytes(offset, 1)?[0].saturating_add(7);
+            offset += 1;
+        }
+
+        let mut size = usize::from(control & 0x1f);
+        if size >= 29 {
+            let len = size - 28;
+            let bytes = self.bytes(offset, len)?;
+            offset += len;
+            size = match len {
+                1 => 29 + read_usize(bytes),
+                2 => 285 + read_usize(bytes),
+                _ => 65_821 + read_usize(bytes),
+            };
+        }
+
+        let value = match kind {
+            2 => {
+                let bytes = self.bytes(offset, size)?;
+                offset += size;
+                let string =
+                    std::str::from_utf8(bytes).map_err(|_| GeoIpError("invalid string"))?;
+                Value::String(string)
+            }
+
+            // double, bytes, int32 and float
+            3 | 4 | 8 | 15 => {
+                self.bytes(offset, size)?;
+                offset += size;
+                Value::Other
+            }
+
+            // uint16, uint32, uint64 and uint128
+            5 | 6 | 9 | 10 => {
+                if size > 16 {
+                    return Err(GeoIpError("invalid unsigned integer"));
+                }
+                let bytes = self.bytes(offset, size)?;
+                offset += size;
+                Value::Uint(read_uint(bytes))
+            }
+
+            7 => {
+                let mut entries = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (key, next) = self.decode(offset, depth + 1)?;
+                    let key = key.as_str().ok_or(GeoIpError("invalid map key"))?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    entries.push((key, value));
+                    offset = next;
+                }
+                Value::Map(entries)
+            }
+
+            11 => {
+                for _ in 0..size {
+                    let (_, next) = self.decode(offset, depth + 1)?;
+                    offset = next;
+                }
+                Value::Other
+            }
+
+            // boolean, whose value is its size
+            14 => Value::Other,
+
+            _ => return Err(GeoIpError("unsupported data type")),
+        };
+
+        Ok((value, offset))
+    }
+}
+
+/// A single MaxMind DB file loaded in memory
+struct Database {
+    bytes: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u128,
+    data_start: usize,
+    data_end: usize,
+    ipv4_start: usize,
+}
+
+impl Database {
+    fn new(bytes: Vec<u8>) -> Result<Self, GeoIpError> {
+        let marker = bytes
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or(GeoIpError("metadata not found"))?;
+
+        let decoder = Decoder {
+            section: &bytes[marker + METADATA_MARKER.len()..],
+        };
+        let (metadata, _) = decoder.decode(0, 0)?;
+
+        let node_count = metadata
+            .get("node_count")
+            .and_then(Value::as_uint)
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or(GeoIpError("invalid node count"))?;
+        let record_size = metadata
+            .get("record_size")
+            .and_then(Value::as_uint)
+            .and_then(|n| usize::try_from(n).ok())
+            .filter(|size| matches!(size, 24 | 28 | 32))
+            .ok_or(GeoIpError("unsupported record size"))?;
+        let ip_version = metadata
+            .get("ip_version")
+            .and_then(Value::as_uint)
+            .filter(|version| matches!(version, 4 | 6))
+            .ok_or(GeoIpError("unsupported IP version"))?;
+
+        let data_start = node_count
+            .checked_mul(record_size / 4)
+            .and_then(|tree_size| tree_size.checked_add(DATA_SECTION_SEPARATOR))
+            .filter(|start| *start <= marker)
+            .ok_or(GeoIpError("search tree is too large"))?;
+
+        let mut database = Self {
+            bytes,
+            node_count,
+            record_size,
+            ip_version,
+            data_start,
+            data_end: marker,
+            ipv4_start: 0,
+        };
+
+        // IPv4 addresses are stored in IPv6 trees under ::/96
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = database.read_record(node, false);
+            }
+            database.ipv4_start = node;
+        }
+
+        Ok(database)
+    }
+
+    /// Read the left or right record of a node of the search tree
+    fn read_record(&self, node: usize, right: bool) -> usize {
+        let bytes = &self.bytes;
+        match self.record_size {
+            24 => {
+                let base = node * 6 + if right { 3 } else { 0 };
+                read_usize(&bytes[base..base + 3])
+            }
+            28 => {
+                let base = node * 7;
+                let middle = usize::from(bytes[base + 3]);
+                if right {
+                    ((middle & 0x0F) << 24) | read_usize(&bytes[base + 4..base + 7])
+                } else {
+                    ((middle & 0xF0) << 20) | read_usize(&bytes[base..base + 3])
+                }
+            }
+            _ => {
+                let base = node * 8 + if right { 4 } else { 0 };
+                read_usize(&bytes[base..base + 4])
+            }
+        }
+    }
+
+    /// Find the data record of an IP address
+    fn lookup(&self, ip: IpAddr) -> Result<Option<Value<'_>>, GeoIpError> {
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            ip @ IpAddr::V4(_) => ip,
+        };
+
+        let (address, mut node) = match ip {
+            IpAddr::V4(ip) => (ip.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(ip) => (ip.octets().to_vec(), 0),
+        };
+
+        for bit in 0..address.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            let right = (address[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+            node = self.read_record(node, right);
+        }
+
+        if node <= self.node_count {
+            // Either not found, or the address is too short for the tree
+            return Ok(None);
+        }
+
+        let offset = (node - self.node_count)
+            .checked_sub(DATA_SECTION_SEPARATOR)
+            .ok_or(GeoIpError("invalid data pointer"))?;
+        let decoder = Decoder {
+            section: &self.bytes[self.data_start..self.data_end],
+        };
+        let (value, _) = decoder.decode(offset, 0)?;
+        Ok(Some(value))
+    }
+}
+
+/// Extract the location from a City, Country or ASN database record
+fn location_from_record(record: &Value<'_>) -> GeoLocation {
+    let country = record
+        .get("country")
+        .or_else(|| record.get("registered_country"));
+    let english_name = |value: Option<&Value<'_>>| {
+        value
+            .and_then(|v| v.get("names"))
+            .and_then(|names| names.get("en"))
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+    };
+
+    GeoLocation {
+        country_code: country
+            .and_then(|c| c.get("iso_code"))
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        country: english_name(country),
+        city: english_name(record.get("city")),
+        asn: record
+            .get("autonomous_system_number")
+            .and_then(Value::as_uint)
+            .and_then(|n| u32::try_from(n).ok()),
+        as_organization: record
+            .get("autonomous_system_organization")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+    }
+}
+
+/// Looks up the approximate location of IP addresses in MaxMind DB files
+#[derive(Clone)]
+pub struct GeoIp {
+    databases: Arc<Vec<Database>>,
+}
+
+impl std::fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("databases", &self.databases.len())
+            .finish()
+    }
+}
+
+impl Default for GeoIp {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl GeoIp {
+    /// Create a lookup which never finds anything
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            databases: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Load the given database files contents
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files is not a valid MaxMind DB file
+    pub fn new(files: Vec<Vec<u8>>) -> Result<Self, GeoIpError> {
+        let databases = files
+            .into_iter()
+            .map(Database::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            databases: Arc::new(databases),
+        })
+    }
+
+    /// Look up the approximate location of an IP address, combining the
+    /// information of all the databases
+    #[must_use]
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let location = self
+            .databases
+            .iter()
+            .filter_map(|database| match database.lookup(ip) {
+                Ok(record) => record,
+                Err(e) => {
+                    tracing::warn!(
+                        error = &e as &dyn std::error::Error,
+                        %ip,
+                        "Failed to look up IP address"
+                    );
+                    None
+                }
+            })
+            .map(|record| location_from_record(&record))
+            .fold(GeoLocation::default(), GeoLocation::or);
+
+        (!location.is_empty()).then_some(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode the control byte and the data of a string
+    fn string(value: &str) -> Vec<u8> {
+        let mut bytes = vec![(2 << 5) | u8::try_from(value.len()).unwrap()];
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn uint32(value: u32) -> Vec<u8> {
+        let mut bytes = vec![(6 << 5) | 4];
+        bytes.extend_from_slice(&value.to_be_bytes());
+        bytes
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = vec![(7 << 5) | u8::try_from(entries.len()).unwrap()];
+        for (key, value) in entries {
+            bytes.extend(string(key));
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    /// Build an IPv4 database with a single record for `203.0.113.0/24`
+    fn sample_database(record: Vec<u8>) -> Vec<u8> {
+        let prefix = [203_u8, 0, 113];
+        let node_count: usize = 24;
+        let data_pointer = node_count + DATA_SECTION_SEPARATOR;
+
+        let mut bytes = Vec::new();
+        for node in 0..node_count {
+            let bit = (prefix[node / 8] >> (7 - node % 8)) & 1 == 1;
+            let next = if node + 1 == node_count {
+                data_pointer
+            } else {
+                node + 1
+            };
+            let (left, right) = if bit {
+                (node_count, next)
+            } else {
+                (next, node_count)
+            };
+            bytes.extend_from_slice(&u32::try_from(left).unwrap().to_be_bytes()[1..]);
+            bytes.extend_from_slice(&u32::try_from(right).unwrap().to_be_bytes()[1..]);
+        }
+
+        bytes.extend_from_slice(&[0; DATA_SECTION_SEPARATOR]);
+        bytes.extend(record);
+        bytes.extend_from_slice(METADATA_MARKER);
+        bytes.extend(map(&[
+            ("node_count", uint32(24)),
+            ("record_size", uint32(24)),
+            ("ip_version", uint32(4)),
+        ]));
+        bytes
+    }
+
+    #[test]
+    fn test_lookup() {
+        let city = sample_database(map(&[
+            (
+                "country",
+                map(&[
+                    ("iso_code", string("FR")),
+                    ("names", map(&[("en", string("France"))])),
+                ]),
+            ),
+            ("city", map(&[("names", map(&[("en", string("Paris"))]))])),
+        ]));
+        let asn = sample_database(map(&[
+            ("autonomous_system_number", uint32(64_496)),
+            ("autonomous_system_organization", string("Example ISP")),
+        ]));
+
+        let geoip = GeoIp::new(vec![city, asn]).unwrap();
+
+        let location = geoip.lookup("203.0.113.42".parse().unwrap()).unwrap();
+        assert_eq!(
+            location,
+            GeoLocation {
+                country_code: Some("FR".to_owned()),
+                country: Some("France".to_owned()),
+                city: Some("Paris".to_owned()),
+                asn: Some(64_496),
+                as_organization: Some("Example ISP".to_owned()),
+            }
+        );
+
+        // IPv4-mapped IPv6 addresses are looked up as IPv4 addresses
+        let location = geoip.lookup("::ffff:203.0.113.1".parse().unwrap());
+        assert_eq!(location.unwrap().city.as_deref(), Some("Paris"));
+
+        // Addresses out of the database are not found
+        assert!(geoip.lookup("198.51.100.1".parse().unwrap()).is_none());
+        assert!(geoip.lookup("2001:db8::1".parse().unwrap()).is_none());
+
+        // Nothing is ever found without a database
+        assert!(GeoIp::disabled()
+            .lookup("203.0.113.42".parse().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_database() {
+        assert!(GeoIp::new(vec![b"not a database".to_vec()]).is_err());
+    }
+}
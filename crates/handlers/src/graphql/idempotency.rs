@@ -0,0 +1,218 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the `Idempotency-Key` header on GraphQL requests.
+//!
+//! Automation using the admin mutations, like `addUser` or
+//! `createOAuth2Session`, can send an idempotency key along with the request.
+//! If the request is retried with the same key, the original response is
+//! replayed instead of running the mutations again, so that retries don't
+//! create duplicate users or tokens.
+
+use axum::{
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+use headers::{ContentType, Header, HeaderName, HeaderValue};
+use mas_data_model::IdempotencyKey;
+use mas_storage::{
+    idempotency::IdempotencyKeyRepository, oauth2::OAuth2ClientRepository, BoxClock, BoxRepository,
+    BoxRng, Repository, RepositoryAccess, RepositoryError,
+};
+use mas_storage_pg::PgRepository;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::{Requester, RouteError};
+
+/// The name of the `Idempotency-Key` header
+pub static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// The longest idempotency key accepted
+const MAX_KEY_LENGTH: usize = 255;
+
+/// The `Idempotency-Key` header, as described by
+/// `draft-ietf-httpapi-idempotency-key-header`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyKeyHeader(String);
+
+impl Header for IdempotencyKeyHeader {
+    fn name() -> &'static HeaderName {
+        &IDEMPOTENCY_KEY
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        if values.next().is_some() {
+            return Err(headers::Error::invalid());
+        }
+
+        let value = value.to_str().map_err(|_| headers::Error::invalid())?;
+
+        // The draft defines the value as a structured field string, but lots of
+        // clients send the bare key
+        let key = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH || key.contains(['"', '\\']) {
+            return Err(headers::Error::invalid());
+        }
+
+        Ok(Self(key.to_owned()))
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", self.0)) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+/// What to do with a request carrying an idempotency key
+pub enum Reservation {
+    /// The key was never used, the request should be processed
+    New(IdempotencyKey),
+
+    /// The request was already processed, its response should be replayed
+    Replay(String),
+}
+
+async fn repository(pool: &PgPool) -> Result<BoxRepository, RouteError> {
+    let repo = PgRepository::from_pool(pool)
+        .await
+        .map_err(RepositoryError::from_error)?;
+
+    Ok(repo.map_err(RepositoryError::from_error).boxed())
+}
+
+/// Reserve the idempotency key for this request, or find the response to the
+/// previous request which used it.
+///
+/// The reservation is committed right away, so that concurrent retries notice
+/// that the request is being processed.
+pub async fn reserve(
+    pool: &PgPool,
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    requester: &Requester,
+    IdempotencyKeyHeader(key): IdempotencyKeyHeader,
+    body: &[u8],
+) -> Result<Reservation, RouteError> {
+    // Keys are scoped to the client, so they can only be used with an access
+    // token
+    let session = requester
+        .oauth2_session()
+        .ok_or(RouteError::IdempotencyKeyUnsupported)?;
+
+    let mut repo = repository(pool).await?;
+
+    let client = repo
+        .oauth2_client()
+        .lookup(session.client_id)
+        .await?
+        .ok_or(RouteError::LoadFailed)?;
+
+    let request_hash = format!("{:x}", Sha256::digest(body));
+
+    let reserved = match repo.idempotency_key().find(&client, &key).await? {
+        Some(existing) => existing,
+        None => {
+            let reserved = repo
+                .idempotency_key()
+                .add(rng, clock, &client, key, request_hash)
+                .await?
+                // Another request reserved the key in the meantime
+                .ok_or(RouteError::IdempotencyKeyInProgress)?;
+
+            repo.save().await?;
+            return Ok(Reservation::New(reserved));
+        }
+    };
+
+    repo.cancel().await?;
+
+    if reserved.request_hash != request_hash {
+        return Err(RouteError::IdempotencyKeyReused);
+    }
+
+    reserved
+        .response
+        .map(Reservation::Replay)
+        .ok_or(RouteError::IdempotencyKeyInProgress)
+}
+
+/// Record the response to the request, so that it gets replayed on retries.
+///
+/// If the request failed, the key is released so that it can be retried.
+pub async fn complete(
+    pool: &PgPool,
+    reserved: IdempotencyKey,
+    response: &async_graphql::Response,
+) -> Result<(), RouteError> {
+    let mut repo = repository(pool).await?;
+
+    if response.is_ok() {
+        let response =
+            serde_json::to_string(response).map_err(|e| RouteError::Internal(Box::new(e)))?;
+        repo.idempotency_key().complete(reserved, response).await?;
+    } else {
+        repo.idempotency_key().remove(reserved).await?;
+    }
+
+    repo.save().await?;
+    Ok(())
+}
+
+/// Replay the response to a previous request
+pub fn replay(response: String) -> Response {
+    (TypedHeader(ContentType::json()), response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use headers::HeaderMapExt;
+    use hyper::HeaderMap;
+
+    use super::*;
+
+    fn decode(value: &'static str) -> Option<IdempotencyKeyHeader> {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_static(value));
+        headers.typed_get()
+    }
+
+    #[test]
+    fn test_decode_header() {
+        assert_eq!(
+            decode("\"8e03978e-40d5-43e8-bc93-6894a57f9324\""),
+            Some(IdempotencyKeyHeader(
+                "8e03978e-40d5-43e8-bc93-6894a57f9324".to_owned()
+            ))
+        );
+        assert_eq!(
+            decode("8e03978e-40d5-43e8-bc93-6894a57f9324"),
+            Some(IdempotencyKeyHeader(
+                "8e03978e-40d5-43e8-bc93-6894a57f9324".to_owned()
+            ))
+        );
+        assert_eq!(decode("\"\""), None);
+        assert_eq!(decode("\"a\"b\""), None);
+    }
+}
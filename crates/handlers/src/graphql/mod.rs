@@ -23,24 +23,30 @@ use async_graphql::{
 };
 use axum::{
     async_trait,
-    extract::{BodyStream, RawQuery, State as AxumState},
+    body::Bytes,
+    extract::{
+        rejection::{TypedHeaderRejection, TypedHeaderRejectionReason},
+        RawQuery, State as AxumState,
+    },
     http::StatusCode,
     response::{Html, IntoResponse, Response},
     Json, TypedHeader,
 };
-use futures_util::TryStreamExt;
 use headers::{authorization::Bearer, Authorization, ContentType, HeaderValue};
 use hyper::header::CACHE_CONTROL;
 use mas_axum_utils::{
-    cookies::CookieJar, sentry::SentryEventID, FancyError, SessionInfo, SessionInfoExt,
+    cookies::CookieJar, sentry::SentryEventID, user_authorization::BearerError, FancyError,
+    SessionInfo, SessionInfoExt,
 };
 use mas_data_model::{BrowserSession, Session, SiteConfig, User};
+use mas_keystore::{Encrypter, Keystore};
 use mas_matrix::HomeserverConnection;
 use mas_policy::{InstantiateError, Policy, PolicyFactory};
 use mas_storage::{
     BoxClock, BoxRepository, BoxRng, Clock, Repository, RepositoryError, SystemClock,
 };
 use mas_storage_pg::PgRepository;
+use oauth2_types::scope::ScopeToken;
 use opentelemetry_semantic_conventions::trace::{GRAPHQL_DOCUMENT, GRAPHQL_OPERATION_NAME};
 use rand::{thread_rng, SeedableRng};
 use rand_chacha::ChaChaRng;
@@ -48,6 +54,7 @@ use sqlx::PgPool;
 use tracing::{info_span, Instrument};
 use ulid::Ulid;
 
+pub(crate) mod idempotency;
 mod model;
 mod mutations;
 mod query;
@@ -55,21 +62,35 @@ mod state;
 
 pub use self::state::{BoxState, State};
 use self::{
+    idempotency::{IdempotencyKeyHeader, Reservation},
     model::{CreationEvent, Node},
     mutations::Mutation,
     query::Query,
 };
-use crate::{impl_from_error_for_route, passwords::PasswordManager, BoundActivityTracker};
+use crate::{
+    impl_from_error_for_route, passwords::PasswordManager, BoundActivityTracker, GeoIp,
+    HttpClientFactory, IntrospectionCache, MetadataCache, NetworkBans,
+};
 
 #[cfg(test)]
 mod tests;
 
+/// The scope needed to use the GraphQL API with an access token
+const GRAPHQL_SCOPE: ScopeToken = ScopeToken::from_static("urn:mas:graphql:*");
+
 struct GraphQLState {
     pool: PgPool,
     homeserver_connection: Arc<dyn HomeserverConnection<Error = anyhow::Error>>,
     policy_factory: Arc<PolicyFactory>,
     site_config: SiteConfig,
     password_manager: PasswordManager,
+    introspection_cache: IntrospectionCache,
+    geoip: GeoIp,
+    encrypter: Encrypter,
+    key_store: Keystore,
+    http_client_factory: HttpClientFactory,
+    metadata_cache: MetadataCache,
+    network_bans: NetworkBans,
 }
 
 #[async_trait]
@@ -94,6 +115,34 @@ impl state::State for GraphQLState {
         &self.site_config
     }
 
+    fn introspection_cache(&self) -> &IntrospectionCache {
+        &self.introspection_cache
+    }
+
+    fn geoip(&self) -> &GeoIp {
+        &self.geoip
+    }
+
+    fn encrypter(&self) -> &Encrypter {
+        &self.encrypter
+    }
+
+    fn key_store(&self) -> &Keystore {
+        &self.key_store
+    }
+
+    fn http_client_factory(&self) -> &HttpClientFactory {
+        &self.http_client_factory
+    }
+
+    fn metadata_cache(&self) -> &MetadataCache {
+        &self.metadata_cache
+    }
+
+    fn network_bans(&self) -> &NetworkBans {
+        &self.network_bans
+    }
+
     fn homeserver_connection(&self) -> &dyn HomeserverConnection<Error = anyhow::Error> {
         self.homeserver_connection.as_ref()
     }
@@ -113,12 +162,20 @@ impl state::State for GraphQLState {
 }
 
 #[must_use]
+#[allow(clippy::too_many_arguments)]
 pub fn schema(
     pool: &PgPool,
     policy_factory: &Arc<PolicyFactory>,
     homeserver_connection: impl HomeserverConnection<Error = anyhow::Error> + 'static,
     site_config: SiteConfig,
     password_manager: PasswordManager,
+    introspection_cache: IntrospectionCache,
+    geoip: GeoIp,
+    encrypter: Encrypter,
+    key_store: Keystore,
+    http_client_factory: HttpClientFactory,
+    metadata_cache: MetadataCache,
+    network_bans: NetworkBans,
 ) -> Schema {
     let state = GraphQLState {
         pool: pool.clone(),
@@ -126,6 +183,13 @@ pub fn schema(
         homeserver_connection: Arc::new(homeserver_connection),
         site_config,
         password_manager,
+        introspection_cache,
+        geoip,
+        encrypter,
+        key_store,
+        http_client_factory,
+        metadata_cache,
+        network_bans,
     };
     let state: BoxState = Box::new(state);
 
@@ -165,6 +229,18 @@ pub enum RouteError {
 
     #[error(transparent)]
     ParseRequest(#[from] async_graphql::ParseRequestError),
+
+    #[error("Invalid Idempotency-Key header")]
+    InvalidIdempotencyKey,
+
+    #[error("Idempotency keys can only be used with an access token")]
+    IdempotencyKeyUnsupported,
+
+    #[error("The idempotency key was already used for another request")]
+    IdempotencyKeyReused,
+
+    #[error("A request with the same idempotency key is being processed")]
+    IdempotencyKeyInProgress,
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
@@ -185,8 +261,10 @@ impl IntoResponse for RouteError {
 
             Self::InvalidToken => {
                 let error = async_graphql::Error::new("Invalid token");
+                let bearer_error = BearerError::InvalidToken;
                 (
-                    StatusCode::UNAUTHORIZED,
+                    bearer_error.status_code(),
+                    bearer_error,
                     Json(serde_json::json!({"errors": [error]})),
                 )
                     .into_response()
@@ -194,8 +272,12 @@ impl IntoResponse for RouteError {
 
             Self::MissingScope => {
                 let error = async_graphql::Error::new("Missing urn:mas:graphql:* scope");
+                let bearer_error = BearerError::InsufficientScope {
+                    scope: Some(GRAPHQL_SCOPE),
+                };
                 (
-                    StatusCode::UNAUTHORIZED,
+                    bearer_error.status_code(),
+                    bearer_error,
                     Json(serde_json::json!({"errors": [error]})),
                 )
                     .into_response()
@@ -209,6 +291,19 @@ impl IntoResponse for RouteError {
                 )
                     .into_response()
             }
+
+            e @ (Self::InvalidIdempotencyKey
+            | Self::IdempotencyKeyUnsupported
+            | Self::IdempotencyKeyReused
+            | Self::IdempotencyKeyInProgress) => {
+                let status = match e {
+                    Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+                    Self::IdempotencyKeyInProgress => StatusCode::CONFLICT,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                let error = async_graphql::Error::new(e.to_string());
+                (status, Json(serde_json::json!({"errors": [error]}))).into_response()
+            }
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -258,7 +353,7 @@ async fn get_requester(
             return Err(RouteError::InvalidToken);
         }
 
-        if !session.scope.contains("urn:mas:graphql:*") {
+        if !session.scope.contains(GRAPHQL_SCOPE.as_str()) {
             return Err(RouteError::MissingScope);
         }
 
@@ -278,36 +373,62 @@ async fn get_requester(
     Ok(requester)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn post(
     AxumState(schema): AxumState<Schema>,
+    AxumState(pool): AxumState<PgPool>,
     clock: BoxClock,
+    mut rng: BoxRng,
     repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
     cookie_jar: CookieJar,
     content_type: Option<TypedHeader<ContentType>>,
     authorization: Option<TypedHeader<Authorization<Bearer>>>,
-    body: BodyStream,
-) -> Result<impl IntoResponse, RouteError> {
+    idempotency_key: Result<TypedHeader<IdempotencyKeyHeader>, TypedHeaderRejection>,
+    body: Bytes,
+) -> Result<Response, RouteError> {
     let token = authorization
         .as_ref()
         .map(|TypedHeader(Authorization(bearer))| bearer.token());
     let (session_info, _cookie_jar) = cookie_jar.session_info();
     let requester = get_requester(&clock, &activity_tracker, repo, session_info, token).await?;
 
+    let idempotency_key = match idempotency_key {
+        Ok(TypedHeader(key)) => Some(key),
+        Err(e) if matches!(e.reason(), TypedHeaderRejectionReason::Missing) => None,
+        Err(_) => return Err(RouteError::InvalidIdempotencyKey),
+    };
+
+    let reserved = if let Some(key) = idempotency_key {
+        match idempotency::reserve(&pool, &mut rng, &clock, &requester, key, &body).await? {
+            Reservation::New(reserved) => Some(reserved),
+            Reservation::Replay(response) => return Ok(idempotency::replay(response)),
+        }
+    } else {
+        None
+    };
+
     let content_type = content_type.map(|TypedHeader(h)| h.to_string());
 
-    let request = async_graphql::http::receive_body(
-        content_type,
-        body.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-            .into_async_read(),
-        MultipartOptions::default(),
-    )
-    .await?
-    .data(requester); // XXX: this should probably return another error response?
+    let request =
+        async_graphql::http::receive_body(content_type, &body[..], MultipartOptions::default())
+            .await?
+            .data(requester); // XXX: this should probably return another error response?
 
     let span = span_for_graphql_request(&request);
     let response = schema.execute(request).instrument(span).await;
 
+    if let Some(reserved) = reserved {
+        // The mutations already ran at this point, so failing to record the
+        // response shouldn't fail the request
+        if let Err(e) = idempotency::complete(&pool, reserved, &response).await {
+            tracing::error!(
+                error = &e as &dyn std::error::Error,
+                "Failed to record the response for the idempotency key"
+            );
+        }
+    }
+
     let cache_control = response
         .cache_control
         .value()
@@ -316,7 +437,7 @@ pub async fn post(
 
     let headers = response.http_headers.clone();
 
-    Ok((headers, cache_control, Json(response)))
+    Ok((headers, cache_control, Json(response)).into_response())
 }
 
 pub async fn get(
@@ -403,6 +524,12 @@ impl OwnerId for mas_data_model::UserEmail {
     }
 }
 
+impl OwnerId for mas_data_model::UserPhone {
+    fn owner_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
 impl OwnerId for Session {
     fn owner_id(&self) -> Option<Ulid> {
         self.user_id
@@ -23,8 +23,8 @@ use mas_storage::{
 };
 
 use super::{
-    AppSession, CompatSession, Cursor, NodeCursor, NodeType, OAuth2Session, PreloadedTotalCount,
-    SessionState, User, UserAgent,
+    AppSession, CompatSession, Cursor, Location, NodeCursor, NodeType, OAuth2Session,
+    PreloadedTotalCount, SessionState, User, UserAgent,
 };
 use crate::graphql::state::ContextExt;
 
@@ -97,11 +97,27 @@ impl BrowserSession {
         self.0.last_active_ip.map(|ip| ip.to_string())
     }
 
+    /// The approximate location of the last IP address used by the session.
+    pub async fn last_active_location(&self, ctx: &Context<'_>) -> Option<Location> {
+        let ip = self.0.last_active_ip?;
+        ctx.state().geoip().lookup(ip).map(Location::from)
+    }
+
     /// The last time the session was active.
     pub async fn last_active_at(&self) -> Option<DateTime<Utc>> {
         self.0.last_active_at
     }
 
+    /// Whether this session was started by an admin impersonating the user.
+    pub async fn impersonated(&self) -> bool {
+        self.0.is_impersonation()
+    }
+
+    /// When the session expires. Only impersonation sessions expire.
+    pub async fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.0.expires_at
+    }
+
     /// Get the list of both compat and OAuth 2.0 sessions started by this
     /// browser session, chronologically sorted
     #[allow(clippy::too_many_arguments)]
@@ -18,7 +18,7 @@ use chrono::{DateTime, Utc};
 use mas_storage::{compat::CompatSessionRepository, user::UserRepository};
 use url::Url;
 
-use super::{BrowserSession, NodeType, SessionState, User, UserAgent};
+use super::{BrowserSession, Location, NodeType, SessionState, User, UserAgent};
 use crate::graphql::state::ContextExt;
 
 /// Lazy-loaded reverse reference.
@@ -164,6 +164,12 @@ impl CompatSession {
         self.session.last_active_ip.map(|ip| ip.to_string())
     }
 
+    /// The approximate location of the last IP address used by the session.
+    pub async fn last_active_location(&self, ctx: &Context<'_>) -> Option<Location> {
+        let ip = self.session.last_active_ip?;
+        ctx.state().geoip().lookup(ip).map(Location::from)
+    }
+
     /// The last time the session was active.
     pub async fn last_active_at(&self) -> Option<DateTime<Utc>> {
         self.session.last_active_at
@@ -19,6 +19,7 @@ mod browser_sessions;
 mod compat_sessions;
 mod cursor;
 mod matrix;
+mod network_bans;
 mod node;
 mod oauth;
 mod site_config;
@@ -30,11 +31,12 @@ pub use self::{
     browser_sessions::{Authentication, BrowserSession},
     compat_sessions::{CompatSession, CompatSsoLogin},
     cursor::{Cursor, NodeCursor},
+    network_bans::NetworkBan,
     node::{Node, NodeType},
     oauth::{OAuth2Client, OAuth2Session},
     site_config::{SiteConfig, SITE_CONFIG_ID},
     upstream_oauth::{UpstreamOAuth2Link, UpstreamOAuth2Provider},
-    users::{AppSession, User, UserEmail},
+    users::{AppSession, User, UserEmail, UserPhone},
     viewer::{Anonymous, Viewer, ViewerSession},
 };
 
@@ -50,6 +52,7 @@ pub enum CreationEvent {
     CompatSession(Box<CompatSession>),
     BrowserSession(Box<BrowserSession>),
     UserEmail(Box<UserEmail>),
+    UserPhone(Box<UserPhone>),
     UpstreamOAuth2Provider(Box<UpstreamOAuth2Provider>),
     UpstreamOAuth2Link(Box<UpstreamOAuth2Link>),
     OAuth2Session(Box<OAuth2Session>),
@@ -141,3 +144,35 @@ impl From<mas_data_model::UserAgent> for UserAgent {
         }
     }
 }
+
+/// The approximate location of an IP address, as resolved from the configured
+/// GeoIP databases
+#[derive(SimpleObject)]
+pub struct Location {
+    /// The ISO 3166-1 alpha-2 code of the country
+    pub country_code: Option<String>,
+
+    /// The name of the country, in English
+    pub country: Option<String>,
+
+    /// The name of the city, in English
+    pub city: Option<String>,
+
+    /// The autonomous system number of the network
+    pub asn: Option<u32>,
+
+    /// The organization operating the autonomous system
+    pub as_organization: Option<String>,
+}
+
+impl From<mas_data_model::GeoLocation> for Location {
+    fn from(location: mas_data_model::GeoLocation) -> Self {
+        Self {
+            country_code: location.country_code,
+            country: location.country,
+            city: location.city,
+            asn: location.asn,
+            as_organization: location.as_organization,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_graphql::{Description, Object, ID};
+use chrono::{DateTime, Utc};
+
+use super::NodeType;
+
+/// A temporary ban of a network, which can't reach the service until it
+/// expires or is lifted.
+#[derive(Debug, Clone, Description)]
+pub struct NetworkBan(pub mas_data_model::NetworkBan);
+
+#[Object(use_type_description)]
+impl NetworkBan {
+    /// ID of the object.
+    pub async fn id(&self) -> ID {
+        NodeType::NetworkBan.id(self.0.id)
+    }
+
+    /// The banned network, in the CIDR notation.
+    pub async fn network(&self) -> String {
+        self.0.network.to_string()
+    }
+
+    /// Why an administrator banned the network. Automatic bans have no
+    /// reason.
+    pub async fn reason(&self) -> Option<&str> {
+        self.0.reason.as_deref()
+    }
+
+    /// Whether the network was banned automatically, after too many failed
+    /// authentication attempts.
+    pub async fn automatic(&self) -> bool {
+        self.0.is_automatic()
+    }
+
+    /// When the object was created.
+    pub async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// When the ban ends.
+    pub async fn expires_at(&self) -> DateTime<Utc> {
+        self.0.expires_at
+    }
+
+    /// When the ban was lifted by an administrator, if it was.
+    pub async fn lifted_at(&self) -> Option<DateTime<Utc>> {
+        self.0.lifted_at
+    }
+}
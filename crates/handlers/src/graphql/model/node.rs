@@ -20,6 +20,7 @@ use ulid::Ulid;
 use super::{
     Anonymous, Authentication, BrowserSession, CompatSession, CompatSsoLogin, OAuth2Client,
     OAuth2Session, SiteConfig, UpstreamOAuth2Link, UpstreamOAuth2Provider, User, UserEmail,
+    UserPhone,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -28,12 +29,14 @@ pub enum NodeType {
     BrowserSession,
     CompatSession,
     CompatSsoLogin,
+    NetworkBan,
     OAuth2Client,
     OAuth2Session,
     UpstreamOAuth2Provider,
     UpstreamOAuth2Link,
     User,
     UserEmail,
+    UserPhone,
 }
 
 #[derive(Debug, Error)]
@@ -52,12 +55,14 @@ impl NodeType {
             NodeType::BrowserSession => "browser_session",
             NodeType::CompatSession => "compat_session",
             NodeType::CompatSsoLogin => "compat_sso_login",
+            NodeType::NetworkBan => "network_ban",
             NodeType::OAuth2Client => "oauth2_client",
             NodeType::OAuth2Session => "oauth2_session",
             NodeType::UpstreamOAuth2Provider => "upstream_oauth2_provider",
             NodeType::UpstreamOAuth2Link => "upstream_oauth2_link",
             NodeType::User => "user",
             NodeType::UserEmail => "user_email",
+            NodeType::UserPhone => "user_phone",
         }
     }
 
@@ -67,12 +72,14 @@ impl NodeType {
             "browser_session" => Some(NodeType::BrowserSession),
             "compat_session" => Some(NodeType::CompatSession),
             "compat_sso_login" => Some(NodeType::CompatSsoLogin),
+            "network_ban" => Some(NodeType::NetworkBan),
             "oauth2_client" => Some(NodeType::OAuth2Client),
             "oauth2_session" => Some(NodeType::OAuth2Session),
             "upstream_oauth2_provider" => Some(NodeType::UpstreamOAuth2Provider),
             "upstream_oauth2_link" => Some(NodeType::UpstreamOAuth2Link),
             "user" => Some(NodeType::User),
             "user_email" => Some(NodeType::UserEmail),
+            "user_phone" => Some(NodeType::UserPhone),
             _ => None,
         }
     }
@@ -128,4 +135,5 @@ pub enum Node {
     UpstreamOAuth2Link(Box<UpstreamOAuth2Link>),
     User(Box<User>),
     UserEmail(Box<UserEmail>),
+    UserPhone(Box<UserPhone>),
 }
@@ -20,7 +20,7 @@ use oauth2_types::{oidc::ApplicationType, scope::Scope};
 use ulid::Ulid;
 use url::Url;
 
-use super::{BrowserSession, NodeType, SessionState, User, UserAgent};
+use super::{BrowserSession, Location, NodeType, SessionState, User, UserAgent};
 use crate::graphql::{state::ContextExt, UserId};
 
 /// An OAuth 2.0 session represents a client session which used the OAuth APIs
@@ -128,6 +128,12 @@ impl OAuth2Session {
         self.0.last_active_ip.map(|ip| ip.to_string())
     }
 
+    /// The approximate location of the last IP address used by the session.
+    pub async fn last_active_location(&self, ctx: &Context<'_>) -> Option<Location> {
+        let ip = self.0.last_active_ip?;
+        ctx.state().geoip().lookup(ip).map(Location::from)
+    }
+
     /// The last time the session was active.
     pub async fn last_active_at(&self) -> Option<DateTime<Utc>> {
         self.0.last_active_at
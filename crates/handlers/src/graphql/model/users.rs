@@ -24,7 +24,10 @@ use mas_storage::{
     compat::{CompatSessionFilter, CompatSsoLoginFilter, CompatSsoLoginRepository},
     oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
     upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository},
-    user::{BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository},
+    user::{
+        BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository,
+        UserPhoneRepository,
+    },
     Pagination, RepositoryAccess,
 };
 
@@ -74,11 +77,28 @@ impl User {
         self.0.locked_at
     }
 
+    /// When the user was shadow-banned.
+    pub async fn shadow_banned_at(&self) -> Option<DateTime<Utc>> {
+        self.0.shadow_banned_at
+    }
+
     /// Whether the user can request admin privileges.
     pub async fn can_request_admin(&self) -> bool {
         self.0.can_request_admin
     }
 
+    /// Whether the user is a non-interactive account, used by a bot or a
+    /// bridge.
+    pub async fn is_bot(&self) -> bool {
+        self.0.is_bot
+    }
+
+    /// Whether the user is a guest account, created through the Matrix
+    /// compatibility layer.
+    pub async fn is_guest(&self) -> bool {
+        self.0.is_guest
+    }
+
     /// Access to the user's Matrix account information.
     async fn matrix(&self, ctx: &Context<'_>) -> Result<MatrixUser, async_graphql::Error> {
         let state = ctx.state();
@@ -376,6 +396,17 @@ impl User {
         .await
     }
 
+    /// Get the list of phone numbers, sorted by number
+    async fn phones(&self, ctx: &Context<'_>) -> Result<Vec<UserPhone>, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+
+        let phones = repo.user_phone().all(&self.0).await?;
+        repo.cancel().await?;
+
+        Ok(phones.into_iter().map(UserPhone).collect())
+    }
+
     /// Get the list of OAuth 2.0 sessions, chronologically sorted
     #[allow(clippy::too_many_arguments)]
     async fn oauth2_sessions(
@@ -700,6 +731,34 @@ impl UserEmail {
     }
 }
 
+/// A user phone number
+#[derive(Description)]
+pub struct UserPhone(pub mas_data_model::UserPhone);
+
+#[Object(use_type_description)]
+impl UserPhone {
+    /// ID of the object.
+    pub async fn id(&self) -> ID {
+        NodeType::UserPhone.id(self.0.id)
+    }
+
+    /// Phone number, in the E.164 format
+    async fn phone_number(&self) -> &str {
+        &self.0.phone_number
+    }
+
+    /// When the object was created.
+    pub async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// When the phone number was confirmed. Is `null` if the phone number was
+    /// never verified by the user.
+    async fn confirmed_at(&self) -> Option<DateTime<Utc>> {
+        self.0.confirmed_at
+    }
+}
+
 /// The state of a compatibility session.
 #[derive(Enum, Copy, Clone, Eq, PartialEq)]
 pub enum UserEmailState {
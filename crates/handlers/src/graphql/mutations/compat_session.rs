@@ -110,6 +110,11 @@ impl CompatSessionMutations {
 
         repo.save().await?;
 
+        state
+            .introspection_cache()
+            .invalidate_session(session.id)
+            .await;
+
         Ok(EndCompatSessionPayload::Ended(Box::new(session)))
     }
 }
@@ -71,6 +71,70 @@ impl SetDisplayNamePayload {
     }
 }
 
+/// The input for the `setAvatarUrl` mutation
+#[derive(InputObject)]
+struct SetAvatarUrlInput {
+    /// The ID of the user to set the avatar of
+    user_id: ID,
+
+    /// The `mxc://` URL of the avatar to set. If `None`, the avatar will be
+    /// removed.
+    avatar_url: Option<String>,
+}
+
+/// The status of the `setAvatarUrl` mutation
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum SetAvatarUrlStatus {
+    /// The avatar URL was set
+    Set,
+    /// The avatar URL is invalid
+    Invalid,
+}
+
+/// The payload of the `setAvatarUrl` mutation
+#[derive(Description)]
+enum SetAvatarUrlPayload {
+    Set(User),
+    Invalid,
+}
+
+#[Object(use_type_description)]
+impl SetAvatarUrlPayload {
+    /// Status of the operation
+    async fn status(&self) -> SetAvatarUrlStatus {
+        match self {
+            SetAvatarUrlPayload::Set(_) => SetAvatarUrlStatus::Set,
+            SetAvatarUrlPayload::Invalid => SetAvatarUrlStatus::Invalid,
+        }
+    }
+
+    /// The user that was updated
+    async fn user(&self) -> Option<&User> {
+        match self {
+            SetAvatarUrlPayload::Set(user) => Some(user),
+            SetAvatarUrlPayload::Invalid => None,
+        }
+    }
+}
+
+/// Check that an avatar URL looks like a valid Matrix content URI, in the form
+/// `mxc://<server-name>/<media-id>`
+fn is_valid_avatar_url(avatar_url: &str) -> bool {
+    let Some((server_name, media_id)) = avatar_url
+        .strip_prefix("mxc://")
+        .and_then(|rest| rest.split_once('/'))
+    else {
+        return false;
+    };
+
+    avatar_url.len() <= 512
+        && !server_name.is_empty()
+        && !media_id.is_empty()
+        && media_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
 #[Object]
 impl MatrixMutations {
     /// Set the display name of a user
@@ -92,27 +156,28 @@ impl MatrixMutations {
             return Err(async_graphql::Error::new("Unauthorized"));
         }
 
+        if let Some(display_name) = &input.display_name {
+            // Let's do some basic validation on the display name
+            if display_name.len() > 256 {
+                return Ok(SetDisplayNamePayload::Invalid);
+            }
+
+            if display_name.is_empty() {
+                return Ok(SetDisplayNamePayload::Invalid);
+            }
+        }
+
         let mut repo = state.repository().await?;
         let user = repo
             .user()
             .lookup(id)
             .await?
             .context("Failed to lookup user")?;
-        repo.cancel().await?;
 
         let conn = state.homeserver_connection();
         let mxid = conn.mxid(&user.username);
 
         if let Some(display_name) = &input.display_name {
-            // Let's do some basic validation on the display name
-            if display_name.len() > 256 {
-                return Ok(SetDisplayNamePayload::Invalid);
-            }
-
-            if display_name.is_empty() {
-                return Ok(SetDisplayNamePayload::Invalid);
-            }
-
             conn.set_displayname(&mxid, display_name)
                 .await
                 .context("Failed to set display name")?;
@@ -122,6 +187,65 @@ impl MatrixMutations {
                 .context("Failed to unset display name")?;
         }
 
-        Ok(SetDisplayNamePayload::Set(User(user.clone())))
+        // Only store the display name once the homeserver accepted it
+        let user = repo
+            .user()
+            .set_display_name(user, input.display_name)
+            .await?;
+        repo.save().await?;
+
+        Ok(SetDisplayNamePayload::Set(User(user)))
+    }
+
+    /// Set the avatar URL of a user
+    async fn set_avatar_url(
+        &self,
+        ctx: &Context<'_>,
+        input: SetAvatarUrlInput,
+    ) -> Result<SetAvatarUrlPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let id = NodeType::User.extract_ulid(&input.user_id)?;
+        let requester = ctx.requester();
+
+        if !requester.is_owner_or_admin(&UserId(id)) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        // Allow non-admins to change their avatar if the site config allows them to
+        // change their profile
+        if !requester.is_admin() && !state.site_config().displayname_change_allowed {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        if let Some(avatar_url) = &input.avatar_url {
+            if !is_valid_avatar_url(avatar_url) {
+                return Ok(SetAvatarUrlPayload::Invalid);
+            }
+        }
+
+        let mut repo = state.repository().await?;
+        let user = repo
+            .user()
+            .lookup(id)
+            .await?
+            .context("Failed to lookup user")?;
+
+        let conn = state.homeserver_connection();
+        let mxid = conn.mxid(&user.username);
+
+        if let Some(avatar_url) = &input.avatar_url {
+            conn.set_avatar_url(&mxid, avatar_url)
+                .await
+                .context("Failed to set avatar URL")?;
+        } else {
+            conn.unset_avatar_url(&mxid)
+                .await
+                .context("Failed to unset avatar URL")?;
+        }
+
+        let user = repo.user().set_avatar_url(user, input.avatar_url).await?;
+        repo.save().await?;
+
+        Ok(SetAvatarUrlPayload::Set(User(user)))
     }
 }
@@ -15,9 +15,12 @@
 mod browser_session;
 mod compat_session;
 mod matrix;
+mod network_ban;
 mod oauth2_session;
+mod upstream_oauth;
 mod user;
 mod user_email;
+mod user_phone;
 
 use async_graphql::MergedObject;
 
@@ -25,11 +28,14 @@ use async_graphql::MergedObject;
 #[derive(Default, MergedObject)]
 pub struct Mutation(
     user_email::UserEmailMutations,
+    user_phone::UserPhoneMutations,
     user::UserMutations,
     oauth2_session::OAuth2SessionMutations,
     compat_session::CompatSessionMutations,
     browser_session::BrowserSessionMutations,
     matrix::MatrixMutations,
+    upstream_oauth::UpstreamOAuthMutations,
+    network_ban::NetworkBanMutations,
 );
 
 impl Mutation {
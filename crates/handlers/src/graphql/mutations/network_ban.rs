@@ -0,0 +1,206 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use chrono::Duration;
+use mas_data_model::Network;
+use mas_storage::RepositoryAccess;
+
+use crate::graphql::{
+    model::{NetworkBan, NodeType},
+    state::ContextExt,
+};
+
+/// How long a network is banned for by default, in seconds
+const DEFAULT_BAN_DURATION: i64 = 24 * 60 * 60;
+
+#[derive(Default)]
+pub struct NetworkBanMutations {
+    _private: (),
+}
+
+/// The input for the `banNetwork` mutation.
+#[derive(InputObject)]
+struct BanNetworkInput {
+    /// The network to ban, in the CIDR notation. A single IP address bans only
+    /// that address.
+    network: String,
+
+    /// Why the network is banned.
+    reason: String,
+
+    /// How long the network is banned for, in seconds. Defaults to a day.
+    duration: Option<i64>,
+}
+
+/// The status of the `banNetwork` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum BanNetworkStatus {
+    /// The network was banned.
+    Banned,
+
+    /// The network or the duration is invalid.
+    Invalid,
+}
+
+/// The payload for the `banNetwork` mutation.
+#[derive(Description)]
+enum BanNetworkPayload {
+    Banned(mas_data_model::NetworkBan),
+    Invalid,
+}
+
+#[Object(use_type_description)]
+impl BanNetworkPayload {
+    /// Status of the operation
+    async fn status(&self) -> BanNetworkStatus {
+        match self {
+            Self::Banned(_) => BanNetworkStatus::Banned,
+            Self::Invalid => BanNetworkStatus::Invalid,
+        }
+    }
+
+    /// The ban which was put in place.
+    async fn network_ban(&self) -> Option<NetworkBan> {
+        match self {
+            Self::Banned(ban) => Some(NetworkBan(ban.clone())),
+            Self::Invalid => None,
+        }
+    }
+}
+
+/// The input for the `liftNetworkBan` mutation.
+#[derive(InputObject)]
+struct LiftNetworkBanInput {
+    /// The ID of the ban to lift.
+    network_ban_id: ID,
+}
+
+/// The status of the `liftNetworkBan` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum LiftNetworkBanStatus {
+    /// The ban was lifted.
+    Lifted,
+
+    /// The ban was not found, or is not in effect anymore.
+    NotFound,
+}
+
+/// The payload for the `liftNetworkBan` mutation.
+#[derive(Description)]
+enum LiftNetworkBanPayload {
+    Lifted(mas_data_model::NetworkBan),
+    NotFound,
+}
+
+#[Object(use_type_description)]
+impl LiftNetworkBanPayload {
+    /// Status of the operation
+    async fn status(&self) -> LiftNetworkBanStatus {
+        match self {
+            Self::Lifted(_) => LiftNetworkBanStatus::Lifted,
+            Self::NotFound => LiftNetworkBanStatus::NotFound,
+        }
+    }
+
+    /// The ban which was lifted.
+    async fn network_ban(&self) -> Option<NetworkBan> {
+        match self {
+            Self::Lifted(ban) => Some(NetworkBan(ban.clone())),
+            Self::NotFound => None,
+        }
+    }
+}
+
+#[Object]
+impl NetworkBanMutations {
+    /// Ban a network, so that it can't reach the service until the ban
+    /// expires. This is only available to administrators.
+    async fn ban_network(
+        &self,
+        ctx: &Context<'_>,
+        input: BanNetworkInput,
+    ) -> Result<BanNetworkPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let Ok(network) = input.network.parse::<Network>() else {
+            return Ok(BanNetworkPayload::Invalid);
+        };
+
+        let duration = input.duration.unwrap_or(DEFAULT_BAN_DURATION);
+        let Some(duration) = Duration::try_seconds(duration).filter(|d| *d > Duration::zero())
+        else {
+            return Ok(BanNetworkPayload::Invalid);
+        };
+
+        let clock = state.clock();
+        let mut rng = state.rng();
+        let mut repo = state.repository().await?;
+
+        let ban = repo
+            .network_ban()
+            .add(
+                &mut rng,
+                &clock,
+                network,
+                Some(input.reason),
+                clock.now() + duration,
+            )
+            .await?;
+
+        repo.save().await?;
+
+        state.network_bans().insert(ban.clone());
+
+        Ok(BanNetworkPayload::Banned(ban))
+    }
+
+    /// Lift a network ban before it expires. This is only available to
+    /// administrators.
+    async fn lift_network_ban(
+        &self,
+        ctx: &Context<'_>,
+        input: LiftNetworkBanInput,
+    ) -> Result<LiftNetworkBanPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let clock = state.clock();
+        let mut repo = state.repository().await?;
+
+        let id = NodeType::NetworkBan.extract_ulid(&input.network_ban_id)?;
+        let ban = repo.network_ban().lookup(id).await?;
+
+        let Some(ban) = ban.filter(|ban| ban.is_active(clock.now())) else {
+            return Ok(LiftNetworkBanPayload::NotFound);
+        };
+
+        let ban = repo.network_ban().lift(&clock, ban).await?;
+
+        repo.save().await?;
+
+        state.network_bans().remove(ban.id);
+
+        Ok(LiftNetworkBanPayload::Lifted(ban))
+    }
+}
@@ -256,6 +256,11 @@ impl OAuth2SessionMutations {
 
         repo.save().await?;
 
+        state
+            .introspection_cache()
+            .invalidate_session(session.id)
+            .await;
+
         Ok(EndOAuth2SessionPayload::Ended(session))
     }
 }
@@ -0,0 +1,158 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use chrono::{DateTime, Utc};
+use mas_storage::{
+    upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository},
+    RepositoryAccess,
+};
+
+use crate::{
+    graphql::{model::NodeType, state::ContextExt},
+    upstream_oauth2::tokens::{fresh_access_token, FreshAccessToken, UpstreamAccessToken},
+};
+
+#[derive(Default)]
+pub struct UpstreamOAuthMutations {
+    _private: (),
+}
+
+/// The input for the `fetchUpstreamOauth2AccessToken` mutation.
+#[derive(InputObject)]
+struct FetchUpstreamOAuth2AccessTokenInput {
+    /// The ID of the upstream OAuth 2.0 link to get an access token for.
+    upstream_oauth2_link_id: ID,
+}
+
+/// The status of the `fetchUpstreamOauth2AccessToken` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum FetchUpstreamOAuth2AccessTokenStatus {
+    /// A valid access token was returned.
+    Fetched,
+
+    /// The link was not found.
+    NotFound,
+
+    /// No tokens are stored for this link, either because the provider is not
+    /// configured to store them, or because the user did not log in through
+    /// it since.
+    NotStored,
+
+    /// The access token expired and could not be refreshed. The user has to
+    /// log in again through the provider.
+    Expired,
+}
+
+/// The payload for the `fetchUpstreamOauth2AccessToken` mutation.
+#[derive(Description)]
+enum FetchUpstreamOAuth2AccessTokenPayload {
+    /// A valid access token was returned.
+    Fetched(UpstreamAccessToken),
+
+    /// The link was not found.
+    NotFound,
+
+    /// No tokens are stored for this link.
+    NotStored,
+
+    /// The access token expired and could not be refreshed.
+    Expired,
+}
+
+#[Object(use_type_description)]
+impl FetchUpstreamOAuth2AccessTokenPayload {
+    /// Status of the operation
+    async fn status(&self) -> FetchUpstreamOAuth2AccessTokenStatus {
+        match self {
+            Self::Fetched(_) => FetchUpstreamOAuth2AccessTokenStatus::Fetched,
+            Self::NotFound => FetchUpstreamOAuth2AccessTokenStatus::NotFound,
+            Self::NotStored => FetchUpstreamOAuth2AccessTokenStatus::NotStored,
+            Self::Expired => FetchUpstreamOAuth2AccessTokenStatus::Expired,
+        }
+    }
+
+    /// The access token issued by the upstream provider.
+    async fn access_token(&self) -> Option<&str> {
+        match self {
+            Self::Fetched(token) => Some(&token.access_token),
+            _ => None,
+        }
+    }
+
+    /// When the access token expires, if known.
+    async fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Fetched(token) => token.expires_at,
+            _ => None,
+        }
+    }
+}
+
+#[Object]
+impl UpstreamOAuthMutations {
+    /// Get a valid access token issued by an upstream provider for a user,
+    /// refreshing it if needed, so that it can be used to call the provider on
+    /// their behalf. This only works for providers which have `store_tokens`
+    /// enabled, and is only available to administrators.
+    async fn fetch_upstream_oauth2_access_token(
+        &self,
+        ctx: &Context<'_>,
+        input: FetchUpstreamOAuth2AccessTokenInput,
+    ) -> Result<FetchUpstreamOAuth2AccessTokenPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let link_id = NodeType::UpstreamOAuth2Link.extract_ulid(&input.upstream_oauth2_link_id)?;
+
+        let mut repo = state.repository().await?;
+        let Some(link) = repo.upstream_oauth_link().lookup(link_id).await? else {
+            return Ok(FetchUpstreamOAuth2AccessTokenPayload::NotFound);
+        };
+
+        let Some(provider) = repo
+            .upstream_oauth_provider()
+            .lookup(link.provider_id)
+            .await?
+        else {
+            return Ok(FetchUpstreamOAuth2AccessTokenPayload::NotFound);
+        };
+
+        let mut rng = state.rng();
+        let clock = state.clock();
+        let result = fresh_access_token(
+            &mut repo,
+            &mut rng,
+            &clock,
+            state.http_client_factory(),
+            state.metadata_cache(),
+            state.key_store(),
+            state.encrypter(),
+            &provider,
+            &link,
+        )
+        .await?;
+
+        repo.save().await?;
+
+        Ok(match result {
+            FreshAccessToken::Valid(token) => FetchUpstreamOAuth2AccessTokenPayload::Fetched(token),
+            FreshAccessToken::NotStored => FetchUpstreamOAuth2AccessTokenPayload::NotStored,
+            FreshAccessToken::Expired => FetchUpstreamOAuth2AccessTokenPayload::Expired,
+        })
+    }
+}
@@ -14,17 +14,27 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use mas_data_model::Device;
 use mas_storage::{
-    job::{DeactivateUserJob, JobRepositoryExt, ProvisionUserJob},
-    user::UserRepository,
+    compat::{CompatSessionFilter, CompatSessionRepository},
+    job::{
+        DeactivateUserJob, DeleteDeviceJob, JobRepositoryExt, ProvisionUserJob,
+        SendPasswordChangeNotificationJob,
+    },
+    oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
+    user::{BrowserSessionFilter, BrowserSessionRepository, UserRepository},
+    Pagination,
 };
 use tracing::{info, warn};
 use zeroize::Zeroizing;
 
-use crate::graphql::{
-    model::{NodeType, User},
-    state::ContextExt,
-    UserId,
+use crate::{
+    graphql::{
+        model::{NodeType, User},
+        state::ContextExt,
+        Requester, UserId,
+    },
+    views::shared::requires_cross_signing_reset_reauth,
 };
 
 #[derive(Default)]
@@ -44,6 +54,12 @@ struct AddUserInput {
     /// by an application service needs to exist in MAS to craft special
     /// tokens (like with admin access) for them
     skip_homeserver_check: Option<bool>,
+
+    /// Flag the user as a non-interactive account, used by a bot or a bridge.
+    ///
+    /// Long-lived tokens can then be issued for it with the
+    /// `createOauth2Session` mutation.
+    bot: Option<bool>,
 }
 
 /// The status of the `addUser` mutation.
@@ -141,6 +157,52 @@ impl LockUserPayload {
     }
 }
 
+/// The input for the `unlockUser` mutation.
+#[derive(InputObject)]
+struct UnlockUserInput {
+    /// The ID of the user to unlock.
+    user_id: ID,
+}
+
+/// The status of the `unlockUser` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum UnlockUserStatus {
+    /// The user was unlocked.
+    Unlocked,
+
+    /// The user was not found.
+    NotFound,
+}
+
+/// The payload for the `unlockUser` mutation.
+#[derive(Description)]
+enum UnlockUserPayload {
+    /// The user was unlocked.
+    Unlocked(mas_data_model::User),
+
+    /// The user was not found.
+    NotFound,
+}
+
+#[Object(use_type_description)]
+impl UnlockUserPayload {
+    /// Status of the operation
+    async fn status(&self) -> UnlockUserStatus {
+        match self {
+            Self::Unlocked(_) => UnlockUserStatus::Unlocked,
+            Self::NotFound => UnlockUserStatus::NotFound,
+        }
+    }
+
+    /// The user that was unlocked.
+    async fn user(&self) -> Option<User> {
+        match self {
+            Self::Unlocked(user) => Some(User(user.clone())),
+            Self::NotFound => None,
+        }
+    }
+}
+
 /// The input for the `setCanRequestAdmin` mutation.
 #[derive(InputObject)]
 struct SetCanRequestAdminInput {
@@ -172,6 +234,37 @@ impl SetCanRequestAdminPayload {
     }
 }
 
+/// The input for the `setShadowBanned` mutation.
+#[derive(InputObject)]
+struct SetShadowBannedInput {
+    /// The ID of the user to update.
+    user_id: ID,
+
+    /// Whether the user is shadow-banned.
+    shadow_banned: bool,
+}
+
+/// The payload for the `setShadowBanned` mutation.
+#[derive(Description)]
+enum SetShadowBannedPayload {
+    /// The user was updated.
+    Updated(mas_data_model::User),
+
+    /// The user was not found.
+    NotFound,
+}
+
+#[Object(use_type_description)]
+impl SetShadowBannedPayload {
+    /// The user that was updated.
+    async fn user(&self) -> Option<User> {
+        match self {
+            Self::Updated(user) => Some(User(user.clone())),
+            Self::NotFound => None,
+        }
+    }
+}
+
 /// The input for the `allowUserCrossSigningReset` mutation.
 #[derive(InputObject)]
 struct AllowUserCrossSigningResetInput {
@@ -200,6 +293,54 @@ impl AllowUserCrossSigningResetPayload {
     }
 }
 
+/// The input for the `endAllSessions` mutation.
+#[derive(InputObject)]
+struct EndAllSessionsInput {
+    /// The ID of the user whose sessions should be ended.
+    /// If you are not a server administrator then this must be your own user
+    /// ID.
+    user_id: ID,
+}
+
+/// The status of the `endAllSessions` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum EndAllSessionsStatus {
+    /// All the sessions of the user were ended.
+    Ended,
+
+    /// The user was not found.
+    NotFound,
+}
+
+/// The payload for the `endAllSessions` mutation.
+#[derive(Description)]
+enum EndAllSessionsPayload {
+    /// All the sessions of the user were ended.
+    Ended(mas_data_model::User),
+
+    /// The user was not found.
+    NotFound,
+}
+
+#[Object(use_type_description)]
+impl EndAllSessionsPayload {
+    /// Status of the operation
+    async fn status(&self) -> EndAllSessionsStatus {
+        match self {
+            Self::Ended(_) => EndAllSessionsStatus::Ended,
+            Self::NotFound => EndAllSessionsStatus::NotFound,
+        }
+    }
+
+    /// The user whose sessions were ended.
+    async fn user(&self) -> Option<User> {
+        match self {
+            Self::Ended(user) => Some(User(user.clone())),
+            Self::NotFound => None,
+        }
+    }
+}
+
 /// The input for the `setPassword` mutation.
 #[derive(InputObject)]
 struct SetPasswordInput {
@@ -335,6 +476,12 @@ impl UserMutations {
 
         let user = repo.user().add(&mut rng, &clock, input.username).await?;
 
+        let user = if input.bot.unwrap_or(false) {
+            repo.user().set_bot(user, true).await?
+        } else {
+            user
+        };
+
         repo.job()
             .schedule_job(ProvisionUserJob::new(&user))
             .await?;
@@ -379,9 +526,43 @@ impl UserMutations {
 
         repo.save().await?;
 
+        state.introspection_cache().invalidate_user(user.id).await;
+
         Ok(LockUserPayload::Locked(user))
     }
 
+    /// Unlock a user, allowing them to sign in again and making their existing
+    /// sessions usable again. This is only available to administrators.
+    async fn unlock_user(
+        &self,
+        ctx: &Context<'_>,
+        input: UnlockUserInput,
+    ) -> Result<UnlockUserPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let mut repo = state.repository().await?;
+
+        let user_id = NodeType::User.extract_ulid(&input.user_id)?;
+        let user = repo.user().lookup(user_id).await?;
+
+        let Some(user) = user else {
+            return Ok(UnlockUserPayload::NotFound);
+        };
+
+        let user = repo.user().unlock(user).await?;
+
+        repo.save().await?;
+
+        state.introspection_cache().invalidate_user(user.id).await;
+
+        Ok(UnlockUserPayload::Unlocked(user))
+    }
+
     /// Set whether a user can request admin. This is only available to
     /// administrators.
     async fn set_can_request_admin(
@@ -415,6 +596,47 @@ impl UserMutations {
         Ok(SetCanRequestAdminPayload::Updated(user))
     }
 
+    /// Set whether a user is shadow-banned. Shadow-banned users can still sign
+    /// in and use their sessions, but their tokens are flagged on
+    /// introspection, so that the homeserver can silently drop their traffic.
+    /// This is only available to administrators.
+    async fn set_shadow_banned(
+        &self,
+        ctx: &Context<'_>,
+        input: SetShadowBannedInput,
+    ) -> Result<SetShadowBannedPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let mut repo = state.repository().await?;
+
+        let user_id = NodeType::User.extract_ulid(&input.user_id)?;
+        let user = repo.user().lookup(user_id).await?;
+
+        let Some(user) = user else {
+            return Ok(SetShadowBannedPayload::NotFound);
+        };
+
+        let user = if input.shadow_banned {
+            info!(user.id = %user.id, "Shadow-banning user");
+            repo.user().shadow_ban(&state.clock(), user).await?
+        } else {
+            info!(user.id = %user.id, "Lifting the shadow-ban of user");
+            repo.user().lift_shadow_ban(user).await?
+        };
+
+        repo.save().await?;
+
+        // Cached introspection results would not reflect the new state
+        state.introspection_cache().invalidate_user(user.id).await;
+
+        Ok(SetShadowBannedPayload::Updated(user))
+    }
+
     /// Temporarily allow user to reset their cross-signing keys.
     async fn allow_user_cross_signing_reset(
         &self,
@@ -431,6 +653,19 @@ impl UserMutations {
 
         let mut repo = state.repository().await?;
         let user = repo.user().lookup(user_id).await?;
+
+        // Users approving the reset from their browser must have authenticated
+        // recently
+        if let Requester::BrowserSession(session) = requester {
+            let clock = state.clock();
+            if requires_cross_signing_reset_reauth(&mut repo, &clock, state.site_config(), session)
+                .await?
+            {
+                repo.cancel().await?;
+                return Err(async_graphql::Error::new("Recent authentication required"));
+            }
+        }
+
         repo.cancel().await?;
 
         let Some(user) = user else {
@@ -447,6 +682,109 @@ impl UserMutations {
         Ok(AllowUserCrossSigningResetPayload::Allowed(user))
     }
 
+    /// End all the sessions of a user: browser sessions, OAuth 2.0 sessions
+    /// and compatibility sessions, along with their tokens. The devices
+    /// belonging to those sessions are deleted from the homeserver.
+    ///
+    /// This can be used by server administrators on any user, or by a user on
+    /// their own account.
+    async fn end_all_sessions(
+        &self,
+        ctx: &Context<'_>,
+        input: EndAllSessionsInput,
+    ) -> Result<EndAllSessionsPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let user_id = NodeType::User.extract_ulid(&input.user_id)?;
+        let requester = ctx.requester();
+
+        if !requester.is_owner_or_admin(&UserId(user_id)) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let mut repo = state.repository().await?;
+        let clock = state.clock();
+
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            return Ok(EndAllSessionsPayload::NotFound);
+        };
+
+        // Sessions are finished as we go, so we always look at the first page of
+        // the remaining active sessions
+        let pagination = Pagination::first(100);
+
+        loop {
+            let page = repo
+                .compat_session()
+                .list(
+                    CompatSessionFilter::new().for_user(&user).active_only(),
+                    pagination,
+                )
+                .await?;
+
+            if page.edges.is_empty() {
+                break;
+            }
+
+            for (session, _) in page.edges {
+                repo.job()
+                    .schedule_job(DeleteDeviceJob::new(&user, &session.device))
+                    .await?;
+                repo.compat_session().finish(&clock, session).await?;
+            }
+        }
+
+        loop {
+            let page = repo
+                .oauth2_session()
+                .list(
+                    OAuth2SessionFilter::new().for_user(&user).active_only(),
+                    pagination,
+                )
+                .await?;
+
+            if page.edges.is_empty() {
+                break;
+            }
+
+            for session in page.edges {
+                for scope in &*session.scope {
+                    if let Some(device) = Device::from_scope_token(scope) {
+                        repo.job()
+                            .schedule_job(DeleteDeviceJob::new(&user, &device))
+                            .await?;
+                    }
+                }
+                repo.oauth2_session().finish(&clock, session).await?;
+            }
+        }
+
+        loop {
+            let page = repo
+                .browser_session()
+                .list(
+                    BrowserSessionFilter::new().for_user(&user).active_only(),
+                    pagination,
+                )
+                .await?;
+
+            if page.edges.is_empty() {
+                break;
+            }
+
+            for session in page.edges {
+                repo.browser_session().finish(&clock, session).await?;
+            }
+        }
+
+        repo.save().await?;
+
+        info!(user.id = %user.id, "Ended all the sessions of the user");
+
+        state.introspection_cache().invalidate_user(user.id).await;
+
+        Ok(EndAllSessionsPayload::Ended(user))
+    }
+
     /// Set the password for a user.
     ///
     /// This can be used by server administrators to set any user's password,
@@ -544,6 +882,10 @@ impl UserMutations {
             )
             .await?;
 
+        repo.job()
+            .schedule_job(SendPasswordChangeNotificationJob::new(&user))
+            .await?;
+
         repo.save().await?;
 
         Ok(SetPasswordPayload {
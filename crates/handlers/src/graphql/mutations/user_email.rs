@@ -15,10 +15,11 @@
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob, VerifyEmailJob},
-    user::{UserEmailRepository, UserRepository},
+    job::{JobRepositoryExt, ProvisionUserJob, SendEmailChangeNotificationJob, VerifyEmailJob},
+    user::{UserEmailChangeRepository, UserEmailRepository, UserRepository},
     RepositoryAccess,
 };
+use rand::distributions::{Alphanumeric, DistString};
 
 use crate::graphql::{
     model::{NodeType, User, UserEmail},
@@ -664,9 +665,45 @@ impl UserEmailMutations {
             return Ok(SetPrimaryEmailPayload::Unverified);
         }
 
+        let user = repo
+            .user()
+            .lookup(user_email.user_id)
+            .await?
+            .context("Failed to load user")?;
+
+        let previous_email = repo.user_email().get_primary(&user).await?;
+
         repo.user_email().set_as_primary(&user_email).await?;
 
-        // The user primary email should already be up to date
+        // Let the previous address know about the change, so that it can be undone
+        // if it wasn't the user
+        if let Some(previous_email) = previous_email.filter(|e| e.id != user_email.id) {
+            let clock = state.clock();
+            let mut rng = state.rng();
+
+            let undo_ticket = Alphanumeric.sample_string(&mut rng, 32);
+            let change = repo
+                .user_email_change()
+                .add(
+                    &mut rng,
+                    &clock,
+                    &user,
+                    previous_email.email,
+                    user_email.email.clone(),
+                    undo_ticket,
+                )
+                .await?;
+            let change = repo
+                .user_email_change()
+                .mark_as_confirmed(&clock, change)
+                .await?;
+
+            repo.job()
+                .schedule_job(SendEmailChangeNotificationJob::new(&change))
+                .await?;
+        }
+
+        // The user primary email should now be up to date
         let user = repo
             .user()
             .lookup(user_email.user_id)
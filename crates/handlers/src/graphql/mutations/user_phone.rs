@@ -0,0 +1,356 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context as _;
+use async_graphql::{Context, Description, Enum, InputObject, Object, ID};
+use mas_storage::{
+    job::{JobRepositoryExt, VerifyPhoneJob},
+    user::{UserPhoneRepository, UserRepository},
+    RepositoryAccess,
+};
+
+use crate::graphql::{
+    model::{NodeType, User, UserPhone},
+    state::ContextExt,
+    UserId,
+};
+
+#[derive(Default)]
+pub struct UserPhoneMutations {
+    _private: (),
+}
+
+/// The input for the `addPhone` mutation
+#[derive(InputObject)]
+struct AddPhoneInput {
+    /// The phone number to add, in the E.164 format
+    phone_number: String,
+
+    /// The ID of the user to add the phone number to
+    user_id: ID,
+
+    /// Skip the phone number verification. Only allowed for admins.
+    skip_verification: Option<bool>,
+}
+
+/// The status of the `addPhone` mutation
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AddPhoneStatus {
+    /// The phone number was added
+    Added,
+    /// The phone number already exists
+    Exists,
+    /// The phone number is invalid
+    Invalid,
+}
+
+/// The payload of the `addPhone` mutation
+#[derive(Description)]
+enum AddPhonePayload {
+    Added(mas_data_model::UserPhone),
+    Exists(mas_data_model::UserPhone),
+    Invalid,
+}
+
+#[Object(use_type_description)]
+impl AddPhonePayload {
+    /// Status of the operation
+    async fn status(&self) -> AddPhoneStatus {
+        match self {
+            AddPhonePayload::Added(_) => AddPhoneStatus::Added,
+            AddPhonePayload::Exists(_) => AddPhoneStatus::Exists,
+            AddPhonePayload::Invalid => AddPhoneStatus::Invalid,
+        }
+    }
+
+    /// The phone number that was added
+    async fn phone(&self) -> Option<UserPhone> {
+        match self {
+            AddPhonePayload::Added(phone) | AddPhonePayload::Exists(phone) => {
+                Some(UserPhone(phone.clone()))
+            }
+            AddPhonePayload::Invalid => None,
+        }
+    }
+
+    /// The user to whom the phone number was added
+    async fn user(&self, ctx: &Context<'_>) -> Result<Option<User>, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+
+        let user_id = match self {
+            AddPhonePayload::Added(phone) | AddPhonePayload::Exists(phone) => phone.user_id,
+            AddPhonePayload::Invalid => return Ok(None),
+        };
+
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .context("User not found")?;
+
+        Ok(Some(User(user)))
+    }
+}
+
+/// The input for the `verifyPhone` mutation
+#[derive(InputObject)]
+struct VerifyPhoneInput {
+    /// The ID of the phone number to verify
+    user_phone_id: ID,
+    /// The verification code
+    code: String,
+}
+
+/// The status of the `verifyPhone` mutation
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum VerifyPhoneStatus {
+    /// The phone number was just verified
+    Verified,
+    /// The phone number was already verified before
+    AlreadyVerified,
+    /// The verification code is invalid
+    InvalidCode,
+}
+
+/// The payload of the `verifyPhone` mutation
+#[derive(Description)]
+enum VerifyPhonePayload {
+    Verified(mas_data_model::UserPhone),
+    AlreadyVerified(mas_data_model::UserPhone),
+    InvalidCode,
+}
+
+#[Object(use_type_description)]
+impl VerifyPhonePayload {
+    /// Status of the operation
+    async fn status(&self) -> VerifyPhoneStatus {
+        match self {
+            VerifyPhonePayload::Verified(_) => VerifyPhoneStatus::Verified,
+            VerifyPhonePayload::AlreadyVerified(_) => VerifyPhoneStatus::AlreadyVerified,
+            VerifyPhonePayload::InvalidCode => VerifyPhoneStatus::InvalidCode,
+        }
+    }
+
+    /// The phone number that was verified
+    async fn phone(&self) -> Option<UserPhone> {
+        match self {
+            VerifyPhonePayload::Verified(phone) | VerifyPhonePayload::AlreadyVerified(phone) => {
+                Some(UserPhone(phone.clone()))
+            }
+            VerifyPhonePayload::InvalidCode => None,
+        }
+    }
+}
+
+/// The input for the `removePhone` mutation
+#[derive(InputObject)]
+struct RemovePhoneInput {
+    /// The ID of the phone number to remove
+    user_phone_id: ID,
+}
+
+/// The status of the `removePhone` mutation
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum RemovePhoneStatus {
+    /// The phone number was removed
+    Removed,
+
+    /// The phone number was not found
+    NotFound,
+}
+
+/// The payload of the `removePhone` mutation
+#[derive(Description)]
+enum RemovePhonePayload {
+    Removed(mas_data_model::UserPhone),
+    NotFound,
+}
+
+#[Object(use_type_description)]
+impl RemovePhonePayload {
+    /// Status of the operation
+    async fn status(&self) -> RemovePhoneStatus {
+        match self {
+            RemovePhonePayload::Removed(_) => RemovePhoneStatus::Removed,
+            RemovePhonePayload::NotFound => RemovePhoneStatus::NotFound,
+        }
+    }
+
+    /// The phone number that was removed
+    async fn phone(&self) -> Option<UserPhone> {
+        match self {
+            RemovePhonePayload::Removed(phone) => Some(UserPhone(phone.clone())),
+            RemovePhonePayload::NotFound => None,
+        }
+    }
+}
+
+#[Object]
+impl UserPhoneMutations {
+    /// Add a phone number to the specified user. If the phone number was
+    /// already added but not verified yet, a new verification code is sent.
+    async fn add_phone(
+        &self,
+        ctx: &Context<'_>,
+        input: AddPhoneInput,
+    ) -> Result<AddPhonePayload, async_graphql::Error> {
+        let state = ctx.state();
+        let id = NodeType::User.extract_ulid(&input.user_id)?;
+        let requester = ctx.requester();
+
+        if !requester.is_owner_or_admin(&UserId(id)) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        // Only admins can skip validation
+        if input.skip_verification.is_some() && !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let skip_verification = input.skip_verification.unwrap_or(false);
+
+        if !mas_data_model::UserPhone::is_valid_number(&input.phone_number) {
+            return Ok(AddPhonePayload::Invalid);
+        }
+
+        let mut repo = state.repository().await?;
+
+        let user = repo
+            .user()
+            .lookup(id)
+            .await?
+            .context("Failed to load user")?;
+
+        // Find an existing phone number
+        let existing_user_phone = repo.user_phone().find(&user, &input.phone_number).await?;
+        let (added, mut user_phone) = if let Some(user_phone) = existing_user_phone {
+            (false, user_phone)
+        } else {
+            let clock = state.clock();
+            let mut rng = state.rng();
+
+            let user_phone = repo
+                .user_phone()
+                .add(&mut rng, &clock, &user, input.phone_number)
+                .await?;
+
+            (true, user_phone)
+        };
+
+        // Schedule a job to verify the phone number if needed
+        if user_phone.confirmed_at.is_none() {
+            if skip_verification {
+                user_phone = repo
+                    .user_phone()
+                    .mark_as_verified(&state.clock(), user_phone)
+                    .await?;
+            } else {
+                // TODO: figure out the locale
+                repo.job()
+                    .schedule_job(VerifyPhoneJob::new(&user_phone))
+                    .await?;
+            }
+        }
+
+        repo.save().await?;
+
+        let payload = if added {
+            AddPhonePayload::Added(user_phone)
+        } else {
+            AddPhonePayload::Exists(user_phone)
+        };
+        Ok(payload)
+    }
+
+    /// Submit a verification code for a phone number
+    async fn verify_phone(
+        &self,
+        ctx: &Context<'_>,
+        input: VerifyPhoneInput,
+    ) -> Result<VerifyPhonePayload, async_graphql::Error> {
+        let state = ctx.state();
+        let user_phone_id = NodeType::UserPhone.extract_ulid(&input.user_phone_id)?;
+        let requester = ctx.requester();
+
+        let clock = state.clock();
+        let mut repo = state.repository().await?;
+
+        let user_phone = repo
+            .user_phone()
+            .lookup(user_phone_id)
+            .await?
+            .context("User phone not found")?;
+
+        if !requester.is_owner_or_admin(&user_phone) {
+            return Err(async_graphql::Error::new("User phone not found"));
+        }
+
+        if user_phone.confirmed_at.is_some() {
+            return Ok(VerifyPhonePayload::AlreadyVerified(user_phone));
+        }
+
+        // Find the verification code
+        let verification = repo
+            .user_phone()
+            .find_verification_code(&clock, &user_phone, &input.code)
+            .await?
+            .filter(|v| v.is_valid());
+
+        let Some(verification) = verification else {
+            return Ok(VerifyPhonePayload::InvalidCode);
+        };
+
+        repo.user_phone()
+            .consume_verification_code(&clock, verification)
+            .await?;
+
+        let user_phone = repo
+            .user_phone()
+            .mark_as_verified(&clock, user_phone)
+            .await?;
+
+        repo.save().await?;
+
+        Ok(VerifyPhonePayload::Verified(user_phone))
+    }
+
+    /// Remove a phone number
+    async fn remove_phone(
+        &self,
+        ctx: &Context<'_>,
+        input: RemovePhoneInput,
+    ) -> Result<RemovePhonePayload, async_graphql::Error> {
+        let state = ctx.state();
+        let user_phone_id = NodeType::UserPhone.extract_ulid(&input.user_phone_id)?;
+        let requester = ctx.requester();
+
+        let mut repo = state.repository().await?;
+
+        let user_phone = repo.user_phone().lookup(user_phone_id).await?;
+        let Some(user_phone) = user_phone else {
+            return Ok(RemovePhonePayload::NotFound);
+        };
+
+        if !requester.is_owner_or_admin(&user_phone) {
+            return Ok(RemovePhonePayload::NotFound);
+        }
+
+        repo.user_phone().remove(user_phone.clone()).await?;
+
+        repo.save().await?;
+
+        Ok(RemovePhonePayload::Removed(user_phone))
+    }
+}
@@ -18,21 +18,31 @@ use mas_storage::user::UserRepository;
 use crate::graphql::{
     model::{
         Anonymous, BrowserSession, CompatSession, Node, NodeType, OAuth2Client, OAuth2Session,
-        SiteConfig, User, UserEmail,
+        SiteConfig, User, UserEmail, UserPhone,
     },
     state::ContextExt,
     UserId,
 };
 
+mod network_ban;
 mod session;
 mod upstream_oauth;
 mod viewer;
 
-use self::{session::SessionQuery, upstream_oauth::UpstreamOAuthQuery, viewer::ViewerQuery};
+use self::{
+    network_ban::NetworkBanQuery, session::SessionQuery, upstream_oauth::UpstreamOAuthQuery,
+    viewer::ViewerQuery,
+};
 
 /// The query root of the GraphQL interface.
 #[derive(Default, MergedObject)]
-pub struct Query(BaseQuery, UpstreamOAuthQuery, SessionQuery, ViewerQuery);
+pub struct Query(
+    BaseQuery,
+    UpstreamOAuthQuery,
+    SessionQuery,
+    ViewerQuery,
+    NetworkBanQuery,
+);
 
 impl Query {
     #[must_use]
@@ -227,6 +237,31 @@ impl BaseQuery {
         Ok(Some(UserEmail(user_email)))
     }
 
+    /// Fetch a user phone number by its ID.
+    async fn user_phone(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<Option<UserPhone>, async_graphql::Error> {
+        let state = ctx.state();
+        let id = NodeType::UserPhone.extract_ulid(&id)?;
+        let requester = ctx.requester();
+
+        let mut repo = state.repository().await?;
+        let user_phone = repo.user_phone().lookup(id).await?;
+        repo.cancel().await?;
+
+        let Some(user_phone) = user_phone else {
+            return Ok(None);
+        };
+
+        if !requester.is_owner_or_admin(&user_phone) {
+            return Ok(None);
+        }
+
+        Ok(Some(UserPhone(user_phone)))
+    }
+
     /// Fetches an object given its ID.
     async fn node(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Node>, async_graphql::Error> {
         // Special case for the anonymous user
@@ -244,7 +279,7 @@ impl BaseQuery {
 
         let ret = match node_type {
             // TODO
-            NodeType::Authentication | NodeType::CompatSsoLogin => None,
+            NodeType::Authentication | NodeType::CompatSsoLogin | NodeType::NetworkBan => None,
 
             NodeType::UpstreamOAuth2Provider => UpstreamOAuthQuery
                 .upstream_oauth2_provider(ctx, id)
@@ -266,6 +301,11 @@ impl BaseQuery {
                 .await?
                 .map(|e| Node::UserEmail(Box::new(e))),
 
+            NodeType::UserPhone => self
+                .user_phone(ctx, id)
+                .await?
+                .map(|p| Node::UserPhone(Box::new(p))),
+
             NodeType::CompatSession => self
                 .compat_session(ctx, id)
                 .await?
@@ -0,0 +1,45 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_graphql::{Context, Object};
+use mas_storage::RepositoryAccess;
+
+use crate::graphql::{model::NetworkBan, state::ContextExt};
+
+#[derive(Default)]
+pub struct NetworkBanQuery;
+
+#[Object]
+impl NetworkBanQuery {
+    /// Get the networks which are currently banned, most recent first. This
+    /// is only available to administrators.
+    async fn network_bans(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<NetworkBan>, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let clock = state.clock();
+        let mut repo = state.repository().await?;
+        let bans = repo.network_ban().all_active(&clock).await?;
+        repo.cancel().await?;
+
+        Ok(bans.into_iter().map(NetworkBan).collect())
+    }
+}
@@ -13,11 +13,15 @@
 // limitations under the License.
 
 use mas_data_model::SiteConfig;
+use mas_keystore::{Encrypter, Keystore};
 use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
 use mas_storage::{BoxClock, BoxRepository, BoxRng, RepositoryError};
 
-use crate::{graphql::Requester, passwords::PasswordManager};
+use crate::{
+    graphql::Requester, passwords::PasswordManager, GeoIp, HttpClientFactory, IntrospectionCache,
+    MetadataCache, NetworkBans,
+};
 
 #[async_trait::async_trait]
 pub trait State {
@@ -28,6 +32,13 @@ pub trait State {
     fn clock(&self) -> BoxClock;
     fn rng(&self) -> BoxRng;
     fn site_config(&self) -> &SiteConfig;
+    fn introspection_cache(&self) -> &IntrospectionCache;
+    fn geoip(&self) -> &GeoIp;
+    fn encrypter(&self) -> &Encrypter;
+    fn key_store(&self) -> &Keystore;
+    fn http_client_factory(&self) -> &HttpClientFactory;
+    fn metadata_cache(&self) -> &MetadataCache;
+    fn network_bans(&self) -> &NetworkBans;
 }
 
 pub type BoxState = Box<dyn State + Send + Sync + 'static>;
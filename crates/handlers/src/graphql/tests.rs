@@ -13,16 +13,28 @@
 // limitations under the License.
 
 use axum::http::Request;
-use hyper::StatusCode;
-use mas_data_model::{AccessToken, Client, TokenType, User};
+use chrono::Duration;
+use hyper::{header::WWW_AUTHENTICATE, StatusCode};
+use mas_data_model::{
+    AccessToken, Client, TokenType, UpstreamOAuthProviderClaimsImports,
+    UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderOnDeprovision,
+    UpstreamOAuthProviderPkceMode, User,
+};
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_matrix::{HomeserverConnection, ProvisionRequest};
 use mas_router::SimpleRoute;
 use mas_storage::{
+    idempotency::IdempotencyKeyRepository,
     oauth2::{OAuth2AccessTokenRepository, OAuth2ClientRepository},
-    RepositoryAccess,
+    upstream_oauth2::{
+        UpstreamOAuthLinkRepository, UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository,
+    },
+    user::BrowserSessionFilter,
+    Clock, Repository, RepositoryAccess,
 };
 use oauth2_types::{
     registration::ClientRegistrationResponse,
-    requests::AccessTokenResponse,
+    requests::{AccessTokenResponse, IntrospectionResponse},
     scope::{Scope, ScopeToken, OPENID},
 };
 use sqlx::PgPool;
@@ -256,7 +268,11 @@ async fn test_oauth2_no_scope(pool: PgPool) {
         }));
 
     let response = state.request(req).await;
-    response.assert_status(StatusCode::UNAUTHORIZED);
+    response.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.headers().get(WWW_AUTHENTICATE).unwrap(),
+        r#"Bearer error="insufficient_scope", scope="urn:mas:graphql:*""#
+    );
     let response: GraphQLResponse = response.json();
 
     assert_eq!(
@@ -745,6 +761,57 @@ async fn test_add_user(pool: PgPool) {
         })
     );
 
+    // Bot users can be added, and are flagged as such
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({
+            "query": r#"
+                mutation {
+                    addUser(input: {username: "carol", bot: true}) {
+                        status
+                        user {
+                            username
+                            isBot
+                        }
+                    }
+                }
+            "#,
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "addUser": {
+                "status": "ADDED",
+                "user": {
+                    "username": "carol",
+                    "isBot": true,
+                },
+            }
+        })
+    );
+
+    let mut repo = state.repository().await.unwrap();
+    let carol = repo
+        .user()
+        .find_by_username("carol")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(carol.is_bot);
+    let alice = repo
+        .user()
+        .find_by_username("alice")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!alice.is_bot);
+    repo.save().await.unwrap();
+
     // This mutation shouldn't accept an invalid username
     let request = Request::post("/graphql")
         .bearer(&access_token)
@@ -775,3 +842,562 @@ async fn test_add_user(pool: PgPool) {
         })
     );
 }
+
+/// Test that retrying a mutation with the same idempotency key replays the
+/// original response
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_idempotency_key(pool: PgPool) {
+    init_tracing();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    // Provision an admin client
+    let request =
+        Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+            "client_uri": "https://example.com/",
+            "contacts": ["contact@example.com"],
+            "token_endpoint_auth_method": "client_secret_post",
+            "grant_types": ["client_credentials"],
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::CREATED);
+
+    let response: ClientRegistrationResponse = response.json();
+    let client_id = response.client_id;
+    let client_secret = response.client_secret.expect("to have a client secret");
+
+    let state = {
+        let mut state = state;
+        state.policy_factory = test_utils::policy_factory(serde_json::json!({
+            "admin_clients": [client_id],
+        }))
+        .await
+        .unwrap();
+        state
+    };
+
+    let request = Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+        "grant_type": "client_credentials",
+        "client_id": client_id,
+        "client_secret": client_secret,
+        "scope": "urn:mas:graphql:* urn:mas:admin",
+    }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let AccessTokenResponse { access_token, .. } = response.json();
+
+    let add_user = |username: &str| {
+        serde_json::json!({
+            "query": r#"
+                mutation AddUser($username: String!) {
+                    addUser(input: {username: $username}) {
+                        status
+                        user {
+                            id
+                        }
+                    }
+                }
+            "#,
+            "variables": { "username": username },
+        })
+    };
+
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .header("Idempotency-Key", "\"create-alice\"")
+        .json(add_user("alice"));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data["addUser"]["status"], "ADDED");
+    let user_id = response.data["addUser"]["user"]["id"].clone();
+
+    // Retrying replays the original response, instead of reporting that the
+    // user already exists
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .header("Idempotency-Key", "\"create-alice\"")
+        .json(add_user("alice"));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data["addUser"]["status"], "ADDED");
+    assert_eq!(response.data["addUser"]["user"]["id"], user_id);
+
+    // Reusing the key for another request is rejected
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .header("Idempotency-Key", "\"create-alice\"")
+        .json(add_user("bob"));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Requests which fail can be retried with the same key
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .header("Idempotency-Key", "\"lock-nobody\"")
+        .json(serde_json::json!({
+            "query": "mutation { lockUser(input: {userId: \"invalid\"}) { status } }",
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(!response.errors.is_empty());
+
+    let mut repo = state.repository().await.unwrap();
+    let client = repo
+        .oauth2_client()
+        .find_by_client_id(&client_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(repo
+        .idempotency_key()
+        .find(&client, "lock-nobody")
+        .await
+        .unwrap()
+        .is_none());
+    repo.cancel().await.unwrap();
+
+    // Idempotency keys can't be used without an access token
+    let request = Request::post("/graphql")
+        .header("Idempotency-Key", "\"anonymous\"")
+        .json(serde_json::json!({
+            "query": "query { viewer { __typename } }",
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+/// Test that administrators can shadow-ban a user, and that the tokens of
+/// shadow-banned users are flagged on introspection
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_set_shadow_banned(pool: PgPool) {
+    init_tracing();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    // Provision a client which will be used to do introspection requests
+    let request =
+        Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+            "contacts": ["hello@introspecting.com"],
+            "client_uri": "https://introspecting.com/",
+            "grant_types": [],
+            "token_endpoint_auth_method": "client_secret_basic",
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::CREATED);
+    let response: ClientRegistrationResponse = response.json();
+    let introspecting_client_id = response.client_id;
+    let introspecting_client_secret = response.client_secret.unwrap();
+
+    let client = create_test_client(&state).await;
+    let admin = create_test_user(&state, "admin").await;
+    let alice = create_test_user(&state, "alice").await;
+    let admin_token =
+        start_oauth_session(&state, &client, &admin, Scope::from_iter([GRAPHQL, ADMIN]))
+            .await
+            .access_token;
+    let user_token = start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+    let alice_token = start_oauth_session(&state, &client, &alice, Scope::from_iter([OPENID]))
+        .await
+        .access_token;
+
+    let (state, client_id, client_secret, token) = (
+        &state,
+        &introspecting_client_id,
+        &introspecting_client_secret,
+        &alice_token,
+    );
+    let introspect = move || async move {
+        let request = Request::post(mas_router::OAuth2Introspection::PATH)
+            .basic_auth(client_id, client_secret)
+            .form(serde_json::json!({ "token": token }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+        response.shadow_banned
+    };
+
+    assert_eq!(introspect().await, None);
+
+    let mutation = r"
+        mutation($userId: ID!, $shadowBanned: Boolean!) {
+            setShadowBanned(input: { userId: $userId, shadowBanned: $shadowBanned }) {
+                user {
+                    id
+                }
+            }
+        }
+    ";
+
+    // Only administrators can shadow-ban users
+    let request = Request::post("/graphql")
+        .bearer(&user_token)
+        .json(serde_json::json!({
+            "query": mutation,
+            "variables": { "userId": format!("user:{}", alice.id), "shadowBanned": true },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(introspect().await, None);
+
+    let request = Request::post("/graphql")
+        .bearer(&admin_token)
+        .json(serde_json::json!({
+            "query": mutation,
+            "variables": { "userId": format!("user:{}", alice.id), "shadowBanned": true },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["setShadowBanned"]["user"]["id"],
+        format!("user:{}", alice.id)
+    );
+
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(user.is_shadow_banned());
+    repo.cancel().await.unwrap();
+
+    // The token of the shadow-banned user is still active, but flagged
+    assert_eq!(introspect().await, Some(true));
+
+    // Lifting the shadow-ban removes the flag
+    let request = Request::post("/graphql")
+        .bearer(&admin_token)
+        .json(serde_json::json!({
+            "query": mutation,
+            "variables": { "userId": format!("user:{}", alice.id), "shadowBanned": false },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(introspect().await, None);
+}
+
+/// Test that a user can end all of their own sessions at once, but not the
+/// sessions of someone else.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_end_all_sessions(pool: PgPool) {
+    init_tracing();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+    let alice_token = start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+    let bob_token = start_oauth_session(&state, &client, &bob, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+
+    let mutation = r"
+        mutation($userId: ID!) {
+            endAllSessions(input: { userId: $userId }) {
+                status
+            }
+        }
+    ";
+
+    // Bob can't end Alice's sessions
+    let request = Request::post("/graphql")
+        .bearer(&bob_token)
+        .json(serde_json::json!({
+            "query": mutation,
+            "variables": { "userId": format!("user:{}", alice.id) },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert_eq!(response.errors.len(), 1);
+
+    // Alice can end her own sessions
+    let request = Request::post("/graphql")
+        .bearer(&alice_token)
+        .json(serde_json::json!({
+            "query": mutation,
+            "variables": { "userId": format!("user:{}", alice.id) },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "endAllSessions": {
+                "status": "ENDED",
+            },
+        })
+    );
+
+    // Alice's token doesn't work anymore, but Bob's still does
+    let request = Request::post("/graphql")
+        .bearer(&alice_token)
+        .json(serde_json::json!({
+            "query": "query { viewer { __typename } }",
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let request = Request::post("/graphql")
+        .bearer(&bob_token)
+        .json(serde_json::json!({
+            "query": "query { viewer { __typename } }",
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+
+    let mut repo = state.repository().await.unwrap();
+    let active_browser_sessions = repo
+        .browser_session()
+        .count(BrowserSessionFilter::new().for_user(&alice).active_only())
+        .await
+        .unwrap();
+    assert_eq!(active_browser_sessions, 0);
+    repo.cancel().await.unwrap();
+}
+
+/// Test that the display name and avatar of a user are stored and pushed to
+/// the homeserver
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_set_profile(pool: PgPool) {
+    init_tracing();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let token = start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+
+    let mxid = state.homeserver_connection.mxid(&alice.username);
+    state
+        .homeserver_connection
+        .provision_user(&ProvisionRequest::new(mxid.clone(), alice.sub.clone()))
+        .await
+        .unwrap();
+
+    let request = Request::post("/graphql")
+        .bearer(&token)
+        .json(serde_json::json!({
+            "query": r#"
+                mutation($userId: ID!) {
+                    setDisplayName(input: { userId: $userId, displayName: "Alice" }) {
+                        status
+                    }
+                    setAvatarUrl(input: { userId: $userId, avatarUrl: "mxc://example.com/abc" }) {
+                        status
+                    }
+                }
+            "#,
+            "variables": { "userId": format!("user:{}", alice.id) },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "setDisplayName": { "status": "SET" },
+            "setAvatarUrl": { "status": "SET" },
+        })
+    );
+
+    let matrix_user = state.homeserver_connection.query_user(&mxid).await.unwrap();
+    assert_eq!(matrix_user.displayname.as_deref(), Some("Alice"));
+    assert_eq!(
+        matrix_user.avatar_url.as_deref(),
+        Some("mxc://example.com/abc")
+    );
+
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("Alice"));
+    assert_eq!(user.avatar_url.as_deref(), Some("mxc://example.com/abc"));
+    repo.cancel().await.unwrap();
+
+    // Only Matrix content URIs are accepted as avatars
+    let request = Request::post("/graphql")
+        .bearer(&token)
+        .json(serde_json::json!({
+            "query": r#"
+                mutation($userId: ID!) {
+                    setAvatarUrl(input: { userId: $userId, avatarUrl: "https://example.com/avatar.png" }) {
+                        status
+                    }
+                }
+            "#,
+            "variables": { "userId": format!("user:{}", alice.id) },
+        }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "setAvatarUrl": { "status": "INVALID" },
+        })
+    );
+}
+
+/// Test that administrators can get the access token stored for an upstream
+/// OAuth 2.0 link
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_fetch_upstream_oauth2_access_token(pool: PgPool) {
+    init_tracing();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let alice_token = start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL]))
+        .await
+        .access_token;
+    let admin_token =
+        start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL, ADMIN]))
+            .await
+            .access_token;
+
+    // Create a provider which stores the tokens, and a link for alice
+    let mut repo = state.repository().await.unwrap();
+    let provider = repo
+        .upstream_oauth_provider()
+        .add(
+            &mut state.rng(),
+            &state.clock,
+            UpstreamOAuthProviderParams {
+                issuer: "https://example.com/".to_owned(),
+                human_name: None,
+                brand_name: None,
+                scope: Scope::from_iter([OPENID]),
+                token_endpoint_auth_method: OAuthClientAuthenticationMethod::None,
+                token_endpoint_signing_alg: None,
+                client_id: "client".to_owned(),
+                encrypted_client_secret: None,
+                claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                authorization_endpoint_override: None,
+                token_endpoint_override: None,
+                jwks_uri_override: None,
+                discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+                pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+                additional_authorization_parameters: Vec::new(),
+                store_tokens: true,
+                on_deprovision: UpstreamOAuthProviderOnDeprovision::Ignore,
+            },
+        )
+        .await
+        .unwrap();
+    let link = repo
+        .upstream_oauth_link()
+        .add(
+            &mut state.rng(),
+            &state.clock,
+            &provider,
+            "alice".to_owned(),
+        )
+        .await
+        .unwrap();
+    repo.upstream_oauth_link()
+        .associate_to_user(&link, &alice)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let mutation = r"
+        mutation($linkId: ID!) {
+            fetchUpstreamOauth2AccessToken(input: { upstreamOauth2LinkId: $linkId }) {
+                status
+                accessToken
+                expiresAt
+            }
+        }
+    ";
+    let variables = serde_json::json!({ "linkId": format!("upstream_oauth2_link:{}", link.id) });
+
+    // Regular users can't use this mutation
+    let request = Request::post("/graphql")
+        .bearer(&alice_token)
+        .json(serde_json::json!({ "query": mutation, "variables": variables }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert_eq!(response.errors.len(), 1);
+
+    // No tokens are stored yet
+    let request = Request::post("/graphql")
+        .bearer(&admin_token)
+        .json(serde_json::json!({ "query": mutation, "variables": variables }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["fetchUpstreamOauth2AccessToken"]["status"],
+        "NOT_STORED"
+    );
+
+    // Store a token which is still valid
+    let mut repo = state.repository().await.unwrap();
+    let encrypted_access_token = state
+        .encrypter
+        .encrypt_to_string(&mut state.rng(), b"upstream-access-token")
+        .unwrap();
+    let expires_at = state.clock.now() + Duration::try_minutes(5).unwrap();
+    repo.upstream_oauth_link()
+        .save_token(
+            &state.clock,
+            &link,
+            encrypted_access_token,
+            None,
+            Some(expires_at),
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let request = Request::post("/graphql")
+        .bearer(&admin_token)
+        .json(serde_json::json!({ "query": mutation, "variables": variables }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["fetchUpstreamOauth2AccessToken"]["status"],
+        "FETCHED"
+    );
+    assert_eq!(
+        response.data["fetchUpstreamOauth2AccessToken"]["accessToken"],
+        "upstream-access-token"
+    );
+
+    // Once expired, it can't be refreshed as there is no refresh token
+    state.clock.advance(Duration::try_minutes(10).unwrap());
+    let request = Request::post("/graphql")
+        .bearer(&admin_token)
+        .json(serde_json::json!({ "query": mutation, "variables": variables }));
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["fetchUpstreamOauth2AccessToken"],
+        serde_json::json!({
+            "status": "EXPIRED",
+            "accessToken": null,
+            "expiresAt": null,
+        })
+    );
+}
@@ -11,13 +11,47 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::collections::BTreeSet;
 
-use axum::{extract::State, response::IntoResponse};
-use mas_axum_utils::FancyError;
-use sqlx::PgPool;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use mas_storage_pg::MIGRATOR;
+use sqlx::{migrate::Migrate, PgPool};
+use thiserror::Error;
 use tracing::{info_span, Instrument};
 
-pub async fn get(State(pool): State<PgPool>) -> Result<impl IntoResponse, FancyError> {
+/// Liveness probe: always succeeds as long as the server is able to answer
+/// requests
+pub async fn get() -> &'static str {
+    "ok"
+}
+
+#[derive(Debug, Error)]
+pub enum ReadinessError {
+    #[error("could not reach the database")]
+    Database(#[from] sqlx::Error),
+
+    #[error("could not list the applied database migrations")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error("there are pending database migrations")]
+    PendingMigrations,
+}
+
+impl IntoResponse for ReadinessError {
+    fn into_response(self) -> Response {
+        tracing::warn!(
+            error = &self as &dyn std::error::Error,
+            "Readiness check failed"
+        );
+        (StatusCode::SERVICE_UNAVAILABLE, self.to_string()).into_response()
+    }
+}
+
+async fn check_database(pool: &PgPool) -> Result<(), ReadinessError> {
     let mut conn = pool.acquire().await?;
 
     sqlx::query("SELECT $1")
@@ -26,12 +60,28 @@ pub async fn get(State(pool): State<PgPool>) -> Result<impl IntoResponse, FancyE
         .instrument(info_span!("DB health"))
         .await?;
 
+    let applied = conn
+        .list_applied_migrations()
+        .instrument(info_span!("DB migrations status"))
+        .await?;
+    let applied: BTreeSet<_> = applied.into_iter().map(|m| m.version).collect();
+    if MIGRATOR.iter().any(|m| !applied.contains(&m.version)) {
+        return Err(ReadinessError::PendingMigrations);
+    }
+
+    Ok(())
+}
+
+/// Readiness probe: checks that the database is reachable and that all the
+/// migrations were applied
+pub async fn ready(State(pool): State<PgPool>) -> Result<&'static str, ReadinessError> {
+    check_database(&pool).await?;
     Ok("ok")
 }
 
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
+    use hyper::Request;
 
     use super::*;
     use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState};
@@ -45,4 +95,14 @@ mod tests {
         response.assert_status(StatusCode::OK);
         assert_eq!(response.body(), "ok");
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_ready(pool: PgPool) {
+        let state = TestState::from_pool(pool).await.unwrap();
+        let request = Request::get("/health/ready").empty();
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(response.body(), "ok");
+    }
 }
@@ -0,0 +1,493 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of the token introspection results.
+//!
+//! The homeserver introspects the access tokens on almost every request it
+//! receives. To avoid hitting the database each time, positive introspection
+//! results can be cached for a short time.
+//!
+//! Entries are keyed by a hash of the token, so that tokens are never stored
+//! as-is. They are tagged with the session and the user they belong to, so
+//! that they can be invalidated when the session ends or the user is locked.
+//! Invalidations done by other processes, like the `manage revoke-tokens`
+//! command, are only seen by the other instances when the entries are kept in
+//! Redis.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use oauth2_types::requests::IntrospectionResponse;
+use redis::{aio::ConnectionManager, AsyncCommands, RedisError, Script};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ulid::Ulid;
+use url::Url;
+
+/// Maximum number of entries kept by the in-memory backend
+const IN_MEMORY_MAX_ENTRIES: usize = 10_000;
+
+/// Prefix of the keys used by the Redis backend
+const REDIS_KEY_PREFIX: &str = "mas:introspection:";
+
+/// Stores an entry and adds it to the sets of its tags. `KEYS[1]` is the key
+/// of the entry, the other keys are the tags.
+const REDIS_INSERT_SCRIPT: &str = r"
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+for i = 2, #KEYS do
+    redis.call('SADD', KEYS[i], KEYS[1])
+    redis.call('PEXPIRE', KEYS[i], ARGV[2])
+end
+return 0
+";
+
+/// Removes all the entries with the tag `KEYS[1]`
+const REDIS_INVALIDATE_SCRIPT: &str = r"
+local keys = redis.call('SMEMBERS', KEYS[1])
+for _, key in ipairs(keys) do
+    redis.call('DEL', key)
+end
+redis.call('DEL', KEYS[1])
+return #keys
+";
+
+/// The session which was introspected, used to record activity on cache hits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "id")]
+pub(crate) enum CachedSession {
+    OAuth2(Ulid),
+    Compat(Ulid),
+}
+
+impl CachedSession {
+    fn id(self) -> Ulid {
+        match self {
+            Self::OAuth2(id) | Self::Compat(id) => id,
+        }
+    }
+}
+
+/// A cached introspection result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedIntrospection {
+    pub(crate) session: CachedSession,
+    pub(crate) user_id: Option<Ulid>,
+    pub(crate) response: IntrospectionResponse,
+}
+
+impl CachedIntrospection {
+    /// The tags under which this entry can be invalidated
+    fn tags(&self) -> Vec<String> {
+        std::iter::once(session_tag(self.session.id()))
+            .chain(self.user_id.map(user_tag))
+            .collect()
+    }
+}
+
+fn session_tag(session_id: Ulid) -> String {
+    format!("session:{session_id}")
+}
+
+fn user_tag(user_id: Ulid) -> String {
+    format!("user:{user_id}")
+}
+
+/// Compute the cache key of a token
+fn token_key(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    format!("token:{digest:x}")
+}
+
+/// A storage backend for the introspection cache
+#[async_trait]
+pub trait IntrospectionCacheBackend: std::fmt::Debug + Send + Sync {
+    /// Get the entry stored under `key`, if it did not expire
+    async fn get(&self, key: &str, now: DateTime<Utc>) -> Option<CachedIntrospection>;
+
+    /// Store an entry under `key` until `expires_at`
+    async fn insert(
+        &self,
+        key: &str,
+        entry: &CachedIntrospection,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    );
+
+    /// Remove the entry stored under `key`
+    async fn remove(&self, key: &str);
+
+    /// Remove all the entries with the given tag
+    async fn invalidate(&self, tag: &str);
+}
+
+/// An introspection cache backend which keeps its entries in memory.
+///
+/// The entries are not shared between multiple instances of the service.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    entries: Mutex<HashMap<String, (CachedIntrospection, DateTime<Utc>)>>,
+}
+
+impl InMemoryBackend {
+    /// Create a new, empty, in-memory backend
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IntrospectionCacheBackend for InMemoryBackend {
+    async fn get(&self, key: &str, now: DateTime<Utc>) -> Option<CachedIntrospection> {
+        let entries = self
+            .entries
+            .lock()
+            .expect("introspection cache lock poisoned");
+        entries
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(entry, _)| entry.clone())
+    }
+
+    async fn insert(
+        &self,
+        key: &str,
+        entry: &CachedIntrospection,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("introspection cache lock poisoned");
+        if entries.len() >= IN_MEMORY_MAX_ENTRIES {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        // If the cache is still full, don't cache this entry
+        if entries.len() < IN_MEMORY_MAX_ENTRIES {
+            entries.insert(key.to_owned(), (entry.clone(), expires_at));
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("introspection cache lock poisoned");
+        entries.remove(key);
+    }
+
+    async fn invalidate(&self, tag: &str) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("introspection cache lock poisoned");
+        entries.retain(|_, (entry, _)| !entry.tags().iter().any(|t| t == tag));
+    }
+}
+
+/// An introspection cache backend which keeps its entries in Redis, so that
+/// they are shared between all the instances of the service.
+///
+/// If Redis can't be reached, the cache is bypassed.
+#[derive(Clone)]
+pub struct RedisBackend {
+    connection: ConnectionManager,
+    insert_script: Arc<Script>,
+    invalidate_script: Arc<Script>,
+}
+
+impl std::fmt::Debug for RedisBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisBackend").finish_non_exhaustive()
+    }
+}
+
+impl RedisBackend {
+    /// Connect to the Redis server at the given URI
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URI is invalid or if the server can't be
+    /// reached
+    pub async fn connect(uri: &Url) -> Result<Self, RedisError> {
+        let client = redis::Client::open(uri.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self {
+            connection,
+            insert_script: Arc::new(Script::new(REDIS_INSERT_SCRIPT)),
+            invalidate_script: Arc::new(Script::new(REDIS_INVALIDATE_SCRIPT)),
+        })
+    }
+}
+
+fn warn_redis_error(e: &RedisError) {
+    tracing::warn!(
+        error = e as &dyn std::error::Error,
+        "Failed to reach Redis for the introspection cache"
+    );
+}
+
+#[async_trait]
+impl IntrospectionCacheBackend for RedisBackend {
+    async fn get(&self, key: &str, _now: DateTime<Utc>) -> Option<CachedIntrospection> {
+        let mut connection = self.connection.clone();
+        let res: Result<Option<String>, RedisError> =
+            connection.get(format!("{REDIS_KEY_PREFIX}{key}")).await;
+
+        match res {
+            Ok(value) => value.and_then(|value| serde_json::from_str(&value).ok()),
+            Err(e) => {
+                warn_redis_error(&e);
+                None
+            }
+        }
+    }
+
+    async fn insert(
+        &self,
+        key: &str,
+        entry: &CachedIntrospection,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) {
+        let Ok(value) = serde_json::to_string(entry) else {
+            return;
+        };
+
+        let ttl = (expires_at - now).num_milliseconds();
+        if ttl <= 0 {
+            return;
+        }
+
+        let mut invocation = self.insert_script.key(format!("{REDIS_KEY_PREFIX}{key}"));
+        for tag in entry.tags() {
+            invocation.key(format!("{REDIS_KEY_PREFIX}{tag}"));
+        }
+
+        let mut connection = self.connection.clone();
+        let res: Result<i64, RedisError> = invocation
+            .arg(value)
+            .arg(ttl)
+            .invoke_async(&mut connection)
+            .await;
+
+        if let Err(e) = res {
+            warn_redis_error(&e);
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        let mut connection = self.connection.clone();
+        let res: Result<i64, RedisError> = connection.del(format!("{REDIS_KEY_PREFIX}{key}")).await;
+        if let Err(e) = res {
+            warn_redis_error(&e);
+        }
+    }
+
+    async fn invalidate(&self, tag: &str) {
+        let mut connection = self.connection.clone();
+        let res: Result<i64, RedisError> = self
+            .invalidate_script
+            .key(format!("{REDIS_KEY_PREFIX}{tag}"))
+            .invoke_async(&mut connection)
+            .await;
+
+        if let Err(e) = res {
+            warn_redis_error(&e);
+        }
+    }
+}
+
+/// Caches positive token introspection results
+#[derive(Debug, Clone)]
+pub struct IntrospectionCache {
+    backend: Arc<dyn IntrospectionCacheBackend>,
+    ttl: Option<Duration>,
+}
+
+impl Default for IntrospectionCache {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl IntrospectionCache {
+    /// Create a cache which doesn't cache anything
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            backend: Arc::new(InMemoryBackend::new()),
+            ttl: None,
+        }
+    }
+
+    /// Create a new cache, keeping entries in memory for at most `ttl`
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_backend(ttl, Arc::new(InMemoryBackend::new()))
+    }
+
+    /// Create a new cache, using the given backend to store its entries
+    #[must_use]
+    pub fn with_backend(ttl: Duration, backend: Arc<dyn IntrospectionCacheBackend>) -> Self {
+        Self {
+            backend,
+            ttl: Some(ttl),
+        }
+    }
+
+    /// Whether introspection results are cached at all
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.ttl.is_some()
+    }
+
+    /// Get the cached introspection result of a token
+    pub(crate) async fn get(&self, token: &str, now: DateTime<Utc>) -> Option<CachedIntrospection> {
+        if self.ttl.is_none() {
+            return None;
+        }
+
+        let entry = self.backend.get(&token_key(token), now).await?;
+
+        // Never return a result for a token which expired in the meantime
+        if entry.response.exp.is_some_and(|exp| exp <= now) {
+            return None;
+        }
+
+        Some(entry)
+    }
+
+    /// Cache the introspection result of a token
+    pub(crate) async fn insert(
+        &self,
+        token: &str,
+        entry: &CachedIntrospection,
+        now: DateTime<Utc>,
+    ) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+
+        // Entries don't outlive the token they describe
+        let expires_at = entry
+            .response
+            .exp
+            .map_or(now + ttl, |exp| exp.min(now + ttl));
+
+        self.backend
+            .insert(&token_key(token), entry, now, expires_at)
+            .await;
+    }
+
+    /// Forget the cached result of a single token, e.g. after it was revoked
+    pub(crate) async fn invalidate_token(&self, token: &str) {
+        if self.ttl.is_some() {
+            self.backend.remove(&token_key(token)).await;
+        }
+    }
+
+    /// Forget the cached results of all the tokens of a session, after it
+    /// ended
+    pub async fn invalidate_session(&self, session_id: Ulid) {
+        if self.ttl.is_some() {
+            self.backend.invalidate(&session_tag(session_id)).await;
+        }
+    }
+
+    /// Forget the cached results of all the tokens of a user, e.g. after it
+    /// was locked
+    pub async fn invalidate_user(&self, user_id: Ulid) {
+        if self.ttl.is_some() {
+            self.backend.invalidate(&user_tag(user_id)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{clock::MockClock, Clock};
+
+    use super::*;
+
+    fn entry(
+        session_id: Ulid,
+        user_id: Option<Ulid>,
+        exp: Option<DateTime<Utc>>,
+    ) -> CachedIntrospection {
+        CachedIntrospection {
+            session: CachedSession::OAuth2(session_id),
+            user_id,
+            response: IntrospectionResponse {
+                active: true,
+                exp,
+                ..IntrospectionResponse::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspection_cache() {
+        let clock = MockClock::default();
+        let cache = IntrospectionCache::new(Duration::seconds(30));
+
+        let session = Ulid::from_parts(1, 0);
+        let other_session = Ulid::from_parts(2, 0);
+        let user = Ulid::from_parts(3, 0);
+
+        let first = entry(session, Some(user), None);
+        cache.insert("first", &first, clock.now()).await;
+        assert_eq!(cache.get("first", clock.now()).await, Some(first.clone()));
+        assert_eq!(cache.get("unknown", clock.now()).await, None);
+
+        // Entries expire after the TTL
+        clock.advance(Duration::seconds(31));
+        assert_eq!(cache.get("first", clock.now()).await, None);
+
+        // Entries don't outlive the token
+        let expiring = entry(session, None, Some(clock.now() + Duration::seconds(10)));
+        cache.insert("expiring", &expiring, clock.now()).await;
+        assert_eq!(cache.get("expiring", clock.now()).await, Some(expiring));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(cache.get("expiring", clock.now()).await, None);
+
+        // Invalidate a single token
+        cache.insert("first", &first, clock.now()).await;
+        cache.invalidate_token("first").await;
+        assert_eq!(cache.get("first", clock.now()).await, None);
+
+        // Invalidate all the tokens of a session
+        let second = entry(other_session, Some(user), None);
+        cache.insert("first", &first, clock.now()).await;
+        cache.insert("second", &second, clock.now()).await;
+        cache.invalidate_session(session).await;
+        assert_eq!(cache.get("first", clock.now()).await, None);
+        assert_eq!(cache.get("second", clock.now()).await, Some(second.clone()));
+
+        // Invalidate all the tokens of a user
+        cache.invalidate_user(user).await;
+        assert_eq!(cache.get("second", clock.now()).await, None);
+
+        // A disabled cache doesn't remember anything
+        let cache = IntrospectionCache::disabled();
+        cache.insert("first", &first, clock.now()).await;
+        assert_eq!(cache.get("first", clock.now()).await, None);
+    }
+}
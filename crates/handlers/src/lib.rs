@@ -23,26 +23,28 @@
     clippy::let_with_type_underscore,
 )]
 
-use std::{convert::Infallible, time::Duration};
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
 use axum::{
     body::{Bytes, HttpBody},
     extract::{FromRef, FromRequestParts, OriginalUri, RawQuery, State},
-    http::Method,
-    response::{Html, IntoResponse},
+    http::{HeaderMap, Method},
+    response::{Html, IntoResponse, Response},
     routing::{get, on, post, MethodFilter},
-    Router,
+    Extension, Json, Router,
 };
 use headers::HeaderName;
 use hyper::{
     header::{
         ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE,
+        WWW_AUTHENTICATE,
     },
     StatusCode, Version,
 };
 use mas_axum_utils::{cookies::CookieJar, FancyError};
 use mas_data_model::SiteConfig;
 use mas_http::CorsLayerExt;
+use mas_i18n::Translator;
 use mas_keystore::{Encrypter, Keystore};
 use mas_matrix::BoxHomeserverConnection;
 use mas_policy::Policy;
@@ -54,16 +56,27 @@ use sqlx::PgPool;
 use tower::util::AndThenLayer;
 use tower_http::cors::{Any, CorsLayer};
 
+mod admin;
 mod compat;
+mod document_cache;
+pub mod geoip;
 mod graphql;
 mod health;
+pub mod introspection_cache;
+pub mod login_risk;
+mod matrix_well_known;
+pub mod network_ban;
 mod oauth2;
+mod openapi;
 pub mod passwords;
+pub mod rate_limit;
+pub mod spam_check;
 pub mod upstream_oauth2;
 mod views;
 
 mod activity_tracker;
 mod captcha;
+mod continuation;
 mod preferred_language;
 #[cfg(test)]
 mod test_utils;
@@ -85,16 +98,26 @@ macro_rules! impl_from_error_for_route {
 }
 
 pub use mas_axum_utils::{
-    cookies::CookieManager, http_client_factory::HttpClientFactory, ErrorWrapper,
+    cookies::CookieManager, http_client_factory::HttpClientFactory, ClientCertificate,
+    ErrorWrapper, RequestId,
 };
 
 pub use self::{
     activity_tracker::{ActivityTracker, Bound as BoundActivityTracker},
+    document_cache::DocumentCache,
+    geoip::GeoIp,
     graphql::{
         schema as graphql_schema, schema_builder as graphql_schema_builder, Schema as GraphQLSchema,
     },
+    introspection_cache::IntrospectionCache,
+    login_risk::LoginRisk,
+    network_ban::{reject_banned_networks, NetworkBans},
+    oauth2::logo::ClientLogoCache,
     preferred_language::PreferredLanguage,
+    rate_limit::Limiter,
+    spam_check::SpamChecker,
     upstream_oauth2::cache::MetadataCache,
+    views::negotiate::ForwardedPrincipal,
 };
 
 pub fn healthcheck_router<S, B>() -> Router<S, B>
@@ -103,19 +126,23 @@ where
     S: Clone + Send + Sync + 'static,
     PgPool: FromRef<S>,
 {
-    Router::new().route(mas_router::Healthcheck::route(), get(self::health::get))
+    Router::new()
+        .route(mas_router::Healthcheck::route(), get(self::health::get))
+        .route(mas_router::Readiness::route(), get(self::health::ready))
 }
 
 pub fn graphql_router<S, B>(playground: bool) -> Router<S, B>
 where
     B: HttpBody + Send + 'static,
-    <B as HttpBody>::Data: Into<Bytes>,
+    <B as HttpBody>::Data: Into<Bytes> + Send,
     <B as HttpBody>::Error: std::error::Error + Send + Sync,
     S: Clone + Send + Sync + 'static,
     graphql::Schema: FromRef<S>,
+    PgPool: FromRef<S>,
     BoundActivityTracker: FromRequestParts<S>,
     BoxRepository: FromRequestParts<S>,
     BoxClock: FromRequestParts<S>,
+    BoxRng: FromRequestParts<S>,
     Encrypter: FromRef<S>,
     CookieJar: FromRequestParts<S>,
 {
@@ -134,6 +161,7 @@ where
                     ACCEPT_LANGUAGE,
                     CONTENT_LANGUAGE,
                     CONTENT_TYPE,
+                    graphql::idempotency::IDEMPOTENCY_KEY.clone(),
                 ]),
         );
 
@@ -147,6 +175,59 @@ where
     router
 }
 
+pub fn openapi_router<S, B>(explorer: bool) -> Router<S, B>
+where
+    B: HttpBody + Send + 'static,
+    S: Clone + Send + Sync + 'static,
+    UrlBuilder: FromRef<S>,
+{
+    let mut router = Router::new().route(
+        mas_router::OpenApiSpec::route(),
+        get(self::openapi::get).layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET]),
+        ),
+    );
+
+    if explorer {
+        router = router.route(
+            mas_router::OpenApiExplorer::route(),
+            get(self::openapi::explorer),
+        );
+    }
+
+    router
+}
+
+pub fn admin_router<S, B>() -> Router<S, B>
+where
+    B: HttpBody + Send + 'static,
+    S: Clone + Send + Sync + 'static,
+    UrlBuilder: FromRef<S>,
+    Templates: FromRef<S>,
+    PreferredLanguage: FromRequestParts<S>,
+    BoxRepository: FromRequestParts<S>,
+    BoxClock: FromRequestParts<S>,
+{
+    Router::new()
+        .route(mas_router::AdminUsers::route(), get(self::admin::users))
+        .route(mas_router::AdminUser::route(), get(self::admin::user))
+        .route(mas_router::AdminClients::route(), get(self::admin::clients))
+        .route(
+            mas_router::AdminStatsAuthentications::route(),
+            get(self::admin::stats::authentications),
+        )
+        .route(
+            mas_router::AdminStatsRegistrations::route(),
+            get(self::admin::stats::registrations),
+        )
+        .route(
+            mas_router::AdminStatsTokens::route(),
+            get(self::admin::stats::tokens),
+        )
+}
+
 pub fn discovery_router<S, B>() -> Router<S, B>
 where
     B: HttpBody + Send + 'static,
@@ -154,6 +235,8 @@ where
     Keystore: FromRef<S>,
     SiteConfig: FromRef<S>,
     UrlBuilder: FromRef<S>,
+    DocumentCache: FromRef<S>,
+    Arc<Translator>: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
 {
@@ -162,6 +245,14 @@ where
             mas_router::OidcConfiguration::route(),
             get(self::oauth2::discovery::get),
         )
+        .route(
+            mas_router::OAuth2AuthorizationServerMetadata::route(),
+            get(self::oauth2::discovery::get),
+        )
+        .route(
+            mas_router::OAuth2AuthorizationServerMetadataWithPath::route(),
+            get(self::oauth2::discovery::get_with_path),
+        )
         .route(
             mas_router::Webfinger::route(),
             get(self::oauth2::webfinger::get),
@@ -181,6 +272,33 @@ where
         )
 }
 
+pub fn matrix_well_known_router<S, B>(
+    homeserver_base_url: url::Url,
+    extra: serde_json::Map<String, serde_json::Value>,
+) -> Router<S, B>
+where
+    B: HttpBody + Send + 'static,
+    S: Clone + Send + Sync + 'static,
+    UrlBuilder: FromRef<S>,
+{
+    let config = self::matrix_well_known::Config {
+        homeserver_base_url,
+        extra,
+    };
+
+    Router::new()
+        .route(
+            mas_router::MatrixClientWellKnown::route(),
+            get(self::matrix_well_known::get),
+        )
+        .layer(Extension(Arc::new(config)))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET]),
+        )
+}
+
 pub fn api_router<S, B>() -> Router<S, B>
 where
     B: HttpBody + Send + 'static,
@@ -189,18 +307,23 @@ where
     S: Clone + Send + Sync + 'static,
     Keystore: FromRef<S>,
     UrlBuilder: FromRef<S>,
+    DocumentCache: FromRef<S>,
     BoxRepository: FromRequestParts<S>,
     ActivityTracker: FromRequestParts<S>,
     BoundActivityTracker: FromRequestParts<S>,
     Encrypter: FromRef<S>,
     HttpClientFactory: FromRef<S>,
+    MetadataCache: FromRef<S>,
     SiteConfig: FromRef<S>,
+    Limiter: FromRef<S>,
+    IntrospectionCache: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
     Policy: FromRequestParts<S>,
 {
-    // All those routes are API-like, with a common CORS layer
-    Router::new()
+    // Those routes are called by the clients, which might be running in a
+    // browser on any origin
+    let public_router = Router::new()
         .route(
             mas_router::OAuth2Keys::route(),
             get(self::oauth2::keys::get),
@@ -212,10 +335,6 @@ where
                 self::oauth2::userinfo::get,
             ),
         )
-        .route(
-            mas_router::OAuth2Introspection::route(),
-            post(self::oauth2::introspection::post),
-        )
         .route(
             mas_router::OAuth2Revocation::route(),
             post(self::oauth2::revoke::post),
@@ -232,10 +351,14 @@ where
             mas_router::OAuth2DeviceAuthorizationEndpoint::route(),
             post(self::oauth2::device::authorize::post),
         )
+        .route(
+            mas_router::OAuth2BackchannelAuthenticationEndpoint::route(),
+            post(self::oauth2::backchannel::authorize::post),
+        )
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
-                .allow_methods(Any)
+                .allow_methods([Method::GET, Method::POST])
                 .allow_otel_headers([
                     AUTHORIZATION,
                     ACCEPT,
@@ -243,8 +366,24 @@ where
                     CONTENT_LANGUAGE,
                     CONTENT_TYPE,
                 ])
+                .expose_headers([WWW_AUTHENTICATE])
                 .max_age(Duration::from_secs(60 * 60)),
+        );
+
+    // The introspection endpoint is only meant to be called by the homeserver,
+    // and the deprovisioning one by upstream providers, so cross-origin requests
+    // are not allowed on them
+    let private_router = Router::new()
+        .route(
+            mas_router::OAuth2Introspection::route(),
+            post(self::oauth2::introspection::post),
         )
+        .route(
+            mas_router::UpstreamOAuth2Deprovision::route(),
+            post(self::upstream_oauth2::deprovision::post),
+        );
+
+    public_router.merge(private_router)
 }
 
 #[allow(clippy::trait_duplication_in_bounds)]
@@ -258,6 +397,9 @@ where
     SiteConfig: FromRef<S>,
     BoxHomeserverConnection: FromRef<S>,
     PasswordManager: FromRef<S>,
+    Limiter: FromRef<S>,
+    NetworkBans: FromRef<S>,
+    IntrospectionCache: FromRef<S>,
     BoundActivityTracker: FromRequestParts<S>,
     BoxRepository: FromRequestParts<S>,
     BoxClock: FromRequestParts<S>,
@@ -276,6 +418,10 @@ where
             mas_router::CompatRefresh::route(),
             post(self::compat::refresh::post),
         )
+        .route(
+            mas_router::CompatRegister::route(),
+            post(self::compat::register::post),
+        )
         .route(
             mas_router::CompatLoginSsoRedirect::route(),
             get(self::compat::login_sso_redirect::get),
@@ -316,13 +462,21 @@ where
     BoxRepository: FromRequestParts<S>,
     CookieJar: FromRequestParts<S>,
     BoundActivityTracker: FromRequestParts<S>,
+    ForwardedPrincipal: FromRequestParts<S>,
     Encrypter: FromRef<S>,
     Templates: FromRef<S>,
     Keystore: FromRef<S>,
     HttpClientFactory: FromRef<S>,
     PasswordManager: FromRef<S>,
     MetadataCache: FromRef<S>,
+    ClientLogoCache: FromRef<S>,
     SiteConfig: FromRef<S>,
+    Limiter: FromRef<S>,
+    NetworkBans: FromRef<S>,
+    SpamChecker: FromRef<S>,
+    LoginRisk: FromRef<S>,
+    IntrospectionCache: FromRef<S>,
+    GeoIp: FromRef<S>,
     BoxHomeserverConnection: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
@@ -362,6 +516,18 @@ where
             mas_router::Login::route(),
             get(self::views::login::get).post(self::views::login::post),
         )
+        .route(
+            mas_router::LoginCode::route(),
+            get(self::views::login_code::get).post(self::views::login_code::post),
+        )
+        .route(
+            mas_router::LoginNegotiate::route(),
+            get(self::views::negotiate::get),
+        )
+        .route(
+            mas_router::LoginCertificate::route(),
+            get(self::views::certificate::get),
+        )
         .route(mas_router::Logout::route(), post(self::views::logout::post))
         .route(
             mas_router::Reauth::route(),
@@ -381,6 +547,28 @@ where
             get(self::views::account::emails::add::get)
                 .post(self::views::account::emails::add::post),
         )
+        .route(
+            mas_router::AccountChangeEmail::route(),
+            get(self::views::account::emails::change::get)
+                .post(self::views::account::emails::change::post),
+        )
+        .route(
+            mas_router::AccountChangeEmailVerify::route(),
+            get(self::views::account::emails::change::verify_get)
+                .post(self::views::account::emails::change::verify_post),
+        )
+        .route(
+            mas_router::AccountExport::route(),
+            get(self::views::account::export::get).post(self::views::account::export::post),
+        )
+        .route(
+            mas_router::AccountExportDownload::route(),
+            get(self::views::account::export::download),
+        )
+        .route(
+            mas_router::Impersonate::route(),
+            get(self::views::impersonate::get).post(self::views::impersonate::post),
+        )
         .route(
             mas_router::AccountRecoveryStart::route(),
             get(self::views::recovery::start::get).post(self::views::recovery::start::post),
@@ -393,6 +581,27 @@ where
             mas_router::AccountRecoveryFinish::route(),
             get(self::views::recovery::finish::get).post(self::views::recovery::finish::post),
         )
+        .route(
+            mas_router::MagicLinkStart::route(),
+            get(self::views::magic_link::start::get).post(self::views::magic_link::start::post),
+        )
+        .route(
+            mas_router::MagicLinkProgress::route(),
+            get(self::views::magic_link::progress::get)
+                .post(self::views::magic_link::progress::post),
+        )
+        .route(
+            mas_router::MagicLinkConfirm::route(),
+            get(self::views::magic_link::confirm::get).post(self::views::magic_link::confirm::post),
+        )
+        .route(
+            mas_router::LoginAlertReport::route(),
+            get(self::views::login_alert::get).post(self::views::login_alert::post),
+        )
+        .route(
+            mas_router::EmailChangeUndo::route(),
+            get(self::views::email_change::get).post(self::views::email_change::post),
+        )
         .route(
             mas_router::OAuth2AuthorizationEndpoint::route(),
             get(self::oauth2::authorization::get),
@@ -421,6 +630,10 @@ where
             mas_router::UpstreamOAuth2Link::route(),
             get(self::upstream_oauth2::link::get).post(self::upstream_oauth2::link::post),
         )
+        .route(
+            mas_router::ClientLogo::route(),
+            get(self::oauth2::logo::get),
+        )
         .route(
             mas_router::DeviceCodeLink::route(),
             get(self::oauth2::device::link::get),
@@ -429,13 +642,27 @@ where
             mas_router::DeviceCodeConsent::route(),
             get(self::oauth2::device::consent::get).post(self::oauth2::device::consent::post),
         )
+        .route(
+            mas_router::BackchannelAuthenticationConsent::route(),
+            get(self::oauth2::backchannel::consent::get)
+                .post(self::oauth2::backchannel::consent::post),
+        )
         .layer(AndThenLayer::new(
             move |response: axum::response::Response| async move {
-                if response.status().is_server_error() {
-                    // Error responses should have an ErrorContext attached to them
-                    let ext = response.extensions().get::<ErrorContext>();
-                    if let Some(ctx) = ext {
-                        if let Ok(res) = templates.render_error(ctx) {
+                let status = response.status();
+                if status.is_server_error() || status.is_client_error() {
+                    // Error responses should have an ErrorContext attached to them, except
+                    // for the ones produced by the router itself
+                    let ctx = match response.extensions().get::<ErrorContext>() {
+                        Some(ctx) => Some(ctx.clone()),
+                        None if status == StatusCode::METHOD_NOT_ALLOWED => {
+                            Some(ErrorContext::new().with_code("method_not_allowed"))
+                        }
+                        None => None,
+                    };
+
+                    if let Some(ctx) = ctx {
+                        if let Ok(res) = templates.render_error(&ctx) {
                             let (mut parts, _original_body) = response.into_parts();
                             parts.headers.remove(CONTENT_TYPE);
                             parts.headers.remove(CONTENT_LENGTH);
@@ -451,6 +678,9 @@ where
 
 /// The fallback handler for all routes that don't match anything else.
 ///
+/// Browsers get a page, and other clients a JSON error: Matrix clients expect
+/// the `M_UNRECOGNIZED` error code for unknown endpoints.
+///
 /// # Errors
 ///
 /// Returns an error if the template rendering fails.
@@ -459,12 +689,253 @@ pub async fn fallback(
     OriginalUri(uri): OriginalUri,
     method: Method,
     version: Version,
+    headers: HeaderMap,
     PreferredLanguage(locale): PreferredLanguage,
-) -> Result<impl IntoResponse, FancyError> {
-    let ctx = NotFoundContext::new(&method, version, &uri).with_language(locale);
-    // XXX: this should look at the Accept header and return JSON if requested
+) -> Result<Response, FancyError> {
+    let accepts_html = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/html"));
+
+    if !accepts_html {
+        let body = if uri.path().starts_with("/_matrix/") {
+            serde_json::json!({
+                "errcode": "M_UNRECOGNIZED",
+                "error": "Unrecognized request",
+            })
+        } else {
+            serde_json::json!({
+                "error": "not_found",
+                "error_description": "The requested resource was not found",
+            })
+        };
 
+        return Ok((StatusCode::NOT_FOUND, Json(body)).into_response());
+    }
+
+    let ctx = NotFoundContext::new(&method, version, &uri).with_language(locale);
     let res = templates.render_not_found(&ctx)?;
 
-    Ok((StatusCode::NOT_FOUND, Html(res)))
+    Ok((StatusCode::NOT_FOUND, Html(res)).into_response())
+}
+
+/// Middleware aborting requests which take longer than the given duration to
+/// be handled, for example because the database is slow to respond, so that
+/// they don't pile up indefinitely.
+///
+/// Timed-out requests get a `503 Service Unavailable` response: browsers get a
+/// page inviting them to try again, and other clients a JSON error.
+pub async fn request_timeout<B>(
+    duration: Duration,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    request: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> Response
+where
+    B: Send,
+{
+    let accepts_html = request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/html"));
+    let is_matrix = request.uri().path().starts_with("/_matrix/");
+
+    let Ok(response) = tokio::time::timeout(duration, next.run(request)).await else {
+        tracing::warn!(?duration, "Request timed out");
+
+        if !accepts_html {
+            let body = if is_matrix {
+                serde_json::json!({
+                    "errcode": "M_UNKNOWN",
+                    "error": "The request timed out, try again later",
+                })
+            } else {
+                serde_json::json!({
+                    "error": "temporarily_unavailable",
+                    "error_description": "The request timed out, try again later",
+                })
+            };
+
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+        }
+
+        let ctx = ErrorContext::new()
+            .with_code("request_timeout")
+            .with_language(&locale);
+
+        return match templates.render_error(&ctx) {
+            Ok(res) => (StatusCode::SERVICE_UNAVAILABLE, Html(res)).into_response(),
+            Err(e) => FancyError::from(e).into_response(),
+        };
+    };
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{extract::State, middleware::Next, routing::get, Router};
+    use hyper::{
+        header::{
+            ACCEPT, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_ORIGIN,
+            ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+        },
+        Request, StatusCode,
+    };
+    use mas_templates::Templates;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use crate::{
+        test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState},
+        PreferredLanguage,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_fallback(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Browsers get a page
+        let request = Request::get("/does-not-exist")
+            .header(ACCEPT, "text/html,application/xhtml+xml")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        assert!(response.body().contains("<html"));
+
+        // Other clients get a JSON error
+        let request = Request::get("/does-not-exist").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"], "not_found");
+
+        // Matrix clients get a Matrix error
+        let request = Request::get("/_matrix/client/v3/does-not-exist").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_UNRECOGNIZED");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_request_timeout(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            "done"
+        };
+        let app = Router::new()
+            .route("/slow", get(slow))
+            .route("/_matrix/client/v3/slow", get(slow))
+            .route("/fast", get(|| async { "done" }))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                |templates: State<Templates>,
+                 locale: PreferredLanguage,
+                 request: Request<String>,
+                 next: Next<String>| {
+                    crate::request_timeout(
+                        Duration::from_millis(50),
+                        templates,
+                        locale,
+                        request,
+                        next,
+                    )
+                },
+            ))
+            .with_state(state);
+
+        // Fast requests are not affected
+        let response = app
+            .clone()
+            .oneshot(Request::get("/fast").empty())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Browsers get a page
+        let request = Request::get("/slow")
+            .header(ACCEPT, "text/html,application/xhtml+xml")
+            .empty();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(std::str::from_utf8(&body).unwrap().contains("<html"));
+
+        // Other clients get a JSON error
+        let response = app
+            .clone()
+            .oneshot(Request::get("/slow").empty())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "temporarily_unavailable");
+
+        // Matrix clients get a Matrix error
+        let request = Request::get("/_matrix/client/v3/slow").empty();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["errcode"], "M_UNKNOWN");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_method_not_allowed_page(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request = Request::delete("/login").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        assert!(response.body().contains("Method not allowed"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_cors(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let preflight = |path: &str| {
+            Request::options(path)
+                .header(ORIGIN, "https://client.example.com")
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(
+                    ACCESS_CONTROL_REQUEST_HEADERS,
+                    "authorization, content-type",
+                )
+                .empty()
+        };
+
+        // Browser-based clients can call the token endpoint
+        let response = state.request(preflight("/oauth2/token")).await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(
+            response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "*"
+        );
+        let allowed_headers = response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_HEADERS)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(allowed_headers.contains("authorization"));
+
+        // But not the introspection endpoint
+        let response = state.request(preflight("/oauth2/introspect")).await;
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
 }
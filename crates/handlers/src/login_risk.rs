@@ -0,0 +1,502 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Risk assessment of password logins
+//!
+//! Each login is matched against a few signals: whether the device or network
+//! is new for the user, whether the address belongs to a network with a low
+//! reputation or is a Tor exit node, and whether the previous login of the
+//! user was from another country too recently to have travelled since. The
+//! login gets the sum of the scores of the signals it matches, and the
+//! thresholds decide whether it is allowed, confirmed with a code sent by
+//! email, or denied. Every assessment is recorded for auditing.
+
+use std::{net::IpAddr, sync::Arc};
+
+use chrono::Duration;
+use mas_data_model::{
+    InvalidNetworkError, LoginRiskAssessment, LoginRiskDecision, LoginRiskSignal, LoginSighting,
+    Network, User, UserAgent,
+};
+use mas_storage::{
+    user::{UserEmailRepository, UserLoginAlertRepository, UserLoginRiskRepository},
+    Clock, RepositoryAccess,
+};
+use rand::RngCore;
+
+use crate::GeoIp;
+
+/// The configuration of the risk assessment
+#[derive(Debug, Clone, Copy)]
+pub struct LoginRiskConfig {
+    /// Score of a login from a new device or network
+    pub new_device_score: u32,
+
+    /// Score of a login from a network with a low reputation
+    pub low_reputation_score: u32,
+
+    /// Score of a login from a Tor exit node
+    pub tor_exit_node_score: u32,
+
+    /// Score of a login from another country than the previous one
+    pub impossible_travel_score: u32,
+
+    /// How long after a login a login from another country is considered
+    /// impossible travel
+    pub impossible_travel_window: Duration,
+
+    /// Score from which the login must be confirmed with a code
+    pub mfa_threshold: u32,
+
+    /// Score from which the login is denied
+    pub deny_threshold: u32,
+}
+
+impl LoginRiskConfig {
+    /// The score of a login matching the given signals
+    fn score(&self, signals: &[LoginRiskSignal]) -> u32 {
+        signals
+            .iter()
+            .map(|signal| match signal {
+                LoginRiskSignal::NewDevice => self.new_device_score,
+                LoginRiskSignal::LowReputation => self.low_reputation_score,
+                LoginRiskSignal::ImpossibleTravel => self.impossible_travel_score,
+                LoginRiskSignal::TorExitNode => self.tor_exit_node_score,
+            })
+            .fold(0, u32::saturating_add)
+    }
+
+    /// The decision to take on a login with the given score
+    fn decide(&self, score: u32) -> LoginRiskDecision {
+        if score >= self.deny_threshold {
+            LoginRiskDecision::Deny
+        } else if score >= self.mfa_threshold {
+            LoginRiskDecision::RequireMfa
+        } else {
+            LoginRiskDecision::Allow
+        }
+    }
+}
+
+/// Parse a list of networks, with one IP address or CIDR range per line
+///
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// # Errors
+///
+/// Returns an error if one of the lines is not a valid address or range
+pub fn parse_network_list(contents: &str) -> Result<Vec<Network>, InvalidNetworkError> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+struct Inner {
+    config: LoginRiskConfig,
+    low_reputation_networks: Vec<Network>,
+    tor_exit_nodes: Vec<Network>,
+}
+
+/// Assesses the risk of password logins, if configured
+#[derive(Clone, Default)]
+pub struct LoginRisk {
+    inner: Option<Arc<Inner>>,
+}
+
+impl std::fmt::Debug for LoginRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginRisk")
+            .field("config", &self.inner.as_ref().map(|inner| inner.config))
+            .finish()
+    }
+}
+
+impl LoginRisk {
+    /// Create an assessor which doesn't assess anything
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Create an assessor with the given configuration
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The scores of the signals and the thresholds
+    /// * `low_reputation_networks`: The networks with a low reputation
+    /// * `tor_exit_nodes`: The addresses of the Tor exit nodes
+    #[must_use]
+    pub fn new(
+        config: LoginRiskConfig,
+        low_reputation_networks: Vec<Network>,
+        tor_exit_nodes: Vec<Network>,
+    ) -> Self {
+        Self {
+            inner: Some(Arc::new(Inner {
+                config,
+                low_reputation_networks,
+                tor_exit_nodes,
+            })),
+        }
+    }
+
+    /// Assess the risk of a password login of the given user, and record the
+    /// assessment
+    ///
+    /// Logins which should be confirmed with a code are denied if the user
+    /// has no confirmed primary email address to send it to.
+    ///
+    /// Returns `None` if the risk assessment is not configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository fails
+    #[allow(clippy::too_many_arguments)]
+    pub async fn assess<R: RepositoryAccess>(
+        &self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        repo: &mut R,
+        geoip: &GeoIp,
+        user: &User,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        request_id: Option<String>,
+    ) -> Result<Option<LoginRiskAssessment>, R::Error> {
+        let Some(inner) = &self.inner else {
+            return Ok(None);
+        };
+        let config = &inner.config;
+
+        let mut signals = Vec::new();
+
+        // Logins without a user agent or an IP address can't be matched against
+        // the known devices, so they are considered to come from a new one
+        let sighting = if let (Some(user_agent), Some(ip)) = (&user_agent, ip_address) {
+            repo.user_login_alert()
+                .check_sighting(
+                    user,
+                    &LoginSighting::device_of(user_agent),
+                    LoginSighting::network_of(ip),
+                )
+                .await?
+        } else {
+            LoginSighting::Unknown
+        };
+
+        if sighting == LoginSighting::Unknown {
+            signals.push(LoginRiskSignal::NewDevice);
+        }
+
+        if let Some(ip) = ip_address {
+            if inner
+                .low_reputation_networks
+                .iter()
+                .any(|network| network.contains(ip))
+            {
+                signals.push(LoginRiskSignal::LowReputation);
+            }
+
+            let last_sighting = repo.user_login_alert().last_sighting(user).await?;
+            if let Some((previous, last_seen_at)) = last_sighting {
+                let country_of = |ip| geoip.lookup(ip).and_then(|location| location.country_code);
+                if clock.now() - last_seen_at < config.impossible_travel_window {
+                    if let (Some(previous), Some(current)) = (country_of(previous), country_of(ip))
+                    {
+                        if previous != current {
+                            signals.push(LoginRiskSignal::ImpossibleTravel);
+                        }
+                    }
+                }
+            }
+
+            if inner
+                .tor_exit_nodes
+                .iter()
+                .any(|network| network.contains(ip))
+            {
+                signals.push(LoginRiskSignal::TorExitNode);
+            }
+        }
+
+        let score = config.score(&signals);
+        let mut decision = config.decide(score);
+
+        if decision == LoginRiskDecision::RequireMfa {
+            let has_confirmed_email = if let Some(user_email_id) = user.primary_user_email_id {
+                repo.user_email()
+                    .lookup(user_email_id)
+                    .await?
+                    .is_some_and(|user_email| user_email.confirmed_at.is_some())
+            } else {
+                false
+            };
+
+            if !has_confirmed_email {
+                decision = LoginRiskDecision::Deny;
+            }
+        }
+
+        let assessment = repo
+            .user_login_risk()
+            .add(
+                rng, clock, user, user_agent, ip_address, request_id, score, signals, decision,
+            )
+            .await?;
+
+        tracing::info!(
+            user_login_risk_assessment.id = %assessment.id,
+            user.id = %user.id,
+            user_login_risk_assessment.score = assessment.score,
+            user_login_risk_assessment.signals = ?assessment.signals,
+            user_login_risk_assessment.decision = %assessment.decision,
+            "Assessed the risk of a login",
+        );
+
+        Ok(Some(assessment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::user::UserRepository;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{init_tracing, TestState};
+
+    fn config() -> LoginRiskConfig {
+        LoginRiskConfig {
+            new_device_score: 40,
+            low_reputation_score: 40,
+            tor_exit_node_score: 60,
+            impossible_travel_score: 60,
+            impossible_travel_window: Duration::try_hours(2).unwrap(),
+            mfa_threshold: 40,
+            deny_threshold: 100,
+        }
+    }
+
+    #[test]
+    fn test_parse_network_list() {
+        let networks = parse_network_list(
+            "# Tor exit nodes\n\n192.0.2.1\n  198.51.100.0/24  \n2001:db8::/32\n",
+        )
+        .unwrap();
+        let networks: Vec<String> = networks.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            networks,
+            ["192.0.2.1/32", "198.51.100.0/24", "2001:db8::/32"]
+        );
+
+        assert!(parse_network_list("192.0.2.1\nnot an address\n").is_err());
+    }
+
+    #[test]
+    fn test_score_and_decide() {
+        let config = config();
+
+        let score = config.score(&[]);
+        assert_eq!(score, 0);
+        assert_eq!(config.decide(score), LoginRiskDecision::Allow);
+
+        let score = config.score(&[LoginRiskSignal::NewDevice]);
+        assert_eq!(score, 40);
+        assert_eq!(config.decide(score), LoginRiskDecision::RequireMfa);
+
+        let score = config.score(&[LoginRiskSignal::NewDevice, LoginRiskSignal::TorExitNode]);
+        assert_eq!(score, 100);
+        assert_eq!(config.decide(score), LoginRiskDecision::Deny);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_assess(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        let login_risk = LoginRisk::new(
+            config(),
+            Vec::new(),
+            parse_network_list("203.0.113.0/24").unwrap(),
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let firefox = UserAgent::parse("Firefox".to_owned());
+        let home: IpAddr = "192.0.2.1".parse().unwrap();
+        let tor: IpAddr = "203.0.113.42".parse().unwrap();
+
+        // Nothing is assessed if the risk assessment is disabled
+        let assessment = LoginRisk::disabled()
+            .assess(
+                &mut rng,
+                &state.clock,
+                &mut repo,
+                &state.geoip,
+                &user,
+                Some(firefox.clone()),
+                Some(tor),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(assessment.is_none());
+
+        // The first login of the user is not from a new device
+        let assessment = login_risk
+            .assess(
+                &mut rng,
+                &state.clock,
+                &mut repo,
+                &state.geoip,
+                &user,
+                Some(firefox.clone()),
+                Some(home),
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(assessment.signals.is_empty());
+        assert_eq!(assessment.decision, LoginRiskDecision::Allow);
+
+        repo.user_login_alert()
+            .record_sighting(
+                &mut rng,
+                &state.clock,
+                &user,
+                &LoginSighting::device_of(&firefox),
+                LoginSighting::network_of(home),
+            )
+            .await
+            .unwrap();
+
+        // A new network needs a code, but the user has no email address to
+        // send it to
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+        let assessment = login_risk
+            .assess(
+                &mut rng,
+                &state.clock,
+                &mut repo,
+                &state.geoip,
+                &user,
+                Some(firefox.clone()),
+                Some(other),
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(assessment.signals, [LoginRiskSignal::NewDevice]);
+        assert_eq!(assessment.score, 40);
+        assert_eq!(assessment.decision, LoginRiskDecision::Deny);
+
+        let user_email = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .mark_as_verified(&state.clock, user_email)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&user_email).await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+
+        let assessment = login_risk
+            .assess(
+                &mut rng,
+                &state.clock,
+                &mut repo,
+                &state.geoip,
+                &user,
+                Some(firefox.clone()),
+                Some(other),
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(assessment.decision, LoginRiskDecision::RequireMfa);
+
+        // A login without a user agent or an IP address comes from a new device
+        for (user_agent, ip) in [(None, Some(home)), (Some(firefox.clone()), None)] {
+            let assessment = login_risk
+                .assess(
+                    &mut rng,
+                    &state.clock,
+                    &mut repo,
+                    &state.geoip,
+                    &user,
+                    user_agent,
+                    ip,
+                    None,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(assessment.signals, [LoginRiskSignal::NewDevice]);
+            assert_eq!(assessment.decision, LoginRiskDecision::RequireMfa);
+        }
+
+        // A new network which is a Tor exit node is denied
+        let assessment = login_risk
+            .assess(
+                &mut rng,
+                &state.clock,
+                &mut repo,
+                &state.geoip,
+                &user,
+                Some(firefox),
+                Some(tor),
+                Some("request-id".to_owned()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            assessment.signals,
+            [LoginRiskSignal::NewDevice, LoginRiskSignal::TorExitNode]
+        );
+        assert_eq!(assessment.decision, LoginRiskDecision::Deny);
+
+        // The assessments are recorded
+        let recorded = repo
+            .user_login_risk()
+            .lookup(assessment.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(recorded, assessment);
+        assert_eq!(recorded.request_id.as_deref(), Some("request-id"));
+
+        repo.save().await.unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handler for the Matrix client discovery document, served on
+//! `/.well-known/matrix/client`
+
+use std::sync::Arc;
+
+use axum::{extract::State, Extension, Json};
+use mas_router::UrlBuilder;
+use serde_json::{json, Map, Value};
+use url::Url;
+
+/// What to put in the document, from the listener configuration
+pub(crate) struct Config {
+    pub homeserver_base_url: Url,
+    pub extra: Map<String, Value>,
+}
+
+/// Build the document, with the additional properties from the configuration
+/// and the ones pointing at the homeserver and at this service
+fn document(config: &Config, url_builder: &UrlBuilder) -> Value {
+    let mut document = config.extra.clone();
+
+    document.insert(
+        "m.homeserver".to_owned(),
+        json!({ "base_url": config.homeserver_base_url }),
+    );
+
+    // As per MSC2965
+    document.insert(
+        "org.matrix.msc2965.authentication".to_owned(),
+        json!({
+            "issuer": url_builder.oidc_issuer(),
+            "account": url_builder.account_management_uri(),
+        }),
+    );
+
+    Value::Object(document)
+}
+
+#[tracing::instrument(name = "handlers.matrix_well_known.get", skip_all)]
+pub(crate) async fn get(
+    State(url_builder): State<UrlBuilder>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Json<Value> {
+    Json(document(&config, &url_builder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let url_builder = UrlBuilder::new("https://auth.example.com/".parse().unwrap(), None, None);
+        let config = Config {
+            homeserver_base_url: "https://matrix.example.com/".parse().unwrap(),
+            extra: json!({
+                "m.identity_server": { "base_url": "https://identity.example.com/" },
+                "m.homeserver": { "base_url": "https://overridden.example.com/" },
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        };
+
+        assert_eq!(
+            document(&config, &url_builder),
+            json!({
+                "m.homeserver": { "base_url": "https://matrix.example.com/" },
+                "m.identity_server": { "base_url": "https://identity.example.com/" },
+                "org.matrix.msc2965.authentication": {
+                    "issuer": "https://auth.example.com/",
+                    "account": "https://auth.example.com/account/",
+                },
+            })
+        );
+    }
+}
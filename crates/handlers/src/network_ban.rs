@@ -0,0 +1,459 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Temporary bans of the networks which fail too many authentication attempts
+//!
+//! Failed attempts are aggregated per network, across all the accounts, using
+//! the rate limiter backend, so that they are shared between instances when
+//! Redis is configured. Once a network exceeds its quota, it is banned for a
+//! while, and all its requests are rejected by the
+//! [`reject_banned_networks`] middleware.
+//!
+//! The active bans are kept in memory, and reloaded regularly from the
+//! database so that bans put in place or lifted by other instances are picked
+//! up.
+//!
+//! Addresses within the trusted proxies are never tracked nor rejected: they
+//! show up when the address of the client could not be inferred from the
+//! proxy headers, and banning them would lock everyone out.
+
+use std::{
+    net::IpAddr,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{
+    header::{ACCEPT, RETRY_AFTER},
+    StatusCode,
+};
+use mas_axum_utils::FancyError;
+use mas_data_model::{Network, NetworkBan};
+use mas_storage::{BoxClock, Clock, RepositoryAccess};
+use mas_storage_pg::PgRepository;
+use mas_templates::{ErrorContext, TemplateContext, Templates};
+use rand::RngCore;
+use sqlx::PgPool;
+use ulid::Ulid;
+
+use crate::{
+    rate_limit::{Limiter, Quota},
+    BoundActivityTracker, PreferredLanguage,
+};
+
+/// The configuration of the automatic bans
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkBansConfig {
+    /// Failed authentication attempts, per network
+    pub failed_attempts: Quota,
+
+    /// How long a network is banned for
+    pub duration: Duration,
+
+    /// Prefix length of the IPv4 networks on which failed attempts are
+    /// aggregated
+    pub ipv4_prefix_len: u8,
+
+    /// Prefix length of the IPv6 networks on which failed attempts are
+    /// aggregated
+    pub ipv6_prefix_len: u8,
+}
+
+impl NetworkBansConfig {
+    /// The network on which the failed attempts of the address are aggregated
+    fn network_of(&self, ip: IpAddr) -> Option<Network> {
+        let prefix_len = match ip {
+            IpAddr::V4(_) => self.ipv4_prefix_len,
+            IpAddr::V6(_) => self.ipv6_prefix_len,
+        };
+
+        Network::new(ip, prefix_len).ok()
+    }
+}
+
+/// Keeps track of the banned networks
+///
+/// Manual bans are always enforced, whereas networks are only banned
+/// automatically if a [`NetworkBansConfig`] is set.
+#[derive(Debug, Clone)]
+pub struct NetworkBans {
+    limiter: Limiter,
+    config: Option<NetworkBansConfig>,
+    trusted_proxies: Arc<Vec<Network>>,
+    active: Arc<RwLock<Vec<NetworkBan>>>,
+}
+
+impl NetworkBans {
+    /// Create a new [`NetworkBans`], counting the failed attempts with the
+    /// given [`Limiter`]
+    #[must_use]
+    pub fn new(limiter: Limiter, config: Option<NetworkBansConfig>) -> Self {
+        Self {
+            limiter,
+            config,
+            trusted_proxies: Arc::default(),
+            active: Arc::default(),
+        }
+    }
+
+    /// Set the networks of the trusted proxies, whose addresses are neither
+    /// tracked nor rejected
+    #[must_use]
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<Network>) -> Self {
+        self.trusted_proxies = Arc::new(trusted_proxies);
+        self
+    }
+
+    /// Whether the address belongs to one of the trusted proxies, meaning the
+    /// address of the client could not be inferred
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
+
+    /// Find the active ban covering the given address, if any
+    #[must_use]
+    pub fn find(&self, now: DateTime<Utc>, ip: IpAddr) -> Option<NetworkBan> {
+        let active = self.active.read().expect("network bans lock poisoned");
+        active
+            .iter()
+            .find(|ban| ban.is_active(now) && ban.network.contains(ip))
+            .cloned()
+    }
+
+    /// Start enforcing a ban which was just put in place
+    pub fn insert(&self, ban: NetworkBan) {
+        let mut active = self.active.write().expect("network bans lock poisoned");
+        active.retain(|b| b.id != ban.id);
+        active.push(ban);
+    }
+
+    /// Stop enforcing a ban which was just lifted
+    pub fn remove(&self, id: Ulid) {
+        let mut active = self.active.write().expect("network bans lock poisoned");
+        active.retain(|b| b.id != id);
+    }
+
+    /// Reload the active bans from the database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository fails
+    pub async fn reload<R: RepositoryAccess + ?Sized>(
+        &self,
+        repo: &mut R,
+        clock: &dyn Clock,
+    ) -> Result<(), R::Error> {
+        let bans = repo.network_ban().all_active(clock).await?;
+        *self.active.write().expect("network bans lock poisoned") = bans;
+        Ok(())
+    }
+
+    /// Spawn a task reloading the active bans at the given interval
+    pub fn run(&self, pool: PgPool, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let bans = self.clone();
+        tokio::spawn(async move {
+            let clock = mas_storage::SystemClock::default();
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+
+                let res = async {
+                    let mut repo = PgRepository::from_pool(&pool).await?;
+                    bans.reload(&mut repo, &clock).await?;
+                    Ok::<_, mas_storage_pg::DatabaseError>(())
+                }
+                .await;
+
+                if let Err(e) = res {
+                    tracing::warn!(
+                        error = &e as &dyn std::error::Error,
+                        "Failed to reload the network bans"
+                    );
+                }
+            }
+        })
+    }
+
+    /// Record a failed authentication attempt from the given address, and ban
+    /// its network if it failed too many times
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository fails
+    pub async fn record_failure<R: RepositoryAccess + ?Sized>(
+        &self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        repo: &mut R,
+        ip: Option<IpAddr>,
+    ) -> Result<(), R::Error> {
+        let (Some(config), Some(ip)) = (self.config, ip) else {
+            return Ok(());
+        };
+
+        if self.is_trusted_proxy(ip) {
+            return Ok(());
+        }
+
+        let Some(network) = config.network_of(ip) else {
+            return Ok(());
+        };
+
+        if self
+            .limiter
+            .take(
+                clock,
+                "failed_authentication.network",
+                network,
+                config.failed_attempts,
+            )
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        // The network might have been banned by a concurrent request
+        if self.find(clock.now(), ip).is_some() {
+            return Ok(());
+        }
+
+        let ban = repo
+            .network_ban()
+            .add(rng, clock, network, None, clock.now() + config.duration)
+            .await?;
+
+        tracing::warn!(
+            network_ban.id = %ban.id,
+            network_ban.network = %ban.network,
+            "Banned a network after too many failed authentication attempts",
+        );
+
+        self.insert(ban);
+
+        Ok(())
+    }
+}
+
+/// Rejects the requests coming from a banned network
+///
+/// Browsers get an error page, and other clients a JSON error. In both cases,
+/// the `Retry-After` header tells when the ban ends.
+pub async fn reject_banned_networks<B>(
+    State(network_bans): State<NetworkBans>,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    clock: BoxClock,
+    activity_tracker: BoundActivityTracker,
+    request: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> Response
+where
+    B: Send,
+{
+    let now = clock.now();
+    let Some(ban) = activity_tracker
+        .ip()
+        .filter(|ip| !network_bans.is_trusted_proxy(*ip))
+        .and_then(|ip| network_bans.find(now, ip))
+    else {
+        return next.run(request).await;
+    };
+
+    tracing::info!(
+        network_ban.id = %ban.id,
+        network_ban.network = %ban.network,
+        "Rejected a request from a banned network",
+    );
+
+    let retry_after = [(RETRY_AFTER, (ban.expires_at - now).num_seconds().max(1))];
+
+    let accepts_html = request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/html"));
+
+    if !accepts_html {
+        let body = if request.uri().path().starts_with("/_matrix/") {
+            serde_json::json!({
+                "errcode": "M_FORBIDDEN",
+                "error": "Too many failed attempts from this network, try again later",
+            })
+        } else {
+            serde_json::json!({
+                "error": "access_denied",
+                "error_description": "Too many failed attempts from this network, try again later",
+            })
+        };
+
+        return (StatusCode::FORBIDDEN, retry_after, Json(body)).into_response();
+    }
+
+    let ctx = ErrorContext::new()
+        .with_code("network_banned")
+        .with_language(&locale);
+
+    match templates.render_error(&ctx) {
+        Ok(res) => (StatusCode::FORBIDDEN, retry_after, Html(res)).into_response(),
+        Err(e) => FancyError::from(e).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{init_tracing, test_limiter_config, TestState};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_record_failure(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        let config = NetworkBansConfig {
+            failed_attempts: Quota {
+                burst: NonZeroU32::new(3).unwrap(),
+                per_second: 0.001,
+            },
+            duration: Duration::try_hours(1).unwrap(),
+            ipv4_prefix_len: 24,
+            ipv6_prefix_len: 64,
+        };
+        let bans = NetworkBans::new(Limiter::new(test_limiter_config()), Some(config));
+
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let neighbour: IpAddr = "192.0.2.200".parse().unwrap();
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+
+        // Failures without an address are ignored
+        let mut repo = state.repository().await.unwrap();
+        for _ in 0..10 {
+            bans.record_failure(&mut rng, &state.clock, &mut repo, None)
+                .await
+                .unwrap();
+        }
+
+        // The first failures are tolerated
+        for _ in 0..3 {
+            bans.record_failure(&mut rng, &state.clock, &mut repo, Some(ip))
+                .await
+                .unwrap();
+        }
+        assert!(bans.find(state.clock.now(), ip).is_none());
+
+        // Failures are aggregated on the whole network
+        bans.record_failure(&mut rng, &state.clock, &mut repo, Some(neighbour))
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let ban = bans.find(state.clock.now(), ip).unwrap();
+        assert!(ban.is_automatic());
+        assert_eq!(ban.network.to_string(), "192.0.2.0/24");
+        assert!(bans.find(state.clock.now(), neighbour).is_some());
+        assert!(bans.find(state.clock.now(), other).is_none());
+
+        // Another instance picks up the ban from the database
+        let other_instance = NetworkBans::new(Limiter::new(test_limiter_config()), None);
+        let mut repo = state.repository().await.unwrap();
+        other_instance
+            .reload(&mut repo, &state.clock)
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(
+            other_instance.find(state.clock.now(), ip).map(|b| b.id),
+            Some(ban.id)
+        );
+
+        // Lifted bans are not enforced anymore
+        other_instance.remove(ban.id);
+        assert!(other_instance.find(state.clock.now(), ip).is_none());
+
+        // The ban ends after a while
+        state.clock.advance(Duration::try_hours(1).unwrap());
+        assert!(bans.find(state.clock.now(), ip).is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_trusted_proxies(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        let config = NetworkBansConfig {
+            failed_attempts: Quota {
+                burst: NonZeroU32::new(1).unwrap(),
+                per_second: 0.001,
+            },
+            duration: Duration::try_hours(1).unwrap(),
+            ipv4_prefix_len: 24,
+            ipv6_prefix_len: 64,
+        };
+        let bans = NetworkBans::new(Limiter::new(test_limiter_config()), Some(config))
+            .with_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()]);
+
+        let proxy: IpAddr = "10.1.2.3".parse().unwrap();
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+
+        // Failures attributed to a trusted proxy are ignored
+        let mut repo = state.repository().await.unwrap();
+        for _ in 0..10 {
+            bans.record_failure(&mut rng, &state.clock, &mut repo, Some(proxy))
+                .await
+                .unwrap();
+        }
+        assert!(bans.find(state.clock.now(), proxy).is_none());
+
+        // Other addresses still get banned
+        for _ in 0..2 {
+            bans.record_failure(&mut rng, &state.clock, &mut repo, Some(client))
+                .await
+                .unwrap();
+        }
+        assert!(bans.find(state.clock.now(), client).is_some());
+
+        // Bans covering a trusted proxy are not enforced on it
+        let network = "10.0.0.0/16".parse().unwrap();
+        let ban = repo
+            .network_ban()
+            .add(
+                &mut rng,
+                &state.clock,
+                network,
+                None,
+                state.clock.now() + Duration::try_hours(1).unwrap(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        bans.insert(ban);
+        assert!(bans.find(state.clock.now(), proxy).is_some());
+        assert!(bans.is_trusted_proxy(proxy));
+        assert!(!bans.is_trusted_proxy(client));
+    }
+}
@@ -17,7 +17,9 @@
 use std::collections::HashMap;
 
 use axum::response::{Html, IntoResponse, Redirect, Response};
+use hyper::header::{CACHE_CONTROL, PRAGMA};
 use mas_data_model::AuthorizationGrant;
+use mas_i18n::DataLocale;
 use mas_templates::{FormPostContext, Templates};
 use oauth2_types::requests::ResponseMode;
 use serde::Serialize;
@@ -38,6 +40,7 @@ pub struct CallbackDestination {
     mode: CallbackDestinationMode,
     safe_redirect_uri: Url,
     state: Option<String>,
+    locale: Option<DataLocale>,
 }
 
 #[derive(Debug, Error)]
@@ -105,9 +108,18 @@ impl CallbackDestination {
             mode,
             safe_redirect_uri: redirect_uri,
             state,
+            locale: None,
         })
     }
 
+    /// Set the language of the page rendered for the `form_post` response
+    /// mode
+    #[must_use]
+    pub fn with_language(mut self, locale: DataLocale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
     pub async fn go<T: Serialize + Send + Sync>(
         self,
         templates: &Templates,
@@ -163,9 +175,20 @@ impl CallbackDestination {
                     state,
                     params,
                 };
-                let ctx = FormPostContext::new(redirect_uri, merged);
+                let mut ctx = FormPostContext::new(redirect_uri, merged);
+                if let Some(locale) = &self.locale {
+                    ctx = ctx.with_language(locale);
+                }
+
                 let rendered = templates.render_form_post(&ctx)?;
-                Ok(Html(rendered).into_response())
+
+                // The page contains the response parameters, so it must not end up in the
+                // browser history or any cache
+                Ok((
+                    [(CACHE_CONTROL, "no-store"), (PRAGMA, "no-cache")],
+                    Html(rendered),
+                )
+                    .into_response())
             }
         }
     }
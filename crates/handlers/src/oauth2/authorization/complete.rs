@@ -23,19 +23,28 @@ use mas_keystore::Keystore;
 use mas_policy::{EvaluationResult, Policy};
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob},
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository, OAuth2SessionRepository},
     user::BrowserSessionRepository,
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use mas_templates::{PolicyViolationContext, TemplateContext, Templates};
-use oauth2_types::requests::AuthorizationResponse;
+use oauth2_types::{
+    errors::{ClientError, ClientErrorCode},
+    requests::AuthorizationResponse,
+    scope,
+};
 use thiserror::Error;
 use tracing::warn;
 use ulid::Ulid;
 
 use super::callback::CallbackDestination;
 use crate::{
-    impl_from_error_for_route, oauth2::generate_id_token, BoundActivityTracker, PreferredLanguage,
+    continuation::Continuations,
+    impl_from_error_for_route,
+    oauth2::generate_id_token,
+    spam_check::{SpamChecker, Verdict},
+    BoundActivityTracker, PreferredLanguage,
 };
 
 #[derive(Debug, Error)]
@@ -95,6 +104,7 @@ pub(crate) async fn get(
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
     State(key_store): State<Keystore>,
+    State(spam_checker): State<SpamChecker>,
     policy: Policy,
     activity_tracker: BoundActivityTracker,
     mut repo: BoxRepository,
@@ -103,6 +113,12 @@ pub(crate) async fn get(
 ) -> Result<Response, RouteError> {
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
+    // Only continue grants which were started in this browser
+    let mut continuations = Continuations::load(&cookie_jar);
+    if let Err(e) = continuations.verify(grant_id, clock.now()) {
+        return Ok((cookie_jar, e).into_response());
+    }
+
     let maybe_session = session_info.load_session(&mut repo).await?;
 
     let grant = repo
@@ -111,7 +127,7 @@ pub(crate) async fn get(
         .await?
         .ok_or(RouteError::NotFound)?;
 
-    let callback_destination = CallbackDestination::try_from(&grant)?;
+    let callback_destination = CallbackDestination::try_from(&grant)?.with_language(locale.clone());
     let continue_grant = PostAuthAction::continue_grant(grant.id);
 
     let Some(session) = maybe_session else {
@@ -141,6 +157,7 @@ pub(crate) async fn get(
         repo,
         key_store,
         policy,
+        &spam_checker,
         &url_builder,
         grant,
         &client,
@@ -150,6 +167,8 @@ pub(crate) async fn get(
     {
         Ok(params) => {
             let res = callback_destination.go(&templates, params).await?;
+            continuations.remove(grant_id);
+            let cookie_jar = continuations.save(cookie_jar);
             Ok((cookie_jar, res).into_response())
         }
         Err(GrantCompletionError::RequiresReauth) => Ok((
@@ -174,6 +193,14 @@ pub(crate) async fn get(
 
             Ok((cookie_jar, Html(content)).into_response())
         }
+        Err(GrantCompletionError::Denied) => {
+            let res = callback_destination
+                .go(&templates, ClientError::from(ClientErrorCode::AccessDenied))
+                .await?;
+            continuations.remove(grant_id);
+            let cookie_jar = continuations.save(cookie_jar);
+            Ok((cookie_jar, res).into_response())
+        }
         Err(GrantCompletionError::NotPending) => Err(RouteError::NotPending),
         Err(GrantCompletionError::Internal(e)) => Err(RouteError::Internal(e)),
     }
@@ -195,6 +222,9 @@ pub enum GrantCompletionError {
 
     #[error("denied by the policy")]
     PolicyViolation(AuthorizationGrant, EvaluationResult),
+
+    #[error("denied by the anti-abuse service")]
+    Denied,
 }
 
 impl_from_error_for_route!(GrantCompletionError: mas_storage::RepositoryError);
@@ -210,6 +240,7 @@ pub(crate) async fn complete(
     mut repo: BoxRepository,
     key_store: Keystore,
     mut policy: Policy,
+    spam_checker: &SpamChecker,
     url_builder: &UrlBuilder,
     grant: AuthorizationGrant,
     client: &Client,
@@ -252,21 +283,46 @@ pub(crate) async fn complete(
         .filter(|scope| Device::from_scope_token(scope).is_none())
         .any(|_| true);
 
+    // OpenID Connect requires the user to explicitly consent to offline access,
+    // even for clients which are otherwise trusted
+    let lacks_offline_consent = grant.scope.contains(&scope::OFFLINE_ACCESS)
+        && !current_consent.contains(&scope::OFFLINE_ACCESS);
+
+    // Trusted clients skip the consent screen, unless it was explicitly asked.
+    // Bots can't interact with the consent screen, so they always skip it
+    let lacks_consent = !browser_session.user.is_bot
+        && ((lacks_consent && !client.skip_consent) || lacks_offline_consent);
+
     // Check if the client lacks consent *or* if consent was explicitly asked
     if lacks_consent || grant.requires_consent {
         repo.save().await?;
         return Err(GrantCompletionError::RequiresConsent);
     }
 
-    // All good, let's start the session
-    let session = repo
-        .oauth2_session()
-        .add_from_browser_session(rng, clock, client, browser_session, grant.scope.clone())
-        .await?;
+    // Give the anti-abuse service a chance to deny the authorization
+    match spam_checker
+        .check_authorization(activity_tracker, browser_session, client, &grant.scope)
+        .await
+    {
+        Verdict::Allow => {}
+        Verdict::Deny => {
+            repo.save().await?;
+            return Err(GrantCompletionError::Denied);
+        }
+        Verdict::ShadowBan => {
+            repo.user()
+                .shadow_ban(clock, browser_session.user.clone())
+                .await?;
+            repo.job()
+                .schedule_job(ProvisionUserJob::new(&browser_session.user).shadow_ban())
+                .await?;
+        }
+    }
 
-    let grant = repo
+    // All good, let's start the session and fulfill the grant
+    let (session, grant) = repo
         .oauth2_authorization_grant()
-        .fulfill(clock, &session, grant)
+        .start_session(rng, clock, browser_session, grant)
         .await?;
 
     // Yep! Let's complete the auth now
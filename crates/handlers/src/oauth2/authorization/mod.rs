@@ -18,7 +18,7 @@ use axum::{
 };
 use hyper::StatusCode;
 use mas_axum_utils::{cookies::CookieJar, csrf::CsrfExt, sentry::SentryEventID, SessionInfoExt};
-use mas_data_model::{AuthorizationCode, Pkce};
+use mas_data_model::{AuthorizationCode, Pkce, SiteConfig};
 use mas_keystore::Keystore;
 use mas_policy::Policy;
 use mas_router::{PostAuthAction, UrlBuilder};
@@ -39,7 +39,13 @@ use thiserror::Error;
 use tracing::warn;
 
 use self::{callback::CallbackDestination, complete::GrantCompletionError};
-use crate::{impl_from_error_for_route, BoundActivityTracker, PreferredLanguage};
+use crate::{
+    continuation::Continuations,
+    impl_from_error_for_route,
+    preferred_language::{choose_locale, UiLocalesExt},
+    spam_check::SpamChecker,
+    BoundActivityTracker, PreferredLanguage,
+};
 
 mod callback;
 pub mod complete;
@@ -105,6 +111,98 @@ pub(crate) struct Params {
     pkce: Option<pkce::AuthorizationRequest>,
 }
 
+/// The maximum length of the `state` parameter, in bytes
+const MAX_STATE_LENGTH: usize = 2048;
+
+/// The maximum length of the `nonce` parameter, in bytes
+const MAX_NONCE_LENGTH: usize = 512;
+
+/// The minimum estimated entropy of a `code_challenge`, in bits
+const MIN_CODE_CHALLENGE_ENTROPY: f64 = 128.0;
+
+/// Check that a free-form parameter is not too long and doesn't contain
+/// control characters, which can't be stored or sent back safely
+fn validate_param(name: &str, value: &str, max_length: usize) -> Result<(), ClientError> {
+    if value.len() > max_length {
+        return Err(ClientError::from(ClientErrorCode::InvalidRequest)
+            .with_description(format!("{name} must be at most {max_length} bytes long")));
+    }
+
+    if value.chars().any(char::is_control) {
+        return Err(ClientError::from(ClientErrorCode::InvalidRequest)
+            .with_description(format!("{name} must not contain control characters")));
+    }
+
+    Ok(())
+}
+
+/// Check that a `code_challenge` has the shape mandated by RFC 7636, and that
+/// it doesn't look like a low-entropy value
+#[allow(clippy::cast_precision_loss)]
+fn validate_code_challenge(challenge: &str) -> Result<(), ClientError> {
+    if !(43..=128).contains(&challenge.len())
+        || !challenge
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'))
+    {
+        return Err(
+            ClientError::from(ClientErrorCode::InvalidRequest).with_description(
+                "code_challenge must be between 43 and 128 unreserved characters".to_owned(),
+            ),
+        );
+    }
+
+    // Estimate the entropy from the distribution of the characters
+    let mut counts = [0_usize; 256];
+    for b in challenge.bytes() {
+        counts[usize::from(b)] += 1;
+    }
+    let length = challenge.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let count = count as f64;
+            -count * (count / length).log2()
+        })
+        .sum();
+
+    if entropy < MIN_CODE_CHALLENGE_ENTROPY {
+        return Err(ClientError::from(ClientErrorCode::InvalidRequest)
+            .with_description("code_challenge does not have enough entropy".to_owned()));
+    }
+
+    Ok(())
+}
+
+impl Params {
+    /// Validate the size and content of the parameters which are stored and
+    /// sent back to the client
+    fn validate(&self) -> Result<(), ClientError> {
+        if let Some(state) = &self.auth.state {
+            validate_param("state", state, MAX_STATE_LENGTH)?;
+        }
+
+        if let Some(nonce) = &self.auth.nonce {
+            validate_param("nonce", nonce, MAX_NONCE_LENGTH)?;
+        } else if self.auth.response_type.has_id_token() {
+            // The nonce is what binds the ID token to the client session, and is
+            // required when it is returned from the authorization endpoint
+            return Err(
+                ClientError::from(ClientErrorCode::InvalidRequest).with_description(
+                    "nonce is required when the response_type includes id_token".to_owned(),
+                ),
+            );
+        }
+
+        if let Some(pkce) = &self.pkce {
+            validate_code_challenge(&pkce.code_challenge)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Given a list of response types and an optional user-defined response mode,
 /// figure out what response mode must be used, and emit an error if the
 /// suggested response mode isn't allowed for the given response types.
@@ -143,6 +241,8 @@ pub(crate) async fn get(
     State(templates): State<Templates>,
     State(key_store): State<Keystore>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(spam_checker): State<SpamChecker>,
     policy: Policy,
     activity_tracker: BoundActivityTracker,
     mut repo: BoxRepository,
@@ -163,25 +263,50 @@ pub(crate) async fn get(
     let response_type = params.auth.response_type;
     let response_mode = resolve_response_mode(&response_type, params.auth.response_mode)?;
 
-    // Now we have a proper callback destination to go to on error
-    let callback_destination = CallbackDestination::try_new(
-        &response_mode,
-        redirect_uri.clone(),
-        params.auth.state.clone(),
-    )?;
+    // Now we have a proper callback destination to go to on error. The state is
+    // only sent back if it is valid
+    let state = params
+        .auth
+        .state
+        .clone()
+        .filter(|state| validate_param("state", state, MAX_STATE_LENGTH).is_ok());
+    let callback_destination =
+        CallbackDestination::try_new(&response_mode, redirect_uri.clone(), state)?;
 
     // Get the session info from the cookie
     let (session_info, cookie_jar) = cookie_jar.session_info();
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
 
+    // Remember the locales requested by the client for the rest of the flow,
+    // and use them right away for this request
+    let ui_locales = params.auth.ui_locales.as_deref().unwrap_or_default();
+    let cookie_jar = cookie_jar.set_ui_locales(ui_locales);
+    let locale = choose_locale(
+        &templates.translator(),
+        ui_locales
+            .iter()
+            .filter_map(|tag| tag.as_str().parse().ok())
+            .chain(std::iter::once(locale)),
+    );
+    let callback_destination = callback_destination.with_language(locale.clone());
+
+    // Remember the grants started in this browser, so that they can't be continued
+    // from another one
+    let mut continuations = Continuations::load(&cookie_jar);
+
     // One day, we will have try blocks
     let res: Result<Response, RouteError> = ({
         let templates = templates.clone();
         let callback_destination = callback_destination.clone();
+        let continuations = &mut continuations;
         async move {
             let maybe_session = session_info.load_session(&mut repo).await?;
             let prompt = params.auth.prompt.as_deref().unwrap_or_default();
 
+            if let Err(error) = params.validate() {
+                return Ok(callback_destination.go(&templates, error).await?);
+            }
+
             // Check if the request/request_uri/registration params are used. If so, reply
             // with the right error since we don't support them.
             if params.auth.request.is_some() {
@@ -213,6 +338,30 @@ pub(crate) async fn get(
                     .await?);
             }
 
+            // Public clients may be forbidden by the configuration
+            if client.is_public() && !site_config.public_clients_allowed {
+                return Ok(callback_destination
+                    .go(
+                        &templates,
+                        ClientError::from(ClientErrorCode::UnauthorizedClient),
+                    )
+                    .await?);
+            }
+
+            // Check that the client is allowed to use this response type
+            if !client
+                .response_types
+                .iter()
+                .any(|allowed| ResponseType::from(allowed.clone()) == response_type)
+            {
+                return Ok(callback_destination
+                    .go(
+                        &templates,
+                        ClientError::from(ClientErrorCode::UnauthorizedClient),
+                    )
+                    .await?);
+            }
+
             // If the client asked for a `id_token` response type, we must check if it can
             // use the `implicit` grant type
             if response_type.has_id_token() && !client.grant_types.contains(&GrantType::Implicit) {
@@ -224,6 +373,20 @@ pub(crate) async fn get(
                     .await?);
             }
 
+            // Apply the default scope and the scope restrictions of the client
+            let scope = match client.restrict_scope(params.auth.scope) {
+                Ok(scope) => scope,
+                Err(e) => {
+                    return Ok(callback_destination
+                        .go(
+                            &templates,
+                            ClientError::from(ClientErrorCode::InvalidScope)
+                                .with_description(e.to_string()),
+                        )
+                        .await?);
+                }
+            };
+
             if params.auth.registration.is_some() {
                 return Ok(callback_destination
                     .go(
@@ -254,6 +417,18 @@ pub(crate) async fn get(
                         .await?);
                 }
 
+                // Public clients must use PKCE
+                if client.is_public() && params.pkce.is_none() {
+                    return Ok(callback_destination
+                        .go(
+                            &templates,
+                            ClientError::from(ClientErrorCode::InvalidRequest).with_description(
+                                "code_challenge is required for public clients".to_owned(),
+                            ),
+                        )
+                        .await?);
+                }
+
                 // 32 random alphanumeric characters, about 190bit of entropy
                 let code: String = (&mut rng)
                     .sample_iter(&Alphanumeric)
@@ -291,7 +466,7 @@ pub(crate) async fn get(
                     &clock,
                     &client,
                     redirect_uri.clone(),
-                    params.auth.scope,
+                    scope,
                     code,
                     params.auth.state.clone(),
                     params.auth.nonce,
@@ -301,6 +476,7 @@ pub(crate) async fn get(
                     requires_consent,
                 )
                 .await?;
+            continuations.add(grant.id, grant.created_at);
             let continue_grant = PostAuthAction::continue_grant(grant.id);
 
             let res = match maybe_session {
@@ -350,6 +526,7 @@ pub(crate) async fn get(
                         repo,
                         key_store,
                         policy,
+                        &spam_checker,
                         &url_builder,
                         grant,
                         &client,
@@ -374,7 +551,10 @@ pub(crate) async fn get(
                                 )
                                 .await?
                         }
-                        Err(GrantCompletionError::PolicyViolation(_grant, _res)) => {
+                        Err(
+                            GrantCompletionError::PolicyViolation(_, _)
+                            | GrantCompletionError::Denied,
+                        ) => {
                             callback_destination
                                 .go(&templates, ClientError::from(ClientErrorCode::AccessDenied))
                                 .await?
@@ -400,6 +580,7 @@ pub(crate) async fn get(
                         repo,
                         key_store,
                         policy,
+                        &spam_checker,
                         &url_builder,
                         grant,
                         &client,
@@ -407,7 +588,10 @@ pub(crate) async fn get(
                     )
                     .await
                     {
-                        Ok(params) => callback_destination.go(&templates, params).await?,
+                        Ok(params) => {
+                            continuations.remove(grant_id);
+                            callback_destination.go(&templates, params).await?
+                        }
                         Err(GrantCompletionError::RequiresConsent) => {
                             url_builder.redirect(&mas_router::Consent(grant_id)).into_response()
                         }
@@ -426,6 +610,12 @@ pub(crate) async fn get(
                             url_builder.redirect(&mas_router::Reauth::and_then(continue_grant))
                                 .into_response()
                         }
+                        Err(GrantCompletionError::Denied) => {
+                            continuations.remove(grant_id);
+                            callback_destination
+                                .go(&templates, ClientError::from(ClientErrorCode::AccessDenied))
+                                .await?
+                        }
                         Err(GrantCompletionError::Internal(e)) => {
                             return Err(RouteError::Internal(e))
                         }
@@ -452,5 +642,411 @@ pub(crate) async fn get(
         }
     };
 
+    let cookie_jar = continuations.save(cookie_jar);
+
     Ok((cookie_jar, response).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_iana::oauth::{
+        OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
+    };
+    use mas_router::{
+        OAuth2AuthorizationEndpoint, OAuth2Introspection, OAuth2RegistrationEndpoint,
+        OAuth2TokenEndpoint, SimpleRoute,
+    };
+    use mas_storage::{oauth2::OAuth2ClientRepository, Clock, RepositoryAccess};
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        requests::{AccessTokenResponse, GrantType, IntrospectionResponse},
+        scope::{Scope, OPENID},
+    };
+    use serde_json::json;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+    use url::Url;
+    use zeroize::Zeroizing;
+
+    use super::{validate_code_challenge, validate_param, Params};
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[test]
+    fn test_validate_param() {
+        assert!(validate_param("state", "some-state", 16).is_ok());
+        assert!(validate_param("state", "some-long-state", 8).is_err());
+        assert!(validate_param("state", "some\0state", 16).is_err());
+        assert!(validate_param("state", "some\nstate", 16).is_err());
+    }
+
+    #[test]
+    fn test_nonce_required_for_id_token() {
+        let validate = |query: &str| {
+            serde_urlencoded::from_str::<Params>(query)
+                .unwrap()
+                .validate()
+        };
+
+        assert!(validate("response_type=code&client_id=client&scope=openid").is_ok());
+        assert!(validate("response_type=code+id_token&client_id=client&scope=openid").is_err());
+        assert!(validate("response_type=id_token&client_id=client&scope=openid").is_err());
+        assert!(
+            validate("response_type=code+id_token&client_id=client&scope=openid&nonce=abc").is_ok()
+        );
+    }
+
+    #[test]
+    fn test_validate_code_challenge() {
+        // A S256 challenge and a plain one
+        assert!(validate_code_challenge("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM").is_ok());
+        assert!(validate_code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").is_ok());
+
+        // Too short, too long, or with invalid characters
+        assert!(validate_code_challenge("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw").is_err());
+        assert!(validate_code_challenge(&"E9Melhoa2OwvFrEMTJgu".repeat(7)).is_err());
+        assert!(validate_code_challenge("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw+cM=").is_err());
+
+        // Low entropy
+        assert!(validate_code_challenge(&"a".repeat(64)).is_err());
+        assert!(validate_code_challenge(&"ab".repeat(32)).is_err());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_authorization_code_flow_bot(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Provision a client
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@client.com"],
+            "client_uri": "https://client.com/",
+            "redirect_uris": ["https://client.com/callback"],
+            "response_types": ["code"],
+            "grant_types": ["authorization_code"],
+            "token_endpoint_auth_method": "client_secret_post",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+
+        // Provision a bot user with a password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "bot".to_owned())
+            .await
+            .unwrap();
+        let user = repo.user().set_bot(user, true).await.unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client.client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+            "state": "some-state",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        let login_url = response.location().to_owned();
+
+        let response = state.follow_redirects(&cookies, response).await;
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let request = Request::post(&login_url).form(json!({
+            "csrf": csrf_token,
+            "username": "bot",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Bots don't get the consent screen, and go straight back to the client
+        let response = state.follow_redirects(&cookies, response).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let callback: Url = response.location().parse().unwrap();
+        assert_eq!(callback.path(), "/callback");
+        let params: std::collections::HashMap<_, _> = callback.query_pairs().collect();
+        assert_eq!(params["state"], "some-state");
+        assert!(params.contains_key("code"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_authorization_code_flow(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Provision a client
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@client.com"],
+            "client_uri": "https://client.com/",
+            "redirect_uris": ["https://client.com/callback"],
+            "response_types": ["code"],
+            "grant_types": ["authorization_code"],
+            "token_endpoint_auth_method": "client_secret_post",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+        let client_id = client.client_id;
+        let client_secret = client.client_secret.unwrap();
+
+        // Provision a user with a password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Start the authorization flow, which should redirect to the login page
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+            "state": "some-state",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        let login_url = response.location().to_owned();
+
+        let response = state.follow_redirects(&cookies, response).await;
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        // Log in
+        let request = Request::post(&login_url).form(json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Continuing the grant should ask for consent
+        let request = Request::get(response.location()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        let consent_url = response.location().to_owned();
+
+        let request = Request::get(&consent_url).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        // Give consent, which should redirect back to the client with a code
+        let request = Request::post(&consent_url).form(json!({ "csrf": csrf_token }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let response = state.follow_redirects(&cookies, response).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let callback: Url = response.location().parse().unwrap();
+        assert_eq!(callback.path(), "/callback");
+        let params: std::collections::HashMap<_, _> = callback.query_pairs().collect();
+        assert_eq!(params["state"], "some-state");
+        let code = params["code"].to_string();
+
+        // Exchange the code for tokens
+        let request = Request::post(OAuth2TokenEndpoint::PATH).form(json!({
+            "grant_type": "authorization_code",
+            "code": code,
+            "redirect_uri": "https://client.com/callback",
+            "client_id": client_id,
+            "client_secret": client_secret,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: AccessTokenResponse = response.json();
+        assert!(response.id_token.is_some());
+
+        // And finally introspect the access token
+        let request = Request::post(OAuth2Introspection::PATH).form(json!({
+            "token": response.access_token,
+            "client_id": client_id,
+            "client_secret": client_secret,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+        assert_eq!(response.username.as_deref(), Some("john"));
+        assert_eq!(response.client_id.as_deref(), Some(client_id.as_str()));
+        assert_eq!(response.scope, Some(Scope::from_iter([OPENID])));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_public_client_requires_pkce(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a public client
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@client.com"],
+            "client_uri": "https://client.com/",
+            "redirect_uris": ["https://client.com/callback"],
+            "response_types": ["code"],
+            "grant_types": ["authorization_code"],
+            "token_endpoint_auth_method": "none",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+
+        // Starting the flow without a code challenge sends back an error
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client.client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let callback: Url = response.location().parse().unwrap();
+        assert_eq!(callback.path(), "/callback");
+        let params: std::collections::HashMap<_, _> = callback.query_pairs().collect();
+        assert_eq!(params["error"], "invalid_request");
+
+        // With a code challenge, it goes to the login page
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client.client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+            "code_challenge": "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+            "code_challenge_method": "S256",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        assert!(!response.location().starts_with("https://client.com/"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_static_client_response_types(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a static client only allowed to use the hybrid flow
+        let encrypted_client_secret = state
+            .encrypter
+            .encrypt_to_string(&mut state.rng(), b"secret")
+            .unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng()),
+                OAuthClientAuthenticationMethod::ClientSecretPost,
+                Some(encrypted_client_secret),
+                None,
+                None,
+                vec!["https://client.com/callback".parse().unwrap()],
+                vec![OAuthAuthorizationEndpointResponseType::CodeIdToken],
+                vec![GrantType::AuthorizationCode, GrantType::Implicit],
+                None,
+                false,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The `code` response type it wasn't configured with is refused
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client.client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let callback: Url = response.location().parse().unwrap();
+        assert_eq!(callback.path(), "/callback");
+        let params: std::collections::HashMap<_, _> = callback.query_pairs().collect();
+        assert_eq!(params["error"], "unauthorized_client");
+
+        // The configured one goes to the login page
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code id_token",
+            "client_id": client.client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": "openid",
+            "nonce": "abcdef",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        assert!(!response.location().starts_with("https://client.com/"));
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{extract::State, response::IntoResponse, Json, TypedHeader};
+use chrono::Duration;
+use headers::{CacheControl, Pragma};
+use hyper::StatusCode;
+use mas_axum_utils::{
+    client_authorization::{ClientAuthorization, CredentialsVerificationError},
+    http_client_factory::HttpClientFactory,
+    sentry::SentryEventID,
+};
+use mas_data_model::{ScopeNotAllowedError, UserAgent};
+use mas_keystore::Encrypter;
+use mas_storage::{
+    job::{JobRepositoryExt, SendBackchannelAuthenticationEmailJob},
+    oauth2::OAuth2BackchannelAuthenticationGrantParams,
+    BoxClock, BoxRepository, BoxRng,
+};
+use oauth2_types::{
+    errors::{ClientError, ClientErrorCode},
+    requests::{BackchannelAuthenticationRequest, BackchannelAuthenticationResponse, GrantType},
+    scope::ScopeToken,
+};
+use rand::distributions::{Alphanumeric, DistString};
+use thiserror::Error;
+
+use crate::{
+    impl_from_error_for_route,
+    rate_limit::{RateLimited, DEVICE_CODE_POLL_INTERVAL},
+    BoundActivityTracker, Limiter,
+};
+
+/// The maximum length of a binding message, in characters. It is displayed to
+/// the user, so it should stay short.
+const MAX_BINDING_MESSAGE_LENGTH: usize = 64;
+
+/// Check that a binding message is short and only contains printable
+/// characters, so that it can't be used to spoof the consent screen or the
+/// email sent to the user
+fn is_valid_binding_message(message: &str) -> bool {
+    !message.trim().is_empty()
+        && message.chars().count() <= MAX_BINDING_MESSAGE_LENGTH
+        && !message.chars().any(|c| {
+            c.is_control()
+                // Bidirectional text overrides, which can reorder the displayed text
+                || matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+        })
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("client not found")]
+    ClientNotFound,
+
+    #[error("client not allowed")]
+    ClientNotAllowed,
+
+    #[error("could not verify client credentials")]
+    ClientCredentialsVerification(#[from] CredentialsVerificationError),
+
+    #[error(transparent)]
+    ScopeNotAllowed(#[from] ScopeNotAllowedError),
+
+    #[error("missing login_hint")]
+    MissingLoginHint,
+
+    #[error("unknown user")]
+    UnknownUser,
+
+    #[error("invalid binding_message")]
+    InvalidBindingMessage,
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        // Being rate limited is not worth reporting to Sentry
+        if let Self::RateLimited(e) = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                e,
+                Json(
+                    ClientError::from(ClientErrorCode::TemporarilyUnavailable)
+                        .with_description("Too many requests, try again later".to_owned()),
+                ),
+            )
+                .into_response();
+        }
+
+        let event_id = sentry::capture_error(&self);
+
+        let response = match self {
+            Self::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ClientError::from(ClientErrorCode::ServerError)),
+            ),
+            Self::ClientNotFound | Self::ClientCredentialsVerification(_) => (
+                StatusCode::UNAUTHORIZED,
+                Json(ClientError::from(ClientErrorCode::InvalidClient)),
+            ),
+            Self::ClientNotAllowed => (
+                StatusCode::UNAUTHORIZED,
+                Json(ClientError::from(ClientErrorCode::UnauthorizedClient)),
+            ),
+            Self::ScopeNotAllowed(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidScope)
+                        .with_description(e.to_string()),
+                ),
+            ),
+            Self::MissingLoginHint => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description("The login_hint parameter is required".to_owned()),
+                ),
+            ),
+            Self::UnknownUser => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::UnknownUserId)
+                        .with_description("The login_hint does not match any user".to_owned()),
+                ),
+            ),
+            Self::InvalidBindingMessage => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidBindingMessage).with_description(
+                        format!(
+                            "The binding_message must be at most {MAX_BINDING_MESSAGE_LENGTH} \
+                             printable characters"
+                        ),
+                    ),
+                ),
+            ),
+            Self::RateLimited(_) => unreachable!("handled above"),
+        };
+
+        (SentryEventID::from(event_id), response).into_response()
+    }
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.backchannel.authorize.post",
+    fields(client.id = client_authorization.client_id()),
+    skip_all,
+    err,
+)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    activity_tracker: BoundActivityTracker,
+    State(http_client_factory): State<HttpClientFactory>,
+    State(encrypter): State<Encrypter>,
+    State(limiter): State<Limiter>,
+    client_authorization: ClientAuthorization<BackchannelAuthenticationRequest>,
+) -> Result<impl IntoResponse, RouteError> {
+    let client = client_authorization
+        .credentials
+        .fetch(&mut repo)
+        .await?
+        .ok_or(RouteError::ClientNotFound)?;
+
+    // Reuse the token endpoint auth method to verify the client
+    let method = client
+        .token_endpoint_auth_method
+        .as_ref()
+        .ok_or(RouteError::ClientNotAllowed)?;
+
+    client_authorization
+        .credentials
+        .verify(&http_client_factory, &encrypter, method, &client)
+        .await?;
+
+    if !client
+        .grant_types
+        .contains(&GrantType::ClientInitiatedBackchannelAuthentication)
+    {
+        return Err(RouteError::ClientNotAllowed);
+    }
+
+    let form = client_authorization.form.unwrap_or_default();
+
+    if form
+        .binding_message
+        .as_deref()
+        .is_some_and(|message| !is_valid_binding_message(message))
+    {
+        return Err(RouteError::InvalidBindingMessage);
+    }
+
+    // We only support identifying the user by their username
+    let login_hint = form.login_hint.ok_or(RouteError::MissingLoginHint)?;
+    let user = repo
+        .user()
+        .find_by_username(&login_hint)
+        .await?
+        .filter(mas_data_model::User::is_valid)
+        .ok_or(RouteError::UnknownUser)?;
+
+    // Each request sends an email to the user, so limit how often a client can
+    // prompt a given user
+    limiter
+        .check_backchannel_authentication(&clock, client.id, user.id)
+        .await?;
+
+    let scope = form
+        .scope
+        .unwrap_or(std::iter::empty::<ScopeToken>().collect());
+
+    // Apply the default scope and the scope restrictions of the client
+    let scope = client.restrict_scope(scope)?;
+
+    let expires_in = Duration::microseconds(20 * 60 * 1000 * 1000);
+
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+    let ip_address = activity_tracker.ip();
+
+    let auth_req_id = Alphanumeric.sample_string(&mut rng, 32);
+
+    let grant = repo
+        .oauth2_backchannel_authentication_grant()
+        .add(
+            &mut rng,
+            &clock,
+            OAuth2BackchannelAuthenticationGrantParams {
+                client: &client,
+                scope,
+                user: &user,
+                auth_req_id,
+                binding_message: form
+                    .binding_message
+                    .map(|message| message.trim().to_owned()),
+                expires_in,
+                ip_address,
+                user_agent,
+            },
+        )
+        .await?;
+
+    // Ask the user to review the request out of band
+    repo.job()
+        .schedule_job(SendBackchannelAuthenticationEmailJob::new(&grant))
+        .await?;
+
+    repo.save().await?;
+
+    let response = BackchannelAuthenticationResponse {
+        auth_req_id: grant.auth_req_id,
+        expires_in,
+        interval: Some(DEVICE_CODE_POLL_INTERVAL),
+    };
+
+    Ok((
+        StatusCode::OK,
+        TypedHeader(CacheControl::new().with_no_store()),
+        TypedHeader(Pragma::no_cache()),
+        Json(response),
+    ))
+}
@@ -0,0 +1,479 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_extra::response::Html;
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_policy::Policy;
+use mas_router::UrlBuilder;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_templates::{
+    BackchannelConsentContext, ErrorContext, PolicyViolationContext, TemplateContext, Templates,
+};
+use serde::Deserialize;
+use tracing::warn;
+use ulid::Ulid;
+
+use crate::{BoundActivityTracker, PreferredLanguage};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Consent,
+    Reject,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConsentForm {
+    action: Action,
+}
+
+fn grant_not_found() -> FancyError {
+    let context = ErrorContext::new()
+        .with_code("backchannel_authentication_not_found")
+        .with_description("Backchannel authentication grant not found".to_owned());
+    FancyError::new(context).with_status(StatusCode::NOT_FOUND)
+}
+
+fn grant_expired() -> FancyError {
+    let context = ErrorContext::new()
+        .with_code("backchannel_authentication_expired")
+        .with_description("Backchannel authentication grant is expired".to_owned());
+    FancyError::new(context).with_status(StatusCode::BAD_REQUEST)
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.backchannel.consent.get",
+    fields(grant.id = %grant_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
+    cookie_jar: CookieJar,
+    Path(grant_id): Path<Ulid>,
+) -> Result<Response, FancyError> {
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::and_continue_backchannel_authentication_grant(grant_id);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let grant = repo
+        .oauth2_backchannel_authentication_grant()
+        .lookup(grant_id)
+        .await?
+        // Only the user the request was made for can act on it
+        .filter(|grant| grant.user_id == session.user.id)
+        .ok_or_else(grant_not_found)?;
+
+    if grant.expires_at < clock.now() {
+        return Err(grant_expired());
+    }
+
+    let client = repo
+        .oauth2_client()
+        .lookup(grant.client_id)
+        .await?
+        .context("Client not found")?;
+
+    // Evaluate the policy
+    let res = policy
+        .evaluate_backchannel_authentication_grant(&grant, &client, &session.user)
+        .await?;
+    if !res.valid() {
+        warn!(
+            violation = ?res,
+            "Backchannel authentication grant for client {} denied by policy",
+            client.id
+        );
+
+        let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+        let ctx = PolicyViolationContext::for_backchannel_authentication_grant(grant, client)
+            .with_session(session)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        let content = templates.render_policy_violation(&ctx)?;
+
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
+    let ctx = BackchannelConsentContext::new(grant, client)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates
+        .render_backchannel_consent(&ctx)
+        .context("Failed to render template")?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.backchannel.consent.post",
+    fields(grant.id = %grant_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
+    cookie_jar: CookieJar,
+    Path(grant_id): Path<Ulid>,
+    Form(form): Form<ProtectedForm<ConsentForm>>,
+) -> Result<Response, FancyError> {
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::and_continue_backchannel_authentication_grant(grant_id);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let grant = repo
+        .oauth2_backchannel_authentication_grant()
+        .lookup(grant_id)
+        .await?
+        // Only the user the request was made for can act on it
+        .filter(|grant| grant.user_id == session.user.id)
+        .ok_or_else(grant_not_found)?;
+
+    if grant.expires_at < clock.now() {
+        return Err(grant_expired());
+    }
+
+    let client = repo
+        .oauth2_client()
+        .lookup(grant.client_id)
+        .await?
+        .context("Client not found")?;
+
+    // Evaluate the policy
+    let res = policy
+        .evaluate_backchannel_authentication_grant(&grant, &client, &session.user)
+        .await?;
+    if !res.valid() {
+        warn!(
+            violation = ?res,
+            "Backchannel authentication grant for client {} denied by policy",
+            client.id
+        );
+
+        let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+        let ctx = PolicyViolationContext::for_backchannel_authentication_grant(grant, client)
+            .with_session(session)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        let content = templates.render_policy_violation(&ctx)?;
+
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
+    let grant = if grant.is_pending() {
+        match form.action {
+            Action::Consent => {
+                repo.oauth2_backchannel_authentication_grant()
+                    .fulfill(&clock, grant, &session)
+                    .await?
+            }
+            Action::Reject => {
+                repo.oauth2_backchannel_authentication_grant()
+                    .reject(&clock, grant, &session)
+                    .await?
+            }
+        }
+    } else {
+        // XXX: In case we're not pending, let's just return the grant as-is
+        // since it might just be a form resubmission, and feedback is nice enough
+        warn!(
+            oauth2_backchannel_authentication_grant.id = %grant.id,
+            browser_session.id = %session.id,
+            user.id = %session.user.id,
+            "Grant is not pending",
+        );
+        grant
+    };
+
+    repo.save().await?;
+
+    let ctx = BackchannelConsentContext::new(grant, client)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates
+        .render_backchannel_consent(&ctx)
+        .context("Failed to render template")?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_axum_utils::SessionInfoExt;
+    use mas_data_model::{BackchannelAuthenticationGrant, BrowserSession, Client, User};
+    use mas_iana::oauth::OAuthClientAuthenticationMethod;
+    use mas_router::{Route, SimpleRoute};
+    use mas_storage::{
+        oauth2::OAuth2BackchannelAuthenticationGrantParams, Clock, RepositoryAccess,
+    };
+    use oauth2_types::{
+        requests::GrantType,
+        scope::{Scope, OPENID},
+    };
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    async fn add_grant(
+        state: &TestState,
+        client: &Client,
+        user: &User,
+        auth_req_id: &str,
+    ) -> BackchannelAuthenticationGrant {
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                OAuth2BackchannelAuthenticationGrantParams {
+                    client,
+                    scope: Scope::from_iter([OPENID]),
+                    user,
+                    auth_req_id: auth_req_id.to_owned(),
+                    binding_message: None,
+                    expires_in: Duration::try_minutes(5).unwrap(),
+                    ip_address: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        grant
+    }
+
+    async fn lookup_grant(state: &TestState, id: Ulid) -> BackchannelAuthenticationGrant {
+        let mut repo = state.repository().await.unwrap();
+        repo.oauth2_backchannel_authentication_grant()
+            .lookup(id)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn logged_in(state: &TestState, session: &BrowserSession) -> CookieHelper {
+        let cookies = CookieHelper::new();
+        cookies.import(state.cookie_jar().set_session(session));
+        cookies
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_consent(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng()),
+                OAuthClientAuthenticationMethod::None,
+                None,
+                None,
+                None,
+                Vec::new(),
+                Vec::new(),
+                vec![GrantType::ClientInitiatedBackchannelAuthentication],
+                None,
+                false,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let alice_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let bob_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &bob, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let grant = add_grant(&state, &client, &alice, "first").await;
+        let route = mas_router::BackchannelAuthenticationConsent::new(grant.id);
+
+        // Without a session, the user is sent to the login page
+        let request = Request::get(&*route.path_and_query()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        assert!(response.location().starts_with(mas_router::Login::route()));
+
+        // Another user can't see or act on the grant
+        let bob_cookies = logged_in(&state, &bob_session);
+        let request = bob_cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        bob_cookies.save_cookies(&response);
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        let request =
+            bob_cookies.with_cookies(Request::get(mas_router::AccountExport::PATH).empty());
+        let response = state.request(request).await;
+        bob_cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let bob_csrf_token = response.csrf_token().to_owned();
+
+        let request = bob_cookies.with_cookies(Request::post(&*route.path_and_query()).form(
+            serde_json::json!({
+                "csrf": bob_csrf_token,
+                "action": "consent",
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        assert!(lookup_grant(&state, grant.id).await.is_pending());
+
+        // The user the request was made for can reject it
+        let cookies = logged_in(&state, &alice_session);
+        let request = cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        let request = cookies.with_cookies(Request::post(&*route.path_and_query()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "reject",
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(lookup_grant(&state, grant.id).await.is_rejected());
+
+        // Submitting the form again doesn't change the outcome
+        let request = cookies.with_cookies(Request::post(&*route.path_and_query()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "consent",
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(lookup_grant(&state, grant.id).await.is_rejected());
+
+        // Consenting fulfills the grant
+        let grant = add_grant(&state, &client, &alice, "second").await;
+        let route = mas_router::BackchannelAuthenticationConsent::new(grant.id);
+        let request = cookies.with_cookies(Request::post(&*route.path_and_query()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "consent",
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(lookup_grant(&state, grant.id).await.is_fulfilled());
+
+        // Expired grants can't be acted on anymore
+        let grant = add_grant(&state, &client, &alice, "third").await;
+        let route = mas_router::BackchannelAuthenticationConsent::new(grant.id);
+        state.clock.advance(Duration::try_minutes(6).unwrap());
+
+        let request = cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request = cookies.with_cookies(Request::post(&*route.path_and_query()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "consent",
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(lookup_grant(&state, grant.id).await.is_pending());
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use axum::{
     extract::{Form, Path, State},
     response::{Html, IntoResponse, Response},
@@ -21,20 +23,26 @@ use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, ProtectedForm},
     sentry::SentryEventID,
-    SessionInfoExt,
+    FancyError, SessionInfoExt,
 };
 use mas_data_model::{AuthorizationGrantStage, Device};
 use mas_policy::Policy;
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository},
-    BoxClock, BoxRepository, BoxRng,
+    BoxClock, BoxRepository, BoxRng, Clock,
+};
+use mas_templates::{
+    ConsentContext, ErrorContext, PolicyViolationContext, TemplateContext, Templates,
 };
-use mas_templates::{ConsentContext, PolicyViolationContext, TemplateContext, Templates};
+use oauth2_types::scope::{Scope, OPENID};
+use serde::Deserialize;
 use thiserror::Error;
 use ulid::Ulid;
 
-use crate::{impl_from_error_for_route, BoundActivityTracker, PreferredLanguage};
+use crate::{
+    continuation::Continuations, impl_from_error_for_route, BoundActivityTracker, PreferredLanguage,
+};
 
 #[derive(Debug, Error)]
 pub enum RouteError {
@@ -55,6 +63,9 @@ pub enum RouteError {
 
     #[error("Failed to load client")]
     NoSuchClient,
+
+    #[error("No scope was granted")]
+    NoScopeGranted,
 }
 
 impl_from_error_for_route!(mas_templates::TemplateError);
@@ -64,6 +75,19 @@ impl_from_error_for_route!(mas_policy::EvaluationError);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
+        if let Self::Csrf(e) = self {
+            return FancyError::csrf(e).into_response();
+        }
+
+        if let Self::NoScopeGranted = self {
+            let context = ErrorContext::new()
+                .with_code("no_scope_granted")
+                .with_description("At least one permission has to be granted".to_owned());
+            return FancyError::new(context)
+                .with_status(StatusCode::BAD_REQUEST)
+                .into_response();
+        }
+
         let event_id = sentry::capture_error(&self);
         (
             SentryEventID::from(event_id),
@@ -73,6 +97,20 @@ impl IntoResponse for RouteError {
     }
 }
 
+/// The consent form, with a `scope:<token>` field for each optional scope the
+/// user kept selected
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConsentForm {
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
+}
+
+impl ConsentForm {
+    fn is_selected(&self, token: &str) -> bool {
+        self.fields.contains_key(&format!("scope:{token}"))
+    }
+}
+
 #[tracing::instrument(
     name = "handlers.oauth2.consent.get",
     fields(grant.id = %grant_id),
@@ -93,6 +131,11 @@ pub(crate) async fn get(
 ) -> Result<Response, RouteError> {
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
+    // Only continue grants which were started in this browser
+    if let Err(e) = Continuations::load(&cookie_jar).verify(grant_id, clock.now()) {
+        return Ok((cookie_jar, e).into_response());
+    }
+
     let maybe_session = session_info.load_session(&mut repo).await?;
 
     let grant = repo
@@ -123,7 +166,14 @@ pub(crate) async fn get(
             .await?;
 
         if res.valid() {
+            // Highlight what the client asks for on top of what was already granted
+            let granted_scope = repo
+                .oauth2_client()
+                .get_consent_for_user(&client, &session.user)
+                .await?;
+
             let ctx = ConsentContext::new(grant, client)
+                .with_granted_scope(&granted_scope)
                 .with_session(session)
                 .with_csrf(csrf_token.form_value())
                 .with_language(locale);
@@ -162,12 +212,17 @@ pub(crate) async fn post(
     cookie_jar: CookieJar,
     State(url_builder): State<UrlBuilder>,
     Path(grant_id): Path<Ulid>,
-    Form(form): Form<ProtectedForm<()>>,
+    Form(form): Form<ProtectedForm<ConsentForm>>,
 ) -> Result<Response, RouteError> {
-    cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar.verify_form(&clock, form)?;
 
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
+    // Only continue grants which were started in this browser
+    if let Err(e) = Continuations::load(&cookie_jar).verify(grant_id, clock.now()) {
+        return Ok((cookie_jar, e).into_response());
+    }
+
     let maybe_session = session_info.load_session(&mut repo).await?;
 
     let grant = repo
@@ -200,6 +255,29 @@ pub(crate) async fn post(
         return Err(RouteError::PolicyViolation);
     }
 
+    // Scopes required for the grant to make sense are not shown as toggles on
+    // the consent page
+    let granted_scope: Scope = grant
+        .scope
+        .iter()
+        .filter(|s| **s == OPENID || Device::from_scope_token(s).is_some() || form.is_selected(s))
+        .cloned()
+        .collect();
+
+    if granted_scope.is_empty() {
+        return Err(RouteError::NoScopeGranted);
+    }
+
+    // Narrow down the grant to what the user agreed to, so that the issued
+    // tokens only carry those scopes
+    let grant = if granted_scope == grant.scope {
+        grant
+    } else {
+        repo.oauth2_authorization_grant()
+            .restrict_scope(grant, granted_scope)
+            .await?
+    };
+
     // Do not consent for the "urn:matrix:org.matrix.msc2967.client:device:*" scope
     let scope_without_device = grant
         .scope
@@ -226,3 +304,189 @@ pub(crate) async fn post(
 
     Ok((cookie_jar, next.go_next(&url_builder)).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_router::{
+        OAuth2AuthorizationEndpoint, OAuth2RegistrationEndpoint, OAuth2TokenEndpoint, Route,
+        SimpleRoute,
+    };
+    use mas_storage::RepositoryAccess;
+    use oauth2_types::{
+        registration::ClientRegistrationResponse, requests::AccessTokenResponse, scope::Scope,
+    };
+    use serde_json::json;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+    use url::Url;
+    use zeroize::Zeroizing;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    /// Start an authorization grant for the given scope, and return the URL of
+    /// the consent page it leads to
+    async fn start_grant(
+        state: &TestState,
+        cookies: &CookieHelper,
+        client_id: &str,
+        scope: &str,
+    ) -> String {
+        let query = serde_urlencoded::to_string(json!({
+            "response_type": "code",
+            "client_id": client_id,
+            "redirect_uri": "https://client.com/callback",
+            "scope": scope,
+            "state": "some-state",
+        }))
+        .unwrap();
+        let request =
+            Request::get(format!("{}?{query}", OAuth2AuthorizationEndpoint::PATH)).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let request = cookies.with_cookies(Request::get(response.location()).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.location().to_owned()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_deselect_scopes(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Provision a client
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@client.com"],
+            "client_uri": "https://client.com/",
+            "redirect_uris": ["https://client.com/callback"],
+            "response_types": ["code"],
+            "grant_types": ["authorization_code"],
+            "token_endpoint_auth_method": "client_secret_post",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+        let client_id = client.client_id;
+        let client_secret = client.client_secret.unwrap();
+
+        // Provision a user with a password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Log in
+        let request = cookies.with_cookies(Request::get(mas_router::Login::route()).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        let request = Request::post(mas_router::Login::route()).form(json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Ask for two optional scopes, and only keep one of them
+        let consent_url = start_grant(&state, &cookies, &client_id, "openid email phone").await;
+        let grant_id: Ulid = consent_url.rsplit('/').next().unwrap().parse().unwrap();
+
+        let request = cookies.with_cookies(Request::get(&consent_url).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        let request = Request::post(&consent_url).form(json!({
+            "csrf": csrf_token,
+            "scope:email": "on",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // The deselected scope is dropped from the stored grant
+        let expected_scope: Scope = "openid email".parse().unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .lookup(grant_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(grant.scope, expected_scope);
+        repo.save().await.unwrap();
+
+        // ...and from the token response
+        let response = state.follow_redirects(&cookies, response).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let callback: Url = response.location().parse().unwrap();
+        let params: std::collections::HashMap<_, _> = callback.query_pairs().collect();
+        let code = params["code"].to_string();
+
+        let request = Request::post(OAuth2TokenEndpoint::PATH).form(json!({
+            "grant_type": "authorization_code",
+            "code": code,
+            "redirect_uri": "https://client.com/callback",
+            "client_id": client_id,
+            "client_secret": client_secret,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: AccessTokenResponse = response.json();
+        assert_eq!(response.scope, Some(expected_scope));
+
+        // Submitting the form with nothing selected is rejected
+        let consent_url = start_grant(&state, &cookies, &client_id, "email phone").await;
+        let grant_id: Ulid = consent_url.rsplit('/').next().unwrap().parse().unwrap();
+
+        let request = cookies.with_cookies(Request::get(&consent_url).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        let request = Request::post(&consent_url).form(json!({ "csrf": csrf_token }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .lookup(grant_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(grant.stage.is_pending());
+        assert_eq!(grant.scope, "email phone".parse::<Scope>().unwrap());
+        repo.save().await.unwrap();
+    }
+}
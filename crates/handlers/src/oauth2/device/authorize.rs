@@ -21,7 +21,7 @@ use mas_axum_utils::{
     http_client_factory::HttpClientFactory,
     sentry::SentryEventID,
 };
-use mas_data_model::UserAgent;
+use mas_data_model::{ScopeNotAllowedError, UserAgent};
 use mas_keystore::Encrypter;
 use mas_router::UrlBuilder;
 use mas_storage::{oauth2::OAuth2DeviceCodeGrantParams, BoxClock, BoxRepository, BoxRng};
@@ -33,7 +33,9 @@ use oauth2_types::{
 use rand::distributions::{Alphanumeric, DistString};
 use thiserror::Error;
 
-use crate::{impl_from_error_for_route, BoundActivityTracker};
+use crate::{
+    impl_from_error_for_route, rate_limit::DEVICE_CODE_POLL_INTERVAL, BoundActivityTracker,
+};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -48,6 +50,9 @@ pub(crate) enum RouteError {
 
     #[error("could not verify client credentials")]
     ClientCredentialsVerification(#[from] CredentialsVerificationError),
+
+    #[error(transparent)]
+    ScopeNotAllowed(#[from] ScopeNotAllowedError),
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
@@ -69,6 +74,13 @@ impl IntoResponse for RouteError {
                 StatusCode::UNAUTHORIZED,
                 Json(ClientError::from(ClientErrorCode::UnauthorizedClient)),
             ),
+            Self::ScopeNotAllowed(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidScope)
+                        .with_description(e.to_string()),
+                ),
+            ),
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -124,6 +136,9 @@ pub(crate) async fn post(
         // XXX: Is this really how we do empty scopes?
         .unwrap_or(std::iter::empty::<ScopeToken>().collect());
 
+    // Apply the default scope and the scope restrictions of the client
+    let scope = client.restrict_scope(scope)?;
+
     let expires_in = Duration::microseconds(20 * 60 * 1000 * 1000);
 
     let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
@@ -157,7 +172,7 @@ pub(crate) async fn post(
         verification_uri: url_builder.device_code_link(),
         verification_uri_complete: Some(url_builder.device_code_link_full(device_code.user_code)),
         expires_in,
-        interval: Some(Duration::microseconds(5 * 1000 * 1000)),
+        interval: Some(DEVICE_CODE_POLL_INTERVAL),
     };
 
     Ok((
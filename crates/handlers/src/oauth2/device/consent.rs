@@ -15,6 +15,7 @@
 use anyhow::Context;
 use axum::{
     extract::{Path, State},
+    http::StatusCode,
     response::{IntoResponse, Response},
     Form,
 };
@@ -27,7 +28,9 @@ use mas_axum_utils::{
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxClock, BoxRepository, BoxRng};
-use mas_templates::{DeviceConsentContext, PolicyViolationContext, TemplateContext, Templates};
+use mas_templates::{
+    DeviceConsentContext, ErrorContext, PolicyViolationContext, TemplateContext, Templates,
+};
 use serde::Deserialize;
 use tracing::warn;
 use ulid::Ulid;
@@ -46,6 +49,26 @@ pub(crate) struct ConsentForm {
     action: Action,
 }
 
+fn grant_not_found() -> FancyError {
+    let context = ErrorContext::new()
+        .with_code("device_code_not_found")
+        .with_description("Device code grant not found".to_owned());
+    FancyError::new(context).with_status(StatusCode::NOT_FOUND)
+}
+
+fn grant_expired() -> FancyError {
+    let context = ErrorContext::new()
+        .with_code("device_code_expired")
+        .with_description("Device code grant is expired".to_owned());
+    FancyError::new(context).with_status(StatusCode::BAD_REQUEST)
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.device.consent.get",
+    fields(grant.id = %grant_id),
+    skip_all,
+    err,
+)]
 pub(crate) async fn get(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -72,15 +95,14 @@ pub(crate) async fn get(
         .record_browser_session(&clock, &session)
         .await;
 
-    // TODO: better error handling
     let grant = repo
         .oauth2_device_code_grant()
         .lookup(grant_id)
         .await?
-        .context("Device grant not found")?;
+        .ok_or_else(grant_not_found)?;
 
     if grant.expires_at < clock.now() {
-        return Err(FancyError::from(anyhow::anyhow!("Grant is expired")));
+        return Err(grant_expired());
     }
 
     let client = repo
@@ -119,6 +141,12 @@ pub(crate) async fn get(
     Ok((cookie_jar, Html(rendered)).into_response())
 }
 
+#[tracing::instrument(
+    name = "handlers.oauth2.device.consent.post",
+    fields(grant.id = %grant_id),
+    skip_all,
+    err,
+)]
 pub(crate) async fn post(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -133,7 +161,9 @@ pub(crate) async fn post(
     Form(form): Form<ProtectedForm<ConsentForm>>,
 ) -> Result<Response, FancyError> {
     let (session_info, cookie_jar) = cookie_jar.session_info();
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
 
     let maybe_session = session_info.load_session(&mut repo).await?;
@@ -147,15 +177,14 @@ pub(crate) async fn post(
         .record_browser_session(&clock, &session)
         .await;
 
-    // TODO: better error handling
     let grant = repo
         .oauth2_device_code_grant()
         .lookup(grant_id)
         .await?
-        .context("Device grant not found")?;
+        .ok_or_else(grant_not_found)?;
 
     if grant.expires_at < clock.now() {
-        return Err(FancyError::from(anyhow::anyhow!("Grant is expired")));
+        return Err(grant_expired());
     }
 
     let client = repo
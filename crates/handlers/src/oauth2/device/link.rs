@@ -29,6 +29,8 @@ use crate::PreferredLanguage;
 
 #[derive(Serialize, Deserialize)]
 pub struct Params {
+    /// The user code, pre-filled when following the `verification_uri_complete`
+    #[serde(alias = "user_code")]
     code: String,
 }
 
@@ -71,7 +73,7 @@ pub(crate) async fn get(
         form_state = form_state.with_error_on_field(DeviceLinkFormField::Code, FieldError::Invalid);
     };
 
-    // Rendre the form
+    // Render the form
     let ctx = DeviceLinkContext::new()
         .with_form_state(form_state)
         .with_language(locale);
@@ -12,7 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use axum::{extract::State, response::IntoResponse, Json};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+use headers::IfNoneMatch;
+use hyper::StatusCode;
+use language_tags::LanguageTag;
+use mas_i18n::Translator;
 use mas_iana::oauth::{
     OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
     PkceCodeChallengeMethod,
@@ -21,13 +31,13 @@ use mas_jose::jwa::SUPPORTED_SIGNING_ALGORITHMS;
 use mas_keystore::Keystore;
 use mas_router::UrlBuilder;
 use oauth2_types::{
-    oidc::{ClaimType, ProviderMetadata, SubjectType},
+    oidc::{BackchannelTokenDeliveryMode, ClaimType, ProviderMetadata, SubjectType},
     requests::{Display, GrantType, Prompt, ResponseMode},
     scope,
 };
 use serde::Serialize;
 
-use crate::SiteConfig;
+use crate::{DocumentCache, SiteConfig};
 
 #[derive(Debug, Serialize)]
 struct DiscoveryResponse {
@@ -43,20 +53,66 @@ struct DiscoveryResponse {
 }
 
 #[tracing::instrument(name = "handlers.oauth2.discovery.get", skip_all)]
-#[allow(clippy::too_many_lines)]
 pub(crate) async fn get(
     State(key_store): State<Keystore>,
     State(url_builder): State<UrlBuilder>,
     State(site_config): State<SiteConfig>,
-) -> impl IntoResponse {
+    State(translator): State<Arc<Translator>>,
+    State(document_cache): State<DocumentCache>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Response {
+    document_cache
+        .discovery(|| discovery_document(&key_store, &url_builder, &site_config, &translator))
+        .respond(if_none_match)
+}
+
+/// Serve the metadata on the RFC 8414 path-insert variant of the well-known
+/// URL, which is only valid if the path matches the one of the issuer
+#[tracing::instrument(name = "handlers.oauth2.discovery.get_with_path", skip_all)]
+pub(crate) async fn get_with_path(
+    Path(issuer_path): Path<String>,
+    State(key_store): State<Keystore>,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(translator): State<Arc<Translator>>,
+    State(document_cache): State<DocumentCache>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Response {
+    let issuer = url_builder.oidc_issuer();
+    if issuer_path.trim_matches('/') != issuer.path().trim_matches('/') {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    get(
+        State(key_store),
+        State(url_builder),
+        State(site_config),
+        State(translator),
+        State(document_cache),
+        if_none_match,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_lines)]
+fn discovery_document(
+    key_store: &Keystore,
+    url_builder: &UrlBuilder,
+    site_config: &SiteConfig,
+    translator: &Translator,
+) -> DiscoveryResponse {
     // This is how clients can authenticate
-    let client_auth_methods_supported = Some(vec![
+    let mut client_auth_methods = vec![
         OAuthClientAuthenticationMethod::ClientSecretBasic,
         OAuthClientAuthenticationMethod::ClientSecretPost,
         OAuthClientAuthenticationMethod::ClientSecretJwt,
         OAuthClientAuthenticationMethod::PrivateKeyJwt,
-        OAuthClientAuthenticationMethod::None,
-    ]);
+        OAuthClientAuthenticationMethod::TlsClientAuth,
+    ];
+    if site_config.public_clients_allowed {
+        client_auth_methods.push(OAuthClientAuthenticationMethod::None);
+    }
+    let client_auth_methods_supported = Some(client_auth_methods);
 
     // Those are the algorithms supported by `mas-jose`
     let client_auth_signing_alg_values_supported = Some(SUPPORTED_SIGNING_ALGORITHMS.to_vec());
@@ -69,13 +125,20 @@ pub(crate) async fn get(
     let authorization_endpoint = Some(url_builder.oauth_authorization_endpoint());
     let token_endpoint = Some(url_builder.oauth_token_endpoint());
     let device_authorization_endpoint = Some(url_builder.oauth_device_authorization_endpoint());
+    let backchannel_authentication_endpoint =
+        Some(url_builder.oauth_backchannel_authentication_endpoint());
     let jwks_uri = Some(url_builder.jwks_uri());
     let introspection_endpoint = Some(url_builder.oauth_introspection_endpoint());
     let revocation_endpoint = Some(url_builder.oauth_revocation_endpoint());
     let userinfo_endpoint = Some(url_builder.oidc_userinfo_endpoint());
     let registration_endpoint = Some(url_builder.oauth_registration_endpoint());
 
-    let scopes_supported = Some(vec![scope::OPENID.to_string(), scope::EMAIL.to_string()]);
+    let scopes_supported = Some(vec![
+        scope::OPENID.to_string(),
+        scope::EMAIL.to_string(),
+        scope::PHONE.to_string(),
+        scope::OFFLINE_ACCESS.to_string(),
+    ]);
 
     let response_types_supported = Some(vec![
         OAuthAuthorizationEndpointResponseType::Code.into(),
@@ -94,6 +157,7 @@ pub(crate) async fn get(
         GrantType::RefreshToken,
         GrantType::ClientCredentials,
         GrantType::DeviceCode,
+        GrantType::ClientInitiatedBackchannelAuthentication,
     ]);
 
     let token_endpoint_auth_methods_supported = client_auth_methods_supported.clone();
@@ -121,6 +185,21 @@ pub(crate) async fn get(
 
     let display_values_supported = Some(vec![Display::Page]);
 
+    // Clients can ask for the pages to be shown in any of the locales we have
+    // translations for
+    let mut ui_locales: Vec<String> = translator
+        .available_locales()
+        .into_iter()
+        .map(ToString::to_string)
+        .collect();
+    ui_locales.sort();
+    let ui_locales_supported = Some(
+        ui_locales
+            .iter()
+            .filter_map(|locale| LanguageTag::parse(locale).ok())
+            .collect(),
+    );
+
     let claim_types_supported = Some(vec![ClaimType::Normal]);
 
     let claims_supported = Some(vec![
@@ -131,6 +210,7 @@ pub(crate) async fn get(
         "exp".to_owned(),
         "nonce".to_owned(),
         "auth_time".to_owned(),
+        "amr".to_owned(),
         "at_hash".to_owned(),
         "c_hash".to_owned(),
     ]);
@@ -174,6 +254,7 @@ pub(crate) async fn get(
         id_token_signing_alg_values_supported,
         userinfo_signing_alg_values_supported,
         display_values_supported,
+        ui_locales_supported,
         claim_types_supported,
         claims_supported,
         claims_parameter_supported,
@@ -181,32 +262,48 @@ pub(crate) async fn get(
         request_uri_parameter_supported,
         prompt_values_supported,
         device_authorization_endpoint,
+        backchannel_authentication_endpoint,
+        backchannel_token_delivery_modes_supported: Some(vec![BackchannelTokenDeliveryMode::Poll]),
+        backchannel_user_code_parameter_supported: Some(false),
+        tls_client_certificate_bound_access_tokens: Some(true),
         ..ProviderMetadata::default()
     };
 
-    Json(DiscoveryResponse {
+    // This needs to be kept in sync with what is supported in the frontend,
+    // see frontend/src/routes/_account.index.tsx
+    let mut account_management_actions_supported = vec![
+        "org.matrix.profile".to_owned(),
+        "org.matrix.sessions_list".to_owned(),
+        "org.matrix.session_view".to_owned(),
+        "org.matrix.session_end".to_owned(),
+        "org.matrix.cross_signing_reset".to_owned(),
+    ];
+    if site_config.password_change_allowed {
+        account_management_actions_supported.push("org.matrix.password_change".to_owned());
+    }
+
+    DiscoveryResponse {
         standard,
         graphql_endpoint: url_builder.graphql_endpoint(),
         account_management_uri: url_builder.account_management_uri(),
-        // This needs to be kept in sync with what is supported in the frontend,
-        // see frontend/src/routes/__root.tsx
-        account_management_actions_supported: vec![
-            "org.matrix.profile".to_owned(),
-            "org.matrix.sessions_list".to_owned(),
-            "org.matrix.session_view".to_owned(),
-            "org.matrix.session_end".to_owned(),
-            "org.matrix.cross_signing_reset".to_owned(),
-        ],
-    })
+        account_management_actions_supported,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
-    use oauth2_types::oidc::ProviderMetadata;
+    use hyper::{
+        header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+        Request, StatusCode,
+    };
+    use mas_data_model::SiteConfig;
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use oauth2_types::oidc::{AccountManagementAction, ProviderMetadata};
     use sqlx::PgPool;
 
-    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+    use crate::test_utils::{
+        init_tracing, test_site_config, RequestBuilderExt, ResponseExt, TestState,
+    };
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_valid_discovery_metadata(pool: PgPool) {
@@ -217,9 +314,92 @@ mod tests {
         let response = state.request(request).await;
         response.assert_status(StatusCode::OK);
 
+        let etag = response.headers().get(ETAG).unwrap().clone();
+        assert!(response.headers().contains_key(CACHE_CONTROL));
+
         let metadata: ProviderMetadata = response.json();
+
+        // We ship at least English translations
+        let ui_locales_supported = metadata.ui_locales_supported.as_deref().unwrap_or_default();
+        assert!(ui_locales_supported.iter().any(|tag| tag.as_str() == "en"));
+
+        // The signing algorithms advertised are the ones of the keys in the keystore
+        let id_token_signing_alg_values_supported = metadata
+            .id_token_signing_alg_values_supported
+            .as_deref()
+            .unwrap_or_default();
+        assert!(id_token_signing_alg_values_supported.contains(&JsonWebSignatureAlg::Rs256));
+        assert!(id_token_signing_alg_values_supported.contains(&JsonWebSignatureAlg::Es256));
+        assert!(!id_token_signing_alg_values_supported.contains(&JsonWebSignatureAlg::EdDsa));
+
         metadata
             .validate(state.url_builder.oidc_issuer().as_str())
             .expect("Invalid metadata");
+
+        // Revalidating the document with its ETag doesn't send it again
+        let request = Request::get("/.well-known/openid-configuration")
+            .header(IF_NONE_MATCH, etag)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_MODIFIED);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_authorization_server_metadata(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request = Request::get("/.well-known/oauth-authorization-server").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let metadata: ProviderMetadata = response.json();
+        assert_eq!(
+            metadata.issuer,
+            Some(state.url_builder.oidc_issuer().into())
+        );
+
+        // The path-insert variant only works with the path of the issuer, which is
+        // empty here
+        let request = Request::get("/.well-known/oauth-authorization-server/other").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_account_management_actions(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request = Request::get("/.well-known/openid-configuration").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let metadata: ProviderMetadata = response.json();
+        let actions = metadata.account_management_actions_supported.unwrap();
+        assert!(actions.contains(&AccountManagementAction::SessionView));
+        assert!(actions.contains(&AccountManagementAction::SessionEnd));
+        assert!(actions.contains(&AccountManagementAction::PasswordChange));
+    }
+
+    /// Changing the password isn't advertised if it isn't allowed
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_account_management_actions_without_password_change(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                password_change_allowed: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let request = Request::get("/.well-known/openid-configuration").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let metadata: ProviderMetadata = response.json();
+        let actions = metadata.account_management_actions_supported.unwrap();
+        assert!(actions.contains(&AccountManagementAction::SessionView));
+        assert!(!actions.contains(&AccountManagementAction::PasswordChange));
     }
 }
@@ -25,17 +25,21 @@ use mas_keystore::Encrypter;
 use mas_storage::{
     compat::{CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository},
     oauth2::{OAuth2AccessTokenRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository},
-    user::UserRepository,
+    user::{BrowserSessionRepository, UserRepository},
     BoxClock, BoxRepository, Clock,
 };
 use oauth2_types::{
     errors::{ClientError, ClientErrorCode},
-    requests::{IntrospectionRequest, IntrospectionResponse},
-    scope::ScopeToken,
+    requests::{Confirmation, IntrospectionRequest, IntrospectionResponse},
+    scope::{ScopeToken, MATRIX_API, MATRIX_GUEST},
 };
 use thiserror::Error;
 
-use crate::{impl_from_error_for_route, ActivityTracker};
+use crate::{
+    impl_from_error_for_route,
+    introspection_cache::{CachedIntrospection, CachedSession},
+    ActivityTracker, IntrospectionCache,
+};
 
 #[derive(Debug, Error)]
 pub enum RouteError {
@@ -161,9 +165,10 @@ const INACTIVE: IntrospectionResponse = IntrospectionResponse {
     aud: None,
     iss: None,
     jti: None,
+    cnf: None,
+    shadow_banned: None,
 };
 
-const API_SCOPE: ScopeToken = ScopeToken::from_static("urn:matrix:org.matrix.msc2967.client:api:*");
 const SYNAPSE_ADMIN_SCOPE: ScopeToken = ScopeToken::from_static("urn:synapse:admin:*");
 
 #[tracing::instrument(
@@ -179,6 +184,7 @@ pub(crate) async fn post(
     mut repo: BoxRepository,
     activity_tracker: ActivityTracker,
     State(encrypter): State<Encrypter>,
+    State(introspection_cache): State<IntrospectionCache>,
     client_authorization: ClientAuthorization<IntrospectionRequest>,
 ) -> Result<impl IntoResponse, RouteError> {
     let client = client_authorization
@@ -214,7 +220,14 @@ pub(crate) async fn post(
     // XXX: we should get the IP from the client introspecting the token
     let ip = None;
 
-    let reply = match token_type {
+    if let Some(cached) = introspection_cache.get(token, clock.now()).await {
+        activity_tracker
+            .record_cached_session(&clock, cached.session, ip)
+            .await;
+        return Ok(Json(cached.response));
+    }
+
+    let (session, user_id, user_session_id, reply) = match token_type {
         TokenType::AccessToken => {
             let access_token = repo
                 .oauth2_access_token()
@@ -238,7 +251,7 @@ pub(crate) async fn post(
 
             // The session might not have a user on it (for Client Credentials grants for
             // example), so we're optionally fetching the user
-            let (sub, username) = if let Some(user_id) = session.user_id {
+            let (sub, username, shadow_banned) = if let Some(user_id) = session.user_id {
                 let user = repo
                     .user()
                     .lookup(user_id)
@@ -249,29 +262,44 @@ pub(crate) async fn post(
                     return Err(RouteError::InvalidUser);
                 }
 
-                (Some(user.sub), Some(user.username))
+                let shadow_banned = user.is_shadow_banned().then_some(true);
+                (Some(user.sub), Some(user.username), shadow_banned)
             } else {
-                (None, None)
+                (None, None, None)
             };
 
             activity_tracker
                 .record_oauth2_session(&clock, &session, ip)
                 .await;
 
-            IntrospectionResponse {
-                active: true,
-                scope: Some(session.scope),
-                client_id: Some(session.client_id.to_string()),
-                username,
-                token_type: Some(OAuthTokenTypeHint::AccessToken),
-                exp: access_token.expires_at,
-                iat: Some(access_token.created_at),
-                nbf: Some(access_token.created_at),
-                sub,
-                aud: None,
-                iss: None,
-                jti: Some(access_token.jti()),
-            }
+            (
+                CachedSession::OAuth2(session.id),
+                session.user_id,
+                session.user_session_id,
+                IntrospectionResponse {
+                    active: true,
+                    scope: Some(session.scope),
+                    client_id: Some(session.client_id.to_string()),
+                    username,
+                    token_type: Some(OAuthTokenTypeHint::AccessToken),
+                    exp: access_token.expires_at,
+                    iat: Some(access_token.created_at),
+                    nbf: Some(access_token.created_at),
+                    sub,
+                    aud: None,
+                    iss: None,
+                    jti: Some(access_token.jti()),
+                    // Let the resource server check that the token is presented
+                    // with the certificate it is bound to
+                    cnf: access_token
+                        .certificate_thumbprint
+                        .clone()
+                        .map(|thumbprint| Confirmation {
+                            x5t_s256: Some(thumbprint),
+                        }),
+                    shadow_banned,
+                },
+            )
         }
 
         TokenType::RefreshToken => {
@@ -297,7 +325,7 @@ pub(crate) async fn post(
 
             // The session might not have a user on it (for Client Credentials grants for
             // example), so we're optionally fetching the user
-            let (sub, username) = if let Some(user_id) = session.user_id {
+            let (sub, username, shadow_banned) = if let Some(user_id) = session.user_id {
                 let user = repo
                     .user()
                     .lookup(user_id)
@@ -308,29 +336,37 @@ pub(crate) async fn post(
                     return Err(RouteError::InvalidUser);
                 }
 
-                (Some(user.sub), Some(user.username))
+                let shadow_banned = user.is_shadow_banned().then_some(true);
+                (Some(user.sub), Some(user.username), shadow_banned)
             } else {
-                (None, None)
+                (None, None, None)
             };
 
             activity_tracker
                 .record_oauth2_session(&clock, &session, ip)
                 .await;
 
-            IntrospectionResponse {
-                active: true,
-                scope: Some(session.scope),
-                client_id: Some(session.client_id.to_string()),
-                username,
-                token_type: Some(OAuthTokenTypeHint::RefreshToken),
-                exp: None,
-                iat: Some(refresh_token.created_at),
-                nbf: Some(refresh_token.created_at),
-                sub,
-                aud: None,
-                iss: None,
-                jti: Some(refresh_token.jti()),
-            }
+            (
+                CachedSession::OAuth2(session.id),
+                session.user_id,
+                session.user_session_id,
+                IntrospectionResponse {
+                    active: true,
+                    scope: Some(session.scope),
+                    client_id: Some(session.client_id.to_string()),
+                    username,
+                    token_type: Some(OAuthTokenTypeHint::RefreshToken),
+                    exp: None,
+                    iat: Some(refresh_token.created_at),
+                    nbf: Some(refresh_token.created_at),
+                    sub,
+                    aud: None,
+                    iss: None,
+                    jti: Some(refresh_token.jti()),
+                    cnf: None,
+                    shadow_banned,
+                },
+            )
         }
 
         TokenType::CompatAccessToken => {
@@ -366,8 +402,14 @@ pub(crate) async fn post(
 
             // Grant the synapse admin scope if the session has the admin flag set.
             let synapse_admin = session.is_synapse_admin.then_some(SYNAPSE_ADMIN_SCOPE);
+            // Guest accounts only get guest access to the Client-Server API
+            let api_scope = if user.is_guest {
+                MATRIX_GUEST
+            } else {
+                MATRIX_API
+            };
             let device_scope = session.device.to_scope_token();
-            let scope = [API_SCOPE, device_scope]
+            let scope = [api_scope, device_scope]
                 .into_iter()
                 .chain(synapse_admin)
                 .collect();
@@ -376,20 +418,27 @@ pub(crate) async fn post(
                 .record_compat_session(&clock, &session, ip)
                 .await;
 
-            IntrospectionResponse {
-                active: true,
-                scope: Some(scope),
-                client_id: Some("legacy".into()),
-                username: Some(user.username),
-                token_type: Some(OAuthTokenTypeHint::AccessToken),
-                exp: access_token.expires_at,
-                iat: Some(access_token.created_at),
-                nbf: Some(access_token.created_at),
-                sub: Some(user.sub),
-                aud: None,
-                iss: None,
-                jti: None,
-            }
+            (
+                CachedSession::Compat(session.id),
+                Some(session.user_id),
+                session.user_session_id,
+                IntrospectionResponse {
+                    active: true,
+                    scope: Some(scope),
+                    client_id: Some("legacy".into()),
+                    username: Some(user.username),
+                    token_type: Some(OAuthTokenTypeHint::AccessToken),
+                    exp: access_token.expires_at,
+                    iat: Some(access_token.created_at),
+                    nbf: Some(access_token.created_at),
+                    sub: Some(user.sub),
+                    aud: None,
+                    iss: None,
+                    jti: None,
+                    cnf: None,
+                    shadow_banned: user.is_shadow_banned().then_some(true),
+                },
+            )
         }
 
         TokenType::CompatRefreshToken => {
@@ -425,8 +474,14 @@ pub(crate) async fn post(
 
             // Grant the synapse admin scope if the session has the admin flag set.
             let synapse_admin = session.is_synapse_admin.then_some(SYNAPSE_ADMIN_SCOPE);
+            // Guest accounts only get guest access to the Client-Server API
+            let api_scope = if user.is_guest {
+                MATRIX_GUEST
+            } else {
+                MATRIX_API
+            };
             let device_scope = session.device.to_scope_token();
-            let scope = [API_SCOPE, device_scope]
+            let scope = [api_scope, device_scope]
                 .into_iter()
                 .chain(synapse_admin)
                 .collect();
@@ -435,23 +490,59 @@ pub(crate) async fn post(
                 .record_compat_session(&clock, &session, ip)
                 .await;
 
-            IntrospectionResponse {
-                active: true,
-                scope: Some(scope),
-                client_id: Some("legacy".into()),
-                username: Some(user.username),
-                token_type: Some(OAuthTokenTypeHint::RefreshToken),
-                exp: None,
-                iat: Some(refresh_token.created_at),
-                nbf: Some(refresh_token.created_at),
-                sub: Some(user.sub),
-                aud: None,
-                iss: None,
-                jti: None,
-            }
+            (
+                CachedSession::Compat(session.id),
+                Some(session.user_id),
+                session.user_session_id,
+                IntrospectionResponse {
+                    active: true,
+                    scope: Some(scope),
+                    client_id: Some("legacy".into()),
+                    username: Some(user.username),
+                    token_type: Some(OAuthTokenTypeHint::RefreshToken),
+                    exp: None,
+                    iat: Some(refresh_token.created_at),
+                    nbf: Some(refresh_token.created_at),
+                    sub: Some(user.sub),
+                    aud: None,
+                    iss: None,
+                    jti: None,
+                    cnf: None,
+                    shadow_banned: user.is_shadow_banned().then_some(true),
+                },
+            )
         }
     };
 
+    // Only access tokens are cached, as refresh tokens are rarely introspected
+    // and get consumed without their session ending
+    let mut cacheable = matches!(
+        token_type,
+        TokenType::AccessToken | TokenType::CompatAccessToken
+    );
+
+    // Sessions started from an expiring browser session, like impersonation
+    // sessions, are finished by the worker which can't invalidate the cache, so
+    // their results are never cached
+    if cacheable && introspection_cache.is_enabled() {
+        if let Some(user_session_id) = user_session_id {
+            let browser_session = repo.browser_session().lookup(user_session_id).await?;
+            if browser_session.map_or(true, |session| session.expires_at.is_some()) {
+                cacheable = false;
+            }
+        }
+    }
+
+    if cacheable {
+        let entry = CachedIntrospection {
+            session,
+            user_id,
+            response: reply,
+        };
+        introspection_cache.insert(token, &entry, clock.now()).await;
+        return Ok(Json(entry.response));
+    }
+
     Ok(Json(reply))
 }
 
@@ -459,7 +550,7 @@ pub(crate) async fn post(
 mod tests {
     use chrono::Duration;
     use hyper::{Request, StatusCode};
-    use mas_data_model::{AccessToken, RefreshToken};
+    use mas_data_model::{AccessToken, Device, RefreshToken, TokenType};
     use mas_iana::oauth::OAuthTokenTypeHint;
     use mas_router::{OAuth2Introspection, OAuth2RegistrationEndpoint, SimpleRoute};
     use mas_storage::Clock;
@@ -475,6 +566,7 @@ mod tests {
     use crate::{
         oauth2::generate_token_pair,
         test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState},
+        IntrospectionCache,
     };
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -756,6 +848,7 @@ mod tests {
         assert_eq!(response.client_id, Some("legacy".to_owned()));
         assert_eq!(response.token_type, Some(OAuthTokenTypeHint::AccessToken));
         assert_eq!(response.scope, Some(expected_scope.clone()));
+        assert_eq!(response.shadow_banned, None);
 
         // Do the same request, but with a token_type_hint
         let request = Request::post(OAuth2Introspection::PATH)
@@ -825,5 +918,202 @@ mod tests {
         response.assert_status(StatusCode::OK);
         let response: IntrospectionResponse = response.json();
         assert!(response.active);
+
+        // Shadow-ban the user: the token should still be active, but flagged
+        let mut repo = state.repository().await.unwrap();
+        repo.user().shadow_ban(&state.clock, user).await.unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(OAuth2Introspection::PATH)
+            .basic_auth(&introspecting_client_id, &introspecting_client_secret)
+            .form(json!({ "token": refresh_token }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+        assert_eq!(response.shadow_banned, Some(true));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_introspection_cache(pool: PgPool) {
+        init_tracing();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.introspection_cache = IntrospectionCache::new(Duration::try_minutes(1).unwrap());
+
+        // Provision a client which will be used to do introspection requests
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@introspecting.com"],
+            "client_uri": "https://introspecting.com/",
+            "grant_types": [],
+            "token_endpoint_auth_method": "client_secret_basic",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+        let introspecting_client_id = client.client_id;
+        let introspecting_client_secret = client.client_secret.unwrap();
+
+        // Provision a user with a compat session
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let device = Device::generate(&mut state.rng());
+        let session = repo
+            .compat_session()
+            .add(&mut state.rng(), &state.clock, &user, device, None, false)
+            .await
+            .unwrap();
+
+        let access_token = TokenType::CompatAccessToken.generate(&mut state.rng());
+        repo.compat_access_token()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &session,
+                access_token.clone(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let introspect = || {
+            Request::post(OAuth2Introspection::PATH)
+                .basic_auth(&introspecting_client_id, &introspecting_client_secret)
+                .form(json!({ "token": access_token }))
+        };
+
+        let response = state.request(introspect()).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+
+        // Lock the user behind the back of the cache: the cached result is still
+        // returned
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lock(&state.clock, user).await.unwrap();
+        repo.save().await.unwrap();
+
+        let response = state.request(introspect()).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+
+        // Once invalidated, the token is introspected again
+        state.introspection_cache.invalidate_user(user.id).await;
+
+        let response = state.request(introspect()).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(!response.active);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_introspection_cache_expiring_session(pool: PgPool) {
+        init_tracing();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.introspection_cache = IntrospectionCache::new(Duration::try_minutes(1).unwrap());
+
+        // Provision a client which will be used to do introspection requests
+        let request = Request::post(OAuth2RegistrationEndpoint::PATH).json(json!({
+            "contacts": ["hello@introspecting.com"],
+            "client_uri": "https://introspecting.com/",
+            "grant_types": [],
+            "token_endpoint_auth_method": "client_secret_basic",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let client: ClientRegistrationResponse = response.json();
+        let introspecting_client_id = client.client_id;
+        let introspecting_client_secret = client.client_secret.unwrap();
+
+        // Provision a compat session started from an impersonation session
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let admin = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "admin".to_owned())
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add_impersonation(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                &admin,
+                None,
+                Duration::try_minutes(10).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let device = Device::generate(&mut state.rng());
+        let session = repo
+            .compat_session()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                device,
+                Some(&browser_session),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let access_token = TokenType::CompatAccessToken.generate(&mut state.rng());
+        repo.compat_access_token()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &session,
+                access_token.clone(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let introspect = || {
+            Request::post(OAuth2Introspection::PATH)
+                .basic_auth(&introspecting_client_id, &introspecting_client_secret)
+                .form(json!({ "token": access_token }))
+        };
+
+        let response = state.request(introspect()).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(response.active);
+
+        // Let the worker finish the expired session: the result was not cached, so
+        // the token is inactive right away
+        state.clock.advance(Duration::try_minutes(11).unwrap());
+        let mut repo = state.repository().await.unwrap();
+        let finished = repo
+            .browser_session()
+            .finish_expired(&state.clock)
+            .await
+            .unwrap();
+        assert_eq!(finished, 1);
+        repo.save().await.unwrap();
+
+        let response = state.request(introspect()).await;
+        response.assert_status(StatusCode::OK);
+        let response: IntrospectionResponse = response.json();
+        assert!(!response.active);
     }
 }
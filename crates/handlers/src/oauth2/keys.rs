@@ -12,11 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{extract::State, response::Response, TypedHeader};
+use headers::IfNoneMatch;
 use mas_keystore::Keystore;
 
+use crate::DocumentCache;
+
 #[tracing::instrument(name = "handlers.oauth2.keys.get", skip_all)]
-pub(crate) async fn get(State(key_store): State<Keystore>) -> impl IntoResponse {
-    let jwks = key_store.public_jwks();
-    Json(jwks)
+pub(crate) async fn get(
+    State(key_store): State<Keystore>,
+    State(document_cache): State<DocumentCache>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Response {
+    document_cache
+        .jwks(|| key_store.public_jwks())
+        .respond(if_none_match)
 }
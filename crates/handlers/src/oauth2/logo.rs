@@ -0,0 +1,353 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proxy for the logos of the OAuth 2.0 clients
+//!
+//! Client logos are shown on the consent screens. Instead of having the
+//! browser of the user load them directly from wherever the client says they
+//! are, which would leak the IP address of the user to the client before they
+//! consented to anything, they are fetched and cached by the server.
+//!
+//! Because the URL of the logo is provided by the client, which can be
+//! dynamically registered by anyone, fetching it is done with a client which
+//! refuses to connect to non-public addresses and doesn't follow redirects.
+
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{
+    body::HttpBody,
+    header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+    HeaderMap, Request, StatusCode,
+};
+use mas_axum_utils::{http_client_factory::HttpClientFactory, sentry::SentryEventID};
+use mas_http::HttpServiceExt;
+use mas_storage::{BoxClock, BoxRepository};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tower::{BoxError, Service, ServiceExt};
+use ulid::Ulid;
+use url::{Host, Url};
+
+use crate::impl_from_error_for_route;
+
+/// How long successfully fetched logos are cached
+static LOGO_MAX_AGE: Duration = Duration::microseconds(60 * 60 * 1000 * 1000);
+
+/// How long failures to fetch logos are cached, so that the endpoint can't be
+/// used to make the server hammer another host
+static FAILURE_MAX_AGE: Duration = Duration::microseconds(5 * 60 * 1000 * 1000);
+
+/// Maximum number of logos kept in the cache
+const MAX_CACHE_ENTRIES: usize = 1000;
+
+/// Maximum size of a logo, in bytes
+const MAX_LOGO_SIZE: usize = 1024 * 1024;
+
+/// Image types which are accepted as logos. SVG images are not accepted, as
+/// they can embed scripts.
+const ALLOWED_CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+#[derive(Debug, Error)]
+enum LogoError {
+    #[error("the logo URL doesn't use the https scheme")]
+    InsecureUrl,
+
+    #[error("the logo URL has no host")]
+    MissingHost,
+
+    #[error("the logo URL points to a non-public address: {0}")]
+    NonPublicAddress(IpAddr),
+
+    #[error("failed to fetch the logo")]
+    Request(#[source] BoxError),
+
+    #[error("the logo server replied with status {0}")]
+    Status(StatusCode),
+
+    #[error("the logo is not a PNG, JPEG, GIF or WebP image")]
+    InvalidContentType,
+
+    #[error("the logo is larger than {MAX_LOGO_SIZE} bytes")]
+    TooLarge,
+}
+
+#[derive(Debug, Clone)]
+struct Logo {
+    content_type: String,
+    data: Bytes,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    logo: Option<Logo>,
+    fetched_at: DateTime<Utc>,
+}
+
+impl CacheEntry {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        let max_age = if self.logo.is_some() {
+            LOGO_MAX_AGE
+        } else {
+            FAILURE_MAX_AGE
+        };
+
+        now - self.fetched_at > max_age
+    }
+}
+
+async fn fetch(http_client_factory: &HttpClientFactory, url: &Url) -> Result<Logo, LogoError> {
+    if url.scheme() != "https" {
+        return Err(LogoError::InsecureUrl);
+    }
+
+    // Host names are checked when resolving them, but IP addresses have to be
+    // checked here
+    let ip = match url.host() {
+        None => return Err(LogoError::MissingHost),
+        Some(Host::Domain(_)) => None,
+        Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+    };
+
+    if let Some(ip) = ip.filter(|ip| !mas_http::is_public_ip(*ip)) {
+        return Err(LogoError::NonPublicAddress(ip));
+    }
+
+    let request = Request::get(url.as_str())
+        .header(ACCEPT, ALLOWED_CONTENT_TYPES.join(", "))
+        .body(Bytes::new())
+        .map_err(|e| LogoError::Request(e.into()))?;
+
+    let client = http_client_factory
+        .public_client("client_logo")
+        .request_bytes_to_body()
+        .map_err(|e| LogoError::Request(e.into()));
+
+    let response = client.ready_oneshot().await?.call(request).await?;
+
+    if !response.status().is_success() {
+        return Err(LogoError::Status(response.status()));
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map(|mime| mime.essence_str().to_owned())
+        .filter(|content_type| ALLOWED_CONTENT_TYPES.contains(&content_type.as_str()))
+        .ok_or(LogoError::InvalidContentType)?;
+
+    // Read the body, without buffering more than the maximum size
+    let mut body = response.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| LogoError::Request(e.into()))?;
+        if data.len() + chunk.len() > MAX_LOGO_SIZE {
+            return Err(LogoError::TooLarge);
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(Logo {
+        content_type,
+        data: data.into(),
+    })
+}
+
+/// A cache of the client logos, keyed by their URL
+///
+/// Failures are cached for a shorter time than successes. Expired entries
+/// are only evicted when the cache is full.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Default)]
+pub struct ClientLogoCache {
+    cache: Arc<RwLock<HashMap<Url, CacheEntry>>>,
+}
+
+impl ClientLogoCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the logo at the given URL, fetching it if it is not in the cache
+    /// or expired
+    async fn get(
+        &self,
+        http_client_factory: &HttpClientFactory,
+        now: DateTime<Utc>,
+        url: &Url,
+    ) -> Option<Logo> {
+        if let Some(entry) = self.cache.read().await.get(url) {
+            if !entry.expired(now) {
+                return entry.logo.clone();
+            }
+        }
+
+        let logo = match fetch(http_client_factory, url).await {
+            Ok(logo) => Some(logo),
+            Err(e) => {
+                tracing::warn!(%url, error = &e as &dyn std::error::Error, "Failed to fetch client logo");
+                None
+            }
+        };
+
+        let mut cache = self.cache.write().await;
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            cache.retain(|_, entry| !entry.expired(now));
+        }
+
+        if cache.len() < MAX_CACHE_ENTRIES {
+            cache.insert(
+                url.clone(),
+                CacheEntry {
+                    logo: logo.clone(),
+                    fetched_at: now,
+                },
+            );
+        }
+
+        logo
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Client {0} not found")]
+    ClientNotFound(Ulid),
+
+    #[error("Client {0} has no logo, or it could not be fetched")]
+    LogoNotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        let event_id = sentry::capture_error(&self);
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ClientNotFound(_) | Self::LogoNotFound(_) => StatusCode::NOT_FOUND,
+        };
+
+        (status, SentryEventID::from(event_id)).into_response()
+    }
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.logo.get",
+    fields(client.id = %client_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn get(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(cache): State<ClientLogoCache>,
+    State(http_client_factory): State<HttpClientFactory>,
+    Path(client_id): Path<Ulid>,
+) -> Result<Response, RouteError> {
+    let client = repo
+        .oauth2_client()
+        .lookup(client_id)
+        .await?
+        .ok_or(RouteError::ClientNotFound(client_id))?;
+
+    let logo_uri = client.logo_uri.ok_or(RouteError::LogoNotFound(client_id))?;
+
+    let logo = cache
+        .get(&http_client_factory, clock.now(), &logo_uri)
+        .await
+        .ok_or(RouteError::LogoNotFound(client_id))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        logo.content_type
+            .parse()
+            .map_err(|e| RouteError::Internal(Box::new(e)))?,
+    );
+    headers.insert(CACHE_CONTROL, "public, max-age=3600".parse().unwrap());
+    headers.insert(X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+
+    Ok((headers, logo.data).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_router::{Route, SimpleRoute};
+    use oauth2_types::registration::ClientRegistrationResponse;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+
+    #[tokio::test]
+    async fn test_fetch_rejects_unsafe_urls() {
+        let http_client_factory = HttpClientFactory::new();
+
+        let url = Url::parse("http://example.com/logo.png").unwrap();
+        let result = fetch(&http_client_factory, &url).await;
+        assert!(matches!(result, Err(LogoError::InsecureUrl)));
+
+        let url = Url::parse("https://127.0.0.1/logo.png").unwrap();
+        let result = fetch(&http_client_factory, &url).await;
+        assert!(matches!(result, Err(LogoError::NonPublicAddress(_))));
+
+        let url = Url::parse("https://[fd00::1]/logo.png").unwrap();
+        let result = fetch(&http_client_factory, &url).await;
+        assert!(matches!(result, Err(LogoError::NonPublicAddress(_))));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_client_without_logo(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Unknown clients don't have a logo
+        let request =
+            Request::get(mas_router::ClientLogo::new(Ulid::nil()).path_and_query()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        // Provision a client without a logo
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "contacts": ["contact@example.com"],
+                "token_endpoint_auth_method": "none",
+                "redirect_uris": ["https://example.com/callback"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let response: ClientRegistrationResponse = response.json();
+        let client_id: Ulid = response.client_id.parse().unwrap();
+
+        let request = Request::get(mas_router::ClientLogo::new(client_id).path_and_query()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -14,10 +14,11 @@
 
 use std::collections::HashMap;
 
+use base64ct::{Base64UrlUnpadded, Encoding};
 use chrono::Duration;
 use mas_data_model::{
-    AccessToken, Authentication, AuthorizationGrant, BrowserSession, Client, RefreshToken, Session,
-    TokenType,
+    AccessToken, Authentication, AuthenticationMethod, AuthorizationGrant, BrowserSession, Client,
+    RefreshToken, Session, TokenType,
 };
 use mas_iana::jose::JsonWebSignatureAlg;
 use mas_jose::{
@@ -28,14 +29,17 @@ use mas_jose::{
 use mas_keystore::Keystore;
 use mas_router::UrlBuilder;
 use mas_storage::{Clock, RepositoryAccess};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 pub mod authorization;
+pub mod backchannel;
 pub mod consent;
 pub mod device;
 pub mod discovery;
 pub mod introspection;
 pub mod keys;
+pub mod logo;
 pub mod registration;
 pub mod revoke;
 pub mod token;
@@ -80,6 +84,25 @@ pub(crate) fn generate_id_token(
         claims::AUTH_TIME.insert(&mut claims, last_authentication.created_at)?;
     }
 
+    let mut amr = Vec::new();
+
+    // Let the client know that the user proved possession of a certificate
+    if matches!(
+        last_authentication.map(|authentication| &authentication.authentication_method),
+        Some(AuthenticationMethod::ClientCertificate { .. })
+    ) {
+        amr.push("x509".to_owned());
+    }
+
+    // Let the client know that this session is an admin impersonating the user
+    if browser_session.is_impersonation() {
+        amr.push("impersonation".to_owned());
+    }
+
+    if !amr.is_empty() {
+        claims::AMR.insert(&mut claims, amr)?;
+    }
+
     let alg = client
         .id_token_signed_response_alg
         .clone()
@@ -104,6 +127,12 @@ pub(crate) fn generate_id_token(
     Ok(id_token.into_string())
 }
 
+/// Compute the base64url-encoded SHA-256 thumbprint of a DER-encoded
+/// certificate, used in the `x5t#S256` confirmation method of RFC 8705
+pub(crate) fn certificate_thumbprint(certificate: &[u8]) -> String {
+    Base64UrlUnpadded::encode_string(&Sha256::digest(certificate))
+}
+
 pub(crate) async fn generate_token_pair<R: RepositoryAccess>(
     rng: &mut (impl rand::RngCore + Send),
     clock: &impl Clock,
@@ -15,8 +15,9 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use hyper::StatusCode;
 use mas_axum_utils::sentry::SentryEventID;
-use mas_iana::oauth::OAuthClientAuthenticationMethod;
-use mas_keystore::Encrypter;
+use mas_data_model::SiteConfig;
+use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
+use mas_keystore::{Encrypter, Keystore};
 use mas_policy::{Policy, Violation};
 use mas_storage::{oauth2::OAuth2ClientRepository, BoxClock, BoxRepository, BoxRng};
 use oauth2_types::{
@@ -49,6 +50,15 @@ pub(crate) enum RouteError {
 
     #[error("denied by the policy: {0:?}")]
     PolicyDenied(Vec<Violation>),
+
+    #[error("public clients are not allowed")]
+    PublicClientNotAllowed,
+
+    #[error("{field} {alg} is not supported")]
+    UnsupportedSigningAlgorithm {
+        field: &'static str,
+        alg: JsonWebSignatureAlg,
+    },
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
@@ -128,6 +138,27 @@ impl IntoResponse for RouteError {
             )
                 .into_response(),
 
+            Self::PublicClientNotAllowed => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClientMetadata).with_description(
+                        "token_endpoint_auth_method \"none\" is not allowed".to_owned(),
+                    ),
+                ),
+            )
+                .into_response(),
+
+            // This happens if the client asks for its ID tokens or userinfo responses to be
+            // signed with an algorithm we don't have a key for
+            Self::UnsupportedSigningAlgorithm { field, alg } => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClientMetadata)
+                        .with_description(format!("{field} {alg} is not supported")),
+                ),
+            )
+                .into_response(),
+
             // For policy violations, we return an `invalid_client_metadata` error with the details
             // of the violations in most cases. If a violation includes `redirect_uri` in the
             // message, we return an `invalid_redirect_uri` error instead.
@@ -194,6 +225,8 @@ pub(crate) async fn post(
     mut repo: BoxRepository,
     mut policy: Policy,
     State(encrypter): State<Encrypter>,
+    State(key_store): State<Keystore>,
+    State(site_config): State<SiteConfig>,
     body: Result<Json<ClientMetadata>, axum::extract::rejection::JsonRejection>,
 ) -> Result<impl IntoResponse, RouteError> {
     // Propagate any JSON extraction error
@@ -206,6 +239,12 @@ pub(crate) async fn post(
 
     // Some extra validation that is hard to do in OPA and not done by the
     // `validate` method either
+    if metadata.token_endpoint_auth_method == Some(OAuthClientAuthenticationMethod::None)
+        && !site_config.public_clients_allowed
+    {
+        return Err(RouteError::PublicClientNotAllowed);
+    }
+
     if let Some(client_uri) = &metadata.client_uri {
         if localised_url_has_public_suffix(client_uri) {
             return Err(RouteError::UrlIsPublicSuffix("client_uri"));
@@ -236,6 +275,29 @@ pub(crate) async fn post(
         }
     }
 
+    // The ID tokens and userinfo responses can only be signed with the algorithms we
+    // have a key for
+    let signing_algorithms = key_store.available_signing_algorithms();
+    for (field, alg) in [
+        (
+            "id_token_signed_response_alg",
+            &metadata.id_token_signed_response_alg,
+        ),
+        (
+            "userinfo_signed_response_alg",
+            &metadata.userinfo_signed_response_alg,
+        ),
+    ] {
+        if let Some(alg) = alg {
+            if !signing_algorithms.contains(alg) {
+                return Err(RouteError::UnsupportedSigningAlgorithm {
+                    field,
+                    alg: alg.clone(),
+                });
+            }
+        }
+    }
+
     for redirect_uri in metadata.redirect_uris() {
         if host_is_public_suffix(redirect_uri) {
             return Err(RouteError::UrlIsPublicSuffix("redirect_uri"));
@@ -255,7 +317,8 @@ pub(crate) async fn post(
         ) => {
             // Let's generate a random client secret
             let client_secret = Alphanumeric.sample_string(&mut rng, 20);
-            let encrypted_client_secret = encrypter.encrypt_to_string(client_secret.as_bytes())?;
+            let encrypted_client_secret =
+                encrypter.encrypt_to_string(&mut rng, client_secret.as_bytes())?;
             (Some(client_secret), Some(encrypted_client_secret))
         }
         _ => (None, None),
@@ -307,6 +370,7 @@ pub(crate) async fn post(
 #[cfg(test)]
 mod tests {
     use hyper::{Request, StatusCode};
+    use mas_data_model::SiteConfig;
     use mas_router::SimpleRoute;
     use oauth2_types::{
         errors::{ClientError, ClientErrorCode},
@@ -317,7 +381,7 @@ mod tests {
 
     use crate::{
         oauth2::registration::host_is_public_suffix,
-        test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState},
+        test_utils::{init_tracing, test_site_config, RequestBuilderExt, ResponseExt, TestState},
     };
 
     #[test]
@@ -476,4 +540,76 @@ mod tests {
         let response: ClientRegistrationResponse = response.json();
         assert!(response.client_secret.is_some());
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_registration_signing_algorithms(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // The test keystore has an EC P-256 key, so ES256 is supported
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "contacts": ["hello@example.com"],
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+                "id_token_signed_response_alg": "ES256",
+                "userinfo_signed_response_alg": "ES256",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        // There is no Ed25519 key in the keystore
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "contacts": ["hello@example.com"],
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+                "id_token_signed_response_alg": "EdDSA",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let response: ClientError = response.json();
+        assert_eq!(response.error, ClientErrorCode::InvalidClientMetadata);
+        assert_eq!(
+            response.error_description.unwrap(),
+            "id_token_signed_response_alg EdDSA is not supported"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_registration_public_clients_forbidden(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                public_clients_allowed: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "contacts": ["hello@example.com"],
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let response: ClientError = response.json();
+        assert_eq!(response.error, ClientErrorCode::InvalidClientMetadata);
+    }
 }
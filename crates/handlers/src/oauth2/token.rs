@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::OnceLock;
+
 use axum::{extract::State, response::IntoResponse, Json, TypedHeader};
 use chrono::Duration;
 use headers::{CacheControl, HeaderMap, HeaderMapExt, Pragma};
@@ -22,7 +24,8 @@ use mas_axum_utils::{
     sentry::SentryEventID,
 };
 use mas_data_model::{
-    AuthorizationGrantStage, Client, Device, DeviceCodeGrantState, SiteConfig, TokenType, UserAgent,
+    AuthorizationGrantStage, BackchannelAuthenticationGrantState, Client, Device,
+    DeviceCodeGrantState, ScopeNotAllowedError, Session, SiteConfig, TokenType, UserAgent,
 };
 use mas_keystore::{Encrypter, Keystore};
 use mas_oidc_client::types::scope::ScopeToken;
@@ -34,24 +37,29 @@ use mas_storage::{
         OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
         OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
-    user::BrowserSessionRepository,
+    user::{BrowserSessionRepository, UserRepository},
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use oauth2_types::{
     errors::{ClientError, ClientErrorCode},
     pkce::CodeChallengeError,
     requests::{
-        AccessTokenRequest, AccessTokenResponse, AuthorizationCodeGrant, ClientCredentialsGrant,
-        DeviceCodeGrant, GrantType, RefreshTokenGrant,
+        AccessTokenRequest, AccessTokenResponse, AuthorizationCodeGrant,
+        BackchannelAuthenticationGrant, ClientCredentialsGrant, DeviceCodeGrant, GrantType,
+        RefreshTokenGrant,
     },
     scope,
 };
+use opentelemetry::{metrics::Counter, Key};
 use thiserror::Error;
 use tracing::debug;
 use ulid::Ulid;
 
-use super::{generate_id_token, generate_token_pair};
-use crate::{impl_from_error_for_route, BoundActivityTracker};
+use super::{certificate_thumbprint, generate_id_token, generate_token_pair};
+use crate::{
+    impl_from_error_for_route, rate_limit::RateLimited, BoundActivityTracker, IntrospectionCache,
+    Limiter,
+};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -64,6 +72,12 @@ pub(crate) enum RouteError {
     #[error("pkce verification failed")]
     PkceVerification(#[from] CodeChallengeError),
 
+    #[error("public clients must use pkce")]
+    PkceRequired,
+
+    #[error("redirect uri mismatch")]
+    RedirectUriMismatch,
+
     #[error("client not found")]
     ClientNotFound,
 
@@ -88,12 +102,18 @@ pub(crate) enum RouteError {
     #[error("session {0} is invalid")]
     SessionInvalid(Ulid),
 
+    #[error("user {0} is locked")]
+    UserLocked(Ulid),
+
     #[error("client id mismatch: expected {expected}, got {actual}")]
     ClientIDMismatch { expected: Ulid, actual: Ulid },
 
     #[error("policy denied the request")]
     DeniedByPolicy(Vec<mas_policy::Violation>),
 
+    #[error(transparent)]
+    ScopeNotAllowed(#[from] ScopeNotAllowedError),
+
     #[error("unsupported grant type")]
     UnsupportedGrantType,
 
@@ -106,25 +126,65 @@ pub(crate) enum RouteError {
     #[error("failed to load oauth session")]
     NoSuchOAuthSession,
 
+    #[error("failed to load user")]
+    NoSuchUser,
+
     #[error("device code grant expired")]
     DeviceCodeExpired,
 
     #[error("device code grant is still pending")]
     DeviceCodePending,
 
+    #[error("device code grant is polled too often")]
+    DeviceCodeSlowDown,
+
     #[error("device code grant was rejected")]
     DeviceCodeRejected,
 
     #[error("device code grant was already exchanged")]
     DeviceCodeExchanged,
+
+    #[error("backchannel authentication grant expired")]
+    BackchannelAuthenticationExpired,
+
+    #[error("backchannel authentication grant is still pending")]
+    BackchannelAuthenticationPending,
+
+    #[error("backchannel authentication grant is polled too often")]
+    BackchannelAuthenticationSlowDown,
+
+    #[error("backchannel authentication grant was rejected")]
+    BackchannelAuthenticationRejected,
+
+    #[error("backchannel authentication grant was already exchanged")]
+    BackchannelAuthenticationExchanged,
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
 }
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
+        // Being rate limited is not worth reporting to Sentry
+        if let Self::RateLimited(e) = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                e,
+                Json(
+                    ClientError::from(ClientErrorCode::TemporarilyUnavailable)
+                        .with_description("Too many requests, try again later".to_owned()),
+                ),
+            )
+                .into_response();
+        }
+
         let event_id = sentry::capture_error(&self);
 
         let response = match self {
-            Self::Internal(_) | Self::NoSuchBrowserSession | Self::NoSuchOAuthSession => (
+            Self::Internal(_)
+            | Self::NoSuchBrowserSession
+            | Self::NoSuchOAuthSession
+            | Self::NoSuchUser => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ClientError::from(ClientErrorCode::ServerError)),
             ),
@@ -139,6 +199,22 @@ impl IntoResponse for RouteError {
                         .with_description(format!("PKCE verification failed: {err}")),
                 ),
             ),
+            Self::PkceRequired => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidGrant)
+                        .with_description("Public clients must use PKCE".to_owned()),
+                ),
+            ),
+            Self::RedirectUriMismatch => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidGrant).with_description(
+                        "redirect_uri does not match the one used in the authorization request"
+                            .to_owned(),
+                    ),
+                ),
+            ),
             Self::ClientNotFound | Self::ClientCredentialsVerification(_) => (
                 StatusCode::UNAUTHORIZED,
                 Json(ClientError::from(ClientErrorCode::InvalidClient)),
@@ -159,23 +235,36 @@ impl IntoResponse for RouteError {
                     ),
                 ),
             ),
-            Self::DeviceCodeRejected => (
+            Self::ScopeNotAllowed(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidScope)
+                        .with_description(e.to_string()),
+                ),
+            ),
+            Self::DeviceCodeRejected | Self::BackchannelAuthenticationRejected => (
                 StatusCode::FORBIDDEN,
                 Json(ClientError::from(ClientErrorCode::AccessDenied)),
             ),
-            Self::DeviceCodeExpired => (
+            Self::DeviceCodeExpired | Self::BackchannelAuthenticationExpired => (
                 StatusCode::FORBIDDEN,
                 Json(ClientError::from(ClientErrorCode::ExpiredToken)),
             ),
-            Self::DeviceCodePending => (
+            Self::DeviceCodePending | Self::BackchannelAuthenticationPending => (
                 StatusCode::FORBIDDEN,
                 Json(ClientError::from(ClientErrorCode::AuthorizationPending)),
             ),
+            Self::DeviceCodeSlowDown | Self::BackchannelAuthenticationSlowDown => (
+                StatusCode::FORBIDDEN,
+                Json(ClientError::from(ClientErrorCode::SlowDown)),
+            ),
             Self::InvalidGrant
             | Self::DeviceCodeExchanged
+            | Self::BackchannelAuthenticationExchanged
             | Self::RefreshTokenNotFound
             | Self::RefreshTokenInvalid(_)
             | Self::SessionInvalid(_)
+            | Self::UserLocked(_)
             | Self::ClientIDMismatch { .. }
             | Self::GrantNotFound => (
                 StatusCode::BAD_REQUEST,
@@ -185,6 +274,7 @@ impl IntoResponse for RouteError {
                 StatusCode::BAD_REQUEST,
                 Json(ClientError::from(ClientErrorCode::UnsupportedGrantType)),
             ),
+            Self::RateLimited(_) => unreachable!("handled above"),
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -195,6 +285,27 @@ impl_from_error_for_route!(mas_storage::RepositoryError);
 impl_from_error_for_route!(mas_policy::EvaluationError);
 impl_from_error_for_route!(super::IdTokenSignatureError);
 
+const GRANT_TYPE: Key = Key::from_static_str("grant_type");
+
+/// Counts the number of tokens issued by the token endpoint, by grant type
+fn token_issuance_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = opentelemetry::global::meter_with_version(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            Some(opentelemetry_semantic_conventions::SCHEMA_URL),
+            None,
+        );
+
+        meter
+            .u64_counter("mas.oauth2.token_issued")
+            .with_description("The number of tokens issued by the token endpoint")
+            .with_unit(opentelemetry::metrics::Unit::new("{token}"))
+            .init()
+    })
+}
+
 #[tracing::instrument(
     name = "handlers.oauth2.token.post",
     fields(client.id = client_authorization.client_id()),
@@ -211,11 +322,18 @@ pub(crate) async fn post(
     mut repo: BoxRepository,
     State(site_config): State<SiteConfig>,
     State(encrypter): State<Encrypter>,
+    State(limiter): State<Limiter>,
+    State(introspection_cache): State<IntrospectionCache>,
     policy: Policy,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     client_authorization: ClientAuthorization<AccessTokenRequest>,
 ) -> Result<impl IntoResponse, RouteError> {
     let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    limiter
+        .check_token_requester(&clock, activity_tracker.ip())
+        .await?;
+
     let client = client_authorization
         .credentials
         .fetch(&mut repo)
@@ -227,14 +345,38 @@ pub(crate) async fn post(
         .as_ref()
         .ok_or(RouteError::ClientNotAllowed)?;
 
+    if client.is_public() && !site_config.public_clients_allowed {
+        return Err(RouteError::ClientNotAllowed);
+    }
+
     client_authorization
         .credentials
         .verify(&http_client_factory, &encrypter, method, &client)
         .await?;
 
+    limiter.check_token_client(&clock, client.id).await?;
+
+    // Access tokens issued to clients which authenticated with a TLS client
+    // certificate are bound to it, as per RFC 8705
+    let certificate_thumbprint = client_authorization
+        .credentials
+        .certificate()
+        .map(certificate_thumbprint);
+
     let form = client_authorization.form.ok_or(RouteError::BadRequest)?;
 
-    let (reply, repo) = match form {
+    let grant_type = match &form {
+        AccessTokenRequest::AuthorizationCode(_) => "authorization_code",
+        AccessTokenRequest::RefreshToken(_) => "refresh_token",
+        AccessTokenRequest::ClientCredentials(_) => "client_credentials",
+        AccessTokenRequest::DeviceCode(_) => "urn:ietf:params:oauth:grant-type:device_code",
+        AccessTokenRequest::ClientInitiatedBackchannelAuthentication(_) => {
+            "urn:openid:params:grant-type:ciba"
+        }
+        _ => "unknown",
+    };
+
+    let (reply, mut repo) = match form {
         AccessTokenRequest::AuthorizationCode(grant) => {
             authorization_code_grant(
                 &mut rng,
@@ -245,6 +387,7 @@ pub(crate) async fn post(
                 &key_store,
                 &url_builder,
                 &site_config,
+                &introspection_cache,
                 repo,
                 user_agent,
             )
@@ -258,6 +401,7 @@ pub(crate) async fn post(
                 &grant,
                 &client,
                 &site_config,
+                &introspection_cache,
                 repo,
                 user_agent,
             )
@@ -287,6 +431,23 @@ pub(crate) async fn post(
                 &key_store,
                 &url_builder,
                 &site_config,
+                &limiter,
+                repo,
+                user_agent,
+            )
+            .await?
+        }
+        AccessTokenRequest::ClientInitiatedBackchannelAuthentication(grant) => {
+            backchannel_authentication_grant(
+                &mut rng,
+                &clock,
+                &activity_tracker,
+                &grant,
+                &client,
+                &key_store,
+                &url_builder,
+                &site_config,
+                &limiter,
                 repo,
                 user_agent,
             )
@@ -297,8 +458,22 @@ pub(crate) async fn post(
         }
     };
 
+    if let Some(certificate_thumbprint) = certificate_thumbprint {
+        let access_token = repo
+            .oauth2_access_token()
+            .find_by_token(&reply.access_token)
+            .await?
+            .ok_or_else(|| RouteError::Internal("issued access token not found".into()))?;
+
+        repo.oauth2_access_token()
+            .bind_to_certificate(access_token, certificate_thumbprint)
+            .await?;
+    }
+
     repo.save().await?;
 
+    token_issuance_counter().add(1, &[GRANT_TYPE.string(grant_type)]);
+
     let mut headers = HeaderMap::new();
     headers.typed_insert(CacheControl::new().with_no_store());
     headers.typed_insert(Pragma::no_cache());
@@ -306,6 +481,12 @@ pub(crate) async fn post(
     Ok((headers, Json(reply)))
 }
 
+/// Whether refresh tokens can be issued for this session, which requires the
+/// `offline_access` scope if the server is configured to do so
+fn offline_access_granted(site_config: &SiteConfig, session: &Session) -> bool {
+    !site_config.offline_access_required || session.scope.contains(&scope::OFFLINE_ACCESS)
+}
+
 #[allow(clippy::too_many_lines)] // TODO: refactor some parts out
 async fn authorization_code_grant(
     mut rng: &mut BoxRng,
@@ -316,6 +497,7 @@ async fn authorization_code_grant(
     key_store: &Keystore,
     url_builder: &UrlBuilder,
     site_config: &SiteConfig,
+    introspection_cache: &IntrospectionCache,
     mut repo: BoxRepository,
     user_agent: Option<UserAgent>,
 ) -> Result<(AccessTokenResponse, BoxRepository), RouteError> {
@@ -352,8 +534,9 @@ async fn authorization_code_grant(
                     .lookup(session_id)
                     .await?
                     .ok_or(RouteError::NoSuchOAuthSession)?;
-                repo.oauth2_session().finish(clock, session).await?;
+                let session = repo.oauth2_session().finish(clock, session).await?;
                 repo.save().await?;
+                introspection_cache.invalidate_session(session.id).await;
             }
 
             return Err(RouteError::InvalidGrant);
@@ -395,6 +578,26 @@ async fn authorization_code_grant(
         return Err(RouteError::UnauthorizedClient);
     }
 
+    // Public clients have no secret, so PKCE and the exact redirect URI are the
+    // only things binding the code to the client which started the flow
+    if client.is_public() {
+        if code.pkce.is_none() {
+            return Err(RouteError::PkceRequired);
+        }
+
+        if grant.redirect_uri.is_none() {
+            return Err(RouteError::RedirectUriMismatch);
+        }
+    }
+
+    if grant
+        .redirect_uri
+        .as_ref()
+        .is_some_and(|redirect_uri| *redirect_uri != authz_grant.redirect_uri)
+    {
+        return Err(RouteError::RedirectUriMismatch);
+    }
+
     match (code.pkce.as_ref(), grant.code_verifier.as_ref()) {
         (None, None) => {}
         // We have a challenge but no verifier (or vice-versa)? Bad request.
@@ -422,8 +625,18 @@ async fn authorization_code_grant(
         .await?;
 
     let ttl = site_config.access_token_ttl;
-    let (access_token, refresh_token) =
-        generate_token_pair(&mut rng, clock, &mut repo, &session, ttl).await?;
+    let (access_token, refresh_token) = if offline_access_granted(site_config, &session) {
+        let (access_token, refresh_token) =
+            generate_token_pair(&mut rng, clock, &mut repo, &session, ttl).await?;
+        (access_token, Some(refresh_token))
+    } else {
+        let access_token_str = TokenType::AccessToken.generate(&mut rng);
+        let access_token = repo
+            .oauth2_access_token()
+            .add(&mut rng, clock, &session, access_token_str, Some(ttl))
+            .await?;
+        (access_token, None)
+    };
 
     let id_token = if session.scope.contains(&scope::OPENID) {
         Some(generate_id_token(
@@ -443,9 +656,12 @@ async fn authorization_code_grant(
 
     let mut params = AccessTokenResponse::new(access_token.access_token)
         .with_expires_in(ttl)
-        .with_refresh_token(refresh_token.refresh_token)
         .with_scope(session.scope.clone());
 
+    if let Some(refresh_token) = refresh_token {
+        params = params.with_refresh_token(refresh_token.refresh_token);
+    }
+
     if let Some(id_token) = id_token {
         params = params.with_id_token(id_token);
     }
@@ -485,6 +701,7 @@ async fn refresh_token_grant(
     grant: &RefreshTokenGrant,
     client: &Client,
     site_config: &SiteConfig,
+    introspection_cache: &IntrospectionCache,
     mut repo: BoxRepository,
     user_agent: Option<UserAgent>,
 ) -> Result<(AccessTokenResponse, BoxRepository), RouteError> {
@@ -522,6 +739,20 @@ async fn refresh_token_grant(
         return Err(RouteError::SessionInvalid(session.id));
     }
 
+    // Tokens of locked users can't be refreshed, but the session is kept so
+    // that it can be used again once the user is unlocked
+    if let Some(user_id) = session.user_id {
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .ok_or(RouteError::NoSuchUser)?;
+
+        if !user.is_valid() {
+            return Err(RouteError::UserLocked(user.id));
+        }
+    }
+
     if client.id != session.client_id {
         // As per https://datatracker.ietf.org/doc/html/rfc6749#section-5.2
         return Err(RouteError::ClientIDMismatch {
@@ -546,9 +777,13 @@ async fn refresh_token_grant(
     if let Some(access_token_id) = refresh_token.access_token_id {
         let access_token = repo.oauth2_access_token().lookup(access_token_id).await?;
         if let Some(access_token) = access_token {
-            repo.oauth2_access_token()
+            let access_token = repo
+                .oauth2_access_token()
                 .revoke(clock, access_token)
                 .await?;
+            introspection_cache
+                .invalidate_token(&access_token.access_token)
+                .await;
         }
     }
 
@@ -582,6 +817,9 @@ async fn client_credentials_grant(
         .clone()
         .unwrap_or_else(|| std::iter::empty::<ScopeToken>().collect());
 
+    // Apply the default scope and the scope restrictions of the client
+    let scope = client.restrict_scope(scope)?;
+
     // Make the request go through the policy engine
     let res = policy
         .evaluate_client_credentials_grant(&scope, client)
@@ -637,6 +875,7 @@ async fn device_code_grant(
     key_store: &Keystore,
     url_builder: &UrlBuilder,
     site_config: &SiteConfig,
+    limiter: &Limiter,
     mut repo: BoxRepository,
     user_agent: Option<UserAgent>,
 ) -> Result<(AccessTokenResponse, BoxRepository), RouteError> {
@@ -665,6 +904,13 @@ async fn device_code_grant(
 
     let browser_session_id = match &grant.state {
         DeviceCodeGrantState::Pending => {
+            // Clients polling faster than the advertised interval are asked to
+            // slow down
+            limiter
+                .check_device_code_poll(clock, grant.id)
+                .await
+                .map_err(|_| RouteError::DeviceCodeSlowDown)?;
+
             return Err(RouteError::DeviceCodePending);
         }
         DeviceCodeGrantState::Rejected { .. } => {
@@ -711,7 +957,9 @@ async fn device_code_grant(
 
     // If the client uses the refresh token grant type, we also generate a refresh
     // token
-    if client.grant_types.contains(&GrantType::RefreshToken) {
+    if client.grant_types.contains(&GrantType::RefreshToken)
+        && offline_access_granted(site_config, &session)
+    {
         let refresh_token_str = TokenType::RefreshToken.generate(rng);
 
         let refresh_token = repo
@@ -768,20 +1016,178 @@ async fn device_code_grant(
     Ok((params, repo))
 }
 
+async fn backchannel_authentication_grant(
+    rng: &mut BoxRng,
+    clock: &impl Clock,
+    activity_tracker: &BoundActivityTracker,
+    grant: &BackchannelAuthenticationGrant,
+    client: &Client,
+    key_store: &Keystore,
+    url_builder: &UrlBuilder,
+    site_config: &SiteConfig,
+    limiter: &Limiter,
+    mut repo: BoxRepository,
+    user_agent: Option<UserAgent>,
+) -> Result<(AccessTokenResponse, BoxRepository), RouteError> {
+    // Check that the client is allowed to use this grant type
+    if !client
+        .grant_types
+        .contains(&GrantType::ClientInitiatedBackchannelAuthentication)
+    {
+        return Err(RouteError::UnauthorizedClient);
+    }
+
+    let grant = repo
+        .oauth2_backchannel_authentication_grant()
+        .find_by_auth_req_id(&grant.auth_req_id)
+        .await?
+        .ok_or(RouteError::GrantNotFound)?;
+
+    // Check that the client match
+    if client.id != grant.client_id {
+        return Err(RouteError::ClientIDMismatch {
+            expected: grant.client_id,
+            actual: client.id,
+        });
+    }
+
+    if grant.expires_at < clock.now() {
+        return Err(RouteError::BackchannelAuthenticationExpired);
+    }
+
+    let browser_session_id = match &grant.state {
+        BackchannelAuthenticationGrantState::Pending => {
+            // Polling is limited per grant the same way as for device code
+            // grants, which use the same interval
+            limiter
+                .check_device_code_poll(clock, grant.id)
+                .await
+                .map_err(|_| RouteError::BackchannelAuthenticationSlowDown)?;
+
+            return Err(RouteError::BackchannelAuthenticationPending);
+        }
+        BackchannelAuthenticationGrantState::Rejected { .. } => {
+            return Err(RouteError::BackchannelAuthenticationRejected);
+        }
+        BackchannelAuthenticationGrantState::Exchanged { .. } => {
+            return Err(RouteError::BackchannelAuthenticationExchanged);
+        }
+        BackchannelAuthenticationGrantState::Fulfilled {
+            browser_session_id, ..
+        } => *browser_session_id,
+    };
+
+    let browser_session = repo
+        .browser_session()
+        .lookup(browser_session_id)
+        .await?
+        .ok_or(RouteError::NoSuchBrowserSession)?;
+
+    // Start the session
+    let mut session = repo
+        .oauth2_session()
+        .add_from_browser_session(rng, clock, client, &browser_session, grant.scope.clone())
+        .await?;
+
+    if let Some(user_agent) = user_agent {
+        session = repo
+            .oauth2_session()
+            .record_user_agent(session, user_agent)
+            .await?;
+    }
+
+    repo.oauth2_backchannel_authentication_grant()
+        .exchange(clock, grant, &session)
+        .await?;
+
+    let ttl = site_config.access_token_ttl;
+    let access_token_str = TokenType::AccessToken.generate(rng);
+
+    let access_token = repo
+        .oauth2_access_token()
+        .add(rng, clock, &session, access_token_str, Some(ttl))
+        .await?;
+
+    let mut params =
+        AccessTokenResponse::new(access_token.access_token.clone()).with_expires_in(ttl);
+
+    // If the client uses the refresh token grant type, we also generate a refresh
+    // token
+    if client.grant_types.contains(&GrantType::RefreshToken)
+        && offline_access_granted(site_config, &session)
+    {
+        let refresh_token_str = TokenType::RefreshToken.generate(rng);
+
+        let refresh_token = repo
+            .oauth2_refresh_token()
+            .add(rng, clock, &session, &access_token, refresh_token_str)
+            .await?;
+
+        params = params.with_refresh_token(refresh_token.refresh_token);
+    }
+
+    // If the client asked for an ID token, we generate one
+    if session.scope.contains(&scope::OPENID) {
+        let id_token = generate_id_token(
+            rng,
+            clock,
+            url_builder,
+            key_store,
+            client,
+            None,
+            &browser_session,
+            Some(&access_token),
+            None,
+        )?;
+
+        params = params.with_id_token(id_token);
+    }
+
+    // Look for device to provision
+    for scope in &*session.scope {
+        if let Some(device) = Device::from_scope_token(scope) {
+            repo.job()
+                .schedule_job(ProvisionDeviceJob::new(&browser_session.user, &device))
+                .await?;
+        }
+    }
+
+    activity_tracker
+        .record_oauth2_session(clock, &session)
+        .await;
+
+    if !session.scope.is_empty() {
+        // We only return the scope if it's not empty
+        params = params.with_scope(session.scope);
+    }
+
+    Ok((params, repo))
+}
+
 #[cfg(test)]
 mod tests {
     use hyper::Request;
-    use mas_data_model::{AccessToken, AuthorizationCode, RefreshToken};
+    use mas_axum_utils::ClientCertificate;
+    use mas_data_model::{AccessToken, AuthorizationCode, Pkce, RefreshToken};
+    use mas_iana::oauth::{
+        OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
+        PkceCodeChallengeMethod,
+    };
     use mas_router::SimpleRoute;
+    use mas_storage::oauth2::OAuth2ClientRepository;
     use oauth2_types::{
         registration::ClientRegistrationResponse,
-        requests::{DeviceAuthorizationResponse, ResponseMode},
-        scope::{Scope, OPENID},
+        requests::{BackchannelAuthenticationResponse, DeviceAuthorizationResponse, ResponseMode},
+        scope::{Scope, OFFLINE_ACCESS, OPENID},
     };
     use sqlx::PgPool;
 
     use super::*;
-    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+    use crate::test_utils::{
+        init_tracing, test_site_config, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    const CODE_VERIFIER: &str = "thisisaverysecurecodeverifierwhichislongenough";
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_auth_code_grant(pool: PgPool) {
@@ -840,7 +1246,10 @@ mod tests {
                 Scope::from_iter([OPENID]),
                 Some(AuthorizationCode {
                     code: code.to_owned(),
-                    pkce: None,
+                    pkce: Some(Pkce::new(
+                        PkceCodeChallengeMethod::Plain,
+                        CODE_VERIFIER.to_owned(),
+                    )),
                 }),
                 Some("state".to_owned()),
                 Some("nonce".to_owned()),
@@ -879,6 +1288,7 @@ mod tests {
                 "grant_type": "authorization_code",
                 "code": code,
                 "redirect_uri": grant.redirect_uri,
+                "code_verifier": CODE_VERIFIER,
                 "client_id": client.client_id,
             }));
 
@@ -896,6 +1306,7 @@ mod tests {
                 "grant_type": "authorization_code",
                 "code": code,
                 "redirect_uri": grant.redirect_uri,
+                "code_verifier": CODE_VERIFIER,
                 "client_id": client.client_id,
             }));
 
@@ -916,6 +1327,7 @@ mod tests {
                 "grant_type": "authorization_code",
                 "code": code,
                 "redirect_uri": grant.redirect_uri,
+                "code_verifier": CODE_VERIFIER,
                 "client_id": client.client_id,
             }));
 
@@ -940,7 +1352,10 @@ mod tests {
                 Scope::from_iter([OPENID]),
                 Some(AuthorizationCode {
                     code: code.to_owned(),
-                    pkce: None,
+                    pkce: Some(Pkce::new(
+                        PkceCodeChallengeMethod::Plain,
+                        CODE_VERIFIER.to_owned(),
+                    )),
                 }),
                 Some("state".to_owned()),
                 Some("nonce".to_owned()),
@@ -984,6 +1399,7 @@ mod tests {
                 "grant_type": "authorization_code",
                 "code": code,
                 "redirect_uri": grant.redirect_uri,
+                "code_verifier": CODE_VERIFIER,
                 "client_id": client.client_id,
             }));
 
@@ -994,9 +1410,17 @@ mod tests {
     }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
-    async fn test_refresh_token_grant(pool: PgPool) {
+    async fn test_offline_access_required(pool: PgPool) {
         init_tracing();
-        let state = TestState::from_pool(pool).await.unwrap();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                offline_access_required: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
 
         // Provision a client
         let request =
@@ -1014,8 +1438,6 @@ mod tests {
 
         let ClientRegistrationResponse { client_id, .. } = response.json();
 
-        // Let's provision a user and create a session for them. This part is hard to
-        // test with just HTTP requests, so we'll use the repository directly.
         let mut repo = state.repository().await.unwrap();
 
         let user = repo
@@ -1030,7 +1452,6 @@ mod tests {
             .await
             .unwrap();
 
-        // Lookup the client in the database.
         let client = repo
             .oauth2_client()
             .find_by_client_id(&client_id)
@@ -1038,47 +1459,341 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        // Get a token pair
-        let session = repo
-            .oauth2_session()
-            .add_from_browser_session(
-                &mut state.rng(),
-                &state.clock,
-                &client,
-                &browser_session,
-                Scope::from_iter([OPENID]),
-            )
-            .await
-            .unwrap();
-
-        let (AccessToken { access_token, .. }, RefreshToken { refresh_token, .. }) =
-            generate_token_pair(
-                &mut state.rng(),
-                &state.clock,
-                &mut repo,
-                &session,
-                Duration::microseconds(5 * 60 * 1000 * 1000),
-            )
-            .await
-            .unwrap();
+        // Start two grants, only one of them with the `offline_access` scope
+        let mut grants = Vec::new();
+        for (code, scope) in [
+            ("codewithoutoffline", Scope::from_iter([OPENID])),
+            (
+                "codewithoffline",
+                Scope::from_iter([OPENID, OFFLINE_ACCESS]),
+            ),
+        ] {
+            let grant = repo
+                .oauth2_authorization_grant()
+                .add(
+                    &mut state.rng(),
+                    &state.clock,
+                    &client,
+                    "https://example.com/callback".parse().unwrap(),
+                    scope,
+                    Some(AuthorizationCode {
+                        code: code.to_owned(),
+                        pkce: Some(Pkce::new(
+                            PkceCodeChallengeMethod::Plain,
+                            CODE_VERIFIER.to_owned(),
+                        )),
+                    }),
+                    Some("state".to_owned()),
+                    Some("nonce".to_owned()),
+                    None,
+                    ResponseMode::Query,
+                    false,
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let session = repo
+                .oauth2_session()
+                .add_from_browser_session(
+                    &mut state.rng(),
+                    &state.clock,
+                    &client,
+                    &browser_session,
+                    grant.scope.clone(),
+                )
+                .await
+                .unwrap();
+
+            let grant = repo
+                .oauth2_authorization_grant()
+                .fulfill(&state.clock, &session, grant)
+                .await
+                .unwrap();
+
+            grants.push(grant);
+        }
 
         repo.save().await.unwrap();
 
-        // First check that the token is valid
-        assert!(state.is_access_token_valid(&access_token).await);
-
-        // Now call the token endpoint to get an access token.
+        // Without the `offline_access` scope, no refresh token is issued
         let request =
             Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
-                "grant_type": "refresh_token",
-                "refresh_token": refresh_token,
+                "grant_type": "authorization_code",
+                "code": "codewithoutoffline",
+                "redirect_uri": grants[0].redirect_uri,
+                "code_verifier": CODE_VERIFIER,
                 "client_id": client.client_id,
             }));
 
         let response = state.request(request).await;
         response.assert_status(StatusCode::OK);
+        let AccessTokenResponse {
+            access_token,
+            refresh_token,
+            ..
+        } = response.json();
+        assert!(state.is_access_token_valid(&access_token).await);
+        assert!(refresh_token.is_none());
 
-        let old_access_token = access_token;
+        // With it, the client also gets a refresh token
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": "codewithoffline",
+                "redirect_uri": grants[1].redirect_uri,
+                "code_verifier": CODE_VERIFIER,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let AccessTokenResponse { refresh_token, .. } = response.json();
+        assert!(refresh_token.is_some());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_public_client_requirements(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+
+        // Provision a public client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "contacts": ["contact@example.com"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Start and fulfill a grant without PKCE, and one with PKCE
+        let mut grants = Vec::new();
+        for (code, pkce) in [
+            ("codewithoutpkce", None),
+            (
+                "codewithpkce",
+                Some(Pkce::new(
+                    PkceCodeChallengeMethod::Plain,
+                    CODE_VERIFIER.to_owned(),
+                )),
+            ),
+        ] {
+            let grant = repo
+                .oauth2_authorization_grant()
+                .add(
+                    &mut state.rng(),
+                    &state.clock,
+                    &client,
+                    "https://example.com/callback".parse().unwrap(),
+                    Scope::from_iter([OPENID]),
+                    Some(AuthorizationCode {
+                        code: code.to_owned(),
+                        pkce,
+                    }),
+                    None,
+                    None,
+                    None,
+                    ResponseMode::Query,
+                    false,
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let session = repo
+                .oauth2_session()
+                .add_from_browser_session(
+                    &mut state.rng(),
+                    &state.clock,
+                    &client,
+                    &browser_session,
+                    grant.scope.clone(),
+                )
+                .await
+                .unwrap();
+
+            let grant = repo
+                .oauth2_authorization_grant()
+                .fulfill(&state.clock, &session, grant)
+                .await
+                .unwrap();
+            grants.push(grant);
+        }
+
+        repo.save().await.unwrap();
+
+        // The code without PKCE can't be exchanged
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": "codewithoutpkce",
+                "redirect_uri": grants[0].redirect_uri,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::InvalidGrant);
+
+        // The redirect URI must be given, and match exactly
+        for redirect_uri in [None, Some("https://example.com/callback?foo=bar")] {
+            let mut form = serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": "codewithpkce",
+                "code_verifier": CODE_VERIFIER,
+                "client_id": client.client_id,
+            });
+            if let Some(redirect_uri) = redirect_uri {
+                form["redirect_uri"] = redirect_uri.into();
+            }
+            let request = Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(form);
+
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+            let ClientError { error, .. } = response.json();
+            assert_eq!(error, ClientErrorCode::InvalidGrant);
+        }
+
+        // Public clients can be forbidden altogether
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                public_clients_allowed: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": "codewithpkce",
+                "redirect_uri": grants[1].redirect_uri,
+                "code_verifier": CODE_VERIFIER,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::UnauthorizedClient);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_token_grant(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "contacts": ["contact@example.com"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code", "refresh_token"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        // Let's provision a user and create a session for them. This part is hard to
+        // test with just HTTP requests, so we'll use the repository directly.
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        // Lookup the client in the database.
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Get a token pair
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+
+        let (AccessToken { access_token, .. }, RefreshToken { refresh_token, .. }) =
+            generate_token_pair(
+                &mut state.rng(),
+                &state.clock,
+                &mut repo,
+                &session,
+                Duration::microseconds(5 * 60 * 1000 * 1000),
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        // First check that the token is valid
+        assert!(state.is_access_token_valid(&access_token).await);
+
+        // Now call the token endpoint to get an access token.
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "refresh_token",
+                "refresh_token": refresh_token,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let old_access_token = access_token;
         let old_refresh_token = refresh_token;
         let response: AccessTokenResponse = response.json();
         let access_token = response.access_token;
@@ -1116,6 +1831,105 @@ mod tests {
         let _: AccessTokenResponse = response.json();
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_token_grant_locked_user(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "contacts": ["contact@example.com"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code", "refresh_token"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+
+        let (_, RefreshToken { refresh_token, .. }) = generate_token_pair(
+            &mut state.rng(),
+            &state.clock,
+            &mut repo,
+            &session,
+            Duration::microseconds(5 * 60 * 1000 * 1000),
+        )
+        .await
+        .unwrap();
+
+        // Lock the user
+        let user = repo.user().lock(&state.clock, user).await.unwrap();
+
+        repo.save().await.unwrap();
+
+        // The refresh token can't be used while the user is locked
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "refresh_token",
+                "refresh_token": refresh_token,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::InvalidGrant);
+
+        // Unlock the user, the same refresh token can now be used
+        let mut repo = state.repository().await.unwrap();
+        repo.user().unlock(user).await.unwrap();
+        repo.save().await.unwrap();
+
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "refresh_token",
+                "refresh_token": refresh_token,
+                "client_id": client.client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let _: AccessTokenResponse = response.json();
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_client_credentials(pool: PgPool) {
         init_tracing();
@@ -1243,6 +2057,78 @@ mod tests {
         response.assert_status(StatusCode::OK);
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_tls_client_auth(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let certificate = include_bytes!("../../../keystore/tests/keys/client.cert.der").to_vec();
+
+        // Provision a static client authenticating with its certificate
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng()),
+                OAuthClientAuthenticationMethod::TlsClientAuth,
+                None,
+                None,
+                None,
+                Vec::new(),
+                Vec::new(),
+                vec![GrantType::ClientCredentials],
+                None,
+                false,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some("example.com".to_owned()),
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        let client_id = client.client_id;
+
+        // A request without a certificate is rejected
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "client_credentials",
+                "client_id": client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // With the certificate, the client gets a token bound to it
+        let request = Request::post(mas_router::OAuth2TokenEndpoint::PATH)
+            .extension(ClientCertificate(Some(certificate.clone())))
+            .form(serde_json::json!({
+                "grant_type": "client_credentials",
+                "client_id": client_id,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: AccessTokenResponse = response.json();
+
+        let mut repo = state.repository().await.unwrap();
+        let access_token = repo
+            .oauth2_access_token()
+            .find_by_token(&response.access_token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            access_token.certificate_thumbprint,
+            Some(certificate_thumbprint(&certificate))
+        );
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_device_code_grant(pool: PgPool) {
         init_tracing();
@@ -1368,6 +2254,19 @@ mod tests {
         let ClientError { error, .. } = response.json();
         assert_eq!(error, ClientErrorCode::AuthorizationPending);
 
+        // Polling again right away is too fast
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "urn:ietf:params:oauth:grant-type:device_code",
+                "device_code": device_grant.device_code,
+                "client_id": client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::SlowDown);
+
         state.clock.advance(Duration::try_hours(1).unwrap());
 
         // Poll again, it should be expired
@@ -1429,6 +2328,266 @@ mod tests {
         assert_eq!(error, ClientErrorCode::AccessDenied);
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_backchannel_authentication_grant(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Dynamically registered clients can't use the CIBA grant
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "contacts": ["contact@example.com"],
+                "token_endpoint_auth_method": "client_secret_post",
+                "grant_types": ["urn:openid:params:grant-type:ciba", "refresh_token"],
+                "response_types": [],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // Provision a static client allowed to use the CIBA grant
+        let client_secret = "secret";
+        let encrypted_client_secret = state
+            .encrypter
+            .encrypt_to_string(&mut state.rng(), client_secret.as_bytes())
+            .unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng()),
+                OAuthClientAuthenticationMethod::ClientSecretPost,
+                Some(encrypted_client_secret),
+                None,
+                None,
+                Vec::new(),
+                Vec::new(),
+                vec![
+                    GrantType::ClientInitiatedBackchannelAuthentication,
+                    GrantType::RefreshToken,
+                ],
+                None,
+                false,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let client_id = client.client_id;
+
+        // Provision the user the client will ask about
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Asking for an unknown user fails
+        let request = Request::post(mas_router::OAuth2BackchannelAuthenticationEndpoint::PATH)
+            .form(serde_json::json!({
+                "client_id": client_id,
+                "client_secret": client_secret,
+                "scope": "openid",
+                "login_hint": "bob",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::UnknownUserId);
+
+        // Binding messages must be short and printable
+        for binding_message in ["Order\n#1234", "\u{202E}4321# redrO", &"a".repeat(65)] {
+            let request = Request::post(mas_router::OAuth2BackchannelAuthenticationEndpoint::PATH)
+                .form(serde_json::json!({
+                    "client_id": client_id,
+                    "client_secret": client_secret,
+                    "scope": "openid",
+                    "login_hint": "alice",
+                    "binding_message": binding_message,
+                }));
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+
+            let ClientError { error, .. } = response.json();
+            assert_eq!(error, ClientErrorCode::InvalidBindingMessage);
+        }
+
+        // Start a backchannel authentication request
+        let request = Request::post(mas_router::OAuth2BackchannelAuthenticationEndpoint::PATH)
+            .form(serde_json::json!({
+                "client_id": client_id,
+                "client_secret": client_secret,
+                "scope": "openid",
+                "login_hint": "alice",
+                "binding_message": "Order #1234",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let backchannel_grant: BackchannelAuthenticationResponse = response.json();
+        assert_eq!(backchannel_grant.auth_req_id.len(), 32);
+
+        // Poll the token endpoint, it should be pending
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "urn:openid:params:grant-type:ciba",
+                "auth_req_id": backchannel_grant.auth_req_id,
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::AuthorizationPending);
+
+        // Approve the request on behalf of the user
+        let mut repo = state.repository().await.unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .find_by_auth_req_id(&backchannel_grant.auth_req_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(grant.user_id, user.id);
+        assert_eq!(grant.binding_message.as_deref(), Some("Order #1234"));
+
+        repo.oauth2_backchannel_authentication_grant()
+            .fulfill(&state.clock, grant, &browser_session)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        // Now call the token endpoint to get an access token
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "urn:openid:params:grant-type:ciba",
+                "auth_req_id": backchannel_grant.auth_req_id,
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let response: AccessTokenResponse = response.json();
+        assert!(state.is_access_token_valid(&response.access_token).await);
+        assert!(response.refresh_token.is_some());
+        assert!(response.id_token.is_some());
+
+        // The grant can't be exchanged twice
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "urn:openid:params:grant-type:ciba",
+                "auth_req_id": backchannel_grant.auth_req_id,
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::InvalidGrant);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_static_client_grant_types(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a static client only allowed to use the authorization code grant
+        let client_secret = "secret";
+        let encrypted_client_secret = state
+            .encrypter
+            .encrypt_to_string(&mut state.rng(), client_secret.as_bytes())
+            .unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng()),
+                OAuthClientAuthenticationMethod::ClientSecretPost,
+                Some(encrypted_client_secret),
+                None,
+                None,
+                vec!["https://example.com/callback".parse().unwrap()],
+                vec![OAuthAuthorizationEndpointResponseType::Code],
+                vec![GrantType::AuthorizationCode],
+                None,
+                false,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        let client_id = client.client_id;
+
+        // The grants it wasn't configured with are refused
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "client_credentials",
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::UnauthorizedClient);
+
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "refresh_token",
+                "client_id": client_id,
+                "client_secret": client_secret,
+                "refresh_token": "mar_doesnotexist",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::UnauthorizedClient);
+
+        // The authorization code grant goes through to the code validation
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "client_id": client_id,
+                "client_secret": client_secret,
+                "code": "doesnotexist",
+                "redirect_uri": "https://example.com/callback",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let ClientError { error, .. } = response.json();
+        assert_eq!(error, ClientErrorCode::InvalidGrant);
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_unsupported_grant(pool: PgPool) {
         init_tracing();
@@ -21,7 +21,7 @@ use hyper::StatusCode;
 use mas_axum_utils::{
     jwt::JwtResponse,
     sentry::SentryEventID,
-    user_authorization::{AuthorizationVerificationError, UserAuthorization},
+    user_authorization::{AuthorizationVerificationError, BearerError, UserAuthorization},
 };
 use mas_jose::{
     constraints::Constrainable,
@@ -30,7 +30,9 @@ use mas_jose::{
 use mas_keystore::Keystore;
 use mas_router::UrlBuilder;
 use mas_storage::{
-    oauth2::OAuth2ClientRepository, user::UserEmailRepository, BoxClock, BoxRepository, BoxRng,
+    oauth2::OAuth2ClientRepository,
+    user::{UserEmailRepository, UserPhoneRepository},
+    BoxClock, BoxRepository, BoxRng,
 };
 use oauth2_types::scope;
 use serde::Serialize;
@@ -46,6 +48,8 @@ struct UserInfo {
     username: String,
     email: Option<String>,
     email_verified: Option<bool>,
+    phone_number: Option<String>,
+    phone_number_verified: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -90,9 +94,8 @@ impl IntoResponse for RouteError {
             Self::Internal(_) | Self::InvalidSigningKey | Self::NoSuchClient | Self::NoSuchUser => {
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
             }
-            Self::AuthorizationVerificationError(_) | Self::Unauthorized => {
-                StatusCode::UNAUTHORIZED.into_response()
-            }
+            Self::AuthorizationVerificationError(e) => e.into_response(),
+            Self::Unauthorized => BearerError::InvalidToken.into_response(),
         };
 
         (SentryEventID::from(event_id), response).into_response()
@@ -109,12 +112,10 @@ pub async fn get(
     State(key_store): State<Keystore>,
     user_authorization: UserAuthorization,
 ) -> Result<Response, RouteError> {
-    let session = user_authorization.protected(&mut repo, &clock).await?;
-
     // This endpoint requires the `openid` scope.
-    if !session.scope.contains("openid") {
-        return Err(RouteError::Unauthorized);
-    }
+    let session = user_authorization
+        .protected(&mut repo, &clock, &scope::OPENID)
+        .await?;
 
     // Fail if the session is not associated with a user.
     let Some(user_id) = session.user_id else {
@@ -137,11 +138,24 @@ pub async fn get(
         None
     };
 
+    // Only verified phone numbers are exposed
+    let user_phone = if session.scope.contains(&scope::PHONE) {
+        repo.user_phone()
+            .all(&user)
+            .await?
+            .into_iter()
+            .find(|p| p.confirmed_at.is_some())
+    } else {
+        None
+    };
+
     let user_info = UserInfo {
         sub: user.sub.clone(),
         username: user.username.clone(),
         email_verified: user_email.as_ref().map(|u| u.confirmed_at.is_some()),
         email: user_email.map(|u| u.email),
+        phone_number_verified: user_phone.as_ref().map(|_| true),
+        phone_number: user_phone.map(|p| p.phone_number),
     };
 
     let client = repo
@@ -0,0 +1,265 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenAPI 3.1 description of the JSON APIs exposed by the service.
+//!
+//! The schemas of the request and response bodies are derived from the types
+//! used by the handlers, so that they can't drift apart.
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Json,
+};
+use mas_router::{SimpleRoute, UrlBuilder};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Value};
+
+use crate::compat;
+
+/// Convert an axum route to an OpenAPI path template
+fn path_template(route: &str) -> String {
+    route
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => format!("{{{param}}}"),
+            None => segment.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The JSON request body of an operation
+fn json_body<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    json!({
+        "required": true,
+        "content": {
+            "application/json": {
+                "schema": generator.subject_schema_for::<T>(),
+            },
+        },
+    })
+}
+
+/// A JSON response of an operation
+fn json_response<T: JsonSchema>(generator: &mut SchemaGenerator, description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": generator.subject_schema_for::<T>(),
+            },
+        },
+    })
+}
+
+/// Generate the OpenAPI document of the Matrix compatibility API
+#[must_use]
+pub fn spec(url_builder: &UrlBuilder) -> Value {
+    let settings = SchemaSettings::draft2019_09().with(|s| {
+        s.definitions_path = "#/components/schemas/".to_owned();
+        s.option_add_null_type = true;
+        s.option_nullable = false;
+    });
+    let mut generator = settings.into_generator();
+
+    let version = json!({
+        "name": "version",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "enum": ["v3", "r0"] },
+    });
+    let error = json_response::<compat::MatrixError>(&mut generator, "Request failed");
+    let bearer = json!([{ "accessToken": [] }]);
+
+    let paths = json!({
+        path_template(mas_router::CompatLogin::PATH): {
+            "parameters": [version],
+            "get": {
+                "tags": ["compat"],
+                "summary": "List the supported login types",
+                "operationId": "compatLoginTypes",
+                "responses": {
+                    "200": json_response::<compat::login::LoginTypes>(&mut generator, "The supported login types"),
+                },
+            },
+            "post": {
+                "tags": ["compat"],
+                "summary": "Log in with a password or a login token",
+                "operationId": "compatLogin",
+                "requestBody": json_body::<compat::login::RequestBody>(&mut generator),
+                "responses": {
+                    "200": json_response::<compat::login::ResponseBody>(&mut generator, "The user is logged in"),
+                    "400": error,
+                    "403": error,
+                    "429": error,
+                },
+            },
+        },
+        path_template(mas_router::CompatLogout::PATH): {
+            "parameters": [version],
+            "post": {
+                "tags": ["compat"],
+                "summary": "End the session of the access token",
+                "operationId": "compatLogout",
+                "security": bearer,
+                "responses": {
+                    "200": {
+                        "description": "The session ended",
+                        "content": {
+                            "application/json": {
+                                "schema": { "type": "object" },
+                            },
+                        },
+                    },
+                    "401": error,
+                },
+            },
+        },
+        path_template(mas_router::CompatRefresh::PATH): {
+            "parameters": [version],
+            "post": {
+                "tags": ["compat"],
+                "summary": "Exchange a refresh token for a new access token",
+                "operationId": "compatRefresh",
+                "requestBody": json_body::<compat::refresh::RequestBody>(&mut generator),
+                "responses": {
+                    "200": json_response::<compat::refresh::ResponseBody>(&mut generator, "A new pair of tokens"),
+                    "401": error,
+                },
+            },
+        },
+        path_template(mas_router::CompatLoginSsoRedirect::PATH): {
+            "parameters": [version],
+            "get": {
+                "tags": ["compat"],
+                "summary": "Start a login through the web interface",
+                "operationId": "compatLoginSsoRedirect",
+                "parameters": [{
+                    "name": "redirectUrl",
+                    "in": "query",
+                    "required": true,
+                    "schema": { "type": "string", "format": "uri" },
+                }],
+                "responses": {
+                    "303": { "description": "Redirect to the login page" },
+                },
+            },
+        },
+    });
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Matrix Authentication Service",
+            "description": "JSON APIs of the Matrix Authentication Service",
+            "version": env!("CARGO_PKG_VERSION"),
+            "license": {
+                "name": "Apache-2.0",
+                "identifier": "Apache-2.0",
+            },
+        },
+        "servers": [{ "url": url_builder.http_base() }],
+        "tags": [{
+            "name": "compat",
+            "description": "Matrix Client-Server API endpoints for clients which don't support OAuth 2.0",
+        }],
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(),
+            "securitySchemes": {
+                "accessToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                },
+            },
+        },
+    })
+}
+
+#[tracing::instrument(name = "handlers.openapi.get", skip_all)]
+pub(crate) async fn get(State(url_builder): State<UrlBuilder>) -> impl IntoResponse {
+    Json(spec(&url_builder))
+}
+
+/// An interactive explorer of the API, loading Swagger UI from a CDN
+pub(crate) async fn explorer() -> impl IntoResponse {
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <title>Matrix Authentication Service API</title>
+    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+      window.ui = SwaggerUIBundle({{ url: "{spec}", dom_id: "#swagger-ui" }});
+    </script>
+  </body>
+</html>
+"##,
+        spec = mas_router::OpenApiSpec::PATH,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{init_tracing, RequestBuilderExt, ResponseExt, TestState};
+
+    #[test]
+    fn test_path_template() {
+        assert_eq!(
+            path_template("/_matrix/client/:version/login"),
+            "/_matrix/client/{version}/login"
+        );
+        assert_eq!(path_template("/health"), "/health");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_openapi_spec(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request = Request::get(mas_router::OpenApiSpec::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let spec: Value = response.json();
+
+        assert_eq!(spec["openapi"], "3.1.0");
+        assert_eq!(spec["servers"][0]["url"], "https://example.com/");
+
+        let login = &spec["paths"]["/_matrix/client/{version}/login"];
+        assert!(login["get"].is_object());
+        assert!(login["post"]["requestBody"].is_object());
+
+        // All the references must point to a known schema
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        let serialized = spec.to_string();
+        for reference in serialized.split("\"$ref\":\"").skip(1) {
+            let reference = reference.split('"').next().unwrap();
+            let name = reference.strip_prefix("#/components/schemas/").unwrap();
+            assert!(schemas.contains_key(name), "unknown schema {name}");
+        }
+    }
+}
@@ -20,9 +20,71 @@ use axum::{
     http::request::Parts,
     TypedHeader,
 };
-use mas_axum_utils::language_detection::AcceptLanguage;
+use language_tags::LanguageTag;
+use mas_axum_utils::{
+    cookies::{CookieJar, CookieManager},
+    language_detection::AcceptLanguage,
+};
 use mas_i18n::{locale, DataLocale, Translator};
 
+/// Name of the cookie in which the locales requested by a client through the
+/// `ui_locales` authorization parameter are kept, so that they apply to the
+/// pages of the whole authorization flow
+static UI_LOCALES_COOKIE: &str = "ui-locales";
+
+/// An extension trait for [`CookieJar`] to remember the locales requested by a
+/// client
+pub(crate) trait UiLocalesExt {
+    /// Remember the locales requested by a client, replacing the ones
+    /// previously requested
+    #[must_use]
+    fn set_ui_locales(self, ui_locales: &[LanguageTag]) -> Self;
+
+    /// Get the locales last requested by a client, in order of preference
+    fn ui_locales(&self) -> Vec<DataLocale>;
+}
+
+impl UiLocalesExt for CookieJar {
+    fn set_ui_locales(self, ui_locales: &[LanguageTag]) -> Self {
+        let ui_locales: Vec<&str> = ui_locales.iter().map(LanguageTag::as_str).collect();
+        self.save(UI_LOCALES_COOKIE, &ui_locales, false)
+    }
+
+    fn ui_locales(&self) -> Vec<DataLocale> {
+        let ui_locales: Vec<String> = self
+            .load(UI_LOCALES_COOKIE)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        ui_locales
+            .iter()
+            .filter_map(|tag| tag.parse().ok())
+            .collect()
+    }
+}
+
+/// Add the locales a locale should fall back to, on top of the locale itself
+fn with_aliases(lang: DataLocale) -> Vec<DataLocale> {
+    // XXX: this is hacky as we may want to actually maintain proper language
+    // aliases at some point, but `zh-CN` doesn't fallback
+    // automatically to `zh-Hans`, so we insert it manually here.
+    // For some reason, `zh-TW` does fallback to `zh-Hant` correctly.
+    if lang == locale!("zh-CN").into() {
+        vec![lang, locale!("zh-Hans").into()]
+    } else {
+        vec![lang]
+    }
+}
+
+/// Choose the best locale out of the ones requested, in order of preference
+pub(crate) fn choose_locale(
+    translator: &Translator,
+    requested: impl IntoIterator<Item = DataLocale>,
+) -> DataLocale {
+    translator.choose_locale(requested.into_iter().flat_map(with_aliases))
+}
+
 pub struct PreferredLanguage(pub DataLocale);
 
 #[async_trait]
@@ -30,31 +92,26 @@ impl<S> FromRequestParts<S> for PreferredLanguage
 where
     S: Send + Sync,
     Arc<Translator>: FromRef<S>,
+    CookieManager: FromRef<S>,
 {
     type Rejection = Infallible;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let translator: Arc<Translator> = FromRef::from_ref(state);
+        let cookie_jar = CookieJar::from_request_parts(parts, state).await?;
         let accept_language: Option<TypedHeader<AcceptLanguage>> =
             FromRequestParts::from_request_parts(parts, state).await?;
 
-        let iter = accept_language
+        // The locales requested by the client take precedence over the ones
+        // the browser sends
+        let accept_language = accept_language
             .iter()
             .flat_map(|TypedHeader(accept_language)| accept_language.iter())
-            .flat_map(|lang| {
-                let lang = DataLocale::from(lang);
-                // XXX: this is hacky as we may want to actually maintain proper language
-                // aliases at some point, but `zh-CN` doesn't fallback
-                // automatically to `zh-Hans`, so we insert it manually here.
-                // For some reason, `zh-TW` does fallback to `zh-Hant` correctly.
-                if lang == locale!("zh-CN").into() {
-                    vec![lang, locale!("zh-Hans").into()]
-                } else {
-                    vec![lang]
-                }
-            });
-
-        let locale = translator.choose_locale(iter);
+            .map(DataLocale::from)
+            .collect::<Vec<_>>();
+        let requested = cookie_jar.ui_locales().into_iter().chain(accept_language);
+
+        let locale = choose_locale(&translator, requested);
 
         Ok(PreferredLanguage(locale))
     }
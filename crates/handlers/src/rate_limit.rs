@@ -0,0 +1,659 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rate limiting of sensitive operations, like password logins or account
+//! recovery.
+//!
+//! The state of the rate limiters is kept in memory by default. Deployments
+//! running multiple instances of the service can share it through Redis, so
+//! that adding instances doesn't multiply the limits.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use axum::response::{IntoResponseParts, ResponseParts};
+use chrono::{DateTime, Duration, Utc};
+use hyper::header::RETRY_AFTER;
+use mas_storage::Clock;
+use redis::{aio::ConnectionManager, RedisError, Script};
+use thiserror::Error;
+use ulid::Ulid;
+use url::Url;
+
+/// Above this number of buckets, the in-memory backend will evict the buckets
+/// which are full again
+const IN_MEMORY_CLEANUP_THRESHOLD: usize = 10_000;
+
+/// Prefix of the keys used by the Redis backend
+const REDIS_KEY_PREFIX: &str = "mas:rate_limit:";
+
+/// How often clients are allowed to poll the token endpoint for a pending
+/// device code grant
+pub(crate) static DEVICE_CODE_POLL_INTERVAL: Duration = Duration::microseconds(5 * 1000 * 1000);
+
+/// The parameters of a single rate limiter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    /// The maximum number of requests which can be done in a burst
+    pub burst: NonZeroU32,
+
+    /// The number of requests per second allowed once the burst is exhausted
+    pub per_second: f64,
+}
+
+impl Quota {
+    /// The time it takes to refill a single cell of the bucket
+    fn emission_interval(&self) -> Duration {
+        let micros = (1_000_000.0 / self.per_second).ceil();
+        // The cast saturates for very low rates
+        #[allow(clippy::cast_possible_truncation)]
+        Duration::microseconds(micros as i64)
+    }
+
+    /// How far in the future the theoretical arrival time can be while still
+    /// accepting requests
+    fn tolerance(&self) -> Duration {
+        self.emission_interval()
+            .checked_mul(i32::try_from(self.burst.get() - 1).unwrap_or(i32::MAX))
+            .unwrap_or(Duration::max_value())
+    }
+}
+
+/// The configuration of all the rate limiters
+#[derive(Debug, Clone, Copy)]
+pub struct LimiterConfig {
+    /// Password login attempts, per requester IP address
+    pub login_per_ip: Quota,
+
+    /// Password login attempts, per username
+    pub login_per_account: Quota,
+
+    /// Registrations, per requester IP address
+    pub registration_per_ip: Quota,
+
+    /// Account recovery attempts, per requester IP address
+    pub account_recovery_per_ip: Quota,
+
+    /// Account recovery attempts, per email address
+    pub account_recovery_per_address: Quota,
+
+    /// Token requests, per requester IP address
+    pub token_per_ip: Quota,
+
+    /// Token requests, per client
+    pub token_per_client: Quota,
+
+    /// Backchannel authentication requests, per client
+    pub backchannel_authentication_per_client: Quota,
+
+    /// Backchannel authentication requests, per targeted user
+    pub backchannel_authentication_per_user: Quota,
+}
+
+/// Returned when an operation was denied by a rate limiter
+#[derive(Debug, Error, Clone, Copy)]
+#[error("rate limited, retry in {retry_after}")]
+pub struct RateLimited {
+    retry_after: Duration,
+}
+
+impl RateLimited {
+    /// How long the requester should wait before trying again
+    #[must_use]
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// How long the requester should wait before trying again, rounded up to
+    /// the next second
+    #[must_use]
+    pub fn retry_after_secs(&self) -> i64 {
+        let secs = self.retry_after.num_seconds();
+        if self.retry_after > Duration::seconds(secs) {
+            secs + 1
+        } else {
+            secs
+        }
+    }
+}
+
+impl IntoResponseParts for RateLimited {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.headers_mut()
+            .insert(RETRY_AFTER, self.retry_after_secs().into());
+        Ok(res)
+    }
+}
+
+/// A storage backend for the rate limiters
+///
+/// Buckets are tracked using the Generic Cell Rate Algorithm, which only
+/// requires storing a single timestamp per key: the theoretical arrival time
+/// of the next request.
+#[async_trait]
+pub trait RateLimiterBackend: std::fmt::Debug + Send + Sync {
+    /// Take a single cell from the bucket identified by `key`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bucket is empty
+    async fn take(&self, key: &str, quota: Quota, now: DateTime<Utc>) -> Result<(), RateLimited>;
+}
+
+/// A rate limiter backend which keeps its state in memory.
+///
+/// The state is not shared between multiple instances of the service.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    buckets: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl InMemoryBackend {
+    /// Create a new, empty, in-memory backend
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimiterBackend for InMemoryBackend {
+    async fn take(&self, key: &str, quota: Quota, now: DateTime<Utc>) -> Result<(), RateLimited> {
+        let interval = quota.emission_interval();
+        let tolerance = quota.tolerance();
+
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+
+        if buckets.len() > IN_MEMORY_CLEANUP_THRESHOLD {
+            // Buckets with a theoretical arrival time in the past are full again,
+            // so we don't need to remember them
+            buckets.retain(|_, tat| *tat > now);
+        }
+
+        let tat = buckets.get(key).copied().unwrap_or(now).max(now);
+        // Very low rates can push the timestamps out of range, so saturate them
+        let allow_at = tat
+            .checked_sub_signed(tolerance)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        if now < allow_at {
+            return Err(RateLimited {
+                retry_after: allow_at - now,
+            });
+        }
+
+        let next_tat = tat
+            .checked_add_signed(interval)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        buckets.insert(key.to_owned(), next_tat);
+        Ok(())
+    }
+}
+
+/// The GCRA, run atomically by Redis. Timestamps are in microseconds. It
+/// returns 0 if the request is allowed, or how long to wait otherwise.
+const REDIS_GCRA_SCRIPT: &str = r"
+local now = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local tolerance = tonumber(ARGV[3])
+
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then
+    tat = now
+end
+
+local allow_at = tat - tolerance
+if now < allow_at then
+    return allow_at - now
+end
+
+local new_tat = tat + interval
+local ttl = math.ceil((new_tat - now) / 1000)
+redis.call('SET', KEYS[1], string.format('%d', new_tat), 'PX', ttl)
+return 0
+";
+
+/// A rate limiter backend which keeps its state in Redis, so that it is
+/// shared between all the instances of the service.
+///
+/// Keys expire once their bucket is full again. If Redis can't be reached,
+/// requests are let through, so that an outage of Redis doesn't lock
+/// everyone out.
+#[derive(Clone)]
+pub struct RedisBackend {
+    connection: ConnectionManager,
+    script: Arc<Script>,
+}
+
+impl std::fmt::Debug for RedisBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisBackend").finish_non_exhaustive()
+    }
+}
+
+impl RedisBackend {
+    /// Connect to the Redis server at the given URI
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URI is invalid or if the server can't be
+    /// reached
+    pub async fn connect(uri: &Url) -> Result<Self, RedisError> {
+        let client = redis::Client::open(uri.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self {
+            connection,
+            script: Arc::new(Script::new(REDIS_GCRA_SCRIPT)),
+        })
+    }
+}
+
+#[async_trait]
+impl RateLimiterBackend for RedisBackend {
+    async fn take(&self, key: &str, quota: Quota, now: DateTime<Utc>) -> Result<(), RateLimited> {
+        let mut connection = self.connection.clone();
+        let res: Result<i64, RedisError> = self
+            .script
+            .key(format!("{REDIS_KEY_PREFIX}{key}"))
+            .arg(now.timestamp_micros())
+            .arg(
+                quota
+                    .emission_interval()
+                    .num_microseconds()
+                    .unwrap_or(i64::MAX),
+            )
+            .arg(quota.tolerance().num_microseconds().unwrap_or(i64::MAX))
+            .invoke_async(&mut connection)
+            .await;
+
+        match res {
+            Ok(0) => Ok(()),
+            Ok(micros) => Err(RateLimited {
+                retry_after: Duration::microseconds(micros),
+            }),
+            Err(e) => {
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to reach Redis, not enforcing the rate limit"
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Rate limits sensitive operations
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    backend: Arc<dyn RateLimiterBackend>,
+    config: LimiterConfig,
+}
+
+impl Limiter {
+    /// Create a new limiter, storing its state in memory
+    #[must_use]
+    pub fn new(config: LimiterConfig) -> Self {
+        Self::with_backend(config, Arc::new(InMemoryBackend::new()))
+    }
+
+    /// Create a new limiter, using the given backend to store its state
+    #[must_use]
+    pub fn with_backend(config: LimiterConfig, backend: Arc<dyn RateLimiterBackend>) -> Self {
+        Self { backend, config }
+    }
+
+    pub(crate) async fn take(
+        &self,
+        clock: &dyn Clock,
+        kind: &str,
+        key: impl std::fmt::Display + Send,
+        quota: Quota,
+    ) -> Result<(), RateLimited> {
+        let key = format!("{kind}:{key}");
+        let res = self.backend.take(&key, quota, clock.now()).await;
+        if let Err(e) = &res {
+            tracing::info!(rate_limit.kind = kind, "{e}");
+        }
+        res
+    }
+
+    /// Check a password login attempt
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either the requester or the account are rate
+    /// limited
+    pub async fn check_password(
+        &self,
+        clock: &dyn Clock,
+        requester: Option<IpAddr>,
+        username: &str,
+    ) -> Result<(), RateLimited> {
+        if let Some(ip) = requester {
+            self.take(clock, "login.ip", ip, self.config.login_per_ip)
+                .await?;
+        }
+
+        self.take(
+            clock,
+            "login.account",
+            username,
+            self.config.login_per_account,
+        )
+        .await
+    }
+
+    /// Check a registration attempt
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the requester is rate limited
+    pub async fn check_registration(
+        &self,
+        clock: &dyn Clock,
+        requester: Option<IpAddr>,
+    ) -> Result<(), RateLimited> {
+        let Some(ip) = requester else {
+            return Ok(());
+        };
+
+        self.take(
+            clock,
+            "registration.ip",
+            ip,
+            self.config.registration_per_ip,
+        )
+        .await
+    }
+
+    /// Check an account recovery attempt
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either the requester or the email address are rate
+    /// limited
+    pub async fn check_account_recovery(
+        &self,
+        clock: &dyn Clock,
+        requester: Option<IpAddr>,
+        email: &str,
+    ) -> Result<(), RateLimited> {
+        if let Some(ip) = requester {
+            self.take(
+                clock,
+                "account_recovery.ip",
+                ip,
+                self.config.account_recovery_per_ip,
+            )
+            .await?;
+        }
+
+        // Email addresses are case-insensitive
+        self.take(
+            clock,
+            "account_recovery.address",
+            email.to_lowercase(),
+            self.config.account_recovery_per_address,
+        )
+        .await
+    }
+
+    /// Check a request to the token endpoint, before the client is
+    /// authenticated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the requester is rate limited
+    pub async fn check_token_requester(
+        &self,
+        clock: &dyn Clock,
+        requester: Option<IpAddr>,
+    ) -> Result<(), RateLimited> {
+        let Some(ip) = requester else {
+            return Ok(());
+        };
+
+        self.take(clock, "token.ip", ip, self.config.token_per_ip)
+            .await
+    }
+
+    /// Check a request to the token endpoint, once the client is
+    /// authenticated. This must not be called before, else anyone could
+    /// exhaust the bucket of a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client is rate limited
+    pub async fn check_token_client(
+        &self,
+        clock: &dyn Clock,
+        client_id: Ulid,
+    ) -> Result<(), RateLimited> {
+        self.take(
+            clock,
+            "token.client",
+            client_id,
+            self.config.token_per_client,
+        )
+        .await
+    }
+
+    /// Check a backchannel authentication request, once the client is
+    /// authenticated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either the client or the targeted user are rate
+    /// limited
+    pub async fn check_backchannel_authentication(
+        &self,
+        clock: &dyn Clock,
+        client_id: Ulid,
+        user_id: Ulid,
+    ) -> Result<(), RateLimited> {
+        self.take(
+            clock,
+            "backchannel_authentication.client",
+            client_id,
+            self.config.backchannel_authentication_per_client,
+        )
+        .await?;
+
+        self.take(
+            clock,
+            "backchannel_authentication.user",
+            user_id,
+            self.config.backchannel_authentication_per_user,
+        )
+        .await
+    }
+
+    /// Check a poll of the token endpoint for a pending device code grant
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client polled more often than the advertised
+    /// interval
+    pub async fn check_device_code_poll(
+        &self,
+        clock: &dyn Clock,
+        grant_id: Ulid,
+    ) -> Result<(), RateLimited> {
+        #[allow(clippy::cast_precision_loss)]
+        let interval = DEVICE_CODE_POLL_INTERVAL.num_seconds() as f64;
+        let quota = Quota {
+            burst: NonZeroU32::MIN,
+            per_second: 1.0 / interval,
+        };
+
+        self.take(clock, "device_code.poll", grant_id, quota).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::clock::MockClock;
+
+    use super::*;
+
+    fn quota(burst: u32, per_second: f64) -> Quota {
+        Quota {
+            burst: NonZeroU32::new(burst).unwrap(),
+            per_second,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_backend() {
+        let clock = MockClock::default();
+        let backend = InMemoryBackend::new();
+        let quota = quota(3, 1.0);
+
+        // The first three requests go through
+        for _ in 0..3 {
+            backend.take("key", quota, clock.now()).await.unwrap();
+        }
+
+        // The fourth one is rate limited for a second
+        let err = backend.take("key", quota, clock.now()).await.unwrap_err();
+        assert_eq!(err.retry_after(), Duration::seconds(1));
+        assert_eq!(err.retry_after_secs(), 1);
+
+        // Other keys are not affected
+        backend.take("other", quota, clock.now()).await.unwrap();
+
+        // After a second, one more request is allowed
+        clock.advance(Duration::seconds(1));
+        backend.take("key", quota, clock.now()).await.unwrap();
+        backend.take("key", quota, clock.now()).await.unwrap_err();
+
+        // After a long time, the bucket is full again
+        clock.advance(Duration::hours(1));
+        for _ in 0..3 {
+            backend.take("key", quota, clock.now()).await.unwrap();
+        }
+        backend.take("key", quota, clock.now()).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_backend_low_rate() {
+        let clock = MockClock::default();
+        let backend = InMemoryBackend::new();
+
+        // Rates this low saturate the timestamps instead of overflowing
+        let large = quota(u32::MAX, f64::MIN_POSITIVE);
+        backend.take("key", large, clock.now()).await.unwrap();
+        backend.take("key", large, clock.now()).await.unwrap();
+
+        let single = quota(1, f64::MIN_POSITIVE);
+        backend.take("other", single, clock.now()).await.unwrap();
+        backend
+            .take("other", single, clock.now())
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_limiter_password() {
+        let clock = MockClock::default();
+        let config = LimiterConfig {
+            login_per_ip: quota(2, 1.0 / 60.0),
+            login_per_account: quota(2, 1.0 / 60.0),
+            registration_per_ip: quota(1, 1.0),
+            account_recovery_per_ip: quota(1, 1.0),
+            account_recovery_per_address: quota(1, 1.0),
+            token_per_ip: quota(1, 1.0),
+            token_per_client: quota(1, 1.0),
+            backchannel_authentication_per_client: quota(1, 1.0),
+            backchannel_authentication_per_user: quota(1, 1.0),
+        };
+        let limiter = Limiter::new(config);
+
+        let ip1 = Some(IpAddr::from([192, 0, 2, 1]));
+        let ip2 = Some(IpAddr::from([192, 0, 2, 2]));
+
+        // The first IP can try twice
+        limiter.check_password(&clock, ip1, "alice").await.unwrap();
+        limiter.check_password(&clock, ip1, "bob").await.unwrap();
+        let err = limiter
+            .check_password(&clock, ip1, "alice")
+            .await
+            .unwrap_err();
+        assert_eq!(err.retry_after_secs(), 60);
+
+        // Another IP can still try, until the account itself is limited
+        limiter.check_password(&clock, ip2, "alice").await.unwrap();
+        limiter
+            .check_password(&clock, ip2, "alice")
+            .await
+            .unwrap_err();
+
+        // Requests without a known IP are only limited per account
+        limiter.check_password(&clock, None, "bob").await.unwrap();
+        limiter
+            .check_password(&clock, None, "bob")
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_limiter_device_code_poll() {
+        let clock = MockClock::default();
+        let limiter = Limiter::new(crate::test_utils::test_limiter_config());
+        let grant = Ulid::nil();
+
+        limiter.check_device_code_poll(&clock, grant).await.unwrap();
+        let err = limiter
+            .check_device_code_poll(&clock, grant)
+            .await
+            .unwrap_err();
+        assert_eq!(err.retry_after(), DEVICE_CODE_POLL_INTERVAL);
+
+        clock.advance(DEVICE_CODE_POLL_INTERVAL);
+        limiter.check_device_code_poll(&clock, grant).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_limiter_backchannel_authentication() {
+        let clock = MockClock::default();
+        let limiter = Limiter::new(crate::test_utils::test_limiter_config());
+        let client = Ulid::from_parts(0, 1);
+        let alice = Ulid::from_parts(0, 2);
+        let bob = Ulid::from_parts(0, 3);
+
+        // A single user can only be prompted a few times in a row
+        for _ in 0..3 {
+            limiter
+                .check_backchannel_authentication(&clock, client, alice)
+                .await
+                .unwrap();
+        }
+        limiter
+            .check_backchannel_authentication(&clock, client, alice)
+            .await
+            .unwrap_err();
+
+        // Other users can still be prompted
+        limiter
+            .check_backchannel_authentication(&clock, client, bob)
+            .await
+            .unwrap();
+    }
+}
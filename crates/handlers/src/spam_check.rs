@@ -0,0 +1,309 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Check registrations and authorizations against an external anti-abuse
+//! service
+//!
+//! The service is called over HTTP with a JSON description of the request,
+//! and replies with a verdict, with the same semantics as the registration
+//! checks of the Synapse spam-checker modules: the request is either allowed,
+//! denied, or allowed but the user is shadow-banned on the homeserver.
+
+use std::{net::IpAddr, sync::Arc};
+
+use axum::BoxError;
+use headers::{Authorization, HeaderMapExt};
+use hyper::{body::Bytes, Request, Response};
+use mas_axum_utils::http_client_factory::HttpClientFactory;
+use mas_data_model::{BrowserSession, Client, UserAgent};
+use mas_http::HttpServiceExt;
+use oauth2_types::scope::Scope;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{Service, ServiceExt};
+use url::Url;
+
+use crate::BoundActivityTracker;
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("invalid bearer token for the anti-abuse service")]
+    InvalidToken,
+
+    #[error("failed to build the request to the anti-abuse service")]
+    Request(#[from] hyper::http::Error),
+
+    #[error("the anti-abuse service returned an error")]
+    RequestFailed(#[source] BoxError),
+}
+
+/// What to do with a request, as decided by the anti-abuse service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Let the request through
+    Allow,
+
+    /// Reject the request
+    Deny,
+
+    /// Let the request through, but shadow-ban the user, both in this service
+    /// and on the homeserver
+    ShadowBan,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+enum Check<'a> {
+    Registration {
+        username: &'a str,
+        email: Option<&'a str>,
+        auth_provider_id: &'a str,
+    },
+    Authorization {
+        username: &'a str,
+        client_id: &'a str,
+        scope: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct CheckRequest<'a> {
+    #[serde(flatten)]
+    check: Check<'a>,
+    ip: Option<IpAddr>,
+    user_agent: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckResponse {
+    verdict: Verdict,
+}
+
+struct Inner {
+    http_client_factory: HttpClientFactory,
+    url: Url,
+    token: Option<String>,
+    allow_on_error: bool,
+}
+
+/// Checks registrations and authorizations against an external anti-abuse
+/// service, if one is configured
+#[derive(Clone, Default)]
+pub struct SpamChecker {
+    inner: Option<Arc<Inner>>,
+}
+
+impl std::fmt::Debug for SpamChecker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpamChecker")
+            .field("url", &self.inner.as_ref().map(|inner| &inner.url))
+            .finish()
+    }
+}
+
+impl SpamChecker {
+    /// Create a checker which allows everything
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Create a checker calling the anti-abuse service at the given URL
+    ///
+    /// # Parameters
+    ///
+    /// * `http_client_factory`: The factory of the HTTP client used to call the
+    ///   service
+    /// * `url`: The URL of the endpoint
+    /// * `token`: An optional bearer token to authenticate with
+    /// * `allow_on_error`: Whether to allow the requests if the service can't
+    ///   be reached or fails
+    #[must_use]
+    pub fn new(
+        http_client_factory: HttpClientFactory,
+        url: Url,
+        token: Option<String>,
+        allow_on_error: bool,
+    ) -> Self {
+        Self {
+            inner: Some(Arc::new(Inner {
+                http_client_factory,
+                url,
+                token,
+                allow_on_error,
+            })),
+        }
+    }
+
+    /// Check a new registration
+    ///
+    /// `auth_provider_id` is `password` for password-based registrations, and
+    /// the ID of the upstream provider for registrations through one.
+    pub async fn check_registration(
+        &self,
+        activity_tracker: &BoundActivityTracker,
+        user_agent: Option<&UserAgent>,
+        username: &str,
+        email: Option<&str>,
+        auth_provider_id: &str,
+    ) -> Verdict {
+        self.check(CheckRequest {
+            check: Check::Registration {
+                username,
+                email,
+                auth_provider_id,
+            },
+            ip: activity_tracker.ip(),
+            user_agent: user_agent.map(|ua| ua.raw.as_str()),
+        })
+        .await
+    }
+
+    /// Check the authorization of a client on behalf of the user of a browser
+    /// session
+    pub async fn check_authorization(
+        &self,
+        activity_tracker: &BoundActivityTracker,
+        browser_session: &BrowserSession,
+        client: &Client,
+        scope: &Scope,
+    ) -> Verdict {
+        self.check(CheckRequest {
+            check: Check::Authorization {
+                username: &browser_session.user.username,
+                client_id: &client.client_id,
+                scope: scope.to_string(),
+            },
+            ip: activity_tracker.ip(),
+            user_agent: browser_session
+                .user_agent
+                .as_ref()
+                .map(|ua| ua.raw.as_str()),
+        })
+        .await
+    }
+
+    async fn check(&self, request: CheckRequest<'_>) -> Verdict {
+        let Some(inner) = &self.inner else {
+            return Verdict::Allow;
+        };
+
+        match inner.call(&request).await {
+            Ok(verdict) => {
+                if verdict != Verdict::Allow {
+                    tracing::info!(?verdict, "The anti-abuse service flagged a request");
+                }
+
+                verdict
+            }
+
+            Err(e) => {
+                tracing::error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to call the anti-abuse service"
+                );
+
+                if inner.allow_on_error {
+                    Verdict::Allow
+                } else {
+                    Verdict::Deny
+                }
+            }
+        }
+    }
+}
+
+impl Inner {
+    #[tracing::instrument(
+        name = "spam_check.call",
+        skip_all,
+        fields(
+            "otel.kind" = "client",
+            url.full = %self.url,
+        ),
+        err,
+    )]
+    async fn call(&self, body: &CheckRequest<'_>) -> Result<Verdict, Error> {
+        let mut request = Request::post(self.url.as_str()).body(body)?;
+
+        if let Some(token) = &self.token {
+            let authorization = Authorization::bearer(token).map_err(|_| Error::InvalidToken)?;
+            request.headers_mut().typed_insert(authorization);
+        }
+
+        let client = self
+            .http_client_factory
+            .client("spam_check")
+            .request_bytes_to_body()
+            .json_request()
+            .response_body_to_bytes()
+            .catch_http_errors(|response: Response<Bytes>| response.status())
+            .json_response::<CheckResponse>()
+            .map_err(|e| Error::RequestFailed(e.into()));
+
+        let response = client.ready_oneshot().await?.call(request).await?;
+
+        Ok(response.into_body().verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_request() {
+        let request = CheckRequest {
+            check: Check::Registration {
+                username: "alice",
+                email: Some("alice@example.com"),
+                auth_provider_id: "password",
+            },
+            ip: Some("192.0.2.1".parse().unwrap()),
+            user_agent: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "check": "registration",
+                "username": "alice",
+                "email": "alice@example.com",
+                "auth_provider_id": "password",
+                "ip": "192.0.2.1",
+                "user_agent": null,
+            })
+        );
+
+        let response: CheckResponse =
+            serde_json::from_value(serde_json::json!({ "verdict": "shadow_ban" })).unwrap();
+        assert_eq!(response.verdict, Verdict::ShadowBan);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let checker = SpamChecker::disabled();
+        let request = CheckRequest {
+            check: Check::Authorization {
+                username: "alice",
+                client_id: "client",
+                scope: "openid".to_owned(),
+            },
+            ip: None,
+            user_agent: None,
+        };
+
+        assert_eq!(checker.check(request).await, Verdict::Allow);
+    }
+}
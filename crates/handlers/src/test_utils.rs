@@ -14,6 +14,7 @@
 
 use std::{
     convert::Infallible,
+    num::NonZeroU32,
     sync::{Arc, Mutex, RwLock},
     task::{Context, Poll},
 };
@@ -29,7 +30,7 @@ use cookie_store::{CookieStore, RawCookie};
 use futures_util::future::BoxFuture;
 use headers::{Authorization, ContentType, HeaderMapExt, HeaderName, HeaderValue};
 use hyper::{
-    header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+    header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
     Request, Response, StatusCode,
 };
 use mas_axum_utils::{
@@ -37,7 +38,7 @@ use mas_axum_utils::{
     http_client_factory::HttpClientFactory,
     ErrorWrapper,
 };
-use mas_data_model::SiteConfig;
+use mas_data_model::{DataRetentionConfig, SecurityNotificationsConfig, SiteConfig};
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, JsonWebKey, JsonWebKeySet, Keystore, PrivateKey};
 use mas_matrix::{BoxHomeserverConnection, HomeserverConnection, MockHomeserverConnection};
@@ -55,9 +56,12 @@ use url::Url;
 
 use crate::{
     graphql,
+    oauth2::logo::ClientLogoCache,
     passwords::{Hasher, PasswordManager},
+    rate_limit::{LimiterConfig, Quota},
     upstream_oauth2::cache::MetadataCache,
-    ActivityTracker, BoundActivityTracker,
+    ActivityTracker, BoundActivityTracker, DocumentCache, ForwardedPrincipal, GeoIp,
+    IntrospectionCache, Limiter, LoginRisk, NetworkBans, SpamChecker,
 };
 
 // This might fail if it's not the first time it's being called, which is fine,
@@ -99,6 +103,8 @@ pub(crate) struct TestState {
     pub key_store: Keystore,
     pub cookie_manager: CookieManager,
     pub metadata_cache: MetadataCache,
+    pub client_logo_cache: ClientLogoCache,
+    pub document_cache: DocumentCache,
     pub encrypter: Encrypter,
     pub url_builder: UrlBuilder,
     pub homeserver_connection: Arc<MockHomeserverConnection>,
@@ -108,6 +114,12 @@ pub(crate) struct TestState {
     pub password_manager: PasswordManager,
     pub site_config: SiteConfig,
     pub activity_tracker: ActivityTracker,
+    pub limiter: Limiter,
+    pub network_bans: NetworkBans,
+    pub spam_checker: SpamChecker,
+    pub login_risk: LoginRisk,
+    pub introspection_cache: IntrospectionCache,
+    pub geoip: GeoIp,
     pub clock: Arc<MockClock>,
     pub rng: Arc<Mutex<ChaChaRng>>,
 }
@@ -125,16 +137,54 @@ pub fn test_site_config() -> SiteConfig {
         access_token_ttl: Duration::try_minutes(5).unwrap(),
         compat_token_ttl: Duration::try_minutes(5).unwrap(),
         server_name: "example.com".to_owned(),
+        service_name: None,
+        logo_uri: None,
+        primary_color: None,
+        accent_color: None,
+        custom_css: None,
         policy_uri: Some("https://example.com/policy".parse().unwrap()),
         tos_uri: Some("https://example.com/tos".parse().unwrap()),
         imprint: None,
+        announcement: None,
         password_login_enabled: true,
         password_registration_enabled: true,
         email_change_allowed: true,
         displayname_change_allowed: true,
         password_change_allowed: true,
         account_recovery_allowed: true,
+        login_alerts_enabled: true,
+        email_otp_enabled: false,
+        magic_link_login_allowed: true,
+        guest_registration_allowed: true,
+        public_clients_allowed: true,
+        offline_access_required: false,
+        browser_session_idle_timeout: None,
+        browser_session_max_lifetime: None,
+        sensitive_action_reauth_ttl: None,
+        data_retention: DataRetentionConfig::default(),
+        security_notifications: SecurityNotificationsConfig::default(),
         captcha: None,
+        spnego_login: None,
+        client_certificate_login: None,
+    }
+}
+
+pub fn test_limiter_config() -> LimiterConfig {
+    let quota = |burst, per_second| Quota {
+        burst: NonZeroU32::new(burst).unwrap(),
+        per_second,
+    };
+
+    LimiterConfig {
+        login_per_ip: quota(3, 3.0 / 60.0),
+        login_per_account: quota(1800, 1800.0 / 3600.0),
+        registration_per_ip: quota(3, 3.0 / 3600.0),
+        account_recovery_per_ip: quota(3, 3.0 / 3600.0),
+        account_recovery_per_address: quota(3, 1.0 / 3600.0),
+        token_per_ip: quota(100, 10.0),
+        token_per_client: quota(500, 50.0),
+        backchannel_authentication_per_client: quota(100, 1.0),
+        backchannel_authentication_per_user: quota(3, 3.0 / 3600.0),
     }
 }
 
@@ -155,6 +205,7 @@ impl TestState {
 
         let templates = Templates::load(
             workspace_root.join("templates"),
+            None,
             url_builder.clone(),
             workspace_root.join("frontend/dist/manifest.json"),
             workspace_root.join("translations"),
@@ -163,18 +214,24 @@ impl TestState {
         )
         .await?;
 
-        // TODO: add more test keys to the store
         let rsa =
             PrivateKey::load_pem(include_str!("../../keystore/tests/keys/rsa.pkcs1.pem")).unwrap();
         let rsa = JsonWebKey::new(rsa).with_kid("test-rsa");
 
-        let jwks = JsonWebKeySet::new(vec![rsa]);
+        let ec_p256 =
+            PrivateKey::load_pem(include_str!("../../keystore/tests/keys/ec-p256.pkcs8.pem"))
+                .unwrap();
+        let ec_p256 = JsonWebKey::new(ec_p256).with_kid("test-ec-p256");
+
+        let jwks = JsonWebKeySet::new(vec![rsa, ec_p256]);
         let key_store = Keystore::new(jwks);
 
         let encrypter = Encrypter::new(&[0x42; 32]);
         let cookie_manager = CookieManager::derive_from(url_builder.http_base(), &[0x42; 32]);
 
         let metadata_cache = MetadataCache::new();
+        let client_logo_cache = ClientLogoCache::new();
+        let document_cache = DocumentCache::new();
 
         let password_manager = if site_config.password_login_enabled {
             PasswordManager::new([(1, Hasher::argon2id(None))])?
@@ -192,6 +249,12 @@ impl TestState {
         let clock = Arc::new(MockClock::default());
         let rng = Arc::new(Mutex::new(ChaChaRng::seed_from_u64(42)));
 
+        let introspection_cache = IntrospectionCache::disabled();
+        let geoip = GeoIp::disabled();
+
+        let limiter = Limiter::new(test_limiter_config());
+        let network_bans = NetworkBans::new(limiter.clone(), None);
+
         let graphql_state = TestGraphQLState {
             pool: pool.clone(),
             policy_factory: Arc::clone(&policy_factory),
@@ -200,6 +263,13 @@ impl TestState {
             rng: Arc::clone(&rng),
             clock: Arc::clone(&clock),
             password_manager: password_manager.clone(),
+            introspection_cache: introspection_cache.clone(),
+            geoip: geoip.clone(),
+            encrypter: encrypter.clone(),
+            key_store: key_store.clone(),
+            http_client_factory: http_client_factory.clone(),
+            metadata_cache: metadata_cache.clone(),
+            network_bans: network_bans.clone(),
         };
         let state: crate::graphql::BoxState = Box::new(graphql_state);
 
@@ -214,6 +284,8 @@ impl TestState {
             key_store,
             cookie_manager,
             metadata_cache,
+            client_logo_cache,
+            document_cache,
             encrypter,
             url_builder,
             homeserver_connection,
@@ -223,6 +295,12 @@ impl TestState {
             password_manager,
             site_config,
             activity_tracker,
+            limiter,
+            network_bans,
+            spam_checker: SpamChecker::disabled(),
+            login_risk: LoginRisk::disabled(),
+            introspection_cache,
+            geoip,
             clock,
             rng,
         })
@@ -242,6 +320,9 @@ impl TestState {
             .merge(crate::compat_router())
             .merge(crate::human_router(self.templates.clone()))
             .merge(crate::graphql_router(false))
+            .merge(crate::openapi_router(false))
+            .merge(crate::admin_router())
+            .fallback(crate::fallback)
             .with_state(self.clone());
 
         // Both unwrap are on Infallible, so this is safe
@@ -266,6 +347,31 @@ impl TestState {
         Response::from_parts(parts, body)
     }
 
+    /// Follow the redirects to pages of the service, carrying the cookies
+    /// along, and return the first response which isn't one.
+    ///
+    /// Redirects to other origins, like the redirect URI of a client, are not
+    /// followed.
+    pub async fn follow_redirects(
+        &self,
+        cookies: &CookieHelper,
+        mut response: Response<String>,
+    ) -> Response<String> {
+        while response.status().is_redirection() {
+            let location = response.location();
+            if !location.starts_with('/') {
+                break;
+            }
+
+            let request = Request::get(location).empty();
+            let request = cookies.with_cookies(request);
+            response = self.request(request).await;
+            cookies.save_cookies(&response);
+        }
+
+        response
+    }
+
     pub async fn repository(&self) -> Result<BoxRepository, DatabaseError> {
         let repo = PgRepository::from_pool(&self.pool).await?;
         Ok(repo
@@ -317,6 +423,13 @@ struct TestGraphQLState {
     clock: Arc<MockClock>,
     rng: Arc<Mutex<ChaChaRng>>,
     password_manager: PasswordManager,
+    introspection_cache: IntrospectionCache,
+    geoip: GeoIp,
+    encrypter: Encrypter,
+    key_store: Keystore,
+    http_client_factory: HttpClientFactory,
+    metadata_cache: MetadataCache,
+    network_bans: NetworkBans,
 }
 
 #[async_trait]
@@ -351,6 +464,34 @@ impl graphql::State for TestGraphQLState {
         &self.site_config
     }
 
+    fn introspection_cache(&self) -> &IntrospectionCache {
+        &self.introspection_cache
+    }
+
+    fn geoip(&self) -> &GeoIp {
+        &self.geoip
+    }
+
+    fn encrypter(&self) -> &Encrypter {
+        &self.encrypter
+    }
+
+    fn key_store(&self) -> &Keystore {
+        &self.key_store
+    }
+
+    fn http_client_factory(&self) -> &HttpClientFactory {
+        &self.http_client_factory
+    }
+
+    fn metadata_cache(&self) -> &MetadataCache {
+        &self.metadata_cache
+    }
+
+    fn network_bans(&self) -> &NetworkBans {
+        &self.network_bans
+    }
+
     fn rng(&self) -> BoxRng {
         let mut parent_rng = self.rng.lock().expect("Failed to lock RNG");
         let rng = ChaChaRng::from_rng(&mut *parent_rng).expect("Failed to seed RNG");
@@ -418,6 +559,18 @@ impl FromRef<TestState> for CookieManager {
     }
 }
 
+impl FromRef<TestState> for ClientLogoCache {
+    fn from_ref(input: &TestState) -> Self {
+        input.client_logo_cache.clone()
+    }
+}
+
+impl FromRef<TestState> for DocumentCache {
+    fn from_ref(input: &TestState) -> Self {
+        input.document_cache.clone()
+    }
+}
+
 impl FromRef<TestState> for MetadataCache {
     fn from_ref(input: &TestState) -> Self {
         input.metadata_cache.clone()
@@ -430,6 +583,42 @@ impl FromRef<TestState> for SiteConfig {
     }
 }
 
+impl FromRef<TestState> for Limiter {
+    fn from_ref(input: &TestState) -> Self {
+        input.limiter.clone()
+    }
+}
+
+impl FromRef<TestState> for NetworkBans {
+    fn from_ref(input: &TestState) -> Self {
+        input.network_bans.clone()
+    }
+}
+
+impl FromRef<TestState> for SpamChecker {
+    fn from_ref(input: &TestState) -> Self {
+        input.spam_checker.clone()
+    }
+}
+
+impl FromRef<TestState> for LoginRisk {
+    fn from_ref(input: &TestState) -> Self {
+        input.login_risk.clone()
+    }
+}
+
+impl FromRef<TestState> for IntrospectionCache {
+    fn from_ref(input: &TestState) -> Self {
+        input.introspection_cache.clone()
+    }
+}
+
+impl FromRef<TestState> for GeoIp {
+    fn from_ref(input: &TestState) -> Self {
+        input.geoip.clone()
+    }
+}
+
 impl FromRef<TestState> for BoxHomeserverConnection {
     fn from_ref(input: &TestState) -> Self {
         Box::new(input.homeserver_connection.clone())
@@ -453,14 +642,41 @@ impl FromRequestParts<TestState> for BoundActivityTracker {
     type Rejection = Infallible;
 
     async fn from_request_parts(
-        _parts: &mut axum::http::request::Parts,
+        parts: &mut axum::http::request::Parts,
         state: &TestState,
     ) -> Result<Self, Self::Rejection> {
-        let ip = None;
+        // Requests in tests don't have a peer address, so the client address is
+        // taken from the `X-Forwarded-For` header, as set by a trusted proxy
+        let ip = parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
         Ok(state.activity_tracker.clone().bind(ip))
     }
 }
 
+#[async_trait]
+impl FromRequestParts<TestState> for ForwardedPrincipal {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &TestState,
+    ) -> Result<Self, Self::Rejection> {
+        // Requests in tests don't have a peer address, so they are all
+        // considered as coming from the trusted reverse proxy
+        let principal = state.site_config.spnego_login.as_ref().and_then(|config| {
+            parts
+                .headers
+                .get(config.principal_header.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        });
+        Ok(Self(principal))
+    }
+}
+
 #[async_trait]
 impl FromRequestParts<TestState> for BoxClock {
     type Rejection = Infallible;
@@ -593,6 +809,20 @@ pub(crate) trait ResponseExt {
     /// Panics if the response is missing the `Content-Type: application/json`,
     /// or if the body is not valid JSON.
     fn json<T: DeserializeOwned>(&self) -> T;
+
+    /// Get the value of the `Location` header of a redirect response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the response doesn't have a valid `Location` header.
+    fn location(&self) -> &str;
+
+    /// Get the CSRF token from the forms of an HTML page.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page doesn't have a CSRF token.
+    fn csrf_token(&self) -> &str;
 }
 
 impl ResponseExt for Response<String> {
@@ -629,6 +859,24 @@ impl ResponseExt for Response<String> {
         self.assert_header_value(CONTENT_TYPE, "application/json");
         serde_json::from_str(self.body()).expect("JSON deserialization failed")
     }
+
+    #[track_caller]
+    fn location(&self) -> &str {
+        self.headers()
+            .get(LOCATION)
+            .expect("Missing Location header")
+            .to_str()
+            .expect("Invalid Location header")
+    }
+
+    #[track_caller]
+    fn csrf_token(&self) -> &str {
+        self.body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("Missing CSRF token")
+    }
 }
 
 /// A helper for storing and retrieving cookies in tests.
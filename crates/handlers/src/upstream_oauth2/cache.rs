@@ -12,16 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
 
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     UpstreamOAuthProvider, UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderPkceMode,
 };
 use mas_http::HttpService;
 use mas_iana::oauth::PkceCodeChallengeMethod;
-use mas_oidc_client::error::DiscoveryError;
-use mas_storage::{upstream_oauth2::UpstreamOAuthProviderRepository, RepositoryAccess};
+use mas_jose::{constraints::Constrainable, jwk::PublicJsonWebKeySet};
+use mas_oidc_client::error::{DiscoveryError, JwksError};
+use mas_storage::{upstream_oauth2::UpstreamOAuthProviderRepository, Clock, RepositoryAccess};
 use oauth2_types::oidc::VerifiedProviderMetadata;
+use opentelemetry::{metrics::Counter, KeyValue};
 use tokio::sync::RwLock;
 use url::Url;
 
@@ -147,6 +153,7 @@ impl<'a> LazyProviderInfos<'a> {
 pub struct MetadataCache {
     cache: Arc<RwLock<HashMap<String, Arc<VerifiedProviderMetadata>>>>,
     insecure_cache: Arc<RwLock<HashMap<String, Arc<VerifiedProviderMetadata>>>>,
+    jwks: JwksCache,
 }
 
 impl MetadataCache {
@@ -155,6 +162,12 @@ impl MetadataCache {
         Self::default()
     }
 
+    /// Get the cache of the upstream providers JWKS
+    #[must_use]
+    pub fn jwks(&self) -> &JwksCache {
+        &self.jwks
+    }
+
     /// Warm up the cache by fetching all the known providers from the database
     /// and inserting them into the cache.
     ///
@@ -277,14 +290,169 @@ impl MetadataCache {
     }
 }
 
+/// How long a JWKS is considered fresh before being fetched again
+const JWKS_TTL: Duration = Duration::minutes(15);
+
+/// The minimum time between two fetches of the same JWKS, so that tokens signed
+/// with an unknown key don't make us hammer the provider
+const JWKS_REFRESH_COOLDOWN: Duration = Duration::minutes(1);
+
+fn jwks_refresh_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = opentelemetry::global::meter_with_version(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            Some(opentelemetry_semantic_conventions::SCHEMA_URL),
+            None,
+        );
+
+        meter
+            .u64_counter("mas.upstream_oauth2.jwks.refresh")
+            .with_description("The number of times the JWKS of an upstream provider was fetched")
+            .with_unit(opentelemetry::metrics::Unit::new("{refresh}"))
+            .init()
+    })
+}
+
+#[derive(Debug)]
+struct CachedJwks {
+    jwks: Arc<PublicJsonWebKeySet>,
+    fetched_at: DateTime<Utc>,
+    last_attempt_at: DateTime<Utc>,
+}
+
+/// A cache of the upstream providers JWKS, keyed by their URI
+///
+/// Entries are fetched again once they are older than [`JWKS_TTL`], or earlier
+/// if a token is signed with a key which isn't in the cached set, which
+/// happens when the provider rotates its keys. If fetching fails, the
+/// previously known keys are used until the provider is reachable again.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Default)]
+pub struct JwksCache {
+    cache: Arc<std::sync::RwLock<HashMap<Url, CachedJwks>>>,
+}
+
+impl JwksCache {
+    /// Get the JWKS at the given URI, fetching it if needed.
+    ///
+    /// If `kid` is set and isn't part of the cached JWKS, it is fetched again,
+    /// unless it was already fetched recently.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JWKS is not in the cache and fetching it failed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache lock was poisoned
+    #[tracing::instrument(name = "jwks_cache.get", fields(%jwks_uri), skip_all, err)]
+    pub async fn get(
+        &self,
+        http_service: &HttpService,
+        clock: &impl Clock,
+        jwks_uri: &Url,
+        kid: Option<&str>,
+    ) -> Result<Arc<PublicJsonWebKeySet>, JwksError> {
+        let now = clock.now();
+
+        let cached = self
+            .cache
+            .read()
+            .unwrap()
+            .get(jwks_uri)
+            .map(|entry| (entry.jwks.clone(), entry.fetched_at, entry.last_attempt_at));
+
+        let Some((jwks, fetched_at, last_attempt_at)) = cached else {
+            return self.fetch(http_service, now, jwks_uri, "miss").await;
+        };
+
+        let has_kid = kid.map_or(true, |kid| jwks.iter().any(|key| key.kid() == Some(kid)));
+        let fresh = now - fetched_at < JWKS_TTL;
+        if (fresh && has_kid) || now - last_attempt_at < JWKS_REFRESH_COOLDOWN {
+            return Ok(jwks);
+        }
+
+        let reason = if has_kid { "expired" } else { "unknown_kid" };
+        match self.fetch(http_service, now, jwks_uri, reason).await {
+            Ok(jwks) => Ok(jwks),
+            Err(e) => {
+                tracing::warn!(
+                    %jwks_uri,
+                    error = &e as &dyn std::error::Error,
+                    "Failed to refresh the provider JWKS, using the previously known keys"
+                );
+                Ok(jwks)
+            }
+        }
+    }
+
+    async fn fetch(
+        &self,
+        http_service: &HttpService,
+        now: DateTime<Utc>,
+        jwks_uri: &Url,
+        reason: &'static str,
+    ) -> Result<Arc<PublicJsonWebKeySet>, JwksError> {
+        let result = mas_oidc_client::requests::jose::fetch_jwks(http_service, jwks_uri).await;
+
+        jwks_refresh_counter().add(
+            1,
+            &[
+                KeyValue::new("reason", reason),
+                KeyValue::new("result", if result.is_ok() { "success" } else { "failure" }),
+            ],
+        );
+
+        let mut cache = self.cache.write().unwrap();
+        match result {
+            Ok(jwks) => {
+                let jwks = Arc::new(jwks);
+                cache.insert(
+                    jwks_uri.clone(),
+                    CachedJwks {
+                        jwks: jwks.clone(),
+                        fetched_at: now,
+                        last_attempt_at: now,
+                    },
+                );
+                Ok(jwks)
+            }
+            Err(e) => {
+                // Record the attempt so that we don't retry on every request
+                if let Some(entry) = cache.get_mut(jwks_uri) {
+                    entry.last_attempt_at = now;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Get how long ago each cached JWKS was successfully fetched
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache lock was poisoned
+    #[must_use]
+    pub fn ages(&self, now: DateTime<Utc>) -> Vec<(Url, Duration)> {
+        self.cache
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(jwks_uri, entry)| (jwks_uri.clone(), now - entry.fetched_at))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::too_many_lines)]
 
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
     use hyper::{body::Bytes, Request, Response, StatusCode};
-    use mas_data_model::UpstreamOAuthProviderClaimsImports;
+    use mas_data_model::{UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderOnDeprovision};
     use mas_http::BoxCloneSyncService;
     use mas_iana::oauth::OAuthClientAuthenticationMethod;
     use mas_storage::{clock::MockClock, Clock};
@@ -507,6 +675,8 @@ mod tests {
             disabled_at: None,
             claims_imports: UpstreamOAuthProviderClaimsImports::default(),
             additional_authorization_parameters: Vec::new(),
+            store_tokens: false,
+            on_deprovision: UpstreamOAuthProviderOnDeprovision::Ignore,
         };
 
         // Without any override, it should just use discovery
@@ -626,4 +796,103 @@ mod tests {
             assert_eq!(calls.load(Ordering::SeqCst), 3);
         }
     }
+
+    #[tokio::test]
+    async fn test_jwks_cache() {
+        init_tracing();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let key_version = Arc::new(AtomicUsize::new(0));
+        let failing = Arc::new(AtomicBool::new(false));
+        let closure_calls = Arc::clone(&calls);
+        let closure_key_version = Arc::clone(&key_version);
+        let closure_failing = Arc::clone(&failing);
+        let handler = move |_req: Request<Bytes>| {
+            let calls = Arc::clone(&closure_calls);
+            let key_version = Arc::clone(&closure_key_version);
+            let failing = Arc::clone(&closure_failing);
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+
+                if failing.load(Ordering::SeqCst) {
+                    let mut response = Response::new(Bytes::default());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    return Ok::<_, BoxError>(response);
+                }
+
+                let body = match key_version.load(Ordering::SeqCst) {
+                    0 => Bytes::from_static(
+                        br#"{"keys": [{"kty": "RSA", "kid": "first", "n": "AQAB", "e": "AQAB"}]}"#,
+                    ),
+                    _ => Bytes::from_static(
+                        br#"{"keys": [{"kty": "RSA", "kid": "second", "n": "AQAB", "e": "AQAB"}]}"#,
+                    ),
+                };
+
+                let mut response = Response::new(body);
+                *response.status_mut() = StatusCode::OK;
+                Ok::<_, BoxError>(response)
+            }
+        };
+
+        let clock = MockClock::default();
+        let service = BoxCloneSyncService::new(tower::service_fn(handler));
+        let jwks_uri = Url::parse("https://valid.example.com/jwks").unwrap();
+        let cache = JwksCache::default();
+
+        // The first call fetches the JWKS
+        let jwks = cache.get(&service, &clock, &jwks_uri, None).await.unwrap();
+        assert_eq!(jwks.len(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Known keys are served from the cache
+        cache
+            .get(&service, &clock, &jwks_uri, Some("first"))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The provider rotates its keys
+        key_version.store(1, Ordering::SeqCst);
+
+        // An unknown key doesn't trigger a refresh right after a fetch
+        let jwks = cache
+            .get(&service, &clock, &jwks_uri, Some("second"))
+            .await
+            .unwrap();
+        assert_eq!(jwks[0].kid(), Some("first"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // But it does once the cooldown is over, even if the JWKS is still fresh
+        clock.advance(Duration::minutes(2));
+        let jwks = cache
+            .get(&service, &clock, &jwks_uri, Some("second"))
+            .await
+            .unwrap();
+        assert_eq!(jwks[0].kid(), Some("second"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Once expired, the JWKS is fetched again, and the previous keys are kept if
+        // that fails
+        failing.store(true, Ordering::SeqCst);
+        clock.advance(Duration::minutes(20));
+        let jwks = cache.get(&service, &clock, &jwks_uri, None).await.unwrap();
+        assert_eq!(jwks[0].kid(), Some("second"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // The failed attempt doesn't get retried immediately
+        cache.get(&service, &clock, &jwks_uri, None).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // The age reflects the last successful fetch
+        let ages = cache.ages(clock.now());
+        assert_eq!(ages, vec![(jwks_uri.clone(), Duration::minutes(20))]);
+
+        // An unknown JWKS fails if it can't be fetched
+        let other_uri = Url::parse("https://other.example.com/jwks").unwrap();
+        cache
+            .get(&service, &clock, &other_uri, None)
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
 }
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use axum::{
     extract::{Path, Query, State},
     response::IntoResponse,
@@ -21,9 +23,11 @@ use mas_axum_utils::{
     cookies::CookieJar, http_client_factory::HttpClientFactory, sentry::SentryEventID,
 };
 use mas_data_model::UpstreamOAuthProvider;
+use mas_jose::jwt::Jwt;
 use mas_keystore::{Encrypter, Keystore};
-use mas_oidc_client::requests::{
-    authorization_code::AuthorizationValidationData, jose::JwtVerificationData,
+use mas_oidc_client::{
+    error::TokenAuthorizationCodeError,
+    requests::{authorization_code::AuthorizationValidationData, jose::JwtVerificationData},
 };
 use mas_router::UrlBuilder;
 use mas_storage::{
@@ -31,6 +35,7 @@ use mas_storage::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
+    user::UserRepository,
     BoxClock, BoxRepository, BoxRng, Clock,
 };
 use oauth2_types::errors::ClientErrorCode;
@@ -39,8 +44,8 @@ use thiserror::Error;
 use ulid::Ulid;
 
 use super::{
-    cache::LazyProviderInfos, client_credentials_for_provider, template::environment,
-    UpstreamSessionsCookie,
+    cache::LazyProviderInfos, client_credentials_for_provider, groups::sync_groups,
+    template::environment, tokens::save_tokens, UpstreamSessionsCookie,
 };
 use crate::{impl_from_error_for_route, upstream_oauth2::cache::MetadataCache};
 
@@ -111,6 +116,8 @@ impl_from_error_for_route!(mas_oidc_client::error::JwksError);
 impl_from_error_for_route!(mas_oidc_client::error::TokenAuthorizationCodeError);
 impl_from_error_for_route!(super::ProviderCredentialsError);
 impl_from_error_for_route!(super::cookie::UpstreamSessionNotFound);
+impl_from_error_for_route!(super::tokens::UpstreamTokenError);
+impl_from_error_for_route!(super::groups::GroupsSyncError);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
@@ -197,11 +204,6 @@ pub(crate) async fn get(
     let http_service = http_client_factory.http_service("upstream_oauth2.callback");
     let mut lazy_metadata = LazyProviderInfos::new(&metadata_cache, &provider, &http_service);
 
-    // Fetch the JWKS
-    let jwks =
-        mas_oidc_client::requests::jose::fetch_jwks(&http_service, lazy_metadata.jwks_uri().await?)
-            .await?;
-
     // Figure out the client credentials
     let client_credentials = client_credentials_for_provider(
         &provider,
@@ -220,6 +222,41 @@ pub(crate) async fn get(
         redirect_uri,
     };
 
+    // The ID token is verified after the exchange, as we need to know which key
+    // it was signed with to figure out if the cached JWKS is still valid
+    let (response, _) =
+        mas_oidc_client::requests::authorization_code::access_token_with_authorization_code(
+            &http_service,
+            client_credentials,
+            lazy_metadata.token_endpoint().await?,
+            code.clone(),
+            validation_data,
+            None,
+            clock.now(),
+            &mut rng,
+        )
+        .await?;
+
+    let raw_id_token = response
+        .id_token
+        .as_deref()
+        .ok_or(RouteError::MissingIDToken)?;
+
+    // If the token can't be decoded, the verification below will fail anyway
+    let kid = Jwt::<HashMap<String, serde_json::Value>>::try_from(raw_id_token)
+        .ok()
+        .and_then(|jwt| jwt.header().kid().map(ToOwned::to_owned));
+
+    let jwks = metadata_cache
+        .jwks()
+        .get(
+            &http_service,
+            &clock,
+            lazy_metadata.jwks_uri().await?,
+            kid.as_deref(),
+        )
+        .await?;
+
     let id_token_verification_data = JwtVerificationData {
         issuer: &provider.issuer,
         jwks: &jwks,
@@ -228,20 +265,17 @@ pub(crate) async fn get(
         client_id: &provider.client_id,
     };
 
-    let (response, id_token) =
-        mas_oidc_client::requests::authorization_code::access_token_with_authorization_code(
-            &http_service,
-            client_credentials,
-            lazy_metadata.token_endpoint().await?,
-            code,
-            validation_data,
-            Some(id_token_verification_data),
+    let id_token =
+        mas_oidc_client::requests::authorization_code::verify_authorization_code_id_token(
+            &response,
+            &code,
+            &session.nonce,
+            id_token_verification_data,
             clock.now(),
-            &mut rng,
         )
-        .await?;
+        .map_err(TokenAuthorizationCodeError::from)?;
 
-    let (_header, id_token) = id_token.ok_or(RouteError::MissingIDToken)?.into_parts();
+    let (_header, id_token) = id_token.into_parts();
 
     let env = {
         let mut env = environment();
@@ -277,6 +311,21 @@ pub(crate) async fn get(
             .await?
     };
 
+    if provider.store_tokens {
+        save_tokens(
+            &mut repo, &mut rng, &clock, &encrypter, &link, &response, None,
+        )
+        .await?;
+    }
+
+    // If the link is already associated with a user, this is a login: re-sync the
+    // groups of the user from the fresh claims
+    if let Some(user_id) = link.user_id {
+        if let Some(user) = repo.user().lookup(user_id).await? {
+            sync_groups(&mut repo, &provider, user, &id_token).await?;
+        }
+    }
+
     let session = repo
         .upstream_oauth_session()
         .complete_with_link(&clock, session, &link, response.id_token)
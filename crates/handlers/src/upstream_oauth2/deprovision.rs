@@ -0,0 +1,306 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Receive security event tokens pushed by upstream providers when an upstream
+//! identity was disabled or deleted, as per the RISC profile of the OpenID
+//! Shared Signals Framework, and lock the matching local users.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use hyper::StatusCode;
+use mas_axum_utils::{http_client_factory::HttpClientFactory, sentry::SentryEventID};
+use mas_data_model::{UpstreamOAuthProvider, UpstreamOAuthProviderOnDeprovision};
+use mas_jose::jwt::Jwt;
+use mas_oidc_client::{
+    error::JwtVerificationError,
+    requests::jose::{verify_signed_jwt, JwtVerificationData},
+};
+use mas_storage::{
+    job::{DeactivateUserJob, JobRepositoryExt},
+    upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository},
+    user::UserRepository,
+    BoxClock, BoxRepository, RepositoryAccess,
+};
+use serde_json::Value;
+use thiserror::Error;
+use tracing::info;
+use ulid::Ulid;
+
+use super::cache::{LazyProviderInfos, MetadataCache};
+use crate::{impl_from_error_for_route, IntrospectionCache};
+
+const ACCOUNT_DISABLED: &str =
+    "https://schemas.openid.net/secevent/risc/event-type/account-disabled";
+const ACCOUNT_PURGED: &str = "https://schemas.openid.net/secevent/risc/event-type/account-purged";
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error("Provider not found")]
+    ProviderNotFound,
+
+    #[error("Invalid security event token")]
+    InvalidToken(#[source] JwtVerificationError),
+
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_oidc_client::error::DiscoveryError);
+impl_from_error_for_route!(mas_oidc_client::error::JwksError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let event_id = sentry::capture_error(&self);
+        let response = match self {
+            Self::ProviderNotFound => (StatusCode::NOT_FOUND, "Provider not found").into_response(),
+            Self::InvalidToken(_) => (StatusCode::BAD_REQUEST, self.to_string()).into_response(),
+            Self::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+
+        (SentryEventID::from(event_id), response).into_response()
+    }
+}
+
+/// Find the upstream subjects which were disabled or deleted in the claims of
+/// a security event token
+///
+/// The subject is looked up in the event itself, and falls back to the `sub`
+/// claim of the token. Other events are ignored.
+fn deprovisioned_subjects(claims: &HashMap<String, Value>) -> Vec<String> {
+    let Some(Value::Object(events)) = claims.get("events") else {
+        return Vec::new();
+    };
+
+    let mut subjects: Vec<String> = [ACCOUNT_DISABLED, ACCOUNT_PURGED]
+        .into_iter()
+        .filter_map(|event_type| events.get(event_type))
+        .filter_map(|event| {
+            event
+                .pointer("/subject/sub")
+                .or_else(|| claims.get("sub"))
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+        })
+        .collect();
+
+    subjects.dedup();
+    subjects
+}
+
+#[tracing::instrument(
+    name = "handlers.upstream_oauth2.deprovision.post",
+    fields(upstream_oauth_provider.id = %provider_id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn post(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(http_client_factory): State<HttpClientFactory>,
+    State(metadata_cache): State<MetadataCache>,
+    State(introspection_cache): State<IntrospectionCache>,
+    Path(provider_id): Path<Ulid>,
+    body: String,
+) -> Result<impl IntoResponse, RouteError> {
+    // Providers which don't deprovision users are not advertised as accepting
+    // security events
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(provider_id)
+        .await?
+        .filter(UpstreamOAuthProvider::enabled)
+        .filter(|provider| provider.on_deprovision != UpstreamOAuthProviderOnDeprovision::Ignore)
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    let http_service = http_client_factory.http_service("upstream_oauth2.deprovision");
+    let mut lazy_metadata = LazyProviderInfos::new(&metadata_cache, &provider, &http_service);
+
+    // If the token can't be decoded, the verification below will fail anyway
+    let kid = Jwt::<HashMap<String, Value>>::try_from(body.as_str())
+        .ok()
+        .and_then(|jwt| jwt.header().kid().map(ToOwned::to_owned));
+
+    let jwks = metadata_cache
+        .jwks()
+        .get(
+            &http_service,
+            &clock,
+            lazy_metadata.jwks_uri().await?,
+            kid.as_deref(),
+        )
+        .await?;
+
+    let token = verify_signed_jwt(
+        &body,
+        JwtVerificationData {
+            issuer: &provider.issuer,
+            jwks: &jwks,
+            client_id: &provider.client_id,
+            // TODO: make that configurable
+            signing_algorithm: &mas_iana::jose::JsonWebSignatureAlg::Rs256,
+        },
+    )
+    .map_err(RouteError::InvalidToken)?;
+
+    let (_header, claims) = token.into_parts();
+
+    for subject in deprovisioned_subjects(&claims) {
+        let Some(link) = repo
+            .upstream_oauth_link()
+            .find_by_subject(&provider, &subject)
+            .await?
+        else {
+            continue;
+        };
+
+        let Some(user_id) = link.user_id else {
+            continue;
+        };
+
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            continue;
+        };
+
+        // The provider may send the same event multiple times
+        if user.locked_at.is_some() {
+            continue;
+        }
+
+        info!(
+            user.id = %user.id,
+            upstream_oauth_link.id = %link.id,
+            "Upstream identity is gone, locking the user"
+        );
+        let user = repo.user().lock(&clock, user).await?;
+
+        if provider.on_deprovision == UpstreamOAuthProviderOnDeprovision::Deactivate {
+            repo.job()
+                .schedule_job(DeactivateUserJob::new(&user, false))
+                .await?;
+        }
+
+        introspection_cache.invalidate_user(user.id).await;
+    }
+
+    repo.save().await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_iana::oauth::OAuthClientAuthenticationMethod;
+    use mas_router::Route;
+    use mas_storage::upstream_oauth2::UpstreamOAuthProviderParams;
+    use oauth2_types::scope::{Scope, OPENID};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{init_tracing, ResponseExt, TestState};
+
+    #[test]
+    fn test_deprovisioned_subjects() {
+        let claims: HashMap<String, Value> = serde_json::from_value(json!({
+            "sub": "fallback",
+            "events": {
+                ACCOUNT_DISABLED: {
+                    "subject": {
+                        "subject_type": "iss_sub",
+                        "iss": "https://example.com/",
+                        "sub": "alice",
+                    },
+                    "reason": "hijacking",
+                },
+                ACCOUNT_PURGED: {},
+                "https://schemas.openid.net/secevent/risc/event-type/account-enabled": {
+                    "subject": {
+                        "subject_type": "iss_sub",
+                        "iss": "https://example.com/",
+                        "sub": "bob",
+                    },
+                },
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            deprovisioned_subjects(&claims),
+            vec!["alice".to_owned(), "fallback".to_owned()]
+        );
+
+        let claims: HashMap<String, Value> =
+            serde_json::from_value(json!({ "sub": "alice" })).unwrap();
+        assert!(deprovisioned_subjects(&claims).is_empty());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_deprovisioning_disabled(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // Unknown providers don't accept security events
+        let request =
+            Request::post(&*mas_router::UpstreamOAuth2Deprovision::new(Ulid::nil()).path())
+                .body("invalid".to_owned())
+                .unwrap();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+
+        // Neither do providers which ignore them
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: "https://example.com/".to_owned(),
+                    human_name: None,
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: OAuthClientAuthenticationMethod::None,
+                    token_endpoint_signing_alg: None,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: mas_data_model::UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    additional_authorization_parameters: Vec::new(),
+                    store_tokens: false,
+                    on_deprovision: UpstreamOAuthProviderOnDeprovision::Ignore,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request =
+            Request::post(&*mas_router::UpstreamOAuth2Deprovision::new(provider.id).path())
+                .body("invalid".to_owned())
+                .unwrap();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
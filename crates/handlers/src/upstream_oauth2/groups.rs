@@ -0,0 +1,123 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use mas_data_model::{UpstreamOAuthProvider, User};
+use mas_storage::{user::UserRepository, BoxRepository, RepositoryAccess, RepositoryError};
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum GroupsSyncError {
+    #[error("Required claim {claim:?} is missing or is not a list of groups")]
+    MissingClaim { claim: String },
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Extract the list of groups from the upstream claims
+///
+/// The claim can either be a list of strings or a single string. Returns
+/// `None` if the claim is missing or has another shape.
+fn extract_groups(claims: &HashMap<String, Value>, claim: &str) -> Option<Vec<String>> {
+    let mut groups: Vec<String> = match claims.get(claim)? {
+        Value::String(group) => vec![group.clone()],
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map(ToOwned::to_owned))
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+
+    groups.sort_unstable();
+    groups.dedup();
+    Some(groups)
+}
+
+/// Sync the groups of a user from the upstream claims, according to the
+/// provider configuration, and update their admin flag if it is derived from
+/// the groups
+///
+/// # Errors
+///
+/// Returns an error if the claim is required but missing, or if the
+/// repository fails
+pub(crate) async fn sync_groups(
+    repo: &mut BoxRepository,
+    provider: &UpstreamOAuthProvider,
+    mut user: User,
+    claims: &HashMap<String, Value>,
+) -> Result<User, GroupsSyncError> {
+    let preference = &provider.claims_imports.groups;
+    if preference.ignore() {
+        return Ok(user);
+    }
+
+    let Some(groups) = extract_groups(claims, preference.claim()) else {
+        if preference.is_required() {
+            return Err(GroupsSyncError::MissingClaim {
+                claim: preference.claim().to_owned(),
+            });
+        }
+
+        tracing::warn!(
+            upstream_oauth_provider.id = %provider.id,
+            claim = preference.claim(),
+            "Groups claim is missing or invalid, keeping the current groups"
+        );
+        return Ok(user);
+    };
+
+    if user.groups != groups {
+        user = repo.user().set_groups(user, groups).await?;
+    }
+
+    if let Some(is_admin) = preference.is_admin(&user.groups) {
+        if user.can_request_admin != is_admin {
+            user = repo.user().set_can_request_admin(user, is_admin).await?;
+        }
+    }
+
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_groups() {
+        let claims: HashMap<String, Value> = serde_json::from_value(json!({
+            "groups": ["staff", "admins", "staff"],
+            "role": "admin",
+            "invalid": ["staff", 42],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            extract_groups(&claims, "groups"),
+            Some(vec!["admins".to_owned(), "staff".to_owned()])
+        );
+        assert_eq!(
+            extract_groups(&claims, "role"),
+            Some(vec!["admin".to_owned()])
+        );
+        assert_eq!(extract_groups(&claims, "invalid"), None);
+        assert_eq!(extract_groups(&claims, "missing"), None);
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use axum::{
     extract::{Path, State},
     response::{Html, IntoResponse, Response},
@@ -30,7 +32,7 @@ use mas_matrix::BoxHomeserverConnection;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{
-    job::{JobRepositoryExt, ProvisionUserJob},
+    job::{CheckLoginJob, JobRepositoryExt, ProvisionUserJob},
     upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthSessionRepository},
     user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
     BoxClock, BoxRepository, BoxRng, RepositoryAccess,
@@ -45,9 +47,12 @@ use thiserror::Error;
 use tracing::warn;
 use ulid::Ulid;
 
-use super::{template::environment, UpstreamSessionsCookie};
+use super::{groups::sync_groups, template::environment, UpstreamSessionsCookie};
 use crate::{
-    impl_from_error_for_route, views::shared::OptionalPostAuthAction, PreferredLanguage, SiteConfig,
+    impl_from_error_for_route,
+    spam_check::{SpamChecker, Verdict},
+    views::shared::OptionalPostAuthAction,
+    BoundActivityTracker, GeoIp, PreferredLanguage, SiteConfig,
 };
 
 const DEFAULT_LOCALPART_TEMPLATE: &str = "{{ user.preferred_username }}";
@@ -98,19 +103,26 @@ pub(crate) enum RouteError {
     #[error("Homeserver connection error")]
     HomeserverConnection(#[source] anyhow::Error),
 
+    #[error(transparent)]
+    Csrf(#[from] mas_axum_utils::csrf::CsrfError),
+
     #[error(transparent)]
     Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 impl_from_error_for_route!(mas_templates::TemplateError);
-impl_from_error_for_route!(mas_axum_utils::csrf::CsrfError);
 impl_from_error_for_route!(super::cookie::UpstreamSessionNotFound);
 impl_from_error_for_route!(mas_storage::RepositoryError);
 impl_from_error_for_route!(mas_policy::EvaluationError);
 impl_from_error_for_route!(mas_jose::jwt::JwtDecodeError);
+impl_from_error_for_route!(super::groups::GroupsSyncError);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
+        if let Self::Csrf(e) = self {
+            return FancyError::csrf(e).into_response();
+        }
+
         let event_id = sentry::capture_error(&self);
         let response = match self {
             Self::LinkNotFound => (StatusCode::NOT_FOUND, "Link not found").into_response(),
@@ -198,9 +210,12 @@ pub(crate) async fn get(
     mut repo: BoxRepository,
     mut policy: Policy,
     PreferredLanguage(locale): PreferredLanguage,
+    State(site_config): State<SiteConfig>,
+    State(geoip): State<GeoIp>,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
     State(homeserver): State<BoxHomeserverConnection>,
+    activity_tracker: BoundActivityTracker,
     cookie_jar: CookieJar,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     Path(link_id): Path<Ulid>,
@@ -319,6 +334,16 @@ pub(crate) async fn get(
                 .save(cookie_jar, &clock);
             cookie_jar = cookie_jar.set_session(&session);
 
+            if site_config.login_alerts_enabled {
+                repo.job()
+                    .schedule_job(
+                        CheckLoginJob::new(&session, activity_tracker.ip())
+                            .with_location(activity_tracker.ip().and_then(|ip| geoip.lookup(ip)))
+                            .with_language(locale.to_string()),
+                    )
+                    .await?;
+            }
+
             repo.save().await?;
 
             post_auth_action.go_next(&url_builder).into_response()
@@ -491,6 +516,8 @@ pub(crate) async fn post(
     State(homeserver): State<BoxHomeserverConnection>,
     State(url_builder): State<UrlBuilder>,
     State(site_config): State<SiteConfig>,
+    State(spam_checker): State<SpamChecker>,
+    activity_tracker: BoundActivityTracker,
     Path(link_id): Path<Ulid>,
     Form(form): Form<ProtectedForm<FormData>>,
 ) -> Result<Response, RouteError> {
@@ -773,24 +800,59 @@ pub(crate) async fn post(
                     .into_response());
             }
 
+            let verdict = spam_checker
+                .check_registration(
+                    &activity_tracker,
+                    user_agent.as_ref(),
+                    &username,
+                    email.as_deref(),
+                    &provider.id.to_string(),
+                )
+                .await;
+
+            if verdict == Verdict::Deny {
+                let form_state = form_state.with_error_on_form(FormError::Denied);
+
+                let ctx = ctx
+                    .with_form_state(form_state)
+                    .with_csrf(csrf_token.form_value())
+                    .with_language(locale);
+                return Ok((
+                    cookie_jar,
+                    Html(templates.render_upstream_oauth2_do_register(&ctx)?),
+                )
+                    .into_response());
+            }
+
             // Now we can create the user
             let user = repo.user().add(&mut rng, &clock, username).await?;
 
+            let user = if verdict == Verdict::ShadowBan {
+                repo.user().shadow_ban(&clock, user).await?
+            } else {
+                user
+            };
+
             if let Some(terms_url) = &site_config.tos_uri {
                 repo.user_terms()
                     .accept_terms(&mut rng, &clock, &user, terms_url.clone())
                     .await?;
             }
 
-            // And schedule the job to provision it
-            let mut job = ProvisionUserJob::new(&user);
+            // If we have a display name, store it on the user, so that it is set
+            // during provisioning
+            let user = if let Some(name) = display_name {
+                repo.user().set_display_name(user, Some(name)).await?
+            } else {
+                user
+            };
 
-            // If we have a display name, set it during provisioning
-            if let Some(name) = display_name {
-                job = job.set_display_name(name);
+            // And schedule the job to provision it
+            let mut provision_job = ProvisionUserJob::new(&user);
+            if verdict == Verdict::ShadowBan {
+                provision_job = provision_job.shadow_ban();
             }
-
-            repo.job().schedule_job(job).await?;
+            repo.job().schedule_job(provision_job).await?;
 
             // If we have an email, add it to the user
             if let Some(email) = email {
@@ -826,6 +888,22 @@ pub(crate) async fn post(
         _ => return Err(RouteError::InvalidFormAction),
     };
 
+    // The user is now associated with the link, import their groups
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound)?;
+
+    let claims = upstream_session
+        .id_token()
+        .map(Jwt::<'_, HashMap<String, serde_json::Value>>::try_from)
+        .transpose()?
+        .map(|id_token| id_token.into_parts().1)
+        .unwrap_or_default();
+
+    sync_groups(&mut repo, &provider, session.user.clone(), &claims).await?;
+
     let upstream_session = repo
         .upstream_oauth_session()
         .consume(&clock, upstream_session)
@@ -849,7 +927,8 @@ pub(crate) async fn post(
 mod tests {
     use hyper::{header::CONTENT_TYPE, Request, StatusCode};
     use mas_data_model::{
-        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderImportPreference,
+        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderGroupsPreference,
+        UpstreamOAuthProviderImportPreference,
     };
     use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
     use mas_jose::jwt::{JsonWebSignatureHeader, Jwt};
@@ -879,6 +958,11 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
                 template: None,
             },
+            groups: UpstreamOAuthProviderGroupsPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                claim: Some("roles".to_owned()),
+                admin_groups: vec!["mas-admins".to_owned()],
+            },
             ..UpstreamOAuthProviderClaimsImports::default()
         };
 
@@ -886,6 +970,7 @@ mod tests {
             "preferred_username": "john",
             "email": "john@example.com",
             "email_verified": true,
+            "roles": ["staff", "mas-admins"],
         });
 
         // Grab a key to sign the id_token
@@ -926,6 +1011,8 @@ mod tests {
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
                     additional_authorization_parameters: Vec::new(),
+                    store_tokens: false,
+                    on_deprovision: mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore,
                 },
             )
             .await
@@ -973,15 +1060,7 @@ mod tests {
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
 
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
             serde_json::json!({
@@ -1023,5 +1102,12 @@ mod tests {
 
         assert_eq!(email.email, "john@example.com");
         assert!(email.confirmed_at.is_some());
+
+        // The groups were imported, and grant the admin flag
+        assert_eq!(
+            user.groups,
+            vec!["mas-admins".to_owned(), "staff".to_owned()]
+        );
+        assert!(user.can_request_admin);
     }
 }
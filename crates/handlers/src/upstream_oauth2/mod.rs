@@ -25,14 +25,17 @@ pub(crate) mod authorize;
 pub(crate) mod cache;
 pub(crate) mod callback;
 mod cookie;
+pub(crate) mod deprovision;
+mod groups;
 pub(crate) mod link;
 mod template;
+pub(crate) mod tokens;
 
 use self::cookie::UpstreamSessions as UpstreamSessionsCookie;
 
 #[derive(Debug, Error)]
 #[allow(clippy::enum_variant_names)]
-enum ProviderCredentialsError {
+pub(crate) enum ProviderCredentialsError {
     #[error("Provider doesn't have a client secret")]
     MissingClientSecret,
 
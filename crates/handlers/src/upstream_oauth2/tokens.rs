@@ -0,0 +1,218 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Handling of the tokens obtained from upstream providers, which are kept for
+//! the providers which have `store_tokens` enabled.
+
+use std::string::FromUtf8Error;
+
+use chrono::{DateTime, Duration, Utc};
+use mas_axum_utils::http_client_factory::HttpClientFactory;
+use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthLinkToken, UpstreamOAuthProvider};
+use mas_keystore::{aead, DecryptError, Encrypter, Keystore};
+use mas_oidc_client::error::{DiscoveryError, TokenRefreshError, TokenRequestError};
+use mas_storage::{
+    upstream_oauth2::UpstreamOAuthLinkRepository, BoxRepository, BoxRng, Clock, RepositoryAccess,
+};
+use oauth2_types::requests::AccessTokenResponse;
+use thiserror::Error;
+
+use super::{
+    cache::{LazyProviderInfos, MetadataCache},
+    client_credentials_for_provider, ProviderCredentialsError,
+};
+
+/// Access tokens expiring within this delay are refreshed before being handed
+/// out, so that they are still valid when they get used
+const EXPIRATION_LEEWAY: Duration = Duration::seconds(30);
+
+#[derive(Debug, Error)]
+pub(crate) enum UpstreamTokenError {
+    #[error("Could not encrypt the upstream token")]
+    Encrypt(#[source] aead::Error),
+
+    #[error("Could not decrypt the upstream token")]
+    Decrypt(#[from] DecryptError),
+
+    #[error("The upstream token is invalid")]
+    InvalidToken(#[from] FromUtf8Error),
+
+    #[error(transparent)]
+    Discovery(#[from] DiscoveryError),
+
+    #[error(transparent)]
+    Credentials(#[from] ProviderCredentialsError),
+
+    #[error(transparent)]
+    Refresh(#[from] TokenRefreshError),
+
+    #[error(transparent)]
+    Repository(#[from] mas_storage::RepositoryError),
+}
+
+/// A usable access token for an upstream provider
+pub(crate) struct UpstreamAccessToken {
+    pub access_token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The outcome of [`fresh_access_token`]
+pub(crate) enum FreshAccessToken {
+    /// A valid access token
+    Valid(UpstreamAccessToken),
+
+    /// No tokens were stored for this link
+    NotStored,
+
+    /// The access token expired and could not be refreshed
+    Expired,
+}
+
+/// Encrypt and save the tokens from a token endpoint response for a link
+///
+/// If the response doesn't have a refresh token, the given previous one is
+/// kept, as providers don't always rotate refresh tokens.
+pub(crate) async fn save_tokens(
+    repo: &mut BoxRepository,
+    rng: &mut BoxRng,
+    clock: &impl Clock,
+    encrypter: &Encrypter,
+    link: &UpstreamOAuthLink,
+    response: &AccessTokenResponse,
+    previous_encrypted_refresh_token: Option<String>,
+) -> Result<UpstreamOAuthLinkToken, UpstreamTokenError> {
+    let encrypted_access_token = encrypter
+        .encrypt_to_string(rng, response.access_token.as_bytes())
+        .map_err(UpstreamTokenError::Encrypt)?;
+
+    let encrypted_refresh_token = match &response.refresh_token {
+        Some(refresh_token) => Some(
+            encrypter
+                .encrypt_to_string(rng, refresh_token.as_bytes())
+                .map_err(UpstreamTokenError::Encrypt)?,
+        ),
+        None => previous_encrypted_refresh_token,
+    };
+
+    let access_token_expires_at = response
+        .expires_in
+        .map(|expires_in| clock.now() + expires_in);
+
+    let token = repo
+        .upstream_oauth_link()
+        .save_token(
+            clock,
+            link,
+            encrypted_access_token,
+            encrypted_refresh_token,
+            access_token_expires_at,
+        )
+        .await?;
+
+    Ok(token)
+}
+
+/// Get a usable access token for a link, refreshing it with the upstream
+/// provider if it expired
+///
+/// The refreshed tokens are saved in the repository, which needs to be saved
+/// by the caller.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn fresh_access_token(
+    repo: &mut BoxRepository,
+    rng: &mut BoxRng,
+    clock: &impl Clock,
+    http_client_factory: &HttpClientFactory,
+    metadata_cache: &MetadataCache,
+    keystore: &Keystore,
+    encrypter: &Encrypter,
+    provider: &UpstreamOAuthProvider,
+    link: &UpstreamOAuthLink,
+) -> Result<FreshAccessToken, UpstreamTokenError> {
+    let token = repo.upstream_oauth_link().lookup_token(link).await?;
+
+    let Some(token) = token else {
+        return Ok(FreshAccessToken::NotStored);
+    };
+
+    if !token.is_access_token_expired(clock.now(), EXPIRATION_LEEWAY) {
+        let access_token = encrypter.decrypt_string(&token.encrypted_access_token)?;
+        return Ok(FreshAccessToken::Valid(UpstreamAccessToken {
+            access_token: String::from_utf8(access_token)?,
+            expires_at: token.access_token_expires_at,
+        }));
+    }
+
+    let Some(encrypted_refresh_token) = token.encrypted_refresh_token else {
+        return Ok(FreshAccessToken::Expired);
+    };
+
+    let refresh_token = encrypter.decrypt_string(&encrypted_refresh_token)?;
+    let refresh_token = String::from_utf8(refresh_token)?;
+
+    let http_service = http_client_factory.http_service("upstream_oauth2.refresh");
+    let mut lazy_metadata = LazyProviderInfos::new(metadata_cache, provider, &http_service);
+    let token_endpoint = lazy_metadata.token_endpoint().await?;
+
+    let client_credentials =
+        client_credentials_for_provider(provider, token_endpoint, keystore, encrypter)?;
+
+    let result = mas_oidc_client::requests::refresh_token::refresh_access_token(
+        &http_service,
+        client_credentials,
+        token_endpoint,
+        refresh_token,
+        None,
+        None,
+        None,
+        clock.now(),
+        rng,
+    )
+    .await;
+
+    let response = match result {
+        Ok((response, _)) => response,
+
+        // The provider rejected the refresh token, so the tokens we have are
+        // useless
+        Err(TokenRefreshError::Token(TokenRequestError::Http(e))) if e.status.is_client_error() => {
+            tracing::warn!(
+                upstream_oauth_link.id = %link.id,
+                error = &e as &dyn std::error::Error,
+                "The upstream provider refused to refresh the access token"
+            );
+
+            repo.upstream_oauth_link().remove_token(link).await?;
+
+            return Ok(FreshAccessToken::Expired);
+        }
+
+        Err(e) => return Err(e.into()),
+    };
+
+    let token = save_tokens(
+        repo,
+        rng,
+        clock,
+        encrypter,
+        link,
+        &response,
+        Some(encrypted_refresh_token),
+    )
+    .await?;
+
+    Ok(FreshAccessToken::Valid(UpstreamAccessToken {
+        access_token: response.access_token,
+        expires_at: token.access_token_expires_at,
+    }))
+}
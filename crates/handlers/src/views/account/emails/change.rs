@@ -0,0 +1,383 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Form, Path, State},
+    response::{Html, IntoResponse, Response},
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_data_model::{SiteConfig, User, UserEmail, UserEmailChange};
+use mas_policy::Policy;
+use mas_router::{PostAuthAction, UrlBuilder};
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob, SendEmailChangeNotificationJob, VerifyEmailJob},
+    user::{UserEmailChangeRepository, UserEmailRepository},
+    BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess, RepositoryError,
+};
+use mas_templates::{
+    EmailChangeContext, EmailVerificationPageContext, ErrorContext, TemplateContext, Templates,
+};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{views::shared::requires_reauth, BoundActivityTracker, PreferredLanguage};
+
+#[derive(Deserialize, Debug)]
+pub struct EmailForm {
+    email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CodeForm {
+    code: String,
+}
+
+fn email_change_not_allowed() -> FancyError {
+    // XXX: this may not be the best error message, it's not translatable
+    FancyError::new(
+        ErrorContext::new()
+            .with_description("Email change is not allowed".to_owned())
+            .with_details("The site configuration does not allow email changes".to_owned()),
+    )
+}
+
+/// Switch the primary email of the user to the new, verified, address, and
+/// notify the previous address
+async fn complete_change(
+    repo: &mut BoxRepository,
+    clock: &dyn Clock,
+    user: &User,
+    new_email: &UserEmail,
+    change: UserEmailChange,
+    locale: String,
+) -> Result<(), RepositoryError> {
+    repo.user_email().set_as_primary(new_email).await?;
+
+    let change = repo
+        .user_email_change()
+        .mark_as_confirmed(clock, change)
+        .await?;
+
+    repo.job()
+        .schedule_job(SendEmailChangeNotificationJob::new(&change).with_language(locale))
+        .await?;
+
+    repo.job().schedule_job(ProvisionUserJob::new(user)).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "handlers.views.account_email_change.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    activity_tracker: BoundActivityTracker,
+    mut repo: BoxRepository,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if !site_config.email_change_allowed {
+        return Err(email_change_not_allowed());
+    }
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    // Changing the primary address may require a recent authentication
+    if requires_reauth(&mut repo, &clock, &site_config, &session).await? {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::ChangeEmail);
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    }
+
+    // Without a primary address, there is nothing to change, so add one instead
+    let Some(current_email) = repo.user_email().get_primary(&session.user).await? else {
+        let add = mas_router::AccountAddEmail::default();
+        return Ok((cookie_jar, url_builder.redirect(&add)).into_response());
+    };
+
+    let ctx = EmailChangeContext::new(current_email)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let content = templates.render_account_change_email(&ctx)?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.account_email_change.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    PreferredLanguage(locale): PreferredLanguage,
+    mut policy: Policy,
+    cookie_jar: CookieJar,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    activity_tracker: BoundActivityTracker,
+    Form(form): Form<ProtectedForm<EmailForm>>,
+) -> Result<Response, FancyError> {
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if !site_config.email_change_allowed {
+        return Err(email_change_not_allowed());
+    }
+
+    if requires_reauth(&mut repo, &clock, &site_config, &session).await? {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::ChangeEmail);
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    }
+
+    let Some(current_email) = repo.user_email().get_primary(&session.user).await? else {
+        let add = mas_router::AccountAddEmail::default();
+        return Ok((cookie_jar, url_builder.redirect(&add)).into_response());
+    };
+
+    // Validate the email address
+    if form.email.parse::<lettre::Address>().is_err() {
+        return Err(anyhow::anyhow!("Invalid email address").into());
+    }
+
+    if form.email == current_email.email {
+        return Err(anyhow::anyhow!("This is already the current email address").into());
+    }
+
+    // Run the email policy
+    let res = policy.evaluate_email(&form.email).await?;
+    if !res.valid() {
+        return Err(FancyError::new(
+            ErrorContext::new()
+                .with_description(format!("Email address {:?} denied by policy", form.email))
+                .with_details(format!("{res}")),
+        ));
+    }
+
+    let existing_user_email = repo.user_email().find(&session.user, &form.email).await?;
+    let new_email = if let Some(user_email) = existing_user_email {
+        user_email
+    } else {
+        repo.user_email()
+            .add(&mut rng, &clock, &session.user, form.email)
+            .await?
+    };
+
+    let undo_ticket = Alphanumeric.sample_string(&mut rng, 32);
+    let change = repo
+        .user_email_change()
+        .add(
+            &mut rng,
+            &clock,
+            &session.user,
+            current_email.email,
+            new_email.email.clone(),
+            undo_ticket,
+        )
+        .await?;
+
+    // The new address must be verified before it becomes the primary one
+    let next = if new_email.confirmed_at.is_none() {
+        repo.job()
+            .schedule_job(VerifyEmailJob::new(&new_email).with_language(locale.to_string()))
+            .await?;
+
+        url_builder.redirect(&mas_router::AccountChangeEmailVerify::new(change.id))
+    } else {
+        complete_change(
+            &mut repo,
+            &clock,
+            &session.user,
+            &new_email,
+            change,
+            locale.to_string(),
+        )
+        .await?;
+
+        url_builder.redirect(&mas_router::Account::default())
+    };
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    Ok((cookie_jar, next).into_response())
+}
+
+#[tracing::instrument(
+    name = "handlers.views.account_email_change.verify_get",
+    fields(user_email_change.id = %id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn verify_get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    activity_tracker: BoundActivityTracker,
+    mut repo: BoxRepository,
+    Path(id): Path<Ulid>,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let change = repo
+        .user_email_change()
+        .lookup(id)
+        .await?
+        .filter(|c| c.user_id == session.user.id)
+        .context("Could not find email change")?;
+
+    if !change.is_pending() {
+        // This change was already confirmed, skip
+        let destination = url_builder.redirect(&mas_router::Account::default());
+        return Ok((cookie_jar, destination).into_response());
+    }
+
+    let new_email = repo
+        .user_email()
+        .find(&session.user, &change.new_email)
+        .await?
+        .context("Could not find user email")?;
+
+    let ctx = EmailVerificationPageContext::new(new_email)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let content = templates.render_account_verify_email(&ctx)?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[tracing::instrument(
+    name = "handlers.views.account_email_change.verify_post",
+    fields(user_email_change.id = %id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn verify_post(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    State(url_builder): State<UrlBuilder>,
+    activity_tracker: BoundActivityTracker,
+    Path(id): Path<Ulid>,
+    Form(form): Form<ProtectedForm<CodeForm>>,
+) -> Result<Response, FancyError> {
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    let change = repo
+        .user_email_change()
+        .lookup(id)
+        .await?
+        .filter(|c| c.user_id == session.user.id && c.is_pending())
+        .context("Could not find email change")?;
+
+    let new_email = repo
+        .user_email()
+        .find(&session.user, &change.new_email)
+        .await?
+        .context("Could not find user email")?;
+
+    let verification = repo
+        .user_email()
+        .find_verification_code(&clock, &new_email, &form.code)
+        .await?
+        .context("Invalid code")?;
+
+    repo.user_email()
+        .consume_verification_code(&clock, verification)
+        .await?;
+
+    let new_email = repo
+        .user_email()
+        .mark_as_verified(&clock, new_email)
+        .await?;
+
+    complete_change(
+        &mut repo,
+        &clock,
+        &session.user,
+        &new_email,
+        change,
+        locale.to_string(),
+    )
+    .await?;
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let destination = url_builder.redirect(&mas_router::Account::default());
+    Ok((cookie_jar, destination).into_response())
+}
@@ -13,4 +13,5 @@
 // limitations under the License.
 
 pub mod add;
+pub mod change;
 pub mod verify;
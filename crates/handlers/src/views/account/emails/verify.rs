@@ -110,7 +110,9 @@ pub(crate) async fn post(
     Path(id): Path<Ulid>,
     Form(form): Form<ProtectedForm<CodeForm>>,
 ) -> Result<Response, FancyError> {
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
     let maybe_session = session_info.load_session(&mut repo).await?;
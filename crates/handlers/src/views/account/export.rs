@@ -0,0 +1,334 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Form, Path, State},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use hyper::header::CONTENT_DISPOSITION;
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_data_model::SiteConfig;
+use mas_router::{PostAuthAction, UrlBuilder};
+use mas_storage::{
+    job::{ExportUserDataJob, JobRepositoryExt},
+    user::UserExportRepository,
+    BoxClock, BoxRepository, BoxRng, Clock,
+};
+use mas_templates::{AccountExportContext, TemplateContext, Templates};
+use ulid::Ulid;
+
+use crate::{views::shared::requires_reauth, BoundActivityTracker, PreferredLanguage};
+
+#[tracing::instrument(name = "handlers.views.account_export.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    activity_tracker: BoundActivityTracker,
+    mut repo: BoxRepository,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::and_then(PostAuthAction::ExportData);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    // Exporting the data of the account may require a recent authentication
+    if requires_reauth(&mut repo, &clock, &site_config, &session).await? {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::ExportData);
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    }
+
+    let ctx = AccountExportContext::new();
+    let ctx = match repo.user_export().find_latest(&session.user).await? {
+        Some(export) => {
+            let download_link = export
+                .is_available(clock.now())
+                .then(|| url_builder.account_export_download_link(export.id));
+            ctx.with_export(export, download_link)
+        }
+        None => ctx,
+    };
+
+    let ctx = ctx
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let content = templates.render_account_export(&ctx)?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.account_export.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    activity_tracker: BoundActivityTracker,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::and_then(PostAuthAction::ExportData);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if requires_reauth(&mut repo, &clock, &site_config, &session).await? {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::ExportData);
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    }
+
+    // Don't start a new export while one is still being generated
+    let latest = repo.user_export().find_latest(&session.user).await?;
+    if !latest.is_some_and(|export| export.is_pending()) {
+        let export = repo
+            .user_export()
+            .add(&mut rng, &clock, &session.user)
+            .await?;
+
+        repo.job()
+            .schedule_job(ExportUserDataJob::new(&export).with_language(locale.to_string()))
+            .await?;
+    }
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let destination = url_builder.redirect(&mas_router::AccountExport);
+    Ok((cookie_jar, destination).into_response())
+}
+
+#[tracing::instrument(
+    name = "handlers.views.account_export.download",
+    fields(user_export.id = %id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn download(
+    clock: BoxClock,
+    State(url_builder): State<UrlBuilder>,
+    activity_tracker: BoundActivityTracker,
+    mut repo: BoxRepository,
+    Path(id): Path<Ulid>,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::and_then(PostAuthAction::ExportData);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let export = repo
+        .user_export()
+        .lookup(id)
+        .await?
+        .filter(|export| export.user_id == session.user.id)
+        .context("Could not find user export")?;
+
+    // The export page tells the user it expired, and lets them start a new one
+    if !export.is_available(clock.now()) {
+        let destination = url_builder.redirect(&mas_router::AccountExport);
+        return Ok((cookie_jar, destination).into_response());
+    }
+
+    let data = repo
+        .user_export()
+        .get_data(&export)
+        .await?
+        .context("User export has no data")?;
+
+    let disposition = format!(
+        "attachment; filename=\"account-export-{}.json\"",
+        export.created_at.format("%Y-%m-%d")
+    );
+
+    Ok((cookie_jar, [(CONTENT_DISPOSITION, disposition)], Json(data)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        header::{CONTENT_DISPOSITION, LOCATION},
+        Request, StatusCode,
+    };
+    use mas_axum_utils::SessionInfoExt;
+    use mas_data_model::UserExport;
+    use mas_router::{Route, SimpleRoute};
+    use mas_storage::{
+        user::{BrowserSessionRepository, UserExportRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_export(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&session));
+
+        let request = cookies.with_cookies(Request::get(mas_router::AccountExport::PATH).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        // Start an export
+        let request = cookies.with_cookies(Request::post(mas_router::AccountExport::PATH).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let export = repo
+            .user_export()
+            .find_latest(&user)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(export.is_pending());
+
+        // Starting another one while it is pending doesn't do anything
+        let request = cookies.with_cookies(Request::post(mas_router::AccountExport::PATH).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+            }),
+        ));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let latest = repo
+            .user_export()
+            .find_latest(&user)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest, export);
+
+        // Complete the export, like the job would
+        let export = repo
+            .user_export()
+            .complete(
+                &state.clock,
+                export,
+                serde_json::json!({ "user": { "username": "john" } }),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let route = mas_router::AccountExportDownload::new(export.id);
+        let request = cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response
+            .headers()
+            .get(CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("attachment;"));
+        let data: serde_json::Value = response.json();
+        assert_eq!(data["user"]["username"], "john");
+
+        // Other users can't download it
+        let mut repo = state.repository().await.unwrap();
+        let other = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let other_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &other, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let other_cookies = CookieHelper::new();
+        other_cookies.import(state.cookie_jar().set_session(&other_session));
+        let request = other_cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Once it expired, the user is sent back to the export page
+        state.clock.advance(UserExport::VALIDITY);
+        let request = cookies.with_cookies(Request::get(&*route.path_and_query()).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap();
+        assert_eq!(location, mas_router::AccountExport::PATH);
+    }
+}
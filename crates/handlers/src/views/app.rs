@@ -17,11 +17,14 @@ use axum::{
     response::{Html, IntoResponse},
 };
 use mas_axum_utils::{cookies::CookieJar, FancyError, SessionInfoExt};
-use mas_router::{PostAuthAction, UrlBuilder};
+use mas_data_model::SiteConfig;
+use mas_router::{AccountAction, PostAuthAction, UrlBuilder};
 use mas_storage::{BoxClock, BoxRepository};
 use mas_templates::{AppContext, TemplateContext, Templates};
 
-use crate::{BoundActivityTracker, PreferredLanguage};
+use crate::{
+    views::shared::requires_cross_signing_reset_reauth, BoundActivityTracker, PreferredLanguage,
+};
 
 #[tracing::instrument(name = "handlers.views.app.get", skip_all, err)]
 pub async fn get(
@@ -29,7 +32,8 @@ pub async fn get(
     State(templates): State<Templates>,
     activity_tracker: BoundActivityTracker,
     State(url_builder): State<UrlBuilder>,
-    action: Option<Query<mas_router::AccountAction>>,
+    State(site_config): State<SiteConfig>,
+    action: Option<Query<AccountAction>>,
     mut repo: BoxRepository,
     clock: BoxClock,
     cookie_jar: CookieJar,
@@ -53,8 +57,106 @@ pub async fn get(
         .record_browser_session(&clock, &session)
         .await;
 
+    // Approving a cross-signing reset requires the user to have authenticated
+    // recently
+    if matches!(action, Some(AccountAction::OrgMatrixCrossSigningReset))
+        && requires_cross_signing_reset_reauth(&mut repo, &clock, &site_config, &session).await?
+    {
+        return Ok((
+            cookie_jar,
+            url_builder.redirect(&mas_router::Reauth::and_then(
+                PostAuthAction::manage_account(action),
+            )),
+        )
+            .into_response());
+    }
+
     let ctx = AppContext::from_url_builder(&url_builder).with_language(locale);
     let content = templates.render_app(&ctx)?;
 
     Ok((cookie_jar, Html(content)).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{header::LOCATION, Request, StatusCode};
+    use mas_axum_utils::SessionInfoExt;
+    use mas_storage::{
+        user::{BrowserSessionRepository, UserPasswordRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+    use zeroize::Zeroizing;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    /// Test that approving a cross-signing reset requires a recent
+    /// authentication
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_cross_signing_reset_requires_reauth(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hashed_password) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new(b"password".to_vec()))
+            .await
+            .unwrap();
+        let password = repo
+            .user_password()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                version,
+                hashed_password,
+                None,
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_password(&mut rng, &state.clock, &session, &password)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&session));
+
+        let path = "/account/?action=org.matrix.cross_signing_reset";
+
+        // The authentication is fresh, the page is served directly
+        let request = cookies.with_cookies(Request::get(path).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Later on, the user has to authenticate again
+        state.clock.advance(Duration::minutes(10));
+        let request = cookies.with_cookies(Request::get(path).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert!(location.starts_with("/reauth?"));
+        assert!(location.contains("org.matrix.cross_signing_reset"));
+
+        // Other pages are still accessible
+        let request = cookies.with_cookies(Request::get("/account/").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+}
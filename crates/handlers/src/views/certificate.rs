@@ -0,0 +1,328 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    TypedHeader,
+};
+use hyper::StatusCode;
+use mas_axum_utils::{cookies::CookieJar, ClientCertificate, FancyError, SessionInfoExt};
+use mas_data_model::{ClientCertificateMapping, SiteConfig, User, UserAgent};
+use mas_i18n::DataLocale;
+use mas_keystore::CertificateIdentity;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{
+    job::{CheckLoginJob, JobRepositoryExt},
+    user::{BrowserSessionRepository, UserEmailFilter, UserEmailRepository, UserRepository},
+    BoxClock, BoxRepository, BoxRng, Pagination, RepositoryAccess,
+};
+use mas_templates::{ErrorContext, Templates};
+
+use super::shared::OptionalPostAuthAction;
+use crate::{BoundActivityTracker, GeoIp, PreferredLanguage};
+
+fn certificate_login_not_allowed() -> FancyError {
+    // XXX: this may not be the best error message, it's not translatable
+    FancyError::new(
+        ErrorContext::new()
+            .with_description("Certificate login is not allowed".to_owned())
+            .with_details(
+                "The site configuration does not allow logging in with a client certificate"
+                    .to_owned(),
+            ),
+    )
+}
+
+/// Find the user a client certificate maps to, along with the name of the
+/// certificate subject which was used
+async fn find_user(
+    repo: &mut BoxRepository,
+    identity: CertificateIdentity,
+    mapping: ClientCertificateMapping,
+) -> Result<Option<(User, String)>, FancyError> {
+    match mapping {
+        ClientCertificateMapping::CommonName => {
+            let Some(common_name) = identity.common_name else {
+                return Ok(None);
+            };
+
+            let user = repo.user().find_by_username(&common_name).await?;
+            Ok(user.map(|user| (user, common_name)))
+        }
+
+        ClientCertificateMapping::Email => {
+            for email in identity.emails {
+                let page = repo
+                    .user_email()
+                    .list(
+                        UserEmailFilter::new().for_email(&email).verified_only(),
+                        Pagination::first(2),
+                    )
+                    .await?;
+
+                // Ignore addresses which are shared by multiple users, as the
+                // certificate could then log in to any of them
+                let [user_email] = page.edges.as_slice() else {
+                    continue;
+                };
+
+                let user = repo.user().lookup(user_email.user_id).await?;
+                return Ok(user.map(|user| (user, email)));
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+fn unknown_certificate(
+    templates: &Templates,
+    locale: &DataLocale,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let rendered = templates.render_error(
+        &ErrorContext::new()
+            .with_code("Unknown certificate")
+            .with_description("This certificate doesn't match any active account".to_owned())
+            .with_language(locale),
+    )?;
+
+    Ok((StatusCode::FORBIDDEN, cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.certificate.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(geoip): State<GeoIp>,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ClientCertificate(certificate): ClientCertificate,
+) -> Result<Response, FancyError> {
+    let Some(config) = &site_config.client_certificate_login else {
+        return Err(certificate_login_not_allowed());
+    };
+
+    let Some(certificate) = certificate else {
+        // This isn't the listener which requests client certificates, send the
+        // browser there
+        let destination = mas_router::LoginCertificate::from(query.post_auth_action);
+        let destination = config
+            .url
+            .join(destination.path_and_query().trim_start_matches('/'))?;
+        return Ok((cookie_jar, Redirect::to(destination.as_str())).into_response());
+    };
+
+    let identity = match CertificateIdentity::from_der(&certificate) {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Invalid client certificate"
+            );
+            return unknown_certificate(&templates, &locale, cookie_jar);
+        }
+    };
+
+    let Some((user, subject)) = find_user(&mut repo, identity, config.mapping).await? else {
+        return unknown_certificate(&templates, &locale, cookie_jar);
+    };
+
+    if !user.is_valid() {
+        return unknown_certificate(&templates, &locale, cookie_jar);
+    }
+
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    // Start a new browser session, authenticated by the certificate
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, user_agent)
+        .await?;
+
+    repo.browser_session()
+        .authenticate_with_client_certificate(&mut rng, &clock, &browser_session, &subject)
+        .await?;
+
+    if site_config.login_alerts_enabled {
+        repo.job()
+            .schedule_job(
+                CheckLoginJob::new(&browser_session, activity_tracker.ip())
+                    .with_location(activity_tracker.ip().and_then(|ip| geoip.lookup(ip)))
+                    .with_language(locale.to_string()),
+            )
+            .await?;
+    }
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &browser_session)
+        .await;
+
+    // Go back to the public base URL, as this may be the dedicated listener.
+    // The login page then continues with the pending action.
+    let cookie_jar = cookie_jar.set_session(&browser_session);
+    let destination = mas_router::Login::from(query.post_auth_action);
+    Ok((cookie_jar, url_builder.absolute_redirect(&destination)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header::LOCATION, Request, StatusCode};
+    use mas_axum_utils::ClientCertificate;
+    use mas_data_model::{ClientCertificateLoginConfig, ClientCertificateMapping};
+    use mas_storage::{
+        user::{UserEmailRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        test_utils::{
+            init_tracing, test_site_config, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+        },
+        SiteConfig,
+    };
+
+    /// A certificate for `CN=alice`, with the `alice@example.com` and
+    /// `alice.smith@example.com` email addresses
+    const CERTIFICATE: &[u8] = include_bytes!("../../../keystore/tests/keys/client.cert.der");
+
+    async fn state(pool: PgPool, mapping: ClientCertificateMapping) -> TestState {
+        TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                client_certificate_login: Some(ClientCertificateLoginConfig {
+                    url: "https://certs.example.com:8443/".parse().unwrap(),
+                    mapping,
+                }),
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    fn request_with_certificate() -> Request<String> {
+        Request::get("/login/certificate")
+            .extension(ClientCertificate(Some(CERTIFICATE.to_vec())))
+            .empty()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_certificate_login_common_name(pool: PgPool) {
+        init_tracing();
+        let state = state(pool, ClientCertificateMapping::CommonName).await;
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Without a certificate, the browser is sent to the dedicated listener
+        let request = cookies.with_cookies(Request::get("/login/certificate").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "https://certs.example.com:8443/login/certificate");
+
+        // Which logs in the user and sends them back to the public base URL
+        let request = cookies.with_cookies(request_with_certificate());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "https://example.com/login");
+
+        let request = cookies.with_cookies(Request::get("/").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("alice"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_certificate_login_email(pool: PgPool) {
+        init_tracing();
+        let state = state(pool, ClientCertificateMapping::Email).await;
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice.smith@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The email isn't verified yet, so the certificate doesn't match
+        let response = state.request(request_with_certificate()).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user_email()
+            .mark_as_verified(&state.clock, user_email)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = cookies.with_cookies(request_with_certificate());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "https://example.com/login");
+
+        let request = cookies.with_cookies(Request::get("/").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("bob"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_certificate_login_unknown(pool: PgPool) {
+        init_tracing();
+        let state = state(pool, ClientCertificateMapping::CommonName).await;
+
+        // No user matches the certificate
+        let response = state.request(request_with_certificate()).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Garbage isn't accepted either
+        let request = Request::get("/login/certificate")
+            .extension(ClientCertificate(Some(b"not a certificate".to_vec())))
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Form,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError,
+};
+use mas_storage::{
+    job::{JobRepositoryExt, ProvisionUserJob},
+    user::{UserEmailChangeRepository, UserEmailRepository, UserRepository},
+    BoxClock, BoxRepository, BoxRng, RepositoryAccess,
+};
+use mas_templates::{EmailChangeUndoContext, EmptyContext, TemplateContext, Templates};
+use serde::Deserialize;
+
+use crate::PreferredLanguage;
+
+#[derive(Deserialize)]
+pub(crate) struct RouteQuery {
+    ticket: String,
+}
+
+#[tracing::instrument(name = "handlers.views.email_change.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let change = repo
+        .user_email_change()
+        .find_by_undo_ticket(&query.ticket)
+        .await?
+        .filter(|change| change.can_undo(clock.now()));
+
+    let Some(change) = change else {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_email_change_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let context = EmailChangeUndoContext::new(change)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_email_change_undo(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.email_change.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+
+    let change = repo
+        .user_email_change()
+        .find_by_undo_ticket(&query.ticket)
+        .await?
+        .filter(|change| change.can_undo(clock.now()));
+
+    let Some(change) = change else {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_email_change_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let user = repo
+        .user()
+        .lookup(change.user_id)
+        .await?
+        .context("User not found")?;
+
+    // The previous address may have been removed since, in which case it is
+    // added back. Following the link proves the user still owns it.
+    let old_email = repo.user_email().find(&user, &change.old_email).await?;
+    let old_email = if let Some(old_email) = old_email {
+        old_email
+    } else {
+        repo.user_email()
+            .add(&mut rng, &clock, &user, change.old_email.clone())
+            .await?
+    };
+
+    let old_email = if old_email.confirmed_at.is_none() {
+        repo.user_email()
+            .mark_as_verified(&clock, old_email)
+            .await?
+    } else {
+        old_email
+    };
+
+    repo.user_email().set_as_primary(&old_email).await?;
+
+    // The new address can't be trusted anymore
+    if let Some(new_email) = repo.user_email().find(&user, &change.new_email).await? {
+        repo.user_email().remove(new_email).await?;
+    }
+
+    repo.user_email_change()
+        .mark_as_undone(&clock, change)
+        .await?;
+
+    repo.job()
+        .schedule_job(ProvisionUserJob::new(&user))
+        .await?;
+
+    repo.save().await?;
+
+    let context = EmptyContext.with_language(locale);
+    let rendered = templates.render_email_change_undone(&context)?;
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_router::Route;
+    use mas_storage::{
+        user::{UserEmailChangeRepository, UserEmailRepository, UserRepository},
+        RepositoryAccess,
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_undo_email_change(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let old_email = repo
+            .user_email()
+            .add(&mut rng, &state.clock, &user, "john@example.com".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .mark_as_verified(&state.clock, old_email)
+            .await
+            .unwrap();
+        let new_email = repo
+            .user_email()
+            .add(&mut rng, &state.clock, &user, "john@example.org".to_owned())
+            .await
+            .unwrap();
+        let new_email = repo
+            .user_email()
+            .mark_as_verified(&state.clock, new_email)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&new_email).await.unwrap();
+        let change = repo
+            .user_email_change()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "john@example.com".to_owned(),
+                "john@example.org".to_owned(),
+                "undoticket".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.user_email_change()
+            .mark_as_confirmed(&state.clock, change)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let route = mas_router::EmailChangeUndo::new("undoticket".to_owned());
+        let request = Request::get(&*route.path_and_query()).empty();
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("john@example.org"));
+        let csrf_token = response.csrf_token().to_owned();
+
+        let request = Request::post(&*route.path_and_query()).form(serde_json::json!({
+            "csrf": csrf_token,
+        }));
+        let response = state.request(cookies.with_cookies(request)).await;
+        response.assert_status(StatusCode::OK);
+
+        // The previous address is the primary one again, and the new one is gone
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        let primary = repo.user_email().get_primary(&user).await.unwrap().unwrap();
+        assert_eq!(primary.email, "john@example.com");
+        assert!(repo
+            .user_email()
+            .find(&user, "john@example.org")
+            .await
+            .unwrap()
+            .is_none());
+
+        let change = repo
+            .user_email_change()
+            .find_by_undo_ticket("undoticket")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(change.undone_at.is_some());
+        repo.save().await.unwrap();
+
+        // The link can't be used twice
+        let request = Request::get(&*route.path_and_query()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("The link has expired"));
+    }
+}
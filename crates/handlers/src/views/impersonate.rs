@@ -0,0 +1,305 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Impersonation of users by admins
+//!
+//! Admins can start a short-lived browser session for another user, to help
+//! them debug issues with their account. Those sessions record which admin
+//! started them, are marked in the `amr` claim of the ID tokens issued from
+//! them, are never remembered by the browser and are finished once they
+//! expire. The impersonated user can end them like any other session.
+
+use axum::{
+    extract::{Form, State},
+    response::{Html, IntoResponse, Response},
+    TypedHeader,
+};
+use chrono::Duration;
+use hyper::StatusCode;
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_data_model::{BrowserSession, UserAgent};
+use mas_router::UrlBuilder;
+use mas_storage::{user::BrowserSessionRepository, BoxClock, BoxRepository, BoxRng};
+use mas_templates::{
+    ErrorContext, FieldError, FormState, ImpersonateContext, ImpersonateFormField, TemplateContext,
+    Templates,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{BoundActivityTracker, PreferredLanguage};
+
+/// How long impersonation sessions last
+pub(crate) static IMPERSONATION_TTL: Duration = Duration::microseconds(15 * 60 * 1000 * 1000);
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ImpersonateForm {
+    username: String,
+}
+
+/// Only admins can impersonate users, and not from a session which is itself
+/// an impersonation
+fn can_impersonate(session: &BrowserSession) -> bool {
+    session.user.can_request_admin && !session.is_impersonation()
+}
+
+fn forbidden() -> FancyError {
+    let context = ErrorContext::new()
+        .with_code("forbidden")
+        .with_description("Only admins can impersonate users".to_owned());
+
+    FancyError::new(context).with_status(StatusCode::FORBIDDEN)
+}
+
+#[tracing::instrument(name = "handlers.views.impersonate.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if !can_impersonate(&session) {
+        return Err(forbidden());
+    }
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    let context = ImpersonateContext::new()
+        .with_session(session)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_impersonate(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.impersonate.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    activity_tracker: BoundActivityTracker,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<ImpersonateForm>>,
+) -> Result<Response, FancyError> {
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+
+    let Some(session) = maybe_session else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if !can_impersonate(&session) {
+        return Err(forbidden());
+    }
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    // Admins can't impersonate themselves, nor locked users
+    let user = repo
+        .user()
+        .find_by_username(&form.username)
+        .await?
+        .filter(|user| user.id != session.user.id && user.is_valid());
+
+    let Some(user) = user else {
+        let form_state = FormState::from_form(&form)
+            .with_error_on_field(ImpersonateFormField::Username, FieldError::Invalid);
+        let context = ImpersonateContext::new()
+            .with_form_state(form_state)
+            .with_session(session)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        repo.save().await?;
+
+        let rendered = templates.render_impersonate(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+    let impersonation = repo
+        .browser_session()
+        .add_impersonation(
+            &mut rng,
+            &clock,
+            &user,
+            &session.user,
+            user_agent,
+            IMPERSONATION_TTL,
+        )
+        .await?;
+
+    tracing::warn!(
+        admin.id = %session.user.id,
+        admin.username = %session.user.username,
+        user.id = %user.id,
+        user.username = %user.username,
+        user_session.id = %impersonation.id,
+        ip = ?activity_tracker.ip(),
+        "Admin started impersonating a user",
+    );
+
+    repo.save().await?;
+
+    // This replaces the session of the admin in this browser. The cookie is only
+    // kept for the lifetime of the browser.
+    let cookie_jar = cookie_jar.set_session(&impersonation);
+
+    Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        header::{LOCATION, SET_COOKIE},
+        Request, StatusCode,
+    };
+    use mas_axum_utils::SessionInfoExt;
+    use mas_router::SimpleRoute;
+    use mas_storage::{
+        user::{BrowserSessionFilter, BrowserSessionRepository, UserRepository},
+        Clock, Pagination, RepositoryAccess,
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_impersonate(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let admin = repo
+            .user()
+            .add(&mut rng, &state.clock, "admin".to_owned())
+            .await
+            .unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &admin, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&session));
+
+        // The admin can't request admin access yet
+        let request = cookies.with_cookies(Request::get(mas_router::Impersonate::PATH).empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let mut repo = state.repository().await.unwrap();
+        let admin = repo
+            .user()
+            .set_can_request_admin(admin, true)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = cookies.with_cookies(Request::get(mas_router::Impersonate::PATH).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token().to_owned();
+
+        // Admins can't impersonate themselves
+        let request = Request::post(mas_router::Impersonate::PATH).form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "admin",
+        }));
+        let response = state.request(cookies.with_cookies(request)).await;
+        response.assert_status(StatusCode::OK);
+
+        let request = Request::post(mas_router::Impersonate::PATH).form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+        }));
+        let response = state.request(cookies.with_cookies(request)).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/");
+
+        // The session cookie doesn't outlive the browser session
+        let set_cookie = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .find(|value| value.starts_with("session="))
+            .unwrap();
+        assert!(!set_cookie.contains("Expires"));
+        assert!(!set_cookie.contains("Max-Age"));
+
+        // The user now has an impersonation session started by the admin
+        let mut repo = state.repository().await.unwrap();
+        let sessions = repo
+            .browser_session()
+            .list(
+                BrowserSessionFilter::new().for_user(&user),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sessions.edges.len(), 1);
+        let impersonation = &sessions.edges[0];
+        assert_eq!(impersonation.impersonated_by, Some(admin.id));
+        assert_eq!(
+            impersonation.expires_at,
+            Some(state.clock.now() + super::IMPERSONATION_TTL)
+        );
+    }
+}
@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::net::IpAddr;
+
+use anyhow::Context;
 use axum::{
     extract::{Form, Query, State},
     response::{Html, IntoResponse, Response},
@@ -21,30 +24,43 @@ use hyper::StatusCode;
 use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, CsrfToken, ProtectedForm},
-    FancyError, SessionInfoExt,
+    FancyError, RequestId, SessionInfo, SessionInfoExt,
+};
+use mas_data_model::{
+    BrowserSession, LoginRiskDecision, LoginSighting, Password, User, UserAgent, UserEmail,
+    UserLoginCode,
 };
-use mas_data_model::{BrowserSession, UserAgent};
 use mas_i18n::DataLocale;
-use mas_router::{UpstreamOAuth2Authorize, UrlBuilder};
+use mas_router::{LoginCode, LoginNegotiate, UpstreamOAuth2Authorize, UrlBuilder};
 use mas_storage::{
+    job::{CheckLoginJob, JobRepositoryExt, SendLoginCodeEmailJob},
     upstream_oauth2::UpstreamOAuthProviderRepository,
-    user::{BrowserSessionRepository, UserPasswordRepository, UserRepository},
+    user::{
+        BrowserSessionRepository, UserEmailRepository, UserLoginAlertRepository,
+        UserLoginCodeRepository, UserPasswordRepository, UserRepository,
+    },
     BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
 };
 use mas_templates::{
-    FieldError, FormError, LoginContext, LoginFormField, TemplateContext, Templates, ToFormState,
+    FieldError, FormError, LoginContext, LoginFormField, LoginThrottledContext, TemplateContext,
+    Templates, ToFormState,
 };
-use rand::{CryptoRng, Rng};
+use rand::{distributions::Uniform, CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
 use zeroize::Zeroizing;
 
-use super::shared::OptionalPostAuthAction;
-use crate::{passwords::PasswordManager, BoundActivityTracker, PreferredLanguage, SiteConfig};
+use super::{negotiate::NegotiateAttempt, shared::OptionalPostAuthAction};
+use crate::{
+    passwords::PasswordManager, BoundActivityTracker, GeoIp, Limiter, LoginRisk, NetworkBans,
+    PreferredLanguage, SiteConfig,
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct LoginForm {
     username: String,
     password: String,
+    #[serde(default)]
+    remember: String,
 }
 
 impl ToFormState for LoginForm {
@@ -61,7 +77,7 @@ pub(crate) async fn get(
     State(site_config): State<SiteConfig>,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
-    Query(query): Query<OptionalPostAuthAction>,
+    Query(mut query): Query<OptionalPostAuthAction>,
     cookie_jar: CookieJar,
 ) -> Result<Response, FancyError> {
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
@@ -78,6 +94,22 @@ pub(crate) async fn get(
         return Ok((cookie_jar, reply).into_response());
     };
 
+    if site_config.spnego_login.is_some() {
+        if let Some(attempt) = NegotiateAttempt::load(&cookie_jar, &clock) {
+            // SPNEGO was just attempted and the browser was sent back here,
+            // resume the action which was pending then
+            if query.post_auth_action.is_none() {
+                query.post_auth_action = attempt.into_post_auth_action();
+            }
+        } else {
+            // Try to log in with the Kerberos ticket of the browser first
+            let cookie_jar =
+                NegotiateAttempt::new(&clock, query.post_auth_action.clone()).save(cookie_jar);
+            let destination = LoginNegotiate::from(query.post_auth_action);
+            return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+        }
+    }
+
     let providers = repo.upstream_oauth_provider().all_enabled().await?;
 
     // If password-based login is disabled, and there is only one upstream provider,
@@ -116,8 +148,13 @@ pub(crate) async fn post(
     State(site_config): State<SiteConfig>,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(limiter): State<Limiter>,
+    State(network_bans): State<NetworkBans>,
+    State(geoip): State<GeoIp>,
+    State(login_risk): State<LoginRisk>,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
+    RequestId(request_id): RequestId,
     Query(query): Query<OptionalPostAuthAction>,
     cookie_jar: CookieJar,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
@@ -129,7 +166,9 @@ pub(crate) async fn post(
         return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
     }
 
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
 
@@ -165,29 +204,179 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    if let Err(e) = limiter
+        .check_password(&clock, activity_tracker.ip(), &form.username)
+        .await
+    {
+        // Show a dedicated page explaining how long to wait, rather than an
+        // error on the form which doesn't tell when the user can try again
+        let ctx = LoginThrottledContext::new(e.retry_after_secs());
+        let ctx = if let Some(next) = query.load_context(&mut repo).await? {
+            ctx.with_post_action(next)
+        } else {
+            ctx
+        };
+        let content = templates.render_login_throttled(&ctx.with_language(locale))?;
+
+        return Ok((StatusCode::TOO_MANY_REQUESTS, e, cookie_jar, Html(content)).into_response());
+    }
+
     match login(
         password_manager,
         &mut repo,
-        rng,
+        &mut rng,
         &clock,
         &form.username,
         &form.password,
-        user_agent,
     )
     .await
     {
-        Ok(session_info) => {
+        Ok((user, user_password)) => {
+            // Bots can't receive login codes, so their logins are neither
+            // assessed nor confirmed
+            let assessment = if user.is_bot {
+                None
+            } else {
+                login_risk
+                    .assess(
+                        &mut rng,
+                        &clock,
+                        &mut repo,
+                        &geoip,
+                        &user,
+                        user_agent.clone(),
+                        activity_tracker.ip(),
+                        request_id,
+                    )
+                    .await?
+            };
+
+            let code = match assessment.map(|assessment| assessment.decision) {
+                Some(LoginRiskDecision::Deny) => {
+                    let state = state.with_error_on_form(FormError::Denied);
+                    let content = render(
+                        locale,
+                        LoginContext::default().with_form_state(state),
+                        query,
+                        csrf_token,
+                        &mut repo,
+                        &templates,
+                    )
+                    .await?;
+
+                    // Keep the assessment for auditing
+                    repo.save().await?;
+
+                    return Ok((cookie_jar, Html(content)).into_response());
+                }
+
+                Some(LoginRiskDecision::RequireMfa) => {
+                    // The assessment made sure the user has an email address
+                    // to send the code to
+                    let user_email = confirmed_primary_email(&mut repo, &user)
+                        .await?
+                        .context("User has no confirmed primary email address")?;
+
+                    let code = create_login_code(
+                        &mut rng,
+                        &clock,
+                        &mut repo,
+                        &user_email,
+                        &user_password,
+                        user_agent.clone(),
+                        activity_tracker.ip(),
+                    )
+                    .await?;
+
+                    Some(code)
+                }
+
+                Some(LoginRiskDecision::Allow) => {
+                    // Remember the device and network for the next
+                    // assessments. The login alert job does it if enabled.
+                    let ip_address = activity_tracker.ip();
+                    if let (Some(user_agent), Some(ip_address)) = (&user_agent, ip_address) {
+                        if !site_config.login_alerts_enabled {
+                            repo.user_login_alert()
+                                .record_sighting(
+                                    &mut rng,
+                                    &clock,
+                                    &user,
+                                    &LoginSighting::device_of(user_agent),
+                                    LoginSighting::network_of(ip_address),
+                                )
+                                .await?;
+                        }
+                    }
+
+                    None
+                }
+
+                None if site_config.email_otp_enabled && !user.is_bot => {
+                    start_login_code(
+                        &mut rng,
+                        &clock,
+                        &mut repo,
+                        &user,
+                        &user_password,
+                        user_agent.as_ref(),
+                        activity_tracker.ip(),
+                    )
+                    .await?
+                }
+
+                None => None,
+            };
+
+            if let Some(code) = code {
+                repo.job()
+                    .schedule_job(
+                        SendLoginCodeEmailJob::new(&code).with_language(locale.to_string()),
+                    )
+                    .await?;
+
+                repo.save().await?;
+
+                let destination = LoginCode::new(code.id).and_maybe(query.post_auth_action);
+                return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+            }
+
+            let session_info = start_session(
+                &mut rng,
+                &clock,
+                &mut repo,
+                &user,
+                &user_password,
+                user_agent,
+            )
+            .await?;
+
+            if site_config.login_alerts_enabled {
+                repo.job()
+                    .schedule_job(
+                        CheckLoginJob::new(&session_info, activity_tracker.ip())
+                            .with_location(activity_tracker.ip().and_then(|ip| geoip.lookup(ip)))
+                            .with_language(locale.to_string()),
+                    )
+                    .await?;
+            }
+
             repo.save().await?;
 
             activity_tracker
                 .record_browser_session(&clock, &session_info)
                 .await;
 
-            let cookie_jar = cookie_jar.set_session(&session_info);
+            // Unless the user asked to stay signed in, the cookie only lasts for
+            // the browser session
+            let cookie_jar = cookie_jar.update_session_info(
+                &SessionInfo::from_session(&session_info).remember(form.remember == "on"),
+            );
             let reply = query.go_next(&url_builder);
             Ok((cookie_jar, reply).into_response())
         }
         Err(e) => {
+            let failed = matches!(e, FormError::InvalidCredentials);
             let state = state.with_error_on_form(e);
 
             let content = render(
@@ -200,6 +389,13 @@ pub(crate) async fn post(
             )
             .await?;
 
+            if failed {
+                network_bans
+                    .record_failure(&mut rng, &clock, &mut repo, activity_tracker.ip())
+                    .await?;
+                repo.save().await?;
+            }
+
             Ok((cookie_jar, Html(content)).into_response())
         }
     }
@@ -213,8 +409,7 @@ async fn login(
     clock: &impl Clock,
     username: &str,
     password: &str,
-    user_agent: Option<UserAgent>,
-) -> Result<BrowserSession, FormError> {
+) -> Result<(User, Password), FormError> {
     // XXX: we're loosing the error context here
     // First, lookup the user
     let user = repo
@@ -263,22 +458,141 @@ async fn login(
         user_password
     };
 
-    // Start a new session
+    Ok((user, user_password))
+}
+
+/// Start a new browser session for the user, authenticated by the given
+/// password
+pub(crate) async fn start_session<R: RepositoryAccess>(
+    mut rng: impl Rng + CryptoRng + Send,
+    clock: &impl Clock,
+    repo: &mut R,
+    user: &User,
+    user_password: &Password,
+    user_agent: Option<UserAgent>,
+) -> Result<BrowserSession, R::Error> {
     let user_session = repo
         .browser_session()
-        .add(&mut rng, clock, &user, user_agent)
-        .await
-        .map_err(|_| FormError::Internal)?;
+        .add(&mut rng, clock, user, user_agent)
+        .await?;
 
-    // And mark it as authenticated by the password
     repo.browser_session()
-        .authenticate_with_password(&mut rng, clock, &user_session, &user_password)
-        .await
-        .map_err(|_| FormError::Internal)?;
+        .authenticate_with_password(&mut rng, clock, &user_session, user_password)
+        .await?;
 
     Ok(user_session)
 }
 
+/// Find the confirmed primary email address of the user, which login codes
+/// are sent to
+async fn confirmed_primary_email<R: RepositoryAccess>(
+    repo: &mut R,
+    user: &User,
+) -> Result<Option<UserEmail>, R::Error> {
+    let Some(user_email_id) = user.primary_user_email_id else {
+        return Ok(None);
+    };
+
+    let user_email = repo
+        .user_email()
+        .lookup(user_email_id)
+        .await?
+        .filter(|user_email| user_email.confirmed_at.is_some());
+
+    Ok(user_email)
+}
+
+/// Create a code to send to the given email address to confirm a password
+/// login
+async fn create_login_code<R: RepositoryAccess>(
+    mut rng: impl Rng + CryptoRng + Send,
+    clock: &impl Clock,
+    repo: &mut R,
+    user_email: &UserEmail,
+    user_password: &Password,
+    user_agent: Option<UserAgent>,
+    ip_address: Option<IpAddr>,
+) -> Result<UserLoginCode, R::Error> {
+    let range = Uniform::<u32>::from(0..1_000_000);
+    let code = format!("{:06}", rng.sample(range));
+
+    repo.user_login_code()
+        .add(
+            &mut rng,
+            clock,
+            user_email,
+            user_password,
+            user_agent,
+            ip_address,
+            code,
+        )
+        .await
+}
+
+/// Check whether a password login comes from an unknown device or network,
+/// and if so, create a code to send to the primary email address of the user
+/// to confirm it.
+///
+/// A login without a user agent or an IP address is treated as coming from an
+/// unknown device.
+///
+/// Returns `None` if the login doesn't have to be confirmed, in which case the
+/// device and network are recorded as known.
+async fn start_login_code<R: RepositoryAccess>(
+    mut rng: impl Rng + CryptoRng + Send,
+    clock: &impl Clock,
+    repo: &mut R,
+    user: &User,
+    user_password: &Password,
+    user_agent: Option<&UserAgent>,
+    ip_address: Option<IpAddr>,
+) -> Result<Option<UserLoginCode>, R::Error> {
+    let device_and_network = user_agent.zip(ip_address).map(|(user_agent, ip_address)| {
+        (
+            LoginSighting::device_of(user_agent),
+            LoginSighting::network_of(ip_address),
+        )
+    });
+
+    let sighting = if let Some((device, network)) = &device_and_network {
+        repo.user_login_alert()
+            .check_sighting(user, device, *network)
+            .await?
+    } else {
+        LoginSighting::Unknown
+    };
+
+    let user_email = if sighting == LoginSighting::Unknown {
+        confirmed_primary_email(repo, user).await?
+    } else {
+        None
+    };
+
+    // Without a confirmed email address to send the code to, let the login
+    // through like any other
+    let Some(user_email) = user_email else {
+        if let Some((device, network)) = &device_and_network {
+            repo.user_login_alert()
+                .record_sighting(&mut rng, clock, user, device, *network)
+                .await?;
+        }
+        return Ok(None);
+    };
+
+    let user_login_code = create_login_code(
+        &mut rng,
+        clock,
+        repo,
+        &user_email,
+        user_password,
+        user_agent.cloned(),
+        ip_address,
+    )
+    .await?;
+
+    Ok(Some(user_login_code))
+}
+
 async fn render(
     locale: DataLocale,
     ctx: LoginContext,
@@ -302,7 +616,7 @@ async fn render(
 #[cfg(test)]
 mod test {
     use hyper::{
-        header::{CONTENT_TYPE, LOCATION},
+        header::{CONTENT_TYPE, LOCATION, ORIGIN, REFERER, RETRY_AFTER, SET_COOKIE},
         Request, StatusCode,
     };
     use mas_data_model::UpstreamOAuthProviderClaimsImports;
@@ -373,6 +687,8 @@ mod test {
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
                     additional_authorization_parameters: Vec::new(),
+                    store_tokens: false,
+                    on_deprovision: mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore,
                 },
             )
             .await
@@ -408,6 +724,8 @@ mod test {
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
                     additional_authorization_parameters: Vec::new(),
+                    store_tokens: false,
+                    on_deprovision: mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore,
                 },
             )
             .await
@@ -461,15 +779,7 @@ mod test {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Submit the login form
         let request = Request::post("/login").form(serde_json::json!({
@@ -491,4 +801,178 @@ mod test {
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
         assert!(response.body().contains("john"));
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_throttled(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+
+        // Use up all the login attempts allowed for the account
+        while state
+            .limiter
+            .check_password(&*state.clock, None, "john")
+            .await
+            .is_ok()
+        {}
+
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        // The next attempt explains how long to wait instead of showing the form
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(RETRY_AFTER));
+        assert!(
+            response.body().contains("Too many attempts"),
+            "Response body: {}",
+            response.body()
+        );
+        assert!(!response.body().contains(r#"name="password""#));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_remember(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // Provision a user with a password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        for remember in [false, true] {
+            let cookies = CookieHelper::new();
+
+            // Render the login page to get a CSRF token
+            let request = Request::get("/login").empty();
+            let request = cookies.with_cookies(request);
+            let response = state.request(request).await;
+            cookies.save_cookies(&response);
+            response.assert_status(StatusCode::OK);
+            let csrf_token = response.csrf_token();
+
+            // Submit the login form, ticking the checkbox or not
+            let mut form = serde_json::json!({
+                "csrf": csrf_token,
+                "username": "john",
+                "password": "hunter2",
+            });
+            if remember {
+                form["remember"] = "on".into();
+            }
+            let request = Request::post("/login").form(form);
+            let request = cookies.with_cookies(request);
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::SEE_OTHER);
+
+            // The session cookie only outlives the browser session if asked to
+            let set_cookie = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|value| value.to_str().unwrap())
+                .find(|value| value.starts_with("session="))
+                .unwrap();
+            assert_eq!(set_cookie.contains("Max-Age"), remember);
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_cross_origin_form(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        // Provision a user with a password
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Render the login page to get a CSRF token
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let form = serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        });
+
+        // Submitting the form from another origin should fail, even with a valid
+        // CSRF token
+        let request = Request::post("/login")
+            .header(ORIGIN, "https://evil.example.org")
+            .form(form.clone());
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        assert!(response.body().contains("This form could not be verified"));
+
+        // A null origin falls back to the Referer header
+        let request = Request::post("/login")
+            .header(ORIGIN, "null")
+            .header(REFERER, "https://evil.example.org/")
+            .form(form.clone());
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Submitting it without the cookies tells the user their session expired
+        let request = Request::post("/login")
+            .header(ORIGIN, "https://example.com")
+            .form(form.clone());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        assert!(response.body().contains("Your session has expired"));
+
+        // Submitting it from the same origin works, even if the browser hides
+        // the origin
+        let request = Request::post("/login").header(ORIGIN, "null").form(form);
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+    }
 }
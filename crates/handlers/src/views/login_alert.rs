@@ -0,0 +1,331 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Form, TypedHeader,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError,
+};
+use mas_data_model::{Device, SiteConfig, UserAgent};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    app_session::{AppSession, AppSessionFilter},
+    job::{DeleteDeviceJob, JobRepositoryExt},
+    BoxClock, BoxRepository, BoxRng, Pagination,
+};
+use mas_templates::{EmptyContext, LoginAlertReportContext, TemplateContext, Templates};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Deserialize;
+
+use crate::{BoundActivityTracker, IntrospectionCache, PreferredLanguage};
+
+#[derive(Deserialize)]
+pub(crate) struct RouteQuery {
+    ticket: String,
+}
+
+#[tracing::instrument(name = "handlers.views.login_alert.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let alert = repo
+        .user_login_alert()
+        .find_by_ticket(&query.ticket)
+        .await?
+        .filter(|alert| alert.active(clock.now()));
+
+    let Some(alert) = alert else {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_login_alert_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let context = LoginAlertReportContext::new(alert)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_login_alert_report(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.login_alert.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    user_agent: TypedHeader<headers::UserAgent>,
+    activity_tracker: BoundActivityTracker,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(introspection_cache): State<IntrospectionCache>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+
+    let alert = repo
+        .user_login_alert()
+        .find_by_ticket(&query.ticket)
+        .await?
+        .filter(|alert| alert.active(clock.now()));
+
+    let Some(alert) = alert else {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_login_alert_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let browser_session = repo
+        .browser_session()
+        .lookup(alert.user_session_id)
+        .await?
+        .context("Browser session not found")?;
+
+    // End all the sessions which were started from the reported browser session,
+    // and delete the associated devices on the homeserver
+    let filter = AppSessionFilter::new()
+        .for_browser_session(&browser_session)
+        .active_only();
+    loop {
+        let page = repo
+            .app_session()
+            .list(filter, Pagination::first(100))
+            .await?;
+
+        for edge in page.edges {
+            match edge {
+                AppSession::Compat(session) => {
+                    repo.job()
+                        .schedule_job(DeleteDeviceJob::new(&browser_session.user, &session.device))
+                        .await?;
+                    repo.compat_session().finish(&clock, *session).await?;
+                }
+                AppSession::OAuth2(session) => {
+                    for scope in &*session.scope {
+                        if let Some(device) = Device::from_scope_token(scope) {
+                            repo.job()
+                                .schedule_job(DeleteDeviceJob::new(&browser_session.user, &device))
+                                .await?;
+                        }
+                    }
+                    repo.oauth2_session().finish(&clock, *session).await?;
+                }
+            }
+        }
+
+        // Finished sessions don't show up in the list anymore, so we always fetch
+        // the first page
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    if browser_session.finished_at.is_none() {
+        repo.browser_session()
+            .finish(&clock, browser_session.clone())
+            .await?;
+    }
+
+    repo.user_login_alert()
+        .mark_as_reported(&clock, alert)
+        .await?;
+
+    // Whoever signed in most likely knows the password, so it can't be used to
+    // sign in again until it is reset
+    repo.user_password()
+        .invalidate(&clock, &browser_session.user)
+        .await?;
+
+    // Force a password reset by starting an account recovery for the primary
+    // email of the user
+    let user_email = repo
+        .user_email()
+        .get_primary(&browser_session.user)
+        .await?
+        .filter(|email| email.confirmed_at.is_some());
+
+    let (Some(user_email), true) = (user_email, site_config.account_recovery_allowed) else {
+        repo.save().await?;
+        introspection_cache
+            .invalidate_user(browser_session.user.id)
+            .await;
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_login_alert_reported(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    };
+
+    let user_agent = UserAgent::parse(user_agent.as_str().to_owned());
+    let ip_address = activity_tracker.ip();
+
+    let session = repo
+        .user_recovery()
+        .add_session(
+            &mut rng,
+            &clock,
+            user_email.email.clone(),
+            user_agent,
+            ip_address,
+            locale.to_string(),
+        )
+        .await?;
+
+    let ticket = Alphanumeric.sample_string(&mut rng, 32);
+    let ticket = repo
+        .user_recovery()
+        .add_ticket(&mut rng, &clock, &session, &user_email, ticket)
+        .await?;
+
+    repo.save().await?;
+    introspection_cache
+        .invalidate_user(browser_session.user.id)
+        .await;
+
+    Ok((
+        cookie_jar,
+        url_builder.redirect(&mas_router::AccountRecoveryFinish::new(ticket.ticket)),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        header::{LOCATION, USER_AGENT},
+        Request, StatusCode,
+    };
+    use mas_router::Route;
+    use sqlx::PgPool;
+    use zeroize::Zeroizing;
+
+    use crate::test_utils::{
+        init_tracing, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_report_invalidates_password(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .add(&mut rng, &state.clock, &user, "john@example.com".to_owned())
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .mark_as_verified(&state.clock, user_email)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&user_email).await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.user_login_alert()
+            .add(
+                &mut rng,
+                &state.clock,
+                &browser_session,
+                None,
+                "alertticket".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Report the login
+        let route = mas_router::LoginAlertReport::new("alertticket".to_owned());
+        let request = Request::get(&*route.path_and_query()).empty();
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let request = Request::post(&*route.path_and_query())
+            .header(USER_AGENT, "Firefox")
+            .form(serde_json::json!({
+                "csrf": csrf_token,
+            }));
+        let response = state.request(cookies.with_cookies(request)).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert!(location.starts_with("https://example.com/recover/"));
+
+        // The password doesn't work anymore
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user_password().active(&user).await.unwrap().is_none());
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(browser_session.finished_at.is_some());
+        repo.cancel().await.unwrap();
+
+        let cookies = CookieHelper::new();
+        let request = Request::get("/login").empty();
+        let response = state.request(cookies.with_cookies(request)).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let response = state.request(cookies.with_cookies(request)).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("Invalid credentials"));
+    }
+}
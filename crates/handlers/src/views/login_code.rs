@@ -0,0 +1,564 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Form, Path, Query, State},
+    response::{Html, IntoResponse, Response},
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, CsrfToken, ProtectedForm},
+    FancyError, SessionInfo, SessionInfoExt,
+};
+use mas_data_model::{LoginSighting, UserLoginCode};
+use mas_i18n::DataLocale;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{JobRepositoryExt, SendLoginCodeEmailJob},
+    user::{
+        UserEmailRepository, UserLoginAlertRepository, UserLoginCodeRepository,
+        UserPasswordRepository, UserRepository,
+    },
+    BoxClock, BoxRepository, BoxRng, Clock, RepositoryAccess,
+};
+use mas_templates::{
+    FieldError, FormState, LoginCodeContext, LoginCodeFormField, TemplateContext, Templates,
+    ToFormState,
+};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::{login::start_session, shared::OptionalPostAuthAction};
+use crate::{BoundActivityTracker, PreferredLanguage};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct LoginCodeForm {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    remember: String,
+    #[serde(default, skip_serializing)]
+    resend: String,
+}
+
+impl ToFormState for LoginCodeForm {
+    type Field = LoginCodeFormField;
+}
+
+#[tracing::instrument(
+    name = "handlers.views.login_code.get",
+    fields(user_login_code.id = %id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    mut repo: BoxRepository,
+    Query(query): Query<OptionalPostAuthAction>,
+    Path(id): Path<Ulid>,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let Some(code) = repo.user_login_code().lookup(id).await? else {
+        let login = mas_router::Login::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    let content = render(
+        locale,
+        &clock,
+        code,
+        FormState::default(),
+        csrf_token,
+        &mut repo,
+        &templates,
+    )
+    .await?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[tracing::instrument(
+    name = "handlers.views.login_code.post",
+    fields(user_login_code.id = %id),
+    skip_all,
+    err,
+)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    Query(query): Query<OptionalPostAuthAction>,
+    Path(id): Path<Ulid>,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<LoginCodeForm>>,
+) -> Result<Response, FancyError> {
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let Some(code) = repo.user_login_code().lookup(id).await? else {
+        let login = mas_router::Login::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    if !form.resend.is_empty() {
+        if code.can_resend(clock.now()) {
+            let code = repo.user_login_code().record_resend(&clock, code).await?;
+
+            repo.job()
+                .schedule_job(SendLoginCodeEmailJob::new(&code).with_language(locale.to_string()))
+                .await?;
+
+            repo.save().await?;
+        }
+
+        let destination = mas_router::LoginCode::new(id).and_maybe(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+    }
+
+    let mut state = form.to_form_state();
+
+    if !code.active(clock.now()) {
+        let content = render(
+            locale, &clock, code, state, csrf_token, &mut repo, &templates,
+        )
+        .await?;
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
+    if form.code != code.code {
+        let code = repo.user_login_code().record_attempt(code).await?;
+        repo.save().await?;
+
+        state.add_error_on_field(LoginCodeFormField::Code, FieldError::Invalid);
+        let content = render(
+            locale, &clock, code, state, csrf_token, &mut repo, &templates,
+        )
+        .await?;
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
+    let code = repo.user_login_code().consume(&clock, code).await?;
+
+    let user = repo
+        .user()
+        .lookup(code.user_id)
+        .await?
+        .filter(mas_data_model::User::is_valid);
+
+    // The password has to be the same one the user logged in with
+    let user_password = if let Some(user) = &user {
+        repo.user_password()
+            .active(user)
+            .await?
+            .filter(|user_password| user_password.id == code.user_password_id)
+    } else {
+        None
+    };
+
+    let (Some(user), Some(user_password)) = (user, user_password) else {
+        repo.save().await?;
+        let login = mas_router::Login::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    // The device and network are now known, and won't need a code next time
+    if let (Some(user_agent), Some(ip_address)) = (&code.user_agent, code.ip_address) {
+        repo.user_login_alert()
+            .record_sighting(
+                &mut rng,
+                &clock,
+                &user,
+                &LoginSighting::device_of(user_agent),
+                LoginSighting::network_of(ip_address),
+            )
+            .await?;
+    }
+
+    let session_info = start_session(
+        &mut rng,
+        &clock,
+        &mut repo,
+        &user,
+        &user_password,
+        code.user_agent.clone(),
+    )
+    .await?;
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &session_info)
+        .await;
+
+    let cookie_jar = cookie_jar.update_session_info(
+        &SessionInfo::from_session(&session_info).remember(form.remember == "on"),
+    );
+    let reply = query.go_next(&url_builder);
+    Ok((cookie_jar, reply).into_response())
+}
+
+async fn render(
+    locale: DataLocale,
+    clock: &impl Clock,
+    code: UserLoginCode,
+    state: FormState<LoginCodeFormField>,
+    csrf_token: CsrfToken,
+    repo: &mut impl RepositoryAccess,
+    templates: &Templates,
+) -> Result<String, FancyError> {
+    let user_email = repo
+        .user_email()
+        .lookup(code.user_email_id)
+        .await?
+        .context("Could not find user email")?;
+
+    let ctx = LoginCodeContext::new(code, user_email.email, clock.now())
+        .with_form_state(state)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let content = templates.render_login_code(&ctx)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+    use hyper::{
+        header::{LOCATION, USER_AGENT},
+        Request, Response, StatusCode,
+    };
+    use mas_storage::{Clock, RepositoryAccess};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+    use zeroize::Zeroizing;
+
+    use crate::{
+        login_risk::{parse_network_list, LoginRiskConfig},
+        test_utils::{
+            init_tracing, test_site_config, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+        },
+        LoginRisk, SiteConfig,
+    };
+
+    /// Create the `john` user, with the `hunter2` password and a confirmed
+    /// primary email address
+    async fn create_john(state: &TestState) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".as_bytes().to_vec()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .add(&mut rng, &state.clock, &user, "john@example.com".to_owned())
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .mark_as_verified(&state.clock, user_email)
+            .await
+            .unwrap();
+        repo.user_email().set_as_primary(&user_email).await.unwrap();
+        repo.save().await.unwrap();
+    }
+
+    /// Submit the login form as `john` from the given IP address
+    async fn submit_login(state: &TestState, cookies: &CookieHelper, ip: &str) -> Response<String> {
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let request = Request::post("/login")
+            .header(USER_AGENT, "Firefox")
+            .header("X-Forwarded-For", ip)
+            .form(serde_json::json!({
+                "csrf": csrf_token,
+                "username": "john",
+                "password": "hunter2",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response
+    }
+
+    /// Log in as `john` from the given IP address, and return where the login
+    /// redirected to
+    async fn password_login(state: &TestState, cookies: &CookieHelper, ip: &str) -> String {
+        let response = submit_login(state, cookies, ip).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response
+            .headers()
+            .get(LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_code(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                email_otp_enabled: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        create_john(&state).await;
+
+        // The first login of the user doesn't need a code
+        let location = password_login(&state, &CookieHelper::new(), "192.0.2.1").await;
+        assert_eq!(location, "https://example.com/");
+
+        // Logging in from another network does
+        let cookies = CookieHelper::new();
+        let location = password_login(&state, &cookies, "198.51.100.1").await;
+        let path = location
+            .strip_prefix("https://example.com")
+            .unwrap()
+            .to_owned();
+        let id: Ulid = path
+            .strip_prefix("/login/code/")
+            .expect("login should ask for a code")
+            .parse()
+            .unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let code = repo
+            .user_login_code()
+            .lookup(id)
+            .await
+            .unwrap()
+            .expect("login code not found");
+        repo.cancel().await.unwrap();
+
+        let request = Request::get(&*path).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("john@example.com"));
+        let csrf_token = response.csrf_token();
+
+        // A wrong code is rejected
+        let wrong_code = if code.code == "000000" {
+            "111111"
+        } else {
+            "000000"
+        };
+        let request = Request::post(&*path).form(serde_json::json!({
+            "csrf": csrf_token,
+            "code": wrong_code,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("This code is not valid"));
+        let csrf_token = response.csrf_token();
+
+        // The right code starts the session
+        let request = Request::post(&*path).form(serde_json::json!({
+            "csrf": csrf_token,
+            "code": code.code,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let request = Request::get("/").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("john"));
+
+        // The code can't be used twice
+        let mut repo = state.repository().await.unwrap();
+        let code = repo
+            .user_login_code()
+            .lookup(id)
+            .await
+            .unwrap()
+            .expect("login code not found");
+        repo.cancel().await.unwrap();
+        assert!(!code.active(state.clock.now()));
+
+        // The network is now known, and doesn't need a code anymore
+        let location = password_login(&state, &CookieHelper::new(), "198.51.100.1").await;
+        assert_eq!(location, "https://example.com/");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_code_without_user_agent(pool: PgPool) {
+        init_tracing();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                email_otp_enabled: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        create_john(&state).await;
+
+        let location = password_login(&state, &CookieHelper::new(), "192.0.2.1").await;
+        assert_eq!(location, "https://example.com/");
+
+        // A login without a user agent, even from a known network, comes from
+        // an unknown device and needs a code
+        let cookies = CookieHelper::new();
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response.csrf_token();
+
+        let request = Request::post("/login")
+            .header("X-Forwarded-For", "192.0.2.1")
+            .form(serde_json::json!({
+                "csrf": csrf_token,
+                "username": "john",
+                "password": "hunter2",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert!(location.starts_with("https://example.com/login/code/"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_risk(pool: PgPool) {
+        init_tracing();
+        let mut state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                login_alerts_enabled: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        state.login_risk = LoginRisk::new(
+            LoginRiskConfig {
+                new_device_score: 40,
+                low_reputation_score: 40,
+                tor_exit_node_score: 60,
+                impossible_travel_score: 60,
+                impossible_travel_window: Duration::try_hours(2).unwrap(),
+                mfa_threshold: 40,
+                deny_threshold: 100,
+            },
+            Vec::new(),
+            parse_network_list("203.0.113.0/24").unwrap(),
+        );
+        create_john(&state).await;
+
+        // The first login of the user is allowed
+        let location = password_login(&state, &CookieHelper::new(), "192.0.2.1").await;
+        assert_eq!(location, "https://example.com/");
+
+        // A login from a new network needs a code
+        let location = password_login(&state, &CookieHelper::new(), "198.51.100.1").await;
+        assert!(location.starts_with("https://example.com/login/code/"));
+
+        // A login from a new network which is a Tor exit node is denied
+        let response = submit_login(&state, &CookieHelper::new(), "203.0.113.7").await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("This request was denied"));
+
+        // The known network is still allowed
+        let location = password_login(&state, &CookieHelper::new(), "192.0.2.1").await;
+        assert_eq!(location, "https://example.com/");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_bot(pool: PgPool) {
+        init_tracing();
+        let mut state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                email_otp_enabled: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        state.login_risk = LoginRisk::new(
+            LoginRiskConfig {
+                new_device_score: 40,
+                low_reputation_score: 40,
+                tor_exit_node_score: 60,
+                impossible_travel_score: 60,
+                impossible_travel_window: Duration::try_hours(2).unwrap(),
+                mfa_threshold: 40,
+                deny_threshold: 100,
+            },
+            Vec::new(),
+            parse_network_list("203.0.113.0/24").unwrap(),
+        );
+        create_john(&state).await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().find_by_username("john").await.unwrap().unwrap();
+        repo.user().set_bot(user, true).await.unwrap();
+        repo.save().await.unwrap();
+
+        // Bots never need a code, and their logins are not assessed
+        let location = password_login(&state, &CookieHelper::new(), "192.0.2.1").await;
+        assert_eq!(location, "https://example.com/");
+
+        let location = password_login(&state, &CookieHelper::new(), "198.51.100.1").await;
+        assert_eq!(location, "https://example.com/");
+
+        let location = password_login(&state, &CookieHelper::new(), "203.0.113.7").await;
+        assert_eq!(location, "https://example.com/");
+    }
+}
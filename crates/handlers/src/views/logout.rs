@@ -35,7 +35,9 @@ pub(crate) async fn post(
     activity_tracker: BoundActivityTracker,
     Form(form): Form<ProtectedForm<Option<PostAuthAction>>>,
 ) -> Result<impl IntoResponse, FancyError> {
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     let (session_info, mut cookie_jar) = cookie_jar.session_info();
 
@@ -0,0 +1,255 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Form, TypedHeader,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_data_model::{SiteConfig, UserAgent};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{CheckLoginJob, JobRepositoryExt},
+    BoxClock, BoxRepository, BoxRng,
+};
+use mas_templates::{
+    EmptyContext, ErrorContext, MagicLinkConfirmContext, MagicLinkExpiredContext, TemplateContext,
+    Templates,
+};
+use serde::Deserialize;
+
+use super::{cookie::MagicLinkSessions, magic_link_not_allowed};
+use crate::{BoundActivityTracker, GeoIp, PreferredLanguage};
+
+#[derive(Deserialize)]
+pub(crate) struct RouteQuery {
+    ticket: String,
+}
+
+#[tracing::instrument(name = "handlers.views.magic_link_confirm.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+) -> Result<Response, FancyError> {
+    if !site_config.magic_link_login_allowed {
+        return Err(magic_link_not_allowed());
+    }
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let link = repo
+        .user_magic_link()
+        .find_link(&query.ticket)
+        .await?
+        .context("Unknown ticket")?;
+
+    let session = repo
+        .user_magic_link()
+        .lookup_session(link.user_magic_link_session_id)
+        .await?
+        .context("Unknown session")?;
+
+    if session.consumed_at.is_some() {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_magic_link_consumed(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    if !link.active(clock.now()) {
+        let context = MagicLinkExpiredContext::new(session)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+        let rendered = templates.render_magic_link_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let user_email = repo
+        .user_email()
+        .lookup(link.user_email_id)
+        .await?
+        // Only allow confirmed email addresses
+        .filter(|email| email.confirmed_at.is_some())
+        .context("Unknown email address")?;
+
+    let user = repo
+        .user()
+        .lookup(user_email.user_id)
+        .await?
+        .context("Invalid user")?;
+
+    if !user.is_valid() {
+        // TODO: render a 'account locked' page
+        let rendered = templates.render_error(
+            &ErrorContext::new()
+                .with_code("Account locked")
+                .with_language(&locale),
+        )?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    // If the link is opened in another browser than the one which requested
+    // it, the page warns the user, as they may have been sent a link to log
+    // in someone else's browser
+    let same_device = MagicLinkSessions::load(&cookie_jar)
+        .find(session.id)
+        .is_ok();
+
+    let context = MagicLinkConfirmContext::new(user, session, same_device)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_magic_link_confirm(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.magic_link_confirm.post", skip_all, err)]
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    State(site_config): State<SiteConfig>,
+    State(geoip): State<GeoIp>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<RouteQuery>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    if !site_config.magic_link_login_allowed {
+        return Err(magic_link_not_allowed());
+    }
+
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    let () = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let link = repo
+        .user_magic_link()
+        .find_link(&query.ticket)
+        .await?
+        .context("Unknown ticket")?;
+
+    let session = repo
+        .user_magic_link()
+        .lookup_session(link.user_magic_link_session_id)
+        .await?
+        .context("Unknown session")?;
+
+    if session.consumed_at.is_some() {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_magic_link_consumed(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    if !link.active(clock.now()) {
+        let context = MagicLinkExpiredContext::new(session)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+        let rendered = templates.render_magic_link_expired(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let user_email = repo
+        .user_email()
+        .lookup(link.user_email_id)
+        .await?
+        // Only allow confirmed email addresses
+        .filter(|email| email.confirmed_at.is_some())
+        .context("Unknown email address")?;
+
+    let user = repo
+        .user()
+        .lookup(user_email.user_id)
+        .await?
+        .context("Invalid user")?;
+
+    if !user.is_valid() {
+        // TODO: render a 'account locked' page
+        let rendered = templates.render_error(
+            &ErrorContext::new()
+                .with_code("Account locked")
+                .with_language(&locale),
+        )?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    // Mark the session as consumed, so that the other links sent for it can't
+    // be used anymore
+    let session = repo
+        .user_magic_link()
+        .consume_link(&clock, link.clone(), session)
+        .await?;
+
+    // Start a new browser session, authenticated by the link
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, user_agent)
+        .await?;
+
+    repo.browser_session()
+        .authenticate_with_magic_link(&mut rng, &clock, &browser_session, &link)
+        .await?;
+
+    if site_config.login_alerts_enabled {
+        repo.job()
+            .schedule_job(
+                CheckLoginJob::new(&browser_session, activity_tracker.ip())
+                    .with_location(activity_tracker.ip().and_then(|ip| geoip.lookup(ip)))
+                    .with_language(locale.to_string()),
+            )
+            .await?;
+    }
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &browser_session)
+        .await;
+
+    // Only resume the pending action if the link was requested from this
+    // browser, else go to the homepage
+    let sessions = MagicLinkSessions::load(&cookie_jar);
+    let reply = match sessions.find(session.id) {
+        Ok(Some(action)) => action.go_next(&url_builder),
+        Ok(None) | Err(_) => url_builder.redirect(&mas_router::Index),
+    };
+
+    let cookie_jar = sessions
+        .consume(session.id)
+        .save(cookie_jar, &clock)
+        .set_session(&browser_session);
+
+    Ok((cookie_jar, reply).into_response())
+}
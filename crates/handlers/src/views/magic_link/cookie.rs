@@ -0,0 +1,155 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Duration, Utc};
+use mas_axum_utils::cookies::CookieJar;
+use mas_router::PostAuthAction;
+use mas_storage::Clock;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Name of the cookie
+static COOKIE_NAME: &str = "magic-link-sessions";
+
+/// Sessions are forgotten after 30 minutes, which leaves enough time to
+/// receive the email and use the link before it expires
+static SESSION_MAX_TIME: Duration = Duration::microseconds(30 * 60 * 1000 * 1000);
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Payload {
+    session: Ulid,
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl Payload {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        let Ok(ts) = self.session.timestamp_ms().try_into() else {
+            return true;
+        };
+        let Some(when) = DateTime::from_timestamp_millis(ts) else {
+            return true;
+        };
+        now - when > SESSION_MAX_TIME
+    }
+}
+
+/// The magic link sessions which were started from this browser
+///
+/// This is used to tell whether a link is opened on the same device as the
+/// one which requested it, and to resume the action the user was doing before
+/// logging in.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct MagicLinkSessions(Vec<Payload>);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("magic link session not found")]
+pub struct MagicLinkSessionNotFound;
+
+impl MagicLinkSessions {
+    /// Load the magic link sessions cookie
+    pub fn load(cookie_jar: &CookieJar) -> Self {
+        match cookie_jar.load(COOKIE_NAME) {
+            Ok(Some(sessions)) => sessions,
+            Ok(None) => Self::default(),
+            Err(e) => {
+                tracing::warn!("Invalid magic link sessions cookie: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the magic link sessions to the cookie jar
+    pub fn save<C>(self, cookie_jar: CookieJar, clock: &C) -> CookieJar
+    where
+        C: Clock,
+    {
+        let this = self.expire(clock.now());
+        cookie_jar.save(COOKIE_NAME, &this, false)
+    }
+
+    fn expire(mut self, now: DateTime<Utc>) -> Self {
+        self.0.retain(|p| !p.expired(now));
+        self
+    }
+
+    /// Add a new session, with the action to do once logged in
+    pub fn add(mut self, session: Ulid, post_auth_action: Option<PostAuthAction>) -> Self {
+        self.0.push(Payload {
+            session,
+            post_auth_action,
+        });
+        self
+    }
+
+    /// Find a session started from this browser, returning the action to do
+    /// once logged in
+    pub fn find(&self, session: Ulid) -> Result<Option<&PostAuthAction>, MagicLinkSessionNotFound> {
+        self.0
+            .iter()
+            .find(|p| p.session == session)
+            .map(|p| p.post_auth_action.as_ref())
+            .ok_or(MagicLinkSessionNotFound)
+    }
+
+    /// Forget a session once it was used
+    pub fn consume(mut self, session: Ulid) -> Self {
+        self.0.retain(|p| p.session != session);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use super::*;
+
+    #[test]
+    fn test_magic_link_sessions_cookie() {
+        let now = chrono::Utc
+            .with_ymd_and_hms(2018, 1, 18, 1, 30, 22)
+            .unwrap();
+        let mut rng = ChaChaRng::seed_from_u64(42);
+
+        let sessions = MagicLinkSessions::default();
+
+        let first_session = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        let sessions = sessions.add(first_session, Some(PostAuthAction::ChangePassword));
+
+        let now = now + Duration::microseconds(20 * 60 * 1000 * 1000);
+
+        let second_session = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        let sessions = sessions.add(second_session, None);
+
+        let sessions = sessions.expire(now);
+        assert!(matches!(
+            sessions.find(first_session),
+            Ok(Some(PostAuthAction::ChangePassword))
+        ));
+        assert!(matches!(sessions.find(second_session), Ok(None)));
+
+        // The first session expires after 30 minutes
+        let now = now + Duration::microseconds(15 * 60 * 1000 * 1000);
+        let sessions = sessions.expire(now);
+        assert!(sessions.find(first_session).is_err());
+        assert!(matches!(sessions.find(second_session), Ok(None)));
+
+        // Consuming a session forgets it
+        let sessions = sessions.consume(second_session);
+        assert!(sessions.find(second_session).is_err());
+    }
+}
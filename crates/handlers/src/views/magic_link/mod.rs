@@ -0,0 +1,32 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use mas_axum_utils::FancyError;
+use mas_templates::ErrorContext;
+
+pub mod confirm;
+mod cookie;
+pub mod progress;
+pub mod start;
+
+fn magic_link_not_allowed() -> FancyError {
+    // XXX: this may not be the best error message, it's not translatable
+    FancyError::new(
+        ErrorContext::new()
+            .with_description("Login by email link is not allowed".to_owned())
+            .with_details(
+                "The site configuration does not allow logging in by email link".to_owned(),
+            ),
+    )
+}
@@ -0,0 +1,186 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Form, TypedHeader,
+};
+use hyper::StatusCode;
+use lettre::Address;
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError, SessionInfoExt,
+};
+use mas_data_model::{SiteConfig, UserAgent};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{JobRepositoryExt, SendMagicLinkEmailsJob},
+    BoxClock, BoxRepository, BoxRng,
+};
+use mas_templates::{
+    FieldError, FormError, FormState, MagicLinkStartContext, MagicLinkStartFormField,
+    TemplateContext, Templates,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{cookie::MagicLinkSessions, magic_link_not_allowed};
+use crate::{
+    views::shared::OptionalPostAuthAction, BoundActivityTracker, Limiter, PreferredLanguage,
+};
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct StartMagicLinkForm {
+    email: String,
+}
+
+#[tracing::instrument(name = "handlers.views.magic_link_start.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+) -> Result<Response, FancyError> {
+    if !site_config.magic_link_login_allowed {
+        return Err(magic_link_not_allowed());
+    }
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+    if maybe_session.is_some() {
+        return Ok((cookie_jar, query.go_next(&url_builder)).into_response());
+    }
+
+    let context = MagicLinkStartContext::new()
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    repo.save().await?;
+
+    let rendered = templates.render_magic_link_start(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.magic_link_start.post", skip_all, err)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    user_agent: TypedHeader<headers::UserAgent>,
+    activity_tracker: BoundActivityTracker,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(limiter): State<Limiter>,
+    PreferredLanguage(locale): PreferredLanguage,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<StartMagicLinkForm>>,
+) -> Result<Response, FancyError> {
+    if !site_config.magic_link_login_allowed {
+        return Err(magic_link_not_allowed());
+    }
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let maybe_session = session_info.load_session(&mut repo).await?;
+    if maybe_session.is_some() {
+        return Ok((cookie_jar, query.go_next(&url_builder)).into_response());
+    }
+
+    let user_agent = UserAgent::parse(user_agent.as_str().to_owned());
+    let ip_address = activity_tracker.ip();
+
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
+    let mut form_state = FormState::from_form(&form);
+
+    if Address::from_str(&form.email).is_err() {
+        form_state =
+            form_state.with_error_on_field(MagicLinkStartFormField::Email, FieldError::Invalid);
+    }
+
+    if !form_state.is_valid() {
+        repo.save().await?;
+        let context = MagicLinkStartContext::new()
+            .with_form_state(form_state)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        let rendered = templates.render_magic_link_start(&context)?;
+
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    // This shares the limits of account recovery, as both send emails to an
+    // arbitrary address
+    if let Err(e) = limiter
+        .check_account_recovery(&clock, ip_address, &form.email)
+        .await
+    {
+        repo.save().await?;
+        let form_state = form_state.with_error_on_form(FormError::RateLimitExceeded);
+        let context = MagicLinkStartContext::new()
+            .with_form_state(form_state)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        let rendered = templates.render_magic_link_start(&context)?;
+
+        return Ok((StatusCode::TOO_MANY_REQUESTS, e, cookie_jar, Html(rendered)).into_response());
+    }
+
+    let session = repo
+        .user_magic_link()
+        .add_session(
+            &mut rng,
+            &clock,
+            form.email,
+            user_agent,
+            ip_address,
+            locale.to_string(),
+        )
+        .await?;
+
+    repo.job()
+        .schedule_job(SendMagicLinkEmailsJob::new(&session))
+        .await?;
+
+    repo.save().await?;
+
+    // Remember that the session was started from this browser, and what to do
+    // once logged in
+    let cookie_jar = MagicLinkSessions::load(&cookie_jar)
+        .add(session.id, query.post_auth_action)
+        .save(cookie_jar, &clock);
+
+    Ok((
+        cookie_jar,
+        url_builder.redirect(&mas_router::MagicLinkProgress::new(session.id)),
+    )
+        .into_response())
+}
@@ -14,9 +14,16 @@
 
 pub mod account;
 pub mod app;
+pub mod certificate;
+pub mod email_change;
+pub mod impersonate;
 pub mod index;
 pub mod login;
+pub mod login_alert;
+pub mod login_code;
 pub mod logout;
+pub mod magic_link;
+pub mod negotiate;
 pub mod reauth;
 pub mod recovery;
 pub mod register;
@@ -0,0 +1,75 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use chrono::{DateTime, Duration, Utc};
+use mas_axum_utils::cookies::CookieJar;
+use mas_router::PostAuthAction;
+use mas_storage::Clock;
+use serde::{Deserialize, Serialize};
+
+/// Name of the cookie
+static COOKIE_NAME: &str = "negotiate-attempt";
+
+/// How long the login page shows the form instead of trying SPNEGO again,
+/// after an attempt was made
+static ATTEMPT_TTL: Duration = Duration::microseconds(5 * 60 * 1000 * 1000);
+
+/// Remembers that this browser recently tried to log in through SPNEGO
+///
+/// If the browser has no Kerberos ticket, the reverse proxy sends it back to
+/// the login page, which should then show the usual form instead of starting
+/// the negotiation over. As the reverse proxy can't keep the query parameters
+/// when doing so, the action to do once logged in is kept here as well.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct NegotiateAttempt {
+    attempted_at: DateTime<Utc>,
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl NegotiateAttempt {
+    /// Start a new attempt, with the action to do once logged in
+    pub fn new<C>(clock: &C, post_auth_action: Option<PostAuthAction>) -> Self
+    where
+        C: Clock,
+    {
+        Self {
+            attempted_at: clock.now(),
+            post_auth_action,
+        }
+    }
+
+    /// Load the last attempt from the cookie jar, if it is recent enough
+    pub fn load<C>(cookie_jar: &CookieJar, clock: &C) -> Option<Self>
+    where
+        C: Clock,
+    {
+        match cookie_jar.load::<Self>(COOKIE_NAME) {
+            Ok(Some(attempt)) if clock.now() - attempt.attempted_at < ATTEMPT_TTL => Some(attempt),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("Invalid negotiate attempt cookie: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Save the attempt to the cookie jar
+    pub fn save(&self, cookie_jar: CookieJar) -> CookieJar {
+        cookie_jar.save(COOKIE_NAME, self, false)
+    }
+
+    /// The action to do once logged in
+    pub fn into_post_auth_action(self) -> Option<PostAuthAction> {
+        self.post_auth_action
+    }
+}
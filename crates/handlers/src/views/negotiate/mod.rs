@@ -0,0 +1,249 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+use mas_axum_utils::{cookies::CookieJar, FancyError, SessionInfoExt};
+use mas_data_model::{SiteConfig, UserAgent};
+use mas_router::UrlBuilder;
+use mas_storage::{
+    job::{CheckLoginJob, JobRepositoryExt},
+    user::{BrowserSessionRepository, UserRepository},
+    BoxClock, BoxRepository, BoxRng,
+};
+use mas_templates::ErrorContext;
+
+use super::shared::OptionalPostAuthAction;
+use crate::{BoundActivityTracker, GeoIp, PreferredLanguage};
+
+mod cookie;
+
+pub(crate) use self::cookie::NegotiateAttempt;
+
+/// The Kerberos principal forwarded by the reverse proxy which negotiated a
+/// ticket with the browser
+///
+/// This is only set if SPNEGO login is enabled and the request comes from one
+/// of the trusted proxies, as the header could otherwise be forged by the
+/// client.
+#[derive(Debug, Clone, Default)]
+pub struct ForwardedPrincipal(pub Option<String>);
+
+fn negotiate_not_allowed() -> FancyError {
+    // XXX: this may not be the best error message, it's not translatable
+    FancyError::new(
+        ErrorContext::new()
+            .with_description("Kerberos login is not allowed".to_owned())
+            .with_details(
+                "The site configuration does not allow logging in with Kerberos".to_owned(),
+            ),
+    )
+}
+
+#[tracing::instrument(name = "handlers.views.negotiate.get", skip_all, err)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(geoip): State<GeoIp>,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ForwardedPrincipal(principal): ForwardedPrincipal,
+) -> Result<Response, FancyError> {
+    let Some(config) = &site_config.spnego_login else {
+        return Err(negotiate_not_allowed());
+    };
+
+    // Whatever the outcome, the login page should not send the browser here
+    // again right away
+    let cookie_jar = NegotiateAttempt::new(&clock, query.post_auth_action.clone()).save(cookie_jar);
+    let fallback = url_builder.redirect(&mas_router::Login::from(query.post_auth_action.clone()));
+
+    let Some(principal) = principal else {
+        tracing::info!("No Kerberos principal was forwarded by the reverse proxy");
+        return Ok((cookie_jar, fallback).into_response());
+    };
+
+    let Some(username) = config.username_for(&principal) else {
+        tracing::info!(%principal, "Kerberos principal is not in a mapped realm");
+        return Ok((cookie_jar, fallback).into_response());
+    };
+
+    let Some(user) = repo
+        .user()
+        .find_by_username(username)
+        .await?
+        .filter(mas_data_model::User::is_valid)
+    else {
+        tracing::info!(%principal, "Kerberos principal does not map to a valid user");
+        return Ok((cookie_jar, fallback).into_response());
+    };
+
+    let user_agent = user_agent.map(|ua| UserAgent::parse(ua.as_str().to_owned()));
+
+    // Start a new browser session, authenticated by the Kerberos ticket
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, user_agent)
+        .await?;
+
+    repo.browser_session()
+        .authenticate_with_kerberos(&mut rng, &clock, &browser_session, &principal)
+        .await?;
+
+    if site_config.login_alerts_enabled {
+        repo.job()
+            .schedule_job(
+                CheckLoginJob::new(&browser_session, activity_tracker.ip())
+                    .with_location(activity_tracker.ip().and_then(|ip| geoip.lookup(ip)))
+                    .with_language(locale.to_string()),
+            )
+            .await?;
+    }
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &browser_session)
+        .await;
+
+    let cookie_jar = cookie_jar.set_session(&browser_session);
+    let reply = query.go_next(&url_builder);
+    Ok((cookie_jar, reply).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        header::{CONTENT_TYPE, LOCATION},
+        Request, StatusCode,
+    };
+    use mas_data_model::SpnegoLoginConfig;
+    use mas_storage::{user::UserRepository, RepositoryAccess};
+    use sqlx::PgPool;
+
+    use crate::{
+        test_utils::{
+            init_tracing, test_site_config, CookieHelper, RequestBuilderExt, ResponseExt, TestState,
+        },
+        SiteConfig,
+    };
+
+    async fn state(pool: PgPool) -> TestState {
+        TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                spnego_login: Some(SpnegoLoginConfig {
+                    realms: vec!["EXAMPLE.COM".to_owned()],
+                    principal_header: "X-Remote-User".to_owned(),
+                }),
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_negotiate_login(pool: PgPool) {
+        init_tracing();
+        let state = state(pool).await;
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The login page first tries to negotiate a Kerberos ticket
+        let request = cookies.with_cookies(Request::get("/login").empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/login/negotiate");
+
+        // The reverse proxy forwards the principal
+        let request = Request::get("/login/negotiate")
+            .header("X-Remote-User", "john@EXAMPLE.COM")
+            .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/");
+
+        // The user is now logged in
+        let request = cookies.with_cookies(Request::get("/").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("john"));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_negotiate_fallback(pool: PgPool) {
+        init_tracing();
+        let state = state(pool).await;
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // A principal from a realm which isn't mapped is sent back to the login
+        // page
+        let request = Request::get("/login/negotiate")
+            .header("X-Remote-User", "john@OTHER.COM")
+            .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/login");
+
+        // Which now shows the login form instead of negotiating again
+        let request = cookies.with_cookies(Request::get("/login").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+
+        // Same without any principal
+        let request = cookies.with_cookies(Request::get("/login/negotiate").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/login");
+
+        // Once the attempt is old enough, the login page negotiates again
+        state
+            .clock
+            .advance(chrono::Duration::try_minutes(10).unwrap());
+        let request = cookies.with_cookies(Request::get("/login").empty());
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/login/negotiate");
+    }
+}
@@ -26,7 +26,10 @@ use mas_axum_utils::{
 use mas_data_model::SiteConfig;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
-use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_storage::{
+    job::{JobRepositoryExt, SendPasswordChangeNotificationJob},
+    BoxClock, BoxRepository, BoxRng,
+};
 use mas_templates::{
     EmptyContext, ErrorContext, FieldError, FormState, RecoveryExpiredContext,
     RecoveryFinishContext, RecoveryFinishFormField, TemplateContext, Templates,
@@ -47,6 +50,7 @@ pub(crate) struct RouteForm {
     new_password_confirm: String,
 }
 
+#[tracing::instrument(name = "handlers.views.recovery_finish.get", skip_all, err)]
 pub(crate) async fn get(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -124,6 +128,7 @@ pub(crate) async fn get(
     Ok((cookie_jar, Html(rendered)).into_response())
 }
 
+#[tracing::instrument(name = "handlers.views.recovery_finish.post", skip_all, err)]
 #[allow(clippy::too_many_lines)]
 pub(crate) async fn post(
     mut rng: BoxRng,
@@ -197,7 +202,9 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(rendered)).into_response());
     }
 
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     // Check the form
     let mut form_state = FormState::from_form(&form);
@@ -255,6 +262,12 @@ pub(crate) async fn post(
         .add(&mut rng, &clock, &user, version, hashed_password, None)
         .await?;
 
+    repo.job()
+        .schedule_job(
+            SendPasswordChangeNotificationJob::new(&user).with_language(locale.to_string()),
+        )
+        .await?;
+
     // Mark the session as consumed
     repo.user_recovery()
         .consume_ticket(&clock, ticket, session)
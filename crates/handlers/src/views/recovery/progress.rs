@@ -33,6 +33,12 @@ use ulid::Ulid;
 
 use crate::PreferredLanguage;
 
+#[tracing::instrument(
+    name = "handlers.views.recovery_progress.get",
+    fields(user_recovery_session.id = %id),
+    skip_all,
+    err,
+)]
 pub(crate) async fn get(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -85,6 +91,12 @@ pub(crate) async fn get(
     Ok((cookie_jar, Html(rendered)).into_response())
 }
 
+#[tracing::instrument(
+    name = "handlers.views.recovery_progress.post",
+    fields(user_recovery_session.id = %id),
+    skip_all,
+    err,
+)]
 pub(crate) async fn post(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -128,7 +140,9 @@ pub(crate) async fn post(
     }
 
     // Verify the CSRF token
-    let () = cookie_jar.verify_form(&clock, form)?;
+    let () = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     // Schedule a new batch of emails
     repo.job()
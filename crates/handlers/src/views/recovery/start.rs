@@ -19,6 +19,7 @@ use axum::{
     response::{Html, IntoResponse, Response},
     Form, TypedHeader,
 };
+use hyper::StatusCode;
 use lettre::Address;
 use mas_axum_utils::{
     cookies::CookieJar,
@@ -32,18 +33,19 @@ use mas_storage::{
     BoxClock, BoxRepository, BoxRng,
 };
 use mas_templates::{
-    EmptyContext, FieldError, FormState, RecoveryStartContext, RecoveryStartFormField,
+    EmptyContext, FieldError, FormError, FormState, RecoveryStartContext, RecoveryStartFormField,
     TemplateContext, Templates,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{BoundActivityTracker, PreferredLanguage};
+use crate::{BoundActivityTracker, Limiter, PreferredLanguage};
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct StartRecoveryForm {
     email: String,
 }
 
+#[tracing::instrument(name = "handlers.views.recovery_start.get", skip_all, err)]
 pub(crate) async fn get(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -80,6 +82,7 @@ pub(crate) async fn get(
     Ok((cookie_jar, Html(rendered)).into_response())
 }
 
+#[tracing::instrument(name = "handlers.views.recovery_start.post", skip_all, err)]
 pub(crate) async fn post(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -89,6 +92,7 @@ pub(crate) async fn post(
     State(site_config): State<SiteConfig>,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(limiter): State<Limiter>,
     PreferredLanguage(locale): PreferredLanguage,
     cookie_jar: CookieJar,
     Form(form): Form<ProtectedForm<StartRecoveryForm>>,
@@ -111,7 +115,9 @@ pub(crate) async fn post(
     let user_agent = UserAgent::parse(user_agent.as_str().to_owned());
     let ip_address = activity_tracker.ip();
 
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
     let mut form_state = FormState::from_form(&form);
 
     if Address::from_str(&form.email).is_err() {
@@ -131,6 +137,22 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(rendered)).into_response());
     }
 
+    if let Err(e) = limiter
+        .check_account_recovery(&clock, ip_address, &form.email)
+        .await
+    {
+        repo.save().await?;
+        let form_state = form_state.with_error_on_form(FormError::RateLimitExceeded);
+        let context = RecoveryStartContext::new()
+            .with_form_state(form_state)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        let rendered = templates.render_recovery_start(&context)?;
+
+        return Ok((StatusCode::TOO_MANY_REQUESTS, e, cookie_jar, Html(rendered)).into_response());
+    }
+
     let session = repo
         .user_recovery()
         .add_session(
@@ -46,8 +46,10 @@ use zeroize::Zeroizing;
 
 use super::shared::OptionalPostAuthAction;
 use crate::{
-    captcha::Form as CaptchaForm, passwords::PasswordManager, BoundActivityTracker,
-    PreferredLanguage, SiteConfig,
+    captcha::Form as CaptchaForm,
+    passwords::PasswordManager,
+    spam_check::{SpamChecker, Verdict},
+    BoundActivityTracker, Limiter, PreferredLanguage, SiteConfig,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -122,6 +124,8 @@ pub(crate) async fn post(
     State(site_config): State<SiteConfig>,
     State(homeserver): State<BoxHomeserverConnection>,
     State(http_client_factory): State<HttpClientFactory>,
+    State(limiter): State<Limiter>,
+    State(spam_checker): State<SpamChecker>,
     mut policy: Policy,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
@@ -135,7 +139,9 @@ pub(crate) async fn post(
         return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
     }
 
-    let form = cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar
+        .verify_form(&clock, form)
+        .map_err(FancyError::csrf)?;
 
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
 
@@ -251,8 +257,60 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    // Only count registration attempts which would otherwise have succeeded
+    if let Err(e) = limiter
+        .check_registration(&clock, activity_tracker.ip())
+        .await
+    {
+        let state = state.with_error_on_form(FormError::RateLimitExceeded);
+        let content = render(
+            locale,
+            RegisterContext::default().with_form_state(state),
+            query,
+            csrf_token,
+            &mut repo,
+            &templates,
+            site_config.captcha.clone(),
+        )
+        .await?;
+
+        return Ok((StatusCode::TOO_MANY_REQUESTS, e, cookie_jar, Html(content)).into_response());
+    }
+
+    let verdict = spam_checker
+        .check_registration(
+            &activity_tracker,
+            user_agent.as_ref(),
+            &form.username,
+            Some(&form.email),
+            "password",
+        )
+        .await;
+
+    if verdict == Verdict::Deny {
+        let state = state.with_error_on_form(FormError::Denied);
+        let content = render(
+            locale,
+            RegisterContext::default().with_form_state(state),
+            query,
+            csrf_token,
+            &mut repo,
+            &templates,
+            site_config.captcha.clone(),
+        )
+        .await?;
+
+        return Ok((cookie_jar, Html(content)).into_response());
+    }
+
     let user = repo.user().add(&mut rng, &clock, form.username).await?;
 
+    let user = if verdict == Verdict::ShadowBan {
+        repo.user().shadow_ban(&clock, user).await?
+    } else {
+        user
+    };
+
     if let Some(tos_uri) = &site_config.tos_uri {
         repo.user_terms()
             .accept_terms(&mut rng, &clock, &user, tos_uri.clone())
@@ -286,9 +344,11 @@ pub(crate) async fn post(
         .schedule_job(VerifyEmailJob::new(&user_email).with_language(locale.to_string()))
         .await?;
 
-    repo.job()
-        .schedule_job(ProvisionUserJob::new(&user))
-        .await?;
+    let mut provision_job = ProvisionUserJob::new(&user);
+    if verdict == Verdict::ShadowBan {
+        provision_job = provision_job.shadow_ban();
+    }
+    repo.job().schedule_job(provision_job).await?;
 
     repo.save().await?;
 
@@ -386,15 +446,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Submit the registration form
         let request = Request::post(&*mas_router::Register::default().path_and_query()).form(
@@ -436,15 +488,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Submit the registration form
         let request = Request::post(&*mas_router::Register::default().path_and_query()).form(
@@ -477,15 +521,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Submit the registration form
         let request = Request::post(&*mas_router::Register::default().path_and_query()).form(
@@ -528,15 +564,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Submit the registration form
         let request = Request::post(&*mas_router::Register::default().path_and_query()).form(
@@ -571,15 +599,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
-        // Extract the CSRF token from the response body
-        let csrf_token = response
-            .body()
-            .split("name=\"csrf\" value=\"")
-            .nth(1)
-            .unwrap()
-            .split('\"')
-            .next()
-            .unwrap();
+        let csrf_token = response.csrf_token();
 
         // Reserve "john" on the homeserver
         state.homeserver_connection.reserve_localpart("john").await;
@@ -13,12 +13,15 @@
 // limitations under the License.
 
 use anyhow::Context;
+use chrono::Duration;
+use mas_data_model::{BrowserSession, SiteConfig};
 use mas_router::{PostAuthAction, Route, UrlBuilder};
 use mas_storage::{
     compat::CompatSsoLoginRepository,
     oauth2::OAuth2AuthorizationGrantRepository,
     upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository},
-    RepositoryAccess,
+    user::{BrowserSessionRepository, UserPasswordRepository},
+    Clock, RepositoryAccess,
 };
 use mas_templates::{PostAuthContext, PostAuthContextInner};
 use serde::{Deserialize, Serialize};
@@ -73,6 +76,16 @@ impl OptionalPostAuthAction {
                 PostAuthContextInner::ContinueDeviceCodeGrant { grant }
             }
 
+            PostAuthAction::ContinueBackchannelAuthenticationGrant { id } => {
+                let grant = repo
+                    .oauth2_backchannel_authentication_grant()
+                    .lookup(id)
+                    .await?
+                    .context("Failed to load backchannel authentication grant")?;
+                let grant = Box::new(grant);
+                PostAuthContextInner::ContinueBackchannelAuthenticationGrant { grant }
+            }
+
             PostAuthAction::ContinueCompatSsoLogin { id } => {
                 let login = repo
                     .compat_sso_login()
@@ -85,6 +98,10 @@ impl OptionalPostAuthAction {
 
             PostAuthAction::ChangePassword => PostAuthContextInner::ChangePassword,
 
+            PostAuthAction::ChangeEmail => PostAuthContextInner::ChangeEmail,
+
+            PostAuthAction::ExportData => PostAuthContextInner::ExportData,
+
             PostAuthAction::LinkUpstream { id } => {
                 let link = repo
                     .upstream_oauth_link()
@@ -112,3 +129,63 @@ impl OptionalPostAuthAction {
         }))
     }
 }
+
+/// Check whether the user has to enter their password again before performing
+/// a sensitive action, because the last authentication of the browser session
+/// is older than what the site configuration allows
+///
+/// Users who can't re-authenticate with a password are never asked to.
+pub(crate) async fn requires_reauth<R: RepositoryAccess>(
+    repo: &mut R,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    session: &BrowserSession,
+) -> Result<bool, R::Error> {
+    let Some(ttl) = site_config.sensitive_action_reauth_ttl else {
+        return Ok(false);
+    };
+
+    requires_reauth_within(repo, clock, site_config, session, ttl).await
+}
+
+/// Check whether the user has to enter their password again before approving
+/// a reset of their cross-signing keys
+///
+/// Unlike other sensitive actions, this always requires an authentication from
+/// the last few minutes, even if no window is configured.
+pub(crate) async fn requires_cross_signing_reset_reauth<R: RepositoryAccess>(
+    repo: &mut R,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    session: &BrowserSession,
+) -> Result<bool, R::Error> {
+    let max_ttl = Duration::minutes(5);
+    let ttl = site_config
+        .sensitive_action_reauth_ttl
+        .map_or(max_ttl, |ttl| ttl.min(max_ttl));
+
+    requires_reauth_within(repo, clock, site_config, session, ttl).await
+}
+
+async fn requires_reauth_within<R: RepositoryAccess>(
+    repo: &mut R,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    session: &BrowserSession,
+    ttl: Duration,
+) -> Result<bool, R::Error> {
+    if !site_config.password_login_enabled {
+        return Ok(false);
+    }
+
+    if repo.user_password().active(&session.user).await?.is_none() {
+        return Ok(false);
+    }
+
+    let last_authentication = repo
+        .browser_session()
+        .get_last_authentication(session)
+        .await?;
+    let fresh = last_authentication.is_some_and(|auth| auth.created_at > clock.now() - ttl);
+    Ok(!fresh)
+}
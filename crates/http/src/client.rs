@@ -12,6 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::future::BoxFuture;
 use hyper::client::{
     connect::dns::{GaiResolver, Name},
     HttpConnector,
@@ -23,11 +30,23 @@ use mas_tower::{
     InFlightCounterService, TraceLayer, TraceService,
 };
 use opentelemetry_semantic_conventions::trace::SERVER_ADDRESS;
-use tower::Layer;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        VerifierBuilderError, WebPkiServerVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
 use tracing::Span;
 
+use crate::proxy::{Proxy, ProxyConnector};
+
 pub type UntracedClient<B> = hyper::Client<UntracedConnector, B>;
 pub type TracedClient<B> = hyper::Client<TracedConnector, B>;
+pub type PublicTracedClient<B> = hyper::Client<PublicTracedConnector, B>;
 
 /// Create a basic Hyper HTTP & HTTPS client without any tracing
 #[must_use]
@@ -43,13 +62,219 @@ where
 pub type TraceResolver<S> =
     InFlightCounterService<DurationRecorderService<TraceService<S, FnWrapper<fn(&Name) -> Span>>>>;
 pub type UntracedConnector = HttpsConnector<HttpConnector<GaiResolver>>;
-pub type TracedConnector = HttpsConnector<HttpConnector<TraceResolver<GaiResolver>>>;
+pub type TracedConnector =
+    HttpsConnector<ProxyConnector<HttpConnector<TraceResolver<GaiResolver>>>>;
+pub type PublicTracedConnector =
+    HttpsConnector<HttpConnector<TraceResolver<PublicResolver<GaiResolver>>>>;
 
-/// Create a traced HTTP and HTTPS connector
+/// Check if the given IP address is publicly routable, i.e. not a loopback,
+/// private, link-local, or otherwise reserved address
 #[must_use]
-pub fn make_traced_connector() -> TracedConnector
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // "This network", 0.0.0.0/8
+                || a == 0
+                // Shared address space, 100.64.0.0/10
+                || (a == 100 && (b & 0b1100_0000) == 0b0100_0000)
+                // Reserved, 240.0.0.0/4
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+
+            let [a, b, ..] = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (a & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (a & 0xffc0) == 0xfe80
+                // Documentation, 2001:db8::/32
+                || (a == 0x2001 && b == 0x0db8))
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{0} does not resolve to any public address")]
+pub struct NoPublicAddressError(Name);
+
+/// A DNS resolver which filters out the addresses which are not publicly
+/// routable, to avoid making requests to internal services on behalf of
+/// untrusted parties
+#[derive(Debug, Clone)]
+pub struct PublicResolver<S> {
+    inner: S,
+}
+
+impl<S> PublicResolver<S> {
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<Name> for PublicResolver<S>
 where
+    S: Service<Name>,
+    S::Response: Iterator<Item = SocketAddr>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
 {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let future = self.inner.call(name.clone());
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = future
+                .await
+                .map_err(Into::into)?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(NoPublicAddressError(name).into());
+            }
+
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExtraRootsError {
+    #[error("invalid root certificate")]
+    InvalidCertificate(#[source] rustls::Error),
+
+    #[error("could not build the certificate verifier")]
+    Verifier(#[from] VerifierBuilderError),
+}
+
+/// Options applied to the outbound connections
+#[derive(Debug, Clone, Default)]
+pub struct ConnectorOptions {
+    proxy: Option<Proxy>,
+    extra_roots: Option<Arc<WebPkiServerVerifier>>,
+}
+
+impl ConnectorOptions {
+    /// Send the requests through the given proxy
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust the given root certificates, on top of the ones trusted by the
+    /// platform
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the certificates is invalid
+    pub fn with_extra_roots(
+        mut self,
+        roots: impl IntoIterator<Item = CertificateDer<'static>>,
+    ) -> Result<Self, ExtraRootsError> {
+        let mut store = RootCertStore::empty();
+        for root in roots {
+            store
+                .add(root)
+                .map_err(ExtraRootsError::InvalidCertificate)?;
+        }
+
+        if !store.is_empty() {
+            self.extra_roots = Some(WebPkiServerVerifier::builder(Arc::new(store)).build()?);
+        }
+
+        Ok(self)
+    }
+
+    fn tls_config(&self) -> rustls::ClientConfig {
+        let Some(extra_roots) = &self.extra_roots else {
+            return rustls_platform_verifier::tls_config();
+        };
+
+        let verifier = ExtraRootsVerifier {
+            platform: rustls_platform_verifier::Verifier::new(),
+            extra_roots: extra_roots.clone(),
+        };
+
+        rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth()
+    }
+}
+
+/// A certificate verifier which trusts the certificates trusted by the
+/// platform, as well as the ones issued by some extra root certificates
+#[derive(Debug)]
+struct ExtraRootsVerifier {
+    platform: rustls_platform_verifier::Verifier,
+    extra_roots: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for ExtraRootsVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.platform
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .or_else(|error| {
+                // Report the error from the platform verifier if the extra
+                // roots don't help either
+                self.extra_roots
+                    .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+                    .map_err(|_| error)
+            })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.platform.supported_verify_schemes()
+    }
+}
+
+fn trace_resolver<S>(resolver: S) -> TraceResolver<S> {
     let in_flight_counter = InFlightCounterLayer::new("dns.resolve.active_requests");
     let duration_recorder = DurationRecorderLayer::new("dns.resolve.duration");
     let trace_layer = TraceLayer::from_fn(
@@ -63,10 +288,33 @@ where
         }) as fn(&Name) -> Span,
     );
 
-    let resolver = (in_flight_counter, duration_recorder, trace_layer).layer(GaiResolver::new());
+    (in_flight_counter, duration_recorder, trace_layer).layer(resolver)
+}
+
+/// Create a traced HTTP and HTTPS connector
+#[must_use]
+pub fn make_traced_connector(options: &ConnectorOptions) -> TracedConnector {
+    let resolver = trace_resolver(GaiResolver::new());
+    let mut http = HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    let proxy = ProxyConnector::new(http, options.proxy.clone());
 
-    let tls_config = rustls_platform_verifier::tls_config();
-    make_connector(resolver, tls_config)
+    wrap_connector(proxy, options.tls_config())
+}
+
+/// Create a traced HTTP and HTTPS connector which only connects to publicly
+/// routable addresses.
+///
+/// Note that this only applies to host names: URLs with an IP address as
+/// host must be checked separately with [`is_public_ip`].
+///
+/// As the addresses have to be checked before connecting, this connector
+/// never goes through the proxy set in the options.
+#[must_use]
+pub fn make_public_traced_connector(options: &ConnectorOptions) -> PublicTracedConnector {
+    let resolver = trace_resolver(PublicResolver::new(GaiResolver::new()));
+
+    make_connector(resolver, options.tls_config())
 }
 
 fn make_untraced_connector() -> UntracedConnector
@@ -84,10 +332,14 @@ fn make_connector<R>(
     let mut http = HttpConnector::new_with_resolver(resolver);
     http.enforce_http(false);
 
+    wrap_connector(http, tls_config)
+}
+
+fn wrap_connector<C>(connector: C, tls_config: rustls::ClientConfig) -> HttpsConnector<C> {
     HttpsConnectorBuilder::new()
         .with_tls_config(tls_config)
         .https_or_http()
         .enable_http1()
         .enable_http2()
-        .wrap_connector(http)
+        .wrap_connector(connector)
 }
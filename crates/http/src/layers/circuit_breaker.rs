@@ -0,0 +1,260 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A circuit breaker, which stops sending requests to a server which keeps
+//! failing, to give it some time to recover instead of piling up requests.
+
+use std::{
+    collections::HashMap,
+    future::Ready,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures_util::{
+    future::{Either, Map},
+    FutureExt,
+};
+use http::{Request, Response, StatusCode};
+use tower::{Layer, Service};
+
+/// The state of the circuit breaker of a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go through
+    Closed,
+
+    /// The server failed too many times, requests are rejected immediately
+    Open,
+
+    /// The server failed recently, but the next request will go through to
+    /// check whether it recovered
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// A short name of the state, used in metrics and logs
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Marker inserted in the extensions of the responses generated by the
+/// circuit breaker when it rejects a request
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitOpen;
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probing: bool,
+}
+
+impl Breaker {
+    fn state(&self, now: Instant, open_duration: Duration) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if now.duration_since(opened_at) < open_duration => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// The circuit breakers of all the servers the HTTP clients talk to, keyed by
+/// the authority of the request URIs
+///
+/// This is cheap to clone, and clones share the same state.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(30))
+    }
+}
+
+impl CircuitBreakers {
+    /// Create a new set of circuit breakers, which open after
+    /// `failure_threshold` consecutive failures, and stay open for
+    /// `open_duration` before letting a request through again
+    #[must_use]
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            breakers: Arc::default(),
+            failure_threshold,
+            open_duration,
+        }
+    }
+
+    /// Get the current state of the circuit breaker of each server
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock on the breakers is poisoned
+    #[must_use]
+    pub fn states(&self) -> Vec<(String, CircuitState)> {
+        let now = Instant::now();
+        let breakers = self.breakers.lock().unwrap();
+        breakers
+            .iter()
+            .map(|(server, breaker)| (server.clone(), breaker.state(now, self.open_duration)))
+            .collect()
+    }
+
+    /// Check whether a request to the given server can go through
+    fn acquire(&self, server: &str) -> bool {
+        let now = Instant::now();
+        let mut breakers = self.breakers.lock().unwrap();
+        let Some(breaker) = breakers.get_mut(server) else {
+            return true;
+        };
+
+        match breaker.state(now, self.open_duration) {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            // Only let one request through to probe the server
+            CircuitState::HalfOpen if breaker.probing => false,
+            CircuitState::HalfOpen => {
+                breaker.probing = true;
+                true
+            }
+        }
+    }
+
+    /// Record the outcome of a request to the given server
+    fn record(&self, server: &str, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+
+        if success {
+            if let Some(breaker) = breakers.get_mut(server) {
+                if breaker.opened_at.is_some() {
+                    tracing::info!(server, "Server recovered, closing the circuit breaker");
+                }
+                *breaker = Breaker::default();
+            }
+            return;
+        }
+
+        let breaker = breakers.entry(server.to_owned()).or_default();
+        breaker.consecutive_failures += 1;
+        if breaker.probing || breaker.consecutive_failures >= self.failure_threshold {
+            tracing::warn!(
+                server,
+                consecutive_failures = breaker.consecutive_failures,
+                "Server keeps failing, opening the circuit breaker"
+            );
+            breaker.opened_at = Some(Instant::now());
+            breaker.probing = false;
+        }
+    }
+}
+
+/// Whether the response is considered as a failure of the server
+fn is_server_failure(status: StatusCode) -> bool {
+    status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED
+}
+
+type RecordOutcome<T> = Box<dyn FnOnce(T) -> T + Send>;
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker<S> {
+    inner: S,
+    breakers: CircuitBreakers,
+}
+
+impl<S> CircuitBreaker<S> {
+    #[must_use]
+    pub const fn new(inner: S, breakers: CircuitBreakers) -> Self {
+        Self { inner, breakers }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CircuitBreaker<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Error = S::Error;
+    type Response = Response<ResBody>;
+    type Future = Either<
+        Ready<Result<Self::Response, Self::Error>>,
+        Map<S::Future, RecordOutcome<Result<Self::Response, Self::Error>>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let Some(server) = request.uri().authority().map(ToString::to_string) else {
+            let passthrough: RecordOutcome<_> = Box::new(|res| res);
+            return Either::Right(self.inner.call(request).map(passthrough));
+        };
+
+        if !self.breakers.acquire(&server) {
+            tracing::debug!(%server, "Circuit breaker is open, rejecting the request");
+            let mut response = Response::new(ResBody::default());
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            response.extensions_mut().insert(CircuitOpen);
+            return Either::Left(std::future::ready(Ok(response)));
+        }
+
+        let breakers = self.breakers.clone();
+        let record: RecordOutcome<Result<Self::Response, Self::Error>> = Box::new(move |res| {
+            let success = res
+                .as_ref()
+                .is_ok_and(|response| !is_server_failure(response.status()));
+            breakers.record(&server, success);
+            res
+        });
+        Either::Right(self.inner.call(request).map(record))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreakerLayer {
+    breakers: CircuitBreakers,
+}
+
+impl CircuitBreakerLayer {
+    #[must_use]
+    pub const fn new(breakers: CircuitBreakers) -> Self {
+        Self { breakers }
+    }
+
+    /// The circuit breakers shared by the services built by this layer
+    #[must_use]
+    pub const fn breakers(&self) -> &CircuitBreakers {
+        &self.breakers
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker::new(inner, self.breakers.clone())
+    }
+}
@@ -33,22 +33,34 @@ use tower::{
     Layer,
 };
 use tower_http::{
-    follow_redirect::{FollowRedirect, FollowRedirectLayer},
+    follow_redirect::{
+        policy::{FilterCredentials, Limited, PolicyExt},
+        FollowRedirect, FollowRedirectLayer,
+    },
     set_header::{SetRequestHeader, SetRequestHeaderLayer},
     timeout::{Timeout, TimeoutLayer},
 };
 use tracing::Span;
 
+use super::{
+    circuit_breaker::{CircuitBreaker, CircuitBreakerLayer, CircuitBreakers},
+    retry::{Retry, RetryLayer},
+};
+
 pub type ClientService<S> = SetRequestHeader<
     DurationRecorderService<
         InFlightCounterService<
-            ConcurrencyLimit<
-                FollowRedirect<
-                    TraceService<
-                        TraceContextService<Timeout<S>>,
-                        MakeSpanForRequest,
-                        EnrichSpanOnResponse,
-                        EnrichSpanOnError,
+            Retry<
+                CircuitBreaker<
+                    ConcurrencyLimit<
+                        FollowRedirect<
+                            TraceService<
+                                TraceContextService<Timeout<S>>,
+                                MakeSpanForRequest,
+                                EnrichSpanOnResponse,
+                                EnrichSpanOnError,
+                            >,
+                        >,
                     >,
                 >,
             >,
@@ -179,6 +191,8 @@ where
 #[derive(Debug, Clone)]
 pub struct ClientLayer {
     user_agent_layer: SetRequestHeaderLayer<HeaderValue>,
+    retry_layer: RetryLayer,
+    circuit_breaker_layer: CircuitBreakerLayer,
     concurrency_limit_layer: GlobalConcurrencyLimitLayer,
     follow_redirect_layer: FollowRedirectLayer,
     trace_layer: TraceLayer<MakeSpanForRequest, EnrichSpanOnResponse, EnrichSpanOnError>,
@@ -202,6 +216,8 @@ impl ClientLayer {
                 USER_AGENT,
                 HeaderValue::from_static("matrix-authentication-service/0.0.1"),
             ),
+            retry_layer: RetryLayer::default(),
+            circuit_breaker_layer: CircuitBreakerLayer::default(),
             concurrency_limit_layer: GlobalConcurrencyLimitLayer::new(10),
             follow_redirect_layer: FollowRedirectLayer::new(),
             trace_layer: TraceLayer::new(MakeSpanForRequest::default())
@@ -236,6 +252,21 @@ impl ClientLayer {
 
         self
     }
+
+    /// Don't follow redirects, e.g. when the destination of the request was
+    /// checked beforehand
+    #[must_use]
+    pub fn without_redirects(mut self) -> Self {
+        self.follow_redirect_layer =
+            FollowRedirectLayer::with_policy(Limited::new(0).and(FilterCredentials::new()));
+        self
+    }
+
+    /// The circuit breakers shared by all the clients built from this layer
+    #[must_use]
+    pub const fn circuit_breakers(&self) -> &CircuitBreakers {
+        self.circuit_breaker_layer.breakers()
+    }
 }
 
 impl<S> Layer<S> for ClientLayer
@@ -249,6 +280,8 @@ where
             &self.user_agent_layer,
             &self.duration_recorder_layer,
             &self.in_flight_counter_layer,
+            &self.retry_layer,
+            &self.circuit_breaker_layer,
             &self.concurrency_limit_layer,
             &self.follow_redirect_layer,
             &self.trace_layer,
@@ -15,9 +15,11 @@
 pub mod body_to_bytes_response;
 pub mod bytes_to_body_request;
 pub mod catch_http_codes;
+pub mod circuit_breaker;
 pub mod form_urlencoded_request;
 pub mod json_request;
 pub mod json_response;
+pub mod retry;
 
 #[cfg(feature = "client")]
 pub(crate) mod client;
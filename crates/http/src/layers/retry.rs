@@ -0,0 +1,265 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retry idempotent requests which failed because of a transient error, with
+//! an exponential backoff and a retry budget shared between all the clients.
+
+use std::{
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use http::{Method, Request, Response, StatusCode};
+use tower::{Layer, Service, ServiceExt};
+
+use super::circuit_breaker::CircuitOpen;
+
+#[derive(Debug)]
+struct BudgetWindow {
+    started_at: Instant,
+    requests: u32,
+    retries: u32,
+}
+
+/// Limits the number of retries to a fraction of the requests made over a
+/// sliding window, so that retries don't overwhelm a server which is already
+/// struggling
+///
+/// This is cheap to clone, and clones share the same state.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    window: Arc<Mutex<BudgetWindow>>,
+    ttl: Duration,
+    min_retries: u32,
+    retry_ratio: f32,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(10), 10, 0.2)
+    }
+}
+
+impl RetryBudget {
+    /// Create a new retry budget, allowing `min_retries` plus `retry_ratio`
+    /// of the requests made to be retried every `ttl`
+    #[must_use]
+    pub fn new(ttl: Duration, min_retries: u32, retry_ratio: f32) -> Self {
+        Self {
+            window: Arc::new(Mutex::new(BudgetWindow {
+                started_at: Instant::now(),
+                requests: 0,
+                retries: 0,
+            })),
+            ttl,
+            min_retries,
+            retry_ratio,
+        }
+    }
+
+    fn with_window<R>(&self, f: impl FnOnce(&mut BudgetWindow) -> R) -> R {
+        let mut window = self.window.lock().unwrap();
+        if window.started_at.elapsed() >= self.ttl {
+            *window = BudgetWindow {
+                started_at: Instant::now(),
+                requests: 0,
+                retries: 0,
+            };
+        }
+        f(&mut window)
+    }
+
+    /// Record a new request
+    fn deposit(&self) {
+        self.with_window(|window| window.requests = window.requests.saturating_add(1));
+    }
+
+    /// Try to withdraw a retry from the budget
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn withdraw(&self) -> bool {
+        self.with_window(|window| {
+            let allowed =
+                self.min_retries + (window.requests as f32 * self.retry_ratio).floor() as u32;
+            if window.retries < allowed {
+                window.retries += 1;
+                true
+            } else {
+                false
+            }
+        })
+    }
+}
+
+/// Whether a request can safely be sent multiple times
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+/// Whether the outcome of a request is a transient failure worth retrying
+fn is_transient_failure<B, E>(result: &Result<Response<B>, E>) -> bool {
+    match result {
+        Err(_) => true,
+        // Don't retry requests rejected by the circuit breaker
+        Ok(response) if response.extensions().get::<CircuitOpen>().is_some() => false,
+        Ok(response) => matches!(
+            response.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Retry<S> {
+    inner: S,
+    budget: RetryBudget,
+    max_retries: u32,
+    base_backoff: Duration,
+}
+
+impl<S> Retry<S> {
+    #[must_use]
+    pub const fn new(
+        inner: S,
+        budget: RetryBudget,
+        max_retries: u32,
+        base_backoff: Duration,
+    ) -> Self {
+        Self {
+            inner,
+            budget,
+            max_retries,
+            base_backoff,
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Retry<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+    ReqBody: Clone + Send + 'static,
+    ResBody: Send,
+{
+    type Error = S::Error;
+    type Response = Response<ResBody>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The service which was polled ready is the one we have to call first
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let budget = self.budget.clone();
+        let max_retries = self.max_retries;
+        let base_backoff = self.base_backoff;
+
+        Box::pin(async move {
+            budget.deposit();
+
+            if !is_idempotent(request.method()) {
+                return inner.call(request).await;
+            }
+
+            let mut attempt = 0;
+            loop {
+                let result = inner.call(request.clone()).await;
+
+                if attempt >= max_retries || !is_transient_failure(&result) || !budget.withdraw() {
+                    return result;
+                }
+
+                // Drop the failed response before waiting, to release the connection
+                drop(result);
+
+                let backoff = base_backoff * 2_u32.pow(attempt);
+                attempt += 1;
+                tracing::debug!(
+                    attempt,
+                    ?backoff,
+                    uri = %request.uri(),
+                    "Request failed with a transient error, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+
+                inner.ready().await?;
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    budget: RetryBudget,
+    max_retries: u32,
+    base_backoff: Duration,
+}
+
+impl Default for RetryLayer {
+    fn default() -> Self {
+        Self::new(RetryBudget::default())
+    }
+}
+
+impl RetryLayer {
+    /// Create a new retry layer, retrying requests at most twice, after 100ms
+    /// and 200ms
+    #[must_use]
+    pub const fn new(budget: RetryBudget) -> Self {
+        Self {
+            budget,
+            max_retries: 2,
+            base_backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Set the maximum number of times a request is retried
+    #[must_use]
+    pub const fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the delay before the first retry, which doubles on each retry
+    #[must_use]
+    pub const fn with_base_backoff(mut self, base_backoff: Duration) -> Self {
+        self.base_backoff = base_backoff;
+        self
+    }
+}
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry::new(
+            inner,
+            self.budget.clone(),
+            self.max_retries,
+            self.base_backoff,
+        )
+    }
+}
@@ -21,15 +21,20 @@
 mod client;
 mod ext;
 mod layers;
+#[cfg(feature = "client")]
+mod proxy;
 mod service;
 
 #[cfg(feature = "client")]
 pub use self::{
     client::{
-        make_traced_connector, make_untraced_client, Client, TracedClient, TracedConnector,
-        UntracedClient, UntracedConnector,
+        is_public_ip, make_public_traced_connector, make_traced_connector, make_untraced_client,
+        Client, ConnectorOptions, ExtraRootsError, NoPublicAddressError, PublicResolver,
+        PublicTracedClient, PublicTracedConnector, TracedClient, TracedConnector, UntracedClient,
+        UntracedConnector,
     },
     layers::client::{ClientLayer, ClientService},
+    proxy::{InvalidProxyError, Proxy, ProxyConnector, ProxyError},
 };
 pub use self::{
     ext::{set_propagator, CorsLayerExt, ServiceExt as HttpServiceExt},
@@ -37,9 +42,13 @@ pub use self::{
         body_to_bytes_response::{self, BodyToBytesResponse, BodyToBytesResponseLayer},
         bytes_to_body_request::{self, BytesToBodyRequest, BytesToBodyRequestLayer},
         catch_http_codes::{self, CatchHttpCodes, CatchHttpCodesLayer},
+        circuit_breaker::{
+            self, CircuitBreaker, CircuitBreakerLayer, CircuitBreakers, CircuitOpen, CircuitState,
+        },
         form_urlencoded_request::{self, FormUrlencodedRequest, FormUrlencodedRequestLayer},
         json_request::{self, JsonRequest, JsonRequestLayer},
         json_response::{self, JsonResponse, JsonResponseLayer},
+        retry::{self, Retry, RetryBudget, RetryLayer},
     },
     service::{BoxCloneSyncService, HttpService},
 };
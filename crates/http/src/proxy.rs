@@ -0,0 +1,342 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for sending the outbound requests through an HTTP proxy
+
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::future::BoxFuture;
+use headers::{Authorization, HeaderMapExt, ProxyAuthorization};
+use http::{
+    header::PROXY_AUTHORIZATION,
+    uri::{InvalidUri, Scheme},
+    HeaderMap, Uri,
+};
+use hyper::client::connect::Connection;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tower::{BoxError, Service};
+
+/// Maximum size of the response of the proxy to a `CONNECT` request
+const MAX_CONNECT_RESPONSE_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Error)]
+pub enum InvalidProxyError {
+    #[error("invalid proxy URL")]
+    InvalidUri(#[from] InvalidUri),
+
+    #[error("the proxy URL must be an http:// URL with a host")]
+    UnsupportedUri,
+}
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("the destination URL has no host")]
+    MissingHost,
+
+    #[error("failed to talk to the proxy")]
+    Io(#[from] std::io::Error),
+
+    #[error("the proxy closed the connection")]
+    UnexpectedEof,
+
+    #[error("the proxy sent an invalid response")]
+    InvalidResponse,
+
+    #[error("the proxy refused to open a tunnel: {0}")]
+    Refused(String),
+}
+
+/// An HTTP proxy, through which outbound requests are tunneled with the
+/// `CONNECT` method
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    uri: Uri,
+    authorization: Option<String>,
+    no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    /// Create a new proxy from its URL, like `http://proxy.example.com:3128`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid, or isn't an `http://` URL
+    pub fn new(url: &str) -> Result<Self, InvalidProxyError> {
+        let uri: Uri = url.parse()?;
+        if uri.scheme() != Some(&Scheme::HTTP) || uri.host().is_none() {
+            return Err(InvalidProxyError::UnsupportedUri);
+        }
+
+        Ok(Self {
+            uri,
+            authorization: None,
+            no_proxy: Vec::new(),
+        })
+    }
+
+    /// Authenticate to the proxy with the given credentials
+    #[must_use]
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(ProxyAuthorization(
+            Authorization::basic(username, password).0,
+        ));
+        self.authorization = headers
+            .get(PROXY_AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        self
+    }
+
+    /// Set the hosts which should be reached directly, without going through
+    /// the proxy
+    ///
+    /// Each entry is either a host name, which also matches its subdomains,
+    /// an IP address, or `*` to match all hosts.
+    #[must_use]
+    pub fn with_no_proxy(mut self, no_proxy: Vec<String>) -> Self {
+        self.no_proxy = no_proxy
+            .into_iter()
+            .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        self
+    }
+
+    /// Whether requests to the given destination should go through the proxy
+    #[must_use]
+    pub fn should_proxy(&self, dst: &Uri) -> bool {
+        let Some(host) = dst.host() else {
+            return true;
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        let ip: Option<IpAddr> = host.parse().ok();
+
+        !self.no_proxy.iter().any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+
+            if let Some(ip) = ip {
+                return entry.parse() == Ok(ip);
+            }
+
+            host == *entry
+                || host
+                    .strip_suffix(entry.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+}
+
+/// Open a tunnel to the destination through the proxy
+async fn tunnel<S>(stream: &mut S, dst: &Uri, authorization: Option<&str>) -> Result<(), ProxyError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = dst.host().ok_or(ProxyError::MissingHost)?;
+    let port = dst
+        .port_u16()
+        .unwrap_or(if dst.scheme() == Some(&Scheme::HTTPS) {
+            443
+        } else {
+            80
+        });
+
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some(authorization) = authorization {
+        request.push_str("Proxy-Authorization: ");
+        request.push_str(authorization);
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response headers. The destination doesn't send anything before
+    // the client starts talking, so this can't read past them
+    let mut response = Vec::with_capacity(1024);
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|window| window == b"\r\n\r\n") {
+        if response.len() > MAX_CONNECT_RESPONSE_SIZE {
+            return Err(ProxyError::InvalidResponse);
+        }
+
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(ProxyError::UnexpectedEof);
+        }
+        response.extend_from_slice(&buf[..read]);
+    }
+
+    let status_line = response
+        .split(|byte| *byte == b'\r')
+        .next()
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .ok_or(ProxyError::InvalidResponse)?;
+
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(ProxyError::InvalidResponse);
+    };
+
+    if !version.starts_with("HTTP/1.") {
+        return Err(ProxyError::InvalidResponse);
+    }
+
+    if status != "200" {
+        return Err(ProxyError::Refused(status_line.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// A connector which tunnels the connections through a [`Proxy`], if one is
+/// set
+#[derive(Debug, Clone)]
+pub struct ProxyConnector<C> {
+    inner: C,
+    proxy: Option<Arc<Proxy>>,
+}
+
+impl<C> ProxyConnector<C> {
+    #[must_use]
+    pub fn new(inner: C, proxy: Option<Proxy>) -> Self {
+        Self {
+            inner,
+            proxy: proxy.map(Arc::new),
+        }
+    }
+}
+
+impl<C> Service<Uri> for ProxyConnector<C>
+where
+    C: Service<Uri>,
+    C::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let Some(proxy) = self.proxy.clone().filter(|proxy| proxy.should_proxy(&dst)) else {
+            let future = self.inner.call(dst);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        };
+
+        let future = self.inner.call(proxy.uri.clone());
+        Box::pin(async move {
+            let mut stream = future.await.map_err(Into::into)?;
+            tunnel(&mut stream, &dst, proxy.authorization.as_deref()).await?;
+            Ok(stream)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_proxy() {
+        let proxy = Proxy::new("http://proxy.example.com:3128")
+            .unwrap()
+            .with_no_proxy(vec![
+                "internal.example.com".to_owned(),
+                ".corp.example.com".to_owned(),
+                "10.0.0.1".to_owned(),
+                "::1".to_owned(),
+            ]);
+
+        let should_proxy = |uri: &str| proxy.should_proxy(&uri.parse().unwrap());
+
+        assert!(should_proxy("https://example.com/"));
+        assert!(should_proxy("https://notinternal.example.com/"));
+        assert!(!should_proxy("https://internal.example.com/"));
+        assert!(!should_proxy("https://INTERNAL.example.com:8443/"));
+        assert!(!should_proxy("http://synapse.internal.example.com/"));
+        assert!(!should_proxy("https://corp.example.com/"));
+        assert!(!should_proxy("https://idp.corp.example.com/"));
+        assert!(!should_proxy("http://10.0.0.1:8008/"));
+        assert!(should_proxy("http://10.0.0.2:8008/"));
+        assert!(!should_proxy("http://[::1]:8008/"));
+
+        let proxy = proxy.with_no_proxy(vec!["*".to_owned()]);
+        assert!(!proxy.should_proxy(&"https://example.com/".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_proxy() {
+        assert!(Proxy::new("http://proxy.example.com:3128").is_ok());
+        assert!(Proxy::new("https://proxy.example.com").is_err());
+        assert!(Proxy::new("socks5://proxy.example.com").is_err());
+        assert!(Proxy::new("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tunnel() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+
+        let proxy = tokio::spawn(async move {
+            let mut buf = vec![0; 1024];
+            let read = server.read(&mut buf).await.unwrap();
+            let request = String::from_utf8(buf[..read].to_vec()).unwrap();
+            server
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+            request
+        });
+
+        let dst: Uri = "https://example.com/path".parse().unwrap();
+        tunnel(&mut client, &dst, Some("Basic dXNlcjpwYXNz"))
+            .await
+            .unwrap();
+
+        let request = proxy.await.unwrap();
+        assert_eq!(
+            request,
+            "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+
+        // The proxy refusing to open the tunnel is an error
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut buf = vec![0; 1024];
+            let read = server.read(&mut buf).await.unwrap();
+            assert!(read > 0);
+            server
+                .write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let dst: Uri = "http://example.com/".parse().unwrap();
+        let error = tunnel(&mut client, &dst, None).await.unwrap_err();
+        assert!(matches!(error, ProxyError::Refused(_)));
+    }
+}
@@ -12,19 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::convert::Infallible;
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use anyhow::{bail, Context};
 use bytes::{Buf, Bytes};
 use headers::{ContentType, HeaderMapExt};
 use http::{header::ACCEPT, HeaderValue, Request, Response, StatusCode};
 use mas_http::{
-    BodyToBytesResponseLayer, BytesToBodyRequestLayer, CatchHttpCodesLayer,
-    FormUrlencodedRequestLayer, JsonRequestLayer, JsonResponseLayer,
+    BodyToBytesResponseLayer, BytesToBodyRequestLayer, CatchHttpCodesLayer, CircuitBreakerLayer,
+    CircuitBreakers, CircuitOpen, CircuitState, FormUrlencodedRequestLayer, JsonRequestLayer,
+    JsonResponseLayer, RetryBudget, RetryLayer,
 };
 use serde::Deserialize;
 use thiserror::Error;
-use tower::{service_fn, Layer, ServiceExt};
+use tower::{service_fn, Layer, Service, ServiceExt};
 
 #[derive(Debug, Error, Deserialize)]
 #[error("Error code in response: {error}")]
@@ -153,3 +163,161 @@ async fn test_urlencoded_request_body() {
     let res = svc.oneshot(request).await;
     res.expect("the request to succeed");
 }
+
+/// A service which fails with the given status code the first `failures` times
+/// it is called, counting the calls
+#[derive(Clone)]
+struct FlakyService {
+    failures: usize,
+    status: StatusCode,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FlakyService {
+    fn new(failures: usize, status: StatusCode) -> Self {
+        Self {
+            failures,
+            status,
+            calls: Arc::default(),
+        }
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Service<Request<String>> for FlakyService {
+    type Response = Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: Request<String>) -> Self::Future {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut res = Response::new(String::new());
+        if call < self.failures {
+            *res.status_mut() = self.status;
+        }
+        ready(Ok(res))
+    }
+}
+
+#[tokio::test]
+async fn test_retry() {
+    let layer = RetryLayer::new(RetryBudget::default()).with_base_backoff(Duration::from_millis(1));
+
+    // Transient failures of idempotent requests are retried
+    let flaky = FlakyService::new(2, StatusCode::SERVICE_UNAVAILABLE);
+    let svc = layer.layer(flaky.clone());
+    let request = Request::get("https://example.com/")
+        .body(String::new())
+        .unwrap();
+    let response = svc.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(flaky.calls(), 3);
+
+    // But not more than twice
+    let flaky = FlakyService::new(5, StatusCode::SERVICE_UNAVAILABLE);
+    let svc = layer.layer(flaky.clone());
+    let request = Request::get("https://example.com/")
+        .body(String::new())
+        .unwrap();
+    let response = svc.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(flaky.calls(), 3);
+
+    // Other errors are not retried
+    let flaky = FlakyService::new(1, StatusCode::BAD_REQUEST);
+    let svc = layer.layer(flaky.clone());
+    let request = Request::get("https://example.com/")
+        .body(String::new())
+        .unwrap();
+    let response = svc.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(flaky.calls(), 1);
+
+    // Neither are non-idempotent requests
+    let flaky = FlakyService::new(1, StatusCode::SERVICE_UNAVAILABLE);
+    let svc = layer.layer(flaky.clone());
+    let request = Request::post("https://example.com/")
+        .body(String::new())
+        .unwrap();
+    let response = svc.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(flaky.calls(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_budget() {
+    // No retries allowed besides 10% of the requests
+    let layer = RetryLayer::new(RetryBudget::new(Duration::from_secs(60), 0, 0.1))
+        .with_base_backoff(Duration::from_millis(1));
+
+    let flaky = FlakyService::new(usize::MAX, StatusCode::SERVICE_UNAVAILABLE);
+    let svc = layer.layer(flaky.clone());
+
+    for _ in 0..10 {
+        let request = Request::get("https://example.com/")
+            .body(String::new())
+            .unwrap();
+        svc.clone().oneshot(request).await.unwrap();
+    }
+
+    // 10 requests, and a single retry
+    assert_eq!(flaky.calls(), 11);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker() {
+    let breakers = CircuitBreakers::new(2, Duration::from_millis(50));
+    let layer = CircuitBreakerLayer::new(breakers.clone());
+
+    let flaky = FlakyService::new(2, StatusCode::INTERNAL_SERVER_ERROR);
+    let svc = layer.layer(flaky.clone());
+    let request = || {
+        Request::get("https://example.com/")
+            .body(String::new())
+            .unwrap()
+    };
+
+    // The breaker opens after two consecutive failures
+    for _ in 0..2 {
+        let response = svc.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    assert_eq!(
+        breakers.states(),
+        vec![("example.com".to_owned(), CircuitState::Open)]
+    );
+
+    // Requests are then rejected without reaching the server
+    let response = svc.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.extensions().get::<CircuitOpen>().is_some());
+    assert_eq!(flaky.calls(), 2);
+
+    // Other servers are not affected
+    let other = Request::get("https://other.example.com/")
+        .body(String::new())
+        .unwrap();
+    svc.clone().oneshot(other).await.unwrap();
+    assert_eq!(flaky.calls(), 3);
+
+    // After a while, a request goes through, and the breaker closes if it
+    // succeeds
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(
+        breakers.states()[0],
+        ("example.com".to_owned(), CircuitState::HalfOpen)
+    );
+    let response = svc.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        breakers.states(),
+        vec![("example.com".to_owned(), CircuitState::Closed)]
+    );
+}
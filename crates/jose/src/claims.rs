@@ -503,6 +503,7 @@ mod oidc_core {
     use super::{Claim, Equality, Timestamp, TokenHash};
 
     pub const AUTH_TIME: Claim<Timestamp> = Claim::new("auth_time");
+    pub const AMR: Claim<Vec<String>> = Claim::new("amr");
     pub const NONCE: Claim<String, Equality<str>> = Claim::new("nonce");
     pub const AT_HASH: Claim<String, TokenHash> = Claim::new("at_hash");
     pub const C_HASH: Claim<String, TokenHash> = Claim::new("c_hash");
@@ -18,6 +18,7 @@ use aead::Aead;
 use base64ct::{Base64, Encoding};
 use chacha20poly1305::{ChaCha20Poly1305, KeyInit};
 use generic_array::GenericArray;
+use rand::{CryptoRng, RngCore};
 use thiserror::Error;
 
 /// Helps encrypting and decrypting data
@@ -66,13 +67,19 @@ impl Encrypter {
         Ok(encrypted)
     }
 
-    /// Encrypt a payload to a self-contained base64-encoded string
+    /// Encrypt a payload to a self-contained base64-encoded string, using a
+    /// nonce generated by the given RNG
     ///
     /// # Errors
     ///
     /// Will return `Err` when the payload failed to encrypt
-    pub fn encrypt_to_string(&self, decrypted: &[u8]) -> Result<String, aead::Error> {
-        let nonce = rand::random();
+    pub fn encrypt_to_string<R: RngCore + CryptoRng + ?Sized>(
+        &self,
+        rng: &mut R,
+        decrypted: &[u8],
+    ) -> Result<String, aead::Error> {
+        let mut nonce = [0; 12];
+        rng.fill_bytes(&mut nonce);
         let encrypted = self.encrypt(&nonce, decrypted)?;
         let encrypted = [&nonce[..], &encrypted].concat();
         let encrypted = Base64::encode_string(&encrypted);
@@ -32,10 +32,14 @@ use rsa::BigUint;
 use thiserror::Error;
 
 mod encrypter;
+mod x509;
 
 pub use aead;
 
-pub use self::encrypter::{DecryptError, Encrypter};
+pub use self::{
+    encrypter::{DecryptError, Encrypter},
+    x509::CertificateIdentity,
+};
 
 /// Error type used when a key could not be loaded
 #[derive(Debug, Error)]
@@ -0,0 +1,170 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Minimal decoding of X.509 certificates, to identify clients which
+//! authenticated with a TLS client certificate
+
+use const_oid::ObjectIdentifier;
+use der::{asn1::AnyRef, Decode, Reader, SliceReader, Tag};
+
+/// The `commonName` attribute type
+const COMMON_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+
+/// The `subjectAltName` certificate extension
+const SUBJECT_ALT_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.17");
+
+/// The identity of the subject of an X.509 certificate
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CertificateIdentity {
+    /// The common name (`CN`) of the subject, if any
+    pub common_name: Option<String>,
+
+    /// The email addresses in the subject alternative names
+    pub emails: Vec<String>,
+
+    /// The DNS names in the subject alternative names
+    pub dns_names: Vec<String>,
+}
+
+impl CertificateIdentity {
+    /// Get the identity of the subject of a DER-encoded X.509 certificate
+    ///
+    /// This doesn't verify the certificate in any way, which must have been
+    /// done beforehand, usually during the TLS handshake.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificate could not be decoded
+    pub fn from_der(certificate: &[u8]) -> Result<Self, der::Error> {
+        let certificate = AnyRef::from_der(certificate)?;
+        certificate.tag().assert_eq(Tag::Sequence)?;
+        let mut reader = SliceReader::new(certificate.value())?;
+        let tbs_certificate: AnyRef<'_> = reader.decode()?;
+        tbs_certificate.tag().assert_eq(Tag::Sequence)?;
+
+        let mut reader = SliceReader::new(tbs_certificate.value())?;
+        let first: AnyRef<'_> = reader.decode()?;
+        if is_context_specific(first.tag(), 0) {
+            // The first field was the optional version, skip the serial number
+            reader.decode::<AnyRef<'_>>()?;
+        }
+
+        // Skip the signature algorithm, the issuer and the validity
+        for _ in 0..3 {
+            reader.decode::<AnyRef<'_>>()?;
+        }
+
+        let subject: AnyRef<'_> = reader.decode()?;
+        subject.tag().assert_eq(Tag::Sequence)?;
+        let common_name = common_name(subject.value())?;
+
+        // Skip the subject public key info
+        reader.decode::<AnyRef<'_>>()?;
+
+        let mut identity = Self {
+            common_name,
+            ..Self::default()
+        };
+        while !reader.is_finished() {
+            let field: AnyRef<'_> = reader.decode()?;
+            // The extensions are explicitly tagged with [3]
+            if is_context_specific(field.tag(), 3) {
+                identity.add_alt_names(field.value())?;
+            }
+        }
+
+        Ok(identity)
+    }
+
+    /// Add the email addresses and DNS names in the subject alternative names
+    /// extension, out of the DER-encoded `Extensions`
+    fn add_alt_names(&mut self, extensions: &[u8]) -> Result<(), der::Error> {
+        let extensions = AnyRef::from_der(extensions)?;
+        extensions.tag().assert_eq(Tag::Sequence)?;
+        let mut reader = SliceReader::new(extensions.value())?;
+        while !reader.is_finished() {
+            let extension: AnyRef<'_> = reader.decode()?;
+            extension.tag().assert_eq(Tag::Sequence)?;
+
+            let mut extension = SliceReader::new(extension.value())?;
+            let oid: ObjectIdentifier = extension.decode()?;
+            let mut value: AnyRef<'_> = extension.decode()?;
+            // The criticality is optional
+            if value.tag() == Tag::Boolean {
+                value = extension.decode()?;
+            }
+            value.tag().assert_eq(Tag::OctetString)?;
+
+            if oid != SUBJECT_ALT_NAME {
+                continue;
+            }
+
+            let names = AnyRef::from_der(value.value())?;
+            names.tag().assert_eq(Tag::Sequence)?;
+            let mut names = SliceReader::new(names.value())?;
+            while !names.is_finished() {
+                let name: AnyRef<'_> = names.decode()?;
+                let Ok(value) = std::str::from_utf8(name.value()) else {
+                    continue;
+                };
+
+                // Email addresses are `rfc822Name`s, implicitly tagged with [1],
+                // and DNS names are `dNSName`s, implicitly tagged with [2]
+                if is_context_specific(name.tag(), 1) {
+                    self.emails.push(value.to_owned());
+                } else if is_context_specific(name.tag(), 2) {
+                    self.dns_names.push(value.to_owned());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_context_specific(tag: Tag, number: u8) -> bool {
+    matches!(tag, Tag::ContextSpecific { number: n, .. } if n.value() == number)
+}
+
+/// Find the first common name in a DER-encoded `Name`
+fn common_name(name: &[u8]) -> Result<Option<String>, der::Error> {
+    let mut rdns = SliceReader::new(name)?;
+    while !rdns.is_finished() {
+        let rdn: AnyRef<'_> = rdns.decode()?;
+        rdn.tag().assert_eq(Tag::Set)?;
+
+        let mut attributes = SliceReader::new(rdn.value())?;
+        while !attributes.is_finished() {
+            let attribute: AnyRef<'_> = attributes.decode()?;
+            attribute.tag().assert_eq(Tag::Sequence)?;
+
+            let mut reader = SliceReader::new(attribute.value())?;
+            let oid: ObjectIdentifier = reader.decode()?;
+            let value: AnyRef<'_> = reader.decode()?;
+            if oid != COMMON_NAME {
+                continue;
+            }
+
+            let value = match value.tag() {
+                Tag::Utf8String | Tag::PrintableString | Tag::Ia5String => {
+                    std::str::from_utf8(value.value()).ok()
+                }
+                _ => None,
+            };
+
+            return Ok(value.map(ToOwned::to_owned));
+        }
+    }
+
+    Ok(None)
+}
@@ -28,3 +28,9 @@ convert "ec-p384.sec1" "ec-p384"
 
 openssl ecparam -genkey -name secp256k1 -noout -out "${KEYS}/ec-k256.sec1.pem"
 convert "ec-k256.sec1" "ec-k256"
+
+openssl req -x509 -new -key "${KEYS}/ec-p256.sec1.pem" -days 36500 \
+  -subj "/C=FR/O=Example/CN=alice" \
+  -addext "subjectAltName=email:alice@example.com,DNS:example.com,email:alice.smith@example.com" \
+  -out "${KEYS}/client.cert.pem"
+openssl x509 -in "${KEYS}/client.cert.pem" -outform DER -out "${KEYS}/client.cert.der"
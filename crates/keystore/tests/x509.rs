@@ -0,0 +1,39 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use mas_keystore::CertificateIdentity;
+
+#[test]
+fn certificate_identity() {
+    let bytes = include_bytes!("./keys/client.cert.der");
+    let identity = CertificateIdentity::from_der(bytes).unwrap();
+
+    assert_eq!(identity.common_name.as_deref(), Some("alice"));
+    assert_eq!(
+        identity.emails,
+        vec![
+            "alice@example.com".to_owned(),
+            "alice.smith@example.com".to_owned()
+        ]
+    );
+    assert_eq!(identity.dns_names, vec!["example.com".to_owned()]);
+}
+
+#[test]
+fn certificate_identity_invalid() {
+    assert!(CertificateIdentity::from_der(b"not a certificate").is_err());
+
+    // A private key is valid DER, but not a certificate
+    let bytes = include_bytes!("./keys/ec-p256.pkcs8.der");
+    assert!(CertificateIdentity::from_der(bytes).is_err());
+}
@@ -35,6 +35,17 @@ pub struct ConnectionInfo {
 }
 
 impl ConnectionInfo {
+    /// Build the informations of a plain TCP connection from the given peer
+    /// address, without TLS or proxy protocol
+    #[must_use]
+    pub fn from_peer_addr(net_peer_addr: std::net::SocketAddr) -> Self {
+        Self {
+            tls: None,
+            proxy: None,
+            net_peer_addr: Some(net_peer_addr),
+        }
+    }
+
     /// Returns informations about the TLS connection. Returns [`None`] if the
     /// connection was not TLS.
     #[must_use]
@@ -141,6 +141,11 @@ struct SetDisplayNameRequest<'a> {
     displayname: &'a str,
 }
 
+#[derive(Serialize)]
+struct SetAvatarUrlRequest<'a> {
+    avatar_url: &'a str,
+}
+
 #[derive(Serialize)]
 struct SynapseDeactivateUserRequest {
     erase: bool,
@@ -149,6 +154,22 @@ struct SynapseDeactivateUserRequest {
 #[derive(Serialize)]
 struct SynapseAllowCrossSigningResetRequest {}
 
+#[derive(Serialize)]
+struct SynapseShadowBanRequest {}
+
+/// Request body of `/_synapse/admin/v1/send_server_notice`
+#[derive(Serialize)]
+struct SynapseServerNoticeRequest<'a> {
+    user_id: &'a str,
+    content: SynapseServerNoticeContent<'a>,
+}
+
+#[derive(Serialize)]
+struct SynapseServerNoticeContent<'a> {
+    msgtype: &'static str,
+    body: &'a str,
+}
+
 /// Response body of
 /// `/_synapse/admin/v1/username_available?username={localpart}`
 #[derive(Deserialize)]
@@ -500,6 +521,57 @@ impl HomeserverConnection for SynapseConnection {
         self.set_displayname(mxid, "").await
     }
 
+    #[tracing::instrument(
+        name = "homeserver.set_avatar_url",
+        skip_all,
+        fields(
+            matrix.homeserver = self.homeserver,
+            matrix.mxid = mxid,
+            matrix.avatar_url = avatar_url,
+        ),
+        err(Debug),
+    )]
+    async fn set_avatar_url(&self, mxid: &str, avatar_url: &str) -> Result<(), Self::Error> {
+        let mxid = urlencoding::encode(mxid);
+        let mut client = self
+            .http_client_factory
+            .client("homeserver.set_avatar_url")
+            .request_bytes_to_body()
+            .json_request()
+            .response_body_to_bytes()
+            .catch_http_errors(catch_homeserver_error);
+
+        let request = self
+            .put(&format!("_matrix/client/v3/profile/{mxid}/avatar_url"))
+            .body(SetAvatarUrlRequest { avatar_url })?;
+
+        let response = client
+            .ready()
+            .await?
+            .call(request)
+            .await
+            .context("Failed to set avatar URL in Synapse")?;
+
+        if response.status() != StatusCode::OK {
+            return Err(anyhow::anyhow!("Failed to set avatar URL in Synapse"));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "homeserver.unset_avatar_url",
+        skip_all,
+        fields(
+            matrix.homeserver = self.homeserver,
+            matrix.mxid = mxid,
+        ),
+        err(Display),
+    )]
+    async fn unset_avatar_url(&self, mxid: &str) -> Result<(), Self::Error> {
+        self.set_avatar_url(mxid, "").await
+    }
+
     #[tracing::instrument(
         name = "homeserver.allow_cross_signing_reset",
         skip_all,
@@ -539,6 +611,93 @@ impl HomeserverConnection for SynapseConnection {
             ));
         }
 
+        Ok(())
+    }
+    #[tracing::instrument(
+        name = "homeserver.send_notice",
+        skip_all,
+        fields(
+            matrix.homeserver = self.homeserver,
+            matrix.mxid = mxid,
+        ),
+        err(Debug),
+    )]
+    async fn send_notice(&self, mxid: &str, body: &str) -> Result<(), Self::Error> {
+        let mut client = self
+            .http_client_factory
+            .client("homeserver.send_notice")
+            .request_bytes_to_body()
+            .json_request()
+            .response_body_to_bytes()
+            .catch_http_errors(catch_homeserver_error);
+
+        // Server notices are sent by the bot user configured in the
+        // `server_notices` section of the Synapse configuration, in a room
+        // dedicated to each user
+        let request =
+            self.post("_synapse/admin/v1/send_server_notice")
+                .body(SynapseServerNoticeRequest {
+                    user_id: mxid,
+                    content: SynapseServerNoticeContent {
+                        msgtype: "m.text",
+                        body,
+                    },
+                })?;
+
+        let response = client
+            .ready()
+            .await?
+            .call(request)
+            .await
+            .context("Failed to send server notice in Synapse")?;
+
+        if response.status() != StatusCode::OK {
+            return Err(anyhow::anyhow!(
+                "Failed to send server notice in Synapse: {}",
+                response.status()
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "homeserver.shadow_ban_user",
+        skip_all,
+        fields(
+            matrix.homeserver = self.homeserver,
+            matrix.mxid = mxid,
+        ),
+        err(Debug),
+    )]
+    async fn shadow_ban_user(&self, mxid: &str) -> Result<(), Self::Error> {
+        let mxid = urlencoding::encode(mxid);
+        let mut client = self
+            .http_client_factory
+            .client("homeserver.shadow_ban_user")
+            .request_bytes_to_body()
+            .json_request()
+            .response_body_to_bytes()
+            .catch_http_errors(catch_homeserver_error);
+
+        let request = self
+            .post(&format!("_synapse/admin/v1/users/{mxid}/shadow_ban"))
+            .body(SynapseShadowBanRequest {})?;
+
+        let response = client
+            .ready()
+            .await?
+            .call(request)
+            .await
+            .context("Failed to shadow-ban user in Synapse")?;
+
+        if response.status() != StatusCode::OK {
+            return Err(anyhow::anyhow!(
+                "Failed to shadow-ban user in Synapse: {}",
+                response.status()
+            ));
+        }
+
         Ok(())
     }
 }
@@ -300,6 +300,31 @@ pub trait HomeserverConnection: Send + Sync {
     /// could not be unset.
     async fn unset_displayname(&self, mxid: &str) -> Result<(), Self::Error>;
 
+    /// Set the avatar URL of a user on the homeserver.
+    ///
+    /// # Parameters
+    ///
+    /// * `mxid` - The Matrix ID of the user to set the avatar URL for.
+    /// * `avatar_url` - The `mxc://` URL of the avatar to set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the homeserver is unreachable or the avatar URL
+    /// could not be set.
+    async fn set_avatar_url(&self, mxid: &str, avatar_url: &str) -> Result<(), Self::Error>;
+
+    /// Unset the avatar URL of a user on the homeserver.
+    ///
+    /// # Parameters
+    ///
+    /// * `mxid` - The Matrix ID of the user to unset the avatar URL for.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the homeserver is unreachable or the avatar URL
+    /// could not be unset.
+    async fn unset_avatar_url(&self, mxid: &str) -> Result<(), Self::Error>;
+
     /// Temporarily allow a user to reset their cross-signing keys.
     ///
     /// # Parameters
@@ -311,6 +336,33 @@ pub trait HomeserverConnection: Send + Sync {
     /// Returns an error if the homeserver is unreachable or the cross-signing
     /// reset could not be allowed.
     async fn allow_cross_signing_reset(&self, mxid: &str) -> Result<(), Self::Error>;
+
+    /// Send a notice to a user, as a direct message from a bot user on the
+    /// homeserver.
+    ///
+    /// # Parameters
+    ///
+    /// * `mxid` - The Matrix ID of the user to send the notice to.
+    /// * `body` - The plain text body of the notice.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the homeserver is unreachable or the notice could
+    /// not be sent.
+    async fn send_notice(&self, mxid: &str, body: &str) -> Result<(), Self::Error>;
+
+    /// Shadow-ban a user: their requests keep succeeding, but the homeserver
+    /// silently drops what they send to other users.
+    ///
+    /// # Parameters
+    ///
+    /// * `mxid` - The Matrix ID of the user to shadow-ban.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the homeserver is unreachable or the user could not
+    /// be shadow-banned.
+    async fn shadow_ban_user(&self, mxid: &str) -> Result<(), Self::Error>;
 }
 
 #[async_trait::async_trait]
@@ -353,9 +405,25 @@ impl<T: HomeserverConnection + Send + Sync + ?Sized> HomeserverConnection for &T
         (**self).unset_displayname(mxid).await
     }
 
+    async fn set_avatar_url(&self, mxid: &str, avatar_url: &str) -> Result<(), Self::Error> {
+        (**self).set_avatar_url(mxid, avatar_url).await
+    }
+
+    async fn unset_avatar_url(&self, mxid: &str) -> Result<(), Self::Error> {
+        (**self).unset_avatar_url(mxid).await
+    }
+
     async fn allow_cross_signing_reset(&self, mxid: &str) -> Result<(), Self::Error> {
         (**self).allow_cross_signing_reset(mxid).await
     }
+
+    async fn send_notice(&self, mxid: &str, body: &str) -> Result<(), Self::Error> {
+        (**self).send_notice(mxid, body).await
+    }
+
+    async fn shadow_ban_user(&self, mxid: &str) -> Result<(), Self::Error> {
+        (**self).shadow_ban_user(mxid).await
+    }
 }
 
 // Implement for Arc<T> where T: HomeserverConnection
@@ -399,7 +467,23 @@ impl<T: HomeserverConnection + ?Sized> HomeserverConnection for Arc<T> {
         (**self).unset_displayname(mxid).await
     }
 
+    async fn set_avatar_url(&self, mxid: &str, avatar_url: &str) -> Result<(), Self::Error> {
+        (**self).set_avatar_url(mxid, avatar_url).await
+    }
+
+    async fn unset_avatar_url(&self, mxid: &str) -> Result<(), Self::Error> {
+        (**self).unset_avatar_url(mxid).await
+    }
+
     async fn allow_cross_signing_reset(&self, mxid: &str) -> Result<(), Self::Error> {
         (**self).allow_cross_signing_reset(mxid).await
     }
+
+    async fn send_notice(&self, mxid: &str, body: &str) -> Result<(), Self::Error> {
+        (**self).send_notice(mxid, body).await
+    }
+
+    async fn shadow_ban_user(&self, mxid: &str) -> Result<(), Self::Error> {
+        (**self).shadow_ban_user(mxid).await
+    }
 }
@@ -27,6 +27,8 @@ struct MockUser {
     devices: HashSet<String>,
     emails: Option<Vec<String>>,
     cross_signing_reset_allowed: bool,
+    notices: Vec<String>,
+    shadow_banned: bool,
 }
 
 /// A mock implementation of a [`HomeserverConnection`], which never fails and
@@ -53,6 +55,25 @@ impl HomeserverConnection {
     pub async fn reserve_localpart(&self, localpart: &'static str) {
         self.reserved_localparts.write().await.insert(localpart);
     }
+
+    /// Get the notices sent to a user, oldest first.
+    pub async fn notices(&self, mxid: &str) -> Vec<String> {
+        self.users
+            .read()
+            .await
+            .get(mxid)
+            .map(|user| user.notices.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether a user was shadow-banned.
+    pub async fn is_shadow_banned(&self, mxid: &str) -> bool {
+        self.users
+            .read()
+            .await
+            .get(mxid)
+            .is_some_and(|user| user.shadow_banned)
+    }
 }
 
 #[async_trait]
@@ -82,6 +103,8 @@ impl crate::HomeserverConnection for HomeserverConnection {
             devices: HashSet::new(),
             emails: None,
             cross_signing_reset_allowed: false,
+            notices: Vec::new(),
+            shadow_banned: false,
         });
 
         anyhow::ensure!(
@@ -155,12 +178,40 @@ impl crate::HomeserverConnection for HomeserverConnection {
         Ok(())
     }
 
+    async fn set_avatar_url(&self, mxid: &str, avatar_url: &str) -> Result<(), Self::Error> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(mxid).context("User not found")?;
+        user.avatar_url = Some(avatar_url.to_owned());
+        Ok(())
+    }
+
+    async fn unset_avatar_url(&self, mxid: &str) -> Result<(), Self::Error> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(mxid).context("User not found")?;
+        user.avatar_url = None;
+        Ok(())
+    }
+
     async fn allow_cross_signing_reset(&self, mxid: &str) -> Result<(), Self::Error> {
         let mut users = self.users.write().await;
         let user = users.get_mut(mxid).context("User not found")?;
         user.cross_signing_reset_allowed = true;
         Ok(())
     }
+
+    async fn send_notice(&self, mxid: &str, body: &str) -> Result<(), Self::Error> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(mxid).context("User not found")?;
+        user.notices.push(body.to_owned());
+        Ok(())
+    }
+
+    async fn shadow_ban_user(&self, mxid: &str) -> Result<(), Self::Error> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(mxid).context("User not found")?;
+        user.shadow_banned = true;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -205,6 +256,17 @@ mod tests {
         let user = conn.query_user(mxid).await.unwrap();
         assert_eq!(user.displayname, None);
 
+        // Set and unset the avatar URL
+        assert!(conn
+            .set_avatar_url(mxid, "mxc://example.org/abcdef")
+            .await
+            .is_ok());
+        let user = conn.query_user(mxid).await.unwrap();
+        assert_eq!(user.avatar_url, Some("mxc://example.org/abcdef".into()));
+        assert!(conn.unset_avatar_url(mxid).await.is_ok());
+        let user = conn.query_user(mxid).await.unwrap();
+        assert_eq!(user.avatar_url, None);
+
         // Deleting a non-existent device should not fail
         assert!(conn.delete_device(mxid, device).await.is_ok());
 
@@ -213,6 +275,20 @@ mod tests {
         // Create the same device again
         assert!(conn.create_device(mxid, device).await.is_ok());
 
+        // Send a notice to the user
+        assert!(conn.send_notice(mxid, "Hello").await.is_ok());
+        assert_eq!(conn.notices(mxid).await, ["Hello"]);
+        assert!(conn
+            .send_notice("@alice:example.org", "Hello")
+            .await
+            .is_err());
+
+        // Shadow-ban the user
+        assert!(!conn.is_shadow_banned(mxid).await);
+        assert!(conn.shadow_ban_user(mxid).await.is_ok());
+        assert!(conn.is_shadow_banned(mxid).await);
+        assert!(conn.shadow_ban_user("@alice:example.org").await.is_err());
+
         // XXX: there is no API to query devices yet in the trait
         // Delete the device
         assert!(conn.delete_device(mxid, device).await.is_ok());
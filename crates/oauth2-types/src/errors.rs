@@ -264,6 +264,23 @@ pub enum ClientErrorCode {
     /// From [RFC8628](https://www.rfc-editor.org/rfc/rfc8628#section-3.5).
     ExpiredToken,
 
+    /// `invalid_binding_message`
+    ///
+    /// The binding message is invalid or unacceptable for use in the context
+    /// of the given request.
+    ///
+    /// From [OpenID Connect Client-Initiated Backchannel Authentication Flow](https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#rfc.section.13).
+    InvalidBindingMessage,
+
+    /// `unknown_user_id`
+    ///
+    /// The authorization server is not able to identify which end-user the
+    /// client wishes to be authenticated by means of the hint provided in the
+    /// request.
+    ///
+    /// From [OpenID Connect Client-Initiated Backchannel Authentication Flow](https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#rfc.section.13).
+    UnknownUserId,
+
     /// `unsupported_token_type`
     ///
     /// The authorization server does not support the revocation of the
@@ -304,6 +321,8 @@ impl core::fmt::Display for ClientErrorCode {
             ClientErrorCode::AuthorizationPending => f.write_str("authorization_pending"),
             ClientErrorCode::SlowDown => f.write_str("slow_down"),
             ClientErrorCode::ExpiredToken => f.write_str("expired_token"),
+            ClientErrorCode::InvalidBindingMessage => f.write_str("invalid_binding_message"),
+            ClientErrorCode::UnknownUserId => f.write_str("unknown_user_id"),
             ClientErrorCode::UnsupportedTokenType => f.write_str("unsupported_token_type"),
             ClientErrorCode::Unknown(value) => f.write_str(value),
         }
@@ -339,6 +358,8 @@ impl core::str::FromStr for ClientErrorCode {
             "authorization_pending" => Ok(ClientErrorCode::AuthorizationPending),
             "slow_down" => Ok(ClientErrorCode::SlowDown),
             "expired_token" => Ok(ClientErrorCode::ExpiredToken),
+            "invalid_binding_message" => Ok(ClientErrorCode::InvalidBindingMessage),
+            "unknown_user_id" => Ok(ClientErrorCode::UnknownUserId),
             "unsupported_token_type" => Ok(ClientErrorCode::UnsupportedTokenType),
             _ => Ok(ClientErrorCode::Unknown(s.to_owned())),
         }
@@ -428,6 +449,12 @@ impl ClientErrorCode {
             ClientErrorCode::ExpiredToken => {
                 "The \"device_code\" has expired, and the device authorization session has concluded"
             }
+            ClientErrorCode::InvalidBindingMessage => {
+                "The binding message is invalid or unacceptable for use in the context of the given request."
+            }
+            ClientErrorCode::UnknownUserId => {
+                "The authorization server is not able to identify the end-user from the provided hint."
+            }
             ClientErrorCode::UnsupportedTokenType => {
                 "The authorization server does not support the revocation of the presented token type."
             },
@@ -234,6 +234,50 @@ impl core::str::FromStr for ClaimType {
     }
 }
 
+/// Backchannel token delivery modes.
+///
+/// Source: <https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#registration>
+#[derive(SerializeDisplay, DeserializeFromStr, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum BackchannelTokenDeliveryMode {
+    /// The client polls the token endpoint to get the result of the
+    /// authentication.
+    Poll,
+
+    /// The OP sends a notification to the client when the authentication
+    /// completes, and the client then calls the token endpoint.
+    Ping,
+
+    /// The OP sends the tokens directly to the client.
+    Push,
+
+    /// An unknown value.
+    Unknown(String),
+}
+
+impl core::fmt::Display for BackchannelTokenDeliveryMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poll => f.write_str("poll"),
+            Self::Ping => f.write_str("ping"),
+            Self::Push => f.write_str("push"),
+            Self::Unknown(s) => f.write_str(s),
+        }
+    }
+}
+
+impl core::str::FromStr for BackchannelTokenDeliveryMode {
+    type Err = core::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "poll" => Ok(Self::Poll),
+            "ping" => Ok(Self::Ping),
+            "push" => Ok(Self::Push),
+            s => Ok(Self::Unknown(s.to_owned())),
+        }
+    }
+}
+
 /// An account management action that a user can take.
 ///
 /// Source: <https://github.com/matrix-org/matrix-spec-proposals/pull/2965>
@@ -272,6 +316,11 @@ pub enum AccountManagementAction {
     /// The user wishes to reset their cross-signing keys.
     CrossSigningReset,
 
+    /// `org.matrix.password_change`
+    ///
+    /// The user wishes to change their password.
+    PasswordChange,
+
     /// An unknown value.
     Unknown(String),
 }
@@ -285,6 +334,7 @@ impl core::fmt::Display for AccountManagementAction {
             Self::SessionEnd => write!(f, "org.matrix.session_end"),
             Self::AccountDeactivate => write!(f, "org.matrix.account_deactivate"),
             Self::CrossSigningReset => write!(f, "org.matrix.cross_signing_reset"),
+            Self::PasswordChange => write!(f, "org.matrix.password_change"),
             Self::Unknown(value) => write!(f, "{value}"),
         }
     }
@@ -301,6 +351,7 @@ impl core::str::FromStr for AccountManagementAction {
             "org.matrix.session_end" => Ok(Self::SessionEnd),
             "org.matrix.account_deactivate" => Ok(Self::AccountDeactivate),
             "org.matrix.cross_signing_reset" => Ok(Self::CrossSigningReset),
+            "org.matrix.password_change" => Ok(Self::PasswordChange),
             value => Ok(Self::Unknown(value.to_owned())),
         }
     }
@@ -617,6 +668,30 @@ pub struct ProviderMetadata {
     /// [device authorization endpoint]: https://www.rfc-editor.org/rfc/rfc8628
     pub device_authorization_endpoint: Option<Url>,
 
+    /// URL of the authorization server's [backchannel authentication
+    /// endpoint].
+    ///
+    /// [backchannel authentication endpoint]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#auth_backchannel_endpoint
+    pub backchannel_authentication_endpoint: Option<Url>,
+
+    /// JSON array containing the backchannel token delivery modes that this
+    /// OP supports.
+    pub backchannel_token_delivery_modes_supported: Option<Vec<BackchannelTokenDeliveryMode>>,
+
+    /// Indicates whether the OP supports the `user_code` parameter in
+    /// backchannel authentication requests.
+    ///
+    /// Defaults to `false`.
+    pub backchannel_user_code_parameter_supported: Option<bool>,
+
+    /// Indicates whether the authorization server supports [mutual-TLS client
+    /// certificate-bound access tokens].
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [mutual-TLS client certificate-bound access tokens]: https://www.rfc-editor.org/rfc/rfc8705#section-3.3
+    pub tls_client_certificate_bound_access_tokens: Option<bool>,
+
     /// URL of the authorization server's [RP-Initiated Logout endpoint].
     ///
     /// [RP-Initiated Logout endpoint]: https://openid.net/specs/openid-connect-rpinitiated-1_0.html
@@ -493,6 +493,26 @@ impl ClientMetadata {
             return Err(ClientMetadataVerificationError::JwksUriAndJwksMutuallyExclusive);
         }
 
+        // Those URLs are shown to the user, make sure they can't be used to
+        // inject something else than a link to a web page
+        for (field, uris) in [
+            ("client_uri", &self.client_uri),
+            ("logo_uri", &self.logo_uri),
+            ("policy_uri", &self.policy_uri),
+            ("tos_uri", &self.tos_uri),
+        ] {
+            if let Some((_lang, url)) = uris
+                .iter()
+                .flat_map(Localized::iter)
+                .find(|(_lang, url)| url.scheme() != "https" && url.scheme() != "http")
+            {
+                return Err(ClientMetadataVerificationError::UrlNonWebScheme(
+                    field,
+                    url.clone(),
+                ));
+            }
+        }
+
         if let Some(url) = self
             .sector_identifier_uri
             .as_ref()
@@ -860,6 +880,10 @@ pub enum ClientMetadataVerificationError {
     #[error("{0}'s URL doesn't use a https scheme: {1}")]
     UrlNonHttpsScheme(&'static str, Url),
 
+    /// The URL of the given field doesn't use a `http` or `https` scheme.
+    #[error("{0}'s URL doesn't use a http or https scheme: {1}")]
+    UrlNonWebScheme(&'static str, Url),
+
     /// No JWK Set was provided but one is required for the token auth method.
     #[error("missing JWK Set for token auth method")]
     MissingJwksForTokenMethod,
@@ -919,7 +943,7 @@ mod tests {
     use mas_jose::jwk::PublicJsonWebKeySet;
     use url::Url;
 
-    use super::{ClientMetadata, ClientMetadataVerificationError};
+    use super::{ClientMetadata, ClientMetadataVerificationError, Localized};
     use crate::{requests::GrantType, response_type::ResponseType};
 
     fn valid_client_metadata() -> ClientMetadata {
@@ -1394,6 +1418,28 @@ mod tests {
         metadata.validate().unwrap();
     }
 
+    #[test]
+    fn validate_client_uris() {
+        let mut metadata = valid_client_metadata();
+
+        // Err - Non-web URL
+        let logo_uri = Url::parse("javascript:alert(1)").unwrap();
+        metadata.logo_uri = Some(Localized::new(logo_uri.clone(), []));
+        let (field, url) = assert_matches!(
+            metadata.clone().validate(),
+            Err(ClientMetadataVerificationError::UrlNonWebScheme(field, url)) => (field, url)
+        );
+        assert_eq!(field, "logo_uri");
+        assert_eq!(url, logo_uri);
+
+        // Ok - http URL
+        metadata.logo_uri = Some(Localized::new(
+            Url::parse("http://localhost/logo.png").unwrap(),
+            [],
+        ));
+        metadata.validate().unwrap();
+    }
+
     #[test]
     fn validate_introspection_encrypted_response() {
         let mut metadata = valid_client_metadata();
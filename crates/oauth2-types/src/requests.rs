@@ -240,7 +240,10 @@ pub struct AuthorizationRequest {
 
     /// The scope of the access request.
     ///
-    /// OpenID Connect requests must contain the `openid` scope value.
+    /// OpenID Connect requests must contain the `openid` scope value. If it is
+    /// omitted, the authorization server may use a default scope for the
+    /// client.
+    #[serde(default, skip_serializing_if = "Scope::is_empty")]
     pub scope: Scope,
 
     /// Opaque value used to maintain state between the request and the
@@ -467,6 +470,73 @@ impl fmt::Debug for DeviceAuthorizationResponse {
     }
 }
 
+/// A request to the [Backchannel Authentication Endpoint].
+///
+/// [Backchannel Authentication Endpoint]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#auth_request
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct BackchannelAuthenticationRequest {
+    /// The scope of the access request.
+    pub scope: Option<Scope>,
+
+    /// A hint identifying the end-user for whom authentication is being
+    /// requested.
+    pub login_hint: Option<String>,
+
+    /// A human-readable identifier or message intended to be displayed on both
+    /// the consumption device and the authentication device.
+    pub binding_message: Option<String>,
+}
+
+/// The default value of the `interval` between polling requests in the
+/// backchannel authentication flow, if it is not set.
+pub const DEFAULT_BACKCHANNEL_AUTHENTICATION_INTERVAL: Duration =
+    Duration::microseconds(5 * 1000 * 1000);
+
+/// A successful response from the [Backchannel Authentication Endpoint].
+///
+/// [Backchannel Authentication Endpoint]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#auth_response
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BackchannelAuthenticationResponse {
+    /// The identifier of the authentication request, used by the client to
+    /// poll the token endpoint.
+    pub auth_req_id: String,
+
+    /// The lifetime of the `auth_req_id`.
+    #[serde_as(as = "DurationSeconds<i64>")]
+    pub expires_in: Duration,
+
+    /// The minimum amount of time in seconds that the client should wait
+    /// between polling requests to the token endpoint.
+    ///
+    /// Defaults to [`DEFAULT_BACKCHANNEL_AUTHENTICATION_INTERVAL`].
+    #[serde_as(as = "Option<DurationSeconds<i64>>")]
+    pub interval: Option<Duration>,
+}
+
+impl BackchannelAuthenticationResponse {
+    /// The minimum amount of time in seconds that the client should wait
+    /// between polling requests to the token endpoint.
+    ///
+    /// Defaults to [`DEFAULT_BACKCHANNEL_AUTHENTICATION_INTERVAL`].
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        self.interval
+            .unwrap_or(DEFAULT_BACKCHANNEL_AUTHENTICATION_INTERVAL)
+    }
+}
+
+impl fmt::Debug for BackchannelAuthenticationResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackchannelAuthenticationResponse")
+            .field("expires_in", &self.expires_in)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A request to the [Token Endpoint] for the [Authorization Code] grant type.
 ///
 /// [Token Endpoint]: https://www.rfc-editor.org/rfc/rfc6749#section-3.2
@@ -550,6 +620,25 @@ impl fmt::Debug for DeviceCodeGrant {
     }
 }
 
+/// A request to the [Token Endpoint] for the [Client-Initiated Backchannel
+/// Authentication] grant type.
+///
+/// [Token Endpoint]: https://www.rfc-editor.org/rfc/rfc6749#section-3.2
+/// [Client-Initiated Backchannel Authentication]: https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#token_request
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BackchannelAuthenticationGrant {
+    /// The identifier of the authentication request, from the backchannel
+    /// authentication response.
+    pub auth_req_id: String,
+}
+
+impl fmt::Debug for BackchannelAuthenticationGrant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackchannelAuthenticationGrant")
+            .finish_non_exhaustive()
+    }
+}
+
 /// All possible values for the `grant_type` parameter.
 #[derive(
     Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, SerializeDisplay, DeserializeFromStr,
@@ -641,6 +730,10 @@ pub enum AccessTokenRequest {
     #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
     DeviceCode(DeviceCodeGrant),
 
+    /// A request in the Client-Initiated Backchannel Authentication flow.
+    #[serde(rename = "urn:openid:params:grant-type:ciba")]
+    ClientInitiatedBackchannelAuthentication(BackchannelAuthenticationGrant),
+
     /// An unsupported request.
     #[serde(skip_serializing, other)]
     Unsupported,
@@ -794,6 +887,30 @@ pub struct IntrospectionResponse {
 
     /// String identifier for the token.
     pub jti: Option<String>,
+
+    /// Confirmation of the key the token is bound to.
+    pub cnf: Option<Confirmation>,
+
+    /// Whether the user the token belongs to is shadow-banned.
+    ///
+    /// This is a non-standard field, which lets the resource server silently
+    /// drop the traffic of those users. It is omitted for users who are not
+    /// shadow-banned.
+    pub shadow_banned: Option<bool>,
+}
+
+/// The confirmation of the key a token is bound to, as per [RFC 7800].
+///
+/// [RFC 7800]: https://www.rfc-editor.org/rfc/rfc7800
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Confirmation {
+    /// The base64url-encoded SHA-256 thumbprint of the TLS client certificate
+    /// the token is bound to, as per [RFC 8705].
+    ///
+    /// [RFC 8705]: https://www.rfc-editor.org/rfc/rfc8705#section-3.1
+    #[serde(rename = "x5t#S256")]
+    pub x5t_s256: Option<String>,
 }
 
 /// A request to the [Revocation Endpoint].
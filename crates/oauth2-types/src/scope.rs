@@ -45,6 +45,34 @@ impl ScopeToken {
     pub fn as_str(&self) -> &str {
         self.0.as_ref()
     }
+
+    /// Create a Matrix device scope token for the given device ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device ID contains characters which are not
+    /// allowed in a scope token.
+    pub fn try_with_matrix_device(device_id: &str) -> Result<Self, InvalidScope> {
+        format!("{MATRIX_DEVICE_PREFIX}{device_id}").parse()
+    }
+
+    /// Get the device ID of this scope token, if it is a Matrix device scope
+    /// token.
+    #[must_use]
+    pub fn matrix_device_id(&self) -> Option<&str> {
+        self.as_str()
+            .strip_prefix(MATRIX_DEVICE_PREFIX)
+            .filter(|device_id| !device_id.is_empty())
+    }
+
+    /// Get the requested Matrix API scope, e.g. `*` for full access, if it is a
+    /// Matrix API scope token.
+    #[must_use]
+    pub fn matrix_api_scope(&self) -> Option<&str> {
+        self.as_str()
+            .strip_prefix(MATRIX_API_PREFIX)
+            .filter(|scope| !scope.is_empty())
+    }
 }
 
 /// `openid`.
@@ -79,6 +107,24 @@ pub const PHONE: ScopeToken = ScopeToken::from_static("phone");
 /// Endpoint even when the End-User is not present (not logged in).
 pub const OFFLINE_ACCESS: ScopeToken = ScopeToken::from_static("offline_access");
 
+/// The prefix of the Matrix Client-Server API scope tokens.
+const MATRIX_API_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:api:";
+
+/// The prefix of the Matrix device scope tokens.
+const MATRIX_DEVICE_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:device:";
+
+/// `urn:matrix:org.matrix.msc2967.client:api:*`.
+///
+/// Requests full access to the Matrix Client-Server API.
+pub const MATRIX_API: ScopeToken =
+    ScopeToken::from_static("urn:matrix:org.matrix.msc2967.client:api:*");
+
+/// `urn:matrix:org.matrix.msc2967.client:api:guest`.
+///
+/// Requests guest access to the Matrix Client-Server API.
+pub const MATRIX_GUEST: ScopeToken =
+    ScopeToken::from_static("urn:matrix:org.matrix.msc2967.client:api:guest");
+
 // As per RFC6749 appendix A:
 // https://datatracker.ietf.org/doc/html/rfc6749#appendix-A
 //
@@ -117,8 +163,27 @@ impl std::fmt::Display for ScopeToken {
     }
 }
 
+impl Serialize for ScopeToken {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.as_str().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScopeToken {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let token: Cow<'de, str> = Deserialize::deserialize(deserializer)?;
+        ScopeToken::from_str(&token).map_err(serde::de::Error::custom)
+    }
+}
+
 /// A scope.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Scope(BTreeSet<ScopeToken>);
 
 impl Deref for Scope {
@@ -202,8 +267,7 @@ impl<'de> Deserialize<'de> for Scope {
     where
         D: serde::Deserializer<'de>,
     {
-        // FIXME: seems like there is an unnecessary clone here?
-        let scope: String = Deserialize::deserialize(deserializer)?;
+        let scope: Cow<'de, str> = Deserialize::deserialize(deserializer)?;
         Scope::from_str(&scope).map_err(serde::de::Error::custom)
     }
 }
@@ -225,6 +289,47 @@ mod tests {
         assert_eq!(ScopeToken::from_str("invalid\\scope"), Err(InvalidScope));
     }
 
+    #[test]
+    fn matrix_scope_tokens() {
+        let token =
+            ScopeToken::from_str("urn:matrix:org.matrix.msc2967.client:device:ABCDEFGHIJ").unwrap();
+        assert_eq!(token.matrix_device_id(), Some("ABCDEFGHIJ"));
+        assert_eq!(token.matrix_api_scope(), None);
+        assert_eq!(
+            ScopeToken::try_with_matrix_device("ABCDEFGHIJ"),
+            Ok(token.clone())
+        );
+        assert_eq!(
+            ScopeToken::try_with_matrix_device("invalid device"),
+            Err(InvalidScope)
+        );
+
+        assert_eq!(MATRIX_API.matrix_api_scope(), Some("*"));
+        assert_eq!(MATRIX_API.matrix_device_id(), None);
+        assert_eq!(MATRIX_GUEST.matrix_api_scope(), Some("guest"));
+
+        let token = ScopeToken::from_str("urn:matrix:org.matrix.msc2967.client:device:").unwrap();
+        assert_eq!(token.matrix_device_id(), None);
+        assert_eq!(OPENID.matrix_device_id(), None);
+        assert_eq!(OPENID.matrix_api_scope(), None);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let token: ScopeToken = serde_json::from_str(r#""openid""#).unwrap();
+        assert_eq!(token, OPENID);
+        assert_eq!(serde_json::to_string(&token).unwrap(), r#""openid""#);
+        assert!(serde_json::from_str::<ScopeToken>(r#""invalid\\scope""#).is_err());
+
+        let scope: Scope =
+            serde_json::from_str(r#""openid urn:matrix:org.matrix.msc2967.client:api:*""#).unwrap();
+        assert_eq!(scope, Scope::from_iter([OPENID, MATRIX_API]));
+        assert_eq!(
+            serde_json::to_string(&scope).unwrap(),
+            r#""openid urn:matrix:org.matrix.msc2967.client:api:*""#
+        );
+    }
+
     #[test]
     fn parse_scope() {
         let scope = Scope::from_str("openid profile address").unwrap();
@@ -69,6 +69,12 @@ pub enum AccountManagementActionFull {
     /// The user wishes to reset their cross-signing keys.
     #[serde(rename = "org.matrix.cross_signing_reset")]
     CrossSigningReset,
+
+    /// `org.matrix.password_change`
+    ///
+    /// The user wishes to change their password.
+    #[serde(rename = "org.matrix.password_change")]
+    PasswordChange,
 }
 
 #[skip_serializing_none]
@@ -536,39 +536,72 @@ pub async fn access_token_with_authorization_code(
     .await?;
 
     let id_token = if let Some(verification_data) = id_token_verification_data {
-        let signing_alg = verification_data.signing_algorithm;
+        Some(verify_authorization_code_id_token(
+            &token_response,
+            &code,
+            &validation_data.nonce,
+            verification_data,
+            now,
+        )?)
+    } else {
+        None
+    };
 
-        let id_token = token_response
-            .id_token
-            .as_deref()
-            .ok_or(IdTokenError::MissingIdToken)?;
+    Ok((token_response, id_token))
+}
 
-        let id_token = verify_id_token(id_token, verification_data, None, now)?;
+/// Verify the ID Token returned when exchanging an authorization code.
+///
+/// This is the verification done by [`access_token_with_authorization_code`]
+/// when it is given the `id_token_verification_data`. It is useful when the
+/// JWKS to verify the ID Token against can only be figured out once the token
+/// response was received, for example to refresh the keys of the issuer if the
+/// ID Token is signed with an unknown key.
+///
+/// # Arguments
+///
+/// * `token_response` - The response of the Token endpoint.
+///
+/// * `code` - The authorization code that was exchanged.
+///
+/// * `nonce` - The nonce that was sent in the authorization request.
+///
+/// * `verification_data` - The data required to verify the ID Token.
+///
+/// * `now` - The current time.
+///
+/// # Errors
+///
+/// Returns an error if the ID Token is missing or if its verification fails.
+pub fn verify_authorization_code_id_token(
+    token_response: &AccessTokenResponse,
+    code: &str,
+    nonce: &str,
+    verification_data: JwtVerificationData<'_>,
+    now: DateTime<Utc>,
+) -> Result<IdToken<'static>, IdTokenError> {
+    let signing_alg = verification_data.signing_algorithm;
 
-        let mut claims = id_token.payload().clone();
+    let id_token = token_response
+        .id_token
+        .as_deref()
+        .ok_or(IdTokenError::MissingIdToken)?;
 
-        // Access token hash must match.
-        claims::AT_HASH
-            .extract_optional_with_options(
-                &mut claims,
-                TokenHash::new(signing_alg, &token_response.access_token),
-            )
-            .map_err(IdTokenError::from)?;
+    let id_token = verify_id_token(id_token, verification_data, None, now)?;
 
-        // Code hash must match.
-        claims::C_HASH
-            .extract_optional_with_options(&mut claims, TokenHash::new(signing_alg, &code))
-            .map_err(IdTokenError::from)?;
+    let mut claims = id_token.payload().clone();
 
-        // Nonce must match.
-        claims::NONCE
-            .extract_required_with_options(&mut claims, validation_data.nonce.as_str())
-            .map_err(IdTokenError::from)?;
+    // Access token hash must match.
+    claims::AT_HASH.extract_optional_with_options(
+        &mut claims,
+        TokenHash::new(signing_alg, &token_response.access_token),
+    )?;
 
-        Some(id_token.into_owned())
-    } else {
-        None
-    };
+    // Code hash must match.
+    claims::C_HASH.extract_optional_with_options(&mut claims, TokenHash::new(signing_alg, code))?;
 
-    Ok((token_response, id_token))
+    // Nonce must match.
+    claims::NONCE.extract_required_with_options(&mut claims, nonce)?;
+
+    Ok(id_token.into_owned())
 }
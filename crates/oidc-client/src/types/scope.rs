@@ -129,16 +129,12 @@ impl From<StrScopeToken> for ScopeToken {
             "phone" => Self::Phone,
             "offline_access" => Self::OfflineAccess,
             s => {
-                if let Some(matrix_scope) =
-                    s.strip_prefix("urn:matrix:org.matrix.msc2967.client:api:")
-                {
+                if let Some(matrix_scope) = t.matrix_api_scope() {
                     Self::MatrixApi(
                         MatrixApiScopeToken::from_str(matrix_scope)
                             .expect("If the whole string is a valid scope, a substring is too"),
                     )
-                } else if let Some(device_id) =
-                    s.strip_prefix("urn:matrix:org.matrix.msc2967.client:device:")
-                {
+                } else if let Some(device_id) = t.matrix_device_id() {
                     Self::MatrixDevice(PrivString(device_id.to_owned()))
                 } else {
                     Self::Custom(PrivString(s.to_owned()))
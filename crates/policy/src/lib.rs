@@ -14,7 +14,9 @@
 
 pub mod model;
 
-use mas_data_model::{AuthorizationGrant, Client, DeviceCodeGrant, User};
+use mas_data_model::{
+    AuthorizationGrant, BackchannelAuthenticationGrant, Client, DeviceCodeGrant, User,
+};
 use oauth2_types::{registration::VerifiedClientMetadata, scope::Scope};
 use opa_wasm::Runtime;
 use thiserror::Error;
@@ -386,6 +388,42 @@ impl Policy {
 
         Ok(res)
     }
+
+    #[tracing::instrument(
+        name = "policy.evaluate.backchannel_authentication_grant",
+        skip_all,
+        fields(
+            input.backchannel_authentication_grant.id = %grant.id,
+            input.scope = %grant.scope,
+            input.client.id = %client.id,
+            input.user.id = %user.id,
+        ),
+        err,
+    )]
+    pub async fn evaluate_backchannel_authentication_grant(
+        &mut self,
+        grant: &BackchannelAuthenticationGrant,
+        client: &Client,
+        user: &User,
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let input = AuthorizationGrantInput {
+            user: Some(user),
+            client,
+            scope: &grant.scope,
+            grant_type: GrantType::Ciba,
+        };
+
+        let [res]: [EvaluationResult; 1] = self
+            .instance
+            .evaluate(
+                &mut self.store,
+                &self.entrypoints.authorization_grant,
+                &input,
+            )
+            .await?;
+
+        Ok(res)
+    }
 }
 
 #[cfg(test)]
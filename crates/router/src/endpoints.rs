@@ -27,10 +27,15 @@ pub enum PostAuthAction {
     ContinueDeviceCodeGrant {
         id: Ulid,
     },
+    ContinueBackchannelAuthenticationGrant {
+        id: Ulid,
+    },
     ContinueCompatSsoLogin {
         id: Ulid,
     },
     ChangePassword,
+    ChangeEmail,
+    ExportData,
     LinkUpstream {
         id: Ulid,
     },
@@ -51,6 +56,11 @@ impl PostAuthAction {
         PostAuthAction::ContinueDeviceCodeGrant { id }
     }
 
+    #[must_use]
+    pub const fn continue_backchannel_authentication_grant(id: Ulid) -> Self {
+        PostAuthAction::ContinueBackchannelAuthenticationGrant { id }
+    }
+
     #[must_use]
     pub const fn continue_compat_sso_login(id: Ulid) -> Self {
         PostAuthAction::ContinueCompatSsoLogin { id }
@@ -74,10 +84,15 @@ impl PostAuthAction {
             Self::ContinueDeviceCodeGrant { id } => {
                 url_builder.redirect(&DeviceCodeConsent::new(*id))
             }
+            Self::ContinueBackchannelAuthenticationGrant { id } => {
+                url_builder.redirect(&BackchannelAuthenticationConsent::new(*id))
+            }
             Self::ContinueCompatSsoLogin { id } => {
                 url_builder.redirect(&CompatLoginSsoComplete::new(*id, None))
             }
             Self::ChangePassword => url_builder.redirect(&AccountPasswordChange),
+            Self::ChangeEmail => url_builder.redirect(&AccountChangeEmail),
+            Self::ExportData => url_builder.redirect(&AccountExport),
             Self::LinkUpstream { id } => url_builder.redirect(&UpstreamOAuth2Link::new(*id)),
             Self::ManageAccount { action } => url_builder.redirect(&Account {
                 action: action.clone(),
@@ -94,6 +109,33 @@ impl SimpleRoute for OidcConfiguration {
     const PATH: &'static str = "/.well-known/openid-configuration";
 }
 
+/// `GET /.well-known/oauth-authorization-server`
+#[derive(Default, Debug, Clone)]
+pub struct OAuth2AuthorizationServerMetadata;
+
+impl SimpleRoute for OAuth2AuthorizationServerMetadata {
+    const PATH: &'static str = "/.well-known/oauth-authorization-server";
+}
+
+/// `GET /.well-known/oauth-authorization-server/*`
+///
+/// The variant with the path of the issuer inserted after the well-known
+/// suffix, as described in RFC 8414 section 3
+#[derive(Default, Debug, Clone)]
+pub struct OAuth2AuthorizationServerMetadataWithPath;
+
+impl SimpleRoute for OAuth2AuthorizationServerMetadataWithPath {
+    const PATH: &'static str = "/.well-known/oauth-authorization-server/*issuer_path";
+}
+
+/// `GET /.well-known/matrix/client`
+#[derive(Default, Debug, Clone)]
+pub struct MatrixClientWellKnown;
+
+impl SimpleRoute for MatrixClientWellKnown {
+    const PATH: &'static str = "/.well-known/matrix/client";
+}
+
 /// `GET /.well-known/webfinger`
 #[derive(Default, Debug, Clone)]
 pub struct Webfinger;
@@ -181,6 +223,14 @@ impl SimpleRoute for Healthcheck {
     const PATH: &'static str = "/health";
 }
 
+/// `GET /health/ready`
+#[derive(Default, Debug, Clone)]
+pub struct Readiness;
+
+impl SimpleRoute for Readiness {
+    const PATH: &'static str = "/health/ready";
+}
+
 /// `GET|POST /login`
 #[derive(Default, Debug, Clone)]
 pub struct Login {
@@ -221,6 +271,15 @@ impl Login {
         }
     }
 
+    #[must_use]
+    pub const fn and_continue_backchannel_authentication_grant(id: Ulid) -> Self {
+        Self {
+            post_auth_action: Some(PostAuthAction::continue_backchannel_authentication_grant(
+                id,
+            )),
+        }
+    }
+
     #[must_use]
     pub const fn and_continue_compat_sso_login(id: Ulid) -> Self {
         Self {
@@ -255,6 +314,44 @@ impl From<Option<PostAuthAction>> for Login {
     }
 }
 
+/// `GET|POST /login/code/:id`
+#[derive(Debug, Clone)]
+pub struct LoginCode {
+    id: Ulid,
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl LoginCode {
+    #[must_use]
+    pub fn new(id: Ulid) -> Self {
+        Self {
+            id,
+            post_auth_action: None,
+        }
+    }
+
+    #[must_use]
+    pub fn and_maybe(mut self, action: Option<PostAuthAction>) -> Self {
+        self.post_auth_action = action;
+        self
+    }
+}
+
+impl Route for LoginCode {
+    type Query = PostAuthAction;
+    fn route() -> &'static str {
+        "/login/code/:id"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/login/code/{}", self.id).into()
+    }
+}
+
 /// `POST /logout`
 #[derive(Default, Debug, Clone)]
 pub struct Logout;
@@ -452,6 +549,70 @@ impl AccountAddEmail {
     }
 }
 
+/// `GET|POST /change-email`
+#[derive(Default, Debug, Clone)]
+pub struct AccountChangeEmail;
+
+impl SimpleRoute for AccountChangeEmail {
+    const PATH: &'static str = "/change-email";
+}
+
+/// `GET|POST /change-email/:id`
+#[derive(Debug, Clone)]
+pub struct AccountChangeEmailVerify {
+    id: Ulid,
+}
+
+impl AccountChangeEmailVerify {
+    #[must_use]
+    pub fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for AccountChangeEmailVerify {
+    type Query = ();
+    fn route() -> &'static str {
+        "/change-email/:id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/change-email/{}", self.id).into()
+    }
+}
+
+/// `GET|POST /export`
+#[derive(Default, Debug, Clone)]
+pub struct AccountExport;
+
+impl SimpleRoute for AccountExport {
+    const PATH: &'static str = "/export";
+}
+
+/// `GET /export/:id`
+#[derive(Debug, Clone)]
+pub struct AccountExportDownload {
+    id: Ulid,
+}
+
+impl AccountExportDownload {
+    #[must_use]
+    pub fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for AccountExportDownload {
+    type Query = ();
+    fn route() -> &'static str {
+        "/export/:id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/export/{}", self.id).into()
+    }
+}
+
 /// Actions parameters as defined by MSC2965
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "action")]
@@ -478,6 +639,9 @@ pub enum AccountAction {
 
     #[serde(rename = "org.matrix.cross_signing_reset")]
     OrgMatrixCrossSigningReset,
+
+    #[serde(rename = "org.matrix.password_change")]
+    OrgMatrixPasswordChange,
 }
 
 /// `GET /account/`
@@ -568,6 +732,13 @@ impl SimpleRoute for CompatRefresh {
     const PATH: &'static str = "/_matrix/client/:version/refresh";
 }
 
+/// `POST /_matrix/client/v3/register`
+pub struct CompatRegister;
+
+impl SimpleRoute for CompatRegister {
+    const PATH: &'static str = "/_matrix/client/:version/register";
+}
+
 /// `GET /_matrix/client/v3/login/sso/redirect`
 pub struct CompatLoginSsoRedirect;
 
@@ -720,6 +891,29 @@ impl Route for UpstreamOAuth2Link {
     }
 }
 
+/// `POST /upstream/deprovision/:id`
+pub struct UpstreamOAuth2Deprovision {
+    id: Ulid,
+}
+
+impl UpstreamOAuth2Deprovision {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for UpstreamOAuth2Deprovision {
+    type Query = ();
+    fn route() -> &'static str {
+        "/upstream/deprovision/:provider_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/deprovision/{}", self.id).into()
+    }
+}
+
 /// `GET|POST /link`
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct DeviceCodeLink {
@@ -768,6 +962,54 @@ impl DeviceCodeConsent {
     }
 }
 
+/// `GET|POST /ciba/:grant_id`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct BackchannelAuthenticationConsent {
+    id: Ulid,
+}
+
+impl Route for BackchannelAuthenticationConsent {
+    type Query = ();
+    fn route() -> &'static str {
+        "/ciba/:grant_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/ciba/{}", self.id).into()
+    }
+}
+
+impl BackchannelAuthenticationConsent {
+    #[must_use]
+    pub fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+/// `GET /clients/:client_id/logo`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct ClientLogo {
+    id: Ulid,
+}
+
+impl Route for ClientLogo {
+    type Query = ();
+    fn route() -> &'static str {
+        "/clients/:client_id/logo"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/clients/{}/logo", self.id).into()
+    }
+}
+
+impl ClientLogo {
+    #[must_use]
+    pub fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
 /// `POST /oauth2/device`
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct OAuth2DeviceAuthorizationEndpoint;
@@ -776,6 +1018,22 @@ impl SimpleRoute for OAuth2DeviceAuthorizationEndpoint {
     const PATH: &'static str = "/oauth2/device";
 }
 
+/// `POST /oauth2/bc-authorize`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct OAuth2BackchannelAuthenticationEndpoint;
+
+impl SimpleRoute for OAuth2BackchannelAuthenticationEndpoint {
+    const PATH: &'static str = "/oauth2/bc-authorize";
+}
+
+/// `GET|POST /impersonate`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct Impersonate;
+
+impl SimpleRoute for Impersonate {
+    const PATH: &'static str = "/impersonate";
+}
+
 /// `GET|POST /recover`
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct AccountRecoveryStart;
@@ -833,6 +1091,325 @@ impl Route for AccountRecoveryFinish {
     }
 }
 
+/// `GET /login/negotiate`
+#[derive(Default, Debug, Clone)]
+pub struct LoginNegotiate {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl LoginNegotiate {
+    #[must_use]
+    pub fn and_then(action: PostAuthAction) -> Self {
+        Self {
+            post_auth_action: Some(action),
+        }
+    }
+
+    /// Get a reference to the negotiate login's post auth action.
+    #[must_use]
+    pub fn post_auth_action(&self) -> Option<&PostAuthAction> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl Route for LoginNegotiate {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/login/negotiate"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for LoginNegotiate {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
+/// `GET /login/certificate`
+#[derive(Default, Debug, Clone)]
+pub struct LoginCertificate {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl LoginCertificate {
+    #[must_use]
+    pub fn and_then(action: PostAuthAction) -> Self {
+        Self {
+            post_auth_action: Some(action),
+        }
+    }
+
+    /// Get a reference to the certificate login's post auth action.
+    #[must_use]
+    pub fn post_auth_action(&self) -> Option<&PostAuthAction> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl Route for LoginCertificate {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/login/certificate"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for LoginCertificate {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
+/// `GET|POST /login/link`
+#[derive(Default, Debug, Clone)]
+pub struct MagicLinkStart {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl MagicLinkStart {
+    #[must_use]
+    pub fn and_then(action: PostAuthAction) -> Self {
+        Self {
+            post_auth_action: Some(action),
+        }
+    }
+
+    /// Get a reference to the magic link start's post auth action.
+    #[must_use]
+    pub fn post_auth_action(&self) -> Option<&PostAuthAction> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl Route for MagicLinkStart {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/login/link"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for MagicLinkStart {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
+/// `GET|POST /login/link/progress/:session_id`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct MagicLinkProgress {
+    session_id: Ulid,
+}
+
+impl MagicLinkProgress {
+    #[must_use]
+    pub fn new(session_id: Ulid) -> Self {
+        Self { session_id }
+    }
+}
+
+impl Route for MagicLinkProgress {
+    type Query = ();
+    fn route() -> &'static str {
+        "/login/link/progress/:session_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/login/link/progress/{}", self.session_id).into()
+    }
+}
+
+/// `GET|POST /login/link/confirm?ticket=:ticket`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct MagicLinkConfirm {
+    ticket: String,
+}
+
+impl MagicLinkConfirm {
+    #[must_use]
+    pub fn new(ticket: String) -> Self {
+        Self { ticket }
+    }
+}
+
+impl Route for MagicLinkConfirm {
+    type Query = MagicLinkConfirm;
+
+    fn route() -> &'static str {
+        "/login/link/confirm"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
+/// `GET|POST /login-alert/report?ticket=:ticket`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct LoginAlertReport {
+    ticket: String,
+}
+
+impl LoginAlertReport {
+    #[must_use]
+    pub fn new(ticket: String) -> Self {
+        Self { ticket }
+    }
+}
+
+impl Route for LoginAlertReport {
+    type Query = LoginAlertReport;
+
+    fn route() -> &'static str {
+        "/login-alert/report"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
+/// `GET|POST /email-change/undo?ticket=:ticket`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct EmailChangeUndo {
+    ticket: String,
+}
+
+impl EmailChangeUndo {
+    #[must_use]
+    pub fn new(ticket: String) -> Self {
+        Self { ticket }
+    }
+}
+
+impl Route for EmailChangeUndo {
+    type Query = EmailChangeUndo;
+
+    fn route() -> &'static str {
+        "/email-change/undo"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
+/// `GET /admin/users?q=:search&after=:user_id`
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct AdminUsers {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    q: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<Ulid>,
+}
+
+impl AdminUsers {
+    /// List the users whose username contains the given string
+    #[must_use]
+    pub fn with_search(search: String) -> Self {
+        Self {
+            q: Some(search),
+            after: None,
+        }
+    }
+
+    /// Start the list after the given user
+    #[must_use]
+    pub fn after(mut self, id: Ulid) -> Self {
+        self.after = Some(id);
+        self
+    }
+
+    /// Get the search string, if it is not empty
+    #[must_use]
+    pub fn search(&self) -> Option<&str> {
+        self.q.as_deref().filter(|q| !q.is_empty())
+    }
+
+    /// Get the ID of the user after which the list starts
+    #[must_use]
+    pub fn cursor(&self) -> Option<Ulid> {
+        self.after
+    }
+}
+
+impl Route for AdminUsers {
+    type Query = AdminUsers;
+
+    fn route() -> &'static str {
+        "/admin/users"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
+/// `GET /admin/users/:user_id`
+pub struct AdminUser {
+    id: Ulid,
+}
+
+impl AdminUser {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for AdminUser {
+    type Query = ();
+    fn route() -> &'static str {
+        "/admin/users/:user_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/admin/users/{}", self.id).into()
+    }
+}
+
+/// `GET /admin/clients`
+pub struct AdminClients;
+
+impl SimpleRoute for AdminClients {
+    const PATH: &'static str = "/admin/clients";
+}
+
+/// `GET /admin/stats/authentications`
+pub struct AdminStatsAuthentications;
+
+impl SimpleRoute for AdminStatsAuthentications {
+    const PATH: &'static str = "/admin/stats/authentications";
+}
+
+/// `GET /admin/stats/registrations`
+pub struct AdminStatsRegistrations;
+
+impl SimpleRoute for AdminStatsRegistrations {
+    const PATH: &'static str = "/admin/stats/registrations";
+}
+
+/// `GET /admin/stats/tokens`
+pub struct AdminStatsTokens;
+
+impl SimpleRoute for AdminStatsTokens {
+    const PATH: &'static str = "/admin/stats/tokens";
+}
+
 /// `GET /assets`
 pub struct StaticAsset {
     path: String,
@@ -869,3 +1446,17 @@ pub struct GraphQLPlayground;
 impl SimpleRoute for GraphQLPlayground {
     const PATH: &'static str = "/graphql/playground";
 }
+
+/// `GET /api/spec.json`
+pub struct OpenApiSpec;
+
+impl SimpleRoute for OpenApiSpec {
+    const PATH: &'static str = "/api/spec.json";
+}
+
+/// `GET /api/doc/`
+pub struct OpenApiExplorer;
+
+impl SimpleRoute for OpenApiExplorer {
+    const PATH: &'static str = "/api/doc/";
+}
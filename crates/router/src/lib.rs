@@ -49,4 +49,20 @@ mod tests {
             "https://example.com/.well-known/openid-configuration"
         );
     }
+
+    #[test]
+    fn test_admin_urls() {
+        assert_eq!(AdminUsers::default().path_and_query(), "/admin/users");
+        assert_eq!(
+            AdminUsers::with_search("john doe".to_owned())
+                .after(Ulid::nil())
+                .path_and_query(),
+            "/admin/users?q=john+doe&after=00000000000000000000000000"
+        );
+        assert_eq!(
+            AdminUser::new(Ulid::nil()).path_and_query(),
+            "/admin/users/00000000000000000000000000"
+        );
+        assert_eq!(AdminClients.path_and_query(), "/admin/clients");
+    }
 }
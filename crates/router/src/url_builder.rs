@@ -178,6 +178,18 @@ impl UrlBuilder {
         self.absolute_url_for(&crate::endpoints::OAuth2DeviceAuthorizationEndpoint)
     }
 
+    /// OpenID Connect backchannel authentication endpoint
+    #[must_use]
+    pub fn oauth_backchannel_authentication_endpoint(&self) -> Url {
+        self.absolute_url_for(&crate::endpoints::OAuth2BackchannelAuthenticationEndpoint)
+    }
+
+    /// Link to approve a backchannel authentication request
+    #[must_use]
+    pub fn backchannel_authentication_consent(&self, id: Ulid) -> Url {
+        self.absolute_url_for(&crate::endpoints::BackchannelAuthenticationConsent::new(id))
+    }
+
     /// OAuth 2.0 device code link
     #[must_use]
     pub fn device_code_link(&self) -> Url {
@@ -243,6 +255,30 @@ impl UrlBuilder {
     pub fn account_recovery_link(&self, ticket: String) -> Url {
         self.absolute_url_for(&crate::endpoints::AccountRecoveryFinish::new(ticket))
     }
+
+    /// Magic link to log in, sent by email
+    #[must_use]
+    pub fn magic_link(&self, ticket: String) -> Url {
+        self.absolute_url_for(&crate::endpoints::MagicLinkConfirm::new(ticket))
+    }
+
+    /// Login alert report link
+    #[must_use]
+    pub fn login_alert_report_link(&self, ticket: String) -> Url {
+        self.absolute_url_for(&crate::endpoints::LoginAlertReport::new(ticket))
+    }
+
+    /// Email change undo link
+    #[must_use]
+    pub fn email_change_undo_link(&self, ticket: String) -> Url {
+        self.absolute_url_for(&crate::endpoints::EmailChangeUndo::new(ticket))
+    }
+
+    /// Link to download an export of the data of a user
+    #[must_use]
+    pub fn account_export_download_link(&self, id: Ulid) -> Url {
+        self.absolute_url_for(&crate::endpoints::AccountExportDownload::new(id))
+    }
 }
 
 #[cfg(test)]
@@ -222,6 +222,17 @@ impl Manifest {
         self.find_preload(entry)
     }
 
+    /// Find the fingerprinted file name of an asset, relative to the assets
+    /// base, from its original name
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the asset is not in this manifest
+    pub fn file_for<'a>(&'a self, name: &'a Utf8Path) -> Result<&'a Utf8Path, InvalidManifest<'a>> {
+        let entry = self.lookup_by_name(name)?;
+        Ok(&entry.file)
+    }
+
     /// Lookup an entry in the manifest by its original name
     fn lookup_by_name<'a>(
         &self,
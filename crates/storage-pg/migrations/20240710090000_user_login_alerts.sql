@@ -0,0 +1,76 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Stores the devices and networks users logged in from, so that we can tell
+-- when they log in from a new one.
+CREATE TABLE "user_login_sightings" (
+  "user_login_sighting_id" UUID NOT NULL
+    CONSTRAINT "user_login_sightings_pkey"
+    PRIMARY KEY,
+
+  -- The user who logged in
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The user agent of the device used to log in
+  "user_agent" TEXT NOT NULL,
+
+  -- The network the login was made from
+  "ip_network" INET NOT NULL,
+
+  -- When this device and network were first seen
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When this device and network were last seen
+  "last_seen_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  CONSTRAINT "user_login_sightings_user_id_user_agent_ip_network_key"
+    UNIQUE ("user_id", "user_agent", "ip_network")
+);
+
+-- Stores the alerts sent to users when they log in from an unknown device or
+-- network.
+CREATE TABLE "user_login_alerts" (
+  "user_login_alert_id" UUID NOT NULL
+    CONSTRAINT "user_login_alerts_pkey"
+    PRIMARY KEY,
+
+  -- The user who logged in
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The browser session which was started by the login
+  "user_session_id" UUID NOT NULL
+    REFERENCES "user_sessions" ("user_session_id")
+    ON DELETE CASCADE,
+
+  -- The user agent of the device used to log in
+  "user_agent" TEXT,
+
+  -- The IP address the login was made from
+  "ip_address" INET,
+
+  -- The ticket used to report the login
+  "ticket" TEXT NOT NULL
+    CONSTRAINT "user_login_alerts_ticket_key"
+    UNIQUE,
+
+  -- When the alert was created
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the user reported that the login wasn't them
+  "reported_at" TIMESTAMP WITH TIME ZONE
+);
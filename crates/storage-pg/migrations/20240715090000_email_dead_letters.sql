@@ -0,0 +1,36 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Stores the emails which could not be delivered, even after retrying, so that
+-- administrators can look into them.
+CREATE TABLE "email_dead_letters" (
+  "email_dead_letter_id" UUID NOT NULL
+    CONSTRAINT "email_dead_letters_pkey"
+    PRIMARY KEY,
+
+  -- The addresses the email was sent to
+  "recipients" TEXT[] NOT NULL,
+
+  -- The subject of the email
+  "subject" TEXT NOT NULL,
+
+  -- How many times sending the email was attempted
+  "attempts" INTEGER NOT NULL,
+
+  -- The error returned by the last attempt
+  "last_error" TEXT NOT NULL,
+
+  -- When we gave up sending the email
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
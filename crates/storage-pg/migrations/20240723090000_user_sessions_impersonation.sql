@@ -0,0 +1,23 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Browser sessions minted by an admin to impersonate a user. Those sessions
+-- record who started them, and are finished once they expire.
+ALTER TABLE user_sessions
+  ADD COLUMN impersonated_by UUID REFERENCES users (user_id),
+  ADD COLUMN expires_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX user_sessions_expires_at_idx
+  ON user_sessions (expires_at)
+  WHERE expires_at IS NOT NULL AND finished_at IS NULL;
@@ -0,0 +1,46 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Remembers the requests made by clients with an `Idempotency-Key` header, so
+-- that retrying them replays the original response instead of running them
+-- again.
+CREATE TABLE "idempotency_keys" (
+  "idempotency_key_id" UUID NOT NULL
+    CONSTRAINT "idempotency_keys_pkey"
+    PRIMARY KEY,
+
+  -- The client which sent the key
+  "oauth2_client_id" UUID NOT NULL
+    CONSTRAINT "idempotency_keys_oauth2_client_id_fkey"
+    REFERENCES "oauth2_clients" ("oauth2_client_id")
+    ON DELETE CASCADE,
+
+  -- The key itself, as sent by the client
+  "key" TEXT NOT NULL,
+
+  -- A hash of the request, to detect the key being reused for another request
+  "request_hash" TEXT NOT NULL,
+
+  -- The serialized response, set once the request completed
+  "response" TEXT,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  CONSTRAINT "idempotency_keys_oauth2_client_id_key_unique"
+    UNIQUE ("oauth2_client_id", "key")
+);
+
+-- Used to clean up the old keys
+CREATE INDEX "idempotency_keys_created_at_idx"
+  ON "idempotency_keys" ("created_at");
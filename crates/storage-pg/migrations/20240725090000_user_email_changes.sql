@@ -0,0 +1,47 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Stores the changes of the primary email address of users. The addresses are
+-- stored as text rather than references to `user_emails`, so that the change
+-- can still be undone if one of them gets removed in the meantime.
+CREATE TABLE "user_email_changes" (
+  "user_email_change_id" UUID NOT NULL
+    CONSTRAINT "user_email_changes_pkey"
+    PRIMARY KEY,
+
+  -- The user changing their email address
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The primary email address before the change
+  "old_email" TEXT NOT NULL,
+
+  -- The new email address
+  "new_email" TEXT NOT NULL,
+
+  -- The ticket sent to the previous address to undo the change
+  "undo_ticket" TEXT NOT NULL
+    CONSTRAINT "user_email_changes_undo_ticket_key"
+    UNIQUE,
+
+  -- When the change was started
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the new address was verified and set as primary
+  "confirmed_at" TIMESTAMP WITH TIME ZONE,
+
+  -- When the change was undone from the link sent to the previous address
+  "undone_at" TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,54 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Phone numbers (MSISDN) of users, verified by sending them a code by SMS
+CREATE TABLE "user_phones" (
+  "user_phone_id" UUID NOT NULL
+    CONSTRAINT "user_phones_pkey"
+    PRIMARY KEY,
+
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The phone number, in the E.164 format
+  "phone_number" TEXT NOT NULL,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the phone number was verified
+  "confirmed_at" TIMESTAMP WITH TIME ZONE,
+
+  CONSTRAINT "user_phones_user_id_phone_number_key"
+    UNIQUE ("user_id", "phone_number")
+);
+
+CREATE TABLE "user_phone_confirmation_codes" (
+  "user_phone_confirmation_code_id" UUID NOT NULL
+    CONSTRAINT "user_phone_confirmation_codes_pkey"
+    PRIMARY KEY,
+
+  "user_phone_id" UUID NOT NULL
+    REFERENCES "user_phones" ("user_phone_id")
+    ON DELETE CASCADE,
+
+  "code" TEXT NOT NULL,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "consumed_at" TIMESTAMP WITH TIME ZONE,
+
+  CONSTRAINT "user_phone_confirmation_codes_user_phone_id_code_key"
+    UNIQUE ("user_phone_id", "code")
+);
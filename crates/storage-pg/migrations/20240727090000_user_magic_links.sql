@@ -0,0 +1,72 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Stores the requests to log in without a password, by receiving a link by email
+CREATE TABLE "user_magic_link_sessions" (
+  "user_magic_link_session_id" UUID NOT NULL
+    CONSTRAINT "user_magic_link_sessions_pkey"
+    PRIMARY KEY,
+
+  -- The email address for which the login link was requested
+  "email" TEXT NOT NULL,
+
+  -- The user agent of the client that requested the login link
+  "user_agent" TEXT NOT NULL,
+
+  -- The IP address of the client that requested the login link
+  "ip_address" INET,
+
+  -- The language of the client that requested the login link
+  "locale" TEXT NOT NULL,
+
+  -- When the session was created
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When one of the links of the session was used
+  "consumed_at" TIMESTAMP WITH TIME ZONE
+);
+
+-- Stores the login links sent for a magic link session, one per matching user
+CREATE TABLE "user_magic_links" (
+  "user_magic_link_id" UUID NOT NULL
+    CONSTRAINT "user_magic_links_pkey"
+    PRIMARY KEY,
+
+  -- The session this link belongs to
+  "user_magic_link_session_id" UUID NOT NULL
+    REFERENCES "user_magic_link_sessions" ("user_magic_link_session_id")
+    ON DELETE CASCADE,
+
+  -- The user_email to which the link was sent
+  "user_email_id" UUID NOT NULL
+    REFERENCES "user_emails" ("user_email_id")
+    ON DELETE CASCADE,
+
+  -- The secret ticket embedded in the link
+  "ticket" TEXT NOT NULL
+    CONSTRAINT "user_magic_links_ticket_unique"
+    UNIQUE,
+
+  -- When the link was created
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the link expires
+  "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Record browser session authentications done through a login link
+ALTER TABLE "user_session_authentications"
+    ADD COLUMN "user_magic_link_id" UUID
+        REFERENCES "user_magic_links" ("user_magic_link_id")
+        ON DELETE SET NULL;
@@ -0,0 +1,33 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Whether the tokens obtained from the upstream provider should be kept
+ALTER TABLE "upstream_oauth_providers"
+  ADD COLUMN "store_tokens" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The latest tokens obtained from the upstream provider for a link, encrypted
+-- with the application secret
+CREATE TABLE "upstream_oauth_link_tokens" (
+  "upstream_oauth_link_id" UUID NOT NULL
+    CONSTRAINT "upstream_oauth_link_tokens_pkey"
+    PRIMARY KEY
+    CONSTRAINT "upstream_oauth_link_tokens_link_fkey"
+    REFERENCES "upstream_oauth_links" ("upstream_oauth_link_id"),
+
+  "encrypted_access_token" TEXT NOT NULL,
+  "encrypted_refresh_token" TEXT,
+  "access_token_expires_at" TIMESTAMP WITH TIME ZONE,
+
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,24 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+
+-- Subject alternative names which identify the certificate of clients using the
+-- `tls_client_auth` authentication method
+ALTER TABLE oauth2_clients
+  ADD COLUMN tls_client_auth_san_dns TEXT,
+  ADD COLUMN tls_client_auth_san_email TEXT;
+
+-- The SHA-256 thumbprint of the client certificate access tokens are bound to
+ALTER TABLE oauth2_access_tokens
+  ADD COLUMN certificate_thumbprint TEXT;
@@ -0,0 +1,90 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Adds a table to store client-initiated backchannel authentication requests
+--
+-- This has 4 possible states, only going in one direction:
+--
+--     [[ Pending ]]
+--       |       |
+--       |  [ Rejected ] -- The `rejected_at` and `user_session_id` fields are set
+--       |
+-- [ Fulfilled ] -- The `fulfilled_at` and `user_session_id` fields are set
+--       |
+-- [ Exchanged ] -- The `exchanged_at` and `oauth2_session_id` fields are also set
+--
+CREATE TABLE "oauth2_backchannel_authentication_grants" (
+    "oauth2_backchannel_authentication_grant_id" UUID NOT NULL
+        PRIMARY KEY,
+
+    -- The client who initiated the authentication request
+    "oauth2_client_id" UUID NOT NULL
+        REFERENCES "oauth2_clients" ("oauth2_client_id")
+        ON DELETE CASCADE,
+
+    -- The user who is asked to approve the request
+    "user_id" UUID NOT NULL
+        REFERENCES "users" ("user_id")
+        ON DELETE CASCADE,
+
+    -- The scope requested
+    "scope" TEXT NOT NULL,
+
+    -- The random identifier that the client uses to poll for the access token
+    "auth_req_id" TEXT NOT NULL
+        UNIQUE,
+
+    -- The message displayed to the user, if the client provided one
+    "binding_message" TEXT,
+
+    -- Timestamp when the request was created
+    "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- Timestamp when the request expires
+    "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- When the request was fulfilled, i.e. the user has granted access
+    -- This is mutually exclusive with rejected_at
+    "fulfilled_at" TIMESTAMP WITH TIME ZONE,
+
+    -- When the request was rejected, i.e. the user has denied access
+    -- This is mutually exclusive with fulfilled_at
+    "rejected_at" TIMESTAMP WITH TIME ZONE,
+
+    -- When the request was exchanged
+    -- This means "fulfilled_at" has also been set
+    "exchanged_at" TIMESTAMP WITH TIME ZONE,
+
+    -- The OAuth 2.0 session generated for this request
+    -- This means "exchanged_at" has also been set
+    "oauth2_session_id" UUID
+        REFERENCES "oauth2_sessions" ("oauth2_session_id")
+        ON DELETE CASCADE,
+
+    -- The browser session ID that the user used to approve or reject
+    -- This means "fulfilled_at" or "rejected_at" has also been set
+    "user_session_id" UUID
+        REFERENCES "user_sessions" ("user_session_id"),
+
+    -- The IP address of the client when it made the request
+    "ip_address" INET,
+
+    -- The user agent of the client when it made the request
+    "user_agent" TEXT
+);
+
+-- Add a flag on oauth2_clients to indicate whether they can use the CIBA grant
+ALTER TABLE oauth2_clients
+    ADD COLUMN grant_type_ciba BOOLEAN
+        NOT NULL DEFAULT FALSE;
@@ -0,0 +1,37 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Stores the exports of the data of users, generated in the background
+CREATE TABLE "user_exports" (
+  "user_export_id" UUID NOT NULL
+    CONSTRAINT "user_exports_pkey"
+    PRIMARY KEY,
+
+  -- The user whose data is exported
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- When the export was requested
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the export finished generating
+  "completed_at" TIMESTAMP WITH TIME ZONE,
+
+  -- The exported data, once generated
+  "data" JSONB
+);
+
+CREATE INDEX "user_exports_user_id_idx"
+  ON "user_exports" ("user_id");
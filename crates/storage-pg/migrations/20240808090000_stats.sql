@@ -0,0 +1,65 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Aggregates used by the statistics API. Those are materialized views, which
+-- are refreshed periodically by a background job: they lag behind the actual
+-- data, but are cheap to query. Days are in UTC.
+
+-- Number of authentications, per day
+CREATE MATERIALIZED VIEW "stats_daily_authentications" AS
+  SELECT ("created_at" AT TIME ZONE 'UTC')::date AS "day"
+       , COUNT(*) AS "count"
+  FROM "user_session_authentications"
+  GROUP BY "day";
+
+-- Unique indexes are required to refresh the views concurrently
+CREATE UNIQUE INDEX "stats_daily_authentications_day_idx"
+  ON "stats_daily_authentications" ("day");
+
+-- Number of users registered, per day
+CREATE MATERIALIZED VIEW "stats_daily_registrations" AS
+  SELECT ("created_at" AT TIME ZONE 'UTC')::date AS "day"
+       , COUNT(*) AS "count"
+  FROM "users"
+  GROUP BY "day";
+
+CREATE UNIQUE INDEX "stats_daily_registrations_day_idx"
+  ON "stats_daily_registrations" ("day");
+
+-- Number of access tokens issued, per client and per day.
+--
+-- Expired access tokens are regularly deleted, so this is derived from the
+-- sessions, which get a first token when they start, and from the refresh
+-- tokens, each use of which issues a new access token.
+CREATE MATERIALIZED VIEW "stats_daily_oauth2_tokens" AS
+  SELECT "day"
+       , "oauth2_client_id"
+       , COUNT(*) AS "count"
+  FROM (
+    SELECT ("created_at" AT TIME ZONE 'UTC')::date AS "day"
+         , "oauth2_client_id"
+    FROM "oauth2_sessions"
+
+    UNION ALL
+
+    SELECT (rt."consumed_at" AT TIME ZONE 'UTC')::date AS "day"
+         , os."oauth2_client_id"
+    FROM "oauth2_refresh_tokens" rt
+    INNER JOIN "oauth2_sessions" os USING ("oauth2_session_id")
+    WHERE rt."consumed_at" IS NOT NULL
+  ) AS "issued"
+  GROUP BY "day", "oauth2_client_id";
+
+CREATE UNIQUE INDEX "stats_daily_oauth2_tokens_day_client_idx"
+  ON "stats_daily_oauth2_tokens" ("day", "oauth2_client_id");
@@ -0,0 +1,39 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Temporary bans of networks, either put in place automatically after too
+-- many failed authentication attempts, or by an administrator
+CREATE TABLE "network_bans" (
+  "network_ban_id" UUID NOT NULL
+    CONSTRAINT "network_bans_pkey"
+    PRIMARY KEY,
+
+  -- The banned network, with its prefix length
+  "network" INET NOT NULL,
+
+  -- Why an administrator banned the network, NULL for automatic bans
+  "reason" TEXT,
+
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the ban ends
+  "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When an administrator lifted the ban before it expired
+  "lifted_at" TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX "network_bans_expires_at_idx"
+  ON "network_bans" ("expires_at")
+  WHERE "lifted_at" IS NULL;
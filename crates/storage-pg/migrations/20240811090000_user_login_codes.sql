@@ -0,0 +1,64 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Codes sent by email to confirm logins from an unknown device or network.
+-- The browser session is only started once the right code is entered.
+CREATE TABLE "user_login_codes" (
+  "user_login_code_id" UUID NOT NULL
+    CONSTRAINT "user_login_codes_pkey"
+    PRIMARY KEY,
+
+  -- The user who is logging in
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The email address the code is sent to
+  "user_email_id" UUID NOT NULL
+    REFERENCES "user_emails" ("user_email_id")
+    ON DELETE CASCADE,
+
+  -- The password used to log in, which authenticates the session once the
+  -- code is entered
+  "user_password_id" UUID NOT NULL
+    REFERENCES "user_passwords" ("user_password_id")
+    ON DELETE CASCADE,
+
+  -- The user agent of the browser used to log in
+  "user_agent" TEXT,
+
+  -- The IP address the login was made from
+  "ip_address" INET,
+
+  -- The code sent by email
+  "code" TEXT NOT NULL,
+
+  -- How many wrong codes were entered
+  "attempts" INTEGER NOT NULL DEFAULT 0,
+
+  -- How many times the code was sent
+  "sent_count" INTEGER NOT NULL DEFAULT 1,
+
+  -- When the login was attempted
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the code was last sent
+  "last_sent_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the code expires
+  "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+  -- When the right code was entered
+  "consumed_at" TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,47 @@
+-- Copyright 2024 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+
+-- Risk assessments of password logins, kept to audit the decisions taken
+CREATE TABLE "user_login_risk_assessments" (
+  "user_login_risk_assessment_id" UUID NOT NULL
+    CONSTRAINT "user_login_risk_assessments_pkey"
+    PRIMARY KEY,
+
+  -- The user who is logging in
+  "user_id" UUID NOT NULL
+    REFERENCES "users" ("user_id")
+    ON DELETE CASCADE,
+
+  -- The user agent of the browser used to log in
+  "user_agent" TEXT,
+
+  -- The IP address the login was made from
+  "ip_address" INET,
+
+  -- The sum of the weights of the signals
+  "score" INTEGER NOT NULL,
+
+  -- The signals which matched the login
+  "signals" TEXT[] NOT NULL,
+
+  -- The decision taken: 'allow', 'require_mfa' or 'deny'
+  "decision" TEXT NOT NULL,
+
+  -- When the login was assessed
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX "user_login_risk_assessments_user_id_idx"
+  ON "user_login_risk_assessments" ("user_id");
@@ -15,7 +15,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     BrowserSession, CompatSession, CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device,
     User, UserAgent,
@@ -25,7 +25,7 @@ use mas_storage::{
     Clock, Page, Pagination,
 };
 use rand::RngCore;
-use sea_query::{enum_def, Expr, PostgresQueryBuilder, Query};
+use sea_query::{enum_def, Expr, Func, PostgresQueryBuilder, Query};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -33,7 +33,7 @@ use url::Url;
 use uuid::Uuid;
 
 use crate::{
-    iden::{CompatSessions, CompatSsoLogins},
+    iden::{CompatSessions, CompatSsoLogins, Users},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
     DatabaseError, DatabaseInconsistencyError,
@@ -446,6 +446,27 @@ impl<'c> CompatSessionRepository for PgCompatSessionRepository<'c> {
             .and_where_option(filter.device().map(|device| {
                 Expr::col((CompatSessions::Table, CompatSessions::DeviceId)).eq(device.as_str())
             }))
+            .and_where_option(filter.last_active_before().map(|last_active_before| {
+                Expr::expr(Func::coalesce([
+                    Expr::col((CompatSessions::Table, CompatSessions::LastActiveAt)).into(),
+                    Expr::col((CompatSessions::Table, CompatSessions::CreatedAt)).into(),
+                ]))
+                .lt(last_active_before)
+            }))
+            .and_where_option(filter.excludes_bots().then(|| {
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(Users::Table)
+                        .and_where(
+                            Expr::col((Users::Table, Users::UserId))
+                                .equals((CompatSessions::Table, CompatSessions::UserId)),
+                        )
+                        .and_where(Expr::col((Users::Table, Users::IsBot)).eq(true))
+                        .take(),
+                )
+                .not()
+            }))
             .generate_pagination(
                 (CompatSessions::Table, CompatSessions::CompatSessionId),
                 pagination,
@@ -504,6 +525,27 @@ impl<'c> CompatSessionRepository for PgCompatSessionRepository<'c> {
                     exists.not()
                 }
             }))
+            .and_where_option(filter.last_active_before().map(|last_active_before| {
+                Expr::expr(Func::coalesce([
+                    Expr::col((CompatSessions::Table, CompatSessions::LastActiveAt)).into(),
+                    Expr::col((CompatSessions::Table, CompatSessions::CreatedAt)).into(),
+                ]))
+                .lt(last_active_before)
+            }))
+            .and_where_option(filter.excludes_bots().then(|| {
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(Users::Table)
+                        .and_where(
+                            Expr::col((Users::Table, Users::UserId))
+                                .equals((CompatSessions::Table, CompatSessions::UserId)),
+                        )
+                        .and_where(Expr::col((Users::Table, Users::IsBot)).eq(true))
+                        .take(),
+                )
+                .not()
+            }))
             .build_sqlx(PostgresQueryBuilder);
 
         let count: i64 = sqlx::query_scalar_with(&sql, arguments)
@@ -516,6 +558,66 @@ impl<'c> CompatSessionRepository for PgCompatSessionRepository<'c> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.compat_session.forget_last_active_ips",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE compat_sessions
+                SET last_active_ip = NULL
+                WHERE last_active_ip IS NOT NULL
+                  AND last_active_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.compat_session.forget_user_agents",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE compat_sessions
+                SET user_agent = NULL
+                WHERE user_agent IS NOT NULL
+                  AND COALESCE(last_active_at, created_at) < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.compat_session.record_batch_activity",
         skip_all,
@@ -0,0 +1,267 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module containing the PostgreSQL implementation of the repositories
+//! related to outgoing emails
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::EmailDeadLetter;
+use mas_storage::{email::EmailDeadLetterRepository, Clock, Page, Pagination};
+use rand::RngCore;
+use sea_query::{enum_def, Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    iden::EmailDeadLetters, pagination::QueryBuilderExt, tracing::ExecuteExt, DatabaseError,
+    DatabaseInconsistencyError,
+};
+
+/// An implementation of [`EmailDeadLetterRepository`] for a PostgreSQL
+/// connection
+pub struct PgEmailDeadLetterRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgEmailDeadLetterRepository<'c> {
+    /// Create a new [`PgEmailDeadLetterRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[enum_def]
+struct EmailDeadLetterLookup {
+    email_dead_letter_id: Uuid,
+    recipients: Vec<String>,
+    subject: String,
+    attempts: i32,
+    last_error: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<EmailDeadLetterLookup> for EmailDeadLetter {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: EmailDeadLetterLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.email_dead_letter_id);
+        let attempts = value.attempts.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("email_dead_letters")
+                .column("attempts")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(EmailDeadLetter {
+            id,
+            recipients: value.recipients,
+            subject: value.subject,
+            attempts,
+            last_error: value.last_error,
+            created_at: value.created_at,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> EmailDeadLetterRepository for PgEmailDeadLetterRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.email_dead_letter.add",
+        skip_all,
+        fields(
+            db.statement,
+            email_dead_letter.id,
+            email_dead_letter.attempts = attempts,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        recipients: Vec<String>,
+        subject: String,
+        attempts: u32,
+        last_error: String,
+    ) -> Result<EmailDeadLetter, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("email_dead_letter.id", tracing::field::display(id));
+
+        let db_attempts = i32::try_from(attempts).map_err(DatabaseError::to_invalid_operation)?;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO email_dead_letters (
+                      email_dead_letter_id
+                    , recipients
+                    , subject
+                    , attempts
+                    , last_error
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            &recipients,
+            &subject,
+            db_attempts,
+            &last_error,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(EmailDeadLetter {
+            id,
+            recipients,
+            subject,
+            attempts,
+            last_error,
+            created_at,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.email_dead_letter.list",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<EmailDeadLetter>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::EmailDeadLetterId)),
+                EmailDeadLetterLookupIden::EmailDeadLetterId,
+            )
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::Recipients)),
+                EmailDeadLetterLookupIden::Recipients,
+            )
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::Subject)),
+                EmailDeadLetterLookupIden::Subject,
+            )
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::Attempts)),
+                EmailDeadLetterLookupIden::Attempts,
+            )
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::LastError)),
+                EmailDeadLetterLookupIden::LastError,
+            )
+            .expr_as(
+                Expr::col((EmailDeadLetters::Table, EmailDeadLetters::CreatedAt)),
+                EmailDeadLetterLookupIden::CreatedAt,
+            )
+            .from(EmailDeadLetters::Table)
+            .generate_pagination(
+                (EmailDeadLetters::Table, EmailDeadLetters::EmailDeadLetterId),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<EmailDeadLetterLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination
+            .process(edges)
+            .try_map(EmailDeadLetter::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.email_dead_letter.count",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn count(&mut self) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((EmailDeadLetters::Table, EmailDeadLetters::EmailDeadLetterId)).count())
+            .from(EmailDeadLetters::Table)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{clock::MockClock, Pagination, RepositoryAccess};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_email_dead_letter_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        assert_eq!(repo.email_dead_letter().count().await.unwrap(), 0);
+        let list = repo
+            .email_dead_letter()
+            .list(Pagination::first(10))
+            .await
+            .unwrap();
+        assert!(list.edges.is_empty());
+
+        let dead_letter = repo
+            .email_dead_letter()
+            .add(
+                &mut rng,
+                &clock,
+                vec!["alice@example.com".to_owned()],
+                "Verify your email".to_owned(),
+                8,
+                "connection refused".to_owned(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(repo.email_dead_letter().count().await.unwrap(), 1);
+        let list = repo
+            .email_dead_letter()
+            .list(Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(list.edges, vec![dead_letter]);
+        assert!(!list.has_next_page);
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module containing the PostgreSQL implementation of the
+//! [`IdempotencyKeyRepository`]
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{Client, IdempotencyKey};
+use mas_storage::{idempotency::IdempotencyKeyRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`IdempotencyKeyRepository`] for a PostgreSQL
+/// connection
+pub struct PgIdempotencyKeyRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgIdempotencyKeyRepository<'c> {
+    /// Create a new [`PgIdempotencyKeyRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct IdempotencyKeyRow {
+    idempotency_key_id: Uuid,
+    oauth2_client_id: Uuid,
+    key: String,
+    request_hash: String,
+    response: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<IdempotencyKeyRow> for IdempotencyKey {
+    fn from(row: IdempotencyKeyRow) -> Self {
+        IdempotencyKey {
+            id: row.idempotency_key_id.into(),
+            oauth2_client_id: row.oauth2_client_id.into(),
+            key: row.key,
+            request_hash: row.request_hash,
+            response: row.response,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> IdempotencyKeyRepository for PgIdempotencyKeyRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.idempotency_key.find",
+        skip_all,
+        fields(
+            db.statement,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn find(
+        &mut self,
+        client: &Client,
+        key: &str,
+    ) -> Result<Option<IdempotencyKey>, Self::Error> {
+        let row = sqlx::query_as!(
+            IdempotencyKeyRow,
+            r#"
+                SELECT
+                      idempotency_key_id
+                    , oauth2_client_id
+                    , key
+                    , request_hash
+                    , response
+                    , created_at
+                FROM idempotency_keys
+                WHERE oauth2_client_id = $1 AND key = $2
+            "#,
+            Uuid::from(client.id),
+            key,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.idempotency_key.add",
+        skip_all,
+        fields(
+            db.statement,
+            idempotency_key.id,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        key: String,
+        request_hash: String,
+    ) -> Result<Option<IdempotencyKey>, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("idempotency_key.id", tracing::field::display(id));
+
+        let res = sqlx::query!(
+            r#"
+                INSERT INTO idempotency_keys (
+                      idempotency_key_id
+                    , oauth2_client_id
+                    , key
+                    , request_hash
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (oauth2_client_id, key) DO NOTHING
+            "#,
+            Uuid::from(id),
+            Uuid::from(client.id),
+            &key,
+            &request_hash,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(IdempotencyKey {
+            id,
+            oauth2_client_id: client.id,
+            key,
+            request_hash,
+            response: None,
+            created_at,
+        }))
+    }
+
+    #[tracing::instrument(
+        name = "db.idempotency_key.complete",
+        skip_all,
+        fields(
+            db.statement,
+            %idempotency_key.id,
+        ),
+        err,
+    )]
+    async fn complete(
+        &mut self,
+        mut idempotency_key: IdempotencyKey,
+        response: String,
+    ) -> Result<IdempotencyKey, Self::Error> {
+        // This should have been checked by the caller
+        if idempotency_key.is_completed() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE idempotency_keys
+                SET response = $1
+                WHERE idempotency_key_id = $2
+            "#,
+            &response,
+            Uuid::from(idempotency_key.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        idempotency_key.response = Some(response);
+        Ok(idempotency_key)
+    }
+
+    #[tracing::instrument(
+        name = "db.idempotency_key.remove",
+        skip_all,
+        fields(
+            db.statement,
+            %idempotency_key.id,
+        ),
+        err,
+    )]
+    async fn remove(&mut self, idempotency_key: IdempotencyKey) -> Result<(), Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM idempotency_keys
+                WHERE idempotency_key_id = $1
+            "#,
+            Uuid::from(idempotency_key.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "db.idempotency_key.cleanup_expired",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn cleanup_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error> {
+        // Clients are expected to give up retrying after a day
+        let threshold = clock.now() - Duration::microseconds(24 * 60 * 60 * 1000 * 1000);
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM idempotency_keys
+                WHERE created_at < $1
+            "#,
+            threshold,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use mas_storage::{clock::MockClock, RepositoryAccess};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_idempotency_key_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec![],
+                None,
+                None,
+                vec![],
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(repo
+            .idempotency_key()
+            .find(&client, "key")
+            .await
+            .unwrap()
+            .is_none());
+
+        let key = repo
+            .idempotency_key()
+            .add(
+                &mut rng,
+                &clock,
+                &client,
+                "key".to_owned(),
+                "hash".to_owned(),
+            )
+            .await
+            .unwrap()
+            .expect("the key should be reserved");
+        assert!(!key.is_completed());
+
+        // The same key can't be reserved twice
+        let again = repo
+            .idempotency_key()
+            .add(
+                &mut rng,
+                &clock,
+                &client,
+                "key".to_owned(),
+                "hash".to_owned(),
+            )
+            .await
+            .unwrap();
+        assert!(again.is_none());
+
+        let key = repo
+            .idempotency_key()
+            .complete(key, "{}".to_owned())
+            .await
+            .unwrap();
+        let found = repo
+            .idempotency_key()
+            .find(&client, "key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found, key);
+        assert_eq!(found.response.as_deref(), Some("{}"));
+
+        // A released key can be reserved again
+        repo.idempotency_key().remove(found).await.unwrap();
+        let key = repo
+            .idempotency_key()
+            .add(
+                &mut rng,
+                &clock,
+                &client,
+                "key".to_owned(),
+                "hash".to_owned(),
+            )
+            .await
+            .unwrap()
+            .expect("the key should be reserved");
+
+        // Keys are only cleaned up after a day
+        assert_eq!(
+            repo.idempotency_key()
+                .cleanup_expired(&clock)
+                .await
+                .unwrap(),
+            0
+        );
+        clock.advance(Duration::microseconds(25 * 60 * 60 * 1000 * 1000));
+        assert_eq!(
+            repo.idempotency_key()
+                .cleanup_expired(&clock)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(repo
+            .idempotency_key()
+            .find(&client, &key.key)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
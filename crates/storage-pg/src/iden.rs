@@ -24,6 +24,8 @@ pub enum UserSessions {
     UserAgent,
     LastActiveAt,
     LastActiveIp,
+    ImpersonatedBy,
+    ExpiresAt,
 }
 
 #[derive(sea_query::Iden)]
@@ -34,7 +36,13 @@ pub enum Users {
     PrimaryUserEmailId,
     CreatedAt,
     LockedAt,
+    ShadowBannedAt,
     CanRequestAdmin,
+    IsBot,
+    IsGuest,
+    DisplayName,
+    AvatarUrl,
+    Groups,
 }
 
 #[derive(sea_query::Iden)]
@@ -115,6 +123,8 @@ pub enum UpstreamOAuthProviders {
     JwksUriOverride,
     TokenEndpointOverride,
     AuthorizationEndpointOverride,
+    StoreTokens,
+    OnDeprovision,
 }
 
 #[derive(sea_query::Iden)]
@@ -129,3 +139,14 @@ pub enum UpstreamOAuthLinks {
     Subject,
     CreatedAt,
 }
+
+#[derive(sea_query::Iden)]
+pub enum EmailDeadLetters {
+    Table,
+    EmailDeadLetterId,
+    Recipients,
+    Subject,
+    Attempts,
+    LastError,
+    CreatedAt,
+}
@@ -171,8 +171,12 @@ use sqlx::migrate::Migrator;
 
 pub mod app_session;
 pub mod compat;
+pub mod email;
+pub mod idempotency;
 pub mod job;
+pub mod network_ban;
 pub mod oauth2;
+pub mod stats;
 pub mod upstream_oauth2;
 pub mod user;
 
@@ -0,0 +1,304 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module containing the PostgreSQL implementation of the
+//! [`NetworkBanRepository`]
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Network, NetworkBan};
+use mas_storage::{network_ban::NetworkBanRepository, Clock};
+use rand::RngCore;
+use sqlx::{types::ipnetwork::IpNetwork, PgConnection};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+
+/// An implementation of [`NetworkBanRepository`] for a PostgreSQL connection
+pub struct PgNetworkBanRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgNetworkBanRepository<'c> {
+    /// Create a new [`PgNetworkBanRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct NetworkBanLookup {
+    network_ban_id: Uuid,
+    network: IpNetwork,
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    lifted_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<NetworkBanLookup> for NetworkBan {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: NetworkBanLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.network_ban_id);
+        let network = Network::new(value.network.ip(), value.network.prefix()).map_err(|e| {
+            DatabaseInconsistencyError::on("network_bans")
+                .column("network")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(NetworkBan {
+            id,
+            network,
+            reason: value.reason,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            lifted_at: value.lifted_at,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> NetworkBanRepository for PgNetworkBanRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.network_ban.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            network_ban.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<NetworkBan>, Self::Error> {
+        let res = sqlx::query_as!(
+            NetworkBanLookup,
+            r#"
+                SELECT network_ban_id
+                     , network
+                     , reason
+                     , created_at
+                     , expires_at
+                     , lifted_at
+                FROM network_bans
+                WHERE network_ban_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.network_ban.add",
+        skip_all,
+        fields(
+            db.statement,
+            network_ban.id,
+            network_ban.network = %network,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        network: Network,
+        reason: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<NetworkBan, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("network_ban.id", tracing::field::display(id));
+
+        let ip_network = IpNetwork::new(network.address(), network.prefix_len())
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO network_bans
+                    (network_ban_id, network, reason, created_at, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            ip_network,
+            reason.as_deref(),
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(NetworkBan {
+            id,
+            network,
+            reason,
+            created_at,
+            expires_at,
+            lifted_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.network_ban.lift",
+        skip_all,
+        fields(
+            db.statement,
+            %network_ban.id,
+        ),
+        err,
+    )]
+    async fn lift(
+        &mut self,
+        clock: &dyn Clock,
+        mut network_ban: NetworkBan,
+    ) -> Result<NetworkBan, Self::Error> {
+        let lifted_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE network_bans
+                SET lifted_at = $2
+                WHERE network_ban_id = $1
+            "#,
+            Uuid::from(network_ban.id),
+            lifted_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        network_ban.lifted_at = Some(lifted_at);
+        Ok(network_ban)
+    }
+
+    #[tracing::instrument(
+        name = "db.network_ban.all_active",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn all_active(&mut self, clock: &dyn Clock) -> Result<Vec<NetworkBan>, Self::Error> {
+        let res = sqlx::query_as!(
+            NetworkBanLookup,
+            r#"
+                SELECT network_ban_id
+                     , network
+                     , reason
+                     , created_at
+                     , expires_at
+                     , lifted_at
+                FROM network_bans
+                WHERE lifted_at IS NULL
+                  AND expires_at > $1
+                ORDER BY network_ban_id DESC
+            "#,
+            clock.now(),
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        let res: Result<Vec<_>, _> = res.into_iter().map(TryInto::try_into).collect();
+        Ok(res?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use mas_storage::{clock::MockClock, Clock, RepositoryAccess};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_network_ban_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        assert!(repo
+            .network_ban()
+            .all_active(&clock)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let automatic = repo
+            .network_ban()
+            .add(
+                &mut rng,
+                &clock,
+                "192.0.2.0/24".parse().unwrap(),
+                None,
+                clock.now() + Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert!(automatic.is_automatic());
+
+        clock.advance(Duration::minutes(1));
+        let manual = repo
+            .network_ban()
+            .add(
+                &mut rng,
+                &clock,
+                "2001:db8::/64".parse().unwrap(),
+                Some("spam".to_owned()),
+                clock.now() + Duration::days(1),
+            )
+            .await
+            .unwrap();
+        assert!(!manual.is_automatic());
+
+        // The network round-trips through the database
+        let lookup = repo.network_ban().lookup(manual.id).await.unwrap().unwrap();
+        assert_eq!(lookup, manual);
+
+        // The most recent ban comes first
+        let active = repo.network_ban().all_active(&clock).await.unwrap();
+        assert_eq!(active, vec![manual.clone(), automatic.clone()]);
+
+        // Lifted bans are not active anymore
+        let manual = repo.network_ban().lift(&clock, manual).await.unwrap();
+        assert!(!manual.is_active(clock.now()));
+        let active = repo.network_ban().all_active(&clock).await.unwrap();
+        assert_eq!(active, vec![automatic.clone()]);
+
+        // Nor are expired bans
+        clock.advance(Duration::hours(1));
+        assert!(repo
+            .network_ban()
+            .all_active(&clock)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
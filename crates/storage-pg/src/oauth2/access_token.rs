@@ -44,6 +44,7 @@ struct OAuth2AccessTokenLookup {
     created_at: DateTime<Utc>,
     expires_at: Option<DateTime<Utc>>,
     revoked_at: Option<DateTime<Utc>>,
+    certificate_thumbprint: Option<String>,
 }
 
 impl From<OAuth2AccessTokenLookup> for AccessToken {
@@ -60,6 +61,7 @@ impl From<OAuth2AccessTokenLookup> for AccessToken {
             access_token: value.access_token,
             created_at: value.created_at,
             expires_at: value.expires_at,
+            certificate_thumbprint: value.certificate_thumbprint,
         }
     }
 }
@@ -78,6 +80,7 @@ impl<'c> OAuth2AccessTokenRepository for PgOAuth2AccessTokenRepository<'c> {
                      , expires_at
                      , revoked_at
                      , oauth2_session_id
+                     , certificate_thumbprint
 
                 FROM oauth2_access_tokens
 
@@ -114,6 +117,7 @@ impl<'c> OAuth2AccessTokenRepository for PgOAuth2AccessTokenRepository<'c> {
                      , expires_at
                      , revoked_at
                      , oauth2_session_id
+                     , certificate_thumbprint
 
                 FROM oauth2_access_tokens
 
@@ -178,6 +182,7 @@ impl<'c> OAuth2AccessTokenRepository for PgOAuth2AccessTokenRepository<'c> {
             session_id: session.id,
             created_at,
             expires_at,
+            certificate_thumbprint: None,
         })
     }
 
@@ -206,6 +211,40 @@ impl<'c> OAuth2AccessTokenRepository for PgOAuth2AccessTokenRepository<'c> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_access_token.bind_to_certificate",
+        skip_all,
+        fields(
+            db.statement,
+            %access_token.id,
+            %access_token.session_id,
+        ),
+        err,
+    )]
+    async fn bind_to_certificate(
+        &mut self,
+        mut access_token: AccessToken,
+        certificate_thumbprint: String,
+    ) -> Result<AccessToken, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_access_tokens
+                SET certificate_thumbprint = $2
+                WHERE oauth2_access_token_id = $1
+            "#,
+            Uuid::from(access_token.id),
+            &certificate_thumbprint,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        access_token.certificate_thumbprint = Some(certificate_thumbprint);
+        Ok(access_token)
+    }
+
     async fn cleanup_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error> {
         // Cleanup token which expired more than 15 minutes ago
         let threshold = clock.now() - Duration::microseconds(15 * 60 * 1000 * 1000);
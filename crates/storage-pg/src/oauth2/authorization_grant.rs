@@ -17,7 +17,8 @@ use std::num::NonZeroU32;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{
-    AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Client, Pkce, Session,
+    AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, BrowserSession, Client, Pkce,
+    Session, SessionState,
 };
 use mas_iana::oauth::PkceCodeChallengeMethod;
 use mas_storage::{oauth2::OAuth2AuthorizationGrantRepository, Clock};
@@ -443,6 +444,86 @@ impl<'c> OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantReposi
         Ok(grant)
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_authorization_grant.start_session",
+        skip_all,
+        fields(
+            db.statement,
+            %grant.id,
+            client.id = %grant.client_id,
+            %user_session.id,
+            user.id = %user_session.user.id,
+            session.id,
+        ),
+        err,
+    )]
+    async fn start_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        grant: AuthorizationGrant,
+    ) -> Result<(Session, AuthorizationGrant), Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("session.id", tracing::field::display(id));
+
+        let scope_list: Vec<String> = grant.scope.iter().map(|s| s.as_str().to_owned()).collect();
+
+        // The session is inserted and the grant updated in the same statement,
+        // saving a round trip on the authorization endpoint
+        let res = sqlx::query!(
+            r#"
+                WITH oauth2_session AS (
+                    INSERT INTO oauth2_sessions
+                        ( oauth2_session_id
+                        , user_id
+                        , user_session_id
+                        , oauth2_client_id
+                        , scope_list
+                        , created_at
+                        )
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                )
+                UPDATE oauth2_authorization_grants
+                SET fulfilled_at = $6
+                  , oauth2_session_id = $1
+                WHERE oauth2_authorization_grant_id = $7
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_session.user.id),
+            Uuid::from(user_session.id),
+            Uuid::from(grant.client_id),
+            &scope_list,
+            created_at,
+            Uuid::from(grant.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        let session = Session {
+            id,
+            state: SessionState::Valid,
+            created_at,
+            user_id: Some(user_session.user.id),
+            user_session_id: Some(user_session.id),
+            client_id: grant.client_id,
+            scope: grant.scope.clone(),
+            user_agent: None,
+            last_active_at: None,
+            last_active_ip: None,
+        };
+
+        let grant = grant
+            .fulfill(created_at, &session)
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        Ok((session, grant))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_authorization_grant.exchange",
         skip_all,
@@ -511,6 +592,41 @@ impl<'c> OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantReposi
 
         grant.requires_consent = false;
 
+        Ok(grant)
+    }
+    #[tracing::instrument(
+        name = "db.oauth2_authorization_grant.restrict_scope",
+        skip_all,
+        fields(
+            db.statement,
+            %grant.id,
+            client.id = %grant.client_id,
+            grant.scope = %scope,
+        ),
+        err,
+    )]
+    async fn restrict_scope(
+        &mut self,
+        mut grant: AuthorizationGrant,
+        scope: Scope,
+    ) -> Result<AuthorizationGrant, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_authorization_grants
+                SET scope = $2
+                WHERE oauth2_authorization_grant_id = $1
+            "#,
+            Uuid::from(grant.id),
+            scope.to_string(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        grant.scope = scope;
+
         Ok(grant)
     }
 }
@@ -0,0 +1,459 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{
+    BackchannelAuthenticationGrant, BackchannelAuthenticationGrantState, BrowserSession, Session,
+    UserAgent,
+};
+use mas_storage::{
+    oauth2::{
+        OAuth2BackchannelAuthenticationGrantParams, OAuth2BackchannelAuthenticationGrantRepository,
+    },
+    Clock,
+};
+use oauth2_types::scope::Scope;
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{errors::DatabaseInconsistencyError, DatabaseError, ExecuteExt};
+
+/// An implementation of [`OAuth2BackchannelAuthenticationGrantRepository`] for
+/// a PostgreSQL connection
+pub struct PgOAuth2BackchannelAuthenticationGrantRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgOAuth2BackchannelAuthenticationGrantRepository<'c> {
+    /// Create a new [`PgOAuth2BackchannelAuthenticationGrantRepository`] from
+    /// an active PostgreSQL connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct OAuth2BackchannelAuthenticationGrantLookup {
+    oauth2_backchannel_authentication_grant_id: Uuid,
+    oauth2_client_id: Uuid,
+    user_id: Uuid,
+    scope: String,
+    auth_req_id: String,
+    binding_message: Option<String>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    fulfilled_at: Option<DateTime<Utc>>,
+    rejected_at: Option<DateTime<Utc>>,
+    exchanged_at: Option<DateTime<Utc>>,
+    user_session_id: Option<Uuid>,
+    oauth2_session_id: Option<Uuid>,
+    ip_address: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+impl TryFrom<OAuth2BackchannelAuthenticationGrantLookup> for BackchannelAuthenticationGrant {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(
+        OAuth2BackchannelAuthenticationGrantLookup {
+            oauth2_backchannel_authentication_grant_id,
+            oauth2_client_id,
+            user_id,
+            scope,
+            auth_req_id,
+            binding_message,
+            created_at,
+            expires_at,
+            fulfilled_at,
+            rejected_at,
+            exchanged_at,
+            user_session_id,
+            oauth2_session_id,
+            ip_address,
+            user_agent,
+        }: OAuth2BackchannelAuthenticationGrantLookup,
+    ) -> Result<Self, Self::Error> {
+        let id = Ulid::from(oauth2_backchannel_authentication_grant_id);
+        let client_id = Ulid::from(oauth2_client_id);
+
+        let scope: Scope = scope.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("oauth2_backchannel_authentication_grants")
+                .column("scope")
+                .row(id)
+                .source(e)
+        })?;
+
+        let state = match (
+            fulfilled_at,
+            rejected_at,
+            exchanged_at,
+            user_session_id,
+            oauth2_session_id,
+        ) {
+            (None, None, None, None, None) => BackchannelAuthenticationGrantState::Pending,
+
+            (Some(fulfilled_at), None, None, Some(user_session_id), None) => {
+                BackchannelAuthenticationGrantState::Fulfilled {
+                    browser_session_id: Ulid::from(user_session_id),
+                    fulfilled_at,
+                }
+            }
+
+            (None, Some(rejected_at), None, Some(user_session_id), None) => {
+                BackchannelAuthenticationGrantState::Rejected {
+                    browser_session_id: Ulid::from(user_session_id),
+                    rejected_at,
+                }
+            }
+
+            (
+                Some(fulfilled_at),
+                None,
+                Some(exchanged_at),
+                Some(user_session_id),
+                Some(oauth2_session_id),
+            ) => BackchannelAuthenticationGrantState::Exchanged {
+                browser_session_id: Ulid::from(user_session_id),
+                session_id: Ulid::from(oauth2_session_id),
+                fulfilled_at,
+                exchanged_at,
+            },
+
+            _ => {
+                return Err(DatabaseInconsistencyError::on(
+                    "oauth2_backchannel_authentication_grants",
+                )
+                .row(id))
+            }
+        };
+
+        Ok(BackchannelAuthenticationGrant {
+            id,
+            state,
+            client_id,
+            user_id: Ulid::from(user_id),
+            scope,
+            auth_req_id,
+            binding_message,
+            created_at,
+            expires_at,
+            ip_address,
+            user_agent: user_agent.map(UserAgent::parse),
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> OAuth2BackchannelAuthenticationGrantRepository
+    for PgOAuth2BackchannelAuthenticationGrantRepository<'c>
+{
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.add",
+        skip_all,
+        fields(
+            db.statement,
+            oauth2_backchannel_authentication_grant.id,
+            oauth2_backchannel_authentication_grant.scope = %params.scope,
+            oauth2_client.id = %params.client.id,
+            user.id = %params.user.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        params: OAuth2BackchannelAuthenticationGrantParams<'_>,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error> {
+        let now = clock.now();
+        let id = Ulid::from_datetime_with_source(now.into(), rng);
+        tracing::Span::current().record(
+            "oauth2_backchannel_authentication_grant.id",
+            tracing::field::display(id),
+        );
+
+        let created_at = now;
+        let expires_at = now + params.expires_in;
+        let client_id = params.client.id;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO oauth2_backchannel_authentication_grants
+                    ( oauth2_backchannel_authentication_grant_id
+                    , oauth2_client_id
+                    , user_id
+                    , scope
+                    , auth_req_id
+                    , binding_message
+                    , created_at
+                    , expires_at
+                    , ip_address
+                    , user_agent
+                    )
+                VALUES
+                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            Uuid::from(id),
+            Uuid::from(client_id),
+            Uuid::from(params.user.id),
+            params.scope.to_string(),
+            &params.auth_req_id,
+            params.binding_message.as_deref(),
+            created_at,
+            expires_at,
+            params.ip_address as Option<IpAddr>,
+            params.user_agent.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(BackchannelAuthenticationGrant {
+            id,
+            state: BackchannelAuthenticationGrantState::Pending,
+            client_id,
+            user_id: params.user.id,
+            scope: params.scope,
+            auth_req_id: params.auth_req_id,
+            binding_message: params.binding_message,
+            created_at,
+            expires_at,
+            ip_address: params.ip_address,
+            user_agent: params.user_agent,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            oauth2_backchannel_authentication_grant.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(
+        &mut self,
+        id: Ulid,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error> {
+        let res = sqlx::query_as!(
+            OAuth2BackchannelAuthenticationGrantLookup,
+            r#"
+                SELECT oauth2_backchannel_authentication_grant_id
+                     , oauth2_client_id
+                     , user_id
+                     , scope
+                     , auth_req_id
+                     , binding_message
+                     , created_at
+                     , expires_at
+                     , fulfilled_at
+                     , rejected_at
+                     , exchanged_at
+                     , user_session_id
+                     , oauth2_session_id
+                     , ip_address as "ip_address: IpAddr"
+                     , user_agent
+                FROM oauth2_backchannel_authentication_grants
+
+                WHERE oauth2_backchannel_authentication_grant_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.find_by_auth_req_id",
+        skip_all,
+        fields(db.statement),
+        err,
+    )]
+    async fn find_by_auth_req_id(
+        &mut self,
+        auth_req_id: &str,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error> {
+        let res = sqlx::query_as!(
+            OAuth2BackchannelAuthenticationGrantLookup,
+            r#"
+                SELECT oauth2_backchannel_authentication_grant_id
+                     , oauth2_client_id
+                     , user_id
+                     , scope
+                     , auth_req_id
+                     , binding_message
+                     , created_at
+                     , expires_at
+                     , fulfilled_at
+                     , rejected_at
+                     , exchanged_at
+                     , user_session_id
+                     , oauth2_session_id
+                     , ip_address as "ip_address: IpAddr"
+                     , user_agent
+                FROM oauth2_backchannel_authentication_grants
+
+                WHERE auth_req_id = $1
+            "#,
+            auth_req_id,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.fulfill",
+        skip_all,
+        fields(
+            db.statement,
+            oauth2_backchannel_authentication_grant.id = %grant.id,
+            oauth2_client.id = %grant.client_id,
+            browser_session.id = %browser_session.id,
+            user.id = %browser_session.user.id,
+        ),
+        err,
+    )]
+    async fn fulfill(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error> {
+        let fulfilled_at = clock.now();
+        let grant = grant
+            .fulfill(browser_session, fulfilled_at)
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_backchannel_authentication_grants
+                SET fulfilled_at = $1
+                  , user_session_id = $2
+                WHERE oauth2_backchannel_authentication_grant_id = $3
+            "#,
+            fulfilled_at,
+            Uuid::from(browser_session.id),
+            Uuid::from(grant.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(grant)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.reject",
+        skip_all,
+        fields(
+            db.statement,
+            oauth2_backchannel_authentication_grant.id = %grant.id,
+            oauth2_client.id = %grant.client_id,
+            browser_session.id = %browser_session.id,
+            user.id = %browser_session.user.id,
+        ),
+        err,
+    )]
+    async fn reject(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error> {
+        let rejected_at = clock.now();
+        let grant = grant
+            .reject(browser_session, rejected_at)
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_backchannel_authentication_grants
+                SET rejected_at = $1
+                  , user_session_id = $2
+                WHERE oauth2_backchannel_authentication_grant_id = $3
+            "#,
+            rejected_at,
+            Uuid::from(browser_session.id),
+            Uuid::from(grant.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(grant)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_backchannel_authentication_grant.exchange",
+        skip_all,
+        fields(
+            db.statement,
+            oauth2_backchannel_authentication_grant.id = %grant.id,
+            oauth2_client.id = %grant.client_id,
+            oauth2_session.id = %session.id,
+        ),
+        err,
+    )]
+    async fn exchange(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        session: &Session,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error> {
+        let exchanged_at = clock.now();
+        let grant = grant
+            .exchange(session, exchanged_at)
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_backchannel_authentication_grants
+                SET exchanged_at = $1
+                  , oauth2_session_id = $2
+                WHERE oauth2_backchannel_authentication_grant_id = $3
+            "#,
+            exchanged_at,
+            Uuid::from(session.id),
+            Uuid::from(grant.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(grant)
+    }
+}
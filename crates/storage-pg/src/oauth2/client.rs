@@ -54,7 +54,7 @@ impl<'c> PgOAuth2ClientRepository<'c> {
     }
 }
 
-// XXX: response_types & contacts
+// XXX: contacts
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug)]
 struct OAuth2ClientLookup {
@@ -62,11 +62,12 @@ struct OAuth2ClientLookup {
     encrypted_client_secret: Option<String>,
     application_type: Option<String>,
     redirect_uris: Vec<String>,
-    // response_types: Vec<String>,
+    response_types: Option<Vec<String>>,
     grant_type_authorization_code: bool,
     grant_type_refresh_token: bool,
     grant_type_client_credentials: bool,
     grant_type_device_code: bool,
+    grant_type_ciba: bool,
     contacts: Vec<String>,
     client_name: Option<String>,
     logo_uri: Option<String>,
@@ -80,6 +81,12 @@ struct OAuth2ClientLookup {
     token_endpoint_auth_method: Option<String>,
     token_endpoint_auth_signing_alg: Option<String>,
     initiate_login_uri: Option<String>,
+    skip_consent: bool,
+    allowed_scopes: Option<Vec<String>>,
+    default_scope: Option<String>,
+    strip_disallowed_scopes: bool,
+    tls_client_auth_san_dns: Option<String>,
+    tls_client_auth_san_email: Option<String>,
 }
 
 impl TryInto<Client> for OAuth2ClientLookup {
@@ -109,19 +116,24 @@ impl TryInto<Client> for OAuth2ClientLookup {
                     .source(e)
             })?;
 
-        let response_types = vec![
-            OAuthAuthorizationEndpointResponseType::Code,
-            OAuthAuthorizationEndpointResponseType::IdToken,
-            OAuthAuthorizationEndpointResponseType::None,
-        ];
-        /* XXX
-        let response_types: Result<Vec<OAuthAuthorizationEndpointResponseType>, _> =
-            self.response_types.iter().map(|s| s.parse()).collect();
-        let response_types = response_types.map_err(|source| ClientFetchError::ParseField {
-            field: "response_types",
-            source,
-        })?;
-        */
+        // Clients which were not restricted to some response types use the defaults
+        let response_types = match self.response_types {
+            Some(response_types) => response_types
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    DatabaseInconsistencyError::on("oauth2_clients")
+                        .column("response_types")
+                        .row(id)
+                        .source(e)
+                })?,
+            None => vec![
+                OAuthAuthorizationEndpointResponseType::Code,
+                OAuthAuthorizationEndpointResponseType::IdToken,
+                OAuthAuthorizationEndpointResponseType::None,
+            ],
+        };
 
         let mut grant_types = Vec::new();
         if self.grant_type_authorization_code {
@@ -136,6 +148,9 @@ impl TryInto<Client> for OAuth2ClientLookup {
         if self.grant_type_device_code {
             grant_types.push(GrantType::DeviceCode);
         }
+        if self.grant_type_ciba {
+            grant_types.push(GrantType::ClientInitiatedBackchannelAuthentication);
+        }
 
         let logo_uri = self.logo_uri.map(|s| s.parse()).transpose().map_err(|e| {
             DatabaseInconsistencyError::on("oauth2_clients")
@@ -256,6 +271,17 @@ impl TryInto<Client> for OAuth2ClientLookup {
             }
         };
 
+        let default_scope = self
+            .default_scope
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("oauth2_clients")
+                    .column("default_scope")
+                    .row(id)
+                    .source(e)
+            })?;
+
         Ok(Client {
             id,
             client_id: id.to_string(),
@@ -276,6 +302,12 @@ impl TryInto<Client> for OAuth2ClientLookup {
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            skip_consent: self.skip_consent,
+            allowed_scopes: self.allowed_scopes,
+            default_scope,
+            strip_disallowed_scopes: self.strip_disallowed_scopes,
+            tls_client_auth_san_dns: self.tls_client_auth_san_dns,
+            tls_client_auth_san_email: self.tls_client_auth_san_email,
         })
     }
 }
@@ -301,10 +333,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , encrypted_client_secret
                      , application_type
                      , redirect_uris
+                     , response_types
                      , grant_type_authorization_code
                      , grant_type_refresh_token
                      , grant_type_client_credentials
                      , grant_type_device_code
+                     , grant_type_ciba
                      , contacts
                      , client_name
                      , logo_uri
@@ -318,6 +352,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , skip_consent
+                     , allowed_scopes
+                     , default_scope
+                     , strip_disallowed_scopes
+                     , tls_client_auth_san_dns
+                     , tls_client_auth_san_email
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = $1
@@ -353,10 +393,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , encrypted_client_secret
                      , application_type
                      , redirect_uris
+                     , response_types
                      , grant_type_authorization_code
                      , grant_type_refresh_token
                      , grant_type_client_credentials
                      , grant_type_device_code
+                     , grant_type_ciba
                      , contacts
                      , client_name
                      , logo_uri
@@ -370,6 +412,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , skip_consent
+                     , allowed_scopes
+                     , default_scope
+                     , strip_disallowed_scopes
+                     , tls_client_auth_san_dns
+                     , tls_client_auth_san_email
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = ANY($1::uuid[])
@@ -457,10 +505,11 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                     , token_endpoint_auth_method
                     , token_endpoint_auth_signing_alg
                     , initiate_login_uri
+                    , grant_type_ciba
                     , is_static
                     )
                 VALUES
-                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, FALSE)
+                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, FALSE)
             "#,
             Uuid::from(id),
             encrypted_client_secret,
@@ -488,6 +537,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                 .as_ref()
                 .map(ToString::to_string),
             initiate_login_uri.as_ref().map(Url::as_str),
+            grant_types.contains(&GrantType::ClientInitiatedBackchannelAuthentication),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -524,6 +574,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            skip_consent: false,
+            allowed_scopes: None,
+            default_scope: None,
+            strip_disallowed_scopes: false,
+            tls_client_auth_san_dns: None,
+            tls_client_auth_san_email: None,
         })
     }
 
@@ -544,6 +600,20 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        response_types: Vec<OAuthAuthorizationEndpointResponseType>,
+        grant_types: Vec<GrantType>,
+        application_type: Option<ApplicationType>,
+        skip_consent: bool,
+        allowed_scopes: Option<Vec<String>>,
+        default_scope: Option<Scope>,
+        strip_disallowed_scopes: bool,
+        client_name: Option<String>,
+        client_uri: Option<Url>,
+        logo_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+        tls_client_auth_san_dns: Option<String>,
+        tls_client_auth_san_email: Option<String>,
     ) -> Result<Client, Self::Error> {
         let jwks_json = jwks
             .as_ref()
@@ -553,6 +623,10 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
 
         let client_auth_method = client_auth_method.to_string();
         let redirect_uris_array = redirect_uris.iter().map(Url::to_string).collect::<Vec<_>>();
+        let response_types_array = response_types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
 
         sqlx::query!(
             r#"
@@ -560,6 +634,7 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                     ( oauth2_client_id
                     , encrypted_client_secret
                     , redirect_uris
+                    , response_types
                     , grant_type_authorization_code
                     , grant_type_refresh_token
                     , grant_type_client_credentials
@@ -567,13 +642,29 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                     , token_endpoint_auth_method
                     , jwks
                     , jwks_uri
+                    , application_type
+                    , skip_consent
+                    , allowed_scopes
+                    , default_scope
+                    , strip_disallowed_scopes
+                    , client_name
+                    , client_uri
+                    , logo_uri
+                    , policy_uri
+                    , tos_uri
+                    , tls_client_auth_san_dns
+                    , tls_client_auth_san_email
+                    , grant_type_ciba
                     , is_static
                     )
                 VALUES
-                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, TRUE)
+                    ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16
+                    , $17, $18, $19, $20, $21, $22, $23, $24, TRUE
+                    )
                 ON CONFLICT (oauth2_client_id)
                 DO
                     UPDATE SET encrypted_client_secret = EXCLUDED.encrypted_client_secret
+                             , response_types = EXCLUDED.response_types
                              , grant_type_authorization_code = EXCLUDED.grant_type_authorization_code
                              , grant_type_refresh_token = EXCLUDED.grant_type_refresh_token
                              , grant_type_client_credentials = EXCLUDED.grant_type_client_credentials
@@ -581,18 +672,45 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                              , token_endpoint_auth_method = EXCLUDED.token_endpoint_auth_method
                              , jwks = EXCLUDED.jwks
                              , jwks_uri = EXCLUDED.jwks_uri
+                             , application_type = EXCLUDED.application_type
+                             , skip_consent = EXCLUDED.skip_consent
+                             , allowed_scopes = EXCLUDED.allowed_scopes
+                             , default_scope = EXCLUDED.default_scope
+                             , strip_disallowed_scopes = EXCLUDED.strip_disallowed_scopes
+                             , client_name = EXCLUDED.client_name
+                             , client_uri = EXCLUDED.client_uri
+                             , logo_uri = EXCLUDED.logo_uri
+                             , policy_uri = EXCLUDED.policy_uri
+                             , tos_uri = EXCLUDED.tos_uri
+                             , tls_client_auth_san_dns = EXCLUDED.tls_client_auth_san_dns
+                             , tls_client_auth_san_email = EXCLUDED.tls_client_auth_san_email
+                             , grant_type_ciba = EXCLUDED.grant_type_ciba
                              , is_static = TRUE
             "#,
             Uuid::from(client_id),
             encrypted_client_secret,
             &redirect_uris_array,
-            true,
-            true,
-            true,
-            true,
+            &response_types_array,
+            grant_types.contains(&GrantType::AuthorizationCode),
+            grant_types.contains(&GrantType::RefreshToken),
+            grant_types.contains(&GrantType::ClientCredentials),
+            grant_types.contains(&GrantType::DeviceCode),
             client_auth_method,
             jwks_json,
             jwks_uri.as_ref().map(Url::as_str),
+            application_type.as_ref().map(ToString::to_string),
+            skip_consent,
+            allowed_scopes.as_deref(),
+            default_scope.as_ref().map(ToString::to_string),
+            strip_disallowed_scopes,
+            client_name,
+            client_uri.as_ref().map(Url::as_str),
+            logo_uri.as_ref().map(Url::as_str),
+            policy_uri.as_ref().map(Url::as_str),
+            tos_uri.as_ref().map(Url::as_str),
+            tls_client_auth_san_dns.as_deref(),
+            tls_client_auth_san_email.as_deref(),
+            grant_types.contains(&GrantType::ClientInitiatedBackchannelAuthentication),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -609,30 +727,28 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             id: client_id,
             client_id: client_id.to_string(),
             encrypted_client_secret,
-            application_type: None,
+            application_type,
             redirect_uris,
-            response_types: vec![
-                OAuthAuthorizationEndpointResponseType::Code,
-                OAuthAuthorizationEndpointResponseType::IdToken,
-                OAuthAuthorizationEndpointResponseType::None,
-            ],
-            grant_types: vec![
-                GrantType::AuthorizationCode,
-                GrantType::RefreshToken,
-                GrantType::ClientCredentials,
-            ],
+            response_types,
+            grant_types,
             contacts: Vec::new(),
-            client_name: None,
-            logo_uri: None,
-            client_uri: None,
-            policy_uri: None,
-            tos_uri: None,
+            client_name,
+            logo_uri,
+            client_uri,
+            policy_uri,
+            tos_uri,
             jwks,
             id_token_signed_response_alg: None,
             userinfo_signed_response_alg: None,
             token_endpoint_auth_method: None,
             token_endpoint_auth_signing_alg: None,
             initiate_login_uri: None,
+            skip_consent,
+            allowed_scopes,
+            default_scope,
+            strip_disallowed_scopes,
+            tls_client_auth_san_dns,
+            tls_client_auth_san_email,
         })
     }
 
@@ -652,10 +768,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , encrypted_client_secret
                      , application_type
                      , redirect_uris
+                     , response_types
                      , grant_type_authorization_code
                      , grant_type_refresh_token
                      , grant_type_client_credentials
                      , grant_type_device_code
+                     , grant_type_ciba
                      , contacts
                      , client_name
                      , logo_uri
@@ -669,6 +787,12 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , skip_consent
+                     , allowed_scopes
+                     , default_scope
+                     , strip_disallowed_scopes
+                     , tls_client_auth_san_dns
+                     , tls_client_auth_san_email
                 FROM oauth2_clients c
                 WHERE is_static = TRUE
             "#,
@@ -682,6 +806,60 @@ impl<'c> OAuth2ClientRepository for PgOAuth2ClientRepository<'c> {
             .collect()
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_client.all",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn all(&mut self) -> Result<Vec<Client>, Self::Error> {
+        let res = sqlx::query_as!(
+            OAuth2ClientLookup,
+            r#"
+                SELECT oauth2_client_id
+                     , encrypted_client_secret
+                     , application_type
+                     , redirect_uris
+                     , response_types
+                     , grant_type_authorization_code
+                     , grant_type_refresh_token
+                     , grant_type_client_credentials
+                     , grant_type_device_code
+                     , grant_type_ciba
+                     , contacts
+                     , client_name
+                     , logo_uri
+                     , client_uri
+                     , policy_uri
+                     , tos_uri
+                     , jwks_uri
+                     , jwks
+                     , id_token_signed_response_alg
+                     , userinfo_signed_response_alg
+                     , token_endpoint_auth_method
+                     , token_endpoint_auth_signing_alg
+                     , initiate_login_uri
+                     , skip_consent
+                     , allowed_scopes
+                     , default_scope
+                     , strip_disallowed_scopes
+                     , tls_client_auth_san_dns
+                     , tls_client_auth_san_email
+                FROM oauth2_clients c
+                ORDER BY oauth2_client_id
+            "#,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        res.into_iter()
+            .map(|r| r.try_into().map_err(DatabaseError::from))
+            .collect()
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_client.get_consent_for_user",
         skip_all,
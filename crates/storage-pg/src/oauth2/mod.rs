@@ -17,6 +17,7 @@
 
 mod access_token;
 mod authorization_grant;
+mod backchannel_authentication_grant;
 mod client;
 mod device_code_grant;
 mod refresh_token;
@@ -24,8 +25,9 @@ mod session;
 
 pub use self::{
     access_token::PgOAuth2AccessTokenRepository,
-    authorization_grant::PgOAuth2AuthorizationGrantRepository, client::PgOAuth2ClientRepository,
-    device_code_grant::PgOAuth2DeviceCodeGrantRepository,
+    authorization_grant::PgOAuth2AuthorizationGrantRepository,
+    backchannel_authentication_grant::PgOAuth2BackchannelAuthenticationGrantRepository,
+    client::PgOAuth2ClientRepository, device_code_grant::PgOAuth2DeviceCodeGrantRepository,
     refresh_token::PgOAuth2RefreshTokenRepository, session::PgOAuth2SessionRepository,
 };
 
@@ -35,7 +37,10 @@ mod tests {
     use mas_data_model::{AuthorizationCode, UserAgent};
     use mas_storage::{
         clock::MockClock,
-        oauth2::{OAuth2DeviceCodeGrantParams, OAuth2SessionFilter, OAuth2SessionRepository},
+        oauth2::{
+            OAuth2BackchannelAuthenticationGrantParams, OAuth2DeviceCodeGrantParams,
+            OAuth2SessionFilter, OAuth2SessionRepository,
+        },
         Clock, Pagination, Repository,
     };
     use oauth2_types::{
@@ -171,6 +176,21 @@ mod tests {
             .expect("grant not found");
         assert_eq!(grant, grant_lookup);
 
+        // Restrict the scope of the grant, and check it was saved
+        let grant = repo
+            .oauth2_authorization_grant()
+            .restrict_scope(grant, Scope::from_iter([OPENID]))
+            .await
+            .unwrap();
+        let grant_lookup = repo
+            .oauth2_authorization_grant()
+            .lookup(grant.id)
+            .await
+            .unwrap()
+            .expect("grant not found");
+        assert_eq!(grant.scope, Scope::from_iter([OPENID]));
+        assert_eq!(grant, grant_lookup);
+
         // Create a user and a start a user session
         let user = repo
             .user()
@@ -248,6 +268,52 @@ mod tests {
             .unwrap();
         assert!(grant.is_exchanged());
 
+        // Start a session and fulfill another grant in one go
+        let grant = repo
+            .oauth2_authorization_grant()
+            .add(
+                &mut rng,
+                &clock,
+                &client,
+                "https://example.com/redirect".parse().unwrap(),
+                Scope::from_iter([OPENID]),
+                None,
+                None,
+                None,
+                None,
+                ResponseMode::Query,
+                true,
+                false,
+            )
+            .await
+            .unwrap();
+        let (session, grant) = repo
+            .oauth2_authorization_grant()
+            .start_session(&mut rng, &clock, &user_session, grant)
+            .await
+            .unwrap();
+        assert!(grant.is_fulfilled());
+        assert_eq!(session.user_id, Some(user.id));
+        assert_eq!(session.user_session_id, Some(user_session.id));
+        assert_eq!(session.client_id, client.id);
+        assert_eq!(session.scope, grant.scope);
+
+        let grant_lookup = repo
+            .oauth2_authorization_grant()
+            .lookup(grant.id)
+            .await
+            .unwrap()
+            .expect("grant not found");
+        assert_eq!(grant, grant_lookup);
+
+        let session_lookup = repo
+            .oauth2_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .expect("session not found");
+        assert_eq!(session, session_lookup);
+
         // Lookup a non-existing token
         let token = repo
             .oauth2_access_token()
@@ -709,6 +775,37 @@ mod tests {
         assert_eq!(list.edges.len(), 1);
         assert_eq!(list.edges[0], session11);
         assert_eq!(repo.oauth2_session().count(filter).await.unwrap(), 1);
+
+        // Only the sessions which were not used recently are inactive. Sessions
+        // which were never used are matched on their creation time
+        clock.advance(Duration::try_days(1).unwrap());
+        repo.oauth2_session()
+            .record_batch_activity(vec![(session11.id, clock.now(), None)])
+            .await
+            .unwrap();
+        let filter = OAuth2SessionFilter::new().with_last_active_before(clock.now());
+        let list = repo
+            .oauth2_session()
+            .list(filter, pagination)
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 3);
+        assert_eq!(list.edges[0], session12);
+        assert_eq!(list.edges[1], session21);
+        assert_eq!(list.edges[2], session22);
+        assert_eq!(repo.oauth2_session().count(filter).await.unwrap(), 3);
+
+        // The sessions of bots can be left out
+        repo.user().set_bot(user2, true).await.unwrap();
+        let filter = filter.without_bots();
+        let list = repo
+            .oauth2_session()
+            .list(filter, pagination)
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 1);
+        assert_eq!(list.edges[0], session21);
+        assert_eq!(repo.oauth2_session().count(filter).await.unwrap(), 1);
     }
 
     /// Test the [`OAuth2DeviceCodeGrantRepository`] implementation
@@ -936,4 +1033,166 @@ mod tests {
             .await;
         assert!(res.is_err());
     }
+    /// Test the [`OAuth2BackchannelAuthenticationGrantRepository`]
+    /// implementation
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_backchannel_authentication_grant_repository(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        // Provision a client
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                Vec::new(),
+                None,
+                None,
+                vec![GrantType::ClientInitiatedBackchannelAuthentication],
+                Vec::new(),
+                Some("Example".to_owned()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            client.grant_types,
+            vec![GrantType::ClientInitiatedBackchannelAuthentication]
+        );
+
+        // Provision a user and a browser session
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+
+        let scope = Scope::from_iter([OPENID]);
+
+        // Create a backchannel authentication grant
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .add(
+                &mut rng,
+                &clock,
+                OAuth2BackchannelAuthenticationGrantParams {
+                    client: &client,
+                    scope: scope.clone(),
+                    user: &user,
+                    auth_req_id: "authreqid".to_owned(),
+                    binding_message: Some("W4SCT".to_owned()),
+                    expires_in: Duration::try_minutes(5).unwrap(),
+                    ip_address: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(grant.is_pending());
+        assert_eq!(grant.user_id, user.id);
+
+        // Check that we can find the grant by ID and by auth_req_id
+        let id = grant.id;
+        let lookup = repo
+            .oauth2_backchannel_authentication_grant()
+            .lookup(id)
+            .await
+            .unwrap();
+        assert_eq!(lookup.as_ref(), Some(&grant));
+
+        let lookup = repo
+            .oauth2_backchannel_authentication_grant()
+            .find_by_auth_req_id("authreqid")
+            .await
+            .unwrap();
+        assert_eq!(lookup.as_ref(), Some(&grant));
+
+        // Mark it as fulfilled
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .fulfill(&clock, grant, &browser_session)
+            .await
+            .unwrap();
+        assert!(grant.is_fulfilled());
+
+        // It can't be rejected anymore
+        let res = repo
+            .oauth2_backchannel_authentication_grant()
+            .reject(&clock, grant.clone(), &browser_session)
+            .await;
+        assert!(res.is_err());
+
+        // Exchange it for a session
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(&mut rng, &clock, &client, &browser_session, scope.clone())
+            .await
+            .unwrap();
+
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .exchange(&clock, grant, &session)
+            .await
+            .unwrap();
+        assert!(grant.is_exchanged());
+
+        let lookup = repo
+            .oauth2_backchannel_authentication_grant()
+            .lookup(id)
+            .await
+            .unwrap();
+        assert_eq!(lookup.as_ref(), Some(&grant));
+
+        // Create another one and reject it
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .add(
+                &mut rng,
+                &clock,
+                OAuth2BackchannelAuthenticationGrantParams {
+                    client: &client,
+                    scope,
+                    user: &user,
+                    auth_req_id: "second_authreqid".to_owned(),
+                    binding_message: None,
+                    expires_in: Duration::try_minutes(5).unwrap(),
+                    ip_address: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let grant = repo
+            .oauth2_backchannel_authentication_grant()
+            .reject(&clock, grant, &browser_session)
+            .await
+            .unwrap();
+        assert!(grant.is_rejected());
+
+        // It can't be exchanged
+        let res = repo
+            .oauth2_backchannel_authentication_grant()
+            .exchange(&clock, grant, &session)
+            .await;
+        assert!(res.is_err());
+    }
 }
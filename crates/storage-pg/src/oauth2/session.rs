@@ -15,7 +15,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{BrowserSession, Client, Session, SessionState, User, UserAgent};
 use mas_storage::{
     oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
@@ -23,14 +23,14 @@ use mas_storage::{
 };
 use oauth2_types::scope::{Scope, ScopeToken};
 use rand::RngCore;
-use sea_query::{enum_def, extension::postgres::PgExpr, Expr, PostgresQueryBuilder, Query};
+use sea_query::{enum_def, extension::postgres::PgExpr, Expr, Func, PostgresQueryBuilder, Query};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
 use uuid::Uuid;
 
 use crate::{
-    iden::{OAuth2Sessions, UserSessions},
+    iden::{OAuth2Sessions, UserSessions, Users},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
     DatabaseError, DatabaseInconsistencyError,
@@ -316,6 +316,27 @@ impl<'c> OAuth2SessionRepository for PgOAuth2SessionRepository<'c> {
                 let scope: Vec<String> = scope.iter().map(|s| s.as_str().to_owned()).collect();
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::ScopeList)).contains(scope)
             }))
+            .and_where_option(filter.last_active_before().map(|last_active_before| {
+                Expr::expr(Func::coalesce([
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveAt)).into(),
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::CreatedAt)).into(),
+                ]))
+                .lt(last_active_before)
+            }))
+            .and_where_option(filter.excludes_bots().then(|| {
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(Users::Table)
+                        .and_where(
+                            Expr::col((Users::Table, Users::UserId))
+                                .equals((OAuth2Sessions::Table, OAuth2Sessions::UserId)),
+                        )
+                        .and_where(Expr::col((Users::Table, Users::IsBot)).eq(true))
+                        .take(),
+                )
+                .not()
+            }))
             .generate_pagination(
                 (OAuth2Sessions::Table, OAuth2Sessions::OAuth2SessionId),
                 pagination,
@@ -377,6 +398,27 @@ impl<'c> OAuth2SessionRepository for PgOAuth2SessionRepository<'c> {
                 let scope: Vec<String> = scope.iter().map(|s| s.as_str().to_owned()).collect();
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::ScopeList)).contains(scope)
             }))
+            .and_where_option(filter.last_active_before().map(|last_active_before| {
+                Expr::expr(Func::coalesce([
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveAt)).into(),
+                    Expr::col((OAuth2Sessions::Table, OAuth2Sessions::CreatedAt)).into(),
+                ]))
+                .lt(last_active_before)
+            }))
+            .and_where_option(filter.excludes_bots().then(|| {
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(Users::Table)
+                        .and_where(
+                            Expr::col((Users::Table, Users::UserId))
+                                .equals((OAuth2Sessions::Table, OAuth2Sessions::UserId)),
+                        )
+                        .and_where(Expr::col((Users::Table, Users::IsBot)).eq(true))
+                        .take(),
+                )
+                .not()
+            }))
             .build_sqlx(PostgresQueryBuilder);
 
         let count: i64 = sqlx::query_scalar_with(&sql, arguments)
@@ -389,6 +431,66 @@ impl<'c> OAuth2SessionRepository for PgOAuth2SessionRepository<'c> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_session.forget_last_active_ips",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_sessions
+                SET last_active_ip = NULL
+                WHERE last_active_ip IS NOT NULL
+                  AND last_active_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_session.forget_user_agents",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_sessions
+                SET user_agent = NULL
+                WHERE user_agent IS NOT NULL
+                  AND COALESCE(last_active_at, created_at) < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_session.record_batch_activity",
         skip_all,
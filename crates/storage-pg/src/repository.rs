@@ -21,11 +21,16 @@ use mas_storage::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
     },
+    email::EmailDeadLetterRepository,
+    idempotency::IdempotencyKeyRepository,
     job::JobRepository,
+    network_ban::NetworkBanRepository,
     oauth2::{
-        OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository, OAuth2ClientRepository,
+        OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
+        OAuth2BackchannelAuthenticationGrantRepository, OAuth2ClientRepository,
         OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
+    stats::StatsRepository,
     upstream_oauth2::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
@@ -42,19 +47,26 @@ use crate::{
         PgCompatAccessTokenRepository, PgCompatRefreshTokenRepository, PgCompatSessionRepository,
         PgCompatSsoLoginRepository,
     },
+    email::PgEmailDeadLetterRepository,
+    idempotency::PgIdempotencyKeyRepository,
     job::PgJobRepository,
+    network_ban::PgNetworkBanRepository,
     oauth2::{
         PgOAuth2AccessTokenRepository, PgOAuth2AuthorizationGrantRepository,
-        PgOAuth2ClientRepository, PgOAuth2DeviceCodeGrantRepository,
-        PgOAuth2RefreshTokenRepository, PgOAuth2SessionRepository,
+        PgOAuth2BackchannelAuthenticationGrantRepository, PgOAuth2ClientRepository,
+        PgOAuth2DeviceCodeGrantRepository, PgOAuth2RefreshTokenRepository,
+        PgOAuth2SessionRepository,
     },
+    stats::PgStatsRepository,
     upstream_oauth2::{
         PgUpstreamOAuthLinkRepository, PgUpstreamOAuthProviderRepository,
         PgUpstreamOAuthSessionRepository,
     },
     user::{
-        PgBrowserSessionRepository, PgUserEmailRepository, PgUserPasswordRepository,
-        PgUserRecoveryRepository, PgUserRepository, PgUserTermsRepository,
+        PgBrowserSessionRepository, PgUserEmailChangeRepository, PgUserEmailRepository,
+        PgUserExportRepository, PgUserLoginAlertRepository, PgUserLoginCodeRepository,
+        PgUserLoginRiskRepository, PgUserMagicLinkRepository, PgUserPasswordRepository,
+        PgUserPhoneRepository, PgUserRecoveryRepository, PgUserRepository, PgUserTermsRepository,
     },
     DatabaseError,
 };
@@ -185,6 +197,48 @@ where
         Box::new(PgUserRecoveryRepository::new(self.conn.as_mut()))
     }
 
+    fn user_magic_link<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserMagicLinkRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserMagicLinkRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_login_alert<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserLoginAlertRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserLoginAlertRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_login_code<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserLoginCodeRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserLoginCodeRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_login_risk<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserLoginRiskRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserLoginRiskRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_email_change<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserEmailChangeRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserEmailChangeRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_export<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserExportRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserExportRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_phone<'c>(
+        &'c mut self,
+    ) -> Box<dyn mas_storage::user::UserPhoneRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserPhoneRepository::new(self.conn.as_mut()))
+    }
+
     fn user_terms<'c>(
         &'c mut self,
     ) -> Box<dyn mas_storage::user::UserTermsRepository<Error = Self::Error> + 'c> {
@@ -239,6 +293,14 @@ where
         Box::new(PgOAuth2DeviceCodeGrantRepository::new(self.conn.as_mut()))
     }
 
+    fn oauth2_backchannel_authentication_grant<'c>(
+        &'c mut self,
+    ) -> Box<dyn OAuth2BackchannelAuthenticationGrantRepository<Error = Self::Error> + 'c> {
+        Box::new(PgOAuth2BackchannelAuthenticationGrantRepository::new(
+            self.conn.as_mut(),
+        ))
+    }
+
     fn compat_session<'c>(
         &'c mut self,
     ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -266,4 +328,24 @@ where
     fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
         Box::new(PgJobRepository::new(self.conn.as_mut()))
     }
+
+    fn email_dead_letter<'c>(
+        &'c mut self,
+    ) -> Box<dyn EmailDeadLetterRepository<Error = Self::Error> + 'c> {
+        Box::new(PgEmailDeadLetterRepository::new(self.conn.as_mut()))
+    }
+
+    fn idempotency_key<'c>(
+        &'c mut self,
+    ) -> Box<dyn IdempotencyKeyRepository<Error = Self::Error> + 'c> {
+        Box::new(PgIdempotencyKeyRepository::new(self.conn.as_mut()))
+    }
+
+    fn stats<'c>(&'c mut self) -> Box<dyn StatsRepository<Error = Self::Error> + 'c> {
+        Box::new(PgStatsRepository::new(self.conn.as_mut()))
+    }
+
+    fn network_ban<'c>(&'c mut self) -> Box<dyn NetworkBanRepository<Error = Self::Error> + 'c> {
+        Box::new(PgNetworkBanRepository::new(self.conn.as_mut()))
+    }
 }
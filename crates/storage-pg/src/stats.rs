@@ -0,0 +1,328 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module containing the PostgreSQL implementation of the
+//! [`StatsRepository`]
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use mas_data_model::{ClientDailyCount, DailyCount};
+use mas_storage::stats::StatsRepository;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`StatsRepository`] for a PostgreSQL connection
+pub struct PgStatsRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgStatsRepository<'c> {
+    /// Create a new [`PgStatsRepository`] from an active PostgreSQL connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct DailyCountRow {
+    day: NaiveDate,
+    count: i64,
+}
+
+impl TryFrom<DailyCountRow> for DailyCount {
+    type Error = DatabaseError;
+
+    fn try_from(row: DailyCountRow) -> Result<Self, Self::Error> {
+        Ok(DailyCount {
+            day: row.day,
+            count: row
+                .count
+                .try_into()
+                .map_err(DatabaseError::to_invalid_operation)?,
+        })
+    }
+}
+
+struct ClientDailyCountRow {
+    day: NaiveDate,
+    oauth2_client_id: Uuid,
+    count: i64,
+}
+
+impl TryFrom<ClientDailyCountRow> for ClientDailyCount {
+    type Error = DatabaseError;
+
+    fn try_from(row: ClientDailyCountRow) -> Result<Self, Self::Error> {
+        Ok(ClientDailyCount {
+            day: row.day,
+            client_id: row.oauth2_client_id.into(),
+            count: row
+                .count
+                .try_into()
+                .map_err(DatabaseError::to_invalid_operation)?,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> StatsRepository for PgStatsRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.stats.refresh",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn refresh(&mut self) -> Result<(), Self::Error> {
+        // Refreshing concurrently doesn't block the readers while the views are
+        // being recomputed
+        sqlx::query!(
+            r#"
+                REFRESH MATERIALIZED VIEW CONCURRENTLY stats_daily_authentications
+            "#,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                REFRESH MATERIALIZED VIEW CONCURRENTLY stats_daily_registrations
+            "#,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                REFRESH MATERIALIZED VIEW CONCURRENTLY stats_daily_oauth2_tokens
+            "#,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "db.stats.daily_authentications",
+        skip_all,
+        fields(
+            db.statement,
+            %since,
+        ),
+        err,
+    )]
+    async fn daily_authentications(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyCount>, Self::Error> {
+        let rows = sqlx::query_as!(
+            DailyCountRow,
+            r#"
+                SELECT day AS "day!"
+                     , count AS "count!"
+                FROM stats_daily_authentications
+                WHERE day >= $1
+                ORDER BY day
+            "#,
+            since,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    #[tracing::instrument(
+        name = "db.stats.daily_registrations",
+        skip_all,
+        fields(
+            db.statement,
+            %since,
+        ),
+        err,
+    )]
+    async fn daily_registrations(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyCount>, Self::Error> {
+        let rows = sqlx::query_as!(
+            DailyCountRow,
+            r#"
+                SELECT day AS "day!"
+                     , count AS "count!"
+                FROM stats_daily_registrations
+                WHERE day >= $1
+                ORDER BY day
+            "#,
+            since,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    #[tracing::instrument(
+        name = "db.stats.daily_oauth2_tokens",
+        skip_all,
+        fields(
+            db.statement,
+            %since,
+        ),
+        err,
+    )]
+    async fn daily_oauth2_tokens(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<ClientDailyCount>, Self::Error> {
+        let rows = sqlx::query_as!(
+            ClientDailyCountRow,
+            r#"
+                SELECT day AS "day!"
+                     , oauth2_client_id AS "oauth2_client_id!"
+                     , count AS "count!"
+                FROM stats_daily_oauth2_tokens
+                WHERE day >= $1
+                ORDER BY day, oauth2_client_id
+            "#,
+            since,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{clock::MockClock, Clock, RepositoryAccess};
+    use oauth2_types::scope::{Scope, OPENID};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::PgRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_stats_repo(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+        let today = clock.now().date_naive();
+
+        // The views are empty to start with
+        repo.stats().refresh().await.unwrap();
+        assert!(repo
+            .stats()
+            .daily_registrations(today)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+        let password = repo
+            .user_password()
+            .add(&mut rng, &clock, &user, 1, "hash".to_owned(), None)
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_password(&mut rng, &clock, &session, &password)
+            .await
+            .unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec![],
+                None,
+                None,
+                vec![],
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.oauth2_session()
+            .add_from_client_credentials(&mut rng, &clock, &client, Scope::from_iter([OPENID]))
+            .await
+            .unwrap();
+
+        // Nothing shows up until the views are refreshed
+        assert!(repo
+            .stats()
+            .daily_authentications(today)
+            .await
+            .unwrap()
+            .is_empty());
+
+        repo.stats().refresh().await.unwrap();
+
+        let authentications = repo.stats().daily_authentications(today).await.unwrap();
+        assert_eq!(authentications.len(), 1);
+        assert_eq!(authentications[0].day, today);
+        assert_eq!(authentications[0].count, 1);
+
+        let registrations = repo.stats().daily_registrations(today).await.unwrap();
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[0].count, 1);
+
+        let tokens = repo.stats().daily_oauth2_tokens(today).await.unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].client_id, client.id);
+        assert_eq!(tokens[0].count, 1);
+
+        // Days before the given one are not returned
+        let tomorrow = today.succ_opt().unwrap();
+        assert!(repo
+            .stats()
+            .daily_registrations(tomorrow)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -14,7 +14,7 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthProvider, User};
+use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthLinkToken, UpstreamOAuthProvider, User};
 use mas_storage::{
     upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository},
     Clock, Page, Pagination,
@@ -69,6 +69,27 @@ impl From<LinkLookup> for UpstreamOAuthLink {
     }
 }
 
+#[derive(sqlx::FromRow)]
+struct LinkTokenLookup {
+    upstream_oauth_link_id: Uuid,
+    encrypted_access_token: String,
+    encrypted_refresh_token: Option<String>,
+    access_token_expires_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<LinkTokenLookup> for UpstreamOAuthLinkToken {
+    fn from(value: LinkTokenLookup) -> Self {
+        UpstreamOAuthLinkToken {
+            link_id: Ulid::from(value.upstream_oauth_link_id),
+            encrypted_access_token: value.encrypted_access_token,
+            encrypted_refresh_token: value.encrypted_refresh_token,
+            access_token_expires_at: value.access_token_expires_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
 #[async_trait]
 impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
     type Error = DatabaseError;
@@ -388,4 +409,121 @@ impl<'c> UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'c> {
             .try_into()
             .map_err(DatabaseError::to_invalid_operation)
     }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.lookup_token",
+        skip_all,
+        fields(
+            db.statement,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn lookup_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkToken>, Self::Error> {
+        let res = sqlx::query_as!(
+            LinkTokenLookup,
+            r#"
+                SELECT
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    updated_at
+                FROM upstream_oauth_link_tokens
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?
+        .map(Into::into);
+
+        Ok(res)
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.save_token",
+        skip_all,
+        fields(
+            db.statement,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn save_token(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkToken, Self::Error> {
+        let updated_at = clock.now();
+
+        sqlx::query!(
+            r#"
+                INSERT INTO upstream_oauth_link_tokens (
+                    upstream_oauth_link_id,
+                    encrypted_access_token,
+                    encrypted_refresh_token,
+                    access_token_expires_at,
+                    updated_at
+                ) VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (upstream_oauth_link_id)
+                    DO UPDATE
+                    SET
+                        encrypted_access_token = EXCLUDED.encrypted_access_token,
+                        encrypted_refresh_token = EXCLUDED.encrypted_refresh_token,
+                        access_token_expires_at = EXCLUDED.access_token_expires_at,
+                        updated_at = EXCLUDED.updated_at
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+            &encrypted_access_token,
+            encrypted_refresh_token.as_deref(),
+            access_token_expires_at,
+            updated_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UpstreamOAuthLinkToken {
+            link_id: upstream_oauth_link.id,
+            encrypted_access_token,
+            encrypted_refresh_token,
+            access_token_expires_at,
+            updated_at,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.remove_token",
+        skip_all,
+        fields(
+            db.statement,
+            %upstream_oauth_link.id,
+        ),
+        err,
+    )]
+    async fn remove_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<(), Self::Error> {
+        sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_link_tokens
+                WHERE upstream_oauth_link_id = $1
+            "#,
+            Uuid::from(upstream_oauth_link.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(())
+    }
 }
@@ -36,7 +36,7 @@ mod tests {
             UpstreamOAuthSessionRepository,
         },
         user::UserRepository,
-        Pagination, RepositoryAccess,
+        Clock, Pagination, RepositoryAccess,
     };
     use oauth2_types::scope::{Scope, OPENID};
     use rand::SeedableRng;
@@ -77,6 +77,8 @@ mod tests {
                     discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                     pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
                     additional_authorization_parameters: Vec::new(),
+                    store_tokens: false,
+                    on_deprovision: mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore,
                 },
             )
             .await
@@ -208,6 +210,76 @@ mod tests {
 
         assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 1);
 
+        // No tokens are saved for the link at first
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_token(&link)
+            .await
+            .unwrap()
+            .is_none());
+
+        let expires_at = clock.now() + Duration::try_minutes(5).unwrap();
+        repo.upstream_oauth_link()
+            .save_token(
+                &clock,
+                &link,
+                "encrypted-access-token".to_owned(),
+                Some("encrypted-refresh-token".to_owned()),
+                Some(expires_at),
+            )
+            .await
+            .unwrap();
+
+        let token = repo
+            .upstream_oauth_link()
+            .lookup_token(&link)
+            .await
+            .unwrap()
+            .expect("token to be found in the database");
+        assert_eq!(token.link_id, link.id);
+        assert_eq!(token.encrypted_access_token, "encrypted-access-token");
+        assert_eq!(
+            token.encrypted_refresh_token.as_deref(),
+            Some("encrypted-refresh-token")
+        );
+        assert_eq!(token.access_token_expires_at, Some(expires_at));
+        assert!(!token.is_access_token_expired(clock.now(), Duration::zero()));
+
+        // Saving again replaces the previous tokens
+        clock.advance(Duration::try_minutes(10).unwrap());
+        assert!(token.is_access_token_expired(clock.now(), Duration::zero()));
+        repo.upstream_oauth_link()
+            .save_token(&clock, &link, "new-access-token".to_owned(), None, None)
+            .await
+            .unwrap();
+
+        let token = repo
+            .upstream_oauth_link()
+            .lookup_token(&link)
+            .await
+            .unwrap()
+            .expect("token to be found in the database");
+        assert_eq!(token.encrypted_access_token, "new-access-token");
+        assert_eq!(token.encrypted_refresh_token, None);
+        assert_eq!(token.updated_at, clock.now());
+
+        repo.upstream_oauth_link()
+            .remove_token(&link)
+            .await
+            .unwrap();
+        assert!(repo
+            .upstream_oauth_link()
+            .lookup_token(&link)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Keep a token around, so that deleting the provider has to clean it up
+        repo.upstream_oauth_link()
+            .save_token(&clock, &link, "access-token".to_owned(), None, None)
+            .await
+            .unwrap();
+
         // There should be exactly one enabled provider
         assert_eq!(
             repo.upstream_oauth_provider()
@@ -319,6 +391,8 @@ mod tests {
                         discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
                         pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
                         additional_authorization_parameters: Vec::new(),
+                        store_tokens: false,
+                        on_deprovision: mas_data_model::UpstreamOAuthProviderOnDeprovision::Ignore,
                     },
                 )
                 .await
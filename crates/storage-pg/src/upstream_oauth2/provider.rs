@@ -70,6 +70,8 @@ struct ProviderLookup {
     discovery_mode: String,
     pkce_mode: String,
     additional_parameters: Option<Json<Vec<(String, String)>>>,
+    store_tokens: bool,
+    on_deprovision: String,
 }
 
 impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
@@ -146,6 +148,13 @@ impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
                 .source(e)
         })?;
 
+        let on_deprovision = value.on_deprovision.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("upstream_oauth_providers")
+                .column("on_deprovision")
+                .row(id)
+                .source(e)
+        })?;
+
         let additional_authorization_parameters = value
             .additional_parameters
             .map(|Json(x)| x)
@@ -170,6 +179,8 @@ impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
             discovery_mode,
             pkce_mode,
             additional_authorization_parameters,
+            store_tokens: value.store_tokens,
+            on_deprovision,
         })
     }
 }
@@ -209,7 +220,9 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     token_endpoint_override,
                     discovery_mode,
                     pkce_mode,
-                    additional_parameters as "additional_parameters: Json<Vec<(String, String)>>"
+                    additional_parameters as "additional_parameters: Json<Vec<(String, String)>>",
+                    store_tokens,
+                    on_deprovision
                 FROM upstream_oauth_providers
                 WHERE upstream_oauth_provider_id = $1
             "#,
@@ -266,9 +279,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 jwks_uri_override,
                 discovery_mode,
                 pkce_mode,
+                store_tokens,
+                on_deprovision,
                 created_at
             ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9,
-                      $10, $11, $12, $13, $14, $15, $16)
+                      $10, $11, $12, $13, $14, $15, $16, $17, $18)
         "#,
             Uuid::from(id),
             &params.issuer,
@@ -294,6 +309,8 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             params.jwks_uri_override.as_ref().map(ToString::to_string),
             params.discovery_mode.as_str(),
             params.pkce_mode.as_str(),
+            params.store_tokens,
+            params.on_deprovision.as_str(),
             created_at,
         )
         .traced()
@@ -319,6 +336,8 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             discovery_mode: params.discovery_mode,
             pkce_mode: params.pkce_mode,
             additional_authorization_parameters: params.additional_authorization_parameters,
+            store_tokens: params.store_tokens,
+            on_deprovision: params.on_deprovision,
         })
     }
 
@@ -353,6 +372,31 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             .await?;
         }
 
+        // Delete the tokens of the links, as they have a foreign key constraint on
+        // the links.
+        {
+            let span = info_span!(
+                "db.oauth2_client.delete_by_id.link_tokens",
+                upstream_oauth_provider.id = %id,
+                { DB_STATEMENT } = tracing::field::Empty,
+            );
+            sqlx::query!(
+                r#"
+                    DELETE FROM upstream_oauth_link_tokens
+                    WHERE upstream_oauth_link_id IN (
+                        SELECT upstream_oauth_link_id
+                        FROM upstream_oauth_links
+                        WHERE upstream_oauth_provider_id = $1
+                    )
+                "#,
+                Uuid::from(id),
+            )
+            .record(&span)
+            .execute(&mut *self.conn)
+            .instrument(span)
+            .await?;
+        }
+
         // Delete the links next, as they have a foreign key constraint on the
         // providers.
         {
@@ -426,9 +470,11 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     discovery_mode,
                     pkce_mode,
                     additional_parameters,
+                    store_tokens,
+                    on_deprovision,
                     created_at
                 ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9,
-                          $10, $11, $12, $13, $14, $15, $16, $17)
+                          $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
                 ON CONFLICT (upstream_oauth_provider_id) 
                     DO UPDATE
                     SET
@@ -447,7 +493,9 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                         jwks_uri_override = EXCLUDED.jwks_uri_override,
                         discovery_mode = EXCLUDED.discovery_mode,
                         pkce_mode = EXCLUDED.pkce_mode,
-                        additional_parameters = EXCLUDED.additional_parameters
+                        additional_parameters = EXCLUDED.additional_parameters,
+                        store_tokens = EXCLUDED.store_tokens,
+                        on_deprovision = EXCLUDED.on_deprovision
                 RETURNING created_at
             "#,
             Uuid::from(id),
@@ -475,6 +523,8 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             params.discovery_mode.as_str(),
             params.pkce_mode.as_str(),
             Json(&params.additional_authorization_parameters) as _,
+            params.store_tokens,
+            params.on_deprovision.as_str(),
             created_at,
         )
         .traced()
@@ -500,6 +550,8 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
             discovery_mode: params.discovery_mode,
             pkce_mode: params.pkce_mode,
             additional_authorization_parameters: params.additional_authorization_parameters,
+            store_tokens: params.store_tokens,
+            on_deprovision: params.on_deprovision,
         })
     }
 
@@ -675,6 +727,20 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                 )),
                 ProviderLookupIden::AdditionalParameters,
             )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::StoreTokens,
+                )),
+                ProviderLookupIden::StoreTokens,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::OnDeprovision,
+                )),
+                ProviderLookupIden::OnDeprovision,
+            )
             .from(UpstreamOAuthProviders::Table)
             .and_where_option(filter.enabled().map(|enabled| {
                 Expr::col((
@@ -776,7 +842,9 @@ impl<'c> UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'
                     token_endpoint_override,
                     discovery_mode,
                     pkce_mode,
-                    additional_parameters as "additional_parameters: Json<Vec<(String, String)>>"
+                    additional_parameters as "additional_parameters: Json<Vec<(String, String)>>",
+                    store_tokens,
+                    on_deprovision
                 FROM upstream_oauth_providers
                 WHERE disabled_at IS NULL
             "#,
@@ -0,0 +1,285 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{User, UserEmailChange};
+use mas_storage::{user::UserEmailChangeRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`UserEmailChangeRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserEmailChangeRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserEmailChangeRepository<'c> {
+    /// Create a new [`PgUserEmailChangeRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserEmailChangeRow {
+    user_email_change_id: Uuid,
+    user_id: Uuid,
+    old_email: String,
+    new_email: String,
+    undo_ticket: String,
+    created_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    undone_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserEmailChangeRow> for UserEmailChange {
+    fn from(row: UserEmailChangeRow) -> Self {
+        UserEmailChange {
+            id: row.user_email_change_id.into(),
+            user_id: row.user_id.into(),
+            old_email: row.old_email,
+            new_email: row.new_email,
+            undo_ticket: row.undo_ticket,
+            created_at: row.created_at,
+            confirmed_at: row.confirmed_at,
+            undone_at: row.undone_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> UserEmailChangeRepository for PgUserEmailChangeRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_email_change.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_email_change.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserEmailChangeRow,
+            r#"
+                SELECT
+                      user_email_change_id
+                    , user_id
+                    , old_email
+                    , new_email
+                    , undo_ticket
+                    , created_at
+                    , confirmed_at
+                    , undone_at
+                FROM user_email_changes
+                WHERE user_email_change_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email_change.find_by_undo_ticket",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn find_by_undo_ticket(
+        &mut self,
+        undo_ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserEmailChangeRow,
+            r#"
+                SELECT
+                      user_email_change_id
+                    , user_id
+                    , old_email
+                    , new_email
+                    , undo_ticket
+                    , created_at
+                    , confirmed_at
+                    , undone_at
+                FROM user_email_changes
+                WHERE undo_ticket = $1
+            "#,
+            undo_ticket,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email_change.add",
+        skip_all,
+        fields(
+            db.statement,
+            user_email_change.id,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        old_email: String,
+        new_email: String,
+        undo_ticket: String,
+    ) -> Result<UserEmailChange, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_email_change.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_email_changes (
+                      user_email_change_id
+                    , user_id
+                    , old_email
+                    , new_email
+                    , undo_ticket
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            &old_email,
+            &new_email,
+            &undo_ticket,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserEmailChange {
+            id,
+            user_id: user.id,
+            old_email,
+            new_email,
+            undo_ticket,
+            created_at,
+            confirmed_at: None,
+            undone_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email_change.mark_as_confirmed",
+        skip_all,
+        fields(
+            db.statement,
+            %change.id,
+        ),
+        err,
+    )]
+    async fn mark_as_confirmed(
+        &mut self,
+        clock: &dyn Clock,
+        mut change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error> {
+        // This should have been checked by the caller
+        if !change.is_pending() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let confirmed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_email_changes
+                SET confirmed_at = $1
+                WHERE user_email_change_id = $2
+            "#,
+            confirmed_at,
+            Uuid::from(change.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        change.confirmed_at = Some(confirmed_at);
+        Ok(change)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email_change.mark_as_undone",
+        skip_all,
+        fields(
+            db.statement,
+            %change.id,
+        ),
+        err,
+    )]
+    async fn mark_as_undone(
+        &mut self,
+        clock: &dyn Clock,
+        mut change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error> {
+        // This should have been checked by the caller
+        if change.is_pending() || change.undone_at.is_some() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let undone_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_email_changes
+                SET undone_at = $1
+                WHERE user_email_change_id = $2
+            "#,
+            undone_at,
+            Uuid::from(change.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        change.undone_at = Some(undone_at);
+        Ok(change)
+    }
+}
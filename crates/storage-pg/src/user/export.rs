@@ -0,0 +1,238 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{User, UserExport};
+use mas_storage::{user::UserExportRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`UserExportRepository`] for a PostgreSQL connection
+pub struct PgUserExportRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserExportRepository<'c> {
+    /// Create a new [`PgUserExportRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserExportRow {
+    user_export_id: Uuid,
+    user_id: Uuid,
+    created_at: DateTime<Utc>,
+    completed_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserExportRow> for UserExport {
+    fn from(row: UserExportRow) -> Self {
+        UserExport {
+            id: row.user_export_id.into(),
+            user_id: row.user_id.into(),
+            created_at: row.created_at,
+            completed_at: row.completed_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> UserExportRepository for PgUserExportRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_export.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_export.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserExport>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserExportRow,
+            r#"
+                SELECT
+                      user_export_id
+                    , user_id
+                    , created_at
+                    , completed_at
+                FROM user_exports
+                WHERE user_export_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_export.find_latest",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn find_latest(&mut self, user: &User) -> Result<Option<UserExport>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserExportRow,
+            r#"
+                SELECT
+                      user_export_id
+                    , user_id
+                    , created_at
+                    , completed_at
+                FROM user_exports
+                WHERE user_id = $1
+                ORDER BY user_export_id DESC
+                LIMIT 1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_export.add",
+        skip_all,
+        fields(
+            db.statement,
+            user_export.id,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+    ) -> Result<UserExport, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_export.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_exports (
+                      user_export_id
+                    , user_id
+                    , created_at
+                )
+                VALUES ($1, $2, $3)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserExport {
+            id,
+            user_id: user.id,
+            created_at,
+            completed_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_export.complete",
+        skip_all,
+        fields(
+            db.statement,
+            %export.id,
+        ),
+        err,
+    )]
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        mut export: UserExport,
+        data: serde_json::Value,
+    ) -> Result<UserExport, Self::Error> {
+        // This should have been checked by the caller
+        if !export.is_pending() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let completed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_exports
+                SET completed_at = $1
+                  , data = $2
+                WHERE user_export_id = $3
+            "#,
+            completed_at,
+            data,
+            Uuid::from(export.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        export.completed_at = Some(completed_at);
+        Ok(export)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_export.get_data",
+        skip_all,
+        fields(
+            db.statement,
+            %export.id,
+        ),
+        err,
+    )]
+    async fn get_data(
+        &mut self,
+        export: &UserExport,
+    ) -> Result<Option<serde_json::Value>, Self::Error> {
+        let data = sqlx::query_scalar!(
+            r#"
+                SELECT data
+                FROM user_exports
+                WHERE user_export_id = $1
+            "#,
+            Uuid::from(export.id),
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,477 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{BrowserSession, LoginSighting, User, UserAgent, UserLoginAlert};
+use mas_storage::{user::UserLoginAlertRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`UserLoginAlertRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserLoginAlertRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserLoginAlertRepository<'c> {
+    /// Create a new [`PgUserLoginAlertRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserLoginAlertRow {
+    user_login_alert_id: Uuid,
+    user_id: Uuid,
+    user_session_id: Uuid,
+    user_agent: Option<String>,
+    ip_address: Option<IpAddr>,
+    ticket: String,
+    created_at: DateTime<Utc>,
+    reported_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserLoginAlertRow> for UserLoginAlert {
+    fn from(row: UserLoginAlertRow) -> Self {
+        UserLoginAlert {
+            id: row.user_login_alert_id.into(),
+            user_id: row.user_id.into(),
+            user_session_id: row.user_session_id.into(),
+            user_agent: row.user_agent.map(UserAgent::parse),
+            ip_address: row.ip_address,
+            ticket: row.ticket,
+            created_at: row.created_at,
+            reported_at: row.reported_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> UserLoginAlertRepository for PgUserLoginAlertRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.check_sighting",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user_login_sighting.user_agent = user_agent,
+            user_login_sighting.ip_network = %ip_network,
+        ),
+        err,
+    )]
+    async fn check_sighting(
+        &mut self,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                SELECT
+                      EXISTS(
+                        SELECT 1 FROM user_login_sightings
+                        WHERE user_id = $1
+                      ) AS "has_sightings!"
+                    , EXISTS(
+                        SELECT 1 FROM user_login_sightings
+                        WHERE user_id = $1 AND user_agent = $2
+                      ) AS "known_device!"
+                    , EXISTS(
+                        SELECT 1 FROM user_login_sightings
+                        WHERE user_id = $1 AND ip_network = $3
+                      ) AS "known_network!"
+            "#,
+            Uuid::from(user.id),
+            user_agent,
+            ip_network as IpAddr,
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        let sighting = if !res.has_sightings {
+            LoginSighting::First
+        } else if res.known_device && res.known_network {
+            LoginSighting::Known
+        } else {
+            LoginSighting::Unknown
+        };
+
+        Ok(sighting)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.record_sighting",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user_login_sighting.id,
+            user_login_sighting.user_agent = user_agent,
+            user_login_sighting.ip_network = %ip_network,
+        ),
+        err,
+    )]
+    async fn record_sighting(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error> {
+        let sighting = self.check_sighting(user, user_agent, ip_network).await?;
+
+        let now = clock.now();
+        let id = Ulid::from_datetime_with_source(now.into(), rng);
+        tracing::Span::current().record("user_login_sighting.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_login_sightings (
+                      user_login_sighting_id
+                    , user_id
+                    , user_agent
+                    , ip_network
+                    , created_at
+                    , last_seen_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $5)
+                ON CONFLICT (user_id, user_agent, ip_network)
+                DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            user_agent,
+            ip_network as IpAddr,
+            now,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(sighting)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.last_sighting",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn last_sighting(
+        &mut self,
+        user: &User,
+    ) -> Result<Option<(IpAddr, DateTime<Utc>)>, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                SELECT
+                      ip_network as "ip_network: IpAddr"
+                    , last_seen_at
+                FROM user_login_sightings
+                WHERE user_id = $1
+                ORDER BY last_seen_at DESC
+                LIMIT 1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(|res| (res.ip_network, res.last_seen_at)))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_login_alert.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginAlert>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserLoginAlertRow,
+            r#"
+                SELECT
+                      user_login_alert_id
+                    , user_id
+                    , user_session_id
+                    , user_agent
+                    , ip_address as "ip_address: IpAddr"
+                    , ticket
+                    , created_at
+                    , reported_at
+                FROM user_login_alerts
+                WHERE user_login_alert_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.find_by_ticket",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn find_by_ticket(
+        &mut self,
+        ticket: &str,
+    ) -> Result<Option<UserLoginAlert>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserLoginAlertRow,
+            r#"
+                SELECT
+                      user_login_alert_id
+                    , user_id
+                    , user_session_id
+                    , user_agent
+                    , ip_address as "ip_address: IpAddr"
+                    , ticket
+                    , created_at
+                    , reported_at
+                FROM user_login_alerts
+                WHERE ticket = $1
+            "#,
+            ticket,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.add",
+        skip_all,
+        fields(
+            db.statement,
+            user_login_alert.id,
+            %browser_session.id,
+            user.id = %browser_session.user.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        browser_session: &BrowserSession,
+        ip_address: Option<IpAddr>,
+        ticket: String,
+    ) -> Result<UserLoginAlert, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_login_alert.id", tracing::field::display(id));
+
+        let user_agent = browser_session.user_agent.clone();
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_login_alerts (
+                      user_login_alert_id
+                    , user_id
+                    , user_session_id
+                    , user_agent
+                    , ip_address
+                    , ticket
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            Uuid::from(id),
+            Uuid::from(browser_session.user.id),
+            Uuid::from(browser_session.id),
+            user_agent.as_deref(),
+            ip_address as Option<IpAddr>,
+            &ticket,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserLoginAlert {
+            id,
+            user_id: browser_session.user.id,
+            user_session_id: browser_session.id,
+            user_agent,
+            ip_address,
+            ticket,
+            created_at,
+            reported_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.mark_as_reported",
+        skip_all,
+        fields(
+            db.statement,
+            %alert.id,
+        ),
+        err,
+    )]
+    async fn mark_as_reported(
+        &mut self,
+        clock: &dyn Clock,
+        mut alert: UserLoginAlert,
+    ) -> Result<UserLoginAlert, Self::Error> {
+        // This should have been checked by the caller
+        if alert.reported_at.is_some() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let reported_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_alerts
+                SET reported_at = $1
+                WHERE user_login_alert_id = $2
+            "#,
+            reported_at,
+            Uuid::from(alert.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        alert.reported_at = Some(reported_at);
+
+        Ok(alert)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.forget_ip_addresses",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_alerts
+                SET ip_address = NULL
+                WHERE ip_address IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.forget_user_agents",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_alerts
+                SET user_agent = NULL
+                WHERE user_agent IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_alert.forget_sightings",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_sightings(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM user_login_sightings
+                WHERE last_seen_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
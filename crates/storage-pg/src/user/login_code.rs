@@ -0,0 +1,357 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{Password, UserAgent, UserEmail, UserLoginCode};
+use mas_storage::{user::UserLoginCodeRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+
+/// An implementation of [`UserLoginCodeRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserLoginCodeRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserLoginCodeRepository<'c> {
+    /// Create a new [`PgUserLoginCodeRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserLoginCodeRow {
+    user_login_code_id: Uuid,
+    user_id: Uuid,
+    user_email_id: Uuid,
+    user_password_id: Uuid,
+    user_agent: Option<String>,
+    ip_address: Option<IpAddr>,
+    code: String,
+    attempts: i32,
+    sent_count: i32,
+    created_at: DateTime<Utc>,
+    last_sent_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<UserLoginCodeRow> for UserLoginCode {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(row: UserLoginCodeRow) -> Result<Self, Self::Error> {
+        let id = Ulid::from(row.user_login_code_id);
+        let attempts = row.attempts.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_codes")
+                .column("attempts")
+                .row(id)
+                .source(e)
+        })?;
+        let sent_count = row.sent_count.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_codes")
+                .column("sent_count")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(UserLoginCode {
+            id,
+            user_id: row.user_id.into(),
+            user_email_id: row.user_email_id.into(),
+            user_password_id: row.user_password_id.into(),
+            user_agent: row.user_agent.map(UserAgent::parse),
+            ip_address: row.ip_address,
+            code: row.code,
+            attempts,
+            sent_count,
+            created_at: row.created_at,
+            last_sent_at: row.last_sent_at,
+            expires_at: row.expires_at,
+            consumed_at: row.consumed_at,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> UserLoginCodeRepository for PgUserLoginCodeRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_login_code.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_login_code.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginCode>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserLoginCodeRow,
+            r#"
+                SELECT
+                      user_login_code_id
+                    , user_id
+                    , user_email_id
+                    , user_password_id
+                    , user_agent
+                    , ip_address as "ip_address: IpAddr"
+                    , code
+                    , attempts
+                    , sent_count
+                    , created_at
+                    , last_sent_at
+                    , expires_at
+                    , consumed_at
+                FROM user_login_codes
+                WHERE user_login_code_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_code.add",
+        skip_all,
+        fields(
+            db.statement,
+            user_login_code.id,
+            %user_email.id,
+            user.id = %user_email.user_id,
+            %user_password.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_email: &UserEmail,
+        user_password: &Password,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        code: String,
+    ) -> Result<UserLoginCode, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_login_code.id", tracing::field::display(id));
+
+        let expires_at = created_at + UserLoginCode::VALIDITY;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_login_codes (
+                      user_login_code_id
+                    , user_id
+                    , user_email_id
+                    , user_password_id
+                    , user_agent
+                    , ip_address
+                    , code
+                    , created_at
+                    , last_sent_at
+                    , expires_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, $9)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_email.user_id),
+            Uuid::from(user_email.id),
+            Uuid::from(user_password.id),
+            user_agent.as_deref(),
+            ip_address as Option<IpAddr>,
+            &code,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserLoginCode {
+            id,
+            user_id: user_email.user_id,
+            user_email_id: user_email.id,
+            user_password_id: user_password.id,
+            user_agent,
+            ip_address,
+            code,
+            attempts: 0,
+            sent_count: 1,
+            created_at,
+            last_sent_at: created_at,
+            expires_at,
+            consumed_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_code.record_attempt",
+        skip_all,
+        fields(
+            db.statement,
+            %user_login_code.id,
+        ),
+        err,
+    )]
+    async fn record_attempt(
+        &mut self,
+        mut user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_codes
+                SET attempts = attempts + 1
+                WHERE user_login_code_id = $1
+            "#,
+            Uuid::from(user_login_code.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_login_code.attempts += 1;
+
+        Ok(user_login_code)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_code.record_resend",
+        skip_all,
+        fields(
+            db.statement,
+            %user_login_code.id,
+        ),
+        err,
+    )]
+    async fn record_resend(
+        &mut self,
+        clock: &dyn Clock,
+        mut user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error> {
+        let last_sent_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_codes
+                SET sent_count = sent_count + 1
+                  , last_sent_at = $2
+                WHERE user_login_code_id = $1
+            "#,
+            Uuid::from(user_login_code.id),
+            last_sent_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_login_code.sent_count += 1;
+        user_login_code.last_sent_at = last_sent_at;
+
+        Ok(user_login_code)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_code.consume",
+        skip_all,
+        fields(
+            db.statement,
+            %user_login_code.id,
+        ),
+        err,
+    )]
+    async fn consume(
+        &mut self,
+        clock: &dyn Clock,
+        mut user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error> {
+        // This should have been checked by the caller
+        if user_login_code.consumed_at.is_some() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let consumed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_codes
+                SET consumed_at = $2
+                WHERE user_login_code_id = $1
+                  AND consumed_at IS NULL
+            "#,
+            Uuid::from(user_login_code.id),
+            consumed_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_login_code.consumed_at = Some(consumed_at);
+
+        Ok(user_login_code)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_code.forget_ip_addresses",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_codes
+                SET ip_address = NULL
+                WHERE ip_address IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
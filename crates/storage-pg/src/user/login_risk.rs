@@ -0,0 +1,243 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{LoginRiskAssessment, LoginRiskDecision, LoginRiskSignal, User, UserAgent};
+use mas_storage::{user::UserLoginRiskRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+
+/// An implementation of [`UserLoginRiskRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserLoginRiskRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserLoginRiskRepository<'c> {
+    /// Create a new [`PgUserLoginRiskRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserLoginRiskAssessmentRow {
+    user_login_risk_assessment_id: Uuid,
+    user_id: Uuid,
+    user_agent: Option<String>,
+    ip_address: Option<IpAddr>,
+    request_id: Option<String>,
+    score: i32,
+    signals: Vec<String>,
+    decision: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<UserLoginRiskAssessmentRow> for LoginRiskAssessment {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(row: UserLoginRiskAssessmentRow) -> Result<Self, Self::Error> {
+        let id = Ulid::from(row.user_login_risk_assessment_id);
+        let score = row.score.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_risk_assessments")
+                .column("score")
+                .row(id)
+                .source(e)
+        })?;
+        let signals = row
+            .signals
+            .iter()
+            .map(|signal| signal.parse())
+            .collect::<Result<Vec<LoginRiskSignal>, _>>()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("user_login_risk_assessments")
+                    .column("signals")
+                    .row(id)
+                    .source(e)
+            })?;
+        let decision = row.decision.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_risk_assessments")
+                .column("decision")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(LoginRiskAssessment {
+            id,
+            user_id: row.user_id.into(),
+            user_agent: row.user_agent.map(UserAgent::parse),
+            ip_address: row.ip_address,
+            request_id: row.request_id,
+            score,
+            signals,
+            decision,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[async_trait]
+impl<'c> UserLoginRiskRepository for PgUserLoginRiskRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_login_risk.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_login_risk_assessment.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<LoginRiskAssessment>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserLoginRiskAssessmentRow,
+            r#"
+                SELECT
+                      user_login_risk_assessment_id
+                    , user_id
+                    , user_agent
+                    , ip_address as "ip_address: IpAddr"
+                    , request_id
+                    , score
+                    , signals
+                    , decision
+                    , created_at
+                FROM user_login_risk_assessments
+                WHERE user_login_risk_assessment_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_risk.add",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user_login_risk_assessment.id,
+            user_login_risk_assessment.score = score,
+            user_login_risk_assessment.decision = %decision,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        request_id: Option<String>,
+        score: u32,
+        signals: Vec<LoginRiskSignal>,
+        decision: LoginRiskDecision,
+    ) -> Result<LoginRiskAssessment, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current()
+            .record("user_login_risk_assessment.id", tracing::field::display(id));
+
+        let db_score = i32::try_from(score).map_err(DatabaseError::to_invalid_operation)?;
+        let db_signals: Vec<String> = signals.iter().map(ToString::to_string).collect();
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_login_risk_assessments (
+                      user_login_risk_assessment_id
+                    , user_id
+                    , user_agent
+                    , ip_address
+                    , request_id
+                    , score
+                    , signals
+                    , decision
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            user_agent.as_deref(),
+            ip_address as Option<IpAddr>,
+            request_id.as_deref(),
+            db_score,
+            &db_signals,
+            decision.as_str(),
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(LoginRiskAssessment {
+            id,
+            user_id: user.id,
+            user_agent,
+            ip_address,
+            request_id,
+            score,
+            signals,
+            decision,
+            created_at,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_risk.forget_ip_addresses",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_login_risk_assessments
+                SET ip_address = NULL
+                WHERE ip_address IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{UserAgent, UserEmail, UserMagicLink, UserMagicLinkSession};
+use mas_storage::{user::UserMagicLinkRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, ExecuteExt};
+
+/// An implementation of [`UserMagicLinkRepository`] for a PostgreSQL connection
+pub struct PgUserMagicLinkRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserMagicLinkRepository<'c> {
+    /// Create a new [`PgUserMagicLinkRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserMagicLinkSessionRow {
+    user_magic_link_session_id: Uuid,
+    email: String,
+    user_agent: String,
+    ip_address: Option<IpAddr>,
+    locale: String,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserMagicLinkSessionRow> for UserMagicLinkSession {
+    fn from(row: UserMagicLinkSessionRow) -> Self {
+        UserMagicLinkSession {
+            id: row.user_magic_link_session_id.into(),
+            email: row.email,
+            user_agent: UserAgent::parse(row.user_agent),
+            ip_address: row.ip_address,
+            locale: row.locale,
+            created_at: row.created_at,
+            consumed_at: row.consumed_at,
+        }
+    }
+}
+
+struct UserMagicLinkRow {
+    user_magic_link_id: Uuid,
+    user_magic_link_session_id: Uuid,
+    user_email_id: Uuid,
+    ticket: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl From<UserMagicLinkRow> for UserMagicLink {
+    fn from(row: UserMagicLinkRow) -> Self {
+        Self {
+            id: row.user_magic_link_id.into(),
+            user_magic_link_session_id: row.user_magic_link_session_id.into(),
+            user_email_id: row.user_email_id.into(),
+            ticket: row.ticket,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> UserMagicLinkRepository for PgUserMagicLinkRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.lookup_session",
+        skip_all,
+        fields(
+            db.statement,
+            user_magic_link_session.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup_session(
+        &mut self,
+        id: Ulid,
+    ) -> Result<Option<UserMagicLinkSession>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserMagicLinkSessionRow,
+            r#"
+                SELECT
+                      user_magic_link_session_id
+                    , email
+                    , user_agent
+                    , ip_address as "ip_address: IpAddr"
+                    , locale
+                    , created_at
+                    , consumed_at
+                FROM user_magic_link_sessions
+                WHERE user_magic_link_session_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.add_session",
+        skip_all,
+        fields(
+            db.statement,
+            user_magic_link_session.id,
+            user_magic_link_session.email = email,
+            user_magic_link_session.user_agent = &*user_agent,
+            user_magic_link_session.ip_address = ip_address.map(|ip| ip.to_string()),
+        )
+    )]
+    async fn add_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        email: String,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserMagicLinkSession, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_magic_link_session.id", tracing::field::display(id));
+        sqlx::query!(
+            r#"
+                INSERT INTO user_magic_link_sessions (
+                      user_magic_link_session_id
+                    , email
+                    , user_agent
+                    , ip_address
+                    , locale
+                    , created_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            &email,
+            &*user_agent,
+            ip_address as Option<IpAddr>,
+            &locale,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let user_magic_link_session = UserMagicLinkSession {
+            id,
+            email,
+            user_agent,
+            ip_address,
+            locale,
+            created_at,
+            consumed_at: None,
+        };
+
+        Ok(user_magic_link_session)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.find_link",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn find_link(&mut self, ticket: &str) -> Result<Option<UserMagicLink>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserMagicLinkRow,
+            r#"
+                SELECT
+                      user_magic_link_id
+                    , user_magic_link_session_id
+                    , user_email_id
+                    , ticket
+                    , created_at
+                    , expires_at
+                FROM user_magic_links
+                WHERE ticket = $1
+            "#,
+            ticket,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.add_link",
+        skip_all,
+        fields(
+            db.statement,
+            user_magic_link.id,
+            %user_magic_link_session.id,
+            %user_email.id,
+        )
+    )]
+    async fn add_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_magic_link_session: &UserMagicLinkSession,
+        user_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserMagicLink, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_magic_link.id", tracing::field::display(id));
+
+        let expires_at = created_at + UserMagicLink::VALIDITY;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_magic_links (
+                      user_magic_link_id
+                    , user_magic_link_session_id
+                    , user_email_id
+                    , ticket
+                    , created_at
+                    , expires_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_magic_link_session.id),
+            Uuid::from(user_email.id),
+            &ticket,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let link = UserMagicLink {
+            id,
+            user_magic_link_session_id: user_magic_link_session.id,
+            user_email_id: user_email.id,
+            ticket,
+            created_at,
+            expires_at,
+        };
+
+        Ok(link)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.consume_link",
+        skip_all,
+        fields(
+            db.statement,
+            %user_magic_link.id,
+            user_email.id = %user_magic_link.user_email_id,
+            %user_magic_link_session.id,
+            %user_magic_link_session.email,
+        ),
+        err,
+    )]
+    async fn consume_link(
+        &mut self,
+        clock: &dyn Clock,
+        user_magic_link: UserMagicLink,
+        mut user_magic_link_session: UserMagicLinkSession,
+    ) -> Result<UserMagicLinkSession, Self::Error> {
+        // We don't really use the link, we just want to make sure we drop it
+        let _ = user_magic_link;
+
+        // This should have been checked by the caller
+        if user_magic_link_session.consumed_at.is_some() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let consumed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_magic_link_sessions
+                SET consumed_at = $1
+                WHERE user_magic_link_session_id = $2
+            "#,
+            consumed_at,
+            Uuid::from(user_magic_link_session.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        user_magic_link_session.consumed_at = Some(consumed_at);
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(user_magic_link_session)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_magic_link.forget_ip_addresses",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_magic_link_sessions
+                SET ip_address = NULL
+                WHERE ip_address IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+}
@@ -18,16 +18,28 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::User;
-use mas_storage::{user::UserRepository, Clock};
+use mas_storage::{
+    user::{UserFilter, UserRepository},
+    Clock, Page, Pagination,
+};
 use rand::RngCore;
+use sea_query::{enum_def, Expr, LikeExpr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{tracing::ExecuteExt, DatabaseError};
+use crate::{iden::Users, pagination::QueryBuilderExt, tracing::ExecuteExt, DatabaseError};
 
 mod email;
+mod email_change;
+mod export;
+mod login_alert;
+mod login_code;
+mod login_risk;
+mod magic_link;
 mod password;
+mod phone;
 mod recovery;
 mod session;
 mod terms;
@@ -36,9 +48,12 @@ mod terms;
 mod tests;
 
 pub use self::{
-    email::PgUserEmailRepository, password::PgUserPasswordRepository,
-    recovery::PgUserRecoveryRepository, session::PgBrowserSessionRepository,
-    terms::PgUserTermsRepository,
+    email::PgUserEmailRepository, email_change::PgUserEmailChangeRepository,
+    export::PgUserExportRepository, login_alert::PgUserLoginAlertRepository,
+    login_code::PgUserLoginCodeRepository, login_risk::PgUserLoginRiskRepository,
+    magic_link::PgUserMagicLinkRepository, password::PgUserPasswordRepository,
+    phone::PgUserPhoneRepository, recovery::PgUserRecoveryRepository,
+    session::PgBrowserSessionRepository, terms::PgUserTermsRepository,
 };
 
 /// An implementation of [`UserRepository`] for a PostgreSQL connection
@@ -53,14 +68,21 @@ impl<'c> PgUserRepository<'c> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[enum_def]
 struct UserLookup {
     user_id: Uuid,
     username: String,
     primary_user_email_id: Option<Uuid>,
     created_at: DateTime<Utc>,
     locked_at: Option<DateTime<Utc>>,
+    shadow_banned_at: Option<DateTime<Utc>>,
     can_request_admin: bool,
+    is_bot: bool,
+    is_guest: bool,
+    display_name: Option<String>,
+    avatar_url: Option<String>,
+    groups: Vec<String>,
 }
 
 impl From<UserLookup> for User {
@@ -73,7 +95,13 @@ impl From<UserLookup> for User {
             primary_user_email_id: value.primary_user_email_id.map(Into::into),
             created_at: value.created_at,
             locked_at: value.locked_at,
+            shadow_banned_at: value.shadow_banned_at,
             can_request_admin: value.can_request_admin,
+            is_bot: value.is_bot,
+            is_guest: value.is_guest,
+            display_name: value.display_name,
+            avatar_url: value.avatar_url,
+            groups: value.groups,
         }
     }
 }
@@ -100,7 +128,13 @@ impl<'c> UserRepository for PgUserRepository<'c> {
                      , primary_user_email_id
                      , created_at
                      , locked_at
+                     , shadow_banned_at
                      , can_request_admin
+                     , is_bot
+                     , is_guest
+                     , display_name
+                     , avatar_url
+                     , groups
                 FROM users
                 WHERE user_id = $1
             "#,
@@ -133,7 +167,13 @@ impl<'c> UserRepository for PgUserRepository<'c> {
                      , primary_user_email_id
                      , created_at
                      , locked_at
+                     , shadow_banned_at
                      , can_request_admin
+                     , is_bot
+                     , is_guest
+                     , display_name
+                     , avatar_url
+                     , groups
                 FROM users
                 WHERE username = $1
             "#,
@@ -193,7 +233,13 @@ impl<'c> UserRepository for PgUserRepository<'c> {
             primary_user_email_id: None,
             created_at,
             locked_at: None,
+            shadow_banned_at: None,
             can_request_admin: false,
+            is_bot: false,
+            is_guest: false,
+            display_name: None,
+            avatar_url: None,
+            groups: Vec::new(),
         })
     }
 
@@ -290,6 +336,74 @@ impl<'c> UserRepository for PgUserRepository<'c> {
         Ok(user)
     }
 
+    #[tracing::instrument(
+        name = "db.user.shadow_ban",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn shadow_ban(&mut self, clock: &dyn Clock, mut user: User) -> Result<User, Self::Error> {
+        if user.shadow_banned_at.is_some() {
+            return Ok(user);
+        }
+
+        let shadow_banned_at = clock.now();
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET shadow_banned_at = $1
+                WHERE user_id = $2
+            "#,
+            shadow_banned_at,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.shadow_banned_at = Some(shadow_banned_at);
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.lift_shadow_ban",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn lift_shadow_ban(&mut self, mut user: User) -> Result<User, Self::Error> {
+        if user.shadow_banned_at.is_none() {
+            return Ok(user);
+        }
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET shadow_banned_at = NULL
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.shadow_banned_at = None;
+
+        Ok(user)
+    }
+
     #[tracing::instrument(
         name = "db.user.set_can_request_admin",
         skip_all,
@@ -324,4 +438,302 @@ impl<'c> UserRepository for PgUserRepository<'c> {
 
         Ok(user)
     }
+
+    #[tracing::instrument(
+        name = "db.user.set_bot",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user.is_bot = is_bot,
+        ),
+        err,
+    )]
+    async fn set_bot(&mut self, mut user: User, is_bot: bool) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET is_bot = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            is_bot,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.is_bot = is_bot;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_guest",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user.is_guest = is_guest,
+        ),
+        err,
+    )]
+    async fn set_guest(&mut self, mut user: User, is_guest: bool) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET is_guest = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            is_guest,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.is_guest = is_guest;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_display_name",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_display_name(
+        &mut self,
+        mut user: User,
+        display_name: Option<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET display_name = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            display_name.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.display_name = display_name;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_avatar_url",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_avatar_url(
+        &mut self,
+        mut user: User,
+        avatar_url: Option<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET avatar_url = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            avatar_url.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.avatar_url = avatar_url;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_groups",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_groups(
+        &mut self,
+        mut user: User,
+        groups: Vec<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET groups = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            &groups,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.groups = groups;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.list",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: UserFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<User>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((Users::Table, Users::UserId)),
+                UserLookupIden::UserId,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::Username)),
+                UserLookupIden::Username,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::PrimaryUserEmailId)),
+                UserLookupIden::PrimaryUserEmailId,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::CreatedAt)),
+                UserLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::LockedAt)),
+                UserLookupIden::LockedAt,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::ShadowBannedAt)),
+                UserLookupIden::ShadowBannedAt,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::CanRequestAdmin)),
+                UserLookupIden::CanRequestAdmin,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::IsBot)),
+                UserLookupIden::IsBot,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::IsGuest)),
+                UserLookupIden::IsGuest,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::DisplayName)),
+                UserLookupIden::DisplayName,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::AvatarUrl)),
+                UserLookupIden::AvatarUrl,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::Groups)),
+                UserLookupIden::Groups,
+            )
+            .from(Users::Table)
+            .and_where_option(filter.search().map(|search| {
+                Expr::col((Users::Table, Users::Username)).like(username_pattern(search))
+            }))
+            .and_where_option(filter.state().map(|state| {
+                if state.is_locked() {
+                    Expr::col((Users::Table, Users::LockedAt)).is_not_null()
+                } else {
+                    Expr::col((Users::Table, Users::LockedAt)).is_null()
+                }
+            }))
+            .generate_pagination((Users::Table, Users::UserId), pagination)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<UserLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).map(User::from);
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.count",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: UserFilter<'_>) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((Users::Table, Users::UserId)).count())
+            .from(Users::Table)
+            .and_where_option(filter.search().map(|search| {
+                Expr::col((Users::Table, Users::Username)).like(username_pattern(search))
+            }))
+            .and_where_option(filter.state().map(|state| {
+                if state.is_locked() {
+                    Expr::col((Users::Table, Users::LockedAt)).is_not_null()
+                } else {
+                    Expr::col((Users::Table, Users::LockedAt)).is_null()
+                }
+            }))
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+}
+
+/// Build a `LIKE` pattern matching usernames which contain the given string
+fn username_pattern(search: &str) -> LikeExpr {
+    let mut pattern = String::with_capacity(search.len() + 2);
+    pattern.push('%');
+    for c in search.to_lowercase().chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    LikeExpr::new(pattern).escape('\\')
 }
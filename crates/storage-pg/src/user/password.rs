@@ -42,6 +42,7 @@ struct UserPasswordLookup {
     version: i32,
     upgraded_from_id: Option<Uuid>,
     created_at: DateTime<Utc>,
+    invalidated_at: Option<DateTime<Utc>>,
 }
 
 #[async_trait]
@@ -67,6 +68,7 @@ impl<'c> UserPasswordRepository for PgUserPasswordRepository<'c> {
                      , up.version
                      , up.upgraded_from_id
                      , up.created_at
+                     , up.invalidated_at
                 FROM user_passwords up
                 WHERE up.user_id = $1
                 ORDER BY up.created_at DESC
@@ -78,7 +80,10 @@ impl<'c> UserPasswordRepository for PgUserPasswordRepository<'c> {
         .fetch_optional(&mut *self.conn)
         .await?;
 
-        let Some(res) = res else { return Ok(None) };
+        // An invalidated password is only replaced when a new one is set
+        let Some(res) = res.filter(|res| res.invalidated_at.is_none()) else {
+            return Ok(None);
+        };
 
         let id = Ulid::from(res.user_password_id);
 
@@ -154,4 +159,32 @@ impl<'c> UserPasswordRepository for PgUserPasswordRepository<'c> {
             created_at,
         })
     }
+
+    #[tracing::instrument(
+        name = "db.user_password.invalidate",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            %user.username,
+        ),
+        err,
+    )]
+    async fn invalidate(&mut self, clock: &dyn Clock, user: &User) -> Result<(), Self::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE user_passwords
+                SET invalidated_at = $2
+                WHERE user_id = $1
+                  AND invalidated_at IS NULL
+            "#,
+            Uuid::from(user.id),
+            clock.now(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(())
+    }
 }
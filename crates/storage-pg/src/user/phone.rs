@@ -0,0 +1,429 @@
+// Copyright 2022-2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{User, UserPhone, UserPhoneVerification, UserPhoneVerificationState};
+use mas_storage::{user::UserPhoneRepository, Clock};
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{tracing::ExecuteExt, DatabaseError};
+
+/// An implementation of [`UserPhoneRepository`] for a PostgreSQL connection
+pub struct PgUserPhoneRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserPhoneRepository<'c> {
+    /// Create a new [`PgUserPhoneRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserPhoneLookup {
+    user_phone_id: Uuid,
+    user_id: Uuid,
+    phone_number: String,
+    created_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserPhoneLookup> for UserPhone {
+    fn from(e: UserPhoneLookup) -> UserPhone {
+        UserPhone {
+            id: e.user_phone_id.into(),
+            user_id: e.user_id.into(),
+            phone_number: e.phone_number,
+            created_at: e.created_at,
+            confirmed_at: e.confirmed_at,
+        }
+    }
+}
+
+struct UserPhoneConfirmationCodeLookup {
+    user_phone_confirmation_code_id: Uuid,
+    user_phone_id: Uuid,
+    code: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+impl UserPhoneConfirmationCodeLookup {
+    fn into_verification(self, clock: &dyn Clock) -> UserPhoneVerification {
+        let now = clock.now();
+        let state = if let Some(when) = self.consumed_at {
+            UserPhoneVerificationState::AlreadyUsed { when }
+        } else if self.expires_at < now {
+            UserPhoneVerificationState::Expired {
+                when: self.expires_at,
+            }
+        } else {
+            UserPhoneVerificationState::Valid
+        };
+
+        UserPhoneVerification {
+            id: self.user_phone_confirmation_code_id.into(),
+            user_phone_id: self.user_phone_id.into(),
+            code: self.code,
+            state,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> UserPhoneRepository for PgUserPhoneRepository<'c> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_phone.lookup",
+        skip_all,
+        fields(
+            db.statement,
+            user_phone.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserPhone>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserPhoneLookup,
+            r#"
+                SELECT user_phone_id
+                     , user_id
+                     , phone_number
+                     , created_at
+                     , confirmed_at
+                FROM user_phones
+
+                WHERE user_phone_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.find",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user_phone.phone_number = phone_number,
+        ),
+        err,
+    )]
+    async fn find(
+        &mut self,
+        user: &User,
+        phone_number: &str,
+    ) -> Result<Option<UserPhone>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserPhoneLookup,
+            r#"
+                SELECT user_phone_id
+                     , user_id
+                     , phone_number
+                     , created_at
+                     , confirmed_at
+                FROM user_phones
+
+                WHERE user_id = $1 AND phone_number = $2
+            "#,
+            Uuid::from(user.id),
+            phone_number,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.all",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn all(&mut self, user: &User) -> Result<Vec<UserPhone>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserPhoneLookup,
+            r#"
+                SELECT user_phone_id
+                     , user_id
+                     , phone_number
+                     , created_at
+                     , confirmed_at
+                FROM user_phones
+
+                WHERE user_id = $1
+
+                ORDER BY phone_number ASC
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.add",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            user_phone.id,
+            user_phone.phone_number = phone_number,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        phone_number: String,
+    ) -> Result<UserPhone, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_phone.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_phones (user_phone_id, user_id, phone_number, created_at)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            &phone_number,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserPhone {
+            id,
+            user_id: user.id,
+            phone_number,
+            created_at,
+            confirmed_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.remove",
+        skip_all,
+        fields(
+            db.statement,
+            user.id = %user_phone.user_id,
+            %user_phone.id,
+            %user_phone.phone_number,
+        ),
+        err,
+    )]
+    async fn remove(&mut self, user_phone: UserPhone) -> Result<(), Self::Error> {
+        // The verification codes are removed through the ON DELETE CASCADE
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM user_phones
+                WHERE user_phone_id = $1
+            "#,
+            Uuid::from(user_phone.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.mark_as_verified",
+        skip_all,
+        fields(
+            db.statement,
+            %user_phone.id,
+        ),
+        err,
+    )]
+    async fn mark_as_verified(
+        &mut self,
+        clock: &dyn Clock,
+        mut user_phone: UserPhone,
+    ) -> Result<UserPhone, Self::Error> {
+        let confirmed_at = clock.now();
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_phones
+                SET confirmed_at = $2
+                WHERE user_phone_id = $1
+            "#,
+            Uuid::from(user_phone.id),
+            confirmed_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_phone.confirmed_at = Some(confirmed_at);
+        Ok(user_phone)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.add_verification_code",
+        skip_all,
+        fields(
+            db.statement,
+            %user_phone.id,
+            user_phone_verification.id,
+        ),
+        err,
+    )]
+    async fn add_verification_code(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        max_age: chrono::Duration,
+        code: String,
+    ) -> Result<UserPhoneVerification, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_phone_verification.id", tracing::field::display(id));
+        let expires_at = created_at + max_age;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_phone_confirmation_codes
+                  (user_phone_confirmation_code_id, user_phone_id, code, created_at, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_phone.id),
+            code,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserPhoneVerification {
+            id,
+            user_phone_id: user_phone.id,
+            code,
+            created_at,
+            state: UserPhoneVerificationState::Valid,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.find_verification_code",
+        skip_all,
+        fields(
+            db.statement,
+            %user_phone.id,
+            user.id = %user_phone.user_id,
+        ),
+        err,
+    )]
+    async fn find_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        code: &str,
+    ) -> Result<Option<UserPhoneVerification>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserPhoneConfirmationCodeLookup,
+            r#"
+                SELECT user_phone_confirmation_code_id
+                     , user_phone_id
+                     , code
+                     , created_at
+                     , expires_at
+                     , consumed_at
+                FROM user_phone_confirmation_codes
+                WHERE code = $1
+                  AND user_phone_id = $2
+            "#,
+            code,
+            Uuid::from(user_phone.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(|res| res.into_verification(clock)))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_phone.consume_verification_code",
+        skip_all,
+        fields(
+            db.statement,
+            %verification.id,
+            user_phone.id = %verification.user_phone_id,
+        ),
+        err,
+    )]
+    async fn consume_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        mut verification: UserPhoneVerification,
+    ) -> Result<UserPhoneVerification, Self::Error> {
+        if !verification.is_valid() {
+            return Err(DatabaseError::invalid_operation());
+        }
+
+        let consumed_at = clock.now();
+
+        sqlx::query!(
+            r#"
+                UPDATE user_phone_confirmation_codes
+                SET consumed_at = $2
+                WHERE user_phone_confirmation_code_id = $1
+            "#,
+            Uuid::from(verification.id),
+            consumed_at
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        verification.state = UserPhoneVerificationState::AlreadyUsed { when: consumed_at };
+
+        Ok(verification)
+    }
+}
@@ -334,4 +334,34 @@ impl<'c> UserRecoveryRepository for PgUserRecoveryRepository<'c> {
 
         Ok(user_recovery_session)
     }
+
+    #[tracing::instrument(
+        name = "db.user_recovery.forget_ip_addresses",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_recovery_sessions
+                SET ip_address = NULL
+                WHERE ip_address IS NOT NULL
+                  AND created_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
 }
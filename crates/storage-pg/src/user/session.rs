@@ -15,10 +15,10 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     Authentication, AuthenticationMethod, BrowserSession, Password,
-    UpstreamOAuthAuthorizationSession, User, UserAgent,
+    UpstreamOAuthAuthorizationSession, User, UserAgent, UserMagicLink,
 };
 use mas_storage::{user::BrowserSessionRepository, Clock, Page, Pagination};
 use rand::RngCore;
@@ -59,12 +59,20 @@ struct SessionLookup {
     user_session_user_agent: Option<String>,
     user_session_last_active_at: Option<DateTime<Utc>>,
     user_session_last_active_ip: Option<IpAddr>,
+    user_session_impersonated_by: Option<Uuid>,
+    user_session_expires_at: Option<DateTime<Utc>>,
     user_id: Uuid,
     user_username: String,
     user_primary_user_email_id: Option<Uuid>,
     user_created_at: DateTime<Utc>,
     user_locked_at: Option<DateTime<Utc>>,
+    user_shadow_banned_at: Option<DateTime<Utc>>,
     user_can_request_admin: bool,
+    user_is_bot: bool,
+    user_is_guest: bool,
+    user_display_name: Option<String>,
+    user_avatar_url: Option<String>,
+    user_groups: Vec<String>,
 }
 
 impl TryFrom<SessionLookup> for BrowserSession {
@@ -79,7 +87,13 @@ impl TryFrom<SessionLookup> for BrowserSession {
             primary_user_email_id: value.user_primary_user_email_id.map(Into::into),
             created_at: value.user_created_at,
             locked_at: value.user_locked_at,
+            shadow_banned_at: value.user_shadow_banned_at,
             can_request_admin: value.user_can_request_admin,
+            is_bot: value.user_is_bot,
+            is_guest: value.user_is_guest,
+            display_name: value.user_display_name,
+            avatar_url: value.user_avatar_url,
+            groups: value.user_groups,
         };
 
         Ok(BrowserSession {
@@ -90,6 +104,8 @@ impl TryFrom<SessionLookup> for BrowserSession {
             user_agent: value.user_session_user_agent.map(UserAgent::parse),
             last_active_at: value.user_session_last_active_at,
             last_active_ip: value.user_session_last_active_ip,
+            impersonated_by: value.user_session_impersonated_by.map(Ulid::from),
+            expires_at: value.user_session_expires_at,
         })
     }
 }
@@ -99,6 +115,9 @@ struct AuthenticationLookup {
     created_at: DateTime<Utc>,
     user_password_id: Option<Uuid>,
     upstream_oauth_authorization_session_id: Option<Uuid>,
+    user_magic_link_id: Option<Uuid>,
+    kerberos_principal: Option<String>,
+    client_certificate_subject: Option<String>,
 }
 
 impl TryFrom<AuthenticationLookup> for Authentication {
@@ -111,12 +130,28 @@ impl TryFrom<AuthenticationLookup> for Authentication {
             value
                 .upstream_oauth_authorization_session_id
                 .map(Into::into),
+            value.user_magic_link_id.map(Into::into),
+            value.kerberos_principal,
+            value.client_certificate_subject,
         ) {
-            (Some(user_password_id), None) => AuthenticationMethod::Password { user_password_id },
-            (None, Some(upstream_oauth2_session_id)) => AuthenticationMethod::UpstreamOAuth2 {
-                upstream_oauth2_session_id,
-            },
-            (None, None) => AuthenticationMethod::Unknown,
+            (Some(user_password_id), None, None, None, None) => {
+                AuthenticationMethod::Password { user_password_id }
+            }
+            (None, Some(upstream_oauth2_session_id), None, None, None) => {
+                AuthenticationMethod::UpstreamOAuth2 {
+                    upstream_oauth2_session_id,
+                }
+            }
+            (None, None, Some(user_magic_link_id), None, None) => {
+                AuthenticationMethod::MagicLink { user_magic_link_id }
+            }
+            (None, None, None, Some(principal), None) => {
+                AuthenticationMethod::Kerberos { principal }
+            }
+            (None, None, None, None, Some(subject)) => {
+                AuthenticationMethod::ClientCertificate { subject }
+            }
+            (None, None, None, None, None) => AuthenticationMethod::Unknown,
             _ => {
                 return Err(DatabaseInconsistencyError::on("user_session_authentications").row(id));
             }
@@ -153,12 +188,20 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
                      , s.user_agent            AS "user_session_user_agent"
                      , s.last_active_at        AS "user_session_last_active_at"
                      , s.last_active_ip        AS "user_session_last_active_ip: IpAddr"
+                     , s.impersonated_by       AS "user_session_impersonated_by"
+                     , s.expires_at            AS "user_session_expires_at"
                      , u.user_id
                      , u.username              AS "user_username"
                      , u.primary_user_email_id AS "user_primary_user_email_id"
                      , u.created_at            AS "user_created_at"
                      , u.locked_at             AS "user_locked_at"
+                     , u.shadow_banned_at      AS "user_shadow_banned_at"
                      , u.can_request_admin     AS "user_can_request_admin"
+                     , u.is_bot                AS "user_is_bot"
+                     , u.is_guest              AS "user_is_guest"
+                     , u.display_name          AS "user_display_name"
+                     , u.avatar_url            AS "user_avatar_url"
+                     , u.groups                AS "user_groups"
                 FROM user_sessions s
                 INNER JOIN users u
                     USING (user_id)
@@ -219,6 +262,71 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
             user_agent,
             last_active_at: None,
             last_active_ip: None,
+            impersonated_by: None,
+            expires_at: None,
+        };
+
+        Ok(session)
+    }
+
+    #[tracing::instrument(
+        name = "db.browser_session.add_impersonation",
+        skip_all,
+        fields(
+            db.statement,
+            %user.id,
+            %admin.id,
+            user_session.id,
+        ),
+        err,
+    )]
+    async fn add_impersonation(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        admin: &User,
+        user_agent: Option<UserAgent>,
+        ttl: Duration,
+    ) -> Result<BrowserSession, Self::Error> {
+        let created_at = clock.now();
+        let expires_at = created_at + ttl;
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_session.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_sessions
+                    ( user_session_id
+                    , user_id
+                    , created_at
+                    , user_agent
+                    , impersonated_by
+                    , expires_at
+                    )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            created_at,
+            user_agent.as_deref(),
+            Uuid::from(admin.id),
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let session = BrowserSession {
+            id,
+            user: user.clone(),
+            created_at,
+            finished_at: None,
+            user_agent,
+            last_active_at: None,
+            last_active_ip: None,
+            impersonated_by: Some(admin.id),
+            expires_at: Some(expires_at),
         };
 
         Ok(session)
@@ -259,6 +367,85 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
         Ok(user_session)
     }
 
+    #[tracing::instrument(
+        name = "db.browser_session.finish_expired",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn finish_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error> {
+        let finished_at = clock.now();
+        let count = sqlx::query_scalar!(
+            r#"
+                WITH expired AS (
+                    UPDATE user_sessions
+                    SET finished_at = $1
+                    WHERE finished_at IS NULL
+                      AND expires_at < $1
+                    RETURNING user_session_id
+                ), finished_oauth2_sessions AS (
+                    UPDATE oauth2_sessions
+                    SET finished_at = $1
+                    WHERE finished_at IS NULL
+                      AND user_session_id IN (SELECT user_session_id FROM expired)
+                ), finished_compat_sessions AS (
+                    UPDATE compat_sessions
+                    SET finished_at = $1
+                    WHERE finished_at IS NULL
+                      AND user_session_id IN (SELECT user_session_id FROM expired)
+                )
+                SELECT COUNT(*) AS "count!"
+                FROM expired
+            "#,
+            finished_at,
+        )
+        .traced()
+        .fetch_one(&mut *self.conn)
+        .await?;
+
+        Ok(count.try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.browser_session.finish_stale",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn finish_stale(
+        &mut self,
+        clock: &dyn Clock,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+    ) -> Result<usize, Self::Error> {
+        let now = clock.now();
+        let idle_before = idle_timeout.map(|timeout| now - timeout);
+        let created_before = max_lifetime.map(|lifetime| now - lifetime);
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_sessions
+                SET finished_at = $1
+                WHERE finished_at IS NULL
+                  AND (
+                    COALESCE(last_active_at, created_at) < $2
+                    OR created_at < $3
+                  )
+            "#,
+            now,
+            idle_before,
+            created_before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.browser_session.list",
         skip_all,
@@ -297,6 +484,14 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
                 Expr::col((UserSessions::Table, UserSessions::LastActiveIp)),
                 SessionLookupIden::UserSessionLastActiveIp,
             )
+            .expr_as(
+                Expr::col((UserSessions::Table, UserSessions::ImpersonatedBy)),
+                SessionLookupIden::UserSessionImpersonatedBy,
+            )
+            .expr_as(
+                Expr::col((UserSessions::Table, UserSessions::ExpiresAt)),
+                SessionLookupIden::UserSessionExpiresAt,
+            )
             .expr_as(
                 Expr::col((Users::Table, Users::UserId)),
                 SessionLookupIden::UserId,
@@ -317,10 +512,34 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
                 Expr::col((Users::Table, Users::LockedAt)),
                 SessionLookupIden::UserLockedAt,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::ShadowBannedAt)),
+                SessionLookupIden::UserShadowBannedAt,
+            )
             .expr_as(
                 Expr::col((Users::Table, Users::CanRequestAdmin)),
                 SessionLookupIden::UserCanRequestAdmin,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::IsBot)),
+                SessionLookupIden::UserIsBot,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::IsGuest)),
+                SessionLookupIden::UserIsGuest,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::DisplayName)),
+                SessionLookupIden::UserDisplayName,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::AvatarUrl)),
+                SessionLookupIden::UserAvatarUrl,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::Groups)),
+                SessionLookupIden::UserGroups,
+            )
             .from(UserSessions::Table)
             .inner_join(
                 Users::Table,
@@ -492,6 +711,151 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
         })
     }
 
+    #[tracing::instrument(
+        name = "db.browser_session.authenticate_with_magic_link",
+        skip_all,
+        fields(
+            db.statement,
+            %user_session.id,
+            %user_magic_link.id,
+            user_session_authentication.id,
+        ),
+        err,
+    )]
+    async fn authenticate_with_magic_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        user_magic_link: &UserMagicLink,
+    ) -> Result<Authentication, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record(
+            "user_session_authentication.id",
+            tracing::field::display(id),
+        );
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_session_authentications
+                    (user_session_authentication_id, user_session_id, created_at, user_magic_link_id)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_session.id),
+            created_at,
+            Uuid::from(user_magic_link.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(Authentication {
+            id,
+            created_at,
+            authentication_method: AuthenticationMethod::MagicLink {
+                user_magic_link_id: user_magic_link.id,
+            },
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.browser_session.authenticate_with_kerberos",
+        skip_all,
+        fields(
+            db.statement,
+            %user_session.id,
+            user_session_authentication.id,
+        ),
+        err,
+    )]
+    async fn authenticate_with_kerberos(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        principal: &str,
+    ) -> Result<Authentication, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record(
+            "user_session_authentication.id",
+            tracing::field::display(id),
+        );
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_session_authentications
+                    (user_session_authentication_id, user_session_id, created_at, kerberos_principal)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_session.id),
+            created_at,
+            principal,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(Authentication {
+            id,
+            created_at,
+            authentication_method: AuthenticationMethod::Kerberos {
+                principal: principal.to_owned(),
+            },
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.browser_session.authenticate_with_client_certificate",
+        skip_all,
+        fields(
+            db.statement,
+            %user_session.id,
+            user_session_authentication.id,
+        ),
+        err,
+    )]
+    async fn authenticate_with_client_certificate(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        subject: &str,
+    ) -> Result<Authentication, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record(
+            "user_session_authentication.id",
+            tracing::field::display(id),
+        );
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_session_authentications
+                    (user_session_authentication_id, user_session_id, created_at, client_certificate_subject)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user_session.id),
+            created_at,
+            subject,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(Authentication {
+            id,
+            created_at,
+            authentication_method: AuthenticationMethod::ClientCertificate {
+                subject: subject.to_owned(),
+            },
+        })
+    }
+
     #[tracing::instrument(
         name = "db.browser_session.get_last_authentication",
         skip_all,
@@ -512,6 +876,9 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
                      , created_at
                      , user_password_id
                      , upstream_oauth_authorization_session_id
+                     , user_magic_link_id
+                     , kerberos_principal
+                     , client_certificate_subject
                 FROM user_session_authentications
                 WHERE user_session_id = $1
                 ORDER BY created_at DESC
@@ -531,6 +898,66 @@ impl<'c> BrowserSessionRepository for PgBrowserSessionRepository<'c> {
         Ok(Some(authentication))
     }
 
+    #[tracing::instrument(
+        name = "db.browser_session.forget_last_active_ips",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_sessions
+                SET last_active_ip = NULL
+                WHERE last_active_ip IS NOT NULL
+                  AND last_active_at < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.browser_session.forget_user_agents",
+        skip_all,
+        fields(
+            db.statement,
+        ),
+        err,
+    )]
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let before = clock.now() - retention;
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_sessions
+                SET user_agent = NULL
+                WHERE user_agent IS NOT NULL
+                  AND COALESCE(last_active_at, created_at) < $1
+            "#,
+            before,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.browser_session.record_batch_activity",
         skip_all,
@@ -13,13 +13,19 @@
 // limitations under the License.
 
 use chrono::Duration;
+use mas_data_model::{
+    AuthenticationMethod, LoginRiskDecision, LoginRiskSignal, LoginSighting, UserAgent,
+    UserEmailChange, UserExport, UserLoginCode, UserMagicLink,
+};
 use mas_storage::{
     clock::MockClock,
     user::{
-        BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository,
-        UserPasswordRepository, UserRepository,
+        BrowserSessionFilter, BrowserSessionRepository, UserEmailChangeRepository, UserEmailFilter,
+        UserEmailRepository, UserExportRepository, UserFilter, UserLoginAlertRepository,
+        UserLoginCodeRepository, UserLoginRiskRepository, UserMagicLinkRepository,
+        UserPasswordRepository, UserPhoneRepository, UserRepository,
     },
-    Pagination, Repository, RepositoryAccess,
+    Clock, Pagination, Repository, RepositoryAccess,
 };
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
@@ -115,6 +121,186 @@ async fn test_user_repo(pool: PgPool) {
     let user = repo.user().lookup(user.id).await.unwrap().unwrap();
     assert!(!user.can_request_admin);
 
+    // Flag the user as a bot
+    assert!(!user.is_bot);
+    let user = repo.user().set_bot(user, true).await.unwrap();
+    assert!(user.is_bot);
+
+    // Check that the property is retrieved on lookup, and through the browser
+    // sessions
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert!(user.is_bot);
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(session.user.is_bot);
+
+    // Shadow-ban the user
+    assert!(!user.is_shadow_banned());
+    let user = repo.user().shadow_ban(&clock, user).await.unwrap();
+    assert!(user.is_shadow_banned());
+    // A shadow-banned user is still valid
+    assert!(user.is_valid());
+
+    // Check that the property is retrieved on lookup, and through the browser
+    // sessions
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert!(user.is_shadow_banned());
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(session.user.is_shadow_banned());
+
+    // Shadow-banning a second time should not fail
+    let user = repo.user().shadow_ban(&clock, user).await.unwrap();
+    assert!(user.is_shadow_banned());
+
+    // Lift the shadow-ban
+    let user = repo.user().lift_shadow_ban(user).await.unwrap();
+    assert!(!user.is_shadow_banned());
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert!(!user.is_shadow_banned());
+
+    // Set the profile of the user
+    assert_eq!(user.display_name, None);
+    assert_eq!(user.avatar_url, None);
+    let user = repo
+        .user()
+        .set_display_name(user, Some("John Doe".to_owned()))
+        .await
+        .unwrap();
+    let user = repo
+        .user()
+        .set_avatar_url(user, Some("mxc://example.com/abcdef".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("John Doe"));
+
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("John Doe"));
+    assert_eq!(user.avatar_url.as_deref(), Some("mxc://example.com/abcdef"));
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user.display_name.as_deref(), Some("John Doe"));
+
+    // And remove the display name
+    let user = repo.user().set_display_name(user, None).await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert_eq!(user.display_name, None);
+    assert_eq!(user.avatar_url.as_deref(), Some("mxc://example.com/abcdef"));
+
+    // Set the groups of the user
+    assert!(user.groups.is_empty());
+    let user = repo
+        .user()
+        .set_groups(user, vec!["admins".to_owned(), "staff".to_owned()])
+        .await
+        .unwrap();
+    assert_eq!(user.groups, vec!["admins".to_owned(), "staff".to_owned()]);
+
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert_eq!(user.groups, vec!["admins".to_owned(), "staff".to_owned()]);
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        session.user.groups,
+        vec!["admins".to_owned(), "staff".to_owned()]
+    );
+
+    // And clear them
+    let user = repo.user().set_groups(user, Vec::new()).await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    assert!(user.groups.is_empty());
+
+    repo.save().await.unwrap();
+}
+
+/// Test listing and searching users
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_list(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let all = UserFilter::new();
+    assert_eq!(repo.user().count(all).await.unwrap(), 0);
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob_smith".to_owned())
+        .await
+        .unwrap();
+    let bob = repo.user().lock(&clock, bob).await.unwrap();
+    repo.user()
+        .add(&mut rng, &clock, "bobsmith".to_owned())
+        .await
+        .unwrap();
+
+    assert_eq!(repo.user().count(all).await.unwrap(), 3);
+    let page = repo.user().list(all, Pagination::first(10)).await.unwrap();
+    assert_eq!(page.edges.len(), 3);
+    assert!(!page.has_next_page);
+
+    // Searching is case-insensitive and matches substrings
+    let filter = UserFilter::new().matching("LIC");
+    assert_eq!(repo.user().count(filter).await.unwrap(), 1);
+    let page = repo
+        .user()
+        .list(filter, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(page.edges, vec![alice.clone()]);
+
+    // Wildcards in the search are matched literally
+    let filter = UserFilter::new().matching("b_s");
+    assert_eq!(repo.user().count(filter).await.unwrap(), 1);
+    let page = repo
+        .user()
+        .list(filter, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(page.edges, vec![bob.clone()]);
+
+    // Filter on the state of the user
+    let filter = UserFilter::new().locked_only();
+    assert_eq!(repo.user().count(filter).await.unwrap(), 1);
+    let page = repo
+        .user()
+        .list(filter, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(page.edges, vec![bob]);
+
+    let filter = UserFilter::new().active_only();
+    assert_eq!(repo.user().count(filter).await.unwrap(), 2);
+
+    let filter = UserFilter::new().matching("bob").active_only();
+    assert_eq!(repo.user().count(filter).await.unwrap(), 1);
+
     repo.save().await.unwrap();
 }
 
@@ -458,6 +644,35 @@ async fn test_user_password_repo(pool: PgPool) {
         Some(first_password.id)
     );
 
+    // Invalidating the password leaves the user without an active password
+    repo.user_password()
+        .invalidate(&clock, &user)
+        .await
+        .unwrap();
+    assert!(repo.user_password().active(&user).await.unwrap().is_none());
+
+    // Until a new one is set
+    clock.advance(Duration::microseconds(10 * 1000 * 1000));
+    let third_password = repo
+        .user_password()
+        .add(
+            &mut rng,
+            &clock,
+            &user,
+            2,
+            FIRST_PASSWORD_HASH.to_owned(),
+            None,
+        )
+        .await
+        .unwrap();
+    let third_password_lookup = repo
+        .user_password()
+        .active(&user)
+        .await
+        .unwrap()
+        .expect("user should have an active password");
+    assert_eq!(third_password.id, third_password_lookup.id);
+
     repo.save().await.unwrap();
 }
 
@@ -516,6 +731,47 @@ async fn test_user_session(pool: PgPool) {
     assert_eq!(session_lookup.user.id, user.id);
     assert!(session_lookup.finished_at.is_none());
 
+    // Authenticate the session with a Kerberos ticket
+    let authentication = repo
+        .browser_session()
+        .authenticate_with_kerberos(&mut rng, &clock, &session_lookup, "john@EXAMPLE.COM")
+        .await
+        .unwrap();
+    let last = repo
+        .browser_session()
+        .get_last_authentication(&session_lookup)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(last, authentication);
+    assert_eq!(
+        last.authentication_method,
+        AuthenticationMethod::Kerberos {
+            principal: "john@EXAMPLE.COM".to_owned()
+        }
+    );
+
+    // Authenticate it again later with a TLS client certificate
+    clock.advance(Duration::microseconds(10 * 1000 * 1000));
+    let authentication = repo
+        .browser_session()
+        .authenticate_with_client_certificate(&mut rng, &clock, &session_lookup, "john")
+        .await
+        .unwrap();
+    let last = repo
+        .browser_session()
+        .get_last_authentication(&session_lookup)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(last, authentication);
+    assert_eq!(
+        last.authentication_method,
+        AuthenticationMethod::ClientCertificate {
+            subject: "john".to_owned()
+        }
+    );
+
     // Finish the session
     repo.browser_session()
         .finish(&clock, session_lookup)
@@ -550,6 +806,1090 @@ async fn test_user_session(pool: PgPool) {
     assert!(session_lookup.finished_at.is_some());
 }
 
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_session_impersonation(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let admin = repo
+        .user()
+        .add(&mut rng, &clock, "admin".to_owned())
+        .await
+        .unwrap();
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    let regular = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+    assert!(!regular.is_impersonation());
+
+    let session = repo
+        .browser_session()
+        .add_impersonation(
+            &mut rng,
+            &clock,
+            &user,
+            &admin,
+            None,
+            Duration::microseconds(15 * 60 * 1000 * 1000),
+        )
+        .await
+        .unwrap();
+    assert_eq!(session.user.id, user.id);
+    assert_eq!(session.impersonated_by, Some(admin.id));
+    assert_eq!(
+        session.expires_at,
+        Some(clock.now() + Duration::microseconds(15 * 60 * 1000 * 1000))
+    );
+
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .expect("user session not found");
+    assert_eq!(session_lookup, session);
+
+    // Nothing expired yet
+    assert_eq!(
+        repo.browser_session().finish_expired(&clock).await.unwrap(),
+        0
+    );
+
+    // After 15 minutes, the impersonation session is finished, but not the
+    // regular one
+    clock.advance(Duration::microseconds(16 * 60 * 1000 * 1000));
+    assert_eq!(
+        repo.browser_session().finish_expired(&clock).await.unwrap(),
+        1
+    );
+
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .expect("user session not found");
+    assert!(session_lookup.finished_at.is_some());
+
+    let regular_lookup = repo
+        .browser_session()
+        .lookup(regular.id)
+        .await
+        .unwrap()
+        .expect("user session not found");
+    assert!(regular_lookup.finished_at.is_none());
+
+    // Finished sessions are not finished twice
+    assert_eq!(
+        repo.browser_session().finish_expired(&clock).await.unwrap(),
+        0
+    );
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_session_stale(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    let idle = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+    let active = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+
+    let idle_timeout = Some(Duration::try_hours(1).unwrap());
+    let max_lifetime = Some(Duration::try_days(1).unwrap());
+
+    // Nothing is stale yet
+    assert_eq!(
+        repo.browser_session()
+            .finish_stale(&clock, idle_timeout, max_lifetime)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // After 2 hours, only the session which was not used is finished
+    clock.advance(Duration::try_hours(2).unwrap());
+    repo.browser_session()
+        .record_batch_activity(vec![(active.id, clock.now(), None)])
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.browser_session()
+            .finish_stale(&clock, idle_timeout, max_lifetime)
+            .await
+            .unwrap(),
+        1
+    );
+
+    let idle = repo
+        .browser_session()
+        .lookup(idle.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(idle.finished_at.is_some());
+    let active_lookup = repo
+        .browser_session()
+        .lookup(active.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(active_lookup.finished_at.is_none());
+
+    // Without limits, nothing is finished, even if the session is old
+    clock.advance(Duration::try_days(2).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .finish_stale(&clock, None, None)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // The active session is finished once it reaches its maximum lifetime
+    repo.browser_session()
+        .record_batch_activity(vec![(active.id, clock.now(), None)])
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.browser_session()
+            .finish_stale(&clock, idle_timeout, max_lifetime)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_session_forget_last_active_ips(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+
+    let ip = "203.0.113.1".parse().unwrap();
+    repo.browser_session()
+        .record_batch_activity(vec![(session.id, clock.now(), Some(ip))])
+        .await
+        .unwrap();
+
+    let retention = Duration::try_days(30).unwrap();
+
+    // The IP address is kept during the retention window
+    clock.advance(Duration::try_days(29).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .forget_last_active_ips(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session_lookup.last_active_ip, Some(ip));
+
+    // After that, only the last activity time is kept
+    clock.advance(Duration::try_days(2).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .forget_last_active_ips(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session_lookup.last_active_ip, None);
+    assert!(session_lookup.last_active_at.is_some());
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_session_forget_user_agents(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+    let user_agent = UserAgent::parse("Firefox".to_owned());
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, Some(user_agent.clone()))
+        .await
+        .unwrap();
+
+    let retention = Duration::try_days(30).unwrap();
+
+    // A session which was never active is matched on its creation time
+    clock.advance(Duration::try_days(29).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .forget_user_agents(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Recent activity keeps the user agent around
+    repo.browser_session()
+        .record_batch_activity(vec![(session.id, clock.now(), None)])
+        .await
+        .unwrap();
+    clock.advance(Duration::try_days(2).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .forget_user_agents(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session_lookup.user_agent, Some(user_agent));
+
+    // Once the session is unused for longer, the user agent is forgotten
+    clock.advance(Duration::try_days(29).unwrap());
+    assert_eq!(
+        repo.browser_session()
+            .forget_user_agents(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+    let session_lookup = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session_lookup.user_agent, None);
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_login_alert(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    let network = "192.0.2.0".parse().unwrap();
+    let other_network = "198.51.100.0".parse().unwrap();
+
+    // The user never logged in
+    assert!(repo
+        .user_login_alert()
+        .last_sighting(&user)
+        .await
+        .unwrap()
+        .is_none());
+
+    // The first login of the user is recorded as such
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Firefox", network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::First);
+
+    // Logging in again from the same device and network is known
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Firefox", network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::Known);
+
+    // Using a new device is unknown
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Chrome", network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::Unknown);
+
+    // Using a new network is unknown
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Firefox", other_network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::Unknown);
+
+    // Both the device and the network are now known
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Chrome", other_network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::Known);
+
+    // The most recent login is the last sighting
+    clock.advance(Duration::try_minutes(1).unwrap());
+    repo.user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Firefox", network)
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.user_login_alert().last_sighting(&user).await.unwrap(),
+        Some((network, clock.now()))
+    );
+
+    let browser_session = repo
+        .browser_session()
+        .add(
+            &mut rng,
+            &clock,
+            &user,
+            Some(UserAgent::parse("Chrome".to_owned())),
+        )
+        .await
+        .unwrap();
+
+    let alert = repo
+        .user_login_alert()
+        .add(
+            &mut rng,
+            &clock,
+            &browser_session,
+            Some("198.51.100.42".parse().unwrap()),
+            "ticket".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(alert.user_id, user.id);
+    assert_eq!(alert.user_session_id, browser_session.id);
+    assert!(alert.active(clock.now()));
+
+    let alert_lookup = repo
+        .user_login_alert()
+        .find_by_ticket("ticket")
+        .await
+        .unwrap()
+        .expect("login alert not found");
+    assert_eq!(alert_lookup, alert);
+
+    let alert_lookup = repo
+        .user_login_alert()
+        .lookup(alert.id)
+        .await
+        .unwrap()
+        .expect("login alert not found");
+    assert_eq!(alert_lookup, alert);
+
+    assert!(repo
+        .user_login_alert()
+        .find_by_ticket("another-ticket")
+        .await
+        .unwrap()
+        .is_none());
+
+    // Reporting the alert makes it inactive
+    let alert = repo
+        .user_login_alert()
+        .mark_as_reported(&clock, alert)
+        .await
+        .unwrap();
+    assert!(!alert.active(clock.now()));
+
+    // It can't be reported twice
+    assert!(repo
+        .user_login_alert()
+        .mark_as_reported(&clock, alert.clone())
+        .await
+        .is_err());
+
+    // The details of the login are kept during the retention window
+    let retention = Duration::try_days(30).unwrap();
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_user_agents(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_sightings(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // After that, they are forgotten
+    clock.advance(Duration::try_days(31).unwrap());
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_user_agents(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+    let alert_lookup = repo
+        .user_login_alert()
+        .lookup(alert.id)
+        .await
+        .unwrap()
+        .expect("login alert not found");
+    assert_eq!(alert_lookup.ip_address, None);
+    assert_eq!(alert_lookup.user_agent, None);
+
+    // Forgetting the known devices and networks makes the next login look like
+    // the first one
+    assert_eq!(
+        repo.user_login_alert()
+            .forget_sightings(&clock, retention)
+            .await
+            .unwrap(),
+        4
+    );
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(&mut rng, &clock, &user, "Firefox", network)
+        .await
+        .unwrap();
+    assert_eq!(sighting, LoginSighting::First);
+
+    repo.save().await.unwrap();
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_login_code(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+    let user_email = repo
+        .user_email()
+        .add(&mut rng, &clock, &user, "john@example.com".to_owned())
+        .await
+        .unwrap();
+    let password = repo
+        .user_password()
+        .add(&mut rng, &clock, &user, 1, "doesntmatter".to_owned(), None)
+        .await
+        .unwrap();
+
+    let code = repo
+        .user_login_code()
+        .add(
+            &mut rng,
+            &clock,
+            &user_email,
+            &password,
+            Some(UserAgent::parse("Firefox".to_owned())),
+            Some("192.0.2.42".parse().unwrap()),
+            "123456".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(code.user_id, user.id);
+    assert_eq!(code.user_email_id, user_email.id);
+    assert_eq!(code.user_password_id, password.id);
+    assert_eq!(code.attempts, 0);
+    assert_eq!(code.sent_count, 1);
+    assert!(code.active(clock.now()));
+
+    let code_lookup = repo
+        .user_login_code()
+        .lookup(code.id)
+        .await
+        .unwrap()
+        .expect("login code not found");
+    assert_eq!(code_lookup, code);
+
+    // Wrong attempts are counted
+    let code = repo.user_login_code().record_attempt(code).await.unwrap();
+    assert_eq!(code.attempts, 1);
+    assert!(code.active(clock.now()));
+
+    // The code can't be resent right away
+    assert!(!code.can_resend(clock.now()));
+    clock.advance(UserLoginCode::RESEND_INTERVAL);
+    assert!(code.can_resend(clock.now()));
+    let code = repo
+        .user_login_code()
+        .record_resend(&clock, code)
+        .await
+        .unwrap();
+    assert_eq!(code.sent_count, 2);
+    assert!(!code.can_resend(clock.now()));
+
+    // Consuming the code makes it inactive
+    let code = repo.user_login_code().consume(&clock, code).await.unwrap();
+    assert!(!code.active(clock.now()));
+
+    // It can't be consumed twice
+    assert!(repo
+        .user_login_code()
+        .consume(&clock, code.clone())
+        .await
+        .is_err());
+
+    let code_lookup = repo
+        .user_login_code()
+        .lookup(code.id)
+        .await
+        .unwrap()
+        .expect("login code not found");
+    assert_eq!(code_lookup, code);
+
+    // The IP address is forgotten after the retention window
+    let retention = Duration::try_days(30).unwrap();
+    assert_eq!(
+        repo.user_login_code()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    clock.advance(Duration::try_days(31).unwrap());
+    assert_eq!(
+        repo.user_login_code()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+/// Test the user login risk repository
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_login_risk(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    let assessment = repo
+        .user_login_risk()
+        .add(
+            &mut rng,
+            &clock,
+            &user,
+            Some(UserAgent::parse("Firefox".to_owned())),
+            Some("192.0.2.42".parse().unwrap()),
+            Some("abc123".to_owned()),
+            100,
+            vec![LoginRiskSignal::NewDevice, LoginRiskSignal::TorExitNode],
+            LoginRiskDecision::Deny,
+        )
+        .await
+        .unwrap();
+    assert_eq!(assessment.user_id, user.id);
+    assert_eq!(assessment.score, 100);
+    assert_eq!(assessment.decision, LoginRiskDecision::Deny);
+    assert_eq!(assessment.request_id.as_deref(), Some("abc123"));
+
+    let assessment_lookup = repo
+        .user_login_risk()
+        .lookup(assessment.id)
+        .await
+        .unwrap()
+        .expect("login risk assessment not found");
+    assert_eq!(assessment_lookup, assessment);
+
+    // The IP address is forgotten after the retention window
+    let retention = Duration::try_days(30).unwrap();
+    assert_eq!(
+        repo.user_login_risk()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        0
+    );
+    clock.advance(Duration::try_days(31).unwrap());
+    assert_eq!(
+        repo.user_login_risk()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+
+    let assessment_lookup = repo
+        .user_login_risk()
+        .lookup(assessment.id)
+        .await
+        .unwrap()
+        .expect("login risk assessment not found");
+    assert_eq!(assessment_lookup.ip_address, None);
+}
+
+/// Test the user email change repository
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_email_change(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    let change = repo
+        .user_email_change()
+        .add(
+            &mut rng,
+            &clock,
+            &user,
+            "john@example.com".to_owned(),
+            "john@example.org".to_owned(),
+            "ticket".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert!(change.is_pending());
+    assert!(!change.can_undo(clock.now()));
+
+    let found = repo
+        .user_email_change()
+        .lookup(change.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, change);
+
+    // An unconfirmed change can't be undone
+    assert!(repo
+        .user_email_change()
+        .mark_as_undone(&clock, change.clone())
+        .await
+        .is_err());
+
+    clock.advance(Duration::microseconds(60 * 1000 * 1000));
+    let change = repo
+        .user_email_change()
+        .mark_as_confirmed(&clock, change)
+        .await
+        .unwrap();
+    assert!(!change.is_pending());
+    assert!(change.can_undo(clock.now()));
+
+    let found = repo
+        .user_email_change()
+        .find_by_undo_ticket("ticket")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, change);
+    assert!(repo
+        .user_email_change()
+        .find_by_undo_ticket("other")
+        .await
+        .unwrap()
+        .is_none());
+
+    // The change can only be undone for a while
+    assert!(!change.can_undo(clock.now() + UserEmailChange::UNDO_VALIDITY));
+
+    let change = repo
+        .user_email_change()
+        .mark_as_undone(&clock, change)
+        .await
+        .unwrap();
+    assert!(!change.can_undo(clock.now()));
+
+    // It can't be undone twice
+    assert!(repo
+        .user_email_change()
+        .mark_as_undone(&clock, change)
+        .await
+        .is_err());
+}
+
+/// Test the user export repository
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_export(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    assert!(repo
+        .user_export()
+        .find_latest(&user)
+        .await
+        .unwrap()
+        .is_none());
+
+    let export = repo
+        .user_export()
+        .add(&mut rng, &clock, &user)
+        .await
+        .unwrap();
+    assert!(export.is_pending());
+    assert!(!export.is_available(clock.now()));
+
+    let found = repo.user_export().lookup(export.id).await.unwrap().unwrap();
+    assert_eq!(found, export);
+
+    // There is no data until the export is completed
+    assert!(repo
+        .user_export()
+        .get_data(&export)
+        .await
+        .unwrap()
+        .is_none());
+
+    clock.advance(Duration::microseconds(60 * 1000 * 1000));
+    let data = serde_json::json!({ "user": { "username": "john" } });
+    let export = repo
+        .user_export()
+        .complete(&clock, export, data.clone())
+        .await
+        .unwrap();
+    assert!(!export.is_pending());
+    assert!(export.is_available(clock.now()));
+    assert!(!export.is_available(clock.now() + UserExport::VALIDITY));
+
+    let found = repo.user_export().get_data(&export).await.unwrap().unwrap();
+    assert_eq!(found, data);
+
+    // It can't be completed twice
+    assert!(repo
+        .user_export()
+        .complete(&clock, export.clone(), data)
+        .await
+        .is_err());
+
+    // A newer export is returned as the latest one
+    clock.advance(Duration::microseconds(60 * 1000 * 1000));
+    let newer = repo
+        .user_export()
+        .add(&mut rng, &clock, &user)
+        .await
+        .unwrap();
+
+    let latest = repo
+        .user_export()
+        .find_latest(&user)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest, newer);
+    assert_ne!(latest, export);
+}
+
+/// Test the user magic link repository, and authenticating a browser session
+/// with a magic link
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_magic_link(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+    let user_email = repo
+        .user_email()
+        .add(&mut rng, &clock, &user, "john@example.com".to_owned())
+        .await
+        .unwrap();
+
+    let session = repo
+        .user_magic_link()
+        .add_session(
+            &mut rng,
+            &clock,
+            "john@example.com".to_owned(),
+            UserAgent::parse("Mozilla/5.0".to_owned()),
+            None,
+            "en".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert!(session.consumed_at.is_none());
+
+    let found = repo
+        .user_magic_link()
+        .lookup_session(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, session);
+
+    let link = repo
+        .user_magic_link()
+        .add_link(&mut rng, &clock, &session, &user_email, "ticket".to_owned())
+        .await
+        .unwrap();
+    assert!(link.active(clock.now()));
+    assert!(!link.active(clock.now() + UserMagicLink::VALIDITY));
+
+    let found = repo
+        .user_magic_link()
+        .find_link("ticket")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, link);
+    assert!(repo
+        .user_magic_link()
+        .find_link("other")
+        .await
+        .unwrap()
+        .is_none());
+
+    let session = repo
+        .user_magic_link()
+        .consume_link(&clock, link.clone(), session)
+        .await
+        .unwrap();
+    assert!(session.consumed_at.is_some());
+
+    // The session can only be consumed once
+    assert!(repo
+        .user_magic_link()
+        .consume_link(&clock, link.clone(), session)
+        .await
+        .is_err());
+
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+    let authentication = repo
+        .browser_session()
+        .authenticate_with_magic_link(&mut rng, &clock, &browser_session, &link)
+        .await
+        .unwrap();
+
+    let last = repo
+        .browser_session()
+        .get_last_authentication(&browser_session)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(last, authentication);
+    assert_eq!(
+        last.authentication_method,
+        AuthenticationMethod::MagicLink {
+            user_magic_link_id: link.id
+        }
+    );
+
+    // The IP address of the request is forgotten after the retention window
+    let session = repo
+        .user_magic_link()
+        .add_session(
+            &mut rng,
+            &clock,
+            "john@example.com".to_owned(),
+            UserAgent::parse("Mozilla/5.0".to_owned()),
+            Some("203.0.113.1".parse().unwrap()),
+            "en".to_owned(),
+        )
+        .await
+        .unwrap();
+
+    let retention = Duration::try_days(30).unwrap();
+    clock.advance(Duration::try_days(31).unwrap());
+    assert_eq!(
+        repo.user_magic_link()
+            .forget_ip_addresses(&clock, retention)
+            .await
+            .unwrap(),
+        1
+    );
+    let session = repo
+        .user_magic_link()
+        .lookup_session(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.ip_address, None);
+}
+
+/// Test the user phone repository
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_phone_repo(pool: PgPool) {
+    const PHONE_NUMBER: &str = "+447700900123";
+    const CODE: &str = "123456";
+
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "john".to_owned())
+        .await
+        .unwrap();
+
+    assert!(repo
+        .user_phone()
+        .find(&user, PHONE_NUMBER)
+        .await
+        .unwrap()
+        .is_none());
+
+    let user_phone = repo
+        .user_phone()
+        .add(&mut rng, &clock, &user, PHONE_NUMBER.to_owned())
+        .await
+        .unwrap();
+    assert_eq!(user_phone.user_id, user.id);
+    assert!(user_phone.confirmed_at.is_none());
+
+    assert_eq!(
+        repo.user_phone().lookup(user_phone.id).await.unwrap(),
+        Some(user_phone.clone())
+    );
+    assert_eq!(
+        repo.user_phone().all(&user).await.unwrap(),
+        vec![user_phone.clone()]
+    );
+
+    let verification = repo
+        .user_phone()
+        .add_verification_code(
+            &mut rng,
+            &clock,
+            &user_phone,
+            Duration::try_hours(8).unwrap(),
+            CODE.to_owned(),
+        )
+        .await
+        .unwrap();
+
+    // A wrong code doesn't match
+    assert!(repo
+        .user_phone()
+        .find_verification_code(&clock, &user_phone, "000000")
+        .await
+        .unwrap()
+        .is_none());
+
+    let found = repo
+        .user_phone()
+        .find_verification_code(&clock, &user_phone, CODE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, verification);
+    assert!(found.is_valid());
+
+    let consumed = repo
+        .user_phone()
+        .consume_verification_code(&clock, found)
+        .await
+        .unwrap();
+    assert!(!consumed.is_valid());
+
+    // The code can't be consumed twice
+    let found = repo
+        .user_phone()
+        .find_verification_code(&clock, &user_phone, CODE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!found.is_valid());
+    assert!(repo
+        .user_phone()
+        .consume_verification_code(&clock, found)
+        .await
+        .is_err());
+
+    let user_phone = repo
+        .user_phone()
+        .mark_as_verified(&clock, user_phone)
+        .await
+        .unwrap();
+    assert_eq!(user_phone.confirmed_at, Some(clock.now()));
+
+    repo.user_phone().remove(user_phone).await.unwrap();
+    assert!(repo.user_phone().all(&user).await.unwrap().is_empty());
+
+    repo.save().await.unwrap();
+}
+
 #[sqlx::test(migrator = "crate::MIGRATOR")]
 async fn test_user_terms(pool: PgPool) {
     let mut repo = PgRepository::from_pool(&pool).await.unwrap();
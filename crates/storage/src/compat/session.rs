@@ -15,7 +15,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{BrowserSession, CompatSession, CompatSsoLogin, Device, User, UserAgent};
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -69,6 +69,8 @@ pub struct CompatSessionFilter<'a> {
     state: Option<CompatSessionState>,
     auth_type: Option<CompatSessionType>,
     device: Option<&'a Device>,
+    last_active_before: Option<DateTime<Utc>>,
+    exclude_bots: bool,
 }
 
 impl<'a> CompatSessionFilter<'a> {
@@ -143,6 +145,39 @@ impl<'a> CompatSessionFilter<'a> {
     pub fn auth_type(&self) -> Option<CompatSessionType> {
         self.auth_type
     }
+
+    /// Only return sessions which were last active before the given time
+    ///
+    /// Sessions which were never active are matched on their creation time
+    #[must_use]
+    pub fn with_last_active_before(mut self, last_active_before: DateTime<Utc>) -> Self {
+        self.last_active_before = Some(last_active_before);
+        self
+    }
+
+    /// Get the last active before filter
+    ///
+    /// Returns [`None`] if no last active before filter was set
+    #[must_use]
+    pub fn last_active_before(&self) -> Option<DateTime<Utc>> {
+        self.last_active_before
+    }
+
+    /// Don't return the sessions of bot users
+    ///
+    /// Bots use long-lived sessions, which are not subject to the limits
+    /// applied to the sessions of humans
+    #[must_use]
+    pub fn without_bots(mut self) -> Self {
+        self.exclude_bots = true;
+        self
+    }
+
+    /// Whether the sessions of bot users are excluded
+    #[must_use]
+    pub fn excludes_bots(&self) -> bool {
+        self.exclude_bots
+    }
 }
 
 /// A [`CompatSessionRepository`] helps interacting with
@@ -238,6 +273,44 @@ pub trait CompatSessionRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: CompatSessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    /// Forget the IP address of the last activity of the [`CompatSession`]s
+    /// which were last active more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    /// Forget the user agent of the [`CompatSession`]s which were last active
+    /// more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the user agents are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     /// Record a batch of [`CompatSession`] activity
     ///
     /// # Parameters
@@ -297,6 +370,18 @@ repository_impl!(CompatSessionRepository:
 
     async fn count(&mut self, filter: CompatSessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     async fn record_batch_activity(
         &mut self,
         activity: Vec<(Ulid, DateTime<Utc>, Option<IpAddr>)>,
@@ -0,0 +1,90 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Repositories to keep track of outgoing emails
+
+use async_trait::async_trait;
+use mas_data_model::EmailDeadLetter;
+use rand_core::RngCore;
+
+use crate::{repository_impl, Clock, Page, Pagination};
+
+/// An [`EmailDeadLetterRepository`] records the emails which could not be
+/// delivered, even after retrying, so that administrators can look into them
+#[async_trait]
+pub trait EmailDeadLetterRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Record an email which could not be delivered
+    ///
+    /// Returns the newly created [`EmailDeadLetter`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `recipients`: The addresses the email was sent to
+    /// * `subject`: The subject of the email, encrypted as it may contain a
+    ///   code or a link
+    /// * `attempts`: How many times sending the email was attempted
+    /// * `last_error`: The error returned by the last attempt
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        recipients: Vec<String>,
+        subject: String,
+        attempts: u32,
+        last_error: String,
+    ) -> Result<EmailDeadLetter, Self::Error>;
+
+    /// List [`EmailDeadLetter`] with the given pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<EmailDeadLetter>, Self::Error>;
+
+    /// Count the [`EmailDeadLetter`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(EmailDeadLetterRepository:
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        recipients: Vec<String>,
+        subject: String,
+        attempts: u32,
+        last_error: String,
+    ) -> Result<EmailDeadLetter, Self::Error>;
+
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<EmailDeadLetter>, Self::Error>;
+
+    async fn count(&mut self) -> Result<usize, Self::Error>;
+);
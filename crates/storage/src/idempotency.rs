@@ -0,0 +1,143 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Repositories to keep track of the idempotency keys sent by clients
+
+use async_trait::async_trait;
+use mas_data_model::{Client, IdempotencyKey};
+use rand_core::RngCore;
+
+use crate::{repository_impl, Clock};
+
+/// An [`IdempotencyKeyRepository`] remembers the requests made by clients with
+/// an idempotency key, so that retrying them replays the original response
+#[async_trait]
+pub trait IdempotencyKeyRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Find an [`IdempotencyKey`] previously used by a client
+    ///
+    /// Returns `None` if the client never used this key
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client which sent the key
+    /// * `key`: The idempotency key
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find(
+        &mut self,
+        client: &Client,
+        key: &str,
+    ) -> Result<Option<IdempotencyKey>, Self::Error>;
+
+    /// Reserve an idempotency key for a request which is about to be processed
+    ///
+    /// Returns the newly created [`IdempotencyKey`], or `None` if the client
+    /// already used this key, for example because of a concurrent request
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `client`: The client which sent the key
+    /// * `key`: The idempotency key
+    /// * `request_hash`: A hash of the request, to detect the key being reused
+    ///   for another request
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        key: String,
+        request_hash: String,
+    ) -> Result<Option<IdempotencyKey>, Self::Error>;
+
+    /// Record the response to the request, so that it can be replayed
+    ///
+    /// Returns the updated [`IdempotencyKey`]
+    ///
+    /// # Parameters
+    ///
+    /// * `idempotency_key`: The [`IdempotencyKey`] to complete
+    /// * `response`: The serialized response
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn complete(
+        &mut self,
+        idempotency_key: IdempotencyKey,
+        response: String,
+    ) -> Result<IdempotencyKey, Self::Error>;
+
+    /// Release an idempotency key, so that the request can be retried, e.g.
+    /// because it failed
+    ///
+    /// # Parameters
+    ///
+    /// * `idempotency_key`: The [`IdempotencyKey`] to remove
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove(&mut self, idempotency_key: IdempotencyKey) -> Result<(), Self::Error>;
+
+    /// Remove the idempotency keys which are too old to be retried
+    ///
+    /// Returns the number of keys removed
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn cleanup_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(IdempotencyKeyRepository:
+    async fn find(
+        &mut self,
+        client: &Client,
+        key: &str,
+    ) -> Result<Option<IdempotencyKey>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        key: String,
+        request_hash: String,
+    ) -> Result<Option<IdempotencyKey>, Self::Error>;
+
+    async fn complete(
+        &mut self,
+        idempotency_key: IdempotencyKey,
+        response: String,
+    ) -> Result<IdempotencyKey, Self::Error>;
+
+    async fn remove(&mut self, idempotency_key: IdempotencyKey) -> Result<(), Self::Error>;
+
+    async fn cleanup_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+);
@@ -18,6 +18,7 @@ use std::{num::ParseIntError, ops::Deref};
 
 pub use apalis_core::job::{Job, JobId};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -29,6 +30,7 @@ use crate::repository_impl;
 pub struct JobSubmission {
     name: &'static str,
     payload: Value,
+    run_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -122,6 +124,7 @@ impl JobSubmission {
         Self {
             name: J::NAME,
             payload,
+            run_at: None,
         }
     }
 
@@ -152,6 +155,20 @@ impl JobSubmission {
     pub fn payload(&self) -> &Value {
         &self.payload
     }
+
+    /// Set the time at which the job should run, instead of as soon as
+    /// possible.
+    #[must_use]
+    pub fn with_run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+
+    /// The time at which the job should run, if it was set.
+    #[must_use]
+    pub fn run_at(&self) -> Option<DateTime<Utc>> {
+        self.run_at
+    }
 }
 
 /// A [`JobRepository`] is used to schedule jobs to be executed by a worker.
@@ -198,6 +215,22 @@ pub trait JobRepositoryExt {
         &mut self,
         job: J,
     ) -> Result<JobId, Self::Error>;
+
+    /// Schedule a job to be executed no earlier than the given time.
+    ///
+    /// # Parameters
+    ///
+    /// * `job` - The job to schedule.
+    /// * `run_at` - The time at which the job should run.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn schedule_job_at<J: Job + Serialize + Send>(
+        &mut self,
+        job: J,
+        run_at: DateTime<Utc>,
+    ) -> Result<JobId, Self::Error>;
 }
 
 #[async_trait]
@@ -226,12 +259,42 @@ where
         self.schedule_submission(JobSubmission::new_with_span_context(job, span_context))
             .await
     }
+
+    #[tracing::instrument(
+        name = "db.job.schedule_job_at",
+        skip_all,
+        fields(
+            job.name = J::NAME,
+            job.run_at = %run_at,
+        ),
+    )]
+    async fn schedule_job_at<J: Job + Serialize + Send>(
+        &mut self,
+        job: J,
+        run_at: DateTime<Utc>,
+    ) -> Result<JobId, Self::Error> {
+        let span = tracing::Span::current();
+        let ctx = span.context();
+        let span = ctx.span();
+        let span_context = span.span_context();
+
+        self.schedule_submission(
+            JobSubmission::new_with_span_context(job, span_context).with_run_at(run_at),
+        )
+        .await
+    }
 }
 
 mod jobs {
     // XXX: Move this somewhere else?
+    use std::net::IpAddr;
+
     use apalis_core::job::Job;
-    use mas_data_model::{Device, User, UserEmail, UserRecoverySession};
+    use mas_data_model::{
+        BackchannelAuthenticationGrant, BrowserSession, Device, GeoLocation, User, UserEmail,
+        UserEmailChange, UserExport, UserLoginCode, UserMagicLinkSession, UserPhone,
+        UserRecoverySession,
+    };
     use serde::{Deserialize, Serialize};
     use ulid::Ulid;
 
@@ -276,11 +339,54 @@ mod jobs {
         const NAME: &'static str = "verify-email";
     }
 
+    /// A job to verify a phone number, by sending it a code by SMS.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct VerifyPhoneJob {
+        user_phone_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl VerifyPhoneJob {
+        /// Create a new job to verify a phone number.
+        #[must_use]
+        pub fn new(user_phone: &UserPhone) -> Self {
+            Self {
+                user_phone_id: user_phone.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the text message.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the text message.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the phone number to verify.
+        #[must_use]
+        pub fn user_phone_id(&self) -> Ulid {
+            self.user_phone_id
+        }
+    }
+
+    impl Job for VerifyPhoneJob {
+        const NAME: &'static str = "verify-phone";
+    }
+
     /// A job to provision the user on the homeserver.
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct ProvisionUserJob {
         user_id: Ulid,
         set_display_name: Option<String>,
+        #[serde(default)]
+        shadow_ban: bool,
     }
 
     impl ProvisionUserJob {
@@ -290,6 +396,7 @@ mod jobs {
             Self {
                 user_id: user.id,
                 set_display_name: None,
+                shadow_ban: false,
             }
         }
 
@@ -299,6 +406,7 @@ mod jobs {
             Self {
                 user_id,
                 set_display_name: None,
+                shadow_ban: false,
             }
         }
 
@@ -315,6 +423,19 @@ mod jobs {
             self.set_display_name.as_deref()
         }
 
+        /// Shadow-ban the user on the homeserver once it is provisioned.
+        #[must_use]
+        pub fn shadow_ban(mut self) -> Self {
+            self.shadow_ban = true;
+            self
+        }
+
+        /// Whether the user should be shadow-banned.
+        #[must_use]
+        pub fn should_shadow_ban(&self) -> bool {
+            self.shadow_ban
+        }
+
         /// The ID of the user to provision.
         #[must_use]
         pub fn user_id(&self) -> Ulid {
@@ -464,9 +585,415 @@ mod jobs {
     impl Job for SendAccountRecoveryEmailsJob {
         const NAME: &'static str = "send-account-recovery-email";
     }
+
+    /// Send login links by email for a magic link session
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendMagicLinkEmailsJob {
+        user_magic_link_session_id: Ulid,
+    }
+
+    impl SendMagicLinkEmailsJob {
+        /// Create a new job to send login links by email
+        ///
+        /// # Parameters
+        ///
+        /// * `user_magic_link_session` - The magic link session to send the
+        ///   links for
+        #[must_use]
+        pub fn new(user_magic_link_session: &UserMagicLinkSession) -> Self {
+            Self {
+                user_magic_link_session_id: user_magic_link_session.id,
+            }
+        }
+
+        /// The ID of the magic link session to send the links for
+        #[must_use]
+        pub fn user_magic_link_session_id(&self) -> Ulid {
+            self.user_magic_link_session_id
+        }
+    }
+
+    impl Job for SendMagicLinkEmailsJob {
+        const NAME: &'static str = "send-magic-link-emails";
+    }
+
+    /// Check whether a login was made from an unknown device or network, and
+    /// alert the user by email if so
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct CheckLoginJob {
+        user_session_id: Ulid,
+        ip_address: Option<IpAddr>,
+        #[serde(default)]
+        location: Option<GeoLocation>,
+        language: Option<String>,
+    }
+
+    impl CheckLoginJob {
+        /// Create a new job to check the login which started the given browser
+        /// session
+        ///
+        /// # Parameters
+        ///
+        /// * `browser_session` - The browser session started by the login
+        /// * `ip_address` - The IP address the login was made from, if known
+        #[must_use]
+        pub fn new(browser_session: &BrowserSession, ip_address: Option<IpAddr>) -> Self {
+            Self {
+                user_session_id: browser_session.id,
+                ip_address,
+                location: None,
+                language: None,
+            }
+        }
+
+        /// Set the approximate location the login was made from
+        #[must_use]
+        pub fn with_location(mut self, location: Option<GeoLocation>) -> Self {
+            self.location = location;
+            self
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the browser session started by the login
+        #[must_use]
+        pub fn user_session_id(&self) -> Ulid {
+            self.user_session_id
+        }
+
+        /// The IP address the login was made from, if known
+        #[must_use]
+        pub fn ip_address(&self) -> Option<IpAddr> {
+            self.ip_address
+        }
+
+        /// The approximate location the login was made from, if known
+        #[must_use]
+        pub fn location(&self) -> Option<&GeoLocation> {
+            self.location.as_ref()
+        }
+    }
+
+    impl Job for CheckLoginJob {
+        const NAME: &'static str = "check-login";
+    }
+
+    /// Send the code to confirm a login by email
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendLoginCodeEmailJob {
+        user_login_code_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl SendLoginCodeEmailJob {
+        /// Create a new job to send the given login code by email
+        ///
+        /// # Parameters
+        ///
+        /// * `user_login_code` - The login code to send
+        #[must_use]
+        pub fn new(user_login_code: &UserLoginCode) -> Self {
+            Self {
+                user_login_code_id: user_login_code.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the login code to send
+        #[must_use]
+        pub fn user_login_code_id(&self) -> Ulid {
+            self.user_login_code_id
+        }
+    }
+
+    impl Job for SendLoginCodeEmailJob {
+        const NAME: &'static str = "send-login-code-email";
+    }
+
+    /// Ask a user by email to approve a backchannel authentication request
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendBackchannelAuthenticationEmailJob {
+        oauth2_backchannel_authentication_grant_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl SendBackchannelAuthenticationEmailJob {
+        /// Create a new job to ask the user to approve the given backchannel
+        /// authentication grant
+        ///
+        /// # Parameters
+        ///
+        /// * `grant` - The backchannel authentication grant to approve
+        #[must_use]
+        pub fn new(grant: &BackchannelAuthenticationGrant) -> Self {
+            Self {
+                oauth2_backchannel_authentication_grant_id: grant.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the backchannel authentication grant to approve
+        #[must_use]
+        pub fn oauth2_backchannel_authentication_grant_id(&self) -> Ulid {
+            self.oauth2_backchannel_authentication_grant_id
+        }
+    }
+
+    impl Job for SendBackchannelAuthenticationEmailJob {
+        const NAME: &'static str = "send-backchannel-authentication-email";
+    }
+
+    /// Notify the previous address of a user that their primary email address
+    /// changed, with a link to undo the change
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendEmailChangeNotificationJob {
+        user_email_change_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl SendEmailChangeNotificationJob {
+        /// Create a new job to notify the previous address of the given change
+        #[must_use]
+        pub fn new(change: &UserEmailChange) -> Self {
+            Self {
+                user_email_change_id: change.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the email change to notify about
+        #[must_use]
+        pub fn user_email_change_id(&self) -> Ulid {
+            self.user_email_change_id
+        }
+    }
+
+    impl Job for SendEmailChangeNotificationJob {
+        const NAME: &'static str = "send-email-change-notification";
+    }
+
+    /// Notify a user that their password changed
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendPasswordChangeNotificationJob {
+        user_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl SendPasswordChangeNotificationJob {
+        /// Create a new job to notify the given user that their password
+        /// changed
+        #[must_use]
+        pub fn new(user: &User) -> Self {
+            Self {
+                user_id: user.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the notification.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the notification.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the user whose password changed
+        #[must_use]
+        pub fn user_id(&self) -> Ulid {
+            self.user_id
+        }
+    }
+
+    impl Job for SendPasswordChangeNotificationJob {
+        const NAME: &'static str = "send-password-change-notification";
+    }
+
+    /// Generate an export of the data of a user, and send them a link to
+    /// download it by email
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct ExportUserDataJob {
+        user_export_id: Ulid,
+        language: Option<String>,
+    }
+
+    impl ExportUserDataJob {
+        /// Create a new job to generate the given export
+        #[must_use]
+        pub fn new(export: &UserExport) -> Self {
+            Self {
+                user_export_id: export.id,
+                language: None,
+            }
+        }
+
+        /// Set the language to use for the email.
+        #[must_use]
+        pub fn with_language(mut self, language: String) -> Self {
+            self.language = Some(language);
+            self
+        }
+
+        /// The language to use for the email.
+        #[must_use]
+        pub fn language(&self) -> Option<&str> {
+            self.language.as_deref()
+        }
+
+        /// The ID of the export to generate
+        #[must_use]
+        pub fn user_export_id(&self) -> Ulid {
+            self.user_export_id
+        }
+    }
+
+    impl Job for ExportUserDataJob {
+        const NAME: &'static str = "export-user-data";
+    }
+
+    /// Send an already rendered email, retrying with a backoff if it fails
+    ///
+    /// The subject and the content of the email are encrypted, as they may
+    /// contain secrets like codes or links, and jobs are stored as-is in the
+    /// database.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SendEmailJob {
+        from: Option<String>,
+        to: Vec<String>,
+        encrypted_subject: String,
+        encrypted_message: String,
+        attempt: u32,
+    }
+
+    impl SendEmailJob {
+        /// Create a new job to send an email
+        ///
+        /// # Parameters
+        ///
+        /// * `from` - The address of the envelope sender, if any
+        /// * `to` - The addresses of the envelope recipients
+        /// * `encrypted_subject` - The encrypted subject of the email, kept for
+        ///   reporting
+        /// * `encrypted_message` - The encrypted, fully formatted email
+        #[must_use]
+        pub fn new(
+            from: Option<String>,
+            to: Vec<String>,
+            encrypted_subject: String,
+            encrypted_message: String,
+        ) -> Self {
+            Self {
+                from,
+                to,
+                encrypted_subject,
+                encrypted_message,
+                attempt: 0,
+            }
+        }
+
+        /// The same job, for the next attempt at sending the email
+        #[must_use]
+        pub fn next_attempt(&self) -> Self {
+            Self {
+                attempt: self.attempt + 1,
+                ..self.clone()
+            }
+        }
+
+        /// The address of the envelope sender, if any
+        #[must_use]
+        pub fn from(&self) -> Option<&str> {
+            self.from.as_deref()
+        }
+
+        /// The addresses of the envelope recipients
+        #[must_use]
+        pub fn to(&self) -> &[String] {
+            &self.to
+        }
+
+        /// The encrypted subject of the email
+        #[must_use]
+        pub fn encrypted_subject(&self) -> &str {
+            &self.encrypted_subject
+        }
+
+        /// The encrypted, fully formatted email
+        #[must_use]
+        pub fn encrypted_message(&self) -> &str {
+            &self.encrypted_message
+        }
+
+        /// How many times sending this email was already attempted
+        #[must_use]
+        pub fn attempt(&self) -> u32 {
+            self.attempt
+        }
+    }
+
+    impl Job for SendEmailJob {
+        const NAME: &'static str = "send-email";
+    }
 }
 
 pub use self::jobs::{
-    DeactivateUserJob, DeleteDeviceJob, ProvisionDeviceJob, ProvisionUserJob,
-    SendAccountRecoveryEmailsJob, VerifyEmailJob,
+    CheckLoginJob, DeactivateUserJob, DeleteDeviceJob, ExportUserDataJob, ProvisionDeviceJob,
+    ProvisionUserJob, SendAccountRecoveryEmailsJob, SendBackchannelAuthenticationEmailJob,
+    SendEmailChangeNotificationJob, SendEmailJob, SendLoginCodeEmailJob, SendMagicLinkEmailsJob,
+    SendPasswordChangeNotificationJob, VerifyEmailJob, VerifyPhoneJob,
 };
@@ -143,8 +143,12 @@ mod utils;
 
 pub mod app_session;
 pub mod compat;
+pub mod email;
+pub mod idempotency;
 pub mod job;
+pub mod network_ban;
 pub mod oauth2;
+pub mod stats;
 pub mod upstream_oauth2;
 pub mod user;
 
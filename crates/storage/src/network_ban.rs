@@ -0,0 +1,119 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Repository to manage the bans of networks
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Network, NetworkBan};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`NetworkBanRepository`] helps interacting with [`NetworkBan`] saved in
+/// the storage backend
+#[async_trait]
+pub trait NetworkBanRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup a [`NetworkBan`] by its ID
+    ///
+    /// Returns `None` if no [`NetworkBan`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`NetworkBan`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<NetworkBan>, Self::Error>;
+
+    /// Ban a network until the given date
+    ///
+    /// Returns the newly created [`NetworkBan`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `network`: The network to ban
+    /// * `reason`: Why an administrator banned the network, `None` for
+    ///   automatic bans
+    /// * `expires_at`: When the ban ends
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        network: Network,
+        reason: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<NetworkBan, Self::Error>;
+
+    /// Lift a [`NetworkBan`] before it expires
+    ///
+    /// Returns the updated [`NetworkBan`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `network_ban`: The [`NetworkBan`] to lift
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lift(
+        &mut self,
+        clock: &dyn Clock,
+        network_ban: NetworkBan,
+    ) -> Result<NetworkBan, Self::Error>;
+
+    /// List the [`NetworkBan`]s which are still in effect, most recent first
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to get the current time
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn all_active(&mut self, clock: &dyn Clock) -> Result<Vec<NetworkBan>, Self::Error>;
+}
+
+repository_impl!(NetworkBanRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<NetworkBan>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        network: Network,
+        reason: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<NetworkBan, Self::Error>;
+
+    async fn lift(
+        &mut self,
+        clock: &dyn Clock,
+        network_ban: NetworkBan,
+    ) -> Result<NetworkBan, Self::Error>;
+
+    async fn all_active(&mut self, clock: &dyn Clock) -> Result<Vec<NetworkBan>, Self::Error>;
+);
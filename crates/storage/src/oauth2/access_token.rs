@@ -99,6 +99,25 @@ pub trait OAuth2AccessTokenRepository: Send + Sync {
         access_token: AccessToken,
     ) -> Result<AccessToken, Self::Error>;
 
+    /// Bind an access token to the client certificate used to get it
+    ///
+    /// Returns the bound access token
+    ///
+    /// # Parameters
+    ///
+    /// * `access_token`: The access token to bind
+    /// * `certificate_thumbprint`: The base64url-encoded SHA-256 thumbprint of
+    ///   the client certificate
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn bind_to_certificate(
+        &mut self,
+        access_token: AccessToken,
+        certificate_thumbprint: String,
+    ) -> Result<AccessToken, Self::Error>;
+
     /// Cleanup expired access tokens
     ///
     /// Returns the number of access tokens that were cleaned up
@@ -136,5 +155,11 @@ repository_impl!(OAuth2AccessTokenRepository:
         access_token: AccessToken,
     ) -> Result<AccessToken, Self::Error>;
 
+    async fn bind_to_certificate(
+        &mut self,
+        access_token: AccessToken,
+        certificate_thumbprint: String,
+    ) -> Result<AccessToken, Self::Error>;
+
     async fn cleanup_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
 );
@@ -15,7 +15,7 @@
 use std::num::NonZeroU32;
 
 use async_trait::async_trait;
-use mas_data_model::{AuthorizationCode, AuthorizationGrant, Client, Session};
+use mas_data_model::{AuthorizationCode, AuthorizationGrant, BrowserSession, Client, Session};
 use oauth2_types::{requests::ResponseMode, scope::Scope};
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -120,6 +120,37 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
+    /// Start a new [`Session`] out of a [`BrowserSession`] and fulfill the
+    /// authorization grant with it
+    ///
+    /// This is equivalent to calling
+    /// [`OAuth2SessionRepository::add_from_browser_session`] followed by
+    /// [`Self::fulfill`], but in a single round trip to the storage backend.
+    ///
+    /// Returns the newly created [`Session`] and the updated authorization
+    /// grant
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user_session`: The [`BrowserSession`] of the user which completed the
+    ///   authorization
+    /// * `authorization_grant`: The authorization grant to fulfill
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    ///
+    /// [`OAuth2SessionRepository::add_from_browser_session`]: crate::oauth2::OAuth2SessionRepository::add_from_browser_session
+    async fn start_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        authorization_grant: AuthorizationGrant,
+    ) -> Result<(Session, AuthorizationGrant), Self::Error>;
+
     /// Mark an authorization grant as exchanged
     ///
     /// Returns the updated authorization grant
@@ -153,6 +184,25 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
         &mut self,
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
+
+    /// Replace the scope of an authorization grant with the subset of it the
+    /// user agreed to
+    ///
+    /// Returns the updated authorization grant
+    ///
+    /// # Parameters
+    ///
+    /// * `authorization_grant`: The authorization grant to update
+    /// * `scope`: The scope granted by the user
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn restrict_scope(
+        &mut self,
+        authorization_grant: AuthorizationGrant,
+        scope: Scope,
+    ) -> Result<AuthorizationGrant, Self::Error>;
 }
 
 repository_impl!(OAuth2AuthorizationGrantRepository:
@@ -184,6 +234,14 @@ repository_impl!(OAuth2AuthorizationGrantRepository:
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
+    async fn start_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        authorization_grant: AuthorizationGrant,
+    ) -> Result<(Session, AuthorizationGrant), Self::Error>;
+
     async fn exchange(
         &mut self,
         clock: &dyn Clock,
@@ -194,4 +252,10 @@ repository_impl!(OAuth2AuthorizationGrantRepository:
         &mut self,
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
+
+    async fn restrict_scope(
+        &mut self,
+        authorization_grant: AuthorizationGrant,
+        scope: Scope,
+    ) -> Result<AuthorizationGrant, Self::Error>;
 );
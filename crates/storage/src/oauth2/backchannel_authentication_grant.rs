@@ -0,0 +1,234 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use mas_data_model::{
+    BackchannelAuthenticationGrant, BrowserSession, Client, Session, User, UserAgent,
+};
+use oauth2_types::scope::Scope;
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// Parameters used to create a new [`BackchannelAuthenticationGrant`]
+pub struct OAuth2BackchannelAuthenticationGrantParams<'a> {
+    /// The client which requested the backchannel authentication grant
+    pub client: &'a Client,
+
+    /// The scope requested by the client
+    pub scope: Scope,
+
+    /// The user for which the authentication is requested
+    pub user: &'a User,
+
+    /// The identifier of the authentication request, which the client uses to
+    /// poll for authorisation
+    pub auth_req_id: String,
+
+    /// A message to display to the user, which the client also displays
+    pub binding_message: Option<String>,
+
+    /// After how long the authentication request expires
+    pub expires_in: Duration,
+
+    /// IP address from which the request was made
+    pub ip_address: Option<IpAddr>,
+
+    /// The user agent from which the request was made
+    pub user_agent: Option<UserAgent>,
+}
+
+/// An [`OAuth2BackchannelAuthenticationGrantRepository`] helps interacting with
+/// [`BackchannelAuthenticationGrant`] saved in the storage backend.
+#[async_trait]
+pub trait OAuth2BackchannelAuthenticationGrantRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Create a new backchannel authentication grant
+    ///
+    /// Returns the newly created backchannel authentication grant
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: A random number generator
+    /// * `clock`: The clock used to generate timestamps
+    /// * `params`: The parameters used to create the backchannel
+    ///   authentication grant. See the fields of
+    ///   [`OAuth2BackchannelAuthenticationGrantParams`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        params: OAuth2BackchannelAuthenticationGrantParams<'_>,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    /// Lookup a backchannel authentication grant by its ID
+    ///
+    /// Returns the backchannel authentication grant if found, [`None`]
+    /// otherwise
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the backchannel authentication grant
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(
+        &mut self,
+        id: Ulid,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error>;
+
+    /// Lookup a backchannel authentication grant by its authentication request
+    /// ID
+    ///
+    /// Returns the backchannel authentication grant if found, [`None`]
+    /// otherwise
+    ///
+    /// # Parameters
+    ///
+    /// * `auth_req_id`: The authentication request ID of the backchannel
+    ///   authentication grant
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_auth_req_id(
+        &mut self,
+        auth_req_id: &str,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error>;
+
+    /// Mark the backchannel authentication grant as fulfilled with the given
+    /// browser session
+    ///
+    /// Returns the updated backchannel authentication grant
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `grant`: The backchannel authentication grant to fulfill
+    /// * `browser_session`: The browser session which was used to fulfill the
+    ///   backchannel authentication grant
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// backchannel authentication grant is not in the [`Pending`] state
+    ///
+    /// [`Pending`]: mas_data_model::BackchannelAuthenticationGrantState::Pending
+    async fn fulfill(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    /// Mark the backchannel authentication grant as rejected with the given
+    /// browser session
+    ///
+    /// Returns the updated backchannel authentication grant
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `grant`: The backchannel authentication grant to reject
+    /// * `browser_session`: The browser session which was used to reject the
+    ///   backchannel authentication grant
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// backchannel authentication grant is not in the [`Pending`] state
+    ///
+    /// [`Pending`]: mas_data_model::BackchannelAuthenticationGrantState::Pending
+    async fn reject(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    /// Mark the backchannel authentication grant as exchanged and store the
+    /// session which was created
+    ///
+    /// Returns the updated backchannel authentication grant
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `grant`: The backchannel authentication grant to exchange
+    /// * `session`: The OAuth 2.0 session which was created
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// backchannel authentication grant is not in the [`Fulfilled`] state
+    ///
+    /// [`Fulfilled`]: mas_data_model::BackchannelAuthenticationGrantState::Fulfilled
+    async fn exchange(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        session: &Session,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+}
+
+repository_impl!(OAuth2BackchannelAuthenticationGrantRepository:
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        params: OAuth2BackchannelAuthenticationGrantParams<'_>,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    async fn lookup(
+        &mut self,
+        id: Ulid,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error>;
+
+    async fn find_by_auth_req_id(
+        &mut self,
+        auth_req_id: &str,
+    ) -> Result<Option<BackchannelAuthenticationGrant>, Self::Error>;
+
+    async fn fulfill(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    async fn reject(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        browser_session: &BrowserSession,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+
+    async fn exchange(
+        &mut self,
+        clock: &dyn Clock,
+        grant: BackchannelAuthenticationGrant,
+        session: &Session,
+    ) -> Result<BackchannelAuthenticationGrant, Self::Error>;
+);
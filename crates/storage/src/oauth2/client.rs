@@ -16,7 +16,10 @@ use std::collections::{BTreeMap, BTreeSet};
 
 use async_trait::async_trait;
 use mas_data_model::{Client, User};
-use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
+use mas_iana::{
+    jose::JsonWebSignatureAlg,
+    oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod},
+};
 use mas_jose::jwk::PublicJsonWebKeySet;
 use oauth2_types::{oidc::ApplicationType, requests::GrantType, scope::Scope};
 use rand_core::RngCore;
@@ -140,6 +143,24 @@ pub trait OAuth2ClientRepository: Send + Sync {
     /// * `jwks`: The client JWKS, if any
     /// * `jwks_uri`: The client JWKS URI, if any
     /// * `redirect_uris`: The list of redirect URIs used by this client
+    /// * `response_types`: The list of response types this client can use
+    /// * `grant_types`: The list of grant types this client can use
+    /// * `application_type`: The kind of application this client is, if known
+    /// * `skip_consent`: Whether the consent screen is skipped for this client
+    /// * `allowed_scopes`: The scope tokens this client can request, if
+    ///   restricted
+    /// * `default_scope`: The scope used when the client doesn't request any
+    /// * `strip_disallowed_scopes`: Whether scope tokens which are not allowed
+    ///   are removed instead of rejecting the request
+    /// * `client_name`: The human-readable name of the client, if any
+    /// * `client_uri`: The URL of the home page of the client, if any
+    /// * `logo_uri`: The URL of the logo of the client, if any
+    /// * `policy_uri`: The URL of the privacy policy of the client, if any
+    /// * `tos_uri`: The URL of the terms of service of the client, if any
+    /// * `tls_client_auth_san_dns`: The DNS name the client certificate must
+    ///   have, for the `tls_client_auth` authentication method
+    /// * `tls_client_auth_san_email`: The email address the client certificate
+    ///   must have, for the `tls_client_auth` authentication method
     ///
     /// # Errors
     ///
@@ -153,6 +174,20 @@ pub trait OAuth2ClientRepository: Send + Sync {
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        response_types: Vec<OAuthAuthorizationEndpointResponseType>,
+        grant_types: Vec<GrantType>,
+        application_type: Option<ApplicationType>,
+        skip_consent: bool,
+        allowed_scopes: Option<Vec<String>>,
+        default_scope: Option<Scope>,
+        strip_disallowed_scopes: bool,
+        client_name: Option<String>,
+        client_uri: Option<Url>,
+        logo_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+        tls_client_auth_san_dns: Option<String>,
+        tls_client_auth_san_email: Option<String>,
     ) -> Result<Client, Self::Error>;
 
     /// List all static clients
@@ -162,6 +197,14 @@ pub trait OAuth2ClientRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    /// List all clients, static and dynamically registered ones, ordered by
+    /// their ID
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn all(&mut self) -> Result<Vec<Client>, Self::Error>;
+
     /// Get the list of scopes that the user has given consent for the given
     /// client
     ///
@@ -267,10 +310,26 @@ repository_impl!(OAuth2ClientRepository:
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        response_types: Vec<OAuthAuthorizationEndpointResponseType>,
+        grant_types: Vec<GrantType>,
+        application_type: Option<ApplicationType>,
+        skip_consent: bool,
+        allowed_scopes: Option<Vec<String>>,
+        default_scope: Option<Scope>,
+        strip_disallowed_scopes: bool,
+        client_name: Option<String>,
+        client_uri: Option<Url>,
+        logo_uri: Option<Url>,
+        policy_uri: Option<Url>,
+        tos_uri: Option<Url>,
+        tls_client_auth_san_dns: Option<String>,
+        tls_client_auth_san_email: Option<String>,
     ) -> Result<Client, Self::Error>;
 
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    async fn all(&mut self) -> Result<Vec<Client>, Self::Error>;
+
     async fn delete(&mut self, client: Client) -> Result<(), Self::Error>;
 
     async fn delete_by_id(&mut self, id: Ulid) -> Result<(), Self::Error>;
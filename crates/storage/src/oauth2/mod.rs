@@ -16,6 +16,7 @@
 
 mod access_token;
 mod authorization_grant;
+mod backchannel_authentication_grant;
 mod client;
 mod device_code_grant;
 mod refresh_token;
@@ -24,6 +25,9 @@ mod session;
 pub use self::{
     access_token::OAuth2AccessTokenRepository,
     authorization_grant::OAuth2AuthorizationGrantRepository,
+    backchannel_authentication_grant::{
+        OAuth2BackchannelAuthenticationGrantParams, OAuth2BackchannelAuthenticationGrantRepository,
+    },
     client::OAuth2ClientRepository,
     device_code_grant::{OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantRepository},
     refresh_token::OAuth2RefreshTokenRepository,
@@ -15,7 +15,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{BrowserSession, Client, Session, User, UserAgent};
 use oauth2_types::scope::Scope;
 use rand_core::RngCore;
@@ -46,6 +46,8 @@ pub struct OAuth2SessionFilter<'a> {
     client: Option<&'a Client>,
     state: Option<OAuth2SessionState>,
     scope: Option<&'a Scope>,
+    last_active_before: Option<DateTime<Utc>>,
+    exclude_bots: bool,
 }
 
 impl<'a> OAuth2SessionFilter<'a> {
@@ -121,6 +123,39 @@ impl<'a> OAuth2SessionFilter<'a> {
     pub fn scope(&self) -> Option<&Scope> {
         self.scope
     }
+
+    /// Only return sessions which were last active before the given time
+    ///
+    /// Sessions which were never active are matched on their creation time
+    #[must_use]
+    pub fn with_last_active_before(mut self, last_active_before: DateTime<Utc>) -> Self {
+        self.last_active_before = Some(last_active_before);
+        self
+    }
+
+    /// Get the last active before filter
+    ///
+    /// Returns [`None`] if no last active before filter was set
+    #[must_use]
+    pub fn last_active_before(&self) -> Option<DateTime<Utc>> {
+        self.last_active_before
+    }
+
+    /// Don't return the sessions of bot users
+    ///
+    /// Bots use long-lived sessions, which are not subject to the limits
+    /// applied to the sessions of humans
+    #[must_use]
+    pub fn without_bots(mut self) -> Self {
+        self.exclude_bots = true;
+        self
+    }
+
+    /// Whether the sessions of bot users are excluded
+    #[must_use]
+    pub fn excludes_bots(&self) -> bool {
+        self.exclude_bots
+    }
 }
 
 /// An [`OAuth2SessionRepository`] helps interacting with [`Session`]
@@ -272,6 +307,44 @@ pub trait OAuth2SessionRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: OAuth2SessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    /// Forget the IP address of the last activity of the [`Session`]s
+    /// which were last active more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    /// Forget the user agent of the [`Session`]s which were last active
+    /// more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the user agents are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     /// Record a batch of [`Session`] activity
     ///
     /// # Parameters
@@ -341,6 +414,18 @@ repository_impl!(OAuth2SessionRepository:
 
     async fn count(&mut self, filter: OAuth2SessionFilter<'_>) -> Result<usize, Self::Error>;
 
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     async fn record_batch_activity(
         &mut self,
         activity: Vec<(Ulid, DateTime<Utc>, Option<IpAddr>)>,
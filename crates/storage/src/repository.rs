@@ -21,18 +21,25 @@ use crate::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
     },
+    email::EmailDeadLetterRepository,
+    idempotency::IdempotencyKeyRepository,
     job::JobRepository,
+    network_ban::NetworkBanRepository,
     oauth2::{
-        OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository, OAuth2ClientRepository,
+        OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
+        OAuth2BackchannelAuthenticationGrantRepository, OAuth2ClientRepository,
         OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
+    stats::StatsRepository,
     upstream_oauth2::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
     user::{
-        BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
-        UserRecoveryRepository, UserRepository, UserTermsRepository,
+        BrowserSessionRepository, UserEmailChangeRepository, UserEmailRepository,
+        UserExportRepository, UserLoginAlertRepository, UserLoginCodeRepository,
+        UserLoginRiskRepository, UserMagicLinkRepository, UserPasswordRepository,
+        UserPhoneRepository, UserRecoveryRepository, UserRepository, UserTermsRepository,
     },
     MapErr,
 };
@@ -153,6 +160,37 @@ pub trait RepositoryAccess: Send {
     fn user_recovery<'c>(&'c mut self)
         -> Box<dyn UserRecoveryRepository<Error = Self::Error> + 'c>;
 
+    /// Get an [`UserMagicLinkRepository`]
+    fn user_magic_link<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserMagicLinkRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserLoginAlertRepository`]
+    fn user_login_alert<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginAlertRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserLoginCodeRepository`]
+    fn user_login_code<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginCodeRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserLoginRiskRepository`]
+    fn user_login_risk<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginRiskRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserEmailChangeRepository`]
+    fn user_email_change<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserEmailChangeRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserExportRepository`]
+    fn user_export<'c>(&'c mut self) -> Box<dyn UserExportRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserPhoneRepository`]
+    fn user_phone<'c>(&'c mut self) -> Box<dyn UserPhoneRepository<Error = Self::Error> + 'c>;
+
     /// Get an [`UserTermsRepository`]
     fn user_terms<'c>(&'c mut self) -> Box<dyn UserTermsRepository<Error = Self::Error> + 'c>;
 
@@ -193,6 +231,11 @@ pub trait RepositoryAccess: Send {
         &'c mut self,
     ) -> Box<dyn OAuth2DeviceCodeGrantRepository<Error = Self::Error> + 'c>;
 
+    /// Get an [`OAuth2BackchannelAuthenticationGrantRepository`]
+    fn oauth2_backchannel_authentication_grant<'c>(
+        &'c mut self,
+    ) -> Box<dyn OAuth2BackchannelAuthenticationGrantRepository<Error = Self::Error> + 'c>;
+
     /// Get a [`CompatSessionRepository`]
     fn compat_session<'c>(
         &'c mut self,
@@ -215,6 +258,22 @@ pub trait RepositoryAccess: Send {
 
     /// Get a [`JobRepository`]
     fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`EmailDeadLetterRepository`]
+    fn email_dead_letter<'c>(
+        &'c mut self,
+    ) -> Box<dyn EmailDeadLetterRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`IdempotencyKeyRepository`]
+    fn idempotency_key<'c>(
+        &'c mut self,
+    ) -> Box<dyn IdempotencyKeyRepository<Error = Self::Error> + 'c>;
+
+    /// Get a [`StatsRepository`]
+    fn stats<'c>(&'c mut self) -> Box<dyn StatsRepository<Error = Self::Error> + 'c>;
+
+    /// Get a [`NetworkBanRepository`]
+    fn network_ban<'c>(&'c mut self) -> Box<dyn NetworkBanRepository<Error = Self::Error> + 'c>;
 }
 
 /// Implementations of the [`RepositoryAccess`], [`RepositoryTransaction`] and
@@ -229,19 +288,25 @@ mod impls {
             CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
             CompatSsoLoginRepository,
         },
+        email::EmailDeadLetterRepository,
+        idempotency::IdempotencyKeyRepository,
         job::JobRepository,
+        network_ban::NetworkBanRepository,
         oauth2::{
             OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
-            OAuth2ClientRepository, OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository,
-            OAuth2SessionRepository,
+            OAuth2BackchannelAuthenticationGrantRepository, OAuth2ClientRepository,
+            OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository,
         },
+        stats::StatsRepository,
         upstream_oauth2::{
             UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
             UpstreamOAuthSessionRepository,
         },
         user::{
-            BrowserSessionRepository, UserEmailRepository, UserPasswordRepository, UserRepository,
-            UserTermsRepository,
+            BrowserSessionRepository, UserEmailChangeRepository, UserEmailRepository,
+            UserExportRepository, UserLoginAlertRepository, UserLoginCodeRepository,
+            UserLoginRiskRepository, UserMagicLinkRepository, UserPasswordRepository,
+            UserPhoneRepository, UserRepository, UserTermsRepository,
         },
         MapErr, Repository, RepositoryTransaction,
     };
@@ -332,6 +397,49 @@ mod impls {
             Box::new(MapErr::new(self.inner.user_recovery(), &mut self.mapper))
         }
 
+        fn user_magic_link<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserMagicLinkRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_magic_link(), &mut self.mapper))
+        }
+
+        fn user_login_alert<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginAlertRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_login_alert(), &mut self.mapper))
+        }
+
+        fn user_login_code<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginCodeRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_login_code(), &mut self.mapper))
+        }
+
+        fn user_login_risk<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginRiskRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_login_risk(), &mut self.mapper))
+        }
+
+        fn user_email_change<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserEmailChangeRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.user_email_change(),
+                &mut self.mapper,
+            ))
+        }
+
+        fn user_export<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserExportRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_export(), &mut self.mapper))
+        }
+
+        fn user_phone<'c>(&'c mut self) -> Box<dyn UserPhoneRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_phone(), &mut self.mapper))
+        }
+
         fn user_terms<'c>(&'c mut self) -> Box<dyn UserTermsRepository<Error = Self::Error> + 'c> {
             Box::new(MapErr::new(self.inner.user_terms(), &mut self.mapper))
         }
@@ -396,6 +504,16 @@ mod impls {
             ))
         }
 
+        fn oauth2_backchannel_authentication_grant<'c>(
+            &'c mut self,
+        ) -> Box<dyn OAuth2BackchannelAuthenticationGrantRepository<Error = Self::Error> + 'c>
+        {
+            Box::new(MapErr::new(
+                self.inner.oauth2_backchannel_authentication_grant(),
+                &mut self.mapper,
+            ))
+        }
+
         fn compat_session<'c>(
             &'c mut self,
         ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -429,6 +547,31 @@ mod impls {
         fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
             Box::new(MapErr::new(self.inner.job(), &mut self.mapper))
         }
+
+        fn email_dead_letter<'c>(
+            &'c mut self,
+        ) -> Box<dyn EmailDeadLetterRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.email_dead_letter(),
+                &mut self.mapper,
+            ))
+        }
+
+        fn idempotency_key<'c>(
+            &'c mut self,
+        ) -> Box<dyn IdempotencyKeyRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.idempotency_key(), &mut self.mapper))
+        }
+
+        fn stats<'c>(&'c mut self) -> Box<dyn StatsRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.stats(), &mut self.mapper))
+        }
+
+        fn network_ban<'c>(
+            &'c mut self,
+        ) -> Box<dyn NetworkBanRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.network_ban(), &mut self.mapper))
+        }
     }
 
     impl<R: RepositoryAccess + ?Sized> RepositoryAccess for Box<R> {
@@ -472,6 +615,46 @@ mod impls {
             (**self).user_recovery()
         }
 
+        fn user_magic_link<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserMagicLinkRepository<Error = Self::Error> + 'c> {
+            (**self).user_magic_link()
+        }
+
+        fn user_login_alert<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginAlertRepository<Error = Self::Error> + 'c> {
+            (**self).user_login_alert()
+        }
+
+        fn user_login_code<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginCodeRepository<Error = Self::Error> + 'c> {
+            (**self).user_login_code()
+        }
+
+        fn user_login_risk<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginRiskRepository<Error = Self::Error> + 'c> {
+            (**self).user_login_risk()
+        }
+
+        fn user_email_change<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserEmailChangeRepository<Error = Self::Error> + 'c> {
+            (**self).user_email_change()
+        }
+
+        fn user_export<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserExportRepository<Error = Self::Error> + 'c> {
+            (**self).user_export()
+        }
+
+        fn user_phone<'c>(&'c mut self) -> Box<dyn UserPhoneRepository<Error = Self::Error> + 'c> {
+            (**self).user_phone()
+        }
+
         fn user_terms<'c>(&'c mut self) -> Box<dyn UserTermsRepository<Error = Self::Error> + 'c> {
             (**self).user_terms()
         }
@@ -524,6 +707,13 @@ mod impls {
             (**self).oauth2_device_code_grant()
         }
 
+        fn oauth2_backchannel_authentication_grant<'c>(
+            &'c mut self,
+        ) -> Box<dyn OAuth2BackchannelAuthenticationGrantRepository<Error = Self::Error> + 'c>
+        {
+            (**self).oauth2_backchannel_authentication_grant()
+        }
+
         fn compat_session<'c>(
             &'c mut self,
         ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -551,5 +741,27 @@ mod impls {
         fn job<'c>(&'c mut self) -> Box<dyn JobRepository<Error = Self::Error> + 'c> {
             (**self).job()
         }
+
+        fn email_dead_letter<'c>(
+            &'c mut self,
+        ) -> Box<dyn EmailDeadLetterRepository<Error = Self::Error> + 'c> {
+            (**self).email_dead_letter()
+        }
+
+        fn idempotency_key<'c>(
+            &'c mut self,
+        ) -> Box<dyn IdempotencyKeyRepository<Error = Self::Error> + 'c> {
+            (**self).idempotency_key()
+        }
+
+        fn stats<'c>(&'c mut self) -> Box<dyn StatsRepository<Error = Self::Error> + 'c> {
+            (**self).stats()
+        }
+
+        fn network_ban<'c>(
+            &'c mut self,
+        ) -> Box<dyn NetworkBanRepository<Error = Self::Error> + 'c> {
+            (**self).network_ban()
+        }
     }
 }
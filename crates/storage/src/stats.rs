@@ -0,0 +1,98 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Repositories to read aggregated statistics about the usage of the service
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use mas_data_model::{ClientDailyCount, DailyCount};
+
+use crate::repository_impl;
+
+/// A [`StatsRepository`] gives access to aggregated statistics, computed
+/// periodically from the rest of the data
+#[async_trait]
+pub trait StatsRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Recompute the statistics from the current data
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn refresh(&mut self) -> Result<(), Self::Error>;
+
+    /// Get the number of authentications per day, starting from the given
+    /// day, ordered by day
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: The first day to include
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn daily_authentications(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyCount>, Self::Error>;
+
+    /// Get the number of users registered per day, starting from the given
+    /// day, ordered by day
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: The first day to include
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn daily_registrations(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyCount>, Self::Error>;
+
+    /// Get the number of access tokens issued per client and per day, starting
+    /// from the given day, ordered by day
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: The first day to include
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn daily_oauth2_tokens(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<ClientDailyCount>, Self::Error>;
+}
+
+repository_impl!(StatsRepository:
+    async fn refresh(&mut self) -> Result<(), Self::Error>;
+
+    async fn daily_authentications(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyCount>, Self::Error>;
+
+    async fn daily_registrations(&mut self, since: NaiveDate)
+        -> Result<Vec<DailyCount>, Self::Error>;
+
+    async fn daily_oauth2_tokens(
+        &mut self,
+        since: NaiveDate,
+    ) -> Result<Vec<ClientDailyCount>, Self::Error>;
+);
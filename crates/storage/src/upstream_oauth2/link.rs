@@ -13,7 +13,8 @@
 // limitations under the License.
 
 use async_trait::async_trait;
-use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthProvider, User};
+use chrono::{DateTime, Utc};
+use mas_data_model::{UpstreamOAuthLink, UpstreamOAuthLinkToken, UpstreamOAuthProvider, User};
 use rand_core::RngCore;
 use ulid::Ulid;
 
@@ -192,6 +193,63 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
     ///
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: UpstreamOAuthLinkFilter<'_>) -> Result<usize, Self::Error>;
+
+    /// Lookup the tokens obtained from the upstream provider for a link
+    ///
+    /// Returns `None` if no tokens were saved for this link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link to lookup the tokens
+    ///   for
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkToken>, Self::Error>;
+
+    /// Save the tokens obtained from the upstream provider for a link,
+    /// replacing the previous ones
+    ///
+    /// Returns the saved tokens
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `upstream_oauth_link`: The upstream OAuth link to save the tokens for
+    /// * `encrypted_access_token`: The encrypted access token
+    /// * `encrypted_refresh_token`: The encrypted refresh token, if any
+    /// * `access_token_expires_at`: When the access token expires, if known
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn save_token(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkToken, Self::Error>;
+
+    /// Remove the tokens saved for a link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link to remove the tokens
+    ///   of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<(), Self::Error>;
 }
 
 repository_impl!(UpstreamOAuthLinkRepository:
@@ -224,4 +282,23 @@ repository_impl!(UpstreamOAuthLinkRepository:
     ) -> Result<Page<UpstreamOAuthLink>, Self::Error>;
 
     async fn count(&mut self, filter: UpstreamOAuthLinkFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn lookup_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<Option<UpstreamOAuthLinkToken>, Self::Error>;
+
+    async fn save_token(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_link: &UpstreamOAuthLink,
+        encrypted_access_token: String,
+        encrypted_refresh_token: Option<String>,
+        access_token_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<UpstreamOAuthLinkToken, Self::Error>;
+
+    async fn remove_token(
+        &mut self,
+        upstream_oauth_link: &UpstreamOAuthLink,
+    ) -> Result<(), Self::Error>;
 );
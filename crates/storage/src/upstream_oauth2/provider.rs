@@ -17,7 +17,7 @@ use std::marker::PhantomData;
 use async_trait::async_trait;
 use mas_data_model::{
     UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
-    UpstreamOAuthProviderPkceMode,
+    UpstreamOAuthProviderOnDeprovision, UpstreamOAuthProviderPkceMode,
 };
 use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
 use oauth2_types::scope::Scope;
@@ -77,6 +77,12 @@ pub struct UpstreamOAuthProviderParams {
 
     /// Additional parameters to include in the authorization request
     pub additional_authorization_parameters: Vec<(String, String)>,
+
+    /// Whether the tokens obtained from the provider should be kept
+    pub store_tokens: bool,
+
+    /// What to do when the provider says a user is gone
+    pub on_deprovision: UpstreamOAuthProviderOnDeprovision,
 }
 
 /// Filter parameters for listing upstream OAuth 2.0 providers
@@ -0,0 +1,152 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use mas_data_model::{User, UserEmailChange};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserEmailChangeRepository`] helps keeping track of the changes of the
+/// primary email address of users
+#[async_trait]
+pub trait UserEmailChangeRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserEmailChange`] by its ID
+    ///
+    /// Returns `None` if no [`UserEmailChange`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserEmailChange`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    /// Find an [`UserEmailChange`] by its undo ticket
+    ///
+    /// Returns `None` if no [`UserEmailChange`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `undo_ticket`: The undo ticket of the [`UserEmailChange`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_undo_ticket(
+        &mut self,
+        undo_ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    /// Start changing the primary email address of a [`User`]
+    ///
+    /// Returns the newly created, pending, [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user`: The [`User`] changing their email address
+    /// * `old_email`: The current primary email address
+    /// * `new_email`: The new email address
+    /// * `undo_ticket`: The ticket sent to the current address to undo the
+    ///   change
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        old_email: String,
+        new_email: String,
+        undo_ticket: String,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    /// Mark an [`UserEmailChange`] as confirmed, once the new address was
+    /// verified
+    ///
+    /// Returns the updated [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `change`: The [`UserEmailChange`] to mark as confirmed
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn mark_as_confirmed(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    /// Mark an [`UserEmailChange`] as undone
+    ///
+    /// Returns the updated [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `change`: The [`UserEmailChange`] to mark as undone
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn mark_as_undone(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+}
+
+repository_impl!(UserEmailChangeRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    async fn find_by_undo_ticket(
+        &mut self,
+        undo_ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        old_email: String,
+        new_email: String,
+        undo_ticket: String,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    async fn mark_as_confirmed(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    async fn mark_as_undone(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+);
@@ -0,0 +1,135 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use mas_data_model::{User, UserExport};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserExportRepository`] helps keeping track of the exports of the data
+/// of users
+#[async_trait]
+pub trait UserExportRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserExport`] by its ID
+    ///
+    /// Returns `None` if no [`UserExport`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserExport`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserExport>, Self::Error>;
+
+    /// Find the most recent [`UserExport`] of a [`User`]
+    ///
+    /// Returns `None` if the user never exported their data
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to find the export of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_latest(&mut self, user: &User) -> Result<Option<UserExport>, Self::Error>;
+
+    /// Start exporting the data of a [`User`]
+    ///
+    /// Returns the newly created, pending, [`UserExport`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user`: The [`User`] whose data is exported
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+    ) -> Result<UserExport, Self::Error>;
+
+    /// Save the generated data of an [`UserExport`], and mark it as completed
+    ///
+    /// Returns the updated [`UserExport`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `export`: The [`UserExport`] to complete
+    /// * `data`: The exported data
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        export: UserExport,
+        data: serde_json::Value,
+    ) -> Result<UserExport, Self::Error>;
+
+    /// Get the data of a completed [`UserExport`]
+    ///
+    /// Returns `None` if the export is still pending
+    ///
+    /// # Parameters
+    ///
+    /// * `export`: The [`UserExport`] to get the data of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn get_data(
+        &mut self,
+        export: &UserExport,
+    ) -> Result<Option<serde_json::Value>, Self::Error>;
+}
+
+repository_impl!(UserExportRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserExport>, Self::Error>;
+
+    async fn find_latest(&mut self, user: &User) -> Result<Option<UserExport>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+    ) -> Result<UserExport, Self::Error>;
+
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        export: UserExport,
+        data: serde_json::Value,
+    ) -> Result<UserExport, Self::Error>;
+
+    async fn get_data(
+        &mut self,
+        export: &UserExport,
+    ) -> Result<Option<serde_json::Value>, Self::Error>;
+);
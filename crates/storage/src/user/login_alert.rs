@@ -0,0 +1,286 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{BrowserSession, LoginSighting, User, UserLoginAlert};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserLoginAlertRepository`] helps keeping track of the devices and
+/// networks users log in from, and of the [`UserLoginAlert`] sent to them
+#[async_trait]
+pub trait UserLoginAlertRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Check how a login of the given [`User`] from a device and network
+    /// compares to their previous logins, without recording it
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] who is logging in
+    /// * `user_agent`: The device used to log in, as summarized by
+    ///   [`LoginSighting::device_of`]
+    /// * `ip_network`: The network the login is made from
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn check_sighting(
+        &mut self,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error>;
+
+    /// Record a login of the given [`User`] from a device and network
+    ///
+    /// Returns how this login compares to the previous logins of the user
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user`: The [`User`] who logged in
+    /// * `user_agent`: The device used to log in, as summarized by
+    ///   [`LoginSighting::device_of`]
+    /// * `ip_network`: The network the login was made from
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record_sighting(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error>;
+
+    /// Find the network and time of the most recent login of the given
+    /// [`User`]
+    ///
+    /// Returns `None` if the user never logged in, or if their previous
+    /// logins were forgotten
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to look up
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn last_sighting(
+        &mut self,
+        user: &User,
+    ) -> Result<Option<(IpAddr, DateTime<Utc>)>, Self::Error>;
+
+    /// Lookup an [`UserLoginAlert`] by its ID
+    ///
+    /// Returns `None` if no [`UserLoginAlert`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserLoginAlert`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginAlert>, Self::Error>;
+
+    /// Find an [`UserLoginAlert`] by its ticket
+    ///
+    /// Returns `None` if no [`UserLoginAlert`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `ticket`: The ticket of the [`UserLoginAlert`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_ticket(&mut self, ticket: &str)
+        -> Result<Option<UserLoginAlert>, Self::Error>;
+
+    /// Create a new [`UserLoginAlert`] for the given [`BrowserSession`]
+    ///
+    /// Returns the newly created [`UserLoginAlert`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `browser_session`: The [`BrowserSession`] which was started by the
+    ///   login
+    /// * `ip_address`: The IP address the login was made from, if known
+    /// * `ticket`: The ticket used to report the login
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        browser_session: &BrowserSession,
+        ip_address: Option<IpAddr>,
+        ticket: String,
+    ) -> Result<UserLoginAlert, Self::Error>;
+
+    /// Mark an [`UserLoginAlert`] as reported by the user
+    ///
+    /// Returns the updated [`UserLoginAlert`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `alert`: The [`UserLoginAlert`] to mark as reported
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// alert was already reported
+    async fn mark_as_reported(
+        &mut self,
+        clock: &dyn Clock,
+        alert: UserLoginAlert,
+    ) -> Result<UserLoginAlert, Self::Error>;
+
+    /// Forget the IP address of the [`UserLoginAlert`]s created more than
+    /// `retention` ago
+    ///
+    /// Returns the number of alerts updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    /// Forget the user agent of the [`UserLoginAlert`]s created more than
+    /// `retention` ago
+    ///
+    /// Returns the number of alerts updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the user agents are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    /// Forget the devices and networks which were last used to log in more
+    /// than `retention` ago
+    ///
+    /// The next login from one of them is treated as a login from a new
+    /// device and network
+    ///
+    /// Returns the number of sightings removed
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the sightings are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_sightings(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserLoginAlertRepository:
+    async fn check_sighting(
+        &mut self,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error>;
+
+    async fn record_sighting(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: &str,
+        ip_network: IpAddr,
+    ) -> Result<LoginSighting, Self::Error>;
+
+    async fn last_sighting(
+        &mut self,
+        user: &User,
+    ) -> Result<Option<(IpAddr, DateTime<Utc>)>, Self::Error>;
+
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginAlert>, Self::Error>;
+
+    async fn find_by_ticket(&mut self, ticket: &str)
+        -> Result<Option<UserLoginAlert>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        browser_session: &BrowserSession,
+        ip_address: Option<IpAddr>,
+        ticket: String,
+    ) -> Result<UserLoginAlert, Self::Error>;
+
+    async fn mark_as_reported(
+        &mut self,
+        clock: &dyn Clock,
+        alert: UserLoginAlert,
+    ) -> Result<UserLoginAlert, Self::Error>;
+
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    async fn forget_sightings(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+);
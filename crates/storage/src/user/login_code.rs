@@ -0,0 +1,184 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use mas_data_model::{Password, UserAgent, UserEmail, UserLoginCode};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserLoginCodeRepository`] helps interacting with [`UserLoginCode`]
+/// saved in the storage backend
+#[async_trait]
+pub trait UserLoginCodeRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserLoginCode`] by its ID
+    ///
+    /// Returns `None` if no [`UserLoginCode`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserLoginCode`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginCode>, Self::Error>;
+
+    /// Create a new [`UserLoginCode`] for a login with the given password,
+    /// sent to the given [`UserEmail`]
+    ///
+    /// Returns the newly created [`UserLoginCode`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user_email`: The [`UserEmail`] the code is sent to
+    /// * `user_password`: The [`Password`] used to log in
+    /// * `user_agent`: The user agent of the browser used to log in
+    /// * `ip_address`: The IP address the login was made from, if known
+    /// * `code`: The code to send
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    #[allow(clippy::too_many_arguments)]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_email: &UserEmail,
+        user_password: &Password,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        code: String,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    /// Record a wrong attempt at entering the [`UserLoginCode`]
+    ///
+    /// Returns the updated [`UserLoginCode`]
+    ///
+    /// # Parameters
+    ///
+    /// * `user_login_code`: The [`UserLoginCode`] to update
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record_attempt(
+        &mut self,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    /// Record that the [`UserLoginCode`] was sent again
+    ///
+    /// Returns the updated [`UserLoginCode`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `user_login_code`: The [`UserLoginCode`] to update
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn record_resend(
+        &mut self,
+        clock: &dyn Clock,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    /// Consume a [`UserLoginCode`], once the right code was entered
+    ///
+    /// Returns the consumed [`UserLoginCode`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `user_login_code`: The [`UserLoginCode`] to consume
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// code was already consumed
+    async fn consume(
+        &mut self,
+        clock: &dyn Clock,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    /// Forget the IP address of the [`UserLoginCode`]s created more than
+    /// `retention` ago
+    ///
+    /// Returns the number of codes updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserLoginCodeRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserLoginCode>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_email: &UserEmail,
+        user_password: &Password,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        code: String,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    async fn record_attempt(
+        &mut self,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    async fn record_resend(
+        &mut self,
+        clock: &dyn Clock,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    async fn consume(
+        &mut self,
+        clock: &dyn Clock,
+        user_login_code: UserLoginCode,
+    ) -> Result<UserLoginCode, Self::Error>;
+
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+);
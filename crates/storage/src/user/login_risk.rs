@@ -0,0 +1,120 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use mas_data_model::{LoginRiskAssessment, LoginRiskDecision, LoginRiskSignal, User, UserAgent};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserLoginRiskRepository`] helps keeping an audit trail of the
+/// [`LoginRiskAssessment`]s made on password logins
+#[async_trait]
+pub trait UserLoginRiskRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`LoginRiskAssessment`] by its ID
+    ///
+    /// Returns `None` if no [`LoginRiskAssessment`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`LoginRiskAssessment`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<LoginRiskAssessment>, Self::Error>;
+
+    /// Record the [`LoginRiskAssessment`] of a login of the given [`User`]
+    ///
+    /// Returns the newly created [`LoginRiskAssessment`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user`: The [`User`] who is logging in
+    /// * `user_agent`: The user agent of the browser used to log in
+    /// * `ip_address`: The IP address the login was made from, if known
+    /// * `request_id`: The ID of the login request, to correlate it with the
+    ///   logs
+    /// * `score`: The risk score of the login
+    /// * `signals`: The signals which matched the login
+    /// * `decision`: The decision taken on the login
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    #[allow(clippy::too_many_arguments)]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        request_id: Option<String>,
+        score: u32,
+        signals: Vec<LoginRiskSignal>,
+        decision: LoginRiskDecision,
+    ) -> Result<LoginRiskAssessment, Self::Error>;
+
+    /// Forget the IP address of the [`LoginRiskAssessment`]s created more than
+    /// `retention` ago
+    ///
+    /// Returns the number of assessments updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserLoginRiskRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<LoginRiskAssessment>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        user_agent: Option<UserAgent>,
+        ip_address: Option<IpAddr>,
+        request_id: Option<String>,
+        score: u32,
+        signals: Vec<LoginRiskSignal>,
+        decision: LoginRiskDecision,
+    ) -> Result<LoginRiskAssessment, Self::Error>;
+
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+);
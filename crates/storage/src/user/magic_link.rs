@@ -0,0 +1,190 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use mas_data_model::{UserAgent, UserEmail, UserMagicLink, UserMagicLinkSession};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserMagicLinkRepository`] helps interacting with [`UserMagicLinkSession`]
+/// and [`UserMagicLink`] saved in the storage backend
+#[async_trait]
+pub trait UserMagicLinkRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserMagicLinkSession`] by its ID
+    ///
+    /// Returns `None` if no [`UserMagicLinkSession`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserMagicLinkSession`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup_session(
+        &mut self,
+        id: Ulid,
+    ) -> Result<Option<UserMagicLinkSession>, Self::Error>;
+
+    /// Create a new [`UserMagicLinkSession`] for the given email
+    ///
+    /// Returns the newly created [`UserMagicLinkSession`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `email`: The email to create the session for
+    /// * `user_agent`: The user agent of the browser which initiated the
+    ///   session
+    /// * `ip_address`: The IP address of the browser which initiated the
+    ///   session, if known
+    /// * `locale`: The locale of the browser which initiated the session
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        email: String,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserMagicLinkSession, Self::Error>;
+
+    /// Find a [`UserMagicLink`] by its ticket
+    ///
+    /// Returns `None` if no [`UserMagicLink`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `ticket`: The ticket of the [`UserMagicLink`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_link(&mut self, ticket: &str) -> Result<Option<UserMagicLink>, Self::Error>;
+
+    /// Add a [`UserMagicLink`] to the given [`UserMagicLinkSession`] for
+    /// the given [`UserEmail`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `session`: The [`UserMagicLinkSession`] to add the link to
+    /// * `user_email`: The [`UserEmail`] to add the link for
+    /// * `ticket`: The secret ticket of the link
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_magic_link_session: &UserMagicLinkSession,
+        user_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserMagicLink, Self::Error>;
+
+    /// Consume a [`UserMagicLink`] and mark the session as used
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use to record the time of consumption
+    /// * `link`: The [`UserMagicLink`] to consume
+    /// * `session`: The [`UserMagicLinkSession`] to mark as used
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails or if the
+    /// magic link session was already used
+    async fn consume_link(
+        &mut self,
+        clock: &dyn Clock,
+        user_magic_link: UserMagicLink,
+        user_magic_link_session: UserMagicLinkSession,
+    ) -> Result<UserMagicLinkSession, Self::Error>;
+
+    /// Forget the IP address of the [`UserMagicLinkSession`]s created more
+    /// than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserMagicLinkRepository:
+    async fn lookup_session(&mut self, id: Ulid) -> Result<Option<UserMagicLinkSession>, Self::Error>;
+
+    async fn add_session(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        email: String,
+        user_agent: UserAgent,
+        ip_address: Option<IpAddr>,
+        locale: String,
+    ) -> Result<UserMagicLinkSession, Self::Error>;
+
+    async fn find_link(
+        &mut self,
+        ticket: &str,
+    ) -> Result<Option<UserMagicLink>, Self::Error>;
+
+    async fn add_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_magic_link_session: &UserMagicLinkSession,
+        user_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserMagicLink, Self::Error>;
+
+    async fn consume_link(
+        &mut self,
+        clock: &dyn Clock,
+        user_magic_link: UserMagicLink,
+        user_magic_link_session: UserMagicLinkSession,
+    ) -> Result<UserMagicLinkSession, Self::Error>;
+
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+);
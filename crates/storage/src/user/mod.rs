@@ -19,22 +19,112 @@ use mas_data_model::User;
 use rand_core::RngCore;
 use ulid::Ulid;
 
-use crate::{repository_impl, Clock};
+use crate::{pagination::Page, repository_impl, Clock, Pagination};
 
 mod email;
+mod email_change;
+mod export;
+mod login_alert;
+mod login_code;
+mod login_risk;
+mod magic_link;
 mod password;
+mod phone;
 mod recovery;
 mod session;
 mod terms;
 
 pub use self::{
     email::{UserEmailFilter, UserEmailRepository},
+    email_change::UserEmailChangeRepository,
+    export::UserExportRepository,
+    login_alert::UserLoginAlertRepository,
+    login_code::UserLoginCodeRepository,
+    login_risk::UserLoginRiskRepository,
+    magic_link::UserMagicLinkRepository,
     password::UserPasswordRepository,
+    phone::UserPhoneRepository,
     recovery::UserRecoveryRepository,
     session::{BrowserSessionFilter, BrowserSessionRepository},
     terms::UserTermsRepository,
 };
 
+/// The state of a [`User`] to filter on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserState {
+    /// The user is active
+    Active,
+
+    /// The user is locked
+    Locked,
+}
+
+impl UserState {
+    /// Returns true if the filter should only return active users
+    #[must_use]
+    pub fn is_active(self) -> bool {
+        matches!(self, Self::Active)
+    }
+
+    /// Returns true if the filter should only return locked users
+    #[must_use]
+    pub fn is_locked(self) -> bool {
+        matches!(self, Self::Locked)
+    }
+}
+
+/// Filter parameters for listing users
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct UserFilter<'a> {
+    search: Option<&'a str>,
+    state: Option<UserState>,
+}
+
+impl<'a> UserFilter<'a> {
+    /// Create a new [`UserFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter for users whose username contains the given string
+    #[must_use]
+    pub fn matching(mut self, search: &'a str) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    /// Filter for active users
+    #[must_use]
+    pub fn active_only(mut self) -> Self {
+        self.state = Some(UserState::Active);
+        self
+    }
+
+    /// Filter for locked users
+    #[must_use]
+    pub fn locked_only(mut self) -> Self {
+        self.state = Some(UserState::Locked);
+        self
+    }
+
+    /// Get the search filter
+    ///
+    /// Returns [`None`] if no search filter is set
+    #[must_use]
+    pub fn search(&self) -> Option<&str> {
+        self.search
+    }
+
+    /// Get the state filter
+    ///
+    /// Returns [`None`] if no state filter is set
+    #[must_use]
+    pub fn state(&self) -> Option<UserState> {
+        self.state
+    }
+}
+
 /// A [`UserRepository`] helps interacting with [`User`] saved in the storage
 /// backend
 #[async_trait]
@@ -128,6 +218,33 @@ pub trait UserRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn unlock(&mut self, user: User) -> Result<User, Self::Error>;
 
+    /// Shadow-ban a [`User`]
+    ///
+    /// Returns the shadow-banned [`User`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The [`User`] to shadow-ban
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn shadow_ban(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
+
+    /// Lift the shadow-ban of a [`User`]
+    ///
+    /// Returns the [`User`] without the shadow-ban
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to lift the shadow-ban of
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lift_shadow_ban(&mut self, user: User) -> Result<User, Self::Error>;
+
     /// Set whether a [`User`] can request admin
     ///
     /// Returns the [`User`] with the new `can_request_admin` value
@@ -144,6 +261,112 @@ pub trait UserRepository: Send + Sync {
         user: User,
         can_request_admin: bool,
     ) -> Result<User, Self::Error>;
+
+    /// Set whether a [`User`] is a non-interactive account, used by a bot or
+    /// a bridge
+    ///
+    /// Returns the [`User`] with the new `is_bot` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `is_bot`: Whether the user is a bot
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_bot(&mut self, user: User, is_bot: bool) -> Result<User, Self::Error>;
+
+    /// Set whether a [`User`] is a guest account
+    ///
+    /// Returns the [`User`] with the new `is_guest` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `is_guest`: Whether the user is a guest
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_guest(&mut self, user: User, is_guest: bool) -> Result<User, Self::Error>;
+
+    /// Set the display name of a [`User`]
+    ///
+    /// Returns the [`User`] with the new display name
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `display_name`: The new display name, or `None` to remove it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_display_name(
+        &mut self,
+        user: User,
+        display_name: Option<String>,
+    ) -> Result<User, Self::Error>;
+
+    /// Set the avatar URL of a [`User`]
+    ///
+    /// Returns the [`User`] with the new avatar URL
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `avatar_url`: The new `mxc://` avatar URL, or `None` to remove it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_avatar_url(
+        &mut self,
+        user: User,
+        avatar_url: Option<String>,
+    ) -> Result<User, Self::Error>;
+
+    /// Set the groups of a [`User`], as imported from an upstream provider
+    ///
+    /// Returns the [`User`] with the new groups
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `groups`: The new list of groups
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_groups(&mut self, user: User, groups: Vec<String>) -> Result<User, Self::Error>;
+
+    /// List [`User`] with the given filter and pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: UserFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<User>, Self::Error>;
+
+    /// Count the [`User`] with the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: UserFilter<'_>) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(UserRepository:
@@ -158,9 +381,30 @@ repository_impl!(UserRepository:
     async fn exists(&mut self, username: &str) -> Result<bool, Self::Error>;
     async fn lock(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
     async fn unlock(&mut self, user: User) -> Result<User, Self::Error>;
+    async fn shadow_ban(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
+    async fn lift_shadow_ban(&mut self, user: User) -> Result<User, Self::Error>;
     async fn set_can_request_admin(
         &mut self,
         user: User,
         can_request_admin: bool,
     ) -> Result<User, Self::Error>;
+    async fn set_bot(&mut self, user: User, is_bot: bool) -> Result<User, Self::Error>;
+    async fn set_guest(&mut self, user: User, is_guest: bool) -> Result<User, Self::Error>;
+    async fn set_display_name(
+        &mut self,
+        user: User,
+        display_name: Option<String>,
+    ) -> Result<User, Self::Error>;
+    async fn set_avatar_url(
+        &mut self,
+        user: User,
+        avatar_url: Option<String>,
+    ) -> Result<User, Self::Error>;
+    async fn set_groups(&mut self, user: User, groups: Vec<String>) -> Result<User, Self::Error>;
+    async fn list(
+        &mut self,
+        filter: UserFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<User>, Self::Error>;
+    async fn count(&mut self, filter: UserFilter<'_>) -> Result<usize, Self::Error>;
 );
@@ -27,7 +27,8 @@ pub trait UserPasswordRepository: Send + Sync {
 
     /// Get the active password for a user
     ///
-    /// Returns `None` if the user has no password set
+    /// Returns `None` if the user has no password set, or if it was
+    /// invalidated
     ///
     /// # Parameters
     ///
@@ -63,6 +64,19 @@ pub trait UserPasswordRepository: Send + Sync {
         hashed_password: String,
         upgraded_from: Option<&Password>,
     ) -> Result<Password, Self::Error>;
+
+    /// Invalidate the password of a user, so that it can't be used to log in
+    /// until a new one is set
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The user whose password to invalidate
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if underlying repository fails
+    async fn invalidate(&mut self, clock: &dyn Clock, user: &User) -> Result<(), Self::Error>;
 }
 
 repository_impl!(UserPasswordRepository:
@@ -76,4 +90,5 @@ repository_impl!(UserPasswordRepository:
         hashed_password: String,
         upgraded_from: Option<&Password>,
     ) -> Result<Password, Self::Error>;
+    async fn invalidate(&mut self, clock: &dyn Clock, user: &User) -> Result<(), Self::Error>;
 );
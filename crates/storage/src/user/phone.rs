@@ -0,0 +1,232 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use mas_data_model::{User, UserPhone, UserPhoneVerification};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{repository_impl, Clock};
+
+/// A [`UserPhoneRepository`] helps interacting with [`UserPhone`] saved in the
+/// storage backend
+#[async_trait]
+pub trait UserPhoneRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserPhone`] by its ID
+    ///
+    /// Returns `None` if no [`UserPhone`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserPhone`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserPhone>, Self::Error>;
+
+    /// Lookup an [`UserPhone`] by its phone number for a [`User`]
+    ///
+    /// Returns `None` if no matching [`UserPhone`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] for whom to lookup the [`UserPhone`]
+    /// * `phone_number`: The phone number to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find(
+        &mut self,
+        user: &User,
+        phone_number: &str,
+    ) -> Result<Option<UserPhone>, Self::Error>;
+
+    /// Get all [`UserPhone`] of a [`User`]
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] for whom to lookup the [`UserPhone`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn all(&mut self, user: &User) -> Result<Vec<UserPhone>, Self::Error>;
+
+    /// Create a new [`UserPhone`] for a [`User`]
+    ///
+    /// Returns the newly created [`UserPhone`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user`: The [`User`] for whom to create the [`UserPhone`]
+    /// * `phone_number`: The phone number of the [`UserPhone`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        phone_number: String,
+    ) -> Result<UserPhone, Self::Error>;
+
+    /// Delete a [`UserPhone`], along with its verification codes
+    ///
+    /// # Parameters
+    ///
+    /// * `user_phone`: The [`UserPhone`] to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove(&mut self, user_phone: UserPhone) -> Result<(), Self::Error>;
+
+    /// Mark a [`UserPhone`] as verified
+    ///
+    /// Returns the updated [`UserPhone`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `user_phone`: The [`UserPhone`] to mark as verified
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn mark_as_verified(
+        &mut self,
+        clock: &dyn Clock,
+        user_phone: UserPhone,
+    ) -> Result<UserPhone, Self::Error>;
+
+    /// Add a [`UserPhoneVerification`] for a [`UserPhone`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `user_phone`: The [`UserPhone`] for which to add the
+    ///   [`UserPhoneVerification`]
+    /// * `max_age`: The duration for which the [`UserPhoneVerification`] is
+    ///   valid
+    /// * `code`: The code sent by SMS
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_verification_code(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        max_age: chrono::Duration,
+        code: String,
+    ) -> Result<UserPhoneVerification, Self::Error>;
+
+    /// Find a [`UserPhoneVerification`] for a [`UserPhone`] by its code
+    ///
+    /// Returns `None` if no matching [`UserPhoneVerification`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `user_phone`: The [`UserPhone`] for which to lookup the
+    ///   [`UserPhoneVerification`]
+    /// * `code`: The code to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        code: &str,
+    ) -> Result<Option<UserPhoneVerification>, Self::Error>;
+
+    /// Consume a [`UserPhoneVerification`]
+    ///
+    /// Returns the consumed [`UserPhoneVerification`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use
+    /// * `verification`: The [`UserPhoneVerification`] to consume
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails, or if the
+    /// verification code was already consumed
+    async fn consume_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        verification: UserPhoneVerification,
+    ) -> Result<UserPhoneVerification, Self::Error>;
+}
+
+repository_impl!(UserPhoneRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserPhone>, Self::Error>;
+    async fn find(
+        &mut self,
+        user: &User,
+        phone_number: &str,
+    ) -> Result<Option<UserPhone>, Self::Error>;
+    async fn all(&mut self, user: &User) -> Result<Vec<UserPhone>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        phone_number: String,
+    ) -> Result<UserPhone, Self::Error>;
+    async fn remove(&mut self, user_phone: UserPhone) -> Result<(), Self::Error>;
+
+    async fn mark_as_verified(
+        &mut self,
+        clock: &dyn Clock,
+        user_phone: UserPhone,
+    ) -> Result<UserPhone, Self::Error>;
+
+    async fn add_verification_code(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        max_age: chrono::Duration,
+        code: String,
+    ) -> Result<UserPhoneVerification, Self::Error>;
+
+    async fn find_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        user_phone: &UserPhone,
+        code: &str,
+    ) -> Result<Option<UserPhoneVerification>, Self::Error>;
+
+    async fn consume_verification_code(
+        &mut self,
+        clock: &dyn Clock,
+        verification: UserPhoneVerification,
+    ) -> Result<UserPhoneVerification, Self::Error>;
+);
@@ -15,6 +15,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
+use chrono::Duration;
 use mas_data_model::{UserAgent, UserEmail, UserRecoverySession, UserRecoveryTicket};
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -129,6 +130,25 @@ pub trait UserRecoveryRepository: Send + Sync {
         user_recovery_ticket: UserRecoveryTicket,
         user_recovery_session: UserRecoverySession,
     ) -> Result<UserRecoverySession, Self::Error>;
+
+    /// Forget the IP address of the [`UserRecoverySession`]s created more
+    /// than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(UserRecoveryRepository:
@@ -164,4 +184,10 @@ repository_impl!(UserRecoveryRepository:
         user_recovery_ticket: UserRecoveryTicket,
         user_recovery_session: UserRecoverySession,
     ) -> Result<UserRecoverySession, Self::Error>;
+
+    async fn forget_ip_addresses(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
 );
@@ -15,9 +15,10 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     Authentication, BrowserSession, Password, UpstreamOAuthAuthorizationSession, User, UserAgent,
+    UserMagicLink,
 };
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -130,6 +131,33 @@ pub trait BrowserSessionRepository: Send + Sync {
         user_agent: Option<UserAgent>,
     ) -> Result<BrowserSession, Self::Error>;
 
+    /// Create a new [`BrowserSession`] for a [`User`], started by an admin to
+    /// impersonate them
+    ///
+    /// Returns the newly created [`BrowserSession`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The user to impersonate
+    /// * `admin`: The admin who is impersonating the user
+    /// * `user_agent`: If available, the user agent of the browser
+    /// * `ttl`: How long the session is valid for
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_impersonation(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        admin: &User,
+        user_agent: Option<UserAgent>,
+        ttl: Duration,
+    ) -> Result<BrowserSession, Self::Error>;
+
     /// Finish a [`BrowserSession`]
     ///
     /// Returns the finished session
@@ -148,6 +176,44 @@ pub trait BrowserSessionRepository: Send + Sync {
         user_session: BrowserSession,
     ) -> Result<BrowserSession, Self::Error>;
 
+    /// Finish the [`BrowserSession`]s which expired, along with the OAuth 2.0
+    /// and compatibility sessions started from them
+    ///
+    /// Returns the number of browser sessions finished
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn finish_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+
+    /// Finish the [`BrowserSession`]s which were not used for longer than
+    /// `idle_timeout`, or which were started more than `max_lifetime` ago
+    ///
+    /// Unlike [`Self::finish_expired`], this doesn't finish the OAuth 2.0 and
+    /// compatibility sessions started from them.
+    ///
+    /// Returns the number of browser sessions finished
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `idle_timeout`: How long a session can stay unused, if limited
+    /// * `max_lifetime`: How long a session can last, if limited
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn finish_stale(
+        &mut self,
+        clock: &dyn Clock,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+    ) -> Result<usize, Self::Error>;
+
     /// List [`BrowserSession`] with the given filter and pagination
     ///
     /// # Parameters
@@ -217,6 +283,68 @@ pub trait BrowserSessionRepository: Send + Sync {
         upstream_oauth_session: &UpstreamOAuthAuthorizationSession,
     ) -> Result<Authentication, Self::Error>;
 
+    /// Authenticate a [`BrowserSession`] with the given [`UserMagicLink`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user_session`: The session to authenticate
+    /// * `user_magic_link`: The login link which was used to authenticate
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn authenticate_with_magic_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        user_magic_link: &UserMagicLink,
+    ) -> Result<Authentication, Self::Error>;
+
+    /// Authenticate a [`BrowserSession`] with a Kerberos ticket, negotiated
+    /// through SPNEGO
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user_session`: The session to authenticate
+    /// * `principal`: The Kerberos principal presented by the browser
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn authenticate_with_kerberos(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        principal: &str,
+    ) -> Result<Authentication, Self::Error>;
+
+    /// Authenticate a [`BrowserSession`] with a TLS client certificate
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user_session`: The session to authenticate
+    /// * `subject`: The name of the certificate subject which was mapped to the
+    ///   user
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn authenticate_with_client_certificate(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        subject: &str,
+    ) -> Result<Authentication, Self::Error>;
+
     /// Get the last successful authentication for a [`BrowserSession`]
     ///
     /// # Params
@@ -231,6 +359,44 @@ pub trait BrowserSessionRepository: Send + Sync {
         user_session: &BrowserSession,
     ) -> Result<Option<Authentication>, Self::Error>;
 
+    /// Forget the IP address of the last activity of the [`BrowserSession`]s
+    /// which were last active more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the IP addresses are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    /// Forget the user agent of the [`BrowserSession`]s which were last active
+    /// more than `retention` ago
+    ///
+    /// Returns the number of sessions updated
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `retention`: How long the user agents are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     /// Record a batch of [`BrowserSession`] activity
     ///
     /// # Parameters
@@ -256,11 +422,27 @@ repository_impl!(BrowserSessionRepository:
         user: &User,
         user_agent: Option<UserAgent>,
     ) -> Result<BrowserSession, Self::Error>;
+    async fn add_impersonation(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        admin: &User,
+        user_agent: Option<UserAgent>,
+        ttl: Duration,
+    ) -> Result<BrowserSession, Self::Error>;
     async fn finish(
         &mut self,
         clock: &dyn Clock,
         user_session: BrowserSession,
     ) -> Result<BrowserSession, Self::Error>;
+    async fn finish_expired(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+    async fn finish_stale(
+        &mut self,
+        clock: &dyn Clock,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+    ) -> Result<usize, Self::Error>;
 
     async fn list(
         &mut self,
@@ -286,11 +468,47 @@ repository_impl!(BrowserSessionRepository:
         upstream_oauth_session: &UpstreamOAuthAuthorizationSession,
     ) -> Result<Authentication, Self::Error>;
 
+    async fn authenticate_with_magic_link(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        user_magic_link: &UserMagicLink,
+    ) -> Result<Authentication, Self::Error>;
+
+    async fn authenticate_with_kerberos(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        principal: &str,
+    ) -> Result<Authentication, Self::Error>;
+
+    async fn authenticate_with_client_certificate(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user_session: &BrowserSession,
+        subject: &str,
+    ) -> Result<Authentication, Self::Error>;
+
     async fn get_last_authentication(
         &mut self,
         user_session: &BrowserSession,
     ) -> Result<Option<Authentication>, Self::Error>;
 
+    async fn forget_last_active_ips(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
+    async fn forget_user_agents(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+
     async fn record_batch_activity(
         &mut self,
         activity: Vec<(Ulid, DateTime<Utc>, Option<IpAddr>)>,
@@ -0,0 +1,107 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use mas_email::{Address, Mailbox};
+use mas_i18n::locale;
+use mas_storage::{
+    job::{JobWithSpanContext, SendBackchannelAuthenticationEmailJob},
+    oauth2::{OAuth2BackchannelAuthenticationGrantRepository, OAuth2ClientRepository},
+    user::{UserEmailRepository, UserRepository},
+    RepositoryAccess,
+};
+use mas_templates::{EmailBackchannelAuthenticationContext, TemplateContext};
+use tracing::info;
+
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
+
+/// Job to ask a user by email to review a backchannel authentication request
+#[tracing::instrument(
+    name = "job.send_backchannel_authentication_email",
+    fields(
+        oauth2_backchannel_authentication_grant.id = %job.oauth2_backchannel_authentication_grant_id(),
+    ),
+    skip_all,
+    err(Debug),
+)]
+async fn send_backchannel_authentication_email(
+    job: JobWithSpanContext<SendBackchannelAuthenticationEmailJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mailer = state.mailer();
+    let url_builder = state.url_builder();
+    let mut repo = state.repository().await?;
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let grant = repo
+        .oauth2_backchannel_authentication_grant()
+        .lookup(job.oauth2_backchannel_authentication_grant_id())
+        .await?
+        .context("Backchannel authentication grant not found")?;
+
+    if !grant.is_pending() {
+        info!("Backchannel authentication grant is not pending anymore, not sending an email");
+        return Ok(());
+    }
+
+    let user = repo
+        .user()
+        .lookup(grant.user_id)
+        .await?
+        .context("User not found")?;
+
+    let client = repo
+        .oauth2_client()
+        .lookup(grant.client_id)
+        .await?
+        .context("Client not found")?;
+
+    let Some(user_email) = repo.user_email().get_primary(&user).await? else {
+        info!("User has no primary email, not sending a backchannel authentication email");
+        return Ok(());
+    };
+
+    let url = url_builder.backchannel_authentication_consent(grant.id);
+
+    let address: Address = user_email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+    info!("Sending backchannel authentication email to {}", mailbox);
+    let context = EmailBackchannelAuthenticationContext::new(user, client, grant, url)
+        .with_language(language);
+
+    let message = mailer.prepare_backchannel_authentication_email(mailbox, &context)?;
+    queue_email(&state, &mut repo, &message).await?;
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let send_backchannel_authentication_email_worker = crate::build!(SendBackchannelAuthenticationEmailJob => send_backchannel_authentication_email, suffix, state, storage_factory);
+
+    monitor.register(send_backchannel_authentication_email_worker)
+}
@@ -26,7 +26,10 @@ use apalis_core::{
 };
 use apalis_cron::CronStream;
 use chrono::{DateTime, Utc};
-use mas_storage::{oauth2::OAuth2AccessTokenRepository, RepositoryAccess};
+use mas_storage::{
+    idempotency::IdempotencyKeyRepository, oauth2::OAuth2AccessTokenRepository,
+    user::BrowserSessionRepository, RepositoryAccess,
+};
 use tracing::{debug, info};
 
 use crate::{
@@ -62,6 +65,27 @@ pub async fn cleanup_expired_tokens(
     let mut repo = state.repository().await?;
 
     let count = repo.oauth2_access_token().cleanup_expired(&clock).await?;
+
+    // Impersonation sessions are short-lived, finish them as soon as they expire
+    let sessions = repo.browser_session().finish_expired(&clock).await?;
+
+    // Regular sessions are only finished if they were idle or alive for too long
+    let site_config = state.site_config();
+    let stale_sessions = if site_config.browser_session_idle_timeout.is_some()
+        || site_config.browser_session_max_lifetime.is_some()
+    {
+        repo.browser_session()
+            .finish_stale(
+                &clock,
+                site_config.browser_session_idle_timeout,
+                site_config.browser_session_max_lifetime,
+            )
+            .await?
+    } else {
+        0
+    };
+
+    let idempotency_keys = repo.idempotency_key().cleanup_expired(&clock).await?;
     repo.save().await?;
 
     if count == 0 {
@@ -70,6 +94,21 @@ pub async fn cleanup_expired_tokens(
         info!(count, "cleaned up expired tokens");
     }
 
+    if sessions > 0 {
+        info!(count = sessions, "finished expired browser sessions");
+    }
+
+    if stale_sessions > 0 {
+        info!(count = stale_sessions, "finished stale browser sessions");
+    }
+
+    if idempotency_keys > 0 {
+        info!(
+            count = idempotency_keys,
+            "cleaned up expired idempotency keys"
+        );
+    }
+
     Ok(())
 }
 
@@ -15,14 +15,137 @@
 use anyhow::Context;
 use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
 use chrono::Duration;
-use mas_email::{Address, Mailbox};
+use mas_email::{Address, Envelope, Mailbox, Message};
 use mas_i18n::locale;
-use mas_storage::job::{JobWithSpanContext, VerifyEmailJob};
-use mas_templates::{EmailVerificationContext, TemplateContext};
+use mas_storage::{
+    job::{
+        JobRepositoryExt, JobWithSpanContext, SendEmailChangeNotificationJob, SendEmailJob,
+        VerifyEmailJob,
+    },
+    user::{UserEmailChangeRepository, UserRepository},
+    BoxRepository, RepositoryAccess,
+};
+use mas_templates::{EmailChangeNotificationContext, EmailVerificationContext, TemplateContext};
 use rand::{distributions::Uniform, Rng};
-use tracing::info;
+use tracing::{error, info, warn};
 
-use crate::{storage::PostgresStorageFactory, JobContextExt, State};
+use crate::{
+    notify::{notify, SecurityEvent},
+    storage::PostgresStorageFactory,
+    JobContextExt, State,
+};
+
+/// How many times we try to send an email before giving up on it
+const MAX_ATTEMPTS: u32 = 8;
+
+/// How long to wait before retrying to send an email, after the given attempt
+/// failed. This starts at 30 seconds and doubles with each attempt.
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::try_seconds(30 << attempt.min(16)).unwrap()
+}
+
+/// Queue a rendered email, so that it gets sent by the [`SendEmailJob`]
+/// worker, with retries if the transport fails.
+///
+/// The email is only queued once the repository is saved, so that it is sent
+/// if and only if the current transaction is committed.
+pub(crate) async fn queue_email(
+    state: &State,
+    repo: &mut BoxRepository,
+    message: &Message,
+) -> Result<(), anyhow::Error> {
+    let envelope = message.envelope();
+    let from = envelope.from().map(ToString::to_string);
+    let to = envelope.to().iter().map(ToString::to_string).collect();
+    let subject = message.headers().get_raw("Subject").unwrap_or_default();
+
+    let mut rng = state.rng();
+    let encrypter = state.encrypter();
+    let subject = encrypter
+        .encrypt_to_string(&mut rng, subject.as_bytes())
+        .context("Failed to encrypt the email subject")?;
+    let message = encrypter
+        .encrypt_to_string(&mut rng, &message.formatted())
+        .context("Failed to encrypt the email")?;
+
+    repo.job()
+        .schedule_job(SendEmailJob::new(from, to, subject, message))
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "job.send_email",
+    fields(email.attempt = job.attempt()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_email(
+    job: JobWithSpanContext<SendEmailJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mailer = state.mailer();
+    let clock = state.clock();
+
+    let message = state
+        .encrypter()
+        .decrypt_string(job.encrypted_message())
+        .context("Failed to decrypt the email")?;
+
+    let from = job.from().map(str::parse::<Address>).transpose()?;
+    let to = job
+        .to()
+        .iter()
+        .map(|address| address.parse())
+        .collect::<Result<Vec<Address>, _>>()?;
+    let envelope = Envelope::new(from, to)?;
+
+    let Err(e) = mailer.send_raw(&envelope, &message).await else {
+        info!("Email sent");
+        return Ok(());
+    };
+
+    // Sending failed: either schedule another attempt later, or give up and
+    // record the email as undeliverable
+    let mut repo = state.repository().await?;
+    let attempts = job.attempt() + 1;
+    if attempts < MAX_ATTEMPTS {
+        let run_at = clock.now() + retry_delay(job.attempt());
+        warn!(
+            error = &e as &dyn std::error::Error,
+            %run_at,
+            "Failed to send email, retrying later"
+        );
+
+        repo.job()
+            .schedule_job_at(job.next_attempt(), run_at)
+            .await?;
+    } else {
+        error!(
+            error = &e as &dyn std::error::Error,
+            "Failed to send email after {attempts} attempts, giving up"
+        );
+
+        // The subject is kept encrypted, as it may contain a code or a link
+        let mut rng = state.rng();
+        repo.email_dead_letter()
+            .add(
+                &mut rng,
+                &clock,
+                job.to().to_vec(),
+                job.encrypted_subject().to_owned(),
+                attempts,
+                e.to_string(),
+            )
+            .await?;
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
 
 #[tracing::instrument(
     name = "job.verify_email",
@@ -84,11 +207,12 @@ async fn verify_email(
     let context =
         EmailVerificationContext::new(user.clone(), verification.clone()).with_language(language);
 
-    mailer.send_verification_email(mailbox, &context).await?;
+    let message = mailer.prepare_verification_email(mailbox, &context)?;
+    queue_email(&state, &mut repo, &message).await?;
 
     info!(
         email.id = %user_email.id,
-        "Verification email sent"
+        "Verification email queued"
     );
 
     repo.save().await?;
@@ -96,6 +220,48 @@ async fn verify_email(
     Ok(())
 }
 
+#[tracing::instrument(
+    name = "job.send_email_change_notification",
+    fields(user_email_change.id = %job.user_email_change_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_email_change_notification(
+    job: JobWithSpanContext<SendEmailChangeNotificationJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+    let url_builder = state.url_builder();
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let change = repo
+        .user_email_change()
+        .lookup(job.user_email_change_id())
+        .await?
+        .context("User email change not found")?;
+
+    let user = repo
+        .user()
+        .lookup(change.user_id)
+        .await?
+        .context("User not found")?;
+
+    let undo_link = url_builder.email_change_undo_link(change.undo_ticket.clone());
+    let context =
+        EmailChangeNotificationContext::new(user, change, undo_link).with_language(language);
+
+    notify(&state, &mut repo, SecurityEvent::EmailChanged(context)).await;
+
+    repo.save().await?;
+
+    Ok(())
+}
+
 pub(crate) fn register(
     suffix: &str,
     monitor: Monitor<TokioExecutor>,
@@ -104,6 +270,12 @@ pub(crate) fn register(
 ) -> Monitor<TokioExecutor> {
     let verify_email_worker =
         crate::build!(VerifyEmailJob => verify_email, suffix, state, storage_factory);
+    let send_email_worker =
+        crate::build!(SendEmailJob => send_email, suffix, state, storage_factory);
+    let send_email_change_notification_worker = crate::build!(SendEmailChangeNotificationJob => send_email_change_notification, suffix, state, storage_factory);
 
-    monitor.register(verify_email_worker)
+    monitor
+        .register(verify_email_worker)
+        .register(send_email_worker)
+        .register(send_email_change_notification_worker)
 }
@@ -0,0 +1,302 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use mas_data_model::{SessionState, User, UserAgent};
+use mas_email::{Address, Mailbox};
+use mas_i18n::locale;
+use mas_storage::{
+    compat::CompatSessionFilter,
+    job::{ExportUserDataJob, JobWithSpanContext},
+    oauth2::OAuth2SessionFilter,
+    upstream_oauth2::UpstreamOAuthLinkFilter,
+    user::{BrowserSessionFilter, UserEmailRepository, UserExportRepository},
+    BoxRepository, Pagination, RepositoryAccess, RepositoryError,
+};
+use mas_templates::{EmailUserExportContext, TemplateContext};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
+
+fn user_agent(user_agent: Option<&UserAgent>) -> Option<&str> {
+    user_agent.map(|user_agent| user_agent.raw.as_str())
+}
+
+/// Gather everything we know about a user in a single JSON document
+#[allow(clippy::too_many_lines)]
+async fn collect_user_data(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<Value, RepositoryError> {
+    let emails: Vec<Value> = repo
+        .user_email()
+        .all(user)
+        .await?
+        .into_iter()
+        .map(|email| {
+            json!({
+                "email": email.email,
+                "primary": user.primary_user_email_id == Some(email.id),
+                "created_at": email.created_at,
+                "confirmed_at": email.confirmed_at,
+            })
+        })
+        .collect();
+
+    let phones: Vec<Value> = repo
+        .user_phone()
+        .all(user)
+        .await?
+        .into_iter()
+        .map(|phone| {
+            json!({
+                "phone_number": phone.phone_number,
+                "created_at": phone.created_at,
+                "confirmed_at": phone.confirmed_at,
+            })
+        })
+        .collect();
+
+    let mut upstream_links = Vec::new();
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo
+            .upstream_oauth_link()
+            .list(UpstreamOAuthLinkFilter::new().for_user(user), cursor)
+            .await?;
+
+        for link in page.edges {
+            cursor = cursor.after(link.id);
+            let provider = repo
+                .upstream_oauth_provider()
+                .lookup(link.provider_id)
+                .await?;
+            upstream_links.push(json!({
+                "provider": provider.as_ref().map(|provider| &provider.issuer),
+                "provider_name": provider.as_ref().and_then(|provider| provider.human_name.as_ref()),
+                "subject": link.subject,
+                "created_at": link.created_at,
+            }));
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut browser_sessions = Vec::new();
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo
+            .browser_session()
+            .list(BrowserSessionFilter::new().for_user(user), cursor)
+            .await?;
+
+        for session in page.edges {
+            cursor = cursor.after(session.id);
+            browser_sessions.push(json!({
+                "id": session.id,
+                "created_at": session.created_at,
+                "finished_at": session.finished_at,
+                "user_agent": user_agent(session.user_agent.as_ref()),
+                "last_active_at": session.last_active_at,
+                "last_active_ip": session.last_active_ip,
+            }));
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut oauth2_sessions = Vec::new();
+    let mut client_ids = BTreeSet::new();
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo
+            .oauth2_session()
+            .list(OAuth2SessionFilter::new().for_user(user), cursor)
+            .await?;
+
+        for session in page.edges {
+            cursor = cursor.after(session.id);
+            client_ids.insert(session.client_id);
+            let finished_at = match session.state {
+                SessionState::Valid => None,
+                SessionState::Finished { finished_at } => Some(finished_at),
+            };
+            oauth2_sessions.push(json!({
+                "id": session.id,
+                "client_id": session.client_id,
+                "scope": session.scope.to_string(),
+                "created_at": session.created_at,
+                "finished_at": finished_at,
+                "user_agent": user_agent(session.user_agent.as_ref()),
+                "last_active_at": session.last_active_at,
+                "last_active_ip": session.last_active_ip,
+            }));
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut compat_sessions = Vec::new();
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo
+            .compat_session()
+            .list(CompatSessionFilter::new().for_user(user), cursor)
+            .await?;
+
+        for (session, _) in page.edges {
+            cursor = cursor.after(session.id);
+            compat_sessions.push(json!({
+                "id": session.id,
+                "device_id": session.device.as_str(),
+                "created_at": session.created_at,
+                "finished_at": session.finished_at(),
+                "user_agent": user_agent(session.user_agent.as_ref()),
+                "last_active_at": session.last_active_at,
+                "last_active_ip": session.last_active_ip,
+            }));
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    // Consent is given per client, so look at the clients the user had a
+    // session with
+    let clients = repo.oauth2_client().load_batch(client_ids).await?;
+    let mut consents = Vec::new();
+    for client in clients.into_values() {
+        let scope = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, user)
+            .await?;
+
+        if scope.is_empty() {
+            continue;
+        }
+
+        consents.push(json!({
+            "client_id": client.client_id,
+            "client_name": client.client_name,
+            "scope": scope.to_string(),
+        }));
+    }
+
+    Ok(json!({
+        "user": {
+            "id": user.id,
+            "username": user.username,
+            "display_name": user.display_name,
+            "avatar_url": user.avatar_url,
+            "created_at": user.created_at,
+            "locked_at": user.locked_at,
+        },
+        "emails": emails,
+        "phone_numbers": phones,
+        "upstream_links": upstream_links,
+        "browser_sessions": browser_sessions,
+        "oauth2_sessions": oauth2_sessions,
+        "compat_sessions": compat_sessions,
+        "consents": consents,
+    }))
+}
+
+/// Job to generate an export of the data of a user, and send them a link to
+/// download it
+#[tracing::instrument(
+    name = "job.export_user_data",
+    fields(user_export.id = %job.user_export_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn export_user_data(
+    job: JobWithSpanContext<ExportUserDataJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let mailer = state.mailer();
+    let url_builder = state.url_builder();
+    let mut repo = state.repository().await?;
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let export = repo
+        .user_export()
+        .lookup(job.user_export_id())
+        .await?
+        .context("User export not found")?;
+
+    if !export.is_pending() {
+        info!("User export already completed");
+        return Ok(());
+    }
+
+    let user = repo
+        .user()
+        .lookup(export.user_id)
+        .await?
+        .context("User not found")?;
+
+    let data = collect_user_data(&mut repo, &user).await?;
+    let export = repo.user_export().complete(&clock, export, data).await?;
+
+    info!("User export completed");
+
+    if let Some(user_email) = repo.user_email().get_primary(&user).await? {
+        let address: Address = user_email.email.parse()?;
+        let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+        let download_link = url_builder.account_export_download_link(export.id);
+        let context =
+            EmailUserExportContext::new(user, export, download_link).with_language(language);
+
+        let message = mailer.prepare_user_export_email(mailbox, &context)?;
+        queue_email(&state, &mut repo, &message).await?;
+
+        info!("User export email queued");
+    } else {
+        info!("User has no primary email address, not sending the export link");
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let export_user_data_worker =
+        crate::build!(ExportUserDataJob => export_user_data, suffix, state, storage_factory);
+
+    monitor.register(export_user_data_worker)
+}
@@ -15,21 +15,33 @@
 use std::sync::Arc;
 
 use apalis_core::{executor::TokioExecutor, layers::extensions::Extension, monitor::Monitor};
-use mas_email::Mailer;
+use mas_data_model::SiteConfig;
+use mas_email::{Mailer, SmsSender, Webhook};
+use mas_keystore::Encrypter;
 use mas_matrix::HomeserverConnection;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxClock, BoxRepository, Repository, SystemClock};
 use mas_storage_pg::{DatabaseError, PgRepository};
+use mas_templates::Templates;
 use rand::SeedableRng;
 use sqlx::{Pool, Postgres};
 use tracing::debug;
 
 use crate::storage::PostgresStorageFactory;
 
+mod backchannel_authentication;
 mod database;
 mod email;
+mod export;
+mod login_alert;
+mod login_code;
+mod magic_link;
 mod matrix;
+mod notify;
+mod phone;
 mod recovery;
+mod retention;
+mod stats;
 mod storage;
 mod user;
 mod utils;
@@ -38,25 +50,41 @@ mod utils;
 struct State {
     pool: Pool<Postgres>,
     mailer: Mailer,
+    sms_sender: SmsSender,
+    templates: Templates,
+    encrypter: Encrypter,
+    webhook: Option<Webhook>,
     clock: SystemClock,
     homeserver: Arc<dyn HomeserverConnection<Error = anyhow::Error>>,
     url_builder: UrlBuilder,
+    site_config: SiteConfig,
 }
 
 impl State {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pool: Pool<Postgres>,
         clock: SystemClock,
         mailer: Mailer,
+        sms_sender: SmsSender,
+        templates: Templates,
+        encrypter: Encrypter,
+        webhook: Option<Webhook>,
         homeserver: impl HomeserverConnection<Error = anyhow::Error> + 'static,
         url_builder: UrlBuilder,
+        site_config: SiteConfig,
     ) -> Self {
         Self {
             pool,
             mailer,
+            sms_sender,
+            templates,
+            encrypter,
+            webhook,
             clock,
             homeserver: Arc::new(homeserver),
             url_builder,
+            site_config,
         }
     }
 
@@ -76,6 +104,22 @@ impl State {
         &self.mailer
     }
 
+    pub fn sms_sender(&self) -> &SmsSender {
+        &self.sms_sender
+    }
+
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    pub fn encrypter(&self) -> &Encrypter {
+        &self.encrypter
+    }
+
+    pub fn webhook(&self) -> Option<&Webhook> {
+        self.webhook.as_ref()
+    }
+
     // This is fine for now, we may move that to a trait at some point.
     #[allow(clippy::unused_self, clippy::disallowed_methods)]
     pub fn rng(&self) -> rand_chacha::ChaChaRng {
@@ -98,6 +142,10 @@ impl State {
     pub fn url_builder(&self) -> &UrlBuilder {
         &self.url_builder
     }
+
+    pub fn site_config(&self) -> &SiteConfig {
+        &self.site_config
+    }
 }
 
 trait JobContextExt {
@@ -143,27 +191,47 @@ pub(crate) use build;
 /// # Errors
 ///
 /// This function can fail if the database connection fails.
+#[allow(clippy::too_many_arguments)]
 pub async fn init(
     name: &str,
     pool: &Pool<Postgres>,
     mailer: &Mailer,
+    sms_sender: &SmsSender,
+    templates: &Templates,
+    encrypter: &Encrypter,
+    webhook: Option<Webhook>,
     homeserver: impl HomeserverConnection<Error = anyhow::Error> + 'static,
     url_builder: UrlBuilder,
+    site_config: SiteConfig,
 ) -> Result<Monitor<TokioExecutor>, sqlx::Error> {
     let state = State::new(
         pool.clone(),
         SystemClock::default(),
         mailer.clone(),
+        sms_sender.clone(),
+        templates.clone(),
+        encrypter.clone(),
+        webhook,
         homeserver,
         url_builder,
+        site_config,
     );
     let factory = PostgresStorageFactory::new(pool.clone());
     let monitor = Monitor::new().executor(TokioExecutor::new());
     let monitor = self::database::register(name, monitor, &state);
+    let monitor = self::retention::register(name, monitor, &state);
+    let monitor = self::stats::register(name, monitor, &state);
     let monitor = self::email::register(name, monitor, &state, &factory);
+    let monitor = self::phone::register(name, monitor, &state, &factory);
     let monitor = self::matrix::register(name, monitor, &state, &factory);
     let monitor = self::user::register(name, monitor, &state, &factory);
     let monitor = self::recovery::register(name, monitor, &state, &factory);
+    let monitor = self::magic_link::register(name, monitor, &state, &factory);
+    let monitor = self::login_alert::register(name, monitor, &state, &factory);
+    let monitor = self::login_code::register(name, monitor, &state, &factory);
+    let monitor = self::notify::register(name, monitor, &state, &factory);
+    let monitor = self::backchannel_authentication::register(name, monitor, &state, &factory);
+    let monitor = self::export::register(name, monitor, &state, &factory);
     // TODO: we might want to grab the join handle here
     factory.listen().await?;
     debug!(?monitor, "workers registered");
@@ -0,0 +1,120 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use mas_data_model::LoginSighting;
+use mas_i18n::locale;
+use mas_storage::{
+    job::{CheckLoginJob, JobWithSpanContext},
+    user::{BrowserSessionRepository, UserLoginAlertRepository},
+    RepositoryAccess,
+};
+use mas_templates::{EmailLoginAlertContext, TemplateContext};
+use rand::distributions::{Alphanumeric, DistString};
+use tracing::info;
+
+use crate::{
+    notify::{notify, SecurityEvent},
+    storage::PostgresStorageFactory,
+    JobContextExt, State,
+};
+
+/// Job to check whether a login was made from an unknown device or network,
+/// and alert the user if so.
+#[tracing::instrument(
+    name = "job.check_login",
+    fields(user_session.id = %job.user_session_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn check_login(
+    job: JobWithSpanContext<CheckLoginJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let url_builder = state.url_builder();
+    let mut rng = state.rng();
+    let mut repo = state.repository().await?;
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let browser_session = repo
+        .browser_session()
+        .lookup(job.user_session_id())
+        .await?
+        .context("Browser session not found")?;
+
+    if browser_session.user.is_bot {
+        info!("Login is from a bot, not checking it");
+        return Ok(());
+    }
+
+    let (Some(user_agent), Some(ip_address)) = (&browser_session.user_agent, job.ip_address())
+    else {
+        info!("Login has no user agent or IP address, not checking it");
+        return Ok(());
+    };
+
+    let sighting = repo
+        .user_login_alert()
+        .record_sighting(
+            &mut rng,
+            &clock,
+            &browser_session.user,
+            &LoginSighting::device_of(user_agent),
+            LoginSighting::network_of(ip_address),
+        )
+        .await?;
+
+    if sighting != LoginSighting::Unknown {
+        repo.save().await?;
+        return Ok(());
+    }
+
+    let ticket = Alphanumeric.sample_string(&mut rng, 32);
+    let alert = repo
+        .user_login_alert()
+        .add(&mut rng, &clock, &browser_session, Some(ip_address), ticket)
+        .await?;
+
+    let url = url_builder.login_alert_report_link(alert.ticket.clone());
+
+    info!(user_login_alert.id = %alert.id, "Alerting the user of the login");
+    let context = EmailLoginAlertContext::new(browser_session.user.clone(), alert, url)
+        .with_location(job.location())
+        .with_language(language);
+
+    notify(&state, &mut repo, SecurityEvent::NewLogin(context)).await;
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let check_login_worker =
+        crate::build!(CheckLoginJob => check_login, suffix, state, storage_factory);
+
+    monitor.register(check_login_worker)
+}
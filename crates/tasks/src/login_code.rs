@@ -0,0 +1,95 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use mas_email::{Address, Mailbox};
+use mas_i18n::locale;
+use mas_storage::{
+    job::{JobWithSpanContext, SendLoginCodeEmailJob},
+    user::{UserEmailRepository, UserLoginCodeRepository, UserRepository},
+    Clock, RepositoryAccess,
+};
+use mas_templates::{EmailLoginCodeContext, TemplateContext};
+use tracing::info;
+
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
+
+/// Job to send the code confirming a login by email.
+#[tracing::instrument(
+    name = "job.send_login_code_email",
+    fields(user_login_code.id = %job.user_login_code_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_login_code_email(
+    job: JobWithSpanContext<SendLoginCodeEmailJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let mailer = state.mailer();
+    let mut repo = state.repository().await?;
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let code = repo
+        .user_login_code()
+        .lookup(job.user_login_code_id())
+        .await?
+        .context("Login code not found")?;
+
+    if !code.active(clock.now()) {
+        info!("Login code is no longer active, not sending email");
+        return Ok(());
+    }
+
+    let user_email = repo
+        .user_email()
+        .lookup(code.user_email_id)
+        .await?
+        .context("User email not found")?;
+
+    let user = repo
+        .user()
+        .lookup(code.user_id)
+        .await?
+        .context("User not found")?;
+
+    let address: Address = user_email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+    info!("Sending login code to {}", mailbox);
+    let context = EmailLoginCodeContext::new(user, code).with_language(language);
+    let message = mailer.prepare_login_code_email(mailbox, &context)?;
+    queue_email(&state, &mut repo, &message).await?;
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let send_login_code_email_worker = crate::build!(SendLoginCodeEmailJob => send_login_code_email, suffix, state, storage_factory);
+
+    monitor.register(send_login_code_email_worker)
+}
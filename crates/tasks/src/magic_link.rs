@@ -0,0 +1,144 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use mas_email::{Address, Mailbox};
+use mas_i18n::DataLocale;
+use mas_storage::{
+    job::{JobWithSpanContext, SendMagicLinkEmailsJob},
+    user::{UserEmailFilter, UserMagicLinkRepository},
+    Pagination, RepositoryAccess,
+};
+use mas_templates::{EmailMagicLinkContext, TemplateContext};
+use rand::distributions::{Alphanumeric, DistString};
+use tracing::{error, info};
+
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
+
+/// Job to send login links by email for a given magic link session.
+#[tracing::instrument(
+    name = "job.send_magic_link_emails",
+    fields(
+        user_magic_link_session.id = %job.user_magic_link_session_id(),
+        user_magic_link_session.email,
+    ),
+    skip_all,
+    err(Debug),
+)]
+async fn send_magic_link_emails_job(
+    job: JobWithSpanContext<SendMagicLinkEmailsJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let clock = state.clock();
+    let mailer = state.mailer();
+    let url_builder = state.url_builder();
+    let mut rng = state.rng();
+    let mut repo = state.repository().await?;
+
+    let session = repo
+        .user_magic_link()
+        .lookup_session(job.user_magic_link_session_id())
+        .await?
+        .context("Magic link session not found")?;
+
+    tracing::Span::current().record("user_magic_link_session.email", &session.email);
+
+    if session.consumed_at.is_some() {
+        info!("Magic link session already consumed, not sending email");
+        return Ok(());
+    }
+
+    let mut cursor = Pagination::first(50);
+
+    let lang: DataLocale = session
+        .locale
+        .parse()
+        .context("Invalid locale in database on magic link session")?;
+
+    loop {
+        let page = repo
+            .user_email()
+            .list(
+                UserEmailFilter::new()
+                    .for_email(&session.email)
+                    .verified_only(),
+                cursor,
+            )
+            .await?;
+
+        for email in page.edges {
+            let ticket = Alphanumeric.sample_string(&mut rng, 32);
+
+            let link = repo
+                .user_magic_link()
+                .add_link(&mut rng, &clock, &session, &email, ticket)
+                .await?;
+
+            let user_email = repo
+                .user_email()
+                .lookup(email.id)
+                .await?
+                .context("User email not found")?;
+
+            let user = repo
+                .user()
+                .lookup(user_email.user_id)
+                .await?
+                .context("User not found")?;
+
+            let url = url_builder.magic_link(link.ticket);
+
+            let address: Address = user_email.email.parse()?;
+            let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+            info!("Queuing magic link email to {}", mailbox);
+            let context =
+                EmailMagicLinkContext::new(user, session.clone(), url).with_language(lang.clone());
+
+            // XXX: we only log if the email fails to render, to avoid stopping the loop
+            match mailer.prepare_magic_link_email(mailbox, &context) {
+                Ok(message) => queue_email(&state, &mut repo, &message).await?,
+                Err(e) => {
+                    error!(
+                        error = &e as &dyn std::error::Error,
+                        "Failed to render magic link email"
+                    );
+                }
+            }
+
+            cursor = cursor.after(email.id);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let send_magic_link_emails_worker = crate::build!(SendMagicLinkEmailsJob => send_magic_link_emails_job, suffix, state, storage_factory);
+
+    monitor.register(send_magic_link_emails_worker)
+}
@@ -61,10 +61,16 @@ async fn provision_user(
 
     let mut request = ProvisionRequest::new(mxid.clone(), user.sub.clone()).set_emails(emails);
 
-    if let Some(display_name) = job.display_name_to_set() {
+    // The display name explicitly set on the job takes precedence over the one
+    // stored on the user
+    if let Some(display_name) = job.display_name_to_set().or(user.display_name.as_deref()) {
         request = request.set_displayname(display_name.to_owned());
     }
 
+    if let Some(avatar_url) = &user.avatar_url {
+        request = request.set_avatar_url(avatar_url.clone());
+    }
+
     let created = matrix.provision_user(&request).await?;
 
     if created {
@@ -73,6 +79,11 @@ async fn provision_user(
         info!(%user.id, %mxid, "User updated");
     }
 
+    if job.should_shadow_ban() {
+        matrix.shadow_ban_user(&mxid).await?;
+        info!(%user.id, %mxid, "User shadow-banned");
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,337 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Security notifications, sent to users through the channels configured for
+//! each kind of event
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use async_trait::async_trait;
+use mas_data_model::{NotificationChannels, SecurityNotificationsConfig, User};
+use mas_email::{Address, Mailbox, Webhook};
+use mas_i18n::locale;
+use mas_matrix::HomeserverConnection;
+use mas_storage::{
+    job::{JobWithSpanContext, SendPasswordChangeNotificationJob},
+    user::{UserEmailRepository, UserRepository},
+    BoxClock, BoxRepository, RepositoryAccess,
+};
+use mas_templates::{
+    EmailChangeNotificationContext, EmailLoginAlertContext, EmailPasswordChangeNotificationContext,
+    TemplateContext, Templates, WithLanguage,
+};
+use serde_json::{json, Map, Value};
+use tracing::{error, info, warn};
+
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
+
+/// A security-sensitive event on the account of a user
+pub(crate) enum SecurityEvent {
+    /// The account was signed in to from an unknown device or network
+    NewLogin(WithLanguage<EmailLoginAlertContext>),
+
+    /// The password of the account changed
+    PasswordChanged(WithLanguage<EmailPasswordChangeNotificationContext>),
+
+    /// The primary email address of the account changed
+    EmailChanged(WithLanguage<EmailChangeNotificationContext>),
+}
+
+impl SecurityEvent {
+    /// A short name for the kind of event, used in logs and webhook payloads
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::NewLogin(_) => "new_login",
+            Self::PasswordChanged(_) => "password_changed",
+            Self::EmailChanged(_) => "email_changed",
+        }
+    }
+
+    /// The user the event is about
+    fn user(&self) -> &User {
+        match self {
+            Self::NewLogin(context) => context.user(),
+            Self::PasswordChanged(context) => context.user(),
+            Self::EmailChanged(context) => context.user(),
+        }
+    }
+
+    /// The channels this kind of event is sent through
+    fn channels(&self, config: &SecurityNotificationsConfig) -> NotificationChannels {
+        match self {
+            Self::NewLogin(_) => config.new_login,
+            Self::PasswordChanged(_) => config.password_changed,
+            Self::EmailChanged(_) => config.email_changed,
+        }
+    }
+}
+
+/// A channel through which security notifications are sent
+#[async_trait]
+trait Notifier: Send + Sync {
+    /// A short name for the channel, used in logs
+    fn channel(&self) -> &'static str;
+
+    /// Tell the user about the event
+    async fn notify(
+        &self,
+        repo: &mut BoxRepository,
+        event: &SecurityEvent,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// Queues an email to the user, usually to their primary email address
+struct EmailNotifier<'a> {
+    state: &'a State,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier<'_> {
+    fn channel(&self) -> &'static str {
+        "email"
+    }
+
+    async fn notify(
+        &self,
+        repo: &mut BoxRepository,
+        event: &SecurityEvent,
+    ) -> Result<(), anyhow::Error> {
+        let user = event.user();
+
+        let email = if let SecurityEvent::EmailChanged(context) = event {
+            // The notification goes to the previous address, which is the one the
+            // user might have lost control of
+            context.change().old_email.clone()
+        } else {
+            // Unconfirmed addresses may belong to someone else
+            let user_email = repo
+                .user_email()
+                .get_primary(user)
+                .await?
+                .filter(|user_email| user_email.confirmed_at.is_some());
+            let Some(user_email) = user_email else {
+                info!("User has no confirmed primary email, not notifying them by email");
+                return Ok(());
+            };
+            user_email.email
+        };
+
+        let address: Address = email.parse()?;
+        let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+        let mailer = self.state.mailer();
+        let message = match event {
+            SecurityEvent::NewLogin(context) => {
+                mailer.prepare_login_alert_email(mailbox, context)?
+            }
+            SecurityEvent::PasswordChanged(context) => {
+                mailer.prepare_password_change_notification_email(mailbox, context)?
+            }
+            SecurityEvent::EmailChanged(context) => {
+                mailer.prepare_email_change_notification_email(mailbox, context)?
+            }
+        };
+
+        queue_email(self.state, repo, &message).await
+    }
+}
+
+/// Sends a direct message to the user from a bot user on the homeserver. The
+/// message is the plain text variant of the email.
+struct MatrixNotifier<'a> {
+    templates: &'a Templates,
+    homeserver: &'a dyn HomeserverConnection<Error = anyhow::Error>,
+}
+
+#[async_trait]
+impl Notifier for MatrixNotifier<'_> {
+    fn channel(&self) -> &'static str {
+        "matrix"
+    }
+
+    async fn notify(
+        &self,
+        _repo: &mut BoxRepository,
+        event: &SecurityEvent,
+    ) -> Result<(), anyhow::Error> {
+        let body = match event {
+            SecurityEvent::NewLogin(context) => {
+                self.templates.render_email_login_alert_txt(context)?
+            }
+            SecurityEvent::PasswordChanged(context) => self
+                .templates
+                .render_email_password_change_notification_txt(context)?,
+            SecurityEvent::EmailChanged(context) => self
+                .templates
+                .render_email_change_notification_txt(context)?,
+        };
+
+        let mxid = self.homeserver.mxid(&event.user().username);
+        self.homeserver.send_notice(&mxid, body.trim()).await
+    }
+}
+
+/// Posts the event as JSON to the configured webhook
+struct WebhookNotifier<'a> {
+    webhook: &'a Webhook,
+    homeserver: &'a dyn HomeserverConnection<Error = anyhow::Error>,
+    clock: BoxClock,
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier<'_> {
+    fn channel(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(
+        &self,
+        _repo: &mut BoxRepository,
+        event: &SecurityEvent,
+    ) -> Result<(), anyhow::Error> {
+        let user = event.user();
+
+        let mut payload = Map::new();
+        payload.insert("event".to_owned(), json!(event.kind()));
+        payload.insert("sent_at".to_owned(), json!(self.clock.now()));
+        payload.insert(
+            "user".to_owned(),
+            json!({
+                "id": user.id,
+                "username": user.username,
+                "mxid": self.homeserver.mxid(&user.username),
+            }),
+        );
+
+        match event {
+            SecurityEvent::NewLogin(context) => {
+                let alert = context.alert();
+                payload.insert("ip_address".to_owned(), json!(alert.ip_address));
+                payload.insert(
+                    "user_agent".to_owned(),
+                    json!(alert.user_agent.as_ref().map(|ua| &ua.raw)),
+                );
+                payload.insert("location".to_owned(), json!(context.location()));
+                payload.insert("report_link".to_owned(), json!(context.report_link()));
+            }
+            SecurityEvent::PasswordChanged(_) => {}
+            SecurityEvent::EmailChanged(context) => {
+                let change = context.change();
+                payload.insert("old_email".to_owned(), json!(change.old_email));
+                payload.insert("new_email".to_owned(), json!(change.new_email));
+                payload.insert("undo_link".to_owned(), json!(context.undo_link()));
+            }
+        }
+
+        let body = serde_json::to_vec(&Value::Object(payload))?;
+        self.webhook.send(body).await?;
+        Ok(())
+    }
+}
+
+/// Tell a user about a security-sensitive event, through every channel
+/// configured for this kind of event.
+///
+/// Failures are logged per channel, so that one misbehaving channel doesn't
+/// prevent the user from being notified through the others.
+#[tracing::instrument(
+    name = "security_notification.notify",
+    fields(
+        security_notification.event = event.kind(),
+        user.id = %event.user().id,
+    ),
+    skip_all,
+)]
+pub(crate) async fn notify(state: &State, repo: &mut BoxRepository, event: SecurityEvent) {
+    let channels = event.channels(&state.site_config().security_notifications);
+
+    let mut notifiers: Vec<Box<dyn Notifier + '_>> = Vec::new();
+    if channels.email {
+        notifiers.push(Box::new(EmailNotifier { state }));
+    }
+
+    if channels.matrix {
+        notifiers.push(Box::new(MatrixNotifier {
+            templates: state.templates(),
+            homeserver: state.matrix_connection(),
+        }));
+    }
+
+    if channels.webhook {
+        if let Some(webhook) = state.webhook() {
+            notifiers.push(Box::new(WebhookNotifier {
+                webhook,
+                homeserver: state.matrix_connection(),
+                clock: state.clock(),
+            }));
+        } else {
+            warn!("The webhook channel is selected but no webhook is configured");
+        }
+    }
+
+    for notifier in notifiers {
+        let channel = notifier.channel();
+        match notifier.notify(repo, &event).await {
+            Ok(()) => info!(security_notification.channel = channel, "User notified"),
+            Err(e) => error!(
+                error = &*e as &dyn std::error::Error,
+                security_notification.channel = channel,
+                "Failed to notify the user"
+            ),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "job.send_password_change_notification",
+    fields(user.id = %job.user_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn send_password_change_notification(
+    job: JobWithSpanContext<SendPasswordChangeNotificationJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    let user = repo
+        .user()
+        .lookup(job.user_id())
+        .await?
+        .context("User not found")?;
+
+    let context = EmailPasswordChangeNotificationContext::new(user).with_language(language);
+    notify(&state, &mut repo, SecurityEvent::PasswordChanged(context)).await;
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let send_password_change_notification_worker = crate::build!(SendPasswordChangeNotificationJob => send_password_change_notification, suffix, state, storage_factory);
+
+    monitor.register(send_password_change_notification_worker)
+}
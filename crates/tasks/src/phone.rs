@@ -0,0 +1,109 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use apalis_core::{context::JobContext, executor::TokioExecutor, monitor::Monitor};
+use chrono::Duration;
+use mas_i18n::locale;
+use mas_storage::{
+    job::{JobWithSpanContext, VerifyPhoneJob},
+    user::{UserPhoneRepository, UserRepository},
+    RepositoryAccess,
+};
+use mas_templates::{PhoneVerificationContext, TemplateContext};
+use rand::{distributions::Uniform, Rng};
+use tracing::info;
+
+use crate::{storage::PostgresStorageFactory, JobContextExt, State};
+
+#[tracing::instrument(
+    name = "job.verify_phone",
+    fields(user_phone.id = %job.user_phone_id()),
+    skip_all,
+    err(Debug),
+)]
+async fn verify_phone(
+    job: JobWithSpanContext<VerifyPhoneJob>,
+    ctx: JobContext,
+) -> Result<(), anyhow::Error> {
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+    let mut rng = state.rng();
+    let sms_sender = state.sms_sender();
+    let clock = state.clock();
+
+    let language = job
+        .language()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(locale!("en").into());
+
+    // Lookup the user phone
+    let user_phone = repo
+        .user_phone()
+        .lookup(job.user_phone_id())
+        .await?
+        .context("User phone not found")?;
+
+    // Lookup the user associated with the phone number
+    let user = repo
+        .user()
+        .lookup(user_phone.user_id)
+        .await?
+        .context("User not found")?;
+
+    // Generate a verification code
+    let range = Uniform::<u32>::from(0..1_000_000);
+    let code = rng.sample(range);
+    let code = format!("{code:06}");
+
+    // Save the verification code in the database
+    let verification = repo
+        .user_phone()
+        .add_verification_code(
+            &mut rng,
+            &clock,
+            &user_phone,
+            Duration::try_hours(8).unwrap(),
+            code,
+        )
+        .await?;
+
+    // And send the verification text message. The code is only saved if the
+    // message was accepted by the gateway
+    let context = PhoneVerificationContext::new(user, verification).with_language(language);
+    sms_sender
+        .send_verification(&user_phone.phone_number, &context)
+        .await?;
+
+    info!(
+        phone.id = %user_phone.id,
+        "Verification text message sent"
+    );
+
+    repo.save().await?;
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+    storage_factory: &PostgresStorageFactory,
+) -> Monitor<TokioExecutor> {
+    let verify_phone_worker =
+        crate::build!(VerifyPhoneJob => verify_phone, suffix, state, storage_factory);
+
+    monitor.register(verify_phone_worker)
+}
@@ -25,7 +25,7 @@ use mas_templates::{EmailRecoveryContext, TemplateContext};
 use rand::distributions::{Alphanumeric, DistString};
 use tracing::{error, info};
 
-use crate::{storage::PostgresStorageFactory, JobContextExt, State};
+use crate::{email::queue_email, storage::PostgresStorageFactory, JobContextExt, State};
 
 /// Job to send account recovery emails for a given recovery session.
 #[tracing::instrument(
@@ -104,16 +104,19 @@ async fn send_account_recovery_email_job(
             let address: Address = user_email.email.parse()?;
             let mailbox = Mailbox::new(Some(user.username.clone()), address);
 
-            info!("Sending recovery email to {}", mailbox);
+            info!("Queuing recovery email to {}", mailbox);
             let context =
                 EmailRecoveryContext::new(user, session.clone(), url).with_language(lang.clone());
 
-            // XXX: we only log if the email fails to send, to avoid stopping the loop
-            if let Err(e) = mailer.send_recovery_email(mailbox, &context).await {
-                error!(
-                    error = &e as &dyn std::error::Error,
-                    "Failed to send recovery email"
-                );
+            // XXX: we only log if the email fails to render, to avoid stopping the loop
+            match mailer.prepare_recovery_email(mailbox, &context) {
+                Ok(message) => queue_email(&state, &mut repo, &message).await?,
+                Err(e) => {
+                    error!(
+                        error = &e as &dyn std::error::Error,
+                        "Failed to render recovery email"
+                    );
+                }
             }
 
             cursor = cursor.after(email.id);
@@ -0,0 +1,368 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Enforce the retention windows of personal data
+
+use std::{str::FromStr, sync::OnceLock};
+
+use apalis_core::{
+    builder::{WorkerBuilder, WorkerFactoryFn},
+    context::JobContext,
+    executor::TokioExecutor,
+    job::Job,
+    monitor::Monitor,
+    utils::timer::TokioTimer,
+};
+use apalis_cron::CronStream;
+use chrono::{DateTime, Utc};
+use mas_data_model::Device;
+use mas_storage::{
+    compat::{CompatSessionFilter, CompatSessionRepository},
+    job::{DeleteDeviceJob, JobRepositoryExt},
+    oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
+    user::{
+        BrowserSessionRepository, UserLoginAlertRepository, UserLoginCodeRepository,
+        UserLoginRiskRepository, UserMagicLinkRepository, UserRecoveryRepository, UserRepository,
+    },
+    BoxClock, BoxRepository, Clock, Pagination, RepositoryAccess, RepositoryError,
+};
+use opentelemetry::{metrics::Counter, Key};
+use tracing::{debug, info};
+
+use crate::{
+    utils::{metrics_layer, trace_layer, TracedJob},
+    JobContextExt, State,
+};
+
+const TABLE: Key = Key::from_static_str("table");
+const ACTION: Key = Key::from_static_str("action");
+
+/// Counts the rows scrubbed or removed to enforce the retention windows, by
+/// table and action
+fn purged_rows_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = opentelemetry::global::meter_with_version(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            None::<&'static str>,
+            None,
+        );
+
+        meter
+            .u64_counter("mas.data_retention.purged_rows")
+            .with_description(
+                "The number of rows scrubbed or removed to enforce the data retention",
+            )
+            .with_unit(opentelemetry::metrics::Unit::new("{row}"))
+            .init()
+    })
+}
+
+/// What was done to a table to enforce the retention windows
+struct Purged {
+    table: &'static str,
+    action: &'static str,
+    count: usize,
+}
+
+#[derive(Default, Clone)]
+pub struct EnforceDataRetentionJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for EnforceDataRetentionJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for EnforceDataRetentionJob {
+    const NAME: &'static str = "enforce-data-retention";
+}
+
+impl TracedJob for EnforceDataRetentionJob {}
+
+/// End the OAuth 2.0 sessions which were last active before the given time,
+/// and remove their devices from the homeserver. The sessions of bots are
+/// left alone, as they are meant to be long-lived.
+async fn finish_inactive_oauth2_sessions(
+    repo: &mut BoxRepository,
+    clock: &BoxClock,
+    before: DateTime<Utc>,
+) -> Result<usize, RepositoryError> {
+    let filter = OAuth2SessionFilter::new()
+        .active_only()
+        .with_last_active_before(before)
+        .without_bots();
+
+    let mut count = 0;
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo.oauth2_session().list(filter, cursor).await?;
+
+        for session in page.edges {
+            cursor = cursor.after(session.id);
+
+            let user = match session.user_id {
+                Some(user_id) => repo.user().lookup(user_id).await?,
+                None => None,
+            };
+
+            if let Some(user) = user {
+                for scope in &*session.scope {
+                    if let Some(device) = Device::from_scope_token(scope) {
+                        repo.job()
+                            .schedule_job(DeleteDeviceJob::new(&user, &device))
+                            .await?;
+                    }
+                }
+            }
+
+            repo.oauth2_session().finish(clock, session).await?;
+            count += 1;
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(count)
+}
+
+/// End the compatibility sessions which were last active before the given
+/// time, and remove their devices from the homeserver. The sessions of bots
+/// are left alone, as they are meant to be long-lived.
+async fn finish_inactive_compat_sessions(
+    repo: &mut BoxRepository,
+    clock: &BoxClock,
+    before: DateTime<Utc>,
+) -> Result<usize, RepositoryError> {
+    let filter = CompatSessionFilter::new()
+        .active_only()
+        .with_last_active_before(before)
+        .without_bots();
+
+    let mut count = 0;
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo.compat_session().list(filter, cursor).await?;
+
+        for (session, _) in page.edges {
+            cursor = cursor.after(session.id);
+
+            if let Some(user) = repo.user().lookup(session.user_id).await? {
+                repo.job()
+                    .schedule_job(DeleteDeviceJob::new(&user, &session.device))
+                    .await?;
+            }
+
+            repo.compat_session().finish(clock, session).await?;
+            count += 1;
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(count)
+}
+
+#[allow(clippy::too_many_lines)]
+pub async fn enforce_data_retention(
+    job: EnforceDataRetentionJob,
+    ctx: JobContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    debug!("enforce data retention job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let clock = state.clock();
+    let retention = state.site_config().data_retention;
+    let mut repo = state.repository().await?;
+    let mut purged = Vec::new();
+
+    if let Some(window) = retention.ip_addresses {
+        purged.push(Purged {
+            table: "user_sessions",
+            action: "scrub_ip_address",
+            count: repo
+                .browser_session()
+                .forget_last_active_ips(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "oauth2_sessions",
+            action: "scrub_ip_address",
+            count: repo
+                .oauth2_session()
+                .forget_last_active_ips(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "compat_sessions",
+            action: "scrub_ip_address",
+            count: repo
+                .compat_session()
+                .forget_last_active_ips(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_login_alerts",
+            action: "scrub_ip_address",
+            count: repo
+                .user_login_alert()
+                .forget_ip_addresses(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_recovery_sessions",
+            action: "scrub_ip_address",
+            count: repo
+                .user_recovery()
+                .forget_ip_addresses(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_magic_link_sessions",
+            action: "scrub_ip_address",
+            count: repo
+                .user_magic_link()
+                .forget_ip_addresses(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_login_codes",
+            action: "scrub_ip_address",
+            count: repo
+                .user_login_code()
+                .forget_ip_addresses(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_login_risk_assessments",
+            action: "scrub_ip_address",
+            count: repo
+                .user_login_risk()
+                .forget_ip_addresses(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_login_sightings",
+            action: "delete",
+            count: repo
+                .user_login_alert()
+                .forget_sightings(&clock, window)
+                .await?,
+        });
+    }
+
+    if let Some(window) = retention.user_agents {
+        purged.push(Purged {
+            table: "user_sessions",
+            action: "scrub_user_agent",
+            count: repo
+                .browser_session()
+                .forget_user_agents(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "oauth2_sessions",
+            action: "scrub_user_agent",
+            count: repo
+                .oauth2_session()
+                .forget_user_agents(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "compat_sessions",
+            action: "scrub_user_agent",
+            count: repo
+                .compat_session()
+                .forget_user_agents(&clock, window)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "user_login_alerts",
+            action: "scrub_user_agent",
+            count: repo
+                .user_login_alert()
+                .forget_user_agents(&clock, window)
+                .await?,
+        });
+    }
+
+    if let Some(window) = retention.inactive_sessions {
+        let before = clock.now() - window;
+        purged.push(Purged {
+            table: "user_sessions",
+            action: "finish",
+            count: repo
+                .browser_session()
+                .finish_stale(&clock, Some(window), None)
+                .await?,
+        });
+        purged.push(Purged {
+            table: "oauth2_sessions",
+            action: "finish",
+            count: finish_inactive_oauth2_sessions(&mut repo, &clock, before).await?,
+        });
+        purged.push(Purged {
+            table: "compat_sessions",
+            action: "finish",
+            count: finish_inactive_compat_sessions(&mut repo, &clock, before).await?,
+        });
+    }
+
+    repo.save().await?;
+
+    // Only record the metrics once the changes are committed
+    let counter = purged_rows_counter();
+    for Purged {
+        table,
+        action,
+        count,
+    } in purged
+    {
+        if count == 0 {
+            continue;
+        }
+
+        counter.add(
+            count.try_into().unwrap_or(u64::MAX),
+            &[TABLE.string(table), ACTION.string(action)],
+        );
+        info!(table, action, count, "enforced data retention");
+    }
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+) -> Monitor<TokioExecutor> {
+    let schedule = apalis_cron::Schedule::from_str("0 0 * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = EnforceDataRetentionJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(enforce_data_retention);
+
+    monitor.register(worker)
+}
@@ -0,0 +1,87 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Periodically recompute the aggregated statistics
+
+use std::str::FromStr;
+
+use apalis_core::{
+    builder::{WorkerBuilder, WorkerFactoryFn},
+    context::JobContext,
+    executor::TokioExecutor,
+    job::Job,
+    monitor::Monitor,
+    utils::timer::TokioTimer,
+};
+use apalis_cron::CronStream;
+use chrono::{DateTime, Utc};
+use mas_storage::RepositoryAccess;
+use tracing::{debug, info};
+
+use crate::{
+    utils::{metrics_layer, trace_layer, TracedJob},
+    JobContextExt, State,
+};
+
+#[derive(Default, Clone)]
+pub struct RefreshStatsJob {
+    scheduled: DateTime<Utc>,
+}
+
+impl From<DateTime<Utc>> for RefreshStatsJob {
+    fn from(scheduled: DateTime<Utc>) -> Self {
+        Self { scheduled }
+    }
+}
+
+impl Job for RefreshStatsJob {
+    const NAME: &'static str = "refresh-stats";
+}
+
+impl TracedJob for RefreshStatsJob {}
+
+pub async fn refresh_stats(
+    job: RefreshStatsJob,
+    ctx: JobContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    debug!("refresh stats job scheduled at {}", job.scheduled);
+
+    let state = ctx.state();
+    let mut repo = state.repository().await?;
+
+    repo.stats().refresh().await?;
+    repo.save().await?;
+
+    info!("refreshed the statistics");
+
+    Ok(())
+}
+
+pub(crate) fn register(
+    suffix: &str,
+    monitor: Monitor<TokioExecutor>,
+    state: &State,
+) -> Monitor<TokioExecutor> {
+    // The statistics are per day, refreshing them every hour is plenty
+    let schedule = apalis_cron::Schedule::from_str("0 0 * * * *").unwrap();
+    let worker_name = format!("{job}-{suffix}", job = RefreshStatsJob::NAME);
+    let worker = WorkerBuilder::new(worker_name)
+        .stream(CronStream::new(schedule).timer(TokioTimer).to_stream())
+        .layer(state.inject())
+        .layer(metrics_layer())
+        .layer(trace_layer())
+        .build_fn(refresh_stats);
+
+    monitor.register(worker)
+}
@@ -27,13 +27,15 @@ use std::{
 use chrono::{DateTime, Duration, Utc};
 use http::{Method, Uri, Version};
 use mas_data_model::{
-    AuthorizationGrant, BrowserSession, Client, CompatSsoLogin, CompatSsoLoginState,
-    DeviceCodeGrant, UpstreamOAuthLink, UpstreamOAuthProvider, User, UserAgent, UserEmail,
-    UserEmailVerification, UserRecoverySession,
+    AuthorizationGrant, BackchannelAuthenticationGrant, BrowserSession, Client, CompatSession,
+    CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device, DeviceCodeGrant, GeoLocation,
+    Session, SessionState, UpstreamOAuthLink, UpstreamOAuthProvider, User, UserAgent, UserEmail,
+    UserEmailChange, UserEmailVerification, UserExport, UserLoginAlert, UserLoginCode,
+    UserMagicLinkSession, UserPhoneVerification, UserRecoverySession,
 };
 use mas_i18n::DataLocale;
 use mas_router::{Account, GraphQL, PostAuthAction, UrlBuilder};
-use oauth2_types::scope::OPENID;
+use oauth2_types::scope::{Scope, OPENID};
 use rand::{
     distributions::{Alphanumeric, DistString},
     Rng,
@@ -346,12 +348,15 @@ pub enum LoginFormField {
 
     /// The password field
     Password,
+
+    /// The "stay signed in" checkbox
+    Remember,
 }
 
 impl FormField for LoginFormField {
     fn keep(&self) -> bool {
         match self {
-            Self::Username => true,
+            Self::Username | Self::Remember => true,
             Self::Password => false,
         }
     }
@@ -373,6 +378,13 @@ pub enum PostAuthContextInner {
         grant: Box<DeviceCodeGrant>,
     },
 
+    /// Continue a backchannel authentication grant
+    ContinueBackchannelAuthenticationGrant {
+        /// The backchannel authentication grant that will be continued after
+        /// authentication
+        grant: Box<BackchannelAuthenticationGrant>,
+    },
+
     /// Continue legacy login
     /// TODO: add the login context in there
     ContinueCompatSsoLogin {
@@ -383,6 +395,12 @@ pub enum PostAuthContextInner {
     /// Change the account password
     ChangePassword,
 
+    /// Change the primary email address
+    ChangeEmail,
+
+    /// Export the data of the account
+    ExportData,
+
     /// Link an upstream account
     LinkUpstream {
         /// The upstream provider
@@ -477,6 +495,44 @@ impl LoginContext {
     }
 }
 
+/// Context used by the `login_throttled.html` template, shown when too many
+/// login attempts were made
+#[derive(Serialize)]
+pub struct LoginThrottledContext {
+    retry_after: i64,
+    next: Option<PostAuthContext>,
+}
+
+impl TemplateContext for LoginThrottledContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::new(42), Self::new(20 * 60)]
+    }
+}
+
+impl LoginThrottledContext {
+    /// Constructs a context for a login attempt which has to wait
+    /// `retry_after` seconds before trying again
+    #[must_use]
+    pub fn new(retry_after: i64) -> Self {
+        Self {
+            retry_after,
+            next: None,
+        }
+    }
+
+    /// Add a post authentication action to the context
+    #[must_use]
+    pub fn with_post_action(self, context: PostAuthContext) -> Self {
+        Self {
+            next: Some(context),
+            ..self
+        }
+    }
+}
+
 /// Fields of the registration form
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -549,6 +605,8 @@ pub struct ConsentContext {
     grant: AuthorizationGrant,
     client: Client,
     action: PostAuthAction,
+    new_scope: Scope,
+    granted_scope: Scope,
 }
 
 impl TemplateContext for ConsentContext {
@@ -558,16 +616,18 @@ impl TemplateContext for ConsentContext {
     {
         Client::samples(now, rng)
             .into_iter()
-            .map(|client| {
+            .flat_map(|client| {
                 let mut grant = AuthorizationGrant::sample(now, rng);
-                let action = PostAuthAction::continue_grant(grant.id);
                 // XXX
                 grant.client_id = client.id;
-                Self {
-                    grant,
-                    client,
-                    action,
-                }
+
+                // Render the page both for a first consent and for a client
+                // asking for more than what was already granted
+                let granted: Scope = [OPENID].into_iter().collect();
+                [
+                    Self::new(grant.clone(), client.clone()),
+                    Self::new(grant, client).with_granted_scope(&granted),
+                ]
             })
             .collect()
     }
@@ -578,11 +638,47 @@ impl ConsentContext {
     #[must_use]
     pub fn new(grant: AuthorizationGrant, client: Client) -> Self {
         let action = PostAuthAction::continue_grant(grant.id);
+        let new_scope = grant.scope.clone();
         Self {
             grant,
             client,
             action,
+            new_scope,
+            granted_scope: Scope::default(),
+        }
+    }
+
+    /// Set the scope the user already consented to for this client, so that
+    /// only the newly requested scopes are highlighted
+    #[must_use]
+    pub fn with_granted_scope(mut self, granted: &Scope) -> Self {
+        let granted_scope: Scope = self
+            .grant
+            .scope
+            .iter()
+            .filter(|token| granted.contains(token))
+            .cloned()
+            .collect();
+        let new_scope: Scope = self
+            .grant
+            .scope
+            .iter()
+            .filter(|token| !granted.contains(token))
+            .cloned()
+            .collect();
+
+        // Only show the differential screen if something the user has to
+        // consent to is actually new
+        let has_new_scope = new_scope
+            .iter()
+            .any(|token| Device::from_scope_token(token).is_none());
+
+        if has_new_scope && !granted_scope.is_empty() {
+            self.new_scope = new_scope;
+            self.granted_scope = granted_scope;
         }
+
+        self
     }
 }
 
@@ -593,6 +689,8 @@ enum PolicyViolationGrant {
     Authorization(AuthorizationGrant),
     #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
     DeviceCode(DeviceCodeGrant),
+    #[serde(rename = "urn:openid:params:grant-type:ciba")]
+    BackchannelAuthentication(BackchannelAuthenticationGrant),
 }
 
 /// Context used by the `policy_violation.html` template
@@ -630,10 +728,31 @@ impl TemplateContext for PolicyViolationContext {
                         ip_address: None,
                         user_agent: None,
                     },
-                    client,
+                    client.clone(),
                 );
+                let backchannel_authentication_grant =
+                    PolicyViolationContext::for_backchannel_authentication_grant(
+                        BackchannelAuthenticationGrant {
+                            id: Ulid::from_datetime_with_source(now.into(), rng),
+                            state: mas_data_model::BackchannelAuthenticationGrantState::Pending,
+                            client_id: client.id,
+                            scope: [OPENID].into_iter().collect(),
+                            user_id: Ulid::from_datetime_with_source(now.into(), rng),
+                            auth_req_id: Alphanumeric.sample_string(rng, 32),
+                            binding_message: None,
+                            created_at: now - Duration::try_minutes(5).unwrap(),
+                            expires_at: now + Duration::try_minutes(15).unwrap(),
+                            ip_address: None,
+                            user_agent: None,
+                        },
+                        client,
+                    );
 
-                [authorization_grant, device_code_grant]
+                [
+                    authorization_grant,
+                    device_code_grant,
+                    backchannel_authentication_grant,
+                ]
             })
             .collect()
     }
@@ -663,6 +782,21 @@ impl PolicyViolationContext {
             action,
         }
     }
+
+    /// Constructs a context for the policy violation page for a backchannel
+    /// authentication grant
+    #[must_use]
+    pub const fn for_backchannel_authentication_grant(
+        grant: BackchannelAuthenticationGrant,
+        client: Client,
+    ) -> Self {
+        let action = PostAuthAction::continue_backchannel_authentication_grant(grant.id);
+        Self {
+            grant: PolicyViolationGrant::BackchannelAuthentication(grant),
+            client,
+            action,
+        }
+    }
 }
 
 /// Fields of the reauthentication form
@@ -806,236 +940,973 @@ impl TemplateContext for EmailRecoveryContext {
     }
 }
 
-/// Context used by the `emails/verification.{txt,html,subject}` templates
+/// Context used by the `emails/magic_link.{txt,html,subject}` templates
 #[derive(Serialize)]
-pub struct EmailVerificationContext {
+pub struct EmailMagicLinkContext {
     user: User,
-    verification: UserEmailVerification,
+    session: UserMagicLinkSession,
+    login_link: Url,
 }
 
-impl EmailVerificationContext {
-    /// Constructs a context for the verification email
+impl EmailMagicLinkContext {
+    /// Constructs a context for the magic link email
     #[must_use]
-    pub fn new(user: User, verification: UserEmailVerification) -> Self {
-        Self { user, verification }
+    pub fn new(user: User, session: UserMagicLinkSession, login_link: Url) -> Self {
+        Self {
+            user,
+            session,
+            login_link,
+        }
     }
 
-    /// Get the user to which this email is being sent
+    /// Returns the user to which this email is being sent
     #[must_use]
     pub fn user(&self) -> &User {
         &self.user
     }
 
-    /// Get the verification code being sent
+    /// Returns the magic link session which triggered this email
     #[must_use]
-    pub fn verification(&self) -> &UserEmailVerification {
-        &self.verification
+    pub fn session(&self) -> &UserMagicLinkSession {
+        &self.session
     }
 }
 
-impl TemplateContext for EmailVerificationContext {
+impl TemplateContext for EmailMagicLinkContext {
     fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
     where
         Self: Sized,
     {
+        let sessions = UserMagicLinkSession::samples(now, rng);
         User::samples(now, rng)
             .into_iter()
-            .map(|user| {
-                let email = UserEmail {
-                    id: Ulid::from_datetime_with_source(now.into(), rng),
-                    user_id: user.id,
-                    email: "foobar@example.com".to_owned(),
-                    created_at: now,
-                    confirmed_at: None,
-                };
-
-                let verification = UserEmailVerification {
-                    id: Ulid::from_datetime_with_source(now.into(), rng),
-                    user_email_id: email.id,
-                    code: "123456".to_owned(),
-                    created_at: now,
-                    state: mas_data_model::UserEmailVerificationState::Valid,
-                };
+            .zip(sessions)
+            .map(|(user, session)| {
+                let link = "https://example.com/login/link/confirm?ticket=abcdefghijklmnopqrstuvwxyz0123456789"
+                    .parse()
+                    .unwrap();
 
-                Self { user, verification }
+                Self::new(user, session, link)
             })
             .collect()
     }
 }
 
-/// Fields of the email verification form
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum EmailVerificationFormField {
-    /// The code field
-    Code,
+/// Context used by the `emails/login_alert.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailLoginAlertContext {
+    user: User,
+    alert: UserLoginAlert,
+    report_link: Url,
+    location: Option<String>,
 }
 
-impl FormField for EmailVerificationFormField {
-    fn keep(&self) -> bool {
-        match self {
-            Self::Code => true,
+impl EmailLoginAlertContext {
+    /// Constructs a context for the login alert email
+    #[must_use]
+    pub fn new(user: User, alert: UserLoginAlert, report_link: Url) -> Self {
+        Self {
+            user,
+            alert,
+            report_link,
+            location: None,
         }
     }
-}
 
-/// Context used by the `pages/account/verify.html` templates
-#[derive(Serialize)]
-pub struct EmailVerificationPageContext {
-    form: FormState<EmailVerificationFormField>,
-    email: UserEmail,
-}
+    /// Set the approximate location the login was made from
+    #[must_use]
+    pub fn with_location(mut self, location: Option<&GeoLocation>) -> Self {
+        self.location = location
+            .filter(|location| !location.is_empty())
+            .map(ToString::to_string);
+        self
+    }
 
-impl EmailVerificationPageContext {
-    /// Constructs a context for the email verification page
+    /// Returns the user to which this email is being sent
     #[must_use]
-    pub fn new(email: UserEmail) -> Self {
-        Self {
-            form: FormState::default(),
-            email,
-        }
+    pub fn user(&self) -> &User {
+        &self.user
     }
 
-    /// Set the form state
+    /// Returns the alert this email is about
     #[must_use]
-    pub fn with_form_state(self, form: FormState<EmailVerificationFormField>) -> Self {
-        Self { form, ..self }
+    pub fn alert(&self) -> &UserLoginAlert {
+        &self.alert
+    }
+
+    /// Returns the link to report the login
+    #[must_use]
+    pub fn report_link(&self) -> &Url {
+        &self.report_link
+    }
+
+    /// Returns the approximate location of the login, if known
+    #[must_use]
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
     }
 }
 
-impl TemplateContext for EmailVerificationPageContext {
+impl TemplateContext for EmailLoginAlertContext {
     fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
     where
         Self: Sized,
     {
-        let email = UserEmail {
-            id: Ulid::from_datetime_with_source(now.into(), rng),
-            user_id: Ulid::from_datetime_with_source(now.into(), rng),
-            email: "foobar@example.com".to_owned(),
-            created_at: now,
-            confirmed_at: None,
-        };
-
-        vec![Self {
-            form: FormState::default(),
-            email,
-        }]
+        User::samples(now, rng)
+            .into_iter()
+            .zip(UserLoginAlert::samples(now, rng))
+            .map(|(user, alert)| {
+                let link =
+                    "https://example.com/login-alert?ticket=abcdefghijklmnopqrstuvwxyz0123456789"
+                        .parse()
+                        .unwrap();
+                let location = GeoLocation {
+                    country_code: Some("FR".to_owned()),
+                    country: Some("France".to_owned()),
+                    city: Some("Paris".to_owned()),
+                    ..GeoLocation::default()
+                };
+                Self::new(user, alert, link).with_location(Some(&location))
+            })
+            .collect()
     }
 }
 
-/// Fields of the account email add form
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum EmailAddFormField {
-    /// The email
-    Email,
+/// Context used by the `emails/login_code.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailLoginCodeContext {
+    user: User,
+    code: UserLoginCode,
 }
 
-impl FormField for EmailAddFormField {
-    fn keep(&self) -> bool {
-        match self {
-            Self::Email => true,
-        }
+impl EmailLoginCodeContext {
+    /// Constructs a context for the login code email
+    #[must_use]
+    pub fn new(user: User, code: UserLoginCode) -> Self {
+        Self { user, code }
     }
-}
-
-/// Context used by the `pages/account/verify.html` templates
-#[derive(Serialize, Default)]
-pub struct EmailAddContext {
-    form: FormState<EmailAddFormField>,
-}
 
-impl EmailAddContext {
-    /// Constructs a context for the email add page
+    /// Returns the user to which this email is being sent
     #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    pub fn user(&self) -> &User {
+        &self.user
     }
 
-    /// Set the form state
+    /// Returns the login code sent in this email
     #[must_use]
-    pub fn with_form_state(form: FormState<EmailAddFormField>) -> Self {
-        Self { form }
+    pub fn code(&self) -> &UserLoginCode {
+        &self.code
     }
 }
 
-impl TemplateContext for EmailAddContext {
-    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+impl TemplateContext for EmailLoginCodeContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
     where
         Self: Sized,
     {
-        vec![Self::default()]
+        User::samples(now, rng)
+            .into_iter()
+            .zip(UserLoginCode::samples(now, rng))
+            .map(|(user, code)| Self::new(user, code))
+            .collect()
     }
 }
 
-/// Fields of the account recovery start form
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum RecoveryStartFormField {
-    /// The email
-    Email,
+/// Context used by the `emails/backchannel_authentication.{txt,html,subject}`
+/// templates
+#[derive(Serialize)]
+pub struct EmailBackchannelAuthenticationContext {
+    user: User,
+    client: Client,
+    grant: BackchannelAuthenticationGrant,
+    link: Url,
 }
 
-impl FormField for RecoveryStartFormField {
-    fn keep(&self) -> bool {
-        match self {
-            Self::Email => true,
+impl EmailBackchannelAuthenticationContext {
+    /// Constructs a context for the backchannel authentication email
+    #[must_use]
+    pub fn new(
+        user: User,
+        client: Client,
+        grant: BackchannelAuthenticationGrant,
+        link: Url,
+    ) -> Self {
+        Self {
+            user,
+            client,
+            grant,
+            link,
         }
     }
-}
-
-/// Context used by the `pages/recovery/start.html` template
-#[derive(Serialize, Default)]
-pub struct RecoveryStartContext {
-    form: FormState<RecoveryStartFormField>,
-}
 
-impl RecoveryStartContext {
-    /// Constructs a context for the recovery start page
+    /// Returns the user to which this email is being sent
     #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    pub fn user(&self) -> &User {
+        &self.user
     }
 
-    /// Set the form state
+    /// Returns the grant this email is about
     #[must_use]
-    pub fn with_form_state(self, form: FormState<RecoveryStartFormField>) -> Self {
-        Self { form }
+    pub fn grant(&self) -> &BackchannelAuthenticationGrant {
+        &self.grant
     }
 }
 
-impl TemplateContext for RecoveryStartContext {
-    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+impl TemplateContext for EmailBackchannelAuthenticationContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
     where
         Self: Sized,
     {
-        vec![
-            Self::new(),
-            Self::new().with_form_state(
-                FormState::default()
-                    .with_error_on_field(RecoveryStartFormField::Email, FieldError::Required),
-            ),
-            Self::new().with_form_state(
-                FormState::default()
-                    .with_error_on_field(RecoveryStartFormField::Email, FieldError::Invalid),
-            ),
-        ]
+        User::samples(now, rng)
+            .into_iter()
+            .zip(Client::samples(now, rng))
+            .map(|(user, client)| {
+                let grant = BackchannelAuthenticationGrant {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    state: mas_data_model::BackchannelAuthenticationGrantState::Pending,
+                    client_id: client.id,
+                    scope: [OPENID].into_iter().collect(),
+                    user_id: user.id,
+                    auth_req_id: Alphanumeric.sample_string(rng, 32),
+                    binding_message: Some("Order #1234".to_owned()),
+                    created_at: now,
+                    expires_at: now + Duration::try_minutes(20).unwrap(),
+                    ip_address: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+                    user_agent: None,
+                };
+                let link = format!("https://example.com/ciba/{}", grant.id)
+                    .parse()
+                    .unwrap();
+                Self::new(user, client, grant, link)
+            })
+            .collect()
     }
 }
 
-/// Context used by the `pages/recovery/progress.html` template
+/// Context used by the `emails/email_change.{txt,html,subject}` templates
 #[derive(Serialize)]
-pub struct RecoveryProgressContext {
-    session: UserRecoverySession,
+pub struct EmailChangeNotificationContext {
+    user: User,
+    change: UserEmailChange,
+    undo_link: Url,
 }
 
-impl RecoveryProgressContext {
-    /// Constructs a context for the recovery progress page
+impl EmailChangeNotificationContext {
+    /// Constructs a context for the email change notification
     #[must_use]
-    pub fn new(session: UserRecoverySession) -> Self {
-        Self { session }
+    pub fn new(user: User, change: UserEmailChange, undo_link: Url) -> Self {
+        Self {
+            user,
+            change,
+            undo_link,
+        }
     }
-}
+
+    /// Returns the user to which this email is being sent
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Returns the email change this email is about
+    #[must_use]
+    pub fn change(&self) -> &UserEmailChange {
+        &self.change
+    }
+
+    /// Returns the link to undo the change
+    #[must_use]
+    pub fn undo_link(&self) -> &Url {
+        &self.undo_link
+    }
+}
+
+impl TemplateContext for EmailChangeNotificationContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .zip(UserEmailChange::samples(now, rng))
+            .map(|(user, change)| {
+                let link =
+                    "https://example.com/email-change/undo?ticket=aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                        .parse()
+                        .unwrap();
+                Self::new(user, change, link)
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `emails/password_change.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailPasswordChangeNotificationContext {
+    user: User,
+}
+
+impl EmailPasswordChangeNotificationContext {
+    /// Constructs a context for the password change notification
+    #[must_use]
+    pub fn new(user: User) -> Self {
+        Self { user }
+    }
+
+    /// Returns the user to which this email is being sent
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for EmailPasswordChangeNotificationContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng).into_iter().map(Self::new).collect()
+    }
+}
+
+/// Context used by the `emails/user_export.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailUserExportContext {
+    user: User,
+    export: UserExport,
+    download_link: Url,
+}
+
+impl EmailUserExportContext {
+    /// Constructs a context for the email sent when an export is ready
+    #[must_use]
+    pub fn new(user: User, export: UserExport, download_link: Url) -> Self {
+        Self {
+            user,
+            export,
+            download_link,
+        }
+    }
+
+    /// Returns the user to which this email is being sent
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Returns the export this email is about
+    #[must_use]
+    pub fn export(&self) -> &UserExport {
+        &self.export
+    }
+}
+
+impl TemplateContext for EmailUserExportContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .zip(UserExport::samples(now, rng))
+            .map(|(user, export)| {
+                let link = format!("https://example.com/export/{}", export.id)
+                    .parse()
+                    .unwrap();
+                Self::new(user, export, link)
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `sms/verification.txt` template
+#[derive(Serialize)]
+pub struct PhoneVerificationContext {
+    user: User,
+    verification: UserPhoneVerification,
+}
+
+impl PhoneVerificationContext {
+    /// Constructs a context for the verification text message
+    #[must_use]
+    pub fn new(user: User, verification: UserPhoneVerification) -> Self {
+        Self { user, verification }
+    }
+
+    /// Get the user to which this message is being sent
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Get the verification code being sent
+    #[must_use]
+    pub fn verification(&self) -> &UserPhoneVerification {
+        &self.verification
+    }
+}
+
+impl TemplateContext for PhoneVerificationContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .zip(UserPhoneVerification::samples(now, rng))
+            .map(|(user, verification)| Self::new(user, verification))
+            .collect()
+    }
+}
+
+/// Context used by the `emails/verification.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailVerificationContext {
+    user: User,
+    verification: UserEmailVerification,
+}
+
+impl EmailVerificationContext {
+    /// Constructs a context for the verification email
+    #[must_use]
+    pub fn new(user: User, verification: UserEmailVerification) -> Self {
+        Self { user, verification }
+    }
+
+    /// Get the user to which this email is being sent
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Get the verification code being sent
+    #[must_use]
+    pub fn verification(&self) -> &UserEmailVerification {
+        &self.verification
+    }
+}
+
+impl TemplateContext for EmailVerificationContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples(now, rng)
+            .into_iter()
+            .map(|user| {
+                let email = UserEmail {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    user_id: user.id,
+                    email: "foobar@example.com".to_owned(),
+                    created_at: now,
+                    confirmed_at: None,
+                };
+
+                let verification = UserEmailVerification {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    user_email_id: email.id,
+                    code: "123456".to_owned(),
+                    created_at: now,
+                    state: mas_data_model::UserEmailVerificationState::Valid,
+                };
+
+                Self { user, verification }
+            })
+            .collect()
+    }
+}
+
+/// Fields of the email verification form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailVerificationFormField {
+    /// The code field
+    Code,
+}
+
+impl FormField for EmailVerificationFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Code => true,
+        }
+    }
+}
+
+/// Context used by the `pages/account/verify.html` templates
+#[derive(Serialize)]
+pub struct EmailVerificationPageContext {
+    form: FormState<EmailVerificationFormField>,
+    email: UserEmail,
+}
+
+impl EmailVerificationPageContext {
+    /// Constructs a context for the email verification page
+    #[must_use]
+    pub fn new(email: UserEmail) -> Self {
+        Self {
+            form: FormState::default(),
+            email,
+        }
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<EmailVerificationFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+impl TemplateContext for EmailVerificationPageContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let email = UserEmail {
+            id: Ulid::from_datetime_with_source(now.into(), rng),
+            user_id: Ulid::from_datetime_with_source(now.into(), rng),
+            email: "foobar@example.com".to_owned(),
+            created_at: now,
+            confirmed_at: None,
+        };
+
+        vec![Self {
+            form: FormState::default(),
+            email,
+        }]
+    }
+}
+
+/// Fields of the account email add form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailAddFormField {
+    /// The email
+    Email,
+}
+
+impl FormField for EmailAddFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Email => true,
+        }
+    }
+}
+
+/// Context used by the `pages/account/verify.html` templates
+#[derive(Serialize, Default)]
+pub struct EmailAddContext {
+    form: FormState<EmailAddFormField>,
+}
+
+impl EmailAddContext {
+    /// Constructs a context for the email add page
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(form: FormState<EmailAddFormField>) -> Self {
+        Self { form }
+    }
+}
+
+impl TemplateContext for EmailAddContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::default()]
+    }
+}
+
+/// Context used by the `pages/account/emails/change.html` template
+#[derive(Serialize)]
+pub struct EmailChangeContext {
+    form: FormState<EmailAddFormField>,
+    current_email: UserEmail,
+}
+
+impl EmailChangeContext {
+    /// Constructs a context for the email change page
+    #[must_use]
+    pub fn new(current_email: UserEmail) -> Self {
+        Self {
+            form: FormState::default(),
+            current_email,
+        }
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<EmailAddFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+impl TemplateContext for EmailChangeContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let current_email = UserEmail {
+            id: Ulid::from_datetime_with_source(now.into(), rng),
+            user_id: Ulid::from_datetime_with_source(now.into(), rng),
+            email: "alice@example.com".to_owned(),
+            created_at: now,
+            confirmed_at: Some(now),
+        };
+
+        vec![Self::new(current_email)]
+    }
+}
+
+/// Context used by the `pages/account/export.html` template
+#[derive(Serialize, Default)]
+pub struct AccountExportContext {
+    export: Option<UserExport>,
+    expires_at: Option<chrono::DateTime<Utc>>,
+    download_link: Option<Url>,
+}
+
+impl AccountExportContext {
+    /// Constructs a context for the data export page, for a user who never
+    /// exported their data
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the latest export of the user, with the link to download it if it
+    /// is still available
+    #[must_use]
+    pub fn with_export(self, export: UserExport, download_link: Option<Url>) -> Self {
+        Self {
+            expires_at: export.expires_at(),
+            export: Some(export),
+            download_link,
+        }
+    }
+}
+
+impl TemplateContext for AccountExportContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let exports = UserExport::samples(now, rng);
+        let mut samples = vec![Self::new()];
+        for export in exports {
+            let link = format!("https://example.com/export/{}", export.id)
+                .parse()
+                .unwrap();
+
+            // Pending, ready and expired exports
+            let pending = UserExport {
+                completed_at: None,
+                ..export.clone()
+            };
+            samples.push(Self::new().with_export(pending, None));
+            samples.push(Self::new().with_export(export.clone(), Some(link)));
+            samples.push(Self::new().with_export(export, None));
+        }
+        samples
+    }
+}
+
+/// Fields of the impersonation form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImpersonateFormField {
+    /// The username of the user to impersonate
+    Username,
+}
+
+impl FormField for ImpersonateFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Username => true,
+        }
+    }
+}
+
+/// Context used by the `pages/impersonate.html` template
+#[derive(Serialize, Default)]
+pub struct ImpersonateContext {
+    form: FormState<ImpersonateFormField>,
+}
+
+impl ImpersonateContext {
+    /// Constructs a context for the impersonation page
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<ImpersonateFormField>) -> Self {
+        Self { form }
+    }
+}
+
+impl TemplateContext for ImpersonateContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![
+            Self::new(),
+            Self::new().with_form_state(
+                FormState::default()
+                    .with_error_on_field(ImpersonateFormField::Username, FieldError::Invalid),
+            ),
+        ]
+    }
+}
+
+/// Context used by the `pages/admin/users.html` template
+#[derive(Serialize)]
+pub struct AdminUsersContext {
+    users: Vec<User>,
+    total: usize,
+    search: Option<String>,
+    next_page: Option<String>,
+}
+
+impl AdminUsersContext {
+    /// Constructs a context for the list of users, out of the current page of
+    /// users and the total number of users matching the search
+    #[must_use]
+    pub fn new(users: Vec<User>, total: usize) -> Self {
+        Self {
+            users,
+            total,
+            search: None,
+            next_page: None,
+        }
+    }
+
+    /// Set the search string the list was filtered with
+    #[must_use]
+    pub fn with_search(self, search: String) -> Self {
+        Self {
+            search: Some(search),
+            ..self
+        }
+    }
+
+    /// Set the link to the next page of users
+    #[must_use]
+    pub fn with_next_page(self, next_page: String) -> Self {
+        Self {
+            next_page: Some(next_page),
+            ..self
+        }
+    }
+}
+
+impl TemplateContext for AdminUsersContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let users = User::samples(now, rng);
+        let total = users.len();
+        vec![
+            Self::new(Vec::new(), 0),
+            Self::new(Vec::new(), 0).with_search("nobody".to_owned()),
+            Self::new(users.clone(), total),
+            Self::new(users, 42)
+                .with_search("a".to_owned())
+                .with_next_page("/admin/users?q=a&after=00000000000000000000000000".to_owned()),
+        ]
+    }
+}
+
+/// Context used by the `pages/admin/user.html` template
+#[derive(Serialize)]
+pub struct AdminUserContext {
+    user: User,
+    emails: Vec<UserEmail>,
+    browser_sessions: Vec<BrowserSession>,
+    oauth2_sessions: Vec<Session>,
+    compat_sessions: Vec<CompatSession>,
+}
+
+impl AdminUserContext {
+    /// Constructs a context for the details of a user
+    #[must_use]
+    pub fn new(user: User) -> Self {
+        Self {
+            user,
+            emails: Vec::new(),
+            browser_sessions: Vec::new(),
+            oauth2_sessions: Vec::new(),
+            compat_sessions: Vec::new(),
+        }
+    }
+
+    /// Set the email addresses of the user
+    #[must_use]
+    pub fn with_emails(self, emails: Vec<UserEmail>) -> Self {
+        Self { emails, ..self }
+    }
+
+    /// Set the latest sessions of the user, per kind of session
+    #[must_use]
+    pub fn with_sessions(
+        self,
+        browser_sessions: Vec<BrowserSession>,
+        oauth2_sessions: Vec<Session>,
+        compat_sessions: Vec<CompatSession>,
+    ) -> Self {
+        Self {
+            browser_sessions,
+            oauth2_sessions,
+            compat_sessions,
+            ..self
+        }
+    }
+}
+
+impl TemplateContext for AdminUserContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        BrowserSession::samples(now, rng)
+            .into_iter()
+            .map(|browser_session| {
+                let user = browser_session.user.clone();
+                let emails = UserEmail::samples(now, rng);
+                let oauth2_session = Session {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    state: SessionState::Valid,
+                    created_at: now,
+                    user_id: Some(user.id),
+                    user_session_id: Some(browser_session.id),
+                    client_id: Ulid::from_datetime_with_source(now.into(), rng),
+                    scope: [OPENID].into_iter().collect(),
+                    user_agent: browser_session.user_agent.clone(),
+                    last_active_at: Some(now),
+                    last_active_ip: browser_session.last_active_ip,
+                };
+                let compat_session = CompatSession {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    state: CompatSessionState::Finished { finished_at: now },
+                    user_id: user.id,
+                    device: Device::generate(rng),
+                    user_session_id: None,
+                    created_at: now - Duration::days(1),
+                    is_synapse_admin: false,
+                    user_agent: None,
+                    last_active_at: None,
+                    last_active_ip: None,
+                };
+
+                Self::new(user).with_emails(emails).with_sessions(
+                    vec![browser_session],
+                    vec![oauth2_session],
+                    vec![compat_session],
+                )
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `pages/admin/clients.html` template
+#[derive(Serialize)]
+pub struct AdminClientsContext {
+    clients: Vec<Client>,
+}
+
+impl AdminClientsContext {
+    /// Constructs a context for the list of registered clients
+    #[must_use]
+    pub fn new(clients: Vec<Client>) -> Self {
+        Self { clients }
+    }
+}
+
+impl TemplateContext for AdminClientsContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::new(Vec::new()), Self::new(Client::samples(now, rng))]
+    }
+}
+
+/// Fields of the account recovery start form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryStartFormField {
+    /// The email
+    Email,
+}
+
+impl FormField for RecoveryStartFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Email => true,
+        }
+    }
+}
+
+/// Context used by the `pages/recovery/start.html` template
+#[derive(Serialize, Default)]
+pub struct RecoveryStartContext {
+    form: FormState<RecoveryStartFormField>,
+}
+
+impl RecoveryStartContext {
+    /// Constructs a context for the recovery start page
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<RecoveryStartFormField>) -> Self {
+        Self { form }
+    }
+}
+
+impl TemplateContext for RecoveryStartContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![
+            Self::new(),
+            Self::new().with_form_state(
+                FormState::default()
+                    .with_error_on_field(RecoveryStartFormField::Email, FieldError::Required),
+            ),
+            Self::new().with_form_state(
+                FormState::default()
+                    .with_error_on_field(RecoveryStartFormField::Email, FieldError::Invalid),
+            ),
+        ]
+    }
+}
+
+/// Context used by the `pages/recovery/progress.html` template
+#[derive(Serialize)]
+pub struct RecoveryProgressContext {
+    session: UserRecoverySession,
+}
+
+impl RecoveryProgressContext {
+    /// Constructs a context for the recovery progress page
+    #[must_use]
+    pub fn new(session: UserRecoverySession) -> Self {
+        Self { session }
+    }
+}
 
 impl TemplateContext for RecoveryProgressContext {
     fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
@@ -1089,6 +1960,155 @@ impl TemplateContext for RecoveryExpiredContext {
     }
 }
 
+/// Fields of the magic link login start form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MagicLinkStartFormField {
+    /// The email
+    Email,
+}
+
+impl FormField for MagicLinkStartFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Email => true,
+        }
+    }
+}
+
+/// Context used by the `pages/magic_link/start.html` template
+#[derive(Serialize, Default)]
+pub struct MagicLinkStartContext {
+    form: FormState<MagicLinkStartFormField>,
+}
+
+impl MagicLinkStartContext {
+    /// Constructs a context for the magic link start page
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<MagicLinkStartFormField>) -> Self {
+        Self { form }
+    }
+}
+
+impl TemplateContext for MagicLinkStartContext {
+    fn sample(_now: chrono::DateTime<Utc>, _rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![
+            Self::new(),
+            Self::new().with_form_state(
+                FormState::default()
+                    .with_error_on_field(MagicLinkStartFormField::Email, FieldError::Required),
+            ),
+            Self::new().with_form_state(
+                FormState::default()
+                    .with_error_on_field(MagicLinkStartFormField::Email, FieldError::Invalid),
+            ),
+        ]
+    }
+}
+
+/// Context used by the `pages/magic_link/progress.html` template
+#[derive(Serialize)]
+pub struct MagicLinkProgressContext {
+    session: UserMagicLinkSession,
+}
+
+impl MagicLinkProgressContext {
+    /// Constructs a context for the magic link progress page
+    #[must_use]
+    pub fn new(session: UserMagicLinkSession) -> Self {
+        Self { session }
+    }
+}
+
+impl TemplateContext for MagicLinkProgressContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        UserMagicLinkSession::samples(now, rng)
+            .into_iter()
+            .map(Self::new)
+            .collect()
+    }
+}
+
+/// Context used by the `pages/magic_link/confirm.html` template
+#[derive(Serialize)]
+pub struct MagicLinkConfirmContext {
+    user: User,
+    session: UserMagicLinkSession,
+    same_device: bool,
+}
+
+impl MagicLinkConfirmContext {
+    /// Constructs a context for the magic link confirmation page
+    ///
+    /// `same_device` should be true if the link was opened in the browser
+    /// which requested it
+    #[must_use]
+    pub fn new(user: User, session: UserMagicLinkSession, same_device: bool) -> Self {
+        Self {
+            user,
+            session,
+            same_device,
+        }
+    }
+}
+
+impl TemplateContext for MagicLinkConfirmContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let sessions = UserMagicLinkSession::samples(now, rng);
+        User::samples(now, rng)
+            .into_iter()
+            .zip(sessions)
+            .flat_map(|(user, session)| {
+                [
+                    Self::new(user.clone(), session.clone(), true),
+                    Self::new(user, session, false),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `pages/magic_link/expired.html` template
+#[derive(Serialize)]
+pub struct MagicLinkExpiredContext {
+    session: UserMagicLinkSession,
+}
+
+impl MagicLinkExpiredContext {
+    /// Constructs a context for the magic link expired page
+    #[must_use]
+    pub fn new(session: UserMagicLinkSession) -> Self {
+        Self { session }
+    }
+}
+
+impl TemplateContext for MagicLinkExpiredContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        UserMagicLinkSession::samples(now, rng)
+            .into_iter()
+            .map(Self::new)
+            .collect()
+    }
+}
+
 /// Fields of the account recovery finish form
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -1159,6 +2179,142 @@ impl TemplateContext for RecoveryFinishContext {
     }
 }
 
+/// Context used by the `pages/login_alert/report.html` template
+#[derive(Serialize)]
+pub struct LoginAlertReportContext {
+    alert: UserLoginAlert,
+}
+
+impl LoginAlertReportContext {
+    /// Constructs a context for the login alert report page
+    #[must_use]
+    pub fn new(alert: UserLoginAlert) -> Self {
+        Self { alert }
+    }
+}
+
+impl TemplateContext for LoginAlertReportContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        UserLoginAlert::samples(now, rng)
+            .into_iter()
+            .map(Self::new)
+            .collect()
+    }
+}
+
+/// Fields of the login code form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginCodeFormField {
+    /// The code sent by email
+    Code,
+
+    /// The "stay signed in" checkbox
+    Remember,
+}
+
+impl FormField for LoginCodeFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Code => false,
+            Self::Remember => true,
+        }
+    }
+}
+
+/// Context used by the `pages/login_code.html` template
+#[derive(Serialize)]
+pub struct LoginCodeContext {
+    code: UserLoginCode,
+    email: String,
+    active: bool,
+    can_resend: bool,
+    form: FormState<LoginCodeFormField>,
+}
+
+impl LoginCodeContext {
+    /// Constructs a context for the login code page
+    ///
+    /// # Parameters
+    ///
+    /// * `code`: The login code the user has to enter
+    /// * `email`: The email address the code was sent to
+    /// * `now`: The current time, to know whether the code can still be used
+    ///   or sent again
+    #[must_use]
+    pub fn new(code: UserLoginCode, email: String, now: DateTime<Utc>) -> Self {
+        Self {
+            active: code.active(now),
+            can_resend: code.can_resend(now),
+            code,
+            email,
+            form: FormState::default(),
+        }
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<LoginCodeFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+impl TemplateContext for LoginCodeContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        UserLoginCode::samples(now, rng)
+            .into_iter()
+            .flat_map(|code| {
+                let email = "john@example.com".to_owned();
+                [
+                    Self::new(code.clone(), email.clone(), now),
+                    Self::new(code.clone(), email.clone(), now).with_form_state(
+                        FormState::default()
+                            .with_error_on_field(LoginCodeFormField::Code, FieldError::Invalid),
+                    ),
+                    Self::new(
+                        code.clone(),
+                        email.clone(),
+                        now + UserLoginCode::RESEND_INTERVAL,
+                    ),
+                    Self::new(code, email, now + UserLoginCode::VALIDITY),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `pages/email_change/undo.html` template
+#[derive(Serialize)]
+pub struct EmailChangeUndoContext {
+    change: UserEmailChange,
+}
+
+impl EmailChangeUndoContext {
+    /// Constructs a context for the email change undo page
+    #[must_use]
+    pub fn new(change: UserEmailChange) -> Self {
+        Self { change }
+    }
+}
+
+impl TemplateContext for EmailChangeUndoContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        UserEmailChange::samples(now, rng)
+            .into_iter()
+            .map(Self::new)
+            .collect()
+    }
+}
+
 /// Context used by the `pages/upstream_oauth2/{link_mismatch,do_login}.html`
 /// templates
 #[derive(Serialize)]
@@ -1398,7 +2554,8 @@ impl TemplateContext for DeviceConsentContext {
     {
         Client::samples(now, rng)
             .into_iter()
-            .map(|client| {
+            .flat_map(|client| {
+                let browser_session_id = Ulid::from_datetime_with_source(now.into(), rng);
                 let grant = DeviceCodeGrant {
                     id: Ulid::from_datetime_with_source(now.into(), rng),
                     state: mas_data_model::DeviceCodeGrantState::Pending,
@@ -1411,7 +2568,89 @@ impl TemplateContext for DeviceConsentContext {
                     ip_address: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
                     user_agent: Some(UserAgent::parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/93.0.0.0 Safari/537.36".to_owned())),
                 };
-                Self { grant, client }
+
+                // Render the page in every state the grant can be in after a decision
+                let fulfilled = DeviceCodeGrant {
+                    state: mas_data_model::DeviceCodeGrantState::Fulfilled {
+                        browser_session_id,
+                        fulfilled_at: now,
+                    },
+                    ..grant.clone()
+                };
+                let rejected = DeviceCodeGrant {
+                    state: mas_data_model::DeviceCodeGrantState::Rejected {
+                        browser_session_id,
+                        rejected_at: now,
+                    },
+                    ..grant.clone()
+                };
+
+                [grant, fulfilled, rejected].map(|grant| Self {
+                    grant,
+                    client: client.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `backchannel_consent.html` template
+#[derive(Serialize, Debug)]
+pub struct BackchannelConsentContext {
+    grant: BackchannelAuthenticationGrant,
+    client: Client,
+}
+
+impl BackchannelConsentContext {
+    /// Constructs a new context for the given grant and client
+    #[must_use]
+    pub fn new(grant: BackchannelAuthenticationGrant, client: Client) -> Self {
+        Self { grant, client }
+    }
+}
+
+impl TemplateContext for BackchannelConsentContext {
+    fn sample(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        Client::samples(now, rng)
+            .into_iter()
+            .flat_map(|client| {
+                let browser_session_id = Ulid::from_datetime_with_source(now.into(), rng);
+                let grant = BackchannelAuthenticationGrant {
+                    id: Ulid::from_datetime_with_source(now.into(), rng),
+                    state: mas_data_model::BackchannelAuthenticationGrantState::Pending,
+                    client_id: client.id,
+                    scope: [OPENID].into_iter().collect(),
+                    user_id: Ulid::from_datetime_with_source(now.into(), rng),
+                    auth_req_id: Alphanumeric.sample_string(rng, 32),
+                    binding_message: Some("Order #1234".to_owned()),
+                    created_at: now - Duration::try_minutes(5).unwrap(),
+                    expires_at: now + Duration::try_minutes(15).unwrap(),
+                    ip_address: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+                    user_agent: None,
+                };
+
+                let fulfilled = BackchannelAuthenticationGrant {
+                    state: mas_data_model::BackchannelAuthenticationGrantState::Fulfilled {
+                        browser_session_id,
+                        fulfilled_at: now,
+                    },
+                    ..grant.clone()
+                };
+                let rejected = BackchannelAuthenticationGrant {
+                    state: mas_data_model::BackchannelAuthenticationGrantState::Rejected {
+                        browser_session_id,
+                        rejected_at: now,
+                    },
+                    ..grant.clone()
+                };
+
+                [grant, fulfilled, rejected].map(|grant| Self {
+                    grant,
+                    client: client.clone(),
+                })
             })
             .collect()
     }
@@ -1422,6 +2661,7 @@ impl TemplateContext for DeviceConsentContext {
 pub struct FormPostContext<T> {
     redirect_uri: Url,
     params: T,
+    lang: Option<String>,
 }
 
 impl<T: TemplateContext> TemplateContext for FormPostContext<T> {
@@ -1435,6 +2675,7 @@ impl<T: TemplateContext> TemplateContext for FormPostContext<T> {
             .map(|params| FormPostContext {
                 redirect_uri: "https://example.com/callback".parse().unwrap(),
                 params,
+                lang: None,
             })
             .collect()
     }
@@ -1446,8 +2687,16 @@ impl<T> FormPostContext<T> {
         Self {
             redirect_uri,
             params,
+            lang: None,
         }
     }
+
+    /// Add the language to the context
+    #[must_use]
+    pub fn with_language(mut self, lang: &DataLocale) -> Self {
+        self.lang = Some(lang.to_string());
+        self
+    }
 }
 
 /// Context used by the `error.html` template
@@ -1487,6 +2736,11 @@ impl TemplateContext for ErrorContext {
                 .with_description("A fancy description".into())
                 .with_details("Something happened".into()),
             Self::new().with_code("another_error"),
+            Self::new().with_code("method_not_allowed"),
+            Self::new().with_code("csrf_failed"),
+            Self::new().with_code("session_expired"),
+            Self::new().with_code("device_code_expired"),
+            Self::new().with_code("request_timeout"),
             Self::new(),
         ]
     }
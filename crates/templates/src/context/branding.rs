@@ -14,18 +14,100 @@
 
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use minijinja::{
     value::{Enumerator, Object},
     Value,
 };
 
+/// An announcement banner, with its message already rendered to HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Announcement {
+    html: Arc<str>,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+}
+
+impl Announcement {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at.map_or(true, |starts_at| starts_at <= now)
+            && self.ends_at.map_or(true, |ends_at| now < ends_at)
+    }
+}
+
+/// Render a small subset of Markdown to HTML: `**bold**`, `*italic*`,
+/// `` `code` ``, `[links](https://…)` and line breaks. Everything else is
+/// escaped.
+fn render_markdown(input: &str, out: &mut String) {
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if let Some((inner, after)) = rest
+            .strip_prefix("**")
+            .and_then(|r| r.split_once("**"))
+            .filter(|(inner, _)| !inner.is_empty())
+        {
+            out.push_str("<strong>");
+            render_markdown(inner, out);
+            out.push_str("</strong>");
+            rest = after;
+        } else if let Some((inner, after)) = rest
+            .strip_prefix('*')
+            .and_then(|r| r.split_once('*'))
+            .filter(|(inner, _)| !inner.is_empty())
+        {
+            out.push_str("<em>");
+            render_markdown(inner, out);
+            out.push_str("</em>");
+            rest = after;
+        } else if let Some((inner, after)) = rest
+            .strip_prefix('`')
+            .and_then(|r| r.split_once('`'))
+            .filter(|(inner, _)| !inner.is_empty())
+        {
+            out.push_str("<code>");
+            out.push_str(&v_htmlescape::escape(inner).to_string());
+            out.push_str("</code>");
+            rest = after;
+        } else if let Some((text, url, after)) = rest
+            .strip_prefix('[')
+            .and_then(|r| r.split_once("]("))
+            .and_then(|(text, r)| r.split_once(')').map(|(url, after)| (text, url, after)))
+            .filter(|(text, url, _)| {
+                !text.is_empty()
+                    && url::Url::parse(url)
+                        .is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "mailto"))
+            })
+        {
+            out.push_str(r#"<a class="cpd-link" data-kind="primary" href=""#);
+            out.push_str(&v_htmlescape::escape(url).to_string());
+            out.push_str(r#"" rel="noreferrer noopener">"#);
+            render_markdown(text, out);
+            out.push_str("</a>");
+            rest = after;
+        } else if c == '\n' {
+            out.push_str("<br />");
+            rest = &rest[1..];
+        } else {
+            let (current, after) = rest.split_at(c.len_utf8());
+            out.push_str(&v_htmlescape::escape(current).to_string());
+            rest = after;
+        }
+    }
+}
+
 /// Site branding information.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SiteBranding {
     server_name: Arc<str>,
+    service_name: Option<Arc<str>>,
+    logo_uri: Option<Arc<str>>,
+    primary_color: Option<Arc<str>>,
+    accent_color: Option<Arc<str>>,
+    custom_css: Option<Arc<str>>,
     policy_uri: Option<Arc<str>>,
     tos_uri: Option<Arc<str>>,
     imprint: Option<Arc<str>>,
+    announcement: Option<Announcement>,
 }
 
 impl SiteBranding {
@@ -34,12 +116,53 @@ impl SiteBranding {
     pub fn new(server_name: impl Into<Arc<str>>) -> Self {
         Self {
             server_name: server_name.into(),
+            service_name: None,
+            logo_uri: None,
+            primary_color: None,
+            accent_color: None,
+            custom_css: None,
             policy_uri: None,
             tos_uri: None,
             imprint: None,
+            announcement: None,
         }
     }
 
+    /// Set the human-readable service name.
+    #[must_use]
+    pub fn with_service_name(mut self, service_name: impl Into<Arc<str>>) -> Self {
+        self.service_name = Some(service_name.into());
+        self
+    }
+
+    /// Set the logo URI.
+    #[must_use]
+    pub fn with_logo_uri(mut self, logo_uri: impl Into<Arc<str>>) -> Self {
+        self.logo_uri = Some(logo_uri.into());
+        self
+    }
+
+    /// Set the primary brand colour.
+    #[must_use]
+    pub fn with_primary_color(mut self, primary_color: impl Into<Arc<str>>) -> Self {
+        self.primary_color = Some(primary_color.into());
+        self
+    }
+
+    /// Set the accent colour.
+    #[must_use]
+    pub fn with_accent_color(mut self, accent_color: impl Into<Arc<str>>) -> Self {
+        self.accent_color = Some(accent_color.into());
+        self
+    }
+
+    /// Set the additional CSS.
+    #[must_use]
+    pub fn with_custom_css(mut self, custom_css: impl Into<Arc<str>>) -> Self {
+        self.custom_css = Some(custom_css.into());
+        self
+    }
+
     /// Set the policy URI.
     #[must_use]
     pub fn with_policy_uri(mut self, policy_uri: impl Into<Arc<str>>) -> Self {
@@ -60,20 +183,124 @@ impl SiteBranding {
         self.imprint = Some(imprint.into());
         self
     }
+
+    /// Set the announcement banner, displayed between `starts_at` and
+    /// `ends_at`. The message is rendered from a small subset of Markdown.
+    #[must_use]
+    pub fn with_announcement(
+        mut self,
+        message: &str,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        let mut html = String::new();
+        render_markdown(message.trim(), &mut html);
+        self.announcement = Some(Announcement {
+            html: html.into(),
+            starts_at,
+            ends_at,
+        });
+        self
+    }
 }
 
 impl Object for SiteBranding {
     fn get_value(self: &Arc<Self>, name: &Value) -> Option<Value> {
         match name.as_str()? {
             "server_name" => Some(self.server_name.clone().into()),
+            "service_name" => self.service_name.clone().map(Value::from),
+            "logo_uri" => self.logo_uri.clone().map(Value::from),
+            "primary_color" => self.primary_color.clone().map(Value::from),
+            "accent_color" => self.accent_color.clone().map(Value::from),
+            "custom_css" => self.custom_css.clone().map(Value::from),
             "policy_uri" => self.policy_uri.clone().map(Value::from),
             "tos_uri" => self.tos_uri.clone().map(Value::from),
             "imprint" => self.imprint.clone().map(Value::from),
+            "announcement" => {
+                // TODO: grab the clock somewhere
+                #[allow(clippy::disallowed_methods)]
+                let now = Utc::now();
+                self.announcement
+                    .as_ref()
+                    .filter(|announcement| announcement.is_active(now))
+                    .map(|announcement| Value::from_safe_string(announcement.html.to_string()))
+            }
             _ => None,
         }
     }
 
     fn enumerate(self: &Arc<Self>) -> Enumerator {
-        Enumerator::Str(&["server_name", "policy_uri", "tos_uri", "imprint"])
+        Enumerator::Str(&[
+            "server_name",
+            "service_name",
+            "logo_uri",
+            "primary_color",
+            "accent_color",
+            "custom_css",
+            "policy_uri",
+            "tos_uri",
+            "imprint",
+            "announcement",
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(input: &str) -> String {
+        let mut out = String::new();
+        render_markdown(input, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(render("Hello"), "Hello");
+        assert_eq!(
+            render("**Maintenance** on *Monday*"),
+            "<strong>Maintenance</strong> on <em>Monday</em>"
+        );
+        assert_eq!(render("Run `mas-cli`"), "Run <code>mas-cli</code>");
+        assert_eq!(
+            render("See [the **status** page](https://status.example.com/)"),
+            r#"See <a class="cpd-link" data-kind="primary" href="https:&#x2f;&#x2f;status.example.com&#x2f;" rel="noreferrer noopener">the <strong>status</strong> page</a>"#
+        );
+        assert_eq!(render("one\ntwo"), "one<br />two");
+
+        // Unbalanced markers are kept as-is
+        assert_eq!(render("2 * 3 = 6"), "2 * 3 = 6");
+        assert_eq!(render("**bold"), "**bold");
+
+        // HTML is escaped, and only some URL schemes are allowed
+        assert_eq!(
+            render("<script>alert(1)</script>"),
+            "&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"
+        );
+        assert_eq!(
+            render("[click](javascript:alert(1))"),
+            "[click](javascript:alert(1))"
+        );
+        assert_eq!(
+            render(r#"[x](https://example.com/"onmouseover=")"#),
+            r#"<a class="cpd-link" data-kind="primary" href="https:&#x2f;&#x2f;example.com&#x2f;&quot;onmouseover=&quot;" rel="noreferrer noopener">x</a>"#
+        );
+    }
+
+    #[test]
+    fn test_announcement_window() {
+        let starts_at = DateTime::from_timestamp(1_000, 0).unwrap();
+        let ends_at = DateTime::from_timestamp(2_000, 0).unwrap();
+        let announcement = Announcement {
+            html: "".into(),
+            starts_at: Some(starts_at),
+            ends_at: Some(ends_at),
+        };
+
+        assert!(!announcement.is_active(DateTime::from_timestamp(999, 0).unwrap()));
+        assert!(announcement.is_active(starts_at));
+        assert!(announcement.is_active(DateTime::from_timestamp(1_500, 0).unwrap()));
+        assert!(!announcement.is_active(ends_at));
     }
 }
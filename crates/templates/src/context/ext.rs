@@ -35,6 +35,26 @@ impl SiteConfigExt for SiteConfig {
     fn templates_branding(&self) -> SiteBranding {
         let mut branding = SiteBranding::new(self.server_name.clone());
 
+        if let Some(service_name) = &self.service_name {
+            branding = branding.with_service_name(service_name.as_str());
+        }
+
+        if let Some(logo_uri) = &self.logo_uri {
+            branding = branding.with_logo_uri(logo_uri.as_str());
+        }
+
+        if let Some(primary_color) = &self.primary_color {
+            branding = branding.with_primary_color(primary_color.as_str());
+        }
+
+        if let Some(accent_color) = &self.accent_color {
+            branding = branding.with_accent_color(accent_color.as_str());
+        }
+
+        if let Some(custom_css) = &self.custom_css {
+            branding = branding.with_custom_css(custom_css.as_str());
+        }
+
         if let Some(policy_uri) = &self.policy_uri {
             branding = branding.with_policy_uri(policy_uri.as_str());
         }
@@ -47,6 +67,14 @@ impl SiteConfigExt for SiteConfig {
             branding = branding.with_imprint(imprint.as_str());
         }
 
+        if let Some(announcement) = &self.announcement {
+            branding = branding.with_announcement(
+                &announcement.message,
+                announcement.starts_at,
+                announcement.ends_at,
+            );
+        }
+
         branding
     }
 
@@ -55,6 +83,8 @@ impl SiteConfigExt for SiteConfig {
             password_registration: self.password_registration_enabled,
             password_login: self.password_login_enabled,
             account_recovery: self.account_recovery_allowed,
+            magic_link_login: self.magic_link_login_allowed,
+            certificate_login: self.client_certificate_login.is_some(),
         }
     }
 }
@@ -30,6 +30,12 @@ pub struct SiteFeatures {
 
     /// Whether email-based account recovery is enabled.
     pub account_recovery: bool,
+
+    /// Whether passwordless login by email link is enabled.
+    pub magic_link_login: bool,
+
+    /// Whether login with a TLS client certificate is enabled.
+    pub certificate_login: bool,
 }
 
 impl Object for SiteFeatures {
@@ -38,6 +44,8 @@ impl Object for SiteFeatures {
             "password_registration" => Some(Value::from(self.password_registration)),
             "password_login" => Some(Value::from(self.password_login)),
             "account_recovery" => Some(Value::from(self.account_recovery)),
+            "magic_link_login" => Some(Value::from(self.magic_link_login)),
+            "certificate_login" => Some(Value::from(self.certificate_login)),
             _ => None,
         }
     }
@@ -47,6 +55,8 @@ impl Object for SiteFeatures {
             "password_registration",
             "password_login",
             "account_recovery",
+            "magic_link_login",
+            "certificate_login",
         ])
     }
 }
@@ -0,0 +1,35 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-response nonces for the `Content-Security-Policy` header
+//!
+//! The nonce is set by the HTTP layer for the duration of a request, and
+//! exposed to the templates through the `csp_nonce()` function.
+
+use std::future::Future;
+
+tokio::task_local! {
+    static CSP_NONCE: String;
+}
+
+/// Run the given future with a CSP nonce available to the templates rendered
+/// within it
+pub async fn with_csp_nonce<F: Future>(nonce: String, future: F) -> F::Output {
+    CSP_NONCE.scope(nonce, future).await
+}
+
+/// Get the CSP nonce of the current request, if any
+pub(crate) fn current_nonce() -> Option<String> {
+    CSP_NONCE.try_with(Clone::clone).ok()
+}
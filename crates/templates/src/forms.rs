@@ -70,6 +70,12 @@ pub enum FormError {
 
     /// Failed to validate CAPTCHA
     Captcha,
+
+    /// Too many attempts, the user should try again later
+    RateLimitExceeded,
+
+    /// Denied by the anti-abuse checks
+    Denied,
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -50,9 +50,19 @@ pub fn register(
     env.add_filter("split", filter_split);
     env.add_function("add_params_to_url", function_add_params_to_url);
     env.add_function("counter", || Ok(Value::from_object(Counter::default())));
+    env.add_function("csp_nonce", || {
+        crate::csp::current_nonce().unwrap_or_default()
+    });
     env.add_global(
         "include_asset",
         Value::from_object(IncludeAsset {
+            url_builder: url_builder.clone(),
+            vite_manifest: vite_manifest.clone(),
+        }),
+    );
+    env.add_global(
+        "asset_url",
+        Value::from_object(AssetUrl {
             url_builder: url_builder.clone(),
             vite_manifest,
         }),
@@ -482,6 +492,43 @@ impl Object for IncludeAsset {
     }
 }
 
+/// Resolves the URL of a single asset, e.g. an image, from its original name
+struct AssetUrl {
+    url_builder: UrlBuilder,
+    vite_manifest: ViteManifest,
+}
+
+impl std::fmt::Debug for AssetUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AssetUrl")
+            .field("url_builder", &self.url_builder.assets_base())
+            .field("vite_manifest", &"..")
+            .finish()
+    }
+}
+
+impl std::fmt::Display for AssetUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("asset_url")
+    }
+}
+
+impl Object for AssetUrl {
+    fn call(self: &Arc<Self>, _state: &State, args: &[Value]) -> Result<Value, Error> {
+        let (path,): (&str,) = from_args(args)?;
+
+        let file = self.vite_manifest.file_for(path.into()).map_err(|_e| {
+            Error::new(
+                ErrorKind::InvalidOperation,
+                format!("Asset {path:?} not found in the assets manifest"),
+            )
+        })?;
+
+        let url = Utf8Path::new(self.url_builder.assets_base()).join(file);
+        Ok(Value::from(url.into_string()))
+    }
+}
+
 #[derive(Debug, Default)]
 struct Counter {
     count: AtomicUsize,
@@ -17,7 +17,12 @@
 
 //! Templates rendering
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::Context as _;
 use arc_swap::ArcSwap;
@@ -30,29 +35,40 @@ use rand::Rng;
 use serde::Serialize;
 use thiserror::Error;
 use tokio::task::JoinError;
-use tracing::{debug, info};
+use tracing::{debug, error, info, warn};
 use walkdir::DirEntry;
 
 mod context;
+mod csp;
 mod forms;
 mod functions;
+mod overrides;
 
 #[macro_use]
 mod macros;
 
 pub use self::{
     context::{
-        AppContext, CompatSsoContext, ConsentContext, DeviceConsentContext, DeviceLinkContext,
-        DeviceLinkFormField, EmailAddContext, EmailRecoveryContext, EmailVerificationContext,
-        EmailVerificationPageContext, EmptyContext, ErrorContext, FormPostContext, IndexContext,
-        LoginContext, LoginFormField, NotFoundContext, PolicyViolationContext, PostAuthContext,
-        PostAuthContextInner, ReauthContext, ReauthFormField, RecoveryExpiredContext,
-        RecoveryFinishContext, RecoveryFinishFormField, RecoveryProgressContext,
-        RecoveryStartContext, RecoveryStartFormField, RegisterContext, RegisterFormField,
-        SiteBranding, SiteConfigExt, SiteFeatures, TemplateContext, UpstreamExistingLinkContext,
-        UpstreamRegister, UpstreamRegisterFormField, UpstreamSuggestLink, WithCaptcha, WithCsrf,
-        WithLanguage, WithOptionalSession, WithSession,
+        AccountExportContext, AdminClientsContext, AdminUserContext, AdminUsersContext, AppContext,
+        BackchannelConsentContext, CompatSsoContext, ConsentContext, DeviceConsentContext,
+        DeviceLinkContext, DeviceLinkFormField, EmailAddContext,
+        EmailBackchannelAuthenticationContext, EmailChangeContext, EmailChangeNotificationContext,
+        EmailChangeUndoContext, EmailLoginAlertContext, EmailLoginCodeContext,
+        EmailMagicLinkContext, EmailPasswordChangeNotificationContext, EmailRecoveryContext,
+        EmailUserExportContext, EmailVerificationContext, EmailVerificationPageContext,
+        EmptyContext, ErrorContext, FormPostContext, ImpersonateContext, ImpersonateFormField,
+        IndexContext, LoginAlertReportContext, LoginCodeContext, LoginCodeFormField, LoginContext,
+        LoginFormField, LoginThrottledContext, MagicLinkConfirmContext, MagicLinkExpiredContext,
+        MagicLinkProgressContext, MagicLinkStartContext, MagicLinkStartFormField, NotFoundContext,
+        PhoneVerificationContext, PolicyViolationContext, PostAuthContext, PostAuthContextInner,
+        ReauthContext, ReauthFormField, RecoveryExpiredContext, RecoveryFinishContext,
+        RecoveryFinishFormField, RecoveryProgressContext, RecoveryStartContext,
+        RecoveryStartFormField, RegisterContext, RegisterFormField, SiteBranding, SiteConfigExt,
+        SiteFeatures, TemplateContext, UpstreamExistingLinkContext, UpstreamRegister,
+        UpstreamRegisterFormField, UpstreamSuggestLink, WithCaptcha, WithCsrf, WithLanguage,
+        WithOptionalSession, WithSession,
     },
+    csp::with_csp_nonce,
     forms::{FieldError, FormError, FormField, FormState, ToFormState},
 };
 
@@ -76,6 +92,7 @@ pub struct Templates {
     vite_manifest_path: Utf8PathBuf,
     translations_path: Utf8PathBuf,
     path: Utf8PathBuf,
+    overrides_path: Option<Utf8PathBuf>,
 }
 
 /// There was an issue while loading the templates
@@ -129,6 +146,30 @@ pub enum TemplateLoadingError {
         /// List of templates that were loaded
         loaded: HashSet<String>,
     },
+
+    /// A template override does not define all the blocks of the built-in
+    /// template
+    #[error(
+        "template override {template:?} does not define the blocks {missing:?}, which other templates expect it to define"
+    )]
+    OverrideMissingBlocks {
+        /// The overridden template
+        template: String,
+        /// List of the blocks missing from the override
+        missing: BTreeSet<String>,
+    },
+
+    /// A template override does not use some variables essential for the
+    /// template to work
+    #[error(
+        "template override {template:?} does not use {missing:?}, without which the template can't work"
+    )]
+    OverrideMissingVariables {
+        /// The overridden template
+        template: String,
+        /// List of the variables missing from the override
+        missing: BTreeSet<String>,
+    },
 }
 
 fn is_hidden(entry: &DirEntry) -> bool {
@@ -138,8 +179,56 @@ fn is_hidden(entry: &DirEntry) -> bool {
         .is_some_and(|s| s.starts_with('.'))
 }
 
+/// List the files under the given paths, with their modification time and
+/// size, to detect changes between two calls
+fn snapshot_files(paths: &[Utf8PathBuf]) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let mut files = Vec::new();
+    for path in paths {
+        for entry in walkdir::WalkDir::new(path).into_iter().flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if metadata.is_file() {
+                files.push((entry.into_path(), metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// Find the templates in the given directory, and return their path relative
+/// to it
+fn find_templates(root: &Utf8Path) -> Result<Vec<Utf8PathBuf>, TemplateLoadingError> {
+    let mut templates = Vec::new();
+    for entry in walkdir::WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_hidden(e))
+    {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let path = Utf8PathBuf::try_from(entry.into_path())?;
+            let Some(ext) = path.extension() else {
+                continue;
+            };
+
+            if ext == "html" || ext == "txt" || ext == "subject" {
+                templates.push(path.strip_prefix(root)?.to_owned());
+            }
+        }
+    }
+
+    Ok(templates)
+}
+
 impl Templates {
     /// Load the templates from the given config
+    ///
+    /// Templates found in `overrides_path`, if set, take precedence over the
+    /// ones with the same name in `path`.
     #[tracing::instrument(
         name = "templates.load",
         skip_all,
@@ -148,6 +237,7 @@ impl Templates {
     )]
     pub async fn load(
         path: Utf8PathBuf,
+        overrides_path: Option<Utf8PathBuf>,
         url_builder: UrlBuilder,
         vite_manifest_path: Utf8PathBuf,
         translations_path: Utf8PathBuf,
@@ -156,6 +246,7 @@ impl Templates {
     ) -> Result<Self, TemplateLoadingError> {
         let (translator, environment) = Self::load_(
             &path,
+            overrides_path.as_deref(),
             url_builder.clone(),
             &vite_manifest_path,
             &translations_path,
@@ -167,6 +258,7 @@ impl Templates {
             environment: Arc::new(ArcSwap::new(environment)),
             translator: Arc::new(ArcSwap::new(translator)),
             path,
+            overrides_path,
             url_builder,
             vite_manifest_path,
             translations_path,
@@ -177,6 +269,7 @@ impl Templates {
 
     async fn load_(
         path: &Utf8Path,
+        overrides_path: Option<&Utf8Path>,
         url_builder: UrlBuilder,
         vite_manifest_path: &Utf8Path,
         translations_path: &Utf8Path,
@@ -184,6 +277,7 @@ impl Templates {
         features: SiteFeatures,
     ) -> Result<(Arc<Translator>, Arc<minijinja::Environment<'static>>), TemplateLoadingError> {
         let path = path.to_owned();
+        let overrides_path = overrides_path.map(ToOwned::to_owned);
         let span = tracing::Span::current();
 
         // Read the assets manifest from disk
@@ -209,25 +303,30 @@ impl Templates {
                 let mut env = minijinja::Environment::new();
                 let root = path.canonicalize_utf8()?;
                 info!(%root, "Loading templates from filesystem");
-                for entry in walkdir::WalkDir::new(&root)
-                    .min_depth(1)
-                    .into_iter()
-                    .filter_entry(|e| !is_hidden(e))
-                {
-                    let entry = entry?;
-                    if entry.file_type().is_file() {
-                        let path = Utf8PathBuf::try_from(entry.into_path())?;
-                        let Some(ext) = path.extension() else {
-                            continue;
-                        };
-
-                        if ext == "html" || ext == "txt" || ext == "subject" {
-                            let relative = path.strip_prefix(&root)?;
-                            debug!(%relative, "Registering template");
-                            let template = std::fs::read_to_string(&path)?;
-                            env.add_template_owned(relative.as_str().to_owned(), template)?;
-                            loaded.insert(relative.as_str().to_owned());
+                for relative in find_templates(&root)? {
+                    debug!(%relative, "Registering template");
+                    let template = std::fs::read_to_string(root.join(&relative))?;
+                    env.add_template_owned(relative.as_str().to_owned(), template)?;
+                    loaded.insert(relative.into_string());
+                }
+
+                if let Some(overrides_path) = overrides_path {
+                    let overrides_root = overrides_path.canonicalize_utf8()?;
+                    info!(%overrides_root, "Loading template overrides from filesystem");
+                    for relative in find_templates(&overrides_root)? {
+                        let template = std::fs::read_to_string(overrides_root.join(&relative))?;
+                        if loaded.contains(relative.as_str()) {
+                            debug!(%relative, "Overriding template");
+                            let builtin = std::fs::read_to_string(root.join(&relative))?;
+                            self::overrides::check(relative.as_str(), &builtin, &template)?;
+                        } else {
+                            warn!(
+                                %relative,
+                                "Template override does not match any built-in template, it will only be used if another template includes it"
+                            );
                         }
+
+                        env.add_template_owned(relative.into_string(), template)?;
                     }
                 }
 
@@ -269,6 +368,7 @@ impl Templates {
     pub async fn reload(&self) -> Result<(), TemplateLoadingError> {
         let (translator, environment) = Self::load_(
             &self.path,
+            self.overrides_path.as_deref(),
             self.url_builder.clone(),
             &self.vite_manifest_path,
             &self.translations_path,
@@ -284,6 +384,59 @@ impl Templates {
         Ok(())
     }
 
+    /// Watch the templates, translations and assets manifest on disk, and
+    /// reload them when they change
+    ///
+    /// The files are polled at the given interval, in a background task. This
+    /// is meant to be used during development, to avoid restarting the server
+    /// on every change.
+    pub fn watch(&self, interval: Duration) {
+        let templates = self.clone();
+        let mut paths = vec![
+            self.path.clone(),
+            self.translations_path.clone(),
+            self.vite_manifest_path.clone(),
+        ];
+        paths.extend(self.overrides_path.clone());
+        let paths = Arc::new(paths);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let mut previous = None;
+            loop {
+                ticker.tick().await;
+
+                let paths = Arc::clone(&paths);
+                let current =
+                    match tokio::task::spawn_blocking(move || snapshot_files(&paths)).await {
+                        Ok(current) => current,
+                        Err(e) => {
+                            error!(
+                                error = &e as &dyn std::error::Error,
+                                "Failed to list the templates"
+                            );
+                            continue;
+                        }
+                    };
+
+                if previous
+                    .as_ref()
+                    .is_some_and(|previous| *previous != current)
+                {
+                    info!("Templates changed on disk, reloading");
+                    if let Err(e) = templates.reload().await {
+                        error!(
+                            error = &e as &dyn std::error::Error,
+                            "Failed to reload templates"
+                        );
+                    }
+                }
+
+                previous = Some(current);
+            }
+        });
+    }
+
     /// Get the translator
     #[must_use]
     pub fn translator(&self) -> Arc<Translator> {
@@ -327,6 +480,9 @@ register_templates! {
     /// Render the login page
     pub fn render_login(WithLanguage<WithCsrf<LoginContext>>) { "pages/login.html" }
 
+    /// Render the page shown when too many login attempts were made
+    pub fn render_login_throttled(WithLanguage<LoginThrottledContext>) { "pages/login_throttled.html" }
+
     /// Render the registration page
     pub fn render_register(WithLanguage<WithCsrf<WithCaptcha<RegisterContext>>>) { "pages/register.html" }
 
@@ -348,6 +504,12 @@ register_templates! {
     /// Render the email verification page
     pub fn render_account_add_email(WithLanguage<WithCsrf<WithSession<EmailAddContext>>>) { "pages/account/emails/add.html" }
 
+    /// Render the email change page
+    pub fn render_account_change_email(WithLanguage<WithCsrf<WithSession<EmailChangeContext>>>) { "pages/account/emails/change.html" }
+
+    /// Render the data export page
+    pub fn render_account_export(WithLanguage<WithCsrf<WithSession<AccountExportContext>>>) { "pages/account/export.html" }
+
     /// Render the account recovery start page
     pub fn render_recovery_start(WithLanguage<WithCsrf<RecoveryStartContext>>) { "pages/recovery/start.html" }
 
@@ -366,9 +528,57 @@ register_templates! {
     /// Render the account recovery disabled page
     pub fn render_recovery_disabled(WithLanguage<EmptyContext>) { "pages/recovery/disabled.html" }
 
+    /// Render the magic link login start page
+    pub fn render_magic_link_start(WithLanguage<WithCsrf<MagicLinkStartContext>>) { "pages/magic_link/start.html" }
+
+    /// Render the magic link login progress page
+    pub fn render_magic_link_progress(WithLanguage<WithCsrf<MagicLinkProgressContext>>) { "pages/magic_link/progress.html" }
+
+    /// Render the magic link login confirmation page
+    pub fn render_magic_link_confirm(WithLanguage<WithCsrf<MagicLinkConfirmContext>>) { "pages/magic_link/confirm.html" }
+
+    /// Render the magic link expired page
+    pub fn render_magic_link_expired(WithLanguage<WithCsrf<MagicLinkExpiredContext>>) { "pages/magic_link/expired.html" }
+
+    /// Render the magic link consumed page
+    pub fn render_magic_link_consumed(WithLanguage<EmptyContext>) { "pages/magic_link/consumed.html" }
+
+    /// Render the login alert report page
+    pub fn render_login_alert_report(WithLanguage<WithCsrf<LoginAlertReportContext>>) { "pages/login_alert/report.html" }
+
+    /// Render the login alert reported page
+    pub fn render_login_alert_reported(WithLanguage<EmptyContext>) { "pages/login_alert/reported.html" }
+
+    /// Render the login alert link expired page
+    pub fn render_login_alert_expired(WithLanguage<EmptyContext>) { "pages/login_alert/expired.html" }
+
+    /// Render the page asking for the code sent by email to confirm a login
+    pub fn render_login_code(WithLanguage<WithCsrf<LoginCodeContext>>) { "pages/login_code.html" }
+
+    /// Render the email change undo page
+    pub fn render_email_change_undo(WithLanguage<WithCsrf<EmailChangeUndoContext>>) { "pages/email_change/undo.html" }
+
+    /// Render the email change undone page
+    pub fn render_email_change_undone(WithLanguage<EmptyContext>) { "pages/email_change/undone.html" }
+
+    /// Render the email change undo link expired page
+    pub fn render_email_change_expired(WithLanguage<EmptyContext>) { "pages/email_change/expired.html" }
+
     /// Render the re-authentication form
     pub fn render_reauth(WithLanguage<WithCsrf<WithSession<ReauthContext>>>) { "pages/reauth.html" }
 
+    /// Render the admin impersonation page
+    pub fn render_impersonate(WithLanguage<WithCsrf<WithSession<ImpersonateContext>>>) { "pages/impersonate.html" }
+
+    /// Render the list of users on the admin pages
+    pub fn render_admin_users(WithLanguage<AdminUsersContext>) { "pages/admin/users.html" }
+
+    /// Render the details of a user on the admin pages
+    pub fn render_admin_user(WithLanguage<AdminUserContext>) { "pages/admin/user.html" }
+
+    /// Render the list of clients on the admin pages
+    pub fn render_admin_clients(WithLanguage<AdminClientsContext>) { "pages/admin/clients.html" }
+
     /// Render the form used by the form_post response mode
     pub fn render_form_post<T: Serialize>(FormPostContext<T>) { "form_post.html" }
 
@@ -393,6 +603,72 @@ register_templates! {
     /// Render the email verification subject
     pub fn render_email_verification_subject(WithLanguage<EmailVerificationContext>) { "emails/verification.subject" }
 
+    /// Render the magic link email (plain text variant)
+    pub fn render_email_magic_link_txt(WithLanguage<EmailMagicLinkContext>) { "emails/magic_link.txt" }
+
+    /// Render the magic link email (HTML text variant)
+    pub fn render_email_magic_link_html(WithLanguage<EmailMagicLinkContext>) { "emails/magic_link.html" }
+
+    /// Render the magic link email subject
+    pub fn render_email_magic_link_subject(WithLanguage<EmailMagicLinkContext>) { "emails/magic_link.subject" }
+
+    /// Render the login alert email (plain text variant)
+    pub fn render_email_login_alert_txt(WithLanguage<EmailLoginAlertContext>) { "emails/login_alert.txt" }
+
+    /// Render the login alert email (HTML text variant)
+    pub fn render_email_login_alert_html(WithLanguage<EmailLoginAlertContext>) { "emails/login_alert.html" }
+
+    /// Render the login alert email subject
+    pub fn render_email_login_alert_subject(WithLanguage<EmailLoginAlertContext>) { "emails/login_alert.subject" }
+
+    /// Render the login code email (plain text variant)
+    pub fn render_email_login_code_txt(WithLanguage<EmailLoginCodeContext>) { "emails/login_code.txt" }
+
+    /// Render the login code email (HTML text variant)
+    pub fn render_email_login_code_html(WithLanguage<EmailLoginCodeContext>) { "emails/login_code.html" }
+
+    /// Render the login code email subject
+    pub fn render_email_login_code_subject(WithLanguage<EmailLoginCodeContext>) { "emails/login_code.subject" }
+
+    /// Render the backchannel authentication email (plain text variant)
+    pub fn render_email_backchannel_authentication_txt(WithLanguage<EmailBackchannelAuthenticationContext>) { "emails/backchannel_authentication.txt" }
+
+    /// Render the backchannel authentication email (HTML text variant)
+    pub fn render_email_backchannel_authentication_html(WithLanguage<EmailBackchannelAuthenticationContext>) { "emails/backchannel_authentication.html" }
+
+    /// Render the backchannel authentication email subject
+    pub fn render_email_backchannel_authentication_subject(WithLanguage<EmailBackchannelAuthenticationContext>) { "emails/backchannel_authentication.subject" }
+
+    /// Render the email change notification (plain text variant)
+    pub fn render_email_change_notification_txt(WithLanguage<EmailChangeNotificationContext>) { "emails/email_change.txt" }
+
+    /// Render the email change notification (HTML text variant)
+    pub fn render_email_change_notification_html(WithLanguage<EmailChangeNotificationContext>) { "emails/email_change.html" }
+
+    /// Render the email change notification subject
+    pub fn render_email_change_notification_subject(WithLanguage<EmailChangeNotificationContext>) { "emails/email_change.subject" }
+
+    /// Render the password change notification (plain text variant)
+    pub fn render_email_password_change_notification_txt(WithLanguage<EmailPasswordChangeNotificationContext>) { "emails/password_change.txt" }
+
+    /// Render the password change notification (HTML text variant)
+    pub fn render_email_password_change_notification_html(WithLanguage<EmailPasswordChangeNotificationContext>) { "emails/password_change.html" }
+
+    /// Render the password change notification subject
+    pub fn render_email_password_change_notification_subject(WithLanguage<EmailPasswordChangeNotificationContext>) { "emails/password_change.subject" }
+
+    /// Render the email sent when a data export is ready (plain text variant)
+    pub fn render_email_user_export_txt(WithLanguage<EmailUserExportContext>) { "emails/user_export.txt" }
+
+    /// Render the email sent when a data export is ready (HTML text variant)
+    pub fn render_email_user_export_html(WithLanguage<EmailUserExportContext>) { "emails/user_export.html" }
+
+    /// Render the email sent when a data export is ready subject
+    pub fn render_email_user_export_subject(WithLanguage<EmailUserExportContext>) { "emails/user_export.subject" }
+
+    /// Render the phone number verification text message
+    pub fn render_sms_verification(WithLanguage<PhoneVerificationContext>) { "sms/verification.txt" }
+
     /// Render the upstream link mismatch message
     pub fn render_upstream_oauth2_link_mismatch(WithLanguage<WithCsrf<WithSession<UpstreamExistingLinkContext>>>) { "pages/upstream_oauth2/link_mismatch.html" }
 
@@ -407,6 +683,9 @@ register_templates! {
 
     /// Render the device code consent page
     pub fn render_device_consent(WithLanguage<WithCsrf<WithSession<DeviceConsentContext>>>) { "pages/device_consent.html" }
+
+    /// Render the backchannel authentication consent page
+    pub fn render_backchannel_consent(WithLanguage<WithCsrf<WithSession<BackchannelConsentContext>>>) { "pages/backchannel_consent.html" }
 }
 
 impl Templates {
@@ -424,6 +703,7 @@ impl Templates {
         check::render_not_found(self, now, rng)?;
         check::render_app(self, now, rng)?;
         check::render_login(self, now, rng)?;
+        check::render_login_throttled(self, now, rng)?;
         check::render_register(self, now, rng)?;
         check::render_consent(self, now, rng)?;
         check::render_policy_violation(self, now, rng)?;
@@ -431,21 +711,62 @@ impl Templates {
         check::render_index(self, now, rng)?;
         check::render_account_add_email(self, now, rng)?;
         check::render_account_verify_email(self, now, rng)?;
+        check::render_account_change_email(self, now, rng)?;
+        check::render_account_export(self, now, rng)?;
         check::render_recovery_start(self, now, rng)?;
         check::render_recovery_progress(self, now, rng)?;
         check::render_recovery_finish(self, now, rng)?;
         check::render_recovery_expired(self, now, rng)?;
         check::render_recovery_consumed(self, now, rng)?;
         check::render_recovery_disabled(self, now, rng)?;
+        check::render_magic_link_start(self, now, rng)?;
+        check::render_magic_link_progress(self, now, rng)?;
+        check::render_magic_link_confirm(self, now, rng)?;
+        check::render_magic_link_expired(self, now, rng)?;
+        check::render_magic_link_consumed(self, now, rng)?;
+        check::render_login_alert_report(self, now, rng)?;
+        check::render_login_alert_reported(self, now, rng)?;
+        check::render_login_alert_expired(self, now, rng)?;
+        check::render_login_code(self, now, rng)?;
+        check::render_email_change_undo(self, now, rng)?;
+        check::render_email_change_undone(self, now, rng)?;
+        check::render_email_change_expired(self, now, rng)?;
         check::render_reauth(self, now, rng)?;
+        check::render_impersonate(self, now, rng)?;
+        check::render_admin_users(self, now, rng)?;
+        check::render_admin_user(self, now, rng)?;
+        check::render_admin_clients(self, now, rng)?;
         check::render_form_post::<EmptyContext>(self, now, rng)?;
         check::render_error(self, now, rng)?;
         check::render_email_verification_txt(self, now, rng)?;
         check::render_email_verification_html(self, now, rng)?;
         check::render_email_verification_subject(self, now, rng)?;
+        check::render_email_magic_link_txt(self, now, rng)?;
+        check::render_email_magic_link_html(self, now, rng)?;
+        check::render_email_magic_link_subject(self, now, rng)?;
+        check::render_email_login_alert_txt(self, now, rng)?;
+        check::render_email_login_alert_html(self, now, rng)?;
+        check::render_email_login_alert_subject(self, now, rng)?;
+        check::render_email_login_code_txt(self, now, rng)?;
+        check::render_email_login_code_html(self, now, rng)?;
+        check::render_email_login_code_subject(self, now, rng)?;
+        check::render_email_backchannel_authentication_txt(self, now, rng)?;
+        check::render_email_backchannel_authentication_html(self, now, rng)?;
+        check::render_email_backchannel_authentication_subject(self, now, rng)?;
+        check::render_email_change_notification_txt(self, now, rng)?;
+        check::render_email_change_notification_html(self, now, rng)?;
+        check::render_email_change_notification_subject(self, now, rng)?;
+        check::render_email_password_change_notification_txt(self, now, rng)?;
+        check::render_email_password_change_notification_html(self, now, rng)?;
+        check::render_email_password_change_notification_subject(self, now, rng)?;
+        check::render_email_user_export_txt(self, now, rng)?;
+        check::render_email_user_export_html(self, now, rng)?;
+        check::render_email_user_export_subject(self, now, rng)?;
+        check::render_sms_verification(self, now, rng)?;
         check::render_upstream_oauth2_link_mismatch(self, now, rng)?;
         check::render_upstream_oauth2_suggest_link(self, now, rng)?;
         check::render_upstream_oauth2_do_register(self, now, rng)?;
+        check::render_backchannel_consent(self, now, rng)?;
         Ok(())
     }
 }
@@ -463,11 +784,17 @@ mod tests {
 
         let path = Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../templates/");
         let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
-        let branding = SiteBranding::new("example.com");
+        let branding = SiteBranding::new("example.com").with_announcement(
+            "**Planned maintenance** tonight, see [status](https://status.example.com/)",
+            None,
+            None,
+        );
         let features = SiteFeatures {
             password_login: true,
             password_registration: true,
             account_recovery: true,
+            magic_link_login: true,
+            certificate_login: false,
         };
         let vite_manifest_path =
             Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../frontend/dist/manifest.json");
@@ -475,6 +802,7 @@ mod tests {
             Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../translations");
         let templates = Templates::load(
             path,
+            None,
             url_builder,
             vite_manifest_path,
             translations_path,
@@ -0,0 +1,134 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the templates overriding the built-in ones
+
+use std::collections::BTreeSet;
+
+use crate::TemplateLoadingError;
+
+/// Variables without which a template can't work, like the CSRF token of
+/// forms or the links sent by email. If the built-in version of a template
+/// uses one of them, its override has to use it as well.
+const ESSENTIAL_VARIABLES: [&str; 6] = [
+    "csrf_token",
+    "redirect_uri",
+    "params",
+    "verification",
+    "recovery_link",
+    "report_link",
+];
+
+/// Find the names of the blocks defined in a template source
+fn block_names(source: &str) -> BTreeSet<String> {
+    source
+        .split("{%")
+        .skip(1)
+        .filter_map(|tag| {
+            let tag = tag.trim_start_matches(['-', '+']).trim_start();
+            let rest = tag.strip_prefix("block")?;
+            if !rest.starts_with(char::is_whitespace) {
+                return None;
+            }
+
+            let rest = rest.trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            (!name.is_empty()).then(|| name.to_owned())
+        })
+        .collect()
+}
+
+/// Find the variables a template source uses without defining them
+fn variables(name: &str, source: &str) -> Result<BTreeSet<String>, minijinja::Error> {
+    let env = minijinja::Environment::new();
+    let template = env.template_from_named_str(name, source)?;
+    Ok(template.undeclared_variables(false).into_iter().collect())
+}
+
+/// Check that the override of a built-in template still defines the blocks of
+/// the built-in one, and still uses its essential variables
+pub(crate) fn check(
+    template: &str,
+    builtin: &str,
+    override_: &str,
+) -> Result<(), TemplateLoadingError> {
+    let missing: BTreeSet<String> = block_names(builtin)
+        .difference(&block_names(override_))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(TemplateLoadingError::OverrideMissingBlocks {
+            template: template.to_owned(),
+            missing,
+        });
+    }
+
+    let builtin_variables = variables(template, builtin)?;
+    let override_variables = variables(template, override_)?;
+    let missing: BTreeSet<String> = ESSENTIAL_VARIABLES
+        .into_iter()
+        .filter(|variable| {
+            builtin_variables.contains(*variable) && !override_variables.contains(*variable)
+        })
+        .map(ToOwned::to_owned)
+        .collect();
+    if !missing.is_empty() {
+        return Err(TemplateLoadingError::OverrideMissingVariables {
+            template: template.to_owned(),
+            missing,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_override() {
+        let builtin = r#"{% extends "base.html" %}
+{% block content %}
+  <form method="POST"><input type="hidden" name="csrf" value="{{ csrf_token }}" /></form>
+{% endblock content %}"#;
+
+        // Restyling the template is fine, as long as the form still works
+        let restyled = r#"{% extends "base.html" %}
+{%- block content -%}
+  <div class="fancy"><form method="POST"><input type="hidden" name="csrf" value="{{ csrf_token }}" /></form></div>
+{%- endblock %}"#;
+        check("pages/login.html", builtin, restyled).unwrap();
+
+        let no_block = r#"<form method="POST"><input type="hidden" name="csrf" value="{{ csrf_token }}" /></form>"#;
+        let err = check("pages/login.html", builtin, no_block).unwrap_err();
+        assert!(matches!(
+            err,
+            TemplateLoadingError::OverrideMissingBlocks { missing, .. }
+                if missing == BTreeSet::from(["content".to_owned()])
+        ));
+
+        let no_csrf = r#"{% extends "base.html" %}
+{% block content %}<form method="POST"></form>{% endblock %}"#;
+        let err = check("pages/login.html", builtin, no_csrf).unwrap_err();
+        assert!(matches!(
+            err,
+            TemplateLoadingError::OverrideMissingVariables { missing, .. }
+                if missing == BTreeSet::from(["csrf_token".to_owned()])
+        ));
+    }
+}
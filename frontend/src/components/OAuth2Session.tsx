@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+import { Badge } from "@vector-im/compound-web";
 import { parseISO } from "date-fns";
 import { useTranslation } from "react-i18next";
 import { useMutation } from "urql";
@@ -89,6 +90,7 @@ const OAuth2Session: React.FC<Props> = ({ session }) => {
   };
 
   const deviceId = getDeviceIdFromScope(data.scope);
+  const hasOfflineAccess = data.scope.split(" ").includes("offline_access");
 
   const createdAt = parseISO(data.createdAt);
   const lastActiveAt = data.lastActiveAt
@@ -143,6 +145,11 @@ const OAuth2Session: React.FC<Props> = ({ session }) => {
               {deviceId}
             </Card.Info>
           )}
+          {hasOfflineAccess && (
+            <Badge kind="default" className="self-center">
+              {t("frontend.session.offline_access")}
+            </Badge>
+          )}
         </Card.Metadata>
       </Card.LinkBody>
 
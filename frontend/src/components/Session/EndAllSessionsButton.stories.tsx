@@ -0,0 +1,37 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+import { action } from "@storybook/addon-actions";
+import type { Meta, StoryObj } from "@storybook/react";
+
+import EndAllSessionsButton from "./EndAllSessionsButton";
+
+const endAllSessions = action("end-all-sessions");
+
+const meta = {
+  title: "UI/Session/End All Sessions Button",
+  component: EndAllSessionsButton,
+  tags: ["autodocs"],
+  args: {
+    endAllSessions: async (): Promise<void> => {
+      await new Promise((resolve) => setTimeout(resolve, 300));
+      endAllSessions();
+    },
+  },
+} satisfies Meta<typeof EndAllSessionsButton>;
+
+export default meta;
+type Story = StoryObj<typeof EndAllSessionsButton>;
+
+export const Basic: Story = {};
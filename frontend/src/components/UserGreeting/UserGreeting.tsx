@@ -27,7 +27,10 @@ import { useTranslation } from "react-i18next";
 import { useMutation } from "urql";
 
 import { FragmentType, graphql, useFragment } from "../../gql";
-import { SetDisplayNameStatus } from "../../gql/graphql";
+import {
+  SetAvatarUrlStatus,
+  SetDisplayNameStatus,
+} from "../../gql/graphql";
 import * as Dialog from "../Dialog";
 import LoadingSpinner from "../LoadingSpinner";
 
@@ -39,6 +42,7 @@ export const FRAGMENT = graphql(/* GraphQL */ `
     matrix {
       mxid
       displayName
+      avatarUrl
     }
   }
 `);
@@ -64,6 +68,20 @@ const SET_DISPLAYNAME_MUTATION = graphql(/* GraphQL */ `
   }
 `);
 
+const SET_AVATAR_URL_MUTATION = graphql(/* GraphQL */ `
+  mutation SetAvatarUrl($userId: ID!, $avatarUrl: String) {
+    setAvatarUrl(input: { userId: $userId, avatarUrl: $avatarUrl }) {
+      status
+      user {
+        id
+        matrix {
+          avatarUrl
+        }
+      }
+    }
+  }
+`);
+
 // This needs to be its own component because else props and refs aren't passed properly in the trigger
 const EditButton = forwardRef<
   HTMLButtonElement,
@@ -95,6 +113,9 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
   const [setDisplayNameResult, setDisplayName] = useMutation(
     SET_DISPLAYNAME_MUTATION,
   );
+  const [setAvatarUrlResult, setAvatarUrl] = useMutation(
+    SET_AVATAR_URL_MUTATION,
+  );
 
   const [open, setOpen] = useState(false);
   const { t } = useTranslation();
@@ -107,12 +128,22 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
     const form = event.currentTarget;
     const formData = new FormData(form);
     const displayName = (formData.get("displayname") as string) || null;
+    const avatarUrl = (formData.get("avatarurl") as string) || null;
 
     const result = await setDisplayName({ displayName, userId: data.id });
+    if (result.data?.setDisplayName.status !== SetDisplayNameStatus.Set) {
+      return;
+    }
 
-    if (result.data?.setDisplayName.status === SetDisplayNameStatus.Set) {
-      setOpen(false);
+    // Only update the avatar if it changed
+    if (avatarUrl !== (data.matrix.avatarUrl || null)) {
+      const avatarResult = await setAvatarUrl({ avatarUrl, userId: data.id });
+      if (avatarResult.data?.setAvatarUrl.status !== SetAvatarUrlStatus.Set) {
+        return;
+      }
     }
+
+    setOpen(false);
   };
 
   return (
@@ -194,6 +225,28 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
                 </Form.HelpMessage>
               </Form.Field>
 
+              <Form.Field
+                name="avatarurl"
+                serverInvalid={
+                  setAvatarUrlResult.data?.setAvatarUrl.status ===
+                  SetAvatarUrlStatus.Invalid
+                }
+              >
+                <Form.Label>
+                  {t("frontend.account.edit_profile.avatar_url_label")}
+                </Form.Label>
+
+                <Form.TextControl
+                  type="text"
+                  defaultValue={data.matrix.avatarUrl || undefined}
+                  placeholder="mxc://"
+                />
+
+                <Form.HelpMessage>
+                  {t("frontend.account.edit_profile.avatar_url_help")}
+                </Form.HelpMessage>
+              </Form.Field>
+
               <Form.Field name="mxid">
                 <Form.Label>
                   {t("frontend.account.edit_profile.username_label")}
@@ -202,8 +255,13 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
               </Form.Field>
             </div>
 
-            <Form.Submit disabled={setDisplayNameResult.fetching}>
-              {setDisplayNameResult.fetching && <LoadingSpinner inline />}
+            <Form.Submit
+              disabled={
+                setDisplayNameResult.fetching || setAvatarUrlResult.fetching
+              }
+            >
+              {(setDisplayNameResult.fetching ||
+                setAvatarUrlResult.fetching) && <LoadingSpinner inline />}
               {t("action.save")}
             </Form.Submit>
           </Form.Root>
@@ -345,6 +345,14 @@ export default {
             },
             "args": []
           },
+          {
+            "name": "expiresAt",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          },
           {
             "name": "finishedAt",
             "type": {
@@ -364,6 +372,17 @@ export default {
             },
             "args": []
           },
+          {
+            "name": "impersonated",
+            "type": {
+              "kind": "NON_NULL",
+              "ofType": {
+                "kind": "SCALAR",
+                "name": "Any"
+              }
+            },
+            "args": []
+          },
           {
             "name": "lastActiveAt",
             "type": {
@@ -380,6 +399,15 @@ export default {
             },
             "args": []
           },
+          {
+            "name": "lastActiveLocation",
+            "type": {
+              "kind": "OBJECT",
+              "name": "Location",
+              "ofType": null
+            },
+            "args": []
+          },
           {
             "name": "lastAuthentication",
             "type": {
@@ -599,6 +627,15 @@ export default {
             },
             "args": []
           },
+          {
+            "name": "lastActiveLocation",
+            "type": {
+              "kind": "OBJECT",
+              "name": "Location",
+              "ofType": null
+            },
+            "args": []
+          },
           {
             "name": "ssoLogin",
             "type": {
@@ -1081,6 +1118,53 @@ export default {
         ],
         "interfaces": []
       },
+      {
+        "kind": "OBJECT",
+        "name": "Location",
+        "fields": [
+          {
+            "name": "asOrganization",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          },
+          {
+            "name": "asn",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          },
+          {
+            "name": "city",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          },
+          {
+            "name": "country",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          },
+          {
+            "name": "countryCode",
+            "type": {
+              "kind": "SCALAR",
+              "name": "Any"
+            },
+            "args": []
+          }
+        ],
+        "interfaces": []
+      },
       {
         "kind": "OBJECT",
         "name": "LockUserPayload",
@@ -1376,6 +1460,29 @@ export default {
               }
             ]
           },
+          {
+            "name": "setAvatarUrl",
+            "type": {
+              "kind": "NON_NULL",
+              "ofType": {
+                "kind": "OBJECT",
+                "name": "SetAvatarUrlPayload",
+                "ofType": null
+              }
+            },
+            "args": [
+              {
+                "name": "input",
+                "type": {
+                  "kind": "NON_NULL",
+                  "ofType": {
+                    "kind": "SCALAR",
+                    "name": "Any"
+                  }
+                }
+              }
+            ]
+          },
           {
             "name": "setCanRequestAdmin",
             "type": {
@@ -1749,6 +1856,15 @@ export default {
             },
             "args": []
           },
+          {
+            "name": "lastActiveLocation",
+            "type": {
+              "kind": "OBJECT",
+              "name": "Location",
+              "ofType": null
+            },
+            "args": []
+          },
           {
             "name": "scope",
             "type": {
@@ -2366,6 +2482,33 @@ export default {
           }
         ]
       },
+      {
+        "kind": "OBJECT",
+        "name": "SetAvatarUrlPayload",
+        "fields": [
+          {
+            "name": "status",
+            "type": {
+              "kind": "NON_NULL",
+              "ofType": {
+                "kind": "SCALAR",
+                "name": "Any"
+              }
+            },
+            "args": []
+          },
+          {
+            "name": "user",
+            "type": {
+              "kind": "OBJECT",
+              "name": "User",
+              "ofType": null
+            },
+            "args": []
+          }
+        ],
+        "interfaces": []
+      },
       {
         "kind": "OBJECT",
         "name": "SetCanRequestAdminPayload",
@@ -83,6 +83,9 @@ const actionSchema = z
     z.object({
       action: z.literal("org.matrix.cross_signing_reset"),
     }),
+    z.object({
+      action: z.literal("org.matrix.password_change"),
+    }),
     z.object({
       action: z.undefined(),
     }),
@@ -125,6 +128,9 @@ export const Route = createFileRoute("/_account/")({
           to: "/reset-cross-signing",
           search: { deepLink: true },
         });
+
+      case "org.matrix.password_change":
+        throw redirect({ to: "/password/change" });
     }
   },
 
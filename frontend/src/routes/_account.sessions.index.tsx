@@ -14,13 +14,15 @@
 
 import { createFileRoute, notFound } from "@tanstack/react-router";
 import { H3, H5 } from "@vector-im/compound-web";
+import { useCallback } from "react";
 import { useTranslation } from "react-i18next";
-import { useQuery } from "urql";
+import { useMutation, useQuery } from "urql";
 
 import BlockList from "../components/BlockList";
 import { ButtonLink } from "../components/ButtonLink";
 import CompatSession from "../components/CompatSession";
 import OAuth2Session from "../components/OAuth2Session";
+import EndAllSessionsButton from "../components/Session/EndAllSessionsButton";
 import BrowserSessionsOverview from "../components/UserSessionsOverview/BrowserSessionsOverview";
 import { graphql } from "../gql";
 import { Pagination, paginationSchema, usePages } from "../pagination";
@@ -81,6 +83,14 @@ const LIST_QUERY = graphql(/* GraphQL */ `
   }
 `);
 
+const END_ALL_SESSIONS_MUTATION = graphql(/* GraphQL */ `
+  mutation EndAllSessions($userId: ID!) {
+    endAllSessions(input: { userId: $userId }) {
+      status
+    }
+  }
+`);
+
 // A type-safe way to ensure we've handled all session types
 const unknownSessionType = (type: never): never => {
   throw new Error(`Unknown session type: ${type}`);
@@ -118,6 +128,13 @@ function Sessions(): React.ReactElement {
     overview.data?.viewer.__typename === "User" ? overview.data.viewer : null;
   if (user === null) throw notFound();
 
+  const [, endAllSessions] = useMutation(END_ALL_SESSIONS_MUTATION);
+  const onEndAllSessions = useCallback(async (): Promise<void> => {
+    await endAllSessions({ userId: user.id });
+    // This also ended the current browser session
+    window.location.reload();
+  }, [endAllSessions, user.id]);
+
   const [list] = useQuery({ query: LIST_QUERY, variables: pagination });
   if (list.error) throw list.error;
   const appSessions =
@@ -139,6 +156,7 @@ function Sessions(): React.ReactElement {
     <BlockList>
       <H3>{t("frontend.user_sessions_overview.heading")}</H3>
       <BrowserSessionsOverview user={user} />
+      <EndAllSessionsButton endAllSessions={onEndAllSessions} />
 
       <H5>
         {t("frontend.user_sessions_overview.active_sessions", {
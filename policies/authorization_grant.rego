@@ -27,6 +27,8 @@ interactive_grant_type("authorization_code") = true
 
 interactive_grant_type("urn:ietf:params:oauth:grant-type:device_code") = true
 
+interactive_grant_type("urn:openid:params:grant-type:ciba") = true
+
 # Special case to make empty scope work
 allowed_scope("") = true
 
@@ -34,6 +36,10 @@ allowed_scope("openid") = true
 
 allowed_scope("email") = true
 
+allowed_scope("phone") = true
+
+allowed_scope("offline_access") = true
+
 # This grants access to Synapse's admin API endpoints
 allowed_scope("urn:synapse:admin:*") {
 	# Synapse doesn't support user-less tokens yet, so access to the admin API
@@ -59,6 +65,15 @@ allowed_scope("urn:mas:admin") {
 	input.client.id == client
 }
 
+# Users can request the scopes granted to one of their groups, as imported
+# from an upstream provider, through the group_scopes data
+allowed_scope(scope) {
+	interactive_grant_type(input.grant_type)
+	some group in input.user.groups
+	some allowed in data.group_scopes[group]
+	scope == allowed
+}
+
 allowed_scope(scope) {
 	# Grant access to the C-S API only if there is a user
 	interactive_grant_type(input.grant_type)
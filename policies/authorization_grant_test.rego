@@ -21,11 +21,14 @@ test_standard_scopes {
 		with input.client as client
 		with input.scope as "openid email"
 
-	# Not supported yet
-	not allow with input.user as user
+	allow with input.user as user
 		with input.client as client
 		with input.scope as "phone"
 
+	allow with input.user as user
+		with input.client as client
+		with input.scope as "openid offline_access"
+
 	# Not supported yet
 	not allow with input.user as user
 		with input.client as client
@@ -43,6 +46,11 @@ test_matrix_scopes {
 		with input.grant_type as "urn:ietf:params:oauth:grant-type:device_code"
 		with input.scope as "urn:matrix:org.matrix.msc2967.client:api:*"
 
+	allow with input.user as user
+		with input.client as client
+		with input.grant_type as "urn:openid:params:grant-type:ciba"
+		with input.scope as "urn:matrix:org.matrix.msc2967.client:api:*"
+
 	not allow with input.user as user
 		with input.client as client
 		with input.grant_type as "client_credentials"
@@ -155,3 +163,32 @@ test_mas_scopes {
 		with input.grant_type as "authorization_code"
 		with input.scope as "urn:mas:admin"
 }
+
+test_group_scopes {
+	allow with input.user as user
+		with input.user.groups as ["staff"]
+		with input.client as client
+		with data.group_scopes as {"staff": ["urn:example:staff"]}
+		with input.grant_type as "authorization_code"
+		with input.scope as "urn:example:staff"
+
+	not allow with input.user as user
+		with input.user.groups as ["guests"]
+		with input.client as client
+		with data.group_scopes as {"staff": ["urn:example:staff"]}
+		with input.grant_type as "authorization_code"
+		with input.scope as "urn:example:staff"
+
+	not allow with input.user as user
+		with input.client as client
+		with data.group_scopes as {"staff": ["urn:example:staff"]}
+		with input.grant_type as "authorization_code"
+		with input.scope as "urn:example:staff"
+
+	not allow with input.user as user
+		with input.user.groups as ["staff"]
+		with input.client as client
+		with data.group_scopes as {"staff": ["urn:example:staff"]}
+		with input.grant_type as "client_credentials"
+		with input.scope as "urn:example:staff"
+}
@@ -138,6 +138,18 @@ violation[{"msg": "client_credentials grant_type requires some form of client au
 	is_public_client
 }
 
+violation[{"msg": "ciba grant_type requires some form of client authentication"}] {
+	uses_grant_type("urn:openid:params:grant-type:ciba")
+	is_public_client
+}
+
+# The ciba grant type lets a client prompt any user by their username, so it is
+# reserved to the static clients unless explicitly allowed
+violation[{"msg": "ciba grant_type is not allowed for dynamically registered clients"}] {
+	uses_grant_type("urn:openid:params:grant-type:ciba")
+	not data.client_registration.allow_ciba
+}
+
 violation[{"msg": "missing redirect_uris"}] {
 	requires_redirect_uris
 	not input.client_metadata.redirect_uris
@@ -230,3 +242,17 @@ violation[{"msg": "invalid redirect_uri", "redirect_uri": redirect_uri}] {
 	some redirect_uri in input.client_metadata.redirect_uris
 	not valid_redirect_uri(redirect_uri)
 }
+
+# Wildcards, credentials and fragments are never allowed in redirect URIs, even
+# when insecure URIs are allowed
+violation[{"msg": "invalid redirect_uri", "redirect_uri": redirect_uri}] {
+	some redirect_uri in input.client_metadata.redirect_uris
+	regex.match(`[*#]|%2[aA]`, redirect_uri)
+}
+
+# Credentials are in the authority, before the path, query or fragment, which
+# may legitimately contain an '@'
+violation[{"msg": "invalid redirect_uri", "redirect_uri": redirect_uri}] {
+	some redirect_uri in input.client_metadata.redirect_uris
+	regex.match(`^[A-Za-z][A-Za-z0-9+.-]*://[^/?#]*@`, redirect_uri)
+}
@@ -309,6 +309,48 @@ test_web_redirect_uri {
 	}
 }
 
+test_unsafe_redirect_uri {
+	# Wildcards are never allowed
+	not allow with input.client_metadata as {
+		"application_type": "web",
+		"client_uri": "https://example.com/",
+		"redirect_uris": ["https://*.example.com/callback"],
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_insecure_uris as true
+		with data.client_registration.allow_host_mismatch as true
+
+	# Neither are credentials
+	not allow with input.client_metadata as {
+		"application_type": "web",
+		"client_uri": "https://example.com/",
+		"redirect_uris": ["https://example.com@evil.com/callback"],
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_insecure_uris as true
+		with data.client_registration.allow_host_mismatch as true
+
+	# But an '@' in the path or query is fine
+	allow with input.client_metadata as {
+		"application_type": "web",
+		"client_uri": "https://example.com/",
+		"redirect_uris": ["https://example.com/callback/@alice?hint=alice@example.com"],
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_insecure_uris as true
+		with data.client_registration.allow_host_mismatch as true
+
+	# Or fragments
+	not allow with input.client_metadata as {
+		"application_type": "web",
+		"client_uri": "https://example.com/",
+		"redirect_uris": ["https://example.com/callback#fragment"],
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_insecure_uris as true
+		with data.client_registration.allow_host_mismatch as true
+}
+
 test_native_redirect_uri {
 	# This has all the redirect URIs types we're supporting for native apps
 	allow with input.client_metadata as {
@@ -434,6 +476,34 @@ test_client_credentials_grant {
 	}
 }
 
+test_ciba_grant {
+	# Disallowed by default
+	not allow with input.client_metadata as {
+		"grant_types": ["urn:openid:params:grant-type:ciba"],
+		"token_endpoint_auth_method": "client_secret_basic",
+		"client_uri": "https://example.com/",
+		"contacts": ["contact@example.com"],
+	}
+
+	# Allowed for confidential clients if enabled
+	allow with input.client_metadata as {
+		"grant_types": ["urn:openid:params:grant-type:ciba"],
+		"token_endpoint_auth_method": "client_secret_basic",
+		"client_uri": "https://example.com/",
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_ciba as true
+
+	# Disallowed for public clients
+	not allow with input.client_metadata as {
+		"grant_types": ["urn:openid:params:grant-type:ciba"],
+		"token_endpoint_auth_method": "none",
+		"client_uri": "https://example.com/",
+		"contacts": ["contact@example.com"],
+	}
+		with data.client_registration.allow_ciba as true
+}
+
 test_is_subdomain {
 	is_subdomain("example.com", "example.com")
 	is_subdomain("example.com", "app.example.com")